@@ -1,4 +1,10 @@
-use crate::{arch::MMArch, libs::elf::ElfArch, mm::MemoryManagementArch};
+use alloc::vec::Vec;
+
+use crate::{
+    arch::{interrupt::TrapFrame, MMArch},
+    libs::elf::ElfArch,
+    mm::MemoryManagementArch,
+};
 
 #[derive(Debug, Clone, Copy, Hash)]
 pub struct RiscV64ElfArch;
@@ -7,4 +13,13 @@ impl ElfArch for RiscV64ElfArch {
     const ELF_ET_DYN_BASE: usize = MMArch::USER_END_VADDR.data() / 3 * 2;
 
     const ELF_PAGE_SIZE: usize = MMArch::PAGE_SIZE;
+
+    const ELF_MACHINE: u16 = elf::abi::EM_RISCV;
+
+    fn core_copy_regs(frame: &TrapFrame) -> Vec<usize> {
+        // TrapFrame开头的32个寄存器（pc、ra、sp……t6）与Linux的`struct user_regs_struct`的顺序相同
+        let regs =
+            unsafe { core::slice::from_raw_parts(frame as *const TrapFrame as *const usize, 32) };
+        return regs.to_vec();
+    }
 }
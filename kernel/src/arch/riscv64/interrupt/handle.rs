@@ -2,7 +2,14 @@ use core::hint::spin_loop;
 
 use system_error::SystemError;
 
-use crate::{kdebug, kerror};
+use crate::{
+    arch::time::riscv_sbi_timer_irq,
+    kdebug, kerror,
+    mm::{
+        fault::{FaultFlags, PageFaultHandler, VmFaultReason},
+        VirtAddr,
+    },
+};
 
 use super::TrapFrame;
 
@@ -36,8 +43,15 @@ unsafe extern "C" fn riscv64_do_irq(trap_frame: &mut TrapFrame) {
     }
 }
 
+/// supervisor定时器中断的中断号
+const RISCV_SUPERVISOR_TIMER_IRQ: usize = 5;
+
 /// 处理中断
-fn riscv64_do_interrupt(_trap_frame: &mut TrapFrame) {
+fn riscv64_do_interrupt(trap_frame: &mut TrapFrame) {
+    if trap_frame.cause.code() == RISCV_SUPERVISOR_TIMER_IRQ {
+        riscv_sbi_timer_irq();
+        return;
+    }
     kdebug!("todo: riscv64_do_irq: interrupt");
     loop {
         spin_loop();
@@ -146,27 +160,59 @@ fn do_trap_user_env_call(_trap_frame: &mut TrapFrame) -> Result<(), SystemError>
 // 9-11 reserved
 
 /// 处理指令页错误异常 #12
-fn do_trap_insn_page_fault(_trap_frame: &mut TrapFrame) -> Result<(), SystemError> {
-    kerror!("riscv64_do_irq: do_insn_page_fault");
-    loop {
-        spin_loop();
-    }
+fn do_trap_insn_page_fault(trap_frame: &mut TrapFrame) -> Result<(), SystemError> {
+    return do_page_fault(trap_frame, FaultFlags::FAULT_FLAG_INSTRUCTION);
 }
 
 /// 处理页加载错误异常 #13
-fn do_trap_load_page_fault(_trap_frame: &mut TrapFrame) -> Result<(), SystemError> {
-    kerror!("riscv64_do_irq: do_trap_load_page_fault");
-    loop {
-        spin_loop();
-    }
+fn do_trap_load_page_fault(trap_frame: &mut TrapFrame) -> Result<(), SystemError> {
+    return do_page_fault(trap_frame, FaultFlags::empty());
 }
 
 // 14 reserved
 
 /// 处理页存储错误异常 #15
-fn do_trap_store_page_fault(_trap_frame: &mut TrapFrame) -> Result<(), SystemError> {
-    kerror!("riscv64_do_irq: do_trap_store_page_fault");
-    loop {
-        spin_loop();
+fn do_trap_store_page_fault(trap_frame: &mut TrapFrame) -> Result<(), SystemError> {
+    return do_page_fault(trap_frame, FaultFlags::FAULT_FLAG_WRITE);
+}
+
+/// 页错误异常的公共处理函数
+///
+/// 用户地址空间内的页错误交给缺页处理程序处理（按需分配物理页），其余情况均为致命错误
+fn do_page_fault(trap_frame: &mut TrapFrame, mut flags: FaultFlags) -> Result<(), SystemError> {
+    let address = VirtAddr::new(trap_frame.badaddr());
+    let from_user = trap_frame.from_user();
+    if from_user {
+        flags |= FaultFlags::FAULT_FLAG_USER;
     }
+
+    if !address.check_user() {
+        kerror!(
+            "riscv64_do_irq: kernel page fault, address: {:?}, flags: {:?}",
+            address,
+            flags
+        );
+        loop {
+            spin_loop();
+        }
+    }
+
+    let reason = unsafe { PageFaultHandler::do_user_addr_fault(address, flags) };
+    if reason.contains(VmFaultReason::VM_FAULT_COMPLETED) {
+        return Ok(());
+    }
+
+    if !from_user {
+        kerror!(
+            "riscv64_do_irq: kernel failed to access user memory, address: {:?}, reason: {:?}",
+            address,
+            reason
+        );
+        loop {
+            spin_loop();
+        }
+    }
+
+    PageFaultHandler::send_sigsegv(address);
+    return Err(SystemError::EFAULT);
 }
@@ -111,4 +111,9 @@ impl TrapFrame {
     pub fn from_user(&self) -> bool {
         self.status.spp() == riscv::register::sstatus::SPP::User
     }
+
+    /// 获取产生异常的地址（stval寄存器的值）
+    pub fn badaddr(&self) -> usize {
+        self.badaddr
+    }
 }
@@ -1,15 +1,26 @@
 use core::hint::spin_loop;
 
-use crate::{arch::CurrentIrqArch, exception::InterruptArch, kBUG, process::ProcessManager};
+use crate::{
+    arch::{sched::sched, CurrentIrqArch},
+    exception::InterruptArch,
+    kBUG,
+    process::ProcessManager,
+    time::tick::tick_nohz_idle_enter,
+};
 
 impl ProcessManager {
     /// 每个核的idle进程
     pub fn arch_idle_func() -> ! {
         loop {
             if CurrentIrqArch::is_irq_enabled() {
+                let tick_stopped = tick_nohz_idle_enter();
                 unsafe {
                     riscv::asm::wfi();
                 }
+                // 时钟节拍停止之后，不会在时钟中断返回时发起调度，因此需要主动检查
+                if tick_stopped {
+                    sched();
+                }
             } else {
                 kBUG!("Idle process should not be scheduled with IRQs disabled.");
                 spin_loop();
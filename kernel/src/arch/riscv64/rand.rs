@@ -1,3 +1,13 @@
-pub fn rand() -> usize {
-    unimplemented!("RiscV64 rand");
+/// ## 获取硬件随机数
+///
+/// todo: 支持Zkr扩展的seed CSR
+pub fn arch_get_random_long() -> Option<u64> {
+    None
+}
+
+/// ## 获取硬件随机种子
+///
+/// todo: 支持Zkr扩展的seed CSR
+pub fn arch_get_random_seed_long() -> Option<u64> {
+    None
 }
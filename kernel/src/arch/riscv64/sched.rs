@@ -1,4 +1,9 @@
-use crate::sched::SchedArch;
+use crate::{
+    sched::{core::sched_cpu_activate, SchedArch},
+    smp::core::smp_get_processor_id,
+};
+
+use super::time::riscv_sbi_timer_init;
 
 /// 发起调度
 #[no_mangle]
@@ -18,6 +23,7 @@ impl SchedArch for RiscV64SchedArch {
     }
 
     fn initial_setup_sched_local() {
-        todo!()
+        riscv_sbi_timer_init();
+        sched_cpu_activate(smp_get_processor_id());
     }
 }
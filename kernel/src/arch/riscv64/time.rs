@@ -1,8 +1,141 @@
-use crate::time::TimeArch;
+use alloc::sync::{Arc, Weak};
+use system_error::SystemError;
+
+use crate::{
+    libs::spinlock::SpinLock,
+    mm::percpu::PerCpu,
+    smp::{core::smp_get_processor_id, cpu::ProcessorId},
+    time::{
+        clockevents::{ClockEventData, ClockEventDevice, ClockEventFeatures, ClockEventState},
+        clocksource::HZ,
+        TimeArch, NSEC_PER_SEC,
+    },
+};
+
+/// `time`寄存器的计数频率（Hz）
+///
+/// todo: 从设备树的`/cpus/timebase-frequency`中读取，目前使用QEMU virt机器的值
+pub const RISCV_TIMEBASE_FREQ: u64 = 10_000_000;
+
 pub struct RiscV64TimeArch;
 
 impl TimeArch for RiscV64TimeArch {
+    /// 读取`time`寄存器。与`cycle`寄存器不同，它的计数频率是固定的，可以用来计时
     fn get_cycles() -> usize {
-        riscv::register::cycle::read()
+        riscv::register::time::read()
+    }
+
+    fn cycles2ns(cycles: usize) -> usize {
+        return (cycles as u128 * NSEC_PER_SEC as u128 / RISCV_TIMEBASE_FREQ as u128) as usize;
+    }
+}
+
+/// 每个CPU的SBI定时器对应的时钟事件设备
+static SBI_TIMER_CLOCKEVENTS: [SpinLock<Option<Arc<SbiTimerClockEvent>>>;
+    PerCpu::MAX_CPU_NUM as usize] = [const { SpinLock::new(None) }; PerCpu::MAX_CPU_NUM as usize];
+
+/// 通过SBI的TIME扩展编程的定时器，到期时产生supervisor定时器中断
+///
+/// 每个hart有一个，只支持oneshot模式
+#[derive(Debug)]
+pub struct SbiTimerClockEvent {
+    self_ref: Weak<SbiTimerClockEvent>,
+    cpu: ProcessorId,
+    data: ClockEventData,
+}
+
+impl SbiTimerClockEvent {
+    fn new(cpu: ProcessorId) -> Arc<Self> {
+        return Arc::new_cyclic(|self_ref| Self {
+            self_ref: self_ref.clone(),
+            cpu,
+            data: ClockEventData::new(),
+        });
+    }
+}
+
+impl ClockEventDevice for SbiTimerClockEvent {
+    fn name(&self) -> &str {
+        "riscv_sbi_timer"
+    }
+
+    fn features(&self) -> ClockEventFeatures {
+        return ClockEventFeatures::ONESHOT;
+    }
+
+    fn rating(&self) -> u32 {
+        100
+    }
+
+    fn cpu(&self) -> Option<ProcessorId> {
+        return Some(self.cpu);
+    }
+
+    fn period_ns(&self) -> u64 {
+        return NSEC_PER_SEC as u64 / HZ;
+    }
+
+    fn min_delta_ns(&self) -> u64 {
+        return (NSEC_PER_SEC as u64 / RISCV_TIMEBASE_FREQ).max(1);
+    }
+
+    fn max_delta_ns(&self) -> u64 {
+        return i64::MAX as u64;
+    }
+
+    fn set_state(&self, state: ClockEventState) -> Result<(), SystemError> {
+        match state {
+            ClockEventState::Oneshot => unsafe { riscv::register::sie::set_stimer() },
+            ClockEventState::OneshotStopped
+            | ClockEventState::Shutdown
+            | ClockEventState::Detached => {
+                unsafe { riscv::register::sie::clear_stimer() };
+                sbi_rt::set_timer(u64::MAX);
+            }
+            ClockEventState::Periodic => return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+        }
+        return Ok(());
+    }
+
+    fn set_next_event(&self, delta_ns: u64) -> Result<(), SystemError> {
+        let delta = (delta_ns as u128 * RISCV_TIMEBASE_FREQ as u128 / NSEC_PER_SEC as u128) as u64;
+        let ret = sbi_rt::set_timer((riscv::register::time::read() as u64).saturating_add(delta));
+        if ret.error != 0 {
+            return Err(SystemError::EIO);
+        }
+        return Ok(());
+    }
+
+    fn data(&self) -> &ClockEventData {
+        return &self.data;
+    }
+
+    fn clockevent(&self) -> Arc<dyn ClockEventDevice> {
+        return self.self_ref.upgrade().unwrap();
+    }
+}
+
+/// 初始化当前CPU的SBI定时器
+pub fn riscv_sbi_timer_init() {
+    let cpu_id = smp_get_processor_id();
+    let dev = SbiTimerClockEvent::new(cpu_id);
+    SBI_TIMER_CLOCKEVENTS[cpu_id.data() as usize]
+        .lock_irqsave()
+        .replace(dev.clone());
+    (dev as Arc<dyn ClockEventDevice>).register();
+}
+
+/// 处理supervisor定时器中断
+pub fn riscv_sbi_timer_irq() {
+    let cpu_id = smp_get_processor_id();
+    let dev = SBI_TIMER_CLOCKEVENTS[cpu_id.data() as usize]
+        .lock_irqsave()
+        .clone();
+    match dev {
+        Some(dev) => (dev as Arc<dyn ClockEventDevice>).handle_event(),
+        // 没有使用定时器时，避免中断一直产生
+        None => {
+            sbi_rt::set_timer(u64::MAX);
+        }
     }
 }
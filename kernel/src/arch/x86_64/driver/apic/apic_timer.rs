@@ -11,13 +11,17 @@ use crate::exception::manage::irq_manager;
 use crate::exception::IrqNumber;
 
 use crate::kdebug;
+use crate::libs::spinlock::SpinLock;
 use crate::mm::percpu::PerCpu;
-use crate::sched::core::sched_update_jiffies;
 use crate::smp::core::smp_get_processor_id;
 use crate::smp::cpu::ProcessorId;
+use crate::time::clockevents::{
+    ClockEventData, ClockEventDevice, ClockEventFeatures, ClockEventState,
+};
 use crate::time::clocksource::HZ;
+use crate::time::{NSEC_PER_MSEC, NSEC_PER_SEC};
 use alloc::string::ToString;
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
 pub use drop;
 use system_error::SystemError;
 use x86::cpuid::cpuid;
@@ -32,6 +36,10 @@ pub const APIC_TIMER_IRQ_NUM: IrqNumber = IrqNumber::new(151);
 static mut LOCAL_APIC_TIMERS: [RefCell<LocalApicTimer>; PerCpu::MAX_CPU_NUM as usize] =
     [const { RefCell::new(LocalApicTimer::new()) }; PerCpu::MAX_CPU_NUM as usize];
 
+/// 每个CPU的本地APIC定时器对应的时钟事件设备
+static LOCAL_APIC_CLOCKEVENTS: [SpinLock<Option<Arc<LocalApicClockEvent>>>;
+    PerCpu::MAX_CPU_NUM as usize] = [const { SpinLock::new(None) }; PerCpu::MAX_CPU_NUM as usize];
+
 #[allow(dead_code)]
 #[inline(always)]
 pub(super) fn local_apic_timer_instance(
@@ -83,8 +91,13 @@ pub fn apic_timer_init() {
         )
         .expect("Apic timer init failed");
 
-    LocalApicTimerIntrController.install();
-    LocalApicTimerIntrController.enable();
+    // 由时钟节拍模块决定定时器的工作模式
+    let cpu_id = smp_get_processor_id();
+    let dev = LocalApicClockEvent::new(cpu_id);
+    LOCAL_APIC_CLOCKEVENTS[cpu_id.data() as usize]
+        .lock_irqsave()
+        .replace(dev.clone());
+    (dev as Arc<dyn ClockEventDevice>).register();
 }
 
 /// 初始化本地APIC定时器的中断描述符
@@ -100,64 +113,109 @@ pub(super) fn local_apic_timer_irq_desc_init() {
     desc.set_handler(&LocalApicTimerIrqFlowHandler);
 }
 
-/// 初始化BSP的APIC定时器
+/// 本地APIC定时器对应的时钟事件设备
 ///
-fn init_bsp_apic_timer() {
-    kdebug!("init_bsp_apic_timer");
-    assert!(smp_get_processor_id().data() == 0);
-    let mut local_apic_timer = local_apic_timer_instance_mut(ProcessorId::new(0));
-    local_apic_timer.init(
-        LocalApicTimerMode::Periodic,
-        LocalApicTimer::periodic_default_initial_count(),
-        LocalApicTimer::DIVISOR as u32,
-    );
-    kdebug!("init_bsp_apic_timer done");
+/// 每个CPU有一个，只能在对应的CPU上设置状态和编程
+#[derive(Debug)]
+pub struct LocalApicClockEvent {
+    self_ref: Weak<LocalApicClockEvent>,
+    cpu: ProcessorId,
+    max_delta_ns: u64,
+    data: ClockEventData,
 }
 
-fn init_ap_apic_timer() {
-    kdebug!("init_ap_apic_timer");
-    let cpu_id = smp_get_processor_id();
-    assert!(cpu_id.data() != 0);
-
-    let mut local_apic_timer = local_apic_timer_instance_mut(cpu_id);
-    local_apic_timer.init(
-        LocalApicTimerMode::Periodic,
-        LocalApicTimer::periodic_default_initial_count(),
-        LocalApicTimer::DIVISOR as u32,
-    );
-    kdebug!("init_ap_apic_timer done");
+impl LocalApicClockEvent {
+    /// oneshot模式下可以编程的最短时间
+    const MIN_DELTA_NS: u64 = 1000;
+
+    fn new(cpu: ProcessorId) -> Arc<Self> {
+        let counts_per_ms = LocalApicTimer::counts_per_ms().max(1);
+        let max_delta_ns = u32::MAX as u64 * NSEC_PER_MSEC as u64 / counts_per_ms;
+        return Arc::new_cyclic(|self_ref| Self {
+            self_ref: self_ref.clone(),
+            cpu,
+            max_delta_ns,
+            data: ClockEventData::new(),
+        });
+    }
 }
 
-pub(super) struct LocalApicTimerIntrController;
+impl ClockEventDevice for LocalApicClockEvent {
+    fn name(&self) -> &str {
+        "lapic"
+    }
 
-impl LocalApicTimerIntrController {
-    pub(super) fn install(&self) {
-        kdebug!("LocalApicTimerIntrController::install");
-        if smp_get_processor_id().data() == 0 {
-            init_bsp_apic_timer();
-        } else {
-            init_ap_apic_timer();
+    fn features(&self) -> ClockEventFeatures {
+        return ClockEventFeatures::PERIODIC | ClockEventFeatures::ONESHOT;
+    }
+
+    fn rating(&self) -> u32 {
+        100
+    }
+
+    fn cpu(&self) -> Option<ProcessorId> {
+        return Some(self.cpu);
+    }
+
+    fn period_ns(&self) -> u64 {
+        return NSEC_PER_SEC as u64 / HZ;
+    }
+
+    fn min_delta_ns(&self) -> u64 {
+        return Self::MIN_DELTA_NS;
+    }
+
+    fn max_delta_ns(&self) -> u64 {
+        return self.max_delta_ns;
+    }
+
+    fn set_state(&self, state: ClockEventState) -> Result<(), SystemError> {
+        assert!(smp_get_processor_id() == self.cpu);
+        let mut timer = local_apic_timer_instance_mut(self.cpu);
+        match state {
+            ClockEventState::Periodic => {
+                timer.init(
+                    LocalApicTimerMode::Periodic,
+                    LocalApicTimer::periodic_default_initial_count(),
+                    LocalApicTimer::DIVISOR as u32,
+                );
+                timer.start_current();
+            }
+            ClockEventState::Oneshot => {
+                timer.init(
+                    LocalApicTimerMode::Oneshot,
+                    0,
+                    LocalApicTimer::DIVISOR as u32,
+                );
+                timer.start_current();
+            }
+            ClockEventState::OneshotStopped => {
+                // 初始计数为0时，定时器停止计数
+                timer.set_initial_cnt(0);
+            }
+            ClockEventState::Shutdown | ClockEventState::Detached => {
+                timer.stop_current();
+                timer.set_initial_cnt(0);
+            }
         }
+        return Ok(());
     }
 
-    #[allow(dead_code)]
-    pub(super) fn uninstall(&self) {
-        let cpu_id = smp_get_processor_id();
-        let local_apic_timer = local_apic_timer_instance(cpu_id);
-        local_apic_timer.stop_current();
+    fn set_next_event(&self, delta_ns: u64) -> Result<(), SystemError> {
+        assert!(smp_get_processor_id() == self.cpu);
+        let count = (delta_ns as u128 * LocalApicTimer::counts_per_ms() as u128
+            / NSEC_PER_MSEC as u128)
+            .clamp(1, u32::MAX as u128) as u64;
+        local_apic_timer_instance_mut(self.cpu).set_initial_cnt(count);
+        return Ok(());
     }
 
-    pub(super) fn enable(&self) {
-        kdebug!("LocalApicTimerIntrController::enable");
-        let cpu_id = smp_get_processor_id();
-        let mut local_apic_timer = local_apic_timer_instance_mut(cpu_id);
-        local_apic_timer.start_current();
+    fn data(&self) -> &ClockEventData {
+        return &self.data;
     }
 
-    pub(super) fn disable(&self) {
-        let cpu_id = smp_get_processor_id();
-        let local_apic_timer = local_apic_timer_instance_mut(cpu_id);
-        local_apic_timer.stop_current();
+    fn clockevent(&self) -> Arc<dyn ClockEventDevice> {
+        return self.self_ref.upgrade().unwrap();
     }
 }
 
@@ -196,13 +254,17 @@ impl LocalApicTimer {
 
     /// 周期模式下的默认初始值
     pub fn periodic_default_initial_count() -> u64 {
+        return Self::counts_per_ms() * Self::INTERVAL_MS;
+    }
+
+    /// 定时器每毫秒的计数
+    fn counts_per_ms() -> u64 {
         let cpu_khz = TSCManager::cpu_khz();
 
         // 疑惑：这里使用khz吗？
         // 我觉得应该是hz，但是由于旧的代码是测量出initcnt的，而不是计算的
         // 然后我发现使用hz会导致计算出来的initcnt太大，导致系统卡顿，而khz的却能跑
-        let count = cpu_khz * Self::INTERVAL_MS / (Self::DIVISOR);
-        return count;
+        return cpu_khz / Self::DIVISOR;
     }
 
     /// Init this manager.
@@ -213,7 +275,7 @@ impl LocalApicTimer {
         self.triggered = false;
         match mode {
             LocalApicTimerMode::Periodic => self.install_periodic_mode(initial_count, divisor),
-            LocalApicTimerMode::Oneshot => todo!(),
+            LocalApicTimerMode::Oneshot => self.install_oneshot_mode(initial_count, divisor),
             LocalApicTimerMode::Deadline => todo!(),
         }
     }
@@ -234,6 +296,17 @@ impl LocalApicTimer {
         );
     }
 
+    fn install_oneshot_mode(&mut self, initial_count: u64, divisor: u32) {
+        self.mode = LocalApicTimerMode::Oneshot;
+        self.set_divisor(divisor);
+        self.setup_lvt(
+            APIC_TIMER_IRQ_NUM.data() as u8,
+            true,
+            LocalApicTimerMode::Oneshot,
+        );
+        self.set_initial_cnt(initial_count);
+    }
+
     fn setup_lvt(&mut self, vector: u8, mask: bool, mode: LocalApicTimerMode) {
         let mode: u32 = mode as u32;
         let data = (mode << 17) | (vector as u32) | (if mask { 1 << 16 } else { 0 });
@@ -275,7 +348,13 @@ impl LocalApicTimer {
     }
 
     pub(super) fn handle_irq() -> Result<IrqReturn, SystemError> {
-        sched_update_jiffies();
+        let cpu_id = smp_get_processor_id();
+        let dev = LOCAL_APIC_CLOCKEVENTS[cpu_id.data() as usize]
+            .lock_irqsave()
+            .clone();
+        if let Some(dev) = dev {
+            (dev as Arc<dyn ClockEventDevice>).handle_event();
+        }
         return Ok(IrqReturn::Handled);
     }
 }
@@ -6,7 +6,10 @@ use core::{
 };
 
 use acpi::HpetInfo;
-use alloc::{string::ToString, sync::Arc};
+use alloc::{
+    string::ToString,
+    sync::{Arc, Weak},
+};
 use system_error::SystemError;
 
 use crate::{
@@ -19,7 +22,6 @@ use crate::{
         irqdata::IrqHandlerData,
         irqdesc::{IrqHandleFlags, IrqHandler, IrqReturn},
         manage::irq_manager,
-        softirq::{softirq_vectors, SoftirqNumber},
         InterruptArch, IrqNumber,
     },
     kdebug, kerror, kinfo,
@@ -31,7 +33,11 @@ use crate::{
         mmio_buddy::{mmio_pool, MMIOSpaceGuard},
         PhysAddr,
     },
-    time::timer::{clock, timer_get_first_expire, update_timer_jiffies},
+    smp::cpu::ProcessorId,
+    time::{
+        clockevents::{ClockEventData, ClockEventDevice, ClockEventFeatures, ClockEventState},
+        NSEC_PER_USEC,
+    },
 };
 
 static mut HPET_INSTANCE: Option<Hpet> = None;
@@ -46,6 +52,7 @@ pub struct Hpet {
     _mmio_guard: MMIOSpaceGuard,
     inner: RwLock<InnerHpet>,
     enabled: AtomicBool,
+    clock_event: Arc<HpetClockEvent>,
 }
 
 struct InnerHpet {
@@ -97,6 +104,7 @@ impl Hpet {
                 timer_registers_ptr: timer_ptr,
             }),
             enabled: AtomicBool::new(false),
+            clock_event: HpetClockEvent::new(),
         };
 
         return Ok(hpet);
@@ -106,25 +114,51 @@ impl Hpet {
         self.enabled.load(Ordering::SeqCst)
     }
 
-    /// 使能HPET
+    /// 使能HPET，并把定时器0注册为时钟事件设备
     pub fn hpet_enable(&self) -> Result<(), SystemError> {
         let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
 
-        // ！！！这里是临时糊代码的，需要在apic重构的时候修改！！！
         let (inner_guard, regs) = unsafe { self.hpet_regs_mut() };
         let freq = regs.frequency();
         kdebug!("HPET frequency: {} Hz", freq);
+        if unlikely(regs.timers_num() == 0) {
+            return Err(SystemError::ENODEV);
+        }
+        drop(inner_guard);
+
+        irq_manager().request_irq(
+            Self::HPET0_IRQ,
+            "HPET0".to_string(),
+            &HpetIrqHandler,
+            IrqHandleFlags::IRQF_TRIGGER_RISING,
+            None,
+        )?;
+
+        self.enabled.store(true, Ordering::SeqCst);
+        (self.clock_event.clone() as Arc<dyn ClockEventDevice>).register();
+
+        kinfo!("HPET enabled");
+
+        drop(irq_guard);
+        return Ok(());
+    }
+
+    /// 设置定时器0为周期定时，并重新开始计数
+    fn start_timer0_periodic(&self) -> Result<(), SystemError> {
+        // ！！！这里是临时糊代码的，需要在apic重构的时候修改！！！
+        let (inner_guard, regs) = unsafe { self.hpet_regs_mut() };
+        let freq = regs.frequency();
         let ticks = Self::HPET0_INTERVAL_USEC * freq / 1000000;
         if ticks <= 0 || ticks > freq * 8 {
             kerror!("HPET enable: ticks '{ticks}' is invalid");
             return Err(SystemError::EINVAL);
         }
-        if unlikely(regs.timers_num() == 0) {
-            return Err(SystemError::ENODEV);
-        }
-
-        unsafe { regs.write_main_counter_value(0) };
 
+        // 设置定时器之前停止计数
+        unsafe {
+            regs.write_general_config(0);
+            regs.write_main_counter_value(0);
+        };
         drop(inner_guard);
 
         let (inner_guard, timer_reg) = unsafe { self.timer_mut(0).ok_or(SystemError::ENODEV) }?;
@@ -138,26 +172,23 @@ impl Hpet {
         }
         drop(inner_guard);
 
-        irq_manager().request_irq(
-            Self::HPET0_IRQ,
-            "HPET0".to_string(),
-            &HpetIrqHandler,
-            IrqHandleFlags::IRQF_TRIGGER_RISING,
-            None,
-        )?;
-
-        self.enabled.store(true, Ordering::SeqCst);
-
         let (inner_guard, regs) = unsafe { self.hpet_regs_mut() };
 
         // 置位旧设备中断路由兼容标志位、定时器组使能标志位
         unsafe { regs.write_general_config(3) };
 
         drop(inner_guard);
+        return Ok(());
+    }
 
-        kinfo!("HPET enabled");
-
-        drop(irq_guard);
+    /// 关闭定时器0的中断
+    fn stop_timer0(&self) -> Result<(), SystemError> {
+        let (inner_guard, timer_reg) = unsafe { self.timer_mut(0).ok_or(SystemError::ENODEV) }?;
+        let timer_reg = NonNull::new(timer_reg as *mut HpetTimerRegisters).unwrap();
+        unsafe {
+            volwrite!(timer_reg, config, 0);
+        }
+        drop(inner_guard);
         return Ok(());
     }
 
@@ -237,16 +268,79 @@ impl Hpet {
     /// 处理HPET的中断
     pub(super) fn handle_irq(&self, timer_num: u32) {
         if timer_num == 0 {
-            assert!(CurrentIrqArch::is_irq_enabled() == false);
-            update_timer_jiffies(Self::HPET0_INTERVAL_USEC, Self::HPET0_INTERVAL_USEC as i64);
+            (self.clock_event.clone() as Arc<dyn ClockEventDevice>).handle_event();
+        }
+    }
+}
+
+/// HPET定时器0对应的时钟事件设备
+///
+/// 定时器0的中断可以投递到任意CPU，作为全局设备推进jiffies和墙上时间。目前只支持周期模式
+#[derive(Debug)]
+pub struct HpetClockEvent {
+    self_ref: Weak<HpetClockEvent>,
+    data: ClockEventData,
+}
+
+impl HpetClockEvent {
+    fn new() -> Arc<Self> {
+        return Arc::new_cyclic(|self_ref| Self {
+            self_ref: self_ref.clone(),
+            data: ClockEventData::new(),
+        });
+    }
+}
 
-            if let Ok(first_expire) = timer_get_first_expire() {
-                if first_expire <= clock() {
-                    softirq_vectors().raise_softirq(SoftirqNumber::TIMER);
-                }
+impl ClockEventDevice for HpetClockEvent {
+    fn name(&self) -> &str {
+        "hpet"
+    }
+
+    fn features(&self) -> ClockEventFeatures {
+        return ClockEventFeatures::PERIODIC;
+    }
+
+    fn rating(&self) -> u32 {
+        50
+    }
+
+    fn cpu(&self) -> Option<ProcessorId> {
+        None
+    }
+
+    fn period_ns(&self) -> u64 {
+        return Hpet::HPET0_INTERVAL_USEC * NSEC_PER_USEC as u64;
+    }
+
+    fn min_delta_ns(&self) -> u64 {
+        return self.period_ns();
+    }
+
+    fn max_delta_ns(&self) -> u64 {
+        return self.period_ns();
+    }
+
+    fn set_state(&self, state: ClockEventState) -> Result<(), SystemError> {
+        match state {
+            ClockEventState::Periodic => hpet_instance().start_timer0_periodic(),
+            ClockEventState::Shutdown | ClockEventState::Detached => hpet_instance().stop_timer0(),
+            ClockEventState::Oneshot | ClockEventState::OneshotStopped => {
+                Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
             }
         }
     }
+
+    fn set_next_event(&self, _delta_ns: u64) -> Result<(), SystemError> {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+
+    fn data(&self) -> &ClockEventData {
+        return &self.data;
+    }
+
+    fn clockevent(&self) -> Arc<dyn ClockEventDevice> {
+        return self.self_ref.upgrade().unwrap();
+    }
 }
 
 pub fn hpet_init() -> Result<(), SystemError> {
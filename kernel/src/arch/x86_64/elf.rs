@@ -1,4 +1,11 @@
-use crate::{arch::MMArch, libs::elf::ElfArch, mm::MemoryManagementArch};
+use alloc::vec::Vec;
+
+use crate::{
+    arch::{interrupt::TrapFrame, MMArch},
+    libs::elf::ElfArch,
+    mm::MemoryManagementArch,
+    process::ProcessManager,
+};
 
 #[derive(Debug, Clone, Copy, Hash)]
 pub struct X86_64ElfArch;
@@ -7,4 +14,44 @@ impl ElfArch for X86_64ElfArch {
     const ELF_ET_DYN_BASE: usize = MMArch::USER_END_VADDR.data() / 3 * 2;
 
     const ELF_PAGE_SIZE: usize = MMArch::PAGE_SIZE;
+
+    const ELF_MACHINE: u16 = elf::abi::EM_X86_64;
+
+    /// 寄存器的顺序与Linux的`struct user_regs_struct`相同
+    fn core_copy_regs(frame: &TrapFrame) -> Vec<usize> {
+        let pcb = ProcessManager::current_pcb();
+        let arch_info = pcb.arch_info_irqsave();
+        let regs: [u64; 27] = [
+            frame.r15,
+            frame.r14,
+            frame.r13,
+            frame.r12,
+            frame.rbp,
+            frame.rbx,
+            frame.r11,
+            frame.r10,
+            frame.r9,
+            frame.r8,
+            frame.rax,
+            frame.rcx,
+            frame.rdx,
+            frame.rsi,
+            frame.rdi,
+            // orig_rax：不是在系统调用中产生的信号，按照Linux的约定设置为-1
+            u64::MAX,
+            frame.rip,
+            frame.cs,
+            frame.rflags,
+            frame.rsp,
+            frame.ss,
+            arch_info.fsbase() as u64,
+            arch_info.gsbase() as u64,
+            frame.ds,
+            frame.es,
+            // fs和gs段选择子在64位模式下不使用
+            0,
+            0,
+        ];
+        return regs.iter().map(|x| *x as usize).collect();
+    }
 }
@@ -1,8 +1,12 @@
 use system_error::SystemError;
 
 use crate::{
-    arch::CurrentIrqArch, exception::InterruptArch, kerror, kwarn, mm::VirtAddr, print,
-    process::ProcessManager, smp::core::smp_get_processor_id,
+    arch::{mm::fault::X86PfErrorCode, CurrentIrqArch, MMArch},
+    exception::InterruptArch,
+    kerror, kwarn,
+    mm::VirtAddr,
+    process::ProcessManager,
+    smp::core::smp_get_processor_id,
 };
 
 use super::{
@@ -324,42 +328,15 @@ Segment Selector Index: {:#x}\n
 /// 处理页错误 14 #PF
 #[no_mangle]
 unsafe extern "C" fn do_page_fault(regs: &'static TrapFrame, error_code: u64) {
-    kerror!(
-        "do_page_fault(14), \tError code: {:#x},\trsp: {:#x},\trip: {:#x},\t CPU: {}, \tpid: {:?}, \nFault Address: {:#x}",
-        error_code,
-        regs.rsp,
-        regs.rip,
-        smp_get_processor_id().data(),
-        ProcessManager::current_pid(),
-        x86::controlregs::cr2()
-    );
+    let address = VirtAddr::new(x86::controlregs::cr2());
+    let error_code = X86PfErrorCode::from_bits_truncate(error_code);
 
-    if (error_code & 0x01) == 0 {
-        print!("Page Not Present,\t");
-    }
-    if (error_code & 0x02) != 0 {
-        print!("Write Access,\t");
+    if address.check_user() {
+        MMArch::do_user_addr_fault(regs, error_code, address);
     } else {
-        print!("Read Access,\t");
+        CurrentIrqArch::interrupt_enable();
+        MMArch::do_kern_addr_fault(regs, error_code, address);
     }
-
-    if (error_code & 0x04) != 0 {
-        print!("Fault in user(3),\t");
-    } else {
-        print!("Fault in supervisor(0,1,2),\t");
-    }
-
-    if (error_code & 0x08) != 0 {
-        print!("Reserved bit violation cause fault,\t");
-    }
-
-    if (error_code & 0x10) != 0 {
-        print!("Instruction fetch cause fault,\t");
-    }
-    print!("\n");
-
-    CurrentIrqArch::interrupt_enable();
-    panic!("Page Fault");
 }
 
 /// 处理x87 FPU错误 16 #MF
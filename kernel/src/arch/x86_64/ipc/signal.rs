@@ -17,7 +17,7 @@ use crate::{
     },
     kerror,
     mm::MemoryManagementArch,
-    process::ProcessManager,
+    process::{coredump::do_coredump, ProcessManager},
     syscall::{user_access::UserBufferWriter, Syscall},
 };
 
@@ -142,22 +142,22 @@ impl Signal {
     }
 
     /// 调用信号的默认处理函数
-    pub fn handle_default(&self) {
+    pub fn handle_default(&self, frame: &TrapFrame) {
         match self {
             Signal::INVALID => {
                 kerror!("attempting to handler an Invalid");
             }
             Signal::SIGHUP => sig_terminate(self.clone()),
             Signal::SIGINT => sig_terminate(self.clone()),
-            Signal::SIGQUIT => sig_terminate_dump(self.clone()),
-            Signal::SIGILL => sig_terminate_dump(self.clone()),
-            Signal::SIGTRAP => sig_terminate_dump(self.clone()),
-            Signal::SIGABRT_OR_IOT => sig_terminate_dump(self.clone()),
-            Signal::SIGBUS => sig_terminate_dump(self.clone()),
-            Signal::SIGFPE => sig_terminate_dump(self.clone()),
+            Signal::SIGQUIT => sig_terminate_dump(self.clone(), frame),
+            Signal::SIGILL => sig_terminate_dump(self.clone(), frame),
+            Signal::SIGTRAP => sig_terminate_dump(self.clone(), frame),
+            Signal::SIGABRT_OR_IOT => sig_terminate_dump(self.clone(), frame),
+            Signal::SIGBUS => sig_terminate_dump(self.clone(), frame),
+            Signal::SIGFPE => sig_terminate_dump(self.clone(), frame),
             Signal::SIGKILL => sig_terminate(self.clone()),
             Signal::SIGUSR1 => sig_terminate(self.clone()),
-            Signal::SIGSEGV => sig_terminate_dump(self.clone()),
+            Signal::SIGSEGV => sig_terminate_dump(self.clone(), frame),
             Signal::SIGUSR2 => sig_terminate(self.clone()),
             Signal::SIGPIPE => sig_terminate(self.clone()),
             Signal::SIGALRM => sig_terminate(self.clone()),
@@ -170,8 +170,8 @@ impl Signal {
             Signal::SIGTTIN => sig_stop(self.clone()),
             Signal::SIGTTOU => sig_stop(self.clone()),
             Signal::SIGURG => sig_ignore(self.clone()),
-            Signal::SIGXCPU => sig_terminate_dump(self.clone()),
-            Signal::SIGXFSZ => sig_terminate_dump(self.clone()),
+            Signal::SIGXCPU => sig_terminate_dump(self.clone(), frame),
+            Signal::SIGXFSZ => sig_terminate_dump(self.clone(), frame),
             Signal::SIGVTALRM => sig_terminate(self.clone()),
             Signal::SIGPROF => sig_terminate(self.clone()),
             Signal::SIGWINCH => sig_ignore(self.clone()),
@@ -453,6 +453,8 @@ impl SignalArch for X86_64SignalArch {
             (sig_number, info) = siginfo_mut_guard.dequeue_signal(&sig_block);
             // 如果信号非法，则直接返回
             if sig_number == Signal::INVALID {
+                // sigsuspend等系统调用临时修改的屏蔽字，在没有信号需要处理时也要恢复
+                siginfo_mut_guard.restore_saved_sigmask();
                 return;
             }
 
@@ -478,6 +480,8 @@ impl SignalArch for X86_64SignalArch {
             // 如果当前动作是忽略这个信号，就继续循环。
         }
 
+        // 如果屏蔽字被sigsuspend等系统调用临时修改过，则信号处理函数返回后应当恢复原来的屏蔽字
+        siginfo_mut_guard.restore_saved_sigmask();
         let oldset = siginfo_mut_guard.sig_block().clone();
         //避免死锁
         drop(siginfo_mut_guard);
@@ -558,7 +562,7 @@ fn setup_frame(
     match sigaction.action() {
         SigactionType::SaHandler(handler_type) => match handler_type {
             SaHandlerType::SigDefault => {
-                sig.handle_default();
+                sig.handle_default(trap_frame);
                 return Ok(0);
             }
             SaHandlerType::SigCustomized(handler) => {
@@ -566,11 +570,11 @@ fn setup_frame(
                 if handler >= MMArch::USER_END_VADDR {
                     // 如果当前是SIGSEGV,则采用默认函数处理
                     if sig == Signal::SIGSEGV {
-                        sig.handle_default();
+                        sig.handle_default(trap_frame);
                         return Ok(0);
                     } else {
                         kerror!("attempting  to execute a signal handler from kernel");
-                        sig.handle_default();
+                        sig.handle_default(trap_frame);
                         return Err(SystemError::EINVAL);
                     }
                 } else {
@@ -674,6 +678,14 @@ fn setup_frame(
     // 禁用中断
     // trap_frame.rflags &= !(0x200);
 
+    // 信号处理函数执行期间，屏蔽sa_mask中的信号，以及（除非设置了SA_NODEFER）当前信号本身。
+    // 处理函数返回时，rt_sigreturn会从sigcontext中恢复oldset
+    let mut blocked = *oldset | sigaction.mask();
+    if !sigaction.flags().contains(SigFlags::SA_NODEFER) {
+        blocked.insert(sig.into_sigset());
+    }
+    set_current_sig_blocked(&mut blocked);
+
     return Ok(0);
 }
 
@@ -698,9 +710,9 @@ fn sig_terminate(sig: Signal) {
 }
 
 /// 信号默认处理函数——终止进程并生成 core dump
-fn sig_terminate_dump(sig: Signal) {
+fn sig_terminate_dump(sig: Signal, frame: &TrapFrame) {
+    do_coredump(sig, frame);
     ProcessManager::exit(sig as usize);
-    // TODO 生成 coredump 文件
 }
 
 /// 信号默认处理函数——暂停进程
@@ -0,0 +1,106 @@
+use crate::{
+    arch::{interrupt::TrapFrame, CurrentIrqArch},
+    exception::InterruptArch,
+    kerror,
+    mm::{
+        fault::{FaultFlags, PageFaultHandler, VmFaultReason},
+        VirtAddr,
+    },
+    process::ProcessManager,
+    smp::core::smp_get_processor_id,
+};
+
+use super::X86_64MMArch;
+
+bitflags! {
+    /// x86_64页错误的错误码
+    pub struct X86PfErrorCode: u64 {
+        /// 0: 页面不存在; 1: 页面存在，但是权限不允许本次访问
+        const X86_PF_PROT = 1 << 0;
+        /// 0: 读操作; 1: 写操作
+        const X86_PF_WRITE = 1 << 1;
+        /// 0: 内核态访问; 1: 用户态访问
+        const X86_PF_USER = 1 << 2;
+        /// 页表项的保留位被置位
+        const X86_PF_RSVD = 1 << 3;
+        /// 取指令引起的页错误
+        const X86_PF_INSTR = 1 << 4;
+        /// 保护密钥引起的页错误
+        const X86_PF_PK = 1 << 5;
+        /// 影子栈访问引起的页错误
+        const X86_PF_SHSTK = 1 << 6;
+        /// SGX相关的页错误
+        const X86_PF_SGX = 1 << 15;
+    }
+}
+
+impl X86_64MMArch {
+    /// 处理内核地址空间内的页错误
+    ///
+    /// 内核地址空间的页面都是预先映射的，因此这里发生页错误一定是内核的bug
+    pub fn do_kern_addr_fault(regs: &TrapFrame, error_code: X86PfErrorCode, address: VirtAddr) {
+        kerror!(
+            "Kernel page fault: address: {:?}, error_code: {:?}, rip: {:#x}, CPU: {}, pid: {:?}",
+            address,
+            error_code,
+            regs.rip,
+            smp_get_processor_id().data(),
+            ProcessManager::current_pid()
+        );
+        panic!("Kernel page fault");
+    }
+
+    /// 处理用户地址空间内的页错误
+    ///
+    /// 既可能是用户态程序的访问引起的，也可能是内核在系统调用中访问用户内存引起的
+    pub unsafe fn do_user_addr_fault(
+        regs: &TrapFrame,
+        error_code: X86PfErrorCode,
+        address: VirtAddr,
+    ) {
+        if error_code.contains(X86PfErrorCode::X86_PF_RSVD) {
+            kerror!(
+                "Reserved bit violation: address: {:?}, error_code: {:?}, rip: {:#x}",
+                address,
+                error_code,
+                regs.rip
+            );
+            panic!("Page fault caused by reserved bit violation");
+        }
+
+        let mut flags = FaultFlags::empty();
+        if error_code.contains(X86PfErrorCode::X86_PF_WRITE) {
+            flags |= FaultFlags::FAULT_FLAG_WRITE;
+        }
+        if error_code.contains(X86PfErrorCode::X86_PF_INSTR) {
+            flags |= FaultFlags::FAULT_FLAG_INSTRUCTION;
+        }
+        if error_code.contains(X86PfErrorCode::X86_PF_USER) {
+            flags |= FaultFlags::FAULT_FLAG_USER;
+        }
+
+        // 发生页错误时中断是开启的，则重新开启中断：文件映射的缺页需要读取文件，要等待块设备的中断
+        if regs.rflags & (1 << 9) != 0 {
+            CurrentIrqArch::interrupt_enable();
+        }
+
+        let reason = PageFaultHandler::do_user_addr_fault(address, flags);
+        if reason.contains(VmFaultReason::VM_FAULT_COMPLETED) {
+            return;
+        }
+
+        if !error_code.contains(X86PfErrorCode::X86_PF_USER) {
+            // 内核访问用户内存失败，目前还没有异常修复表，因此只能panic
+            kerror!(
+                "Kernel failed to access user memory: address: {:?}, error_code: {:?}, rip: {:#x}, reason: {:?}",
+                address,
+                error_code,
+                regs.rip,
+                reason
+            );
+            panic!("Page fault in kernel mode");
+        }
+
+        PageFaultHandler::send_sigsegv(address);
+    }
+}
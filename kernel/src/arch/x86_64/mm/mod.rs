@@ -1,6 +1,7 @@
 pub mod barrier;
 pub mod bump;
 mod c_adapter;
+pub mod fault;
 
 use alloc::vec::Vec;
 use hashbrown::HashSet;
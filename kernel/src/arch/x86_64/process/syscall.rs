@@ -75,17 +75,30 @@ impl Syscall {
 
         // 把proc_init_info写到用户栈上
 
+        // 用户栈的页面是按需分配的，写入时可能会触发缺页异常，因此不能在持有地址空间锁的情况下写用户栈
+        let mut ustack = unsafe {
+            address_space
+                .write()
+                .user_stack_mut()
+                .expect("No user stack found")
+                .clone_info_only()
+        };
         let (user_sp, argv_ptr) = unsafe {
             param
                 .init_info()
-                .push_at(
-                    address_space
-                        .write()
-                        .user_stack_mut()
-                        .expect("No user stack found"),
-                )
+                .push_at(&mut ustack)
                 .expect("Failed to push proc_init_info to user stack")
         };
+        {
+            let mut guard = address_space.write();
+            guard.user_stack = Some(ustack);
+            guard.saved_auxv = param
+                .init_info()
+                .auxv
+                .iter()
+                .map(|(&k, &v)| (k as usize, v))
+                .collect();
+        }
 
         // kdebug!("write proc_init_info to user stack done");
 
@@ -1,5 +1,57 @@
-use core::arch::x86_64::_rdtsc;
+use core::arch::asm;
 
-pub fn rand() -> usize {
-    return unsafe { (_rdtsc() * _rdtsc() + 998244353_u64 * _rdtsc()) as usize };
+use x86::cpuid::CpuId;
+
+/// RDRAND失败时的重试次数（Intel DRNG软件实现指南的建议值）
+const RDRAND_RETRY_LOOPS: usize = 10;
+
+lazy_static! {
+    static ref HAS_RDRAND: bool = CpuId::new()
+        .get_feature_info()
+        .map(|f| f.has_rdrand())
+        .unwrap_or(false);
+    static ref HAS_RDSEED: bool = CpuId::new()
+        .get_extended_feature_info()
+        .map(|f| f.has_rdseed())
+        .unwrap_or(false);
+}
+
+/// ## 使用RDRAND指令获取一个随机数
+///
+/// 如果CPU不支持RDRAND，或者多次重试后仍然失败，返回None
+pub fn arch_get_random_long() -> Option<u64> {
+    if !*HAS_RDRAND {
+        return None;
+    }
+
+    for _ in 0..RDRAND_RETRY_LOOPS {
+        let val: u64;
+        let ok: u8;
+        unsafe {
+            asm!("rdrand {0}", "setc {1}", out(reg) val, out(reg_byte) ok, options(nomem, nostack));
+        }
+        if ok != 0 {
+            return Some(val);
+        }
+    }
+
+    return None;
+}
+
+/// ## 使用RDSEED指令获取一个随机种子
+///
+/// RDSEED直接来自硬件熵源，熵耗尽时会失败，因此只尝试一次。
+/// 如果CPU不支持RDSEED或者获取失败，返回None
+pub fn arch_get_random_seed_long() -> Option<u64> {
+    if !*HAS_RDSEED {
+        return None;
+    }
+
+    let val: u64;
+    let ok: u8;
+    unsafe {
+        asm!("rdseed {0}", "setc {1}", out(reg) val, out(reg_byte) ok, options(nomem, nostack));
+    }
+
+    return if ok != 0 { Some(val) } else { None };
 }
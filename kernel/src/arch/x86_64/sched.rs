@@ -1,8 +1,11 @@
 use core::hint::spin_loop;
 
 use crate::{
-    exception::InterruptArch, include::bindings::bindings::enter_syscall_int, sched::SchedArch,
-    smp::core::smp_get_processor_id, syscall::SYS_SCHED,
+    exception::InterruptArch,
+    include::bindings::bindings::enter_syscall_int,
+    sched::{core::sched_cpu_activate, SchedArch},
+    smp::core::smp_get_processor_id,
+    syscall::SYS_SCHED,
 };
 
 use super::{driver::apic::apic_timer::apic_timer_init, CurrentIrqArch};
@@ -49,6 +52,8 @@ impl SchedArch for X86_64SchedArch {
                 BSP_INIT_OK = true;
             }
         }
+        // 时钟中断初始化完成之后，这个cpu才参与负载均衡
+        sched_cpu_activate(cpu_id);
 
         drop(irq_guard);
     }
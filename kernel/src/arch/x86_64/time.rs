@@ -1,4 +1,6 @@
-use crate::time::TimeArch;
+use crate::time::{TimeArch, NSEC_PER_MSEC};
+
+use super::driver::tsc::TSCManager;
 
 pub struct X86_64TimeArch;
 
@@ -6,4 +8,12 @@ impl TimeArch for X86_64TimeArch {
     fn get_cycles() -> usize {
         unsafe { x86::time::rdtsc() as usize }
     }
+
+    fn cycles2ns(cycles: usize) -> usize {
+        let khz = TSCManager::tsc_khz();
+        if khz == 0 {
+            return 0;
+        }
+        return (cycles as u128 * NSEC_PER_MSEC as u128 / khz as u128) as usize;
+    }
 }
@@ -1,6 +1,9 @@
 extern crate klog_types;
 
-use core::intrinsics::unlikely;
+use core::{
+    intrinsics::unlikely,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use klog_types::{AllocatorLog, AllocatorLogType, LogSource, MMLogChannel};
 
@@ -17,10 +20,11 @@ use crate::{
 static __MM_ALLOCATOR_LOG_CHANNEL: MMLogChannel<{ MMDebugLogManager::MAX_ALLOC_LOG_NUM }> =
     MMLogChannel::new(MMDebugLogManager::MAX_ALLOC_LOG_NUM);
 
-/// 全局的内存分配器日志id分配器
+/// 全局的内存分配器日志id
 ///
+/// 日志id只增不减，且记录日志时不能分配内存，因此不使用id分配器。
 /// id从1开始, 因为0是无效的id
-static __MM_DEBUG_LOG_IDA: ida::IdAllocator = ida::IdAllocator::new(1, usize::MAX);
+static __MM_DEBUG_LOG_ID: AtomicUsize = AtomicUsize::new(1);
 
 /// 记录内存分配器的日志
 ///
@@ -52,7 +56,7 @@ impl MMDebugLogManager {
     /// - `source`：日志来源
     /// - `pid`：日志来源的pid
     pub fn log(log_type: AllocatorLogType, source: LogSource, pid: Option<Pid>) {
-        let id = __MM_DEBUG_LOG_IDA.alloc().unwrap();
+        let id = __MM_DEBUG_LOG_ID.fetch_add(1, Ordering::SeqCst);
         let log = AllocatorLog::new(
             id as u64,
             log_type,
@@ -3,11 +3,11 @@ use crate::{
     driver::base::{
         device::{
             device_number::{DeviceNumber, Major},
-            Device, DeviceError, IdTable, BLOCKDEVS,
+            Device, DeviceError, IdTable, BDEVMAP, BLOCKDEVS,
         },
         map::{
-            DeviceStruct, DEV_MAJOR_DYN_END, DEV_MAJOR_DYN_EXT_END, DEV_MAJOR_DYN_EXT_START,
-            DEV_MAJOR_HASH_SIZE, DEV_MAJOR_MAX,
+            kobj_map, kobj_unmap, DeviceStruct, DEV_MAJOR_DYN_END, DEV_MAJOR_DYN_EXT_END,
+            DEV_MAJOR_DYN_EXT_START, DEV_MAJOR_HASH_SIZE, DEV_MAJOR_MAX,
         },
     },
     kerror,
@@ -427,11 +427,11 @@ impl BlockDeviceOps {
             kerror!("DEV {} minor range requested ({}-{}) is out of range of maximum range ({}-{}) for a single major\n",
                 name, baseminor, baseminor + minorct - 1, 0, DeviceNumber::MINOR_MASK);
         }
-        let blockdev = DeviceStruct::new(DeviceNumber::new(major, baseminor), minorct, name);
         if major == Major::UNNAMED_MAJOR {
             // 如果主设备号为0,则自动分配主设备号
-            major = Self::find_dynamic_major().expect("Find synamic major error.\n");
+            major = Self::find_dynamic_major()?;
         }
+        let blockdev = DeviceStruct::new(DeviceNumber::new(major, baseminor), minorct, name);
         if let Some(items) = BLOCKDEVS.lock().get_mut(Self::major_to_index(major)) {
             let mut insert_index: usize = 0;
             for (index, item) in items.iter().enumerate() {
@@ -483,25 +483,29 @@ impl BlockDeviceOps {
     }
 
     /// @brief: 块设备注册
-    /// @parameter: cdev: 字符设备实例
-    ///             dev_t: 字符设备号
+    /// @parameter: bdev: 块设备实例
+    ///             id_table: 块设备的设备号
     ///             range: 次设备号范围
     /// @return: none
-    #[allow(dead_code)]
-    pub fn bdev_add(_bdev: Arc<dyn BlockDevice>, id_table: IdTable) -> Result<(), DeviceError> {
+    pub fn bdev_add(
+        bdev: Arc<dyn BlockDevice>,
+        id_table: IdTable,
+        range: usize,
+    ) -> Result<(), DeviceError> {
         if id_table.device_number().data() == 0 {
             kerror!("Device number can't be 0!\n");
+            return Err(DeviceError::RegisterError);
         }
-        todo!("bdev_add")
-        // return device_manager().add_device(bdev.id_table(), bdev.device());
+        kobj_map(BDEVMAP.clone(), id_table.device_number(), range, bdev);
+        return Ok(());
     }
 
     /// @brief: block设备注销
-    /// @parameter: dev_t: 字符设备号
+    /// @parameter: dev_t: 块设备号
     ///             range: 次设备号范围
     /// @return: none
     #[allow(dead_code)]
-    pub fn bdev_del(_devnum: DeviceNumber, _range: usize) {
-        unimplemented!();
+    pub fn bdev_del(devnum: DeviceNumber, range: usize) {
+        kobj_unmap(BDEVMAP.clone(), devnum, range);
     }
 }
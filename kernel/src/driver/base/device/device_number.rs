@@ -9,6 +9,9 @@ impl Major {
     /// 未命名的主设备
     pub const UNNAMED_MAJOR: Self = Self::new(0);
 
+    /// /dev/null, /dev/zero, /dev/random等内存设备
+    pub const MEM_MAJOR: Self = Self::new(1);
+
     pub const IDE0_MAJOR: Self = Self::new(3);
     pub const TTY_MAJOR: Self = Self::new(4);
     pub const TTYAUX_MAJOR: Self = Self::new(5);
@@ -18,6 +21,11 @@ impl Major {
     /// /dev/fb* framebuffers
     pub const FB_MAJOR: Self = Self::new(29);
 
+    /// Unix98 pty的主设备
+    pub const UNIX98_PTY_MASTER_MAJOR: Self = Self::new(128);
+    /// Unix98 pty的从设备(/dev/pts/*)
+    pub const UNIX98_PTY_SLAVE_MAJOR: Self = Self::new(136);
+
     pub const fn new(x: u32) -> Self {
         Major(x)
     }
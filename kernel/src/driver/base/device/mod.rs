@@ -59,6 +59,9 @@ lazy_static! {
     // 全局设备管理实例
     pub static ref DEVMAP: Arc<LockedKObjMap> = Arc::new(LockedKObjMap::default());
 
+    // 全局块设备实例
+    pub static ref BDEVMAP: Arc<LockedKObjMap> = Arc::new(LockedKObjMap::default());
+
 }
 
 /// `/sys/devices` 的 kset 实例
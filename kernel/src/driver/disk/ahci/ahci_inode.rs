@@ -63,6 +63,11 @@ impl LockedAhciInode {
 
         return result;
     }
+
+    /// 获取inode对应的磁盘
+    pub fn disk(&self) -> Arc<LockedAhciDisk> {
+        return self.0.lock().disk.clone();
+    }
 }
 
 impl DeviceINode for LockedAhciInode {
@@ -0,0 +1,623 @@
+//! virtio-blk块设备驱动
+//!
+//! 参考：https://code.dragonos.org.cn/xref/linux-6.1.9/drivers/block/virtio_blk.c
+
+use core::{fmt::Debug, hint::spin_loop};
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use ida::IdAllocator;
+use system_error::SystemError;
+use virtio_drivers::{
+    device::blk::{BlkReq, BlkResp, VirtIOBlk, SECTOR_SIZE},
+    transport::Transport,
+};
+
+use crate::{
+    arch::CurrentIrqArch,
+    driver::{
+        base::{
+            block::{
+                block_device::{BlockDevice, BlockDeviceOps, BlockId, LBA_SIZE},
+                disk_info::Partition,
+            },
+            class::Class,
+            device::{
+                bus::Bus,
+                device_number::{DeviceNumber, Major},
+                driver::Driver,
+                Device, DeviceId, DeviceType, IdTable,
+            },
+            kobject::{KObjType, KObject, KObjectState, LockedKObjectState},
+            kset::KSet,
+        },
+        virtio::{irq::virtio_irq_manager, virtio_impl::HalImpl, VirtIODevice},
+    },
+    exception::{irqdesc::IrqReturn, InterruptArch, IrqNumber},
+    filesystem::{devfs::devfs_register, kernfs::KernFSInode, mbr::MbrDiskPartionTable},
+    kerror, kinfo,
+    libs::{
+        rwlock::{RwLockReadGuard, RwLockWriteGuard},
+        spinlock::SpinLock,
+        wait_queue::WaitQueue,
+    },
+    process::ProcessManager,
+};
+
+use self::virtio_blk_inode::LockedVirtIOBlkInode;
+
+pub mod virtio_blk_inode;
+
+/// 每个virtio-blk磁盘占用的次设备号数量的log2（磁盘本身和它的分区）
+const VIRTIO_BLK_PART_BITS: u32 = 4;
+/// 最多支持的virtio-blk磁盘数量
+const VIRTIO_BLK_MAX_DISKS: usize = 1 << (DeviceNumber::MINOR_BITS - VIRTIO_BLK_PART_BITS);
+
+/// virtio-blk磁盘编号分配器
+static VIRTIO_BLK_IDA: SpinLock<IdAllocator> =
+    SpinLock::new(IdAllocator::new(0, VIRTIO_BLK_MAX_DISKS));
+/// virtio-blk的主设备号，在第一个磁盘注册时动态分配
+static VIRTIO_BLK_MAJOR: SpinLock<Option<Major>> = SpinLock::new(None);
+/// 所有的virtio-blk磁盘
+static VIRTIO_BLK_DISKS: SpinLock<Vec<Arc<dyn BlockDevice>>> = SpinLock::new(Vec::new());
+
+/// ## 获取所有的virtio-blk磁盘
+pub fn virtio_blk_disks() -> Vec<Arc<dyn BlockDevice>> {
+    VIRTIO_BLK_DISKS.lock().clone()
+}
+
+/// ## 根据磁盘编号生成磁盘名称
+///
+/// 与linux相同：vda, vdb, ..., vdz, vdaa, vdab, ...
+fn virtio_blk_name(mut index: usize) -> String {
+    let mut suffix = Vec::new();
+    loop {
+        suffix.push(b'a' + (index % 26) as u8);
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+    suffix.reverse();
+    return format!("vd{}", String::from_utf8(suffix).unwrap());
+}
+
+fn virtio_error_to_system_error(err: virtio_drivers::Error) -> SystemError {
+    match err {
+        virtio_drivers::Error::NotReady => SystemError::EAGAIN_OR_EWOULDBLOCK,
+        virtio_drivers::Error::InvalidParam => SystemError::EINVAL,
+        virtio_drivers::Error::Unsupported => SystemError::EOPNOTSUPP_OR_ENOTSUP,
+        virtio_drivers::Error::QueueFull => SystemError::EBUSY,
+        _ => SystemError::EIO,
+    }
+}
+
+/// 一个已经提交给设备，还未完成的扇区读写请求
+struct VirtIOBlkRequest {
+    /// 请求的编号，用于在请求完成后找到它的提交者
+    id: u64,
+    req: BlkReq,
+    resp: BlkResp,
+    /// 数据缓冲区，长度为一个扇区
+    buf: *mut u8,
+    write: bool,
+}
+
+// 提交者在请求完成之前会一直等待，因此在请求完成之前，buf一直有效
+unsafe impl Send for VirtIOBlkRequest {}
+
+struct InnerVirtIOBlk<T: Transport> {
+    device: VirtIOBlk<HalImpl, T>,
+    /// 已提交给设备的请求，以virtqueue的token为键
+    pending: BTreeMap<u16, Box<VirtIOBlkRequest>>,
+    /// 已完成的请求的结果，以请求的编号为键
+    completed: BTreeMap<u64, Result<(), SystemError>>,
+    /// 下一个请求的编号
+    next_id: u64,
+}
+
+// InnerVirtIOBlk只会在持有锁的情况下被访问
+unsafe impl<T: Transport> Send for InnerVirtIOBlk<T> {}
+
+impl<T: Transport> InnerVirtIOBlk<T> {
+    /// 提交一个扇区的读写请求
+    ///
+    /// ## 安全性
+    ///
+    /// 在请求完成之前，`buf`开始的一个扇区必须一直有效
+    unsafe fn submit(
+        &mut self,
+        sector: BlockId,
+        buf: *mut u8,
+        write: bool,
+    ) -> Result<u64, virtio_drivers::Error> {
+        let mut request = Box::new(VirtIOBlkRequest {
+            id: self.next_id,
+            req: BlkReq::default(),
+            resp: BlkResp::default(),
+            buf,
+            write,
+        });
+
+        let token = if write {
+            let data = core::slice::from_raw_parts(buf, SECTOR_SIZE);
+            self.device
+                .write_block_nb(sector, &mut request.req, data, &mut request.resp)?
+        } else {
+            let data = core::slice::from_raw_parts_mut(buf, SECTOR_SIZE);
+            self.device
+                .read_block_nb(sector, &mut request.req, data, &mut request.resp)?
+        };
+
+        let id = request.id;
+        self.next_id += 1;
+        self.pending.insert(token, request);
+        return Ok(id);
+    }
+
+    /// 回收设备已经处理完成的请求，返回是否有请求完成
+    fn complete_used(&mut self) -> bool {
+        let mut completed = false;
+        while let Some(token) = self.device.peek_used() {
+            let mut request = match self.pending.remove(&token) {
+                Some(request) => request,
+                None => {
+                    kerror!("virtio-blk: unknown token {} in used ring", token);
+                    break;
+                }
+            };
+
+            let result = unsafe {
+                if request.write {
+                    let data = core::slice::from_raw_parts(request.buf, SECTOR_SIZE);
+                    self.device
+                        .complete_write_block(token, &request.req, data, &mut request.resp)
+                } else {
+                    let data = core::slice::from_raw_parts_mut(request.buf, SECTOR_SIZE);
+                    self.device
+                        .complete_read_block(token, &request.req, data, &mut request.resp)
+                }
+            };
+
+            self.completed
+                .insert(request.id, result.map_err(virtio_error_to_system_error));
+            completed = true;
+        }
+
+        return completed;
+    }
+}
+
+/// virtio-blk磁盘
+///
+/// 每个扇区的读写都是一个单独的请求，一次读写会把尽可能多的请求同时放入virtqueue中，
+/// 设备处理完请求后发出中断，由中断处理函数回收请求并唤醒等待的进程
+pub struct VirtIOBlkDevice<T: Transport> {
+    name: String,
+    /// 磁盘编号
+    index: usize,
+    /// 磁盘的设备号，分区的设备号依次递增
+    devnum: DeviceNumber,
+    dev_id: Arc<DeviceId>,
+    /// 磁盘的扇区数
+    capacity: u64,
+    inner: SpinLock<InnerVirtIOBlk<T>>,
+    /// 等待请求完成的进程
+    wait_queue: WaitQueue,
+    partitions: SpinLock<Vec<Arc<Partition>>>,
+    kobj_inner: SpinLock<InnerVirtIOBlkKObject>,
+    kobj_state: LockedKObjectState,
+    self_ref: Weak<Self>,
+}
+
+/// virtio-blk磁盘在设备模型中的状态
+#[derive(Debug, Default)]
+struct InnerVirtIOBlkKObject {
+    bus: Option<Weak<dyn Bus>>,
+    class: Option<Arc<dyn Class>>,
+    driver: Option<Weak<dyn Driver>>,
+    kern_inode: Option<Arc<KernFSInode>>,
+    parent: Option<Weak<dyn KObject>>,
+    kset: Option<Arc<KSet>>,
+    kobj_type: Option<&'static dyn KObjType>,
+}
+
+impl<T: Transport> Debug for VirtIOBlkDevice<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("VirtIOBlkDevice")
+            .field("name", &self.name)
+            .field("devnum", &self.devnum)
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+impl<T: Transport + 'static> VirtIOBlkDevice<T> {
+    fn new(
+        device: VirtIOBlk<HalImpl, T>,
+        index: usize,
+        devnum: DeviceNumber,
+        dev_id: Arc<DeviceId>,
+    ) -> Arc<Self> {
+        let capacity = device.capacity();
+        return Arc::new_cyclic(|self_ref| Self {
+            name: virtio_blk_name(index),
+            index,
+            devnum,
+            dev_id,
+            capacity,
+            inner: SpinLock::new(InnerVirtIOBlk {
+                device,
+                pending: BTreeMap::new(),
+                completed: BTreeMap::new(),
+                next_id: 0,
+            }),
+            wait_queue: WaitQueue::INIT,
+            partitions: SpinLock::new(Vec::new()),
+            kobj_inner: SpinLock::new(InnerVirtIOBlkKObject::default()),
+            kobj_state: LockedKObjectState::new(None),
+            self_ref: self_ref.clone(),
+        });
+    }
+
+    /// 磁盘的设备号
+    pub fn devnum(&self) -> DeviceNumber {
+        self.devnum
+    }
+
+    /// 读写从`lba_id_start`开始的`count`个扇区
+    ///
+    /// 函数返回时，所有已经提交的请求都已经完成，因此`buf`只需要在函数执行期间有效。
+    ///
+    /// 调用者持有自旋锁或者关闭了中断时（例如文件系统在持有inode的锁的情况下读写磁盘），不能睡眠，
+    /// 此时通过轮询设备的used ring来等待请求完成
+    fn transfer(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: *mut u8,
+        write: bool,
+    ) -> Result<(), SystemError> {
+        if lba_id_start as u64 + count as u64 > self.capacity {
+            return Err(SystemError::EINVAL);
+        }
+
+        let can_sleep = ProcessManager::initialized()
+            && CurrentIrqArch::is_irq_enabled()
+            && ProcessManager::current_pcb().preempt_count() == 0;
+
+        let mut submitted = 0;
+        let mut in_flight: Vec<u64> = Vec::new();
+        let mut result = Ok(());
+
+        loop {
+            let mut inner = self.inner.lock_irqsave();
+
+            // 把尽可能多的请求放入virtqueue
+            while submitted < count {
+                let sector_buf = unsafe { buf.add(submitted * LBA_SIZE) };
+                match unsafe { inner.submit(lba_id_start + submitted, sector_buf, write) } {
+                    Ok(id) => {
+                        in_flight.push(id);
+                        submitted += 1;
+                    }
+                    Err(virtio_drivers::Error::QueueFull) => break,
+                    Err(e) => {
+                        // 不再提交新的请求，但仍需等待已提交的请求完成
+                        result = Err(virtio_error_to_system_error(e));
+                        submitted = count;
+                    }
+                }
+            }
+
+            // 中断可能在提交请求之前就已经到来，这里也回收一次
+            if inner.complete_used() {
+                self.wait_queue.wakeup_all(None);
+            }
+
+            in_flight.retain(|id| match inner.completed.remove(id) {
+                Some(r) => {
+                    if r.is_err() {
+                        result = r;
+                    }
+                    false
+                }
+                None => true,
+            });
+
+            if in_flight.is_empty() && submitted >= count {
+                return result;
+            }
+
+            if can_sleep {
+                // 已提交的请求完成之前，buf必须保持有效，因此不能被信号打断
+                self.wait_queue.sleep_uninterruptible_unlock_spinlock(inner);
+            } else {
+                drop(inner);
+                spin_loop();
+            }
+        }
+    }
+}
+
+impl<T: Transport + 'static> VirtIODevice for VirtIOBlkDevice<T> {
+    fn handle_irq(&self, _irq: IrqNumber) -> Result<IrqReturn, SystemError> {
+        let mut inner = self.inner.lock_irqsave();
+        inner.device.ack_interrupt();
+        let completed = inner.complete_used();
+        drop(inner);
+
+        if completed {
+            self.wait_queue.wakeup_all(None);
+        }
+        return Ok(IrqReturn::Handled);
+    }
+
+    fn dev_id(&self) -> &Arc<DeviceId> {
+        return &self.dev_id;
+    }
+}
+
+impl<T: Transport + 'static> BlockDevice for VirtIOBlkDevice<T> {
+    fn read_at(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        if count * LBA_SIZE > buf.len() {
+            return Err(SystemError::EINVAL);
+        }
+        self.transfer(lba_id_start, count, buf.as_mut_ptr(), false)?;
+        return Ok(count * LBA_SIZE);
+    }
+
+    fn write_at(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &[u8],
+    ) -> Result<usize, SystemError> {
+        if count * LBA_SIZE > buf.len() {
+            return Err(SystemError::EINVAL);
+        }
+        // 写请求只会读取缓冲区中的数据
+        self.transfer(lba_id_start, count, buf.as_ptr() as *mut u8, true)?;
+        return Ok(count * LBA_SIZE);
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        // 每个写请求都在设备确认之后才返回，因此没有需要写回的数据
+        return Ok(());
+    }
+
+    #[inline]
+    fn blk_size_log2(&self) -> u8 {
+        9
+    }
+
+    #[inline]
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    #[inline]
+    fn device(&self) -> Arc<dyn Device> {
+        return self.self_ref.upgrade().unwrap();
+    }
+
+    fn block_size(&self) -> usize {
+        LBA_SIZE
+    }
+
+    fn partitions(&self) -> Vec<Arc<Partition>> {
+        return self.partitions.lock().clone();
+    }
+}
+
+impl<T: Transport + 'static> KObject for VirtIOBlkDevice<T> {
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.kobj_inner.lock().kern_inode.clone()
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.kobj_inner.lock().kobj_type
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.kobj_inner.lock().kset.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.kobj_inner.lock().parent.clone()
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.kobj_inner.lock().kern_inode = inode;
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state.write() = state;
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn set_name(&self, _name: String) {
+        // 磁盘的名称由磁盘编号决定，不能修改
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.kobj_inner.lock().kset = kset;
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.kobj_inner.lock().parent = parent;
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.kobj_inner.lock().kobj_type = ktype;
+    }
+}
+
+impl<T: Transport + 'static> Device for VirtIOBlkDevice<T> {
+    fn dev_type(&self) -> DeviceType {
+        return DeviceType::Block;
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new(self.name.clone(), Some(self.devnum))
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        self.kobj_inner.lock().bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Weak<dyn Bus>>) {
+        self.kobj_inner.lock().bus = bus;
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        self.kobj_inner.lock().driver.clone()?.upgrade()
+    }
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn set_driver(&self, driver: Option<Weak<dyn Driver>>) {
+        self.kobj_inner.lock().driver = driver;
+    }
+
+    fn can_match(&self) -> bool {
+        true
+    }
+
+    fn set_can_match(&self, _can_match: bool) {}
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+
+    fn set_class(&self, class: Option<Arc<dyn Class>>) {
+        self.kobj_inner.lock().class = class;
+    }
+}
+
+impl<T: Transport> Drop for VirtIOBlkDevice<T> {
+    fn drop(&mut self) {
+        VIRTIO_BLK_IDA.lock().free(self.index);
+    }
+}
+
+/// 为virtio-blk磁盘分配设备号
+fn virtio_blk_alloc_devnum(index: usize) -> Result<DeviceNumber, SystemError> {
+    let minors = 1 << VIRTIO_BLK_PART_BITS;
+    let baseminor = (index as u32) << VIRTIO_BLK_PART_BITS;
+
+    let mut major = VIRTIO_BLK_MAJOR.lock();
+    let devnum = match *major {
+        Some(major) => BlockDeviceOps::register_blockdev_region(
+            DeviceNumber::new(major, baseminor),
+            minors,
+            "virtblk",
+        )?,
+        None => {
+            let devnum = BlockDeviceOps::alloc_blockdev_region(baseminor, minors, "virtblk")?;
+            *major = Some(devnum.major());
+            devnum
+        }
+    };
+
+    return Ok(devnum);
+}
+
+/// 注册virtio-blk磁盘，扫描它的分区，并在devfs中创建对应的设备节点
+fn virtio_blk_register<T: Transport + 'static>(
+    device: VirtIOBlk<HalImpl, T>,
+    dev_id: Arc<DeviceId>,
+) -> Result<Arc<VirtIOBlkDevice<T>>, SystemError> {
+    let index = VIRTIO_BLK_IDA.lock().alloc().ok_or(SystemError::ENOSPC)?;
+    let devnum = match virtio_blk_alloc_devnum(index) {
+        Ok(devnum) => devnum,
+        Err(e) => {
+            VIRTIO_BLK_IDA.lock().free(index);
+            return Err(e);
+        }
+    };
+
+    let disk = VirtIOBlkDevice::new(device, index, devnum, dev_id);
+    // 读取分区表需要中断来通知请求完成
+    virtio_irq_manager().register_device(disk.clone())?;
+
+    let bdev: Arc<dyn BlockDevice> = disk.clone();
+    match MbrDiskPartionTable::from_disk(&bdev) {
+        Ok(table) => {
+            *disk.partitions.lock() = table.partitions(Arc::downgrade(&bdev));
+        }
+        Err(e) => {
+            kerror!("{}: failed to read partition table: {:?}", disk.name, e);
+        }
+    }
+
+    BlockDeviceOps::bdev_add(bdev.clone(), disk.id_table(), 1 << VIRTIO_BLK_PART_BITS)
+        .map_err(Into::<SystemError>::into)?;
+
+    devfs_register(
+        &disk.name,
+        LockedVirtIOBlkInode::new(bdev.clone(), None, disk.capacity as usize),
+    )?;
+    for partition in disk.partitions() {
+        let name = format!("{}{}", disk.name, partition.partno + 1);
+        devfs_register(
+            &name,
+            LockedVirtIOBlkInode::new(bdev.clone(), Some(partition), disk.capacity as usize),
+        )?;
+    }
+
+    VIRTIO_BLK_DISKS.lock().push(bdev);
+    return Ok(disk);
+}
+
+/// @brief virtio-blk 驱动的初始化
+pub fn virtio_blk<T: Transport + 'static>(transport: T, dev_id: Arc<DeviceId>) {
+    let device = match VirtIOBlk::<HalImpl, T>::new(transport) {
+        Ok(device) => device,
+        Err(e) => {
+            kerror!("VirtIOBlk init failed: {:?}", e);
+            return;
+        }
+    };
+
+    match virtio_blk_register(device, dev_id) {
+        Ok(disk) => {
+            kinfo!(
+                "Virtio-blk driver init successfully!\tDisk: [{}], sectors: [{}], partitions: [{}]",
+                disk.name,
+                disk.capacity,
+                disk.partitions.lock().len()
+            );
+        }
+        Err(e) => {
+            kerror!("Failed to register virtio-blk device: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use crate::driver::base::block::block_device::{BlockDevice, LBA_SIZE};
+use crate::driver::base::block::disk_info::Partition;
+use crate::driver::base::device::device_number::DeviceNumber;
+use crate::filesystem::devfs::{DevFS, DeviceINode};
+use crate::filesystem::vfs::file::FileMode;
+use crate::filesystem::vfs::syscall::ModeType;
+use crate::filesystem::vfs::{
+    core::generate_inode_id, FilePrivateData, FileSystem, FileType, IndexNode, Metadata,
+};
+use crate::{libs::spinlock::SpinLock, time::TimeSpec};
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+#[derive(Debug)]
+pub struct VirtIOBlkInode {
+    /// 指向自身的弱引用
+    self_ref: Weak<LockedVirtIOBlkInode>,
+    /// 指向inode所在的文件系统对象的指针
+    fs: Weak<DevFS>,
+    /// INode 元数据
+    metadata: Metadata,
+    /// INode 对应的磁盘
+    disk: Arc<dyn BlockDevice>,
+    /// INode 对应的分区，为None时表示整个磁盘
+    partition: Option<Arc<Partition>>,
+}
+
+/// virtio-blk磁盘（/dev/vda）或者磁盘上的分区（/dev/vda1）
+#[derive(Debug)]
+pub struct LockedVirtIOBlkInode(pub SpinLock<VirtIOBlkInode>);
+
+impl LockedVirtIOBlkInode {
+    /// ## 参数
+    ///
+    /// - `disk`：inode对应的磁盘
+    /// - `partition`：inode对应的分区，为None时表示整个磁盘
+    /// - `sectors`：整个磁盘的扇区数
+    pub fn new(
+        disk: Arc<dyn BlockDevice>,
+        partition: Option<Arc<Partition>>,
+        sectors: usize,
+    ) -> Arc<Self> {
+        let devnum = disk.device().id_table().device_number();
+        let (raw_dev, sectors) = match &partition {
+            Some(p) => (
+                DeviceNumber::new(devnum.major(), devnum.minor() + p.partno as u32 + 1),
+                p.sectors_num as usize,
+            ),
+            None => (devnum, sectors),
+        };
+
+        let inode = VirtIOBlkInode {
+            self_ref: Weak::default(),
+            fs: Weak::default(),
+            metadata: Metadata {
+                dev_id: 1,
+                inode_id: generate_inode_id(),
+                size: (sectors * LBA_SIZE) as i64,
+                blk_size: LBA_SIZE,
+                blocks: sectors,
+                atime: TimeSpec::default(),
+                mtime: TimeSpec::default(),
+                ctime: TimeSpec::default(),
+                file_type: FileType::BlockDevice,
+                mode: ModeType::from_bits_truncate(0o660),
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev,
+            },
+            disk,
+            partition,
+        };
+
+        let result = Arc::new(LockedVirtIOBlkInode(SpinLock::new(inode)));
+        result.0.lock().self_ref = Arc::downgrade(&result);
+
+        return result;
+    }
+
+    /// 获取inode对应的磁盘
+    pub fn disk(&self) -> Arc<dyn BlockDevice> {
+        return self.0.lock().disk.clone();
+    }
+
+    /// 获取inode对应的分区，为None时表示整个磁盘
+    pub fn partition(&self) -> Option<Arc<Partition>> {
+        return self.0.lock().partition.clone();
+    }
+
+    /// 把inode内的字节偏移量转换为磁盘上的字节偏移量，并限制读写的长度不超出分区的范围
+    fn disk_range(&self, offset: usize, len: usize) -> (Arc<dyn BlockDevice>, usize, usize) {
+        let inode = self.0.lock();
+        let disk = inode.disk.clone();
+        match &inode.partition {
+            Some(p) => {
+                let part_size = p.sectors_num as usize * LBA_SIZE;
+                let len = len.min(part_size.saturating_sub(offset));
+                (disk, p.lba_start as usize * LBA_SIZE + offset, len)
+            }
+            None => (disk, offset, len),
+        }
+    }
+}
+
+impl DeviceINode for LockedVirtIOBlkInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        self.0.lock().fs = fs;
+    }
+}
+
+impl IndexNode for LockedVirtIOBlkInode {
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn open(&self, _data: &mut FilePrivateData, _mode: &FileMode) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn close(&self, _data: &mut FilePrivateData) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        return Ok(self.0.lock().metadata.clone());
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.0.lock().fs.upgrade().unwrap();
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        inode.metadata.atime = metadata.atime;
+        inode.metadata.mtime = metadata.mtime;
+        inode.metadata.ctime = metadata.ctime;
+        inode.metadata.mode = metadata.mode;
+        inode.metadata.uid = metadata.uid;
+        inode.metadata.gid = metadata.gid;
+
+        return Ok(());
+    }
+
+    /// 读设备，offset为分区（或整个磁盘）内的字节偏移量
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+
+        // 磁盘读写会睡眠，不能持有inode的锁
+        let (disk, offset, len) = self.disk_range(offset, len);
+        if len == 0 {
+            return Ok(0);
+        }
+        return disk.read_at_bytes(offset, len, buf);
+    }
+
+    /// 写设备，offset为分区（或整个磁盘）内的字节偏移量
+    fn write_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &[u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+
+        let (disk, offset, len) = self.disk_range(offset, len);
+        if len == 0 {
+            return Err(SystemError::ENOSPC);
+        }
+        return disk.write_at_bytes(offset, len, buf);
+    }
+}
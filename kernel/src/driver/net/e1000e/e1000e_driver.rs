@@ -6,7 +6,7 @@ use crate::{
             device::{bus::Bus, driver::Driver, Device, IdTable},
             kobject::{KObjType, KObject, KObjectState},
         },
-        net::NetDriver,
+        net::{IfFlags, NetDriver},
     },
     kinfo,
     libs::spinlock::SpinLock,
@@ -69,7 +69,9 @@ pub struct E1000EInterface {
     driver: E1000EDriverWrapper,
     iface_id: usize,
     iface: SpinLock<smoltcp::iface::Interface>,
+    sockets: SpinLock<smoltcp::iface::SocketSet<'static>>,
     name: String,
+    flags: SpinLock<IfFlags>,
 }
 impl phy::RxToken for E1000ERxToken {
     fn consume<R, F>(mut self, f: F) -> R
@@ -187,7 +189,11 @@ impl E1000EInterface {
             driver,
             iface_id,
             iface: SpinLock::new(iface),
+            sockets: SpinLock::new(smoltcp::iface::SocketSet::new(vec![])),
             name: format!("eth{}", iface_id),
+            flags: SpinLock::new(
+                IfFlags::UP | IfFlags::BROADCAST | IfFlags::RUNNING | IfFlags::MULTICAST,
+            ),
         });
 
         return result;
@@ -273,6 +279,21 @@ impl NetDriver for E1000EInterface {
         return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
     }
 
+    #[inline(always)]
+    fn sockets(&self) -> &SpinLock<smoltcp::iface::SocketSet<'static>> {
+        return &self.sockets;
+    }
+
+    #[inline]
+    fn flags(&self) -> IfFlags {
+        return *self.flags.lock();
+    }
+
+    #[inline]
+    fn set_flags(&self, flags: IfFlags) {
+        *self.flags.lock() = flags;
+    }
+
     #[inline(always)]
     fn inner_iface(&self) -> &SpinLock<smoltcp::iface::Interface> {
         return &self.iface;
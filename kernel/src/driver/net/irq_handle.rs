@@ -7,7 +7,7 @@ use crate::{
         irqdesc::{IrqHandler, IrqReturn},
         IrqNumber,
     },
-    net::net_core::poll_ifaces_try_lock_onetime,
+    net::net_core::wakeup_net_rx_thread,
 };
 
 /// 默认的网卡中断处理函数
@@ -21,7 +21,8 @@ impl IrqHandler for DefaultNetIrqHandler {
         _static_data: Option<&dyn IrqHandlerData>,
         _dynamic_data: Option<Arc<dyn IrqHandlerData>>,
     ) -> Result<IrqReturn, SystemError> {
-        poll_ifaces_try_lock_onetime().ok();
+        // 中断上下文中不处理数据包，交给网络接收线程轮询网卡
+        wakeup_net_rx_thread();
         Ok(IrqReturn::Handled)
     }
 }
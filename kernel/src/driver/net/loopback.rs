@@ -0,0 +1,275 @@
+//! 回环网络接口
+//!
+//! 发送到回环接口的数据包会直接放入它的接收队列，在下一次轮询时被协议栈处理。
+//! 即使没有网卡，本机的进程之间也能够通过127.0.0.1和::1通信
+
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use smoltcp::{
+    iface::SocketSet,
+    phy::{Loopback, Medium},
+    wire::{self, IpAddress, IpCidr},
+};
+use system_error::SystemError;
+
+use crate::{
+    driver::base::{
+        device::{bus::Bus, driver::Driver, Device, IdTable},
+        kobject::{KObjType, KObject, KObjectState, LockedKObjectState},
+        kset::KSet,
+    },
+    filesystem::kernfs::KernFSInode,
+    kinfo,
+    libs::{
+        rwlock::{RwLockReadGuard, RwLockWriteGuard},
+        spinlock::SpinLock,
+    },
+    net::{generate_iface_id, NET_DRIVERS},
+    time::Instant,
+};
+
+use super::{IfFlags, NetDriver};
+
+/// 回环接口的名称
+pub const LOOPBACK_IFACE_NAME: &str = "lo";
+
+pub struct LoopbackInterface {
+    /// smoltcp提供的回环设备，发送的数据包保存在它的队列中
+    device: SpinLock<Loopback>,
+    iface_id: usize,
+    iface: SpinLock<smoltcp::iface::Interface>,
+    sockets: SpinLock<SocketSet<'static>>,
+    name: String,
+    flags: SpinLock<IfFlags>,
+    kobj_inner: SpinLock<InnerLoopbackKObject>,
+    kobj_state: LockedKObjectState,
+}
+
+/// 回环接口在设备模型中的状态
+#[derive(Debug, Default)]
+struct InnerLoopbackKObject {
+    bus: Option<Weak<dyn Bus>>,
+    devices: Vec<Arc<dyn Device>>,
+    kern_inode: Option<Arc<KernFSInode>>,
+    parent: Option<Weak<dyn KObject>>,
+    kset: Option<Arc<KSet>>,
+    kobj_type: Option<&'static dyn KObjType>,
+}
+
+impl core::fmt::Debug for LoopbackInterface {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("LoopbackInterface")
+            .field("iface_id", &self.iface_id)
+            .field("iface", &"smoltcp::iface::Interface")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl LoopbackInterface {
+    pub fn new() -> Arc<Self> {
+        let iface_id = generate_iface_id();
+        let mut device = Loopback::new(Medium::Ip);
+
+        let mut iface_config = smoltcp::iface::Config::new();
+        // todo: 随机设定这个值。
+        // 参见 https://docs.rs/smoltcp/latest/smoltcp/iface/struct.Config.html#structfield.random_seed
+        iface_config.random_seed = 12345;
+        // 回环接口工作在IP层，没有硬件地址
+        iface_config.hardware_addr = None;
+
+        let mut iface = smoltcp::iface::Interface::new(iface_config, &mut device);
+        iface.update_ip_addrs(|addrs| {
+            addrs
+                .push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
+                .expect("Push ipCidr failed: full");
+            addrs
+                .push(IpCidr::new(IpAddress::v6(0, 0, 0, 0, 0, 0, 0, 1), 128))
+                .expect("Push ipCidr failed: full");
+        });
+
+        return Arc::new(LoopbackInterface {
+            device: SpinLock::new(device),
+            iface_id,
+            iface: SpinLock::new(iface),
+            sockets: SpinLock::new(SocketSet::new(vec![])),
+            name: LOOPBACK_IFACE_NAME.to_string(),
+            flags: SpinLock::new(IfFlags::UP | IfFlags::LOOPBACK | IfFlags::RUNNING),
+            kobj_inner: SpinLock::new(InnerLoopbackKObject::default()),
+            kobj_state: LockedKObjectState::new(None),
+        });
+    }
+}
+
+impl Driver for LoopbackInterface {
+    fn id_table(&self) -> Option<IdTable> {
+        // 回环接口不挂在任何总线上，不需要与设备匹配
+        None
+    }
+
+    fn add_device(&self, device: Arc<dyn Device>) {
+        let mut guard = self.kobj_inner.lock();
+        if guard.devices.iter().any(|dev| Arc::ptr_eq(dev, &device)) {
+            return;
+        }
+        guard.devices.push(device);
+    }
+
+    fn delete_device(&self, device: &Arc<dyn Device>) {
+        self.kobj_inner
+            .lock()
+            .devices
+            .retain(|dev| !Arc::ptr_eq(dev, device));
+    }
+
+    fn devices(&self) -> Vec<Arc<dyn Device>> {
+        self.kobj_inner.lock().devices.clone()
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        self.kobj_inner.lock().bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Weak<dyn Bus>>) {
+        self.kobj_inner.lock().bus = bus;
+    }
+}
+
+impl NetDriver for LoopbackInterface {
+    fn mac(&self) -> smoltcp::wire::EthernetAddress {
+        return wire::EthernetAddress([0; 6]);
+    }
+
+    #[inline]
+    fn nic_id(&self) -> usize {
+        return self.iface_id;
+    }
+
+    #[inline]
+    fn name(&self) -> String {
+        return self.name.clone();
+    }
+
+    fn update_ip_addrs(&self, ip_addrs: &[wire::IpCidr]) -> Result<(), SystemError> {
+        let mut r = Ok(());
+        self.iface.lock().update_ip_addrs(|addrs| {
+            addrs.clear();
+            for cidr in ip_addrs {
+                if addrs.push(*cidr).is_err() {
+                    r = Err(SystemError::EINVAL);
+                    return;
+                }
+            }
+        });
+        return r;
+    }
+
+    fn poll(&self, sockets: &mut SocketSet) -> Result<(), SystemError> {
+        let timestamp: smoltcp::time::Instant = Instant::now().into();
+        let mut guard = self.iface.lock();
+        let mut device = self.device.lock();
+        let poll_res = guard.poll(timestamp, &mut *device, sockets);
+        if poll_res {
+            return Ok(());
+        }
+        return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+    }
+
+    #[inline(always)]
+    fn sockets(&self) -> &SpinLock<SocketSet<'static>> {
+        return &self.sockets;
+    }
+
+    #[inline]
+    fn flags(&self) -> IfFlags {
+        return *self.flags.lock();
+    }
+
+    #[inline]
+    fn set_flags(&self, flags: IfFlags) {
+        *self.flags.lock() = flags;
+    }
+
+    #[inline(always)]
+    fn inner_iface(&self) -> &SpinLock<smoltcp::iface::Interface> {
+        return &self.iface;
+    }
+}
+
+impl KObject for LoopbackInterface {
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.kobj_inner.lock().kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.kobj_inner.lock().kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.kobj_inner.lock().parent.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.kobj_inner.lock().parent = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.kobj_inner.lock().kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.kobj_inner.lock().kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.kobj_inner.lock().kobj_type
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn set_name(&self, _name: String) {
+        // 回环接口的名字固定为lo
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state.write() = state;
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.kobj_inner.lock().kobj_type = ktype;
+    }
+}
+
+/// ## 是否是回环接口
+pub fn is_loopback(iface: &Arc<dyn NetDriver>) -> bool {
+    return iface.as_any_ref().is::<LoopbackInterface>();
+}
+
+/// @brief 创建回环接口，并注册到全局的网卡接口信息表中
+pub fn loopback_init() {
+    let iface = LoopbackInterface::new();
+    NET_DRIVERS
+        .write_irqsave()
+        .insert(iface.nic_id(), iface.clone());
+    kinfo!(
+        "Loopback interface init successfully!\tNetDevID: [{}]",
+        iface.name
+    );
+}
@@ -11,8 +11,54 @@ use system_error::SystemError;
 mod dma;
 pub mod e1000e;
 pub mod irq_handle;
+pub mod loopback;
 pub mod virtio_net;
 
+bitflags! {
+    /// 网络接口的标志
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/include/uapi/linux/if.h#82
+    pub struct IfFlags: u32 {
+        /// 接口已启用
+        const UP = 1 << 0;
+        /// 支持广播
+        const BROADCAST = 1 << 1;
+        const DEBUG = 1 << 2;
+        /// 回环接口
+        const LOOPBACK = 1 << 3;
+        const POINTOPOINT = 1 << 4;
+        const NOTRAILERS = 1 << 5;
+        /// 接口的资源已分配，可以收发数据
+        const RUNNING = 1 << 6;
+        const NOARP = 1 << 7;
+        const PROMISC = 1 << 8;
+        const ALLMULTI = 1 << 9;
+        const MASTER = 1 << 10;
+        const SLAVE = 1 << 11;
+        /// 支持多播
+        const MULTICAST = 1 << 12;
+        const PORTSEL = 1 << 13;
+        const AUTOMEDIA = 1 << 14;
+        const DYNAMIC = 1 << 15;
+    }
+}
+
+impl IfFlags {
+    /// 用户可以通过SIOCSIFFLAGS修改的标志
+    pub const USER_CHANGEABLE: Self = Self::from_bits_truncate(
+        Self::UP.bits()
+            | Self::DEBUG.bits()
+            | Self::NOTRAILERS.bits()
+            | Self::NOARP.bits()
+            | Self::PROMISC.bits()
+            | Self::ALLMULTI.bits()
+            | Self::MULTICAST.bits()
+            | Self::PORTSEL.bits()
+            | Self::AUTOMEDIA.bits()
+            | Self::DYNAMIC.bits(),
+    );
+}
+
 pub trait NetDriver: Driver {
     /// @brief 获取网卡的MAC地址
     fn mac(&self) -> EthernetAddress;
@@ -22,10 +68,22 @@ pub trait NetDriver: Driver {
     /// @brief 获取网卡的id
     fn nic_id(&self) -> usize;
 
+    /// @brief 使用给定的socket集合轮询网卡，处理收到的数据包，并发送socket中待发送的数据
     fn poll(&self, sockets: &mut iface::SocketSet) -> Result<(), SystemError>;
 
+    /// @brief 获取属于这个网络接口的socket集合
+    ///
+    /// 每个网络接口有自己的socket集合，不同接口上的socket可以被同时访问
+    fn sockets(&self) -> &SpinLock<iface::SocketSet<'static>>;
+
     fn update_ip_addrs(&self, ip_addrs: &[wire::IpCidr]) -> Result<(), SystemError>;
 
+    /// @brief 获取网络接口的标志
+    fn flags(&self) -> IfFlags;
+
+    /// @brief 设置网络接口的标志
+    fn set_flags(&self, flags: IfFlags);
+
     /// @brief 获取smoltcp的网卡接口类型
     fn inner_iface(&self) -> &SpinLock<smoltcp::iface::Interface>;
     // fn as_any_ref(&'static self) -> &'static dyn core::any::Any;
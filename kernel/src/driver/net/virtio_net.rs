@@ -11,7 +11,7 @@ use alloc::{
 use smoltcp::{phy, wire};
 use virtio_drivers::{device::net::VirtIONet, transport::Transport};
 
-use super::NetDriver;
+use super::{IfFlags, NetDriver};
 use crate::{
     driver::{
         base::{
@@ -23,7 +23,7 @@ use crate::{
     exception::{irqdesc::IrqReturn, IrqNumber},
     kerror, kinfo,
     libs::spinlock::SpinLock,
-    net::{generate_iface_id, net_core::poll_ifaces_try_lock_onetime, NET_DRIVERS},
+    net::{generate_iface_id, net_core::wakeup_net_rx_thread, NET_DRIVERS},
     time::Instant,
 };
 use system_error::SystemError;
@@ -77,7 +77,9 @@ pub struct VirtioInterface<T: Transport> {
     driver: VirtioNICDriverWrapper<T>,
     iface_id: usize,
     iface: SpinLock<smoltcp::iface::Interface>,
+    sockets: SpinLock<smoltcp::iface::SocketSet<'static>>,
     name: String,
+    flags: SpinLock<IfFlags>,
     dev_id: Arc<DeviceId>,
 }
 
@@ -111,7 +113,11 @@ impl<T: Transport> VirtioInterface<T> {
             driver,
             iface_id,
             iface: SpinLock::new(iface),
+            sockets: SpinLock::new(smoltcp::iface::SocketSet::new(vec![])),
             name: format!("eth{}", iface_id),
+            flags: SpinLock::new(
+                IfFlags::UP | IfFlags::BROADCAST | IfFlags::RUNNING | IfFlags::MULTICAST,
+            ),
             dev_id,
         });
 
@@ -121,7 +127,7 @@ impl<T: Transport> VirtioInterface<T> {
 
 impl<T: Transport + 'static> VirtIODevice for VirtioInterface<T> {
     fn handle_irq(&self, _irq: IrqNumber) -> Result<IrqReturn, SystemError> {
-        poll_ifaces_try_lock_onetime().ok();
+        wakeup_net_rx_thread();
         return Ok(IrqReturn::Handled);
     }
 
@@ -344,6 +350,21 @@ impl<T: Transport + 'static> NetDriver for VirtioInterface<T> {
         return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
     }
 
+    #[inline(always)]
+    fn sockets(&self) -> &SpinLock<smoltcp::iface::SocketSet<'static>> {
+        return &self.sockets;
+    }
+
+    #[inline]
+    fn flags(&self) -> IfFlags {
+        return *self.flags.lock();
+    }
+
+    #[inline]
+    fn set_flags(&self, flags: IfFlags) {
+        *self.flags.lock() = flags;
+    }
+
     #[inline(always)]
     fn inner_iface(&self) -> &SpinLock<smoltcp::iface::Interface> {
         return &self.iface;
@@ -11,7 +11,7 @@ use crate::{
     },
 };
 
-use super::tty_port::current_tty_port;
+use super::{pty::pty_flush_pending, tty_port::current_tty_port};
 
 /// 用于缓存键盘输入的缓冲区
 static KEYBUF: StaticThingBuf<u8, 512> = StaticThingBuf::new();
@@ -45,6 +45,9 @@ fn tty_refresh_thread() -> i32 {
             sched();
         }
 
+        // 处理pty之间传递的数据
+        pty_flush_pending();
+
         let to_dequeue = core::cmp::min(KEYBUF.len(), TO_DEQUEUE_MAX);
         if to_dequeue == 0 {
             continue;
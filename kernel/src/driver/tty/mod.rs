@@ -2,6 +2,7 @@ use alloc::vec::Vec;
 
 pub mod console;
 pub mod kthread;
+pub mod pty;
 pub mod termios;
 pub mod tty_core;
 pub mod tty_device;
@@ -0,0 +1,146 @@
+//! 伪终端(pty)
+//!
+//! 参考：https://code.dragonos.org.cn/xref/linux-6.1.9/drivers/tty/pty.c
+
+use core::any::Any;
+
+use alloc::{
+    collections::VecDeque,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::libs::spinlock::{SpinLock, SpinLockGuard};
+
+use super::{
+    tty_core::TtyCore,
+    tty_port::{TtyPort, TtyPortData},
+};
+
+pub mod unix98pty;
+
+/// pty每一端的接收缓冲区大小
+const PTY_BUFFER_SIZE: usize = 4096;
+
+lazy_static! {
+    /// 接收缓冲区中有数据等待交给线路规程处理的pty
+    static ref PTY_PENDING: SpinLock<Vec<Weak<TtyCore>>> = SpinLock::new(Vec::new());
+}
+
+/// pty的端口
+///
+/// 一端写入的数据先放入另一端端口的缓冲区中，再由tty刷新线程交给另一端的线路规程处理。
+/// 这样写入者不需要在持有自己线路规程的锁时，去获取另一端线路规程的锁（回显时方向相反），避免死锁
+#[derive(Debug)]
+pub struct PtyPort {
+    port_data: SpinLock<TtyPortData>,
+    /// 对端写入，还未交给线路规程处理的数据
+    buffer: SpinLock<VecDeque<u8>>,
+}
+
+impl PtyPort {
+    pub fn new() -> Self {
+        Self {
+            port_data: SpinLock::new(TtyPortData::new()),
+            buffer: SpinLock::new(VecDeque::with_capacity(PTY_BUFFER_SIZE)),
+        }
+    }
+
+    /// 缓冲区剩余的空间
+    pub fn write_room(&self) -> usize {
+        PTY_BUFFER_SIZE - self.buffer.lock_irqsave().len()
+    }
+
+    /// 把对端写入的数据放入缓冲区，返回实际放入的字节数
+    fn push(&self, buf: &[u8]) -> usize {
+        let mut buffer = self.buffer.lock_irqsave();
+        let len = buf.len().min(PTY_BUFFER_SIZE - buffer.len());
+        buffer.extend(&buf[..len]);
+        len
+    }
+
+    /// 把缓冲区中的数据交给线路规程处理，返回缓冲区中是否还有剩余的数据
+    fn flush(&self) -> bool {
+        let data: Vec<u8> = self.buffer.lock_irqsave().iter().copied().collect();
+        if data.is_empty() {
+            return false;
+        }
+
+        // 处理数据时不能持有缓冲区的锁，因为线路规程回显时会向对端的缓冲区写入
+        let received = self.receive_buf(&data, &[], data.len()).unwrap_or(0);
+
+        let mut buffer = self.buffer.lock_irqsave();
+        buffer.drain(..received);
+        !buffer.is_empty()
+    }
+}
+
+impl TtyPort for PtyPort {
+    fn port_data(&self) -> SpinLockGuard<TtyPortData> {
+        self.port_data.lock_irqsave()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// ## 获取tty的pty端口
+fn pty_port(tty: &TtyCore) -> Option<Arc<dyn TtyPort>> {
+    tty.core()
+        .port()
+        .filter(|port| port.as_any_ref().is::<PtyPort>())
+}
+
+/// ## 向pty的接收端写入数据
+///
+/// 返回实际写入的字节数，接收端的缓冲区满时返回0
+pub fn pty_write_to(to: &Arc<TtyCore>, buf: &[u8]) -> Result<usize, SystemError> {
+    let port = pty_port(to).ok_or(SystemError::EIO)?;
+    let port = port.as_any_ref().downcast_ref::<PtyPort>().unwrap();
+    let len = port.push(buf);
+    if len > 0 {
+        let mut pending = PTY_PENDING.lock_irqsave();
+        let to = Arc::downgrade(to);
+        if !pending.iter().any(|tty| tty.ptr_eq(&to)) {
+            pending.push(to);
+        }
+    }
+    Ok(len)
+}
+
+/// ## 接收端缓冲区的剩余空间
+pub fn pty_write_room(to: &TtyCore) -> usize {
+    pty_port(to)
+        .and_then(|port| {
+            port.as_any_ref()
+                .downcast_ref::<PtyPort>()
+                .map(|port| port.write_room())
+        })
+        .unwrap_or(0)
+}
+
+/// ## 把pty缓冲区中的数据交给接收端的线路规程处理
+///
+/// 由tty刷新线程调用
+pub fn pty_flush_pending() {
+    let pending = core::mem::take(&mut *PTY_PENDING.lock_irqsave());
+    for tty in pending.iter().filter_map(|tty| tty.upgrade()) {
+        let port = match pty_port(&tty) {
+            Some(port) => port,
+            None => continue,
+        };
+
+        let remain = port.as_any_ref().downcast_ref::<PtyPort>().unwrap().flush();
+        if remain {
+            // 线路规程的缓冲区已满，等待下次处理
+            PTY_PENDING.lock_irqsave().push(Arc::downgrade(&tty));
+        }
+
+        // 缓冲区腾出了空间，唤醒对端的写者
+        if let Some(link) = tty.core().link() {
+            link.tty_wakeup();
+        }
+    }
+}
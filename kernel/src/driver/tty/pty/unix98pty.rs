@@ -0,0 +1,301 @@
+use alloc::{string::String, sync::Arc};
+use system_error::SystemError;
+
+use crate::{
+    driver::{
+        base::device::{
+            device_number::{DeviceNumber, Major},
+            device_register, IdTable,
+        },
+        tty::{
+            termios::{ControlMode, InputMode, LocalMode, OutputMode, WindowSize, TTY_STD_TERMIOS},
+            tty_core::{TtyCore, TtyCoreData, TtyFlag, TtyIoctlCmd},
+            tty_device::TtyDevice,
+            tty_driver::{
+                TtyDriver, TtyDriverManager, TtyDriverSubType, TtyDriverType, TtyOperation,
+            },
+            tty_port::TtyPort,
+        },
+    },
+    filesystem::{
+        devfs::devfs_register,
+        devpts::{devpts_init, devpts_instance},
+    },
+    mm::VirtAddr,
+    net::event_poll::EPollEventType,
+    syscall::user_access::{UserBufferReader, UserBufferWriter},
+};
+
+use super::{pty_write_room, pty_write_to, PtyPort};
+
+/// Unix98 pty的最大数量
+pub const NR_UNIX98_PTY_MAX: u32 = 4096;
+
+/// /dev/ptmx的设备号
+pub const PTMX_DEVICE_NUMBER: DeviceNumber = DeviceNumber::new(Major::TTYAUX_MAJOR, 2);
+
+static mut PTM_DRIVER: Option<Arc<TtyDriver>> = None;
+static mut PTS_DRIVER: Option<Arc<TtyDriver>> = None;
+
+#[inline(always)]
+pub fn ptm_driver() -> Arc<TtyDriver> {
+    unsafe { PTM_DRIVER.as_ref().unwrap().clone() }
+}
+
+#[inline(always)]
+pub fn pts_driver() -> Arc<TtyDriver> {
+    unsafe { PTS_DRIVER.as_ref().unwrap().clone() }
+}
+
+#[derive(Debug)]
+pub struct Unix98PtyDriverInner;
+
+impl Unix98PtyDriverInner {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl TtyOperation for Unix98PtyDriverInner {
+    /// 创建主设备时，同时创建对应的从设备
+    fn install(&self, driver: Arc<TtyDriver>, tty: Arc<TtyCore>) -> Result<(), SystemError> {
+        let slave_driver = driver.other_pty_driver().ok_or(SystemError::ENODEV)?;
+        let core = tty.core();
+        let slave = TtyCore::new(slave_driver.clone(), core.index());
+
+        // 主从两端互相链接，并各自使用一个端口接收另一端写入的数据
+        core.set_link(Arc::downgrade(&slave));
+        slave.core().set_link(Arc::downgrade(&tty));
+        for t in [&tty, &slave] {
+            let port = Arc::new(PtyPort::new());
+            port.setup_tty(Arc::downgrade(t));
+            t.set_port(port);
+        }
+
+        // 在主设备通过TIOCSPTLCK解锁之前，从设备不能被打开
+        core.flags_write().insert(TtyFlag::PTY_LOCK);
+
+        slave_driver.add_tty(slave);
+        core.add_count();
+
+        Ok(())
+    }
+
+    fn open(&self, tty: &TtyCoreData) -> Result<(), SystemError> {
+        let link = tty.link().ok_or(SystemError::EIO)?;
+        if tty.flags().contains(TtyFlag::OTHER_CLOSED) {
+            return Err(SystemError::EIO);
+        }
+
+        if tty.driver().tty_driver_sub_type() == TtyDriverSubType::PtySlave
+            && link.core().flags().contains(TtyFlag::PTY_LOCK)
+        {
+            return Err(SystemError::EIO);
+        }
+
+        link.core().flags_write().remove(TtyFlag::OTHER_CLOSED);
+
+        Ok(())
+    }
+
+    fn write_room(&self, tty: &TtyCoreData) -> usize {
+        if tty.flow_irqsave().stopped {
+            return 0;
+        }
+
+        match tty.link() {
+            Some(to) => pty_write_room(&to),
+            None => 0,
+        }
+    }
+
+    /// 把数据写入另一端的缓冲区
+    fn write(&self, tty: &TtyCoreData, buf: &[u8], nr: usize) -> Result<usize, SystemError> {
+        if tty.flow_irqsave().stopped {
+            return Ok(0);
+        }
+
+        let to = tty.link().ok_or(SystemError::EIO)?;
+        pty_write_to(&to, &buf[..nr])
+    }
+
+    fn flush_chars(&self, _tty: &TtyCoreData) {}
+
+    fn put_char(&self, tty: &TtyCoreData, ch: u8) -> Result<(), SystemError> {
+        self.write(tty, &[ch], 1)?;
+        Ok(())
+    }
+
+    fn ioctl(&self, tty: Arc<TtyCore>, cmd: u32, arg: usize) -> Result<(), SystemError> {
+        let core = tty.core();
+        if core.driver().tty_driver_sub_type() != TtyDriverSubType::PtyMaster {
+            return Err(SystemError::ENOIOCTLCMD);
+        }
+
+        match cmd {
+            TtyIoctlCmd::TIOCSPTLCK => {
+                let user_reader = UserBufferReader::new(
+                    VirtAddr::new(arg).as_ptr::<i32>(),
+                    core::mem::size_of::<i32>(),
+                    true,
+                )?;
+
+                let mut lock = 0i32;
+                user_reader.copy_one_from_user(&mut lock, 0)?;
+                if lock != 0 {
+                    core.flags_write().insert(TtyFlag::PTY_LOCK);
+                } else {
+                    core.flags_write().remove(TtyFlag::PTY_LOCK);
+                }
+                return Ok(());
+            }
+            TtyIoctlCmd::TIOCGPTLCK => {
+                let mut user_writer = UserBufferWriter::new(
+                    VirtAddr::new(arg).as_ptr::<i32>(),
+                    core::mem::size_of::<i32>(),
+                    true,
+                )?;
+
+                let locked = core.flags().contains(TtyFlag::PTY_LOCK) as i32;
+                user_writer.copy_one_to_user(&locked, 0)?;
+                return Ok(());
+            }
+            TtyIoctlCmd::TIOCGPTN => {
+                let mut user_writer = UserBufferWriter::new(
+                    VirtAddr::new(arg).as_ptr::<u32>(),
+                    core::mem::size_of::<u32>(),
+                    true,
+                )?;
+
+                user_writer.copy_one_to_user(&(core.index() as u32), 0)?;
+                return Ok(());
+            }
+            _ => {
+                return Err(SystemError::ENOIOCTLCMD);
+            }
+        }
+    }
+
+    /// 主从两端共享同一个窗口大小，由从设备的前台进程组接收SIGWINCH
+    fn resize(&self, tty: Arc<TtyCore>, winsize: WindowSize) -> Result<(), SystemError> {
+        tty.tty_do_resize(winsize)?;
+        if let Some(link) = tty.core().link() {
+            *link.core().window_size_write() = winsize;
+        }
+
+        Ok(())
+    }
+
+    fn close(&self, tty: Arc<TtyCore>) -> Result<(), SystemError> {
+        let core = tty.core();
+        if core.count() > 0 {
+            return Ok(());
+        }
+
+        let link = core.link();
+        let is_master = core.driver().tty_driver_sub_type() == TtyDriverSubType::PtyMaster;
+        if let Some(link) = &link {
+            // 通知另一端：这一端已经关闭
+            let mut flags = link.core().flags_write();
+            flags.insert(TtyFlag::OTHER_CLOSED);
+            if is_master {
+                // 主设备关闭后，从设备被挂断
+                flags.insert(TtyFlag::HUPPED);
+            }
+            drop(flags);
+
+            link.core().read_wq().wakeup_all();
+            link.core().write_wq().wakeup_all();
+            link.core()
+                .wakeup_epitems(EPollEventType::EPOLLIN | EPollEventType::EPOLLHUP);
+        }
+
+        if is_master {
+            // 主设备关闭后，整个pty被释放
+            let index = core.index();
+            devpts_instance().remove_pty(index);
+            pts_driver().remove_tty(index);
+            ptm_driver().remove_tty(index);
+        }
+
+        Ok(())
+    }
+}
+
+/// ## 打开/dev/ptmx，创建一对新的pty
+///
+/// 返回pty的主设备，从设备出现在/dev/pts下
+pub fn ptmx_open() -> Result<Arc<TtyCore>, SystemError> {
+    let devpts = devpts_instance();
+    let index = devpts.alloc_index().ok_or(SystemError::ENOSPC)?;
+
+    let tty = match TtyDriver::init_tty_device(ptm_driver(), index) {
+        Ok(tty) => tty,
+        Err(e) => {
+            devpts.free_index(index);
+            return Err(e);
+        }
+    };
+
+    if let Err(e) = devpts.add_pty(index) {
+        pts_driver().remove_tty(index);
+        ptm_driver().remove_tty(index);
+        devpts.free_index(index);
+        return Err(e);
+    }
+
+    Ok(tty)
+}
+
+/// 初始化Unix98 pty的驱动，并创建/dev/ptmx和/dev/pts
+pub fn unix98pty_init() -> Result<(), SystemError> {
+    let mut pts_driver = TtyDriver::new(
+        NR_UNIX98_PTY_MAX,
+        "pts",
+        0,
+        Major::UNIX98_PTY_SLAVE_MAJOR,
+        0,
+        TtyDriverType::Pty,
+        TTY_STD_TERMIOS.clone(),
+        Arc::new(Unix98PtyDriverInner::new()),
+    );
+    pts_driver.set_tty_driver_sub_type(TtyDriverSubType::PtySlave);
+    let pts_driver = TtyDriverManager::tty_register_driver(pts_driver)?;
+
+    // 主设备工作在原始模式下，不对数据做任何处理
+    let mut master_termios = TTY_STD_TERMIOS.clone();
+    master_termios.input_mode = InputMode::empty();
+    master_termios.output_mode = OutputMode::empty();
+    master_termios.control_mode = ControlMode::B38400 | ControlMode::CS8 | ControlMode::CREAD;
+    master_termios.local_mode = LocalMode::empty();
+
+    let mut ptm_driver = TtyDriver::new(
+        NR_UNIX98_PTY_MAX,
+        "ptm",
+        0,
+        Major::UNIX98_PTY_MASTER_MAJOR,
+        0,
+        TtyDriverType::Pty,
+        master_termios,
+        Arc::new(Unix98PtyDriverInner::new()),
+    );
+    ptm_driver.set_tty_driver_sub_type(TtyDriverSubType::PtyMaster);
+    ptm_driver.set_other_pty_driver(pts_driver.clone());
+    let ptm_driver = TtyDriverManager::tty_register_driver(ptm_driver)?;
+
+    unsafe {
+        PTS_DRIVER = Some(pts_driver);
+        PTM_DRIVER = Some(ptm_driver);
+    }
+
+    devpts_init()?;
+
+    let ptmx = TtyDevice::new(
+        String::from("ptmx"),
+        IdTable::new(String::from("ptmx"), Some(PTMX_DEVICE_NUMBER)),
+    );
+    device_register(ptmx.clone())?;
+    devfs_register("ptmx", ptmx)?;
+
+    Ok(())
+}
@@ -3,10 +3,16 @@ use core::{
     sync::atomic::{AtomicBool, AtomicUsize},
 };
 
-use alloc::{collections::LinkedList, string::String, sync::Arc, vec::Vec};
+use alloc::{
+    collections::LinkedList,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
 use system_error::SystemError;
 
 use crate::{
+    arch::ipc::signal::Signal,
     driver::serial::serial8250::send_to_default_serial8250_port,
     libs::{
         rwlock::{RwLock, RwLockReadGuard, RwLockUpgradableGuard, RwLockWriteGuard},
@@ -14,7 +20,7 @@ use crate::{
         wait_queue::EventWaitQueue,
     },
     mm::VirtAddr,
-    net::event_poll::{EPollEventType, EPollItem},
+    net::event_poll::{EPollEventType, EPollItem, EventPoll},
     process::Pid,
     syscall::user_access::{UserBufferReader, UserBufferWriter},
 };
@@ -55,7 +61,7 @@ impl TtyCore {
             ctrl: SpinLock::new(TtyContorlInfo::default()),
             closing: AtomicBool::new(false),
             flow: SpinLock::new(TtyFlowState::default()),
-            link: None,
+            link: RwLock::new(Weak::new()),
             epitems: SpinLock::new(LinkedList::new()),
         };
 
@@ -139,6 +145,27 @@ impl TtyCore {
         self.core()
             .write_wq
             .wakeup(EPollEventType::EPOLLOUT.bits() as u64);
+        self.core()
+            .wakeup_epitems(EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM);
+    }
+
+    /// ## 改变tty的窗口大小
+    ///
+    /// 窗口大小发生变化时，向tty的前台进程组发送SIGWINCH信号
+    pub fn tty_do_resize(&self, windowsize: WindowSize) -> Result<(), SystemError> {
+        let mut window_size = self.core.window_size_write();
+        if *window_size == windowsize {
+            return Ok(());
+        }
+        *window_size = windowsize;
+        drop(window_size);
+
+        let pgid = self.core.contorl_info_irqsave().pgid;
+        if let Some(pgid) = pgid {
+            let _ = Signal::SIGWINCH.send_signal_to_pgrp(pgid);
+        }
+
+        Ok(())
     }
 
     pub fn tty_mode_ioctl(tty: Arc<TtyCore>, cmd: u32, arg: usize) -> Result<usize, SystemError> {
@@ -307,8 +334,8 @@ pub struct TtyCoreData {
     closing: AtomicBool,
     /// 流控状态
     flow: SpinLock<TtyFlowState>,
-    /// 链接tty
-    link: Option<Arc<TtyCore>>,
+    /// 链接tty（pty的另一端）
+    link: RwLock<Weak<TtyCore>>,
     /// epitems
     epitems: SpinLock<LinkedList<Arc<EPollItem>>>,
 }
@@ -360,12 +387,30 @@ impl TtyCoreData {
         *termios_guard = termios;
     }
 
+    #[inline]
+    pub fn flags_write(&self) -> RwLockWriteGuard<'_, TtyFlag> {
+        self.flags.write_irqsave()
+    }
+
     #[inline]
     pub fn add_count(&self) {
         self.count
             .fetch_add(1, core::sync::atomic::Ordering::SeqCst);
     }
 
+    /// 减少tty的打开计数，返回减少后的计数
+    #[inline]
+    pub fn dec_count(&self) -> usize {
+        self.count
+            .fetch_sub(1, core::sync::atomic::Ordering::SeqCst)
+            - 1
+    }
+
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.count.load(core::sync::atomic::Ordering::SeqCst)
+    }
+
     #[inline]
     pub fn read_wq(&self) -> &EventWaitQueue {
         &self.read_wq
@@ -391,6 +436,11 @@ impl TtyCoreData {
         self.window_size.read()
     }
 
+    #[inline]
+    pub fn window_size_write(&self) -> RwLockWriteGuard<WindowSize> {
+        self.window_size.write()
+    }
+
     #[inline]
     pub fn is_closing(&self) -> bool {
         self.closing.load(core::sync::atomic::Ordering::SeqCst)
@@ -403,13 +453,35 @@ impl TtyCoreData {
 
     #[inline]
     pub fn link(&self) -> Option<Arc<TtyCore>> {
-        self.link.clone()
+        self.link.read().upgrade()
+    }
+
+    #[inline]
+    pub fn set_link(&self, link: Weak<TtyCore>) {
+        *self.link.write() = link;
     }
 
     #[inline]
     pub fn add_epitem(&self, epitem: Arc<EPollItem>) {
         self.epitems.lock().push_back(epitem)
     }
+
+    pub fn remove_epitem(&self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
+        let removed = self
+            .epitems
+            .lock()
+            .extract_if(|epitem| epitem.epoll().ptr_eq(epoll))
+            .count();
+        if removed == 0 {
+            return Err(SystemError::ENOENT);
+        }
+        return Ok(());
+    }
+
+    /// 通知监听这个tty的epoll，`events`为发生的事件
+    pub fn wakeup_epitems(&self, events: EPollEventType) {
+        let _ = EventPoll::wakeup_epoll(&self.epitems, events);
+    }
 }
 
 /// TTY 核心接口，不同的tty需要各自实现这个trait
@@ -428,7 +500,10 @@ impl TtyOperation for TtyCore {
 
     #[inline]
     fn write(&self, tty: &TtyCoreData, buf: &[u8], nr: usize) -> Result<usize, SystemError> {
-        send_to_default_serial8250_port(buf);
+        // pty的输出由另一端读取，不需要输出到串口
+        if self.core().tty_driver.tty_driver_type() != TtyDriverType::Pty {
+            send_to_default_serial8250_port(buf);
+        }
         return self.core().tty_driver.driver_funcs().write(tty, buf, nr);
     }
 
@@ -475,6 +550,16 @@ impl TtyOperation for TtyCore {
             .driver_funcs()
             .set_termios(tty, old_termios);
     }
+
+    #[inline]
+    fn resize(&self, tty: Arc<TtyCore>, winsize: WindowSize) -> Result<(), SystemError> {
+        return self.core().tty_driver.driver_funcs().resize(tty, winsize);
+    }
+
+    #[inline]
+    fn close(&self, tty: Arc<TtyCore>) -> Result<(), SystemError> {
+        return self.core().tty_driver.driver_funcs().close(tty);
+    }
 }
 
 bitflags! {
@@ -626,4 +711,10 @@ impl TtyIoctlCmd {
     pub const TIOCCBRK: u32 = 0x5428;
     /// Return the session ID of FD
     pub const TIOCGSID: u32 = 0x5429;
+    /// 获取pty的编号
+    pub const TIOCGPTN: u32 = 0x80045430;
+    /// 锁定/解锁pty的从设备
+    pub const TIOCSPTLCK: u32 = 0x40045431;
+    /// 获取pty从设备的锁定状态
+    pub const TIOCGPTLCK: u32 = 0x80045439;
 }
@@ -1,5 +1,5 @@
 use alloc::{
-    string::{String, ToString},
+    string::String,
     sync::{Arc, Weak},
 };
 use system_error::SystemError;
@@ -37,6 +37,7 @@ use crate::{
 
 use super::{
     kthread::tty_flush_thread_init,
+    pty::unix98pty::{ptmx_open, unix98pty_init, PTMX_DEVICE_NUMBER},
     termios::WindowSize,
     tty_core::{TtyCore, TtyFlag, TtyIoctlCmd},
     tty_driver::{TtyDriver, TtyDriverSubType, TtyDriverType, TtyOperation},
@@ -75,7 +76,7 @@ impl InnerTtyDevice {
 #[derive(Debug)]
 #[cast_to([sync] Device)]
 pub struct TtyDevice {
-    name: &'static str,
+    name: String,
     id_table: IdTable,
     inner: RwLock<InnerTtyDevice>,
     kobj_state: LockedKObjectState,
@@ -84,7 +85,7 @@ pub struct TtyDevice {
 }
 
 impl TtyDevice {
-    pub fn new(name: &'static str, id_table: IdTable) -> Arc<TtyDevice> {
+    pub fn new(name: String, id_table: IdTable) -> Arc<TtyDevice> {
         let dev_num = id_table.device_number();
         let dev = TtyDevice {
             name,
@@ -106,18 +107,24 @@ impl IndexNode for TtyDevice {
         data: &mut crate::filesystem::vfs::FilePrivateData,
         mode: &crate::filesystem::vfs::file::FileMode,
     ) -> Result<(), SystemError> {
-        let dev_num = self.metadata()?.raw_dev;
+        // 文件被复制（fork/dup）时，已经打开的tty只需要增加打开计数
+        if let FilePrivateData::Tty(tty_priv) = data {
+            tty_priv.tty.core().add_count();
+            return Ok(());
+        }
 
-        let tty = TtyDriver::open_tty(dev_num)?;
+        let dev_num = self.metadata()?.raw_dev;
 
-        // 设置privdata
-        *data = FilePrivateData::Tty(TtyFilePrivateData {
-            tty: tty.clone(),
-            mode: *mode,
-        });
+        let tty = if dev_num == PTMX_DEVICE_NUMBER {
+            ptmx_open()?
+        } else {
+            TtyDriver::open_tty(dev_num)?
+        };
 
         let ret = tty.open(tty.core());
         if ret.is_err() {
+            tty.core().dec_count();
+            let _ = tty.close(tty.clone());
             let err = ret.unwrap_err();
             if err == SystemError::ENOSYS {
                 return Err(SystemError::ENODEV);
@@ -125,6 +132,12 @@ impl IndexNode for TtyDevice {
             return Err(err);
         }
 
+        // 设置privdata
+        *data = FilePrivateData::Tty(TtyFilePrivateData {
+            tty: tty.clone(),
+            mode: *mode,
+        });
+
         let driver = tty.core().driver();
         // 考虑noctty（当前tty）
         if !(mode.contains(FileMode::O_NOCTTY) && dev_num == DeviceNumber::new(Major::TTY_MAJOR, 0)
@@ -249,8 +262,15 @@ impl IndexNode for TtyDevice {
         Ok(self.inner.read().metadata.clone())
     }
 
-    fn close(&self, _data: &mut FilePrivateData) -> Result<(), SystemError> {
-        Ok(())
+    fn close(&self, data: &mut FilePrivateData) -> Result<(), SystemError> {
+        let tty = if let FilePrivateData::Tty(tty_priv) = data {
+            tty_priv.tty.clone()
+        } else {
+            return Ok(());
+        };
+
+        tty.core().dec_count();
+        tty.close(tty.clone())
     }
 
     fn resize(&self, _len: usize) -> Result<(), SystemError> {
@@ -292,9 +312,18 @@ impl IndexNode for TtyDevice {
             _ => {}
         }
 
+        // pty主设备的窗口大小保存在从设备中
+        let real_tty = if tty.core().driver().tty_driver_type() == TtyDriverType::Pty
+            && tty.core().driver().tty_driver_sub_type() == TtyDriverSubType::PtyMaster
+        {
+            tty.core().link().ok_or(SystemError::EIO)?
+        } else {
+            tty.clone()
+        };
+
         match cmd {
             TtyIoctlCmd::TIOCGWINSZ => {
-                let core = tty.core();
+                let core = real_tty.core();
                 let winsize = *core.window_size();
 
                 let mut user_writer = UserBufferWriter::new(
@@ -309,6 +338,24 @@ impl IndexNode for TtyDevice {
                 }
                 return Ok(0);
             }
+            TtyIoctlCmd::TIOCSWINSZ => {
+                let user_reader = UserBufferReader::new(
+                    VirtAddr::new(arg).as_ptr::<WindowSize>(),
+                    core::mem::size_of::<WindowSize>(),
+                    true,
+                )?;
+
+                let mut winsize = WindowSize::default();
+                if user_reader.copy_one_from_user(&mut winsize, 0).is_err() {
+                    return Err(SystemError::EFAULT);
+                }
+
+                match real_tty.resize(real_tty.clone(), winsize) {
+                    Err(SystemError::ENOSYS) => real_tty.tty_do_resize(winsize)?,
+                    ret => ret?,
+                }
+                return Ok(0);
+            }
             _ => match TtyJobCtrlManager::job_ctrl_ioctl(tty.clone(), cmd, arg) {
                 Ok(_) => {
                     return Ok(0);
@@ -389,7 +436,7 @@ impl KObject for TtyDevice {
     fn set_kobj_type(&self, _ktype: Option<&'static dyn crate::driver::base::kobject::KObjType>) {}
 
     fn name(&self) -> alloc::string::String {
-        self.name.to_string()
+        self.name.clone()
     }
 
     fn set_name(&self, _name: alloc::string::String) {
@@ -478,12 +525,19 @@ pub struct TtyFilePrivateData {
     mode: FileMode,
 }
 
+impl TtyFilePrivateData {
+    #[inline]
+    pub fn tty(&self) -> Arc<TtyCore> {
+        self.tty.clone()
+    }
+}
+
 /// 初始化tty设备和console子设备
 #[unified_init(INITCALL_DEVICE)]
 #[inline(never)]
 pub fn tty_init() -> Result<(), SystemError> {
     let tty = TtyDevice::new(
-        "tty0",
+        String::from("tty0"),
         IdTable::new(
             String::from("tty0"),
             Some(DeviceNumber::new(Major::TTY_MAJOR, 0)),
@@ -491,7 +545,7 @@ pub fn tty_init() -> Result<(), SystemError> {
     );
 
     let console = TtyDevice::new(
-        "console",
+        String::from("console"),
         IdTable::new(
             String::from("console"),
             Some(DeviceNumber::new(Major::TTYAUX_MAJOR, 1)),
@@ -525,11 +579,12 @@ pub fn tty_init() -> Result<(), SystemError> {
     // 将这两个设备注册到devfs，TODO：这里console设备应该与tty在一个设备group里面
     device_register(tty.clone())?;
     device_register(console.clone())?;
-    devfs_register(tty.name, tty)?;
-    devfs_register(console.name, console)?;
+    devfs_register(&tty.name.clone(), tty)?;
+    devfs_register(&console.name.clone(), console)?;
 
     serial_init()?;
 
     tty_flush_thread_init();
+    unix98pty_init()?;
     return vty_init();
 }
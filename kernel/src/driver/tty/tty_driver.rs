@@ -20,7 +20,7 @@ use crate::{
 };
 
 use super::{
-    termios::Termios,
+    termios::{Termios, WindowSize},
     tty_core::{TtyCore, TtyCoreData},
     tty_ldisc::TtyLdiscManager,
     tty_port::TTY_PORTS,
@@ -35,11 +35,12 @@ pub struct TtyDriverManager;
 impl TtyDriverManager {
     pub fn lookup_tty_driver(dev_num: DeviceNumber) -> Option<(usize, Arc<TtyDriver>)> {
         let drivers_guard = TTY_DRIVERS.lock();
-        for (index, driver) in drivers_guard.iter().enumerate() {
+        for driver in drivers_guard.iter() {
             let base = DeviceNumber::new(driver.major, driver.minor_start);
-            if dev_num < base || dev_num.data() > base.data() + driver.device_count {
+            if dev_num < base || dev_num.data() >= base.data() + driver.device_count {
                 continue;
             }
+            let index = (dev_num.minor() - driver.minor_start) as usize;
             return Some((index, driver.clone()));
         }
 
@@ -47,7 +48,9 @@ impl TtyDriverManager {
     }
 
     /// ## 注册驱动
-    pub fn tty_register_driver(mut driver: TtyDriver) -> Result<(), SystemError> {
+    ///
+    /// 返回注册到全局TtyDriver表中的驱动
+    pub fn tty_register_driver(mut driver: TtyDriver) -> Result<Arc<TtyDriver>, SystemError> {
         // 查看是否注册设备号
         if driver.major == Major::UNNAMED_MAJOR {
             let dev_num = CharDevOps::alloc_chardev_region(
@@ -65,11 +68,12 @@ impl TtyDriverManager {
         driver.flags |= TtyDriverFlag::TTY_DRIVER_INSTALLED;
 
         // 加入全局TtyDriver表
-        TTY_DRIVERS.lock().push(Arc::new(driver));
+        let driver = Arc::new(driver);
+        TTY_DRIVERS.lock().push(driver.clone());
 
         // TODO: 加入procfs?
 
-        Ok(())
+        Ok(driver)
     }
 }
 
@@ -152,6 +156,27 @@ impl TtyDriver {
         self.ttys.lock().insert(tty_core.core().index(), tty_core);
     }
 
+    /// 从驱动管理的tty设备列表中移除tty
+    pub fn remove_tty(&self, index: usize) -> Option<Arc<TtyCore>> {
+        self.ttys.lock().remove(&index)
+    }
+
+    /// 设置驱动程序子类型，需要在注册驱动之前调用
+    pub fn set_tty_driver_sub_type(&mut self, sub_type: TtyDriverSubType) {
+        self.tty_driver_sub_type = sub_type;
+    }
+
+    /// 设置pty另一端的驱动，需要在注册驱动之前调用
+    pub fn set_other_pty_driver(&mut self, driver: Arc<TtyDriver>) {
+        self.pty = Some(driver);
+    }
+
+    /// 获取pty另一端的驱动
+    #[inline]
+    pub fn other_pty_driver(&self) -> Option<Arc<TtyDriver>> {
+        self.pty.clone()
+    }
+
     #[inline]
     pub fn driver_funcs(&self) -> Arc<dyn TtyOperation> {
         self.driver_funcs.clone()
@@ -209,7 +234,11 @@ impl TtyDriver {
         Ok(())
     }
 
-    fn init_tty_device(driver: Arc<TtyDriver>, index: usize) -> Result<Arc<TtyCore>, SystemError> {
+    /// ## 为驱动创建并初始化编号为index的tty
+    pub fn init_tty_device(
+        driver: Arc<TtyDriver>,
+        index: usize,
+    ) -> Result<Arc<TtyCore>, SystemError> {
         let tty = TtyCore::new(driver.clone(), index);
 
         Self::driver_install_tty(driver.clone(), tty.clone())?;
@@ -221,7 +250,7 @@ impl TtyDriver {
             tty.set_port(TTY_PORTS[core.index()].clone());
         }
 
-        TtyLdiscManager::ldisc_setup(tty.clone(), None)?;
+        TtyLdiscManager::ldisc_setup(tty.clone(), core.link())?;
 
         Ok(tty)
     }
@@ -245,10 +274,16 @@ impl TtyDriver {
                 tty.reopen()?;
                 tty
             }
-            None => Self::init_tty_device(driver, index)?,
+            // pty只能通过ptmx创建
+            None if driver.tty_driver_type() == TtyDriverType::Pty => {
+                return Err(SystemError::EIO);
+            }
+            None => Self::init_tty_device(driver.clone(), index)?,
         };
 
-        CURRENT_VCNUM.store(index as isize, Ordering::SeqCst);
+        if driver.tty_driver_type() == TtyDriverType::Console {
+            CURRENT_VCNUM.store(index as isize, Ordering::SeqCst);
+        }
 
         return Ok(tty);
     }
@@ -392,6 +427,18 @@ pub trait TtyOperation: Sync + Send + Debug {
     fn set_termios(&self, _tty: Arc<TtyCore>, _old_termios: Termios) -> Result<(), SystemError> {
         Err(SystemError::ENOSYS)
     }
+
+    /// ## 改变窗口大小
+    ///
+    /// 返回ENOSYS时，由tty层使用默认的方式处理
+    fn resize(&self, _tty: Arc<TtyCore>, _winsize: WindowSize) -> Result<(), SystemError> {
+        Err(SystemError::ENOSYS)
+    }
+
+    /// ## tty的文件被关闭时调用，此时tty的打开计数已经减少
+    fn close(&self, _tty: Arc<TtyCore>) -> Result<(), SystemError> {
+        Ok(())
+    }
 }
 
 #[allow(dead_code)]
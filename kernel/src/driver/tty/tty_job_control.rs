@@ -5,7 +5,7 @@ use crate::{
     arch::ipc::signal::{SigSet, Signal},
     mm::VirtAddr,
     process::{Pid, ProcessManager},
-    syscall::{user_access::UserBufferWriter, Syscall},
+    syscall::user_access::UserBufferWriter,
 };
 
 use super::tty_core::{TtyCore, TtyIoctlCmd};
@@ -59,8 +59,7 @@ impl TtyJobCtrlManager {
                     return Err(SystemError::EIO);
                 }
             } else {
-                // 暂时使用kill而不是killpg
-                Syscall::kill(pgid, sig as i32)?;
+                sig.send_signal_to_pgrp(pgid)?;
                 return Err(SystemError::ERESTART);
             }
         }
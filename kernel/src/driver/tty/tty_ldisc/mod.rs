@@ -99,7 +99,7 @@ impl TtyLdiscManager {
     /// ### 参数
     /// - tty：需要设置的tty
     /// - o_tty: other tty 用于pty pair
-    pub fn ldisc_setup(tty: Arc<TtyCore>, _o_tty: Option<Arc<TtyCore>>) -> Result<(), SystemError> {
+    pub fn ldisc_setup(tty: Arc<TtyCore>, o_tty: Option<Arc<TtyCore>>) -> Result<(), SystemError> {
         let ld = tty.ldisc();
 
         let ret = ld.open(tty);
@@ -110,7 +110,16 @@ impl TtyLdiscManager {
             }
         }
 
-        // TODO: 处理PTY
+        // 对于pty，还需要打开另一端的线路规程
+        if let Some(o_tty) = o_tty {
+            let ret = o_tty.ldisc().open(o_tty);
+            if ret.is_err() {
+                let err = ret.unwrap_err();
+                if err == SystemError::ENOSYS {
+                    return Err(err);
+                }
+            }
+        }
 
         Ok(())
     }
@@ -283,6 +283,8 @@ impl NTtyData {
             tty.core()
                 .read_wq()
                 .wakeup_any((EPollEventType::EPOLLIN | EPollEventType::EPOLLRDBAND).bits() as u64);
+            tty.core()
+                .wakeup_epitems(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM);
         }
     }
 
@@ -511,6 +513,8 @@ impl NTtyData {
                 tty.core().read_wq().wakeup_any(
                     (EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM).bits() as u64,
                 );
+                tty.core()
+                    .wakeup_epitems(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM);
                 return;
             }
 
@@ -524,6 +528,8 @@ impl NTtyData {
                 tty.core().read_wq().wakeup_any(
                     (EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM).bits() as u64,
                 );
+                tty.core()
+                    .wakeup_epitems(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM);
                 return;
             }
 
@@ -552,6 +558,8 @@ impl NTtyData {
                 tty.core().read_wq().wakeup_any(
                     (EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM).bits() as u64,
                 );
+                tty.core()
+                    .wakeup_epitems(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM);
                 return;
             }
         }
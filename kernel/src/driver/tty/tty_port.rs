@@ -1,4 +1,4 @@
-use core::{fmt::Debug, sync::atomic::Ordering};
+use core::{any::Any, fmt::Debug, sync::atomic::Ordering};
 
 use alloc::{
     sync::{Arc, Weak},
@@ -74,6 +74,8 @@ pub enum TtyPortState {
 pub trait TtyPort: Sync + Send + Debug {
     fn port_data(&self) -> SpinLockGuard<TtyPortData>;
 
+    fn as_any_ref(&self) -> &dyn Any;
+
     /// 获取Port的状态
     fn state(&self) -> TtyPortState {
         self.port_data().iflags
@@ -116,4 +118,8 @@ impl TtyPort for DefaultTtyPort {
     fn port_data(&self) -> SpinLockGuard<TtyPortData> {
         self.port_data.lock_irqsave()
     }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
 }
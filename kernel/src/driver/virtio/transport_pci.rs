@@ -24,6 +24,7 @@ use core::{
     fmt::{self, Display, Formatter},
     mem::{align_of, size_of},
     ptr::{self, addr_of_mut, NonNull},
+    sync::atomic::{AtomicUsize, Ordering},
 };
 use system_error::SystemError;
 use virtio_drivers::{
@@ -66,7 +67,19 @@ const VIRTIO_PCI_CAP_ISR_CFG: u8 = 3;
 const VIRTIO_PCI_CAP_DEVICE_CFG: u8 = 4;
 
 /// Virtio设备接收中断的设备号
-const VIRTIO_RECV_VECTOR: IrqNumber = IrqNumber::new(56);
+///
+/// 每个virtio设备使用其中的一个，57号被e1000e使用
+const VIRTIO_RECV_VECTORS: [IrqNumber; 7] = [
+    IrqNumber::new(56),
+    IrqNumber::new(58),
+    IrqNumber::new(59),
+    IrqNumber::new(60),
+    IrqNumber::new(61),
+    IrqNumber::new(62),
+    IrqNumber::new(63),
+];
+/// 下一个virtio设备使用的中断号在`VIRTIO_RECV_VECTORS`中的下标
+static VIRTIO_NEXT_RECV_VECTOR: AtomicUsize = AtomicUsize::new(0);
 /// Virtio设备接收中断的设备号的表项号
 const VIRTIO_RECV_VECTOR_INDEX: u16 = 0;
 // 接收的queue号
@@ -123,12 +136,15 @@ impl PciTransport {
         device: &mut PciDeviceStructureGeneralDevice,
         dev_id: Arc<DeviceId>,
     ) -> Result<Self, VirtioPciError> {
-        let irq = VIRTIO_RECV_VECTOR;
         let header = &device.common_header;
         let bus_device_function = header.bus_device_function;
         if header.vendor_id != VIRTIO_VENDOR_ID {
             return Err(VirtioPciError::InvalidVendorId(header.vendor_id));
         }
+        // 目前缺少对PCI设备中断号的统一管理，所以每个设备从预留的中断号中取一个，不能与其他中断重复
+        let irq = *VIRTIO_RECV_VECTORS
+            .get(VIRTIO_NEXT_RECV_VECTOR.fetch_add(1, Ordering::SeqCst))
+            .ok_or(VirtioPciError::NoIrqVector)?;
         let device_type = device_type(header.device_id);
         // Find the PCI capabilities we need.
         let mut common_cfg: Option<VirtioCapabilityInfo> = None;
@@ -139,7 +155,6 @@ impl PciTransport {
         device.bar_ioremap().unwrap()?;
         device.enable_master();
         let standard_device = device.as_standard_device_mut().unwrap();
-        // 目前缺少对PCI设备中断号的统一管理，所以这里需要指定一个中断号。不能与其他中断重复
         let irq_vector = standard_device.irq_vector_mut().unwrap();
         irq_vector.push(irq);
         standard_device
@@ -456,6 +471,8 @@ pub enum VirtioPciError {
     },
     ///获取虚拟地址失败
     BarGetVaddrFailed,
+    /// 没有可用的中断号
+    NoIrqVector,
     /// A generic PCI error,
     Pci(PciError),
 }
@@ -495,6 +512,7 @@ impl Display for VirtioPciError {
                 vaddr, alignment
             ),
             Self::BarGetVaddrFailed => write!(f, "Get bar virtaddress failed"),
+            Self::NoIrqVector => write!(f, "No free irq vector for the virtio device"),
             Self::Pci(pci_error) => pci_error.fmt(f),
         }
     }
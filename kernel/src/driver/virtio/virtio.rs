@@ -1,32 +1,44 @@
 use super::transport_pci::PciTransport;
 use super::virtio_impl::HalImpl;
 use crate::driver::base::device::DeviceId;
+use crate::driver::disk::virtio_blk::virtio_blk;
 use crate::driver::net::virtio_net::virtio_net;
 use crate::driver::pci::pci::{
-    get_pci_device_structure_mut, PciDeviceStructure, PciDeviceStructureGeneralDevice,
-    PCI_DEVICE_LINKEDLIST,
+    PciDeviceStructure, PciDeviceStructureGeneralDevice, PCI_DEVICE_LINKEDLIST,
 };
 use crate::libs::rwlock::RwLockWriteGuard;
 use crate::{kdebug, kerror, kwarn};
 use alloc::sync::Arc;
 use alloc::{boxed::Box, collections::LinkedList};
 use virtio_drivers::transport::{DeviceType, Transport};
-const NETWORK_CLASS: u8 = 0x2;
-const ETHERNET_SUBCLASS: u8 = 0x0;
+
+/// virtio设备的PCI vendor ID
+const VIRTIO_VENDOR_ID: u16 = 0x1AF4;
+/// virtio设备的PCI device ID范围（包括transitional设备和modern设备）
+const VIRTIO_DEVICE_ID_MIN: u16 = 0x1000;
+const VIRTIO_DEVICE_ID_MAX: u16 = 0x107F;
 
 //Virtio设备寻找过程中出现的问题
 enum VirtioError {
-    VirtioNetNotFound,
-    NetDeviceNotFound,
+    VirtioDeviceNotFound,
 }
 
-///@brief 寻找并加载所有virtio设备的驱动（目前只有virtio-net，但其他virtio设备也可添加）
+///@brief 寻找并加载所有virtio设备的驱动（目前支持virtio-net和virtio-blk，其他virtio设备也可添加）
 pub fn virtio_probe() {
     let mut list = PCI_DEVICE_LINKEDLIST.write();
     if let Ok(virtio_list) = virtio_device_search(&mut list) {
         for virtio_device in virtio_list {
-            let dev_id = virtio_device.common_header.device_id;
-            let dev_id = DeviceId::new(None, Some(format!("virtio_{}", dev_id))).unwrap();
+            // 同一种virtio设备可能有多个，因此用设备在PCI总线上的位置来区分它们
+            let header = &virtio_device.common_header;
+            let bdf = header.bus_device_function;
+            let dev_id = DeviceId::new(
+                None,
+                Some(format!(
+                    "virtio_{}_{:02x}:{:02x}.{}",
+                    header.device_id, bdf.bus, bdf.device, bdf.function
+                )),
+            )
+            .unwrap();
             match PciTransport::new::<HalImpl>(virtio_device, dev_id.clone()) {
                 Ok(mut transport) => {
                     kdebug!(
@@ -49,9 +61,7 @@ pub fn virtio_probe() {
 ///@brief 为virtio设备寻找对应的驱动进行初始化
 fn virtio_device_init(transport: impl Transport + 'static, dev_id: Arc<DeviceId>) {
     match transport.device_type() {
-        DeviceType::Block => {
-            kwarn!("Not support virtio_block device for now");
-        }
+        DeviceType::Block => virtio_blk(transport, dev_id),
         DeviceType::GPU => {
             kwarn!("Not support virtio_gpu device for now");
         }
@@ -68,36 +78,25 @@ fn virtio_device_init(transport: impl Transport + 'static, dev_id: Arc<DeviceId>
 /// @brief 寻找所有的virtio设备
 /// @param list 链表的写锁
 /// @return Result<LinkedList<&'a mut Pci_Device_Structure_General_Device>, VirtioError>  成功则返回包含所有virtio设备结构体的可变引用的链表，失败则返回err
-/// 该函数主要是为其他virtio设备预留支持
 fn virtio_device_search<'a>(
     list: &'a mut RwLockWriteGuard<'_, LinkedList<Box<dyn PciDeviceStructure>>>,
 ) -> Result<LinkedList<&'a mut PciDeviceStructureGeneralDevice>, VirtioError> {
     let mut virtio_list: LinkedList<&mut PciDeviceStructureGeneralDevice> = LinkedList::new();
-    let virtio_net_device = get_virtio_net_device(list)?;
-    virtio_list.push_back(virtio_net_device);
-    Ok(virtio_list)
-}
-
-/// @brief 寻找virtio-net设备
-/// @param list 链表的写锁
-/// @return Result<&'a mut Pci_Device_Structure_General_Device, VirtioError> 成功则返回virtio设备结构体的可变引用，失败则返回err
-fn get_virtio_net_device<'a>(
-    list: &'a mut RwLockWriteGuard<'_, LinkedList<Box<dyn PciDeviceStructure>>>,
-) -> Result<&'a mut PciDeviceStructureGeneralDevice, VirtioError> {
-    let result = get_pci_device_structure_mut(list, NETWORK_CLASS, ETHERNET_SUBCLASS);
-    if result.is_empty() {
-        return Err(VirtioError::NetDeviceNotFound);
-    }
-    for device in result {
-        let standard_device = device.as_standard_device_mut().unwrap();
+    for device in list.iter_mut() {
+        let standard_device = match device.as_standard_device_mut() {
+            Some(standard_device) => standard_device,
+            None => continue,
+        };
         let header = &standard_device.common_header;
-        if header.vendor_id == 0x1AF4
-            && header.device_id >= 0x1000
-            && header.device_id <= 0x103F
-            && standard_device.subsystem_id == 1
+        if header.vendor_id == VIRTIO_VENDOR_ID
+            && (VIRTIO_DEVICE_ID_MIN..=VIRTIO_DEVICE_ID_MAX).contains(&header.device_id)
         {
-            return Ok(standard_device);
+            virtio_list.push_back(standard_device);
         }
     }
-    Err(VirtioError::VirtioNetNotFound)
+
+    if virtio_list.is_empty() {
+        return Err(VirtioError::VirtioDeviceNotFound);
+    }
+    Ok(virtio_list)
 }
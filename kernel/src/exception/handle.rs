@@ -6,7 +6,7 @@ use system_error::SystemError;
 use crate::{
     arch::{interrupt::TrapFrame, CurrentIrqArch},
     exception::irqdesc::InnerIrqDesc,
-    libs::{once::Once, spinlock::SpinLockGuard},
+    libs::{once::Once, rand::add_interrupt_randomness, spinlock::SpinLockGuard},
     process::{ProcessFlags, ProcessManager},
     smp::core::smp_get_processor_id,
 };
@@ -204,6 +204,9 @@ fn do_handle_irq_event(desc: &Arc<IrqDesc>) -> Result<(), SystemError> {
     let irq = irq_data.irq();
     let mut r = Ok(IrqReturn::NotHandled);
 
+    // 中断到来的时间是不可预测的，作为随机数的熵源
+    add_interrupt_randomness(irq.data());
+
     for action in actions {
         let mut action_inner: SpinLockGuard<'_, InnerIrqAction> = action.inner();
         // kdebug!("do_handle_irq_event: action: {:?}", action_inner.name());
@@ -1,10 +1,12 @@
 /// 导出devfs的模块
 pub mod null_dev;
+pub mod random_dev;
 pub mod zero_dev;
 
 use super::vfs::{
     core::{generate_inode_id, ROOT_INODE},
     file::FileMode,
+    mount::{MountFS, MountRecord},
     syscall::ModeType,
     FilePrivateData, FileSystem, FileType, FsInfo, IndexNode, Metadata,
 };
@@ -86,6 +88,7 @@ impl DevFS {
     /// @brief 注册系统内部自带的设备
     fn register_bultinin_device(&self) {
         use null_dev::LockedNullInode;
+        use random_dev::{LockedRandomInode, RandomDeviceType};
         use zero_dev::LockedZeroInode;
         let dev_root: Arc<LockedDevFSInode> = self.root_inode.clone();
         dev_root
@@ -94,6 +97,12 @@ impl DevFS {
         dev_root
             .add_dev("zero", LockedZeroInode::new())
             .expect("DevFS: Failed to register /dev/zero");
+        dev_root
+            .add_dev("random", LockedRandomInode::new(RandomDeviceType::Random))
+            .expect("DevFS: Failed to register /dev/random");
+        dev_root
+            .add_dev("urandom", LockedRandomInode::new(RandomDeviceType::Urandom))
+            .expect("DevFS: Failed to register /dev/urandom");
     }
 
     /// @brief 在devfs内注册设备
@@ -126,8 +135,8 @@ impl DevFS {
                 // 在 /dev/char 下创建设备节点
                 dev_char_inode.add_dev(name, device.clone())?;
 
-                // 特殊处理 tty 设备，挂载在 /dev 下
-                if name.starts_with("tty") && name.len() > 3 {
+                // 特殊处理 tty 设备和ptmx，挂载在 /dev 下
+                if (name.starts_with("tty") && name.len() > 3) || name == "ptmx" {
                     dev_root_inode.add_dev(name, device.clone())?;
                 }
                 device.set_fs(dev_char_inode.0.lock().fs.clone());
@@ -229,6 +238,8 @@ pub struct DevFSInode {
     fs: Weak<DevFS>,
     /// INode 元数据
     metadata: Metadata,
+    /// 符号链接指向的路径，只对符号链接有效
+    link_target: String,
 }
 
 impl DevFSInode {
@@ -263,6 +274,7 @@ impl DevFSInode {
                 raw_dev: DeviceNumber::from(data_ as u32),
             },
             fs: Weak::default(),
+            link_target: String::new(),
         };
     }
 }
@@ -353,6 +365,7 @@ impl LockedDevFSInode {
                 raw_dev: DeviceNumber::from(data as u32),
             },
             fs: guard.fs.clone(),
+            link_target: String::new(),
         })));
 
         // 初始化inode的自引用的weak指针
@@ -394,6 +407,39 @@ impl IndexNode for LockedDevFSInode {
         return self.do_create_with_data(guard, name, file_type, mode, data);
     }
 
+    fn symlink(&self, name: &str, target: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let guard: SpinLockGuard<DevFSInode> = self.0.lock();
+        let result = self.do_create_with_data(
+            guard,
+            name,
+            FileType::SymLink,
+            ModeType::from_bits_truncate(0o777),
+            0,
+        )?;
+
+        // devfs不支持写入文件内容，因此直接设置符号链接指向的路径
+        let mut link = result.downcast_ref::<LockedDevFSInode>().unwrap().0.lock();
+        link.link_target = target.to_string();
+        link.metadata.size = target.len() as i64;
+        drop(link);
+
+        return Ok(result);
+    }
+
+    /// 只能删除符号链接，设备文件需要通过`devfs_unregister`删除
+    fn unlink(&self, name: &str) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        let child = inode.children.get(name).ok_or(SystemError::ENOENT)?;
+        if child.metadata()?.file_type != FileType::SymLink {
+            return Err(SystemError::EPERM);
+        }
+        inode.children.remove(name);
+        return Ok(());
+    }
+
     fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
         let inode = self.0.lock();
 
@@ -501,13 +547,25 @@ impl IndexNode for LockedDevFSInode {
     }
 
     /// 读设备 - 应该调用设备的函数读写，而不是通过文件系统读写
+    ///
+    /// 只有符号链接可以通过文件系统读取，内容为其指向的路径
     fn read_at(
         &self,
-        _offset: usize,
-        _len: usize,
-        _buf: &mut [u8],
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
         _data: &mut super::vfs::file::FilePrivateData,
     ) -> Result<usize, SystemError> {
+        let inode = self.0.lock();
+        if inode.metadata.file_type == FileType::SymLink {
+            let target = inode.link_target.as_bytes();
+            let start = target.len().min(offset);
+            let end = target.len().min(offset + len).min(start + buf.len());
+            buf[..end - start].copy_from_slice(&target[start..end]);
+            return Ok(end - start);
+        }
+        drop(inode);
+
         kerror!("DevFS: read_at is not supported!");
         Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
     }
@@ -570,11 +628,12 @@ pub fn devfs_init() -> Result<(), SystemError> {
         // 创建 devfs 实例
         let devfs: Arc<DevFS> = DevFS::new();
         // devfs 挂载
-        let _t = ROOT_INODE()
+        let mount_fs = ROOT_INODE()
             .find("dev")
             .expect("Cannot find /dev")
             .mount(devfs)
             .expect("Failed to mount devfs");
+        MountFS::add_mount_record(MountRecord::new("devfs", "/dev", "devfs", mount_fs, true));
         kinfo!("DevFS mounted.");
         result = Some(Ok(()));
     });
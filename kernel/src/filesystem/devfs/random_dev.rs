@@ -0,0 +1,164 @@
+use crate::driver::base::device::device_number::{DeviceNumber, Major};
+use crate::filesystem::vfs::file::FileMode;
+use crate::filesystem::vfs::syscall::ModeType;
+use crate::filesystem::vfs::{
+    core::generate_inode_id, FilePrivateData, FileSystem, FileType, IndexNode, Metadata,
+};
+use crate::libs::rand::{add_device_randomness, get_random_bytes, wait_for_random_bytes};
+use crate::{libs::spinlock::SpinLock, time::TimeSpec};
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use super::{DevFS, DeviceINode};
+
+/// 随机数设备的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RandomDeviceType {
+    /// /dev/random，在CRNG获得足够的熵之前，读取会阻塞
+    Random,
+    /// /dev/urandom，读取永远不会阻塞
+    Urandom,
+}
+
+impl RandomDeviceType {
+    fn minor(&self) -> u32 {
+        match self {
+            RandomDeviceType::Random => 8,
+            RandomDeviceType::Urandom => 9,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RandomInode {
+    /// 指向自身的弱引用
+    self_ref: Weak<LockedRandomInode>,
+    /// 指向inode所在的文件系统对象的指针
+    fs: Weak<DevFS>,
+    /// INode 元数据
+    metadata: Metadata,
+    device_type: RandomDeviceType,
+}
+
+/// /dev/random和/dev/urandom
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/drivers/char/random.c
+#[derive(Debug)]
+pub struct LockedRandomInode(SpinLock<RandomInode>);
+
+impl LockedRandomInode {
+    pub fn new(device_type: RandomDeviceType) -> Arc<Self> {
+        let inode = RandomInode {
+            self_ref: Weak::default(),
+            fs: Weak::default(),
+            metadata: Metadata {
+                dev_id: 1,
+                inode_id: generate_inode_id(),
+                size: 0,
+                blk_size: 0,
+                blocks: 0,
+                atime: TimeSpec::default(),
+                mtime: TimeSpec::default(),
+                ctime: TimeSpec::default(),
+                file_type: FileType::CharDevice,
+                mode: ModeType::from_bits_truncate(0o666),
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev: DeviceNumber::new(Major::MEM_MAJOR, device_type.minor()),
+            },
+            device_type,
+        };
+
+        let result = Arc::new(LockedRandomInode(SpinLock::new(inode)));
+        result.0.lock().self_ref = Arc::downgrade(&result);
+
+        return result;
+    }
+}
+
+impl DeviceINode for LockedRandomInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        self.0.lock().fs = fs;
+    }
+}
+
+impl IndexNode for LockedRandomInode {
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn open(&self, _data: &mut FilePrivateData, _mode: &FileMode) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn close(&self, _data: &mut FilePrivateData) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        return Ok(self.0.lock().metadata.clone());
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.0.lock().fs.upgrade().unwrap();
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        inode.metadata.atime = metadata.atime;
+        inode.metadata.mtime = metadata.mtime;
+        inode.metadata.ctime = metadata.ctime;
+        inode.metadata.mode = metadata.mode;
+        inode.metadata.uid = metadata.uid;
+        inode.metadata.gid = metadata.gid;
+
+        return Ok(());
+    }
+
+    /// 读取随机数
+    fn read_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+
+        // 不能在持有inode的锁时睡眠
+        let device_type = self.0.lock().device_type;
+        if device_type == RandomDeviceType::Random {
+            wait_for_random_bytes()?;
+        }
+
+        get_random_bytes(&mut buf[..len]);
+        return Ok(len);
+    }
+
+    /// 写入的数据会被混入熵池，但不会增加熵的计数
+    fn write_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &[u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+
+        add_device_randomness(&buf[..len]);
+        return Ok(len);
+    }
+}
@@ -0,0 +1,250 @@
+use core::any::Any;
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use ida::IdAllocator;
+use system_error::SystemError;
+
+use crate::{
+    driver::{
+        base::device::{
+            device_number::{DeviceNumber, Major},
+            IdTable,
+        },
+        tty::{pty::unix98pty::NR_UNIX98_PTY_MAX, tty_device::TtyDevice},
+    },
+    filesystem::vfs::{
+        core::ROOT_INODE,
+        mount::{MountFS, MountRecord},
+        FileType,
+    },
+    kinfo,
+    libs::{
+        once::Once,
+        spinlock::{SpinLock, SpinLockGuard},
+    },
+};
+
+use super::vfs::{
+    file::{FileMode, FilePrivateData},
+    syscall::ModeType,
+    FileSystem, FsInfo, IndexNode, Metadata,
+};
+
+/// devpts的inode名称的最大长度
+const DEVPTS_MAX_NAMELEN: usize = 16;
+
+static mut DEVPTS_INSTANCE: Option<Arc<DevPtsFS>> = None;
+
+#[inline(always)]
+pub fn devpts_instance() -> Arc<DevPtsFS> {
+    unsafe { DEVPTS_INSTANCE.as_ref().unwrap().clone() }
+}
+
+/// @brief devpts文件系统，存放pty的从设备节点
+///
+/// 通过/dev/ptmx创建的每一对pty，其从设备都会以编号为名，出现在devpts的根目录下
+#[derive(Debug)]
+pub struct DevPtsFS {
+    /// devpts的root inode
+    root_inode: Arc<LockedDevPtsFSInode>,
+    /// pty编号分配器
+    pts_ida: SpinLock<IdAllocator>,
+}
+
+impl FileSystem for DevPtsFS {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        return self.root_inode.clone();
+    }
+
+    fn info(&self) -> FsInfo {
+        return FsInfo {
+            blk_dev_id: 0,
+            max_name_len: DEVPTS_MAX_NAMELEN,
+        };
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl DevPtsFS {
+    pub fn new() -> Arc<Self> {
+        let root = Arc::new(LockedDevPtsFSInode(SpinLock::new(DevPtsFSInode {
+            parent: Weak::default(),
+            self_ref: Weak::default(),
+            children: BTreeMap::new(),
+            metadata: Metadata::new(FileType::Dir, ModeType::from_bits_truncate(0o755)),
+            fs: Weak::default(),
+        })));
+
+        let result = Arc::new(DevPtsFS {
+            root_inode: root,
+            pts_ida: SpinLock::new(IdAllocator::new(0, NR_UNIX98_PTY_MAX as usize)),
+        });
+
+        let mut root_guard: SpinLockGuard<DevPtsFSInode> = result.root_inode.0.lock();
+        root_guard.parent = Arc::downgrade(&result.root_inode);
+        root_guard.self_ref = Arc::downgrade(&result.root_inode);
+        root_guard.fs = Arc::downgrade(&result);
+        drop(root_guard);
+
+        return result;
+    }
+
+    /// @brief 分配一个pty编号
+    pub fn alloc_index(&self) -> Option<usize> {
+        self.pts_ida.lock().alloc()
+    }
+
+    /// @brief 释放pty编号
+    pub fn free_index(&self, index: usize) {
+        self.pts_ida.lock().free(index)
+    }
+
+    /// @brief 在devpts中创建编号为index的pty从设备节点
+    pub fn add_pty(&self, index: usize) -> Result<(), SystemError> {
+        let name = index.to_string();
+        let mut root = self.root_inode.0.lock();
+        if root.children.contains_key(&name) {
+            return Err(SystemError::EEXIST);
+        }
+
+        let device = TtyDevice::new(
+            name.clone(),
+            IdTable::new(
+                name.clone(),
+                Some(DeviceNumber::new(
+                    Major::UNIX98_PTY_SLAVE_MAJOR,
+                    index as u32,
+                )),
+            ),
+        );
+        root.children.insert(name, device);
+
+        return Ok(());
+    }
+
+    /// @brief 移除编号为index的pty从设备节点，并释放它的编号
+    pub fn remove_pty(&self, index: usize) {
+        self.root_inode.0.lock().children.remove(&index.to_string());
+        self.free_index(index);
+    }
+}
+
+/// @brief devpts的根目录inode
+#[derive(Debug)]
+pub struct LockedDevPtsFSInode(SpinLock<DevPtsFSInode>);
+
+#[derive(Debug)]
+pub struct DevPtsFSInode {
+    /// 指向父Inode的弱引用
+    parent: Weak<LockedDevPtsFSInode>,
+    /// 指向自身的弱引用
+    self_ref: Weak<LockedDevPtsFSInode>,
+    /// pty从设备节点
+    children: BTreeMap<String, Arc<TtyDevice>>,
+    /// 当前inode的元数据
+    metadata: Metadata,
+    /// 指向inode所在的文件系统对象的指针
+    fs: Weak<DevPtsFS>,
+}
+
+impl IndexNode for LockedDevPtsFSInode {
+    fn open(&self, _data: &mut FilePrivateData, _mode: &FileMode) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn close(&self, _data: &mut FilePrivateData) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn read_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &mut [u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        return Err(SystemError::EISDIR);
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        return Err(SystemError::EISDIR);
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        return Ok(self.0.lock().metadata.clone());
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.0.lock().fs.upgrade().unwrap();
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let inode = self.0.lock();
+        match name {
+            "" | "." => {
+                return Ok(inode.self_ref.upgrade().ok_or(SystemError::ENOENT)?);
+            }
+            ".." => {
+                return Ok(inode.parent.upgrade().ok_or(SystemError::ENOENT)?);
+            }
+            name => {
+                return Ok(inode.children.get(name).ok_or(SystemError::ENOENT)?.clone());
+            }
+        }
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        let mut keys: Vec<String> = Vec::new();
+        keys.push(String::from("."));
+        keys.push(String::from(".."));
+        keys.append(&mut self.0.lock().children.keys().cloned().collect());
+
+        return Ok(keys);
+    }
+}
+
+/// @brief 初始化devpts，并挂载到/dev/pts
+pub fn devpts_init() -> Result<(), SystemError> {
+    static INIT: Once = Once::new();
+    let mut result = None;
+    INIT.call_once(|| {
+        kinfo!("Initializing DevPtsFS...");
+        // 创建 devpts 实例
+        let devpts: Arc<DevPtsFS> = DevPtsFS::new();
+        unsafe { DEVPTS_INSTANCE = Some(devpts.clone()) };
+
+        // devpts 挂载
+        let mount_fs = ROOT_INODE()
+            .find("dev")
+            .expect("Cannot find /dev")
+            .create("pts", FileType::Dir, ModeType::from_bits_truncate(0o755))
+            .expect("Failed to create /dev/pts")
+            .mount(devpts)
+            .expect("Failed to mount devpts");
+        MountFS::add_mount_record(MountRecord::new(
+            "devpts", "/dev/pts", "devpts", mount_fs, false,
+        ));
+        kinfo!("DevPtsFS mounted.");
+        result = Some(Ok(()));
+    });
+
+    return result.unwrap();
+}
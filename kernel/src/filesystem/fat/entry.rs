@@ -632,7 +632,7 @@ impl FATDir {
                 let mut dot_dot_entry = ShortDirEntry::default();
                 dot_dot_entry.name = ShortNameGenerator::new("..").generate().unwrap();
                 dot_dot_entry.attributes.value = FileAttributes::DIRECTORY;
-                dot_dot_entry.set_first_cluster(self.first_cluster);
+                dot_dot_entry.set_first_cluster(self.dot_dot_cluster(&fs));
                 // todo: 设置创建、访问时间
 
                 dot_dot_entry.flush(&fs, fs.cluster_bytes_offset(first_cluster) + offset)?;
@@ -856,44 +856,133 @@ impl FATDir {
         old_name: &str,
         new_name: &str,
     ) -> Result<FATDirEntry, SystemError> {
-        // 判断源目录项是否存在
-        let old_dentry: FATDirEntry = if let FATDirEntryOrShortName::DirEntry(dentry) =
-            self.check_existence(old_name, None, fs.clone())?
-        {
-            dentry
-        } else {
-            // 如果目标目录项不存在，则返回错误
-            return Err(SystemError::ENOENT);
-        };
+        return self.rename_across(fs, self, old_name, new_name);
+    }
 
-        let short_name = if let FATDirEntryOrShortName::ShortName(s) =
-            self.check_existence(new_name, None, fs.clone())?
-        {
-            s
-        } else {
-            // 如果目标目录项存在，那么就返回错误
-            return Err(SystemError::EEXIST);
+    /// @brief 将当前目录中的一个目录项移动到目标目录中，并重命名
+    ///
+    /// 先在目标目录中创建新的长、短目录项，再删除原来的目录项。若移动的是文件夹，则同时更新它的'..'目录项
+    ///
+    /// @param fs 当前目录所属的文件系统
+    /// @param target 目标目录，可以与当前目录相同
+    /// @param old_name 目录项原来的名字
+    /// @param new_name 目录项新的名字
+    ///
+    /// @return Ok(FATDirEntry) 新的目录项
+    /// @return Err(SystemError) 目标目录中已经存在同名的目录项时，返回-EEXIST；或者返回底层传上来的错误码
+    pub fn rename_across(
+        &self,
+        fs: Arc<FATFileSystem>,
+        target: &FATDir,
+        old_name: &str,
+        new_name: &str,
+    ) -> Result<FATDirEntry, SystemError> {
+        let old_dentry: FATDirEntry = self.find_entry(old_name, None, None, fs.clone())?;
+        // 不允许对根目录项进行重命名
+        let old_range = old_dentry.get_dir_range().ok_or(SystemError::EPERM)?;
+
+        if self.same_dir(target) && old_dentry.eq_name(new_name) {
+            // 只改变名字的大小写时，新的名字会匹配到原来的目录项，因此先删除原来的目录项
+            self.remove_dir_entries(fs.clone(), old_range)?;
+            return target.create_moved_entry(fs, &old_dentry, self, new_name);
+        }
+
+        let new_dentry: FATDirEntry =
+            target.create_moved_entry(fs.clone(), &old_dentry, self, new_name)?;
+        self.remove_dir_entries(fs, old_range)?;
+        return Ok(new_dentry);
+    }
+
+    /// @brief 交换当前目录中的目录项old_name与目标目录中的目录项new_name
+    ///
+    /// @return Ok((FATDirEntry, FATDirEntry)) 交换之后，位于目标目录中的目录项和位于当前目录中的目录项
+    /// @return Err(SystemError) 任意一个目录项不存在时，返回-ENOENT；或者返回底层传上来的错误码
+    pub fn exchange(
+        &self,
+        fs: Arc<FATFileSystem>,
+        target: &FATDir,
+        old_name: &str,
+        new_name: &str,
+    ) -> Result<(FATDirEntry, FATDirEntry), SystemError> {
+        let old_dentry: FATDirEntry = self.find_entry(old_name, None, None, fs.clone())?;
+        let new_dentry: FATDirEntry = target.find_entry(new_name, None, None, fs.clone())?;
+        let old_range = old_dentry.get_dir_range().ok_or(SystemError::EPERM)?;
+        let new_range = new_dentry.get_dir_range().ok_or(SystemError::EPERM)?;
+
+        // 两个名字的目录项都会被重新创建，因此先删除它们，再按照交换之后的名字创建
+        self.remove_dir_entries(fs.clone(), old_range)?;
+        target.remove_dir_entries(fs.clone(), new_range)?;
+
+        let moved_to_target = target.create_moved_entry(fs.clone(), &old_dentry, self, new_name)?;
+        let moved_to_self = self.create_moved_entry(fs, &new_dentry, target, old_name)?;
+        return Ok((moved_to_target, moved_to_self));
+    }
+
+    /// @brief 判断两个FATDir是否为同一个目录
+    #[inline]
+    fn same_dir(&self, other: &FATDir) -> bool {
+        return self.first_cluster == other.first_cluster && self.root_offset == other.root_offset;
+    }
+
+    /// @brief 为从from目录移动过来的目录项，在当前目录中创建名为name的目录项
+    ///
+    /// 新的目录项沿用原来的短目录项中的属性、时间和第一个簇，只重新生成短文件名和长目录项
+    ///
+    /// @param fs 当前目录所属的文件系统
+    /// @param dentry 被移动的目录项
+    /// @param from 被移动的目录项原来所在的目录
+    /// @param name 新的名字
+    fn create_moved_entry(
+        &self,
+        fs: Arc<FATFileSystem>,
+        dentry: &FATDirEntry,
+        from: &FATDir,
+        name: &str,
+    ) -> Result<FATDirEntry, SystemError> {
+        let se: ShortDirEntry = dentry.short_dir_entry().ok_or(SystemError::EPERM)?;
+
+        let short_name = match self.check_existence(name, None, fs.clone())? {
+            FATDirEntryOrShortName::ShortName(s) => s,
+            FATDirEntryOrShortName::DirEntry(_) => {
+                // 目标目录项已经存在
+                return Err(SystemError::EEXIST);
+            }
         };
 
-        let old_short_dentry: Option<ShortDirEntry> = old_dentry.short_dir_entry();
-        if let Some(se) = old_short_dentry {
-            // 删除原来的目录项
-            self.remove(fs.clone(), old_dentry.name().as_str(), false)?;
-
-            // 创建新的目录项
-            let new_dentry: FATDirEntry = self.create_dir_entries(
-                new_name,
-                &short_name,
-                Some(se),
-                se.attributes,
-                fs.clone(),
-            )?;
+        let new_dentry: FATDirEntry = self.create_dir_entries(
+            name.trim(),
+            &short_name,
+            Some(se),
+            se.attributes,
+            fs.clone(),
+        )?;
 
-            return Ok(new_dentry);
-        } else {
-            // 不允许对根目录项进行重命名
-            return Err(SystemError::EPERM);
+        // 文件夹被移动到了另一个目录中，它的'..'目录项需要指向新的父目录
+        if dentry.is_dir() && !self.same_dir(from) {
+            self.adopt_dir(&fs, dentry.first_cluster())?;
+        }
+        return Ok(new_dentry);
+    }
+
+    /// @brief 将第一个簇为dir_cluster的文件夹的'..'目录项指向当前目录
+    fn adopt_dir(&self, fs: &Arc<FATFileSystem>, dir_cluster: Cluster) -> Result<(), SystemError> {
+        // '..'目录项是文件夹中的第二个目录项
+        let offset = fs.cluster_bytes_offset(dir_cluster) + FATRawDirEntry::DIR_ENTRY_LEN;
+        if let FATRawDirEntry::Short(mut dot_dot_entry) = get_raw_dir_entry(fs, offset)? {
+            dot_dot_entry.set_first_cluster(self.dot_dot_cluster(fs));
+            dot_dot_entry.flush(fs, offset)?;
+        }
+        return Ok(());
+    }
+
+    /// @brief 获取当前目录的子文件夹中，'..'目录项应当指向的簇
+    ///
+    /// 按照FAT规范，父目录是根目录时，'..'目录项的第一个簇为0（FAT32的根目录虽然有自己的簇，也是如此）
+    fn dot_dot_cluster(&self, fs: &Arc<FATFileSystem>) -> Cluster {
+        if self.same_dir(&fs.root_dir()) {
+            return Cluster::new(0);
         }
+        return self.first_cluster;
     }
 }
 
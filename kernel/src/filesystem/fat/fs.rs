@@ -10,6 +10,7 @@ use alloc::{
 };
 
 use crate::driver::base::device::device_number::DeviceNumber;
+use crate::filesystem::page_cache::PageCache;
 use crate::filesystem::vfs::SpecialNodeData;
 use crate::ipc::pipe::LockedPipeInode;
 use crate::{
@@ -17,7 +18,7 @@ use crate::{
     filesystem::vfs::{
         core::generate_inode_id,
         file::{FileMode, FilePrivateData},
-        syscall::ModeType,
+        syscall::{ModeType, RenameFlags},
         FileSystem, FileType, IndexNode, InodeId, Metadata,
     },
     kerror,
@@ -109,6 +110,9 @@ pub struct FATInode {
 
     /// 若该节点是特殊文件节点，该字段则为真正的文件节点
     special_node: Option<SpecialNodeData>,
+
+    /// 文件的页面缓存，只有普通文件才有
+    page_cache: Option<Arc<PageCache>>,
 }
 
 impl FATInode {
@@ -129,6 +133,22 @@ impl FATInode {
         };
     }
 
+    /// @brief 获取当前inode对应的文件夹
+    fn dir(&self) -> Result<&FATDir, SystemError> {
+        match &self.inode_type {
+            FATDirEntry::Dir(d) => {
+                return Ok(d);
+            }
+            FATDirEntry::File(_) | FATDirEntry::VolId(_) => {
+                return Err(SystemError::ENOTDIR);
+            }
+            FATDirEntry::UnInit => {
+                kerror!("FATFS: param: Inode_type uninitialized.");
+                return Err(SystemError::EROFS);
+            }
+        }
+    }
+
     fn find(&mut self, name: &str) -> Result<Arc<LockedFATInode>, SystemError> {
         match &self.inode_type {
             FATDirEntry::Dir(d) => {
@@ -166,6 +186,22 @@ impl FATInode {
 }
 
 impl LockedFATInode {
+    /// 判断当前inode是否为dir自身，或者dir的祖先目录
+    ///
+    /// 用于防止把文件夹移动到它自己的子目录中
+    fn is_ancestor_of(&self, dir: &LockedFATInode) -> bool {
+        let mut ancestor: Option<Arc<LockedFATInode>> = dir.0.lock().self_ref.upgrade();
+        while let Some(inode) = ancestor {
+            if core::ptr::eq(inode.as_ref(), self) {
+                return true;
+            }
+            // 根目录的父目录是它自己
+            let parent = inode.0.lock().parent.upgrade();
+            ancestor = parent.filter(|parent| !Arc::ptr_eq(parent, &inode));
+        }
+        return false;
+    }
+
     pub fn new(
         fs: Arc<FATFileSystem>,
         parent: Weak<LockedFATInode>,
@@ -204,9 +240,14 @@ impl LockedFATInode {
                 raw_dev: DeviceNumber::default(),
             },
             special_node: None,
+            page_cache: None,
         })));
 
         inode.0.lock().self_ref = Arc::downgrade(&inode);
+        if file_type == FileType::File {
+            let weak: Weak<dyn IndexNode> = Arc::downgrade(&inode) as Weak<dyn IndexNode>;
+            inode.0.lock().page_cache = Some(PageCache::new(weak));
+        }
 
         inode.0.lock().update_metadata();
 
@@ -324,6 +365,7 @@ impl FATFileSystem {
                 raw_dev: DeviceNumber::default(),
             },
             special_node: None,
+            page_cache: None,
         })));
 
         let result: Arc<FATFileSystem> = Arc::new(FATFileSystem {
@@ -1379,6 +1421,10 @@ impl IndexNode for LockedFATInode {
                     offset as u64,
                 );
                 guard.update_metadata();
+                // 页面缓存中可能有尚未写回的数据，以缓存中的为准
+                if let (Ok(n), Some(page_cache)) = (&r, &guard.page_cache) {
+                    page_cache.read(offset, &mut buf[0..*n]);
+                }
                 return r;
             }
             FATDirEntry::Dir(_) => {
@@ -1405,6 +1451,9 @@ impl IndexNode for LockedFATInode {
             FATDirEntry::File(f) | FATDirEntry::VolId(f) => {
                 let r = f.write(fs, &buf[0..len], offset as u64);
                 guard.update_metadata();
+                if let (Ok(n), Some(page_cache)) = (&r, &guard.page_cache) {
+                    page_cache.write(offset, &buf[0..*n]);
+                }
                 return r;
             }
             FATDirEntry::Dir(_) => {
@@ -1491,6 +1540,9 @@ impl IndexNode for LockedFATInode {
                     file.truncate(fs, len as u64)?;
                 }
                 guard.update_metadata();
+                if let Some(page_cache) = &guard.page_cache {
+                    page_cache.resize(len);
+                }
                 return Ok(());
             }
             FATDirEntry::Dir(_) => return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
@@ -1641,6 +1693,163 @@ impl IndexNode for LockedFATInode {
         }
     }
 
+    fn link(&self, _name: &str, _other: &Arc<dyn IndexNode>) -> Result<(), SystemError> {
+        // FAT的目录项直接记录文件的第一个簇和大小，同一个文件不能有多个目录项，因此不支持硬链接
+        return Err(SystemError::EPERM);
+    }
+
+    fn move_(
+        &self,
+        old_name: &str,
+        target: &Arc<dyn IndexNode>,
+        new_name: &str,
+        flags: RenameFlags,
+    ) -> Result<(), SystemError> {
+        if flags.contains(RenameFlags::RENAME_WHITEOUT) {
+            return Err(SystemError::EINVAL);
+        }
+        let target: &LockedFATInode = target
+            .downcast_ref::<LockedFATInode>()
+            .ok_or(SystemError::EXDEV)?;
+        let same_dir = core::ptr::eq(self, target);
+
+        // 不能把文件夹移动到它自己的子目录中
+        if !same_dir {
+            let old_inode: Arc<LockedFATInode> = self.0.lock().find(old_name)?;
+            if old_inode.is_ancestor_of(target) {
+                return Err(SystemError::EINVAL);
+            }
+            // 交换时，目标也会被移动到当前目录中
+            if flags.contains(RenameFlags::RENAME_EXCHANGE) {
+                let new_inode: Arc<LockedFATInode> = target.0.lock().find(new_name)?;
+                if new_inode.is_ancestor_of(self) {
+                    return Err(SystemError::EINVAL);
+                }
+            }
+        }
+
+        // 按照地址顺序对两个目录上锁，避免两个方向相反的移动操作相互死锁
+        let (mut old_guard, mut new_guard): (
+            SpinLockGuard<FATInode>,
+            Option<SpinLockGuard<FATInode>>,
+        ) = if same_dir {
+            (self.0.lock(), None)
+        } else if (self as *const LockedFATInode) < (target as *const LockedFATInode) {
+            let old_guard = self.0.lock();
+            (old_guard, Some(target.0.lock()))
+        } else {
+            let new_guard = target.0.lock();
+            (self.0.lock(), Some(new_guard))
+        };
+
+        let fs: Arc<FATFileSystem> = old_guard.fs.upgrade().unwrap();
+        let old_dir: FATDir = old_guard.dir()?.clone();
+        let new_dir: FATDir = new_guard.as_deref().unwrap_or(&*old_guard).dir()?.clone();
+        let new_parent: Weak<LockedFATInode> =
+            new_guard.as_deref().unwrap_or(&*old_guard).self_ref.clone();
+
+        let old_inode: Arc<LockedFATInode> = old_guard.find(old_name)?;
+        let existing: Option<Arc<LockedFATInode>> = match new_guard
+            .as_deref_mut()
+            .unwrap_or(&mut *old_guard)
+            .find(new_name)
+        {
+            Ok(inode) => Some(inode),
+            Err(SystemError::ENOENT) => None,
+            Err(e) => return Err(e),
+        };
+        // 新的名字与原来的名字只有大小写不同时，指向的是同一个目录项
+        let existing = existing.filter(|inode| !Arc::ptr_eq(inode, &old_inode));
+        if existing.is_none() && same_dir && old_name == new_name {
+            return Ok(());
+        }
+
+        // 管道文件只存在于缓存中，只需要在缓存中移动
+        if old_inode.0.lock().metadata.file_type == FileType::Pipe {
+            if existing.is_some() {
+                return Err(SystemError::EEXIST);
+            }
+            old_guard.children.remove(&old_name.to_uppercase());
+            new_guard
+                .as_deref_mut()
+                .unwrap_or(&mut *old_guard)
+                .children
+                .insert(new_name.to_uppercase(), old_inode.clone());
+            old_inode.0.lock().parent = new_parent;
+            return Ok(());
+        }
+
+        if flags.contains(RenameFlags::RENAME_EXCHANGE) {
+            let existing = existing.ok_or(SystemError::ENOENT)?;
+            let (moved_to_new, moved_to_old) =
+                old_dir.exchange(fs, &new_dir, old_name, new_name)?;
+
+            old_guard
+                .children
+                .insert(old_name.to_uppercase(), existing.clone());
+            new_guard
+                .as_deref_mut()
+                .unwrap_or(&mut *old_guard)
+                .children
+                .insert(new_name.to_uppercase(), old_inode.clone());
+
+            let mut old_inode_guard = old_inode.0.lock();
+            old_inode_guard.inode_type = moved_to_new;
+            old_inode_guard.parent = new_parent;
+            drop(old_inode_guard);
+
+            let mut existing_guard = existing.0.lock();
+            existing_guard.inode_type = moved_to_old;
+            existing_guard.parent = old_guard.self_ref.clone();
+            return Ok(());
+        }
+
+        let replaced_cluster: Option<Cluster> = if let Some(existing) = existing {
+            if flags.contains(RenameFlags::RENAME_NOREPLACE) {
+                return Err(SystemError::EEXIST);
+            }
+            let old_is_dir = old_inode.0.lock().metadata.file_type == FileType::Dir;
+            let existing_is_dir = existing.0.lock().metadata.file_type == FileType::Dir;
+            match (old_is_dir, existing_is_dir) {
+                (true, false) => return Err(SystemError::ENOTDIR),
+                (false, true) => return Err(SystemError::EISDIR),
+                _ => {}
+            }
+            // 先删除被替换的目标的目录项，它的簇等到重命名成功之后再释放。目标是文件夹时，必须为空
+            let cluster: Cluster = new_dir.get_dir_entry(fs.clone(), new_name)?.first_cluster();
+            new_dir.remove(fs.clone(), new_name, false)?;
+            new_guard
+                .as_deref_mut()
+                .unwrap_or(&mut *old_guard)
+                .children
+                .remove(&new_name.to_uppercase());
+            Some(cluster)
+        } else {
+            None
+        };
+
+        let new_entry: FATDirEntry =
+            old_dir.rename_across(fs.clone(), &new_dir, old_name, new_name)?;
+
+        // 释放被替换的目标的数据簇
+        if let Some(cluster) = replaced_cluster.filter(|c| c.cluster_num >= 2) {
+            fs.deallocate_cluster_chain(cluster)?;
+        }
+
+        // 更新缓存
+        old_guard.children.remove(&old_name.to_uppercase());
+        new_guard
+            .as_deref_mut()
+            .unwrap_or(&mut *old_guard)
+            .children
+            .insert(new_name.to_uppercase(), old_inode.clone());
+
+        let mut old_inode_guard = old_inode.0.lock();
+        old_inode_guard.inode_type = new_entry;
+        old_inode_guard.parent = new_parent;
+        return Ok(());
+    }
+
     fn get_entry_name(&self, ino: InodeId) -> Result<String, SystemError> {
         let guard: SpinLockGuard<FATInode> = self.0.lock();
         if guard.metadata.file_type != FileType::Dir {
@@ -1730,6 +1939,10 @@ impl IndexNode for LockedFATInode {
     fn special_node(&self) -> Option<SpecialNodeData> {
         self.0.lock().special_node.clone()
     }
+
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        self.0.lock().page_cache.clone()
+    }
 }
 
 impl Default for FATFsInfo {
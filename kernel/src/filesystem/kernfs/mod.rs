@@ -21,8 +21,10 @@ use crate::{
 use self::callback::{KernCallbackData, KernFSCallback, KernInodePrivateData};
 
 use super::vfs::{
-    core::generate_inode_id, file::FileMode, syscall::ModeType, FilePrivateData, FileSystem,
-    FileType, FsInfo, IndexNode, InodeId, Metadata,
+    core::generate_inode_id,
+    file::FileMode,
+    syscall::{ModeType, RenameFlags},
+    FilePrivateData, FileSystem, FileType, FsInfo, IndexNode, InodeId, Metadata,
 };
 
 pub mod callback;
@@ -200,6 +202,7 @@ impl IndexNode for KernFSInode {
         _old_name: &str,
         _target: &Arc<dyn IndexNode>,
         _new_name: &str,
+        _flags: RenameFlags,
     ) -> Result<(), SystemError> {
         // 应当通过kernfs的其它方法来操作文件，而不能从用户态直接调用此方法。
         return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
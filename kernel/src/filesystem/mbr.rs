@@ -1,5 +1,16 @@
 #![allow(dead_code)]
-use core::default::Default;
+use core::{default::Default, mem::size_of};
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    driver::base::block::{block_device::BlockDevice, disk_info::Partition, SeekFrom},
+    libs::vec_cursor::VecCursor,
+};
 
 /// @brief MBR硬盘分区表项的结构
 #[repr(packed)]
@@ -63,3 +74,61 @@ impl Default for MbrDiskPartionTable {
         }
     }
 }
+
+impl MbrDiskPartionTable {
+    /// MBR的结束标志
+    pub const BS_TRAILSIG: u16 = 0xAA55;
+
+    /// @brief 从磁盘的第一个扇区中读取MBR分区表
+    pub fn from_disk(disk: &Arc<dyn BlockDevice>) -> Result<Self, SystemError> {
+        let mut buf: Vec<u8> = vec![0; size_of::<MbrDiskPartionTable>()];
+        disk.read_at(0, 1, &mut buf)?;
+
+        let mut table: MbrDiskPartionTable = Default::default();
+        let mut cursor = VecCursor::new(buf);
+        cursor.seek(SeekFrom::SeekCurrent(446))?;
+        for i in 0..4 {
+            table.dpte[i].flags = cursor.read_u8()?;
+            table.dpte[i].starting_head = cursor.read_u8()?;
+            table.dpte[i].starting_sector_cylinder = cursor.read_u16()?;
+            table.dpte[i].part_type = cursor.read_u8()?;
+            table.dpte[i].ending_head = cursor.read_u8()?;
+            table.dpte[i].ending_sector_cylingder = cursor.read_u16()?;
+            table.dpte[i].starting_lba = cursor.read_u32()?;
+            table.dpte[i].total_sectors = cursor.read_u32()?;
+        }
+        table.bs_trailsig = cursor.read_u16()?;
+
+        return Ok(table);
+    }
+
+    /// @brief 分区表是否有效
+    pub fn is_valid(&self) -> bool {
+        let sig = self.bs_trailsig;
+        return sig == Self::BS_TRAILSIG;
+    }
+
+    /// @brief 根据分区表，创建磁盘上所有可用的分区
+    pub fn partitions(&self, disk: Weak<dyn BlockDevice>) -> Vec<Arc<Partition>> {
+        let mut partitions = Vec::new();
+        if !self.is_valid() {
+            return partitions;
+        }
+
+        for i in 0..4 {
+            let entry = self.dpte[i];
+            if entry.part_type == 0 {
+                continue;
+            }
+            partitions.push(Partition::new(
+                entry.starting_sector() as u64,
+                entry.starting_lba as u64,
+                entry.total_sectors as u64,
+                disk.clone(),
+                i as u16,
+            ));
+        }
+
+        return partitions;
+    }
+}
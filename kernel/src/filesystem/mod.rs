@@ -1,7 +1,10 @@
 pub mod devfs;
+pub mod devpts;
 pub mod fat;
 pub mod kernfs;
 pub mod mbr;
+pub mod page_cache;
+pub mod poll;
 pub mod procfs;
 pub mod ramfs;
 pub mod sysfs;
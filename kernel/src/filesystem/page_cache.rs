@@ -0,0 +1,238 @@
+use core::cmp::min;
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    arch::MMArch,
+    libs::spinlock::SpinLock,
+    mm::{
+        allocator::page_frame::{
+            allocate_page_frames, page_frame_ref_dec, page_frame_ref_inc, PageFrameCount,
+        },
+        MemoryManagementArch, PhysAddr,
+    },
+};
+
+use super::vfs::{file::FilePrivateData, IndexNode};
+
+/// 文件的页面缓存
+///
+/// 以页为单位缓存文件的内容，供文件映射（mmap）使用。缓存中的每个物理页都持有一个引用计数，
+/// 映射到用户地址空间的页表项会各自再持有一个引用计数，因此在映射解除之前，物理页不会被释放。
+///
+/// 文件系统需要在`read_at`、`write_at`、`resize`中调用本结构体的对应方法，
+/// 以保证通过read/write访问的数据与映射中的数据一致。
+#[derive(Debug)]
+pub struct PageCache {
+    inner: SpinLock<InnerPageCache>,
+    /// 页面缓存所属的inode
+    inode: Weak<dyn IndexNode>,
+}
+
+#[derive(Debug)]
+struct InnerPageCache {
+    /// 页号到物理页的映射
+    pages: BTreeMap<usize, PhysAddr>,
+    /// 被共享映射修改过，尚未写回文件的页号
+    dirty: BTreeSet<usize>,
+}
+
+impl PageCache {
+    pub fn new(inode: Weak<dyn IndexNode>) -> Arc<Self> {
+        return Arc::new(Self {
+            inner: SpinLock::new(InnerPageCache {
+                pages: BTreeMap::new(),
+                dirty: BTreeSet::new(),
+            }),
+            inode,
+        });
+    }
+
+    /// 获取文件第`index`页对应的物理页，并增加它的引用计数
+    ///
+    /// 如果该页不在缓存中，就分配一个物理页，并从文件中读取数据填充它。超出文件末尾的部分会被填充为0。
+    /// 调用者在不再使用这个物理页时，需要调用`page_frame_ref_dec`
+    ///
+    /// 请注意，调用者不能持有inode的锁，因为填充缓存时需要调用inode的`read_at`方法
+    pub fn get_page(&self, index: usize) -> Result<PhysAddr, SystemError> {
+        if let Some(paddr) = self.find_page(index) {
+            return Ok(paddr);
+        }
+
+        let inode = self.inode.upgrade().ok_or(SystemError::EIO)?;
+        let (paddr, _) =
+            unsafe { allocate_page_frames(PageFrameCount::new(1)) }.ok_or(SystemError::ENOMEM)?;
+        let buf = unsafe { page_slice_mut(paddr) };
+        buf.fill(0);
+
+        // 填充缓存时不能持有缓存的锁，因为文件系统的read_at会回过头来访问页面缓存
+        if let Err(e) = inode.read_at(
+            index * MMArch::PAGE_SIZE,
+            MMArch::PAGE_SIZE,
+            buf,
+            &mut FilePrivateData::Unused,
+        ) {
+            unsafe { page_frame_ref_dec(paddr) };
+            return Err(e);
+        }
+
+        let mut guard = self.inner.lock_irqsave();
+        if let Some(exist) = guard.pages.get(&index) {
+            // 其他人已经抢先填充了这个页面，使用他们的结果
+            let exist = *exist;
+            page_frame_ref_inc(exist);
+            drop(guard);
+            unsafe { page_frame_ref_dec(paddr) };
+            return Ok(exist);
+        }
+        guard.pages.insert(index, paddr);
+        page_frame_ref_inc(paddr);
+        return Ok(paddr);
+    }
+
+    /// 获取已经在缓存中的文件第`index`页对应的物理页，并增加它的引用计数
+    ///
+    /// 与`get_page`不同，该页不在缓存中时不会读取文件，而是返回None。
+    /// 用于不能进行文件读取的场景，例如持有地址空间的锁的缺页处理
+    pub fn find_page(&self, index: usize) -> Option<PhysAddr> {
+        let guard = self.inner.lock_irqsave();
+        let paddr = *guard.pages.get(&index)?;
+        page_frame_ref_inc(paddr);
+        return Some(paddr);
+    }
+
+    /// 把文件第`index`页标记为脏页，等待写回
+    pub fn mark_dirty(&self, index: usize) {
+        let mut guard = self.inner.lock_irqsave();
+        if guard.pages.contains_key(&index) {
+            guard.dirty.insert(index);
+        }
+    }
+
+    /// 把缓存中的数据覆盖到从文件中读取到的数据上
+    ///
+    /// 缓存中的页面可能被共享映射修改过，且还没有写回文件，因此缓存中的数据总是比文件中的新
+    ///
+    /// ## 参数
+    ///
+    /// - `offset`: 读取的数据在文件中的偏移量
+    /// - `buf`: 已经从文件中读取到的数据
+    pub fn read(&self, offset: usize, buf: &mut [u8]) {
+        let guard = self.inner.lock_irqsave();
+        Self::for_each_cached(&guard, offset, buf.len(), |page, buf_off, len| {
+            buf[buf_off..buf_off + len].copy_from_slice(&page[..len]);
+        });
+    }
+
+    /// 把写入文件的数据同步到缓存中的页面上
+    ///
+    /// ## 参数
+    ///
+    /// - `offset`: 写入的数据在文件中的偏移量
+    /// - `buf`: 写入文件的数据
+    pub fn write(&self, offset: usize, buf: &[u8]) {
+        let guard = self.inner.lock_irqsave();
+        Self::for_each_cached(&guard, offset, buf.len(), |page, buf_off, len| {
+            page[..len].copy_from_slice(&buf[buf_off..buf_off + len]);
+        });
+    }
+
+    /// 文件大小改变时，调整缓存中的页面
+    ///
+    /// 完全超出文件末尾的页面会被移出缓存（已经映射了这些页面的地址空间仍然持有它们的引用），
+    /// 最后一页中超出文件末尾的部分会被清零
+    pub fn resize(&self, len: usize) {
+        let mut guard = self.inner.lock_irqsave();
+        let first_removed = (len + MMArch::PAGE_SIZE - 1) / MMArch::PAGE_SIZE;
+        let removed = guard.pages.split_off(&first_removed);
+        guard.dirty.retain(|index| *index < first_removed);
+
+        if len % MMArch::PAGE_SIZE != 0 {
+            if let Some(paddr) = guard.pages.get(&(len / MMArch::PAGE_SIZE)) {
+                let page = unsafe { page_slice_mut(*paddr) };
+                page[len % MMArch::PAGE_SIZE..].fill(0);
+            }
+        }
+        drop(guard);
+
+        for paddr in removed.into_values() {
+            unsafe { page_frame_ref_dec(paddr) };
+        }
+    }
+
+    /// 把缓存中的脏页写回文件
+    pub fn sync(&self) -> Result<(), SystemError> {
+        let inode = match self.inode.upgrade() {
+            Some(inode) => inode,
+            None => return Ok(()),
+        };
+        let file_size = inode.metadata()?.size as usize;
+
+        let dirty: Vec<(usize, Vec<u8>)> = {
+            let mut guard = self.inner.lock_irqsave();
+            let dirty = core::mem::take(&mut guard.dirty);
+            dirty
+                .into_iter()
+                .filter_map(|index| {
+                    let paddr = *guard.pages.get(&index)?;
+                    let data = unsafe { page_slice_mut(paddr) }.to_vec();
+                    Some((index, data))
+                })
+                .collect()
+        };
+
+        // 写回时不能持有缓存的锁，因为文件系统的write_at会回过头来访问页面缓存
+        for (index, data) in dirty {
+            let offset = index * MMArch::PAGE_SIZE;
+            if offset >= file_size {
+                continue;
+            }
+            let len = min(MMArch::PAGE_SIZE, file_size - offset);
+            inode.write_at(offset, len, &data[..len], &mut FilePrivateData::Unused)?;
+        }
+        return Ok(());
+    }
+
+    /// 对区间[offset, offset + len)中已经被缓存的每个页面调用`f`
+    ///
+    /// `f`的参数分别为：从区间在页面内的起始位置开始的页面数据、区间在缓冲区中的偏移量、本次处理的长度
+    fn for_each_cached(
+        inner: &InnerPageCache,
+        offset: usize,
+        len: usize,
+        mut f: impl FnMut(&mut [u8], usize, usize),
+    ) {
+        if len == 0 {
+            return;
+        }
+        let start_index = offset / MMArch::PAGE_SIZE;
+        let end_index = (offset + len - 1) / MMArch::PAGE_SIZE;
+        for (index, paddr) in inner.pages.range(start_index..=end_index) {
+            let page_start = index * MMArch::PAGE_SIZE;
+            let start = core::cmp::max(page_start, offset);
+            let end = min(page_start + MMArch::PAGE_SIZE, offset + len);
+            let page = unsafe { page_slice_mut(*paddr) };
+            f(&mut page[start - page_start..], start - offset, end - start);
+        }
+    }
+}
+
+impl Drop for PageCache {
+    fn drop(&mut self) {
+        let pages = core::mem::take(&mut self.inner.lock_irqsave().pages);
+        for paddr in pages.into_values() {
+            unsafe { page_frame_ref_dec(paddr) };
+        }
+    }
+}
+
+/// 获取物理页在内核地址空间中的切片
+unsafe fn page_slice_mut<'a>(paddr: PhysAddr) -> &'a mut [u8] {
+    let vaddr = MMArch::phys_2_virt(paddr).unwrap();
+    return core::slice::from_raw_parts_mut(vaddr.data() as *mut u8, MMArch::PAGE_SIZE);
+}
@@ -0,0 +1,313 @@
+//! poll和select
+//!
+//! 等待期间为要查询的文件创建一个临时的epoll对象，文件状态发生变化时，文件会唤醒在这个epoll上等待的进程；
+//! 进程被唤醒之后，再通过`IndexNode::poll`重新查询每个文件的状态。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/fs/select.c
+
+pub mod syscall;
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use system_error::SystemError;
+
+use crate::{
+    arch::{sched::sched, CurrentIrqArch},
+    exception::InterruptArch,
+    net::event_poll::{EPollEventType, LockedEventPoll},
+    process::ProcessManager,
+    time::{
+        hrtimer::{HrTimer, HrTimerMode, HrTimerWakeUp},
+        timekeeping::ktime_get_monotonic_ns,
+    },
+};
+
+/// 用户传入poll的结构体
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PollFd {
+    /// 文件描述符，为负数时忽略这一项
+    pub fd: i32,
+    /// 感兴趣的事件
+    pub events: u16,
+    /// 返回发生的事件
+    pub revents: u16,
+}
+
+/// 文件不支持poll时，认为它总是可读可写
+fn default_pollmask() -> EPollEventType {
+    return EPollEventType::EPOLLIN
+        | EPollEventType::EPOLLOUT
+        | EPollEventType::EPOLLRDNORM
+        | EPollEventType::EPOLLWRNORM;
+}
+
+/// select的读集合对应的事件
+fn pollin_set() -> EPollEventType {
+    return EPollEventType::EPOLLRDNORM
+        | EPollEventType::EPOLLRDBAND
+        | EPollEventType::EPOLLIN
+        | EPollEventType::EPOLLHUP
+        | EPollEventType::EPOLLERR;
+}
+
+/// select的写集合对应的事件
+fn pollout_set() -> EPollEventType {
+    return EPollEventType::EPOLLWRBAND
+        | EPollEventType::EPOLLWRNORM
+        | EPollEventType::EPOLLOUT
+        | EPollEventType::EPOLLERR;
+}
+
+/// select的异常集合对应的事件
+fn pollex_set() -> EPollEventType {
+    return EPollEventType::EPOLLPRI;
+}
+
+/// ## 查询当前进程的一个文件描述符的状态
+///
+/// 文件描述符无效时返回EPOLLNVAL
+fn file_poll_mask(fd: i32) -> EPollEventType {
+    let file = ProcessManager::current_pcb()
+        .fd_table()
+        .read()
+        .get_file_by_fd(fd);
+    let file = match file {
+        Some(file) => file,
+        None => return EPollEventType::EPOLLNVAL,
+    };
+    let r = file.lock_irqsave().poll();
+    match r {
+        Ok(mask) => return EPollEventType::from_bits_truncate(mask as u32),
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP) => return default_pollmask(),
+        Err(_) => return EPollEventType::EPOLLERR,
+    }
+}
+
+/// ## 等待文件就绪
+///
+/// ## 参数
+///
+/// - `interest`: 要监听的文件描述符及其感兴趣的事件
+/// - `deadline`: 单调时钟的到期时间（纳秒），为None时一直等待
+/// - `scan`: 查询所有文件的状态，有文件就绪时返回Some
+///
+/// ## 返回值
+///
+/// - `Ok(Some(_))`: `scan`的返回值
+/// - `Ok(None)`: 超时
+/// - `Err(SystemError::EINTR)`: 被信号打断
+fn do_poll_wait<T>(
+    interest: &BTreeMap<i32, EPollEventType>,
+    deadline: Option<i64>,
+    mut scan: impl FnMut() -> Option<T>,
+) -> Result<Option<T>, SystemError> {
+    if let Some(r) = scan() {
+        return Ok(Some(r));
+    }
+    if deadline.map_or(false, |deadline| ktime_get_monotonic_ns() >= deadline) {
+        return Ok(None);
+    }
+
+    let pcb = ProcessManager::current_pcb();
+    let epoll = LockedEventPoll::new();
+    for (fd, events) in interest.iter() {
+        let file = pcb.fd_table().read().get_file_by_fd(*fd);
+        if let Some(file) = file {
+            // 不支持事件通知的文件只能在超时或者其他文件就绪时重新查询
+            epoll.watch(*fd, file, *events).ok();
+        }
+    }
+
+    let r = loop {
+        let timer = deadline.map(|_| HrTimer::new(HrTimerWakeUp::new(pcb.clone())));
+
+        let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+        unsafe { epoll.sleep_without_schedule() };
+        // 加入等待队列之后再查询一次，避免错过在上一次查询之后发生的事件
+        let ready = scan();
+        let timeout = deadline.map_or(false, |deadline| ktime_get_monotonic_ns() >= deadline);
+        let interrupted = pcb.sig_info_irqsave().sig_pending().has_pending();
+        if ready.is_some() || timeout || interrupted {
+            ProcessManager::wakeup(&pcb).ok();
+            drop(irq_guard);
+            if ready.is_some() {
+                break Ok(ready);
+            } else if interrupted {
+                break Err(SystemError::EINTR);
+            } else {
+                break Ok(None);
+            }
+        }
+        if let (Some(timer), Some(deadline)) = (timer.as_ref(), deadline) {
+            timer.start(deadline, HrTimerMode::Abs);
+        }
+        drop(irq_guard);
+        sched();
+
+        if let Some(timer) = timer {
+            timer.cancel();
+        }
+    };
+
+    epoll.unwatch_all();
+    return r;
+}
+
+/// ## poll的实现
+///
+/// ## 参数
+///
+/// - `poll_fds`: 要查询的文件描述符，返回时填写其中的`revents`
+/// - `deadline`: 单调时钟的到期时间（纳秒），为None时一直等待
+///
+/// ## 返回值
+///
+/// `revents`不为0的项的数量，超时返回0
+pub fn do_sys_poll(poll_fds: &mut [PollFd], deadline: Option<i64>) -> Result<usize, SystemError> {
+    // 同一个文件描述符可能出现多次，合并它们感兴趣的事件
+    let mut interest: BTreeMap<i32, EPollEventType> = BTreeMap::new();
+    for poll_fd in poll_fds.iter().filter(|poll_fd| poll_fd.fd >= 0) {
+        let events = EPollEventType::from_bits_truncate(poll_fd.events as u32)
+            | EPollEventType::EPOLLERR
+            | EPollEventType::EPOLLHUP;
+        *interest
+            .entry(poll_fd.fd)
+            .or_insert(EPollEventType::empty()) |= events;
+    }
+
+    let scan = || {
+        let mut count = 0;
+        let revents = poll_fds
+            .iter()
+            .map(|poll_fd| {
+                if poll_fd.fd < 0 {
+                    return 0;
+                }
+                let events = EPollEventType::from_bits_truncate(poll_fd.events as u32)
+                    | EPollEventType::EPOLLERR
+                    | EPollEventType::EPOLLHUP
+                    | EPollEventType::EPOLLNVAL;
+                let mask = file_poll_mask(poll_fd.fd) & events;
+                if !mask.is_empty() {
+                    count += 1;
+                }
+                mask.bits() as u16
+            })
+            .collect::<Vec<_>>();
+        if count > 0 {
+            Some((count, revents))
+        } else {
+            None
+        }
+    };
+
+    let r = do_poll_wait(&interest, deadline, scan)?;
+    let count = match r {
+        Some((count, revents)) => {
+            for (poll_fd, revents) in poll_fds.iter_mut().zip(revents) {
+                poll_fd.revents = revents;
+            }
+            count
+        }
+        None => {
+            poll_fds.iter_mut().for_each(|poll_fd| poll_fd.revents = 0);
+            0
+        }
+    };
+    return Ok(count);
+}
+
+/// select使用的文件描述符集合，每个文件描述符对应一个比特
+pub type FdSet = Vec<u64>;
+
+/// 文件描述符集合中的一个字包含的文件描述符数量
+pub const NFDBITS: usize = u64::BITS as usize;
+
+#[inline]
+fn fd_isset(set: &Option<FdSet>, fd: usize) -> bool {
+    return set
+        .as_ref()
+        .map_or(false, |set| set[fd / NFDBITS] & (1 << (fd % NFDBITS)) != 0);
+}
+
+/// ## select的实现
+///
+/// ## 参数
+///
+/// - `nfds`: 要查询的最大的文件描述符加1
+/// - `readfds`: 要查询是否可读的文件描述符集合，返回时只保留可读的文件描述符
+/// - `writefds`: 要查询是否可写的文件描述符集合，返回时只保留可写的文件描述符
+/// - `exceptfds`: 要查询是否有异常的文件描述符集合，返回时只保留有异常的文件描述符
+/// - `deadline`: 单调时钟的到期时间（纳秒），为None时一直等待
+///
+/// ## 返回值
+///
+/// 三个集合中就绪的文件描述符的总数，超时返回0
+pub fn do_select(
+    nfds: usize,
+    readfds: &mut Option<FdSet>,
+    writefds: &mut Option<FdSet>,
+    exceptfds: &mut Option<FdSet>,
+    deadline: Option<i64>,
+) -> Result<usize, SystemError> {
+    let fd_table = ProcessManager::current_pcb().fd_table();
+    let mut interest: BTreeMap<i32, EPollEventType> = BTreeMap::new();
+    for fd in 0..nfds {
+        let mut events = EPollEventType::empty();
+        if fd_isset(readfds, fd) {
+            events |= pollin_set();
+        }
+        if fd_isset(writefds, fd) {
+            events |= pollout_set();
+        }
+        if fd_isset(exceptfds, fd) {
+            events |= pollex_set();
+        }
+        if events.is_empty() {
+            continue;
+        }
+        if fd_table.read().get_file_by_fd(fd as i32).is_none() {
+            return Err(SystemError::EBADF);
+        }
+        interest.insert(fd as i32, events);
+    }
+    drop(fd_table);
+
+    let words = (nfds + NFDBITS - 1) / NFDBITS;
+    let scan = || {
+        let mut count = 0;
+        let mut result = [vec![0u64; words], vec![0u64; words], vec![0u64; words]];
+        for fd in interest.keys().map(|fd| *fd as usize) {
+            let mask = file_poll_mask(fd as i32);
+            let sets = [
+                (&*readfds, pollin_set()),
+                (&*writefds, pollout_set()),
+                (&*exceptfds, pollex_set()),
+            ];
+            for ((set, events), out) in sets.iter().zip(result.iter_mut()) {
+                if fd_isset(set, fd) && mask.intersects(*events) {
+                    out[fd / NFDBITS] |= 1 << (fd % NFDBITS);
+                    count += 1;
+                }
+            }
+        }
+        if count > 0 {
+            Some((count, result))
+        } else {
+            None
+        }
+    };
+
+    let r = do_poll_wait(&interest, deadline, scan)?;
+    let (count, [rres, wres, eres]) =
+        r.unwrap_or_else(|| (0, [vec![0; words], vec![0; words], vec![0; words]]));
+    for (set, res) in [readfds, writefds, exceptfds]
+        .into_iter()
+        .zip([rres, wres, eres])
+    {
+        if let Some(set) = set.as_mut() {
+            set[..words].copy_from_slice(&res);
+        }
+    }
+    return Ok(count);
+}
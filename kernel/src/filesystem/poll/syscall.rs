@@ -0,0 +1,278 @@
+use core::mem::size_of;
+
+use alloc::{vec, vec::Vec};
+use system_error::SystemError;
+
+use crate::{
+    arch::ipc::signal::SigSet,
+    filesystem::vfs::file::FileDescriptorVec,
+    ipc::syscall::read_sigset_from_user,
+    process::ProcessManager,
+    syscall::{
+        user_access::{UserBufferReader, UserBufferWriter},
+        Syscall,
+    },
+    time::{
+        syscall::PosixTimeval, timekeeping::ktime_get_monotonic_ns, TimeSpec, NSEC_PER_MSEC,
+        NSEC_PER_SEC, NSEC_PER_USEC,
+    },
+};
+
+use super::{do_select, do_sys_poll, FdSet, PollFd, NFDBITS};
+
+/// pselect6的第六个参数
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PSelectSigData {
+    /// 等待期间使用的信号屏蔽字，为空时不修改屏蔽字
+    ss: *const SigSet,
+    /// 信号屏蔽字的大小
+    ss_len: usize,
+}
+
+/// 把相对的超时时间转换为单调时钟的到期时间（纳秒）
+fn timeout_to_deadline(timeout_ns: i64) -> i64 {
+    return ktime_get_monotonic_ns().saturating_add(timeout_ns);
+}
+
+/// 距离到期时间剩余的纳秒数
+fn deadline_remaining(deadline: i64) -> i64 {
+    return (deadline - ktime_get_monotonic_ns()).max(0);
+}
+
+/// 从用户空间读取文件描述符集合，指针为空时返回None
+fn read_fd_set(ptr: *const u64, nfds: usize) -> Result<Option<FdSet>, SystemError> {
+    if ptr.is_null() {
+        return Ok(None);
+    }
+    let words = (nfds + NFDBITS - 1) / NFDBITS;
+    let reader = UserBufferReader::new(ptr, words * size_of::<u64>(), true)?;
+    let mut set = vec![0u64; words];
+    reader.copy_from_user(&mut set, 0)?;
+    return Ok(Some(set));
+}
+
+/// 把文件描述符集合写回用户空间
+fn write_fd_set(ptr: *mut u64, set: &Option<FdSet>) -> Result<(), SystemError> {
+    if let Some(set) = set {
+        let mut writer = UserBufferWriter::new(ptr, set.len() * size_of::<u64>(), true)?;
+        writer.copy_to_user(set, 0)?;
+    }
+    return Ok(());
+}
+
+/// ## 临时修改信号屏蔽字
+///
+/// 返回EINTR时，原来的屏蔽字在处理信号之后恢复，这样才能处理等待期间收到的信号；否则立即恢复
+fn with_sigmask(
+    sigmask: Option<SigSet>,
+    f: impl FnOnce() -> Result<usize, SystemError>,
+) -> Result<usize, SystemError> {
+    let sigmask = match sigmask {
+        Some(sigmask) => sigmask,
+        None => return f(),
+    };
+    let pcb = ProcessManager::current_pcb();
+    pcb.sig_info_mut().set_temporary_sig_block(sigmask);
+    let r = f();
+    if r != Err(SystemError::EINTR) {
+        pcb.sig_info_mut().restore_saved_sigmask();
+    }
+    return r;
+}
+
+impl Syscall {
+    /// # 等待一组文件描述符上的事件
+    ///
+    /// ## 参数
+    ///
+    /// - `fds`: 用户空间的pollfd数组
+    /// - `nfds`: 数组的长度
+    /// - `timeout_ms`: 超时时间（毫秒），为负数时一直等待
+    ///
+    /// ## 返回值
+    ///
+    /// 有事件发生的文件描述符的数量，超时返回0
+    pub fn poll(fds: *mut PollFd, nfds: u32, timeout_ms: i32) -> Result<usize, SystemError> {
+        let deadline = if timeout_ms >= 0 {
+            Some(timeout_to_deadline(
+                timeout_ms as i64 * NSEC_PER_MSEC as i64,
+            ))
+        } else {
+            None
+        };
+        return Self::do_poll(fds, nfds, deadline);
+    }
+
+    /// # 等待一组文件描述符上的事件，等待期间临时替换信号屏蔽字
+    ///
+    /// ## 参数
+    ///
+    /// - `fds`: 用户空间的pollfd数组
+    /// - `nfds`: 数组的长度
+    /// - `tmo_p`: 超时时间，为空时一直等待。返回时写入剩余的时间
+    /// - `sigmask`: 等待期间使用的信号屏蔽字，为空时不修改屏蔽字
+    /// - `sigsetsize`: 信号屏蔽字的大小
+    pub fn ppoll(
+        fds: *mut PollFd,
+        nfds: u32,
+        tmo_p: *mut TimeSpec,
+        sigmask: *const SigSet,
+        sigsetsize: usize,
+    ) -> Result<usize, SystemError> {
+        let deadline = Self::read_timespec_timeout(tmo_p)?;
+        let sigmask = if sigmask.is_null() {
+            None
+        } else {
+            Some(read_sigset_from_user(sigmask, sigsetsize)?)
+        };
+
+        let r = with_sigmask(sigmask, || Self::do_poll(fds, nfds, deadline));
+        if let Some(deadline) = deadline {
+            Self::write_timespec_remaining(tmo_p, deadline);
+        }
+        return r;
+    }
+
+    fn do_poll(fds: *mut PollFd, nfds: u32, deadline: Option<i64>) -> Result<usize, SystemError> {
+        if nfds as usize > FileDescriptorVec::PROCESS_MAX_FD {
+            return Err(SystemError::EINVAL);
+        }
+        let len = nfds as usize * size_of::<PollFd>();
+        let reader = UserBufferReader::new(fds, len, true)?;
+        let mut poll_fds: Vec<PollFd> = reader.read_from_user::<PollFd>(0)?.to_vec();
+
+        let count = do_sys_poll(&mut poll_fds, deadline)?;
+
+        let mut writer = UserBufferWriter::new(fds, len, true)?;
+        writer.copy_to_user(&poll_fds, 0)?;
+        return Ok(count);
+    }
+
+    /// # 同步地等待多个文件描述符就绪
+    ///
+    /// ## 参数
+    ///
+    /// - `nfds`: 三个集合中最大的文件描述符加1
+    /// - `readfds`: 要等待可读的文件描述符集合，可以为空
+    /// - `writefds`: 要等待可写的文件描述符集合，可以为空
+    /// - `exceptfds`: 要等待异常的文件描述符集合，可以为空
+    /// - `timeout`: 超时时间，为空时一直等待。返回时写入剩余的时间
+    pub fn select(
+        nfds: i32,
+        readfds: *mut u64,
+        writefds: *mut u64,
+        exceptfds: *mut u64,
+        timeout: *mut PosixTimeval,
+    ) -> Result<usize, SystemError> {
+        let deadline = if timeout.is_null() {
+            None
+        } else {
+            let reader = UserBufferReader::new(timeout, size_of::<PosixTimeval>(), true)?;
+            let tv = *reader.read_one_from_user::<PosixTimeval>(0)?;
+            if tv.tv_sec < 0 || tv.tv_usec < 0 {
+                return Err(SystemError::EINVAL);
+            }
+            let timeout_ns = (tv.tv_sec as i64)
+                .saturating_mul(NSEC_PER_SEC as i64)
+                .saturating_add(tv.tv_usec as i64 * NSEC_PER_USEC as i64);
+            Some(timeout_to_deadline(timeout_ns))
+        };
+
+        let r = Self::do_select_user(nfds, readfds, writefds, exceptfds, deadline);
+        if let Some(deadline) = deadline {
+            let remaining = deadline_remaining(deadline);
+            let tv = PosixTimeval {
+                tv_sec: remaining / NSEC_PER_SEC as i64,
+                tv_usec: ((remaining % NSEC_PER_SEC as i64) / NSEC_PER_USEC as i64) as _,
+            };
+            if let Ok(mut writer) = UserBufferWriter::new(timeout, size_of::<PosixTimeval>(), true)
+            {
+                writer.copy_one_to_user(&tv, 0).ok();
+            }
+        }
+        return r;
+    }
+
+    /// # 同步地等待多个文件描述符就绪，等待期间临时替换信号屏蔽字
+    ///
+    /// ## 参数
+    ///
+    /// - `nfds`: 三个集合中最大的文件描述符加1
+    /// - `readfds`: 要等待可读的文件描述符集合，可以为空
+    /// - `writefds`: 要等待可写的文件描述符集合，可以为空
+    /// - `exceptfds`: 要等待异常的文件描述符集合，可以为空
+    /// - `timeout`: 超时时间，为空时一直等待。返回时写入剩余的时间
+    /// - `sig`: 等待期间使用的信号屏蔽字，可以为空
+    pub fn pselect6(
+        nfds: i32,
+        readfds: *mut u64,
+        writefds: *mut u64,
+        exceptfds: *mut u64,
+        timeout: *mut TimeSpec,
+        sig: *const PSelectSigData,
+    ) -> Result<usize, SystemError> {
+        let deadline = Self::read_timespec_timeout(timeout)?;
+        let mut sigmask = None;
+        if !sig.is_null() {
+            let reader = UserBufferReader::new(sig, size_of::<PSelectSigData>(), true)?;
+            let data = *reader.read_one_from_user::<PSelectSigData>(0)?;
+            if !data.ss.is_null() {
+                sigmask = Some(read_sigset_from_user(data.ss, data.ss_len)?);
+            }
+        }
+
+        let r = with_sigmask(sigmask, || {
+            Self::do_select_user(nfds, readfds, writefds, exceptfds, deadline)
+        });
+        if let Some(deadline) = deadline {
+            Self::write_timespec_remaining(timeout, deadline);
+        }
+        return r;
+    }
+
+    fn do_select_user(
+        nfds: i32,
+        readfds: *mut u64,
+        writefds: *mut u64,
+        exceptfds: *mut u64,
+        deadline: Option<i64>,
+    ) -> Result<usize, SystemError> {
+        if nfds < 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let nfds = (nfds as usize).min(FileDescriptorVec::PROCESS_MAX_FD);
+
+        let mut rset = read_fd_set(readfds, nfds)?;
+        let mut wset = read_fd_set(writefds, nfds)?;
+        let mut eset = read_fd_set(exceptfds, nfds)?;
+
+        let count = do_select(nfds, &mut rset, &mut wset, &mut eset, deadline)?;
+
+        write_fd_set(readfds, &rset)?;
+        write_fd_set(writefds, &wset)?;
+        write_fd_set(exceptfds, &eset)?;
+        return Ok(count);
+    }
+
+    /// 读取用户传入的超时时间，并转换为到期时间。指针为空时返回None
+    fn read_timespec_timeout(timeout: *const TimeSpec) -> Result<Option<i64>, SystemError> {
+        if timeout.is_null() {
+            return Ok(None);
+        }
+        let reader = UserBufferReader::new(timeout, size_of::<TimeSpec>(), true)?;
+        let ts = *reader.read_one_from_user::<TimeSpec>(0)?;
+        if !ts.is_valid() {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(Some(timeout_to_deadline(ts.total_nsecs())));
+    }
+
+    /// 把剩余的超时时间写回用户空间，写入失败时忽略
+    fn write_timespec_remaining(timeout: *mut TimeSpec, deadline: i64) {
+        let ts = TimeSpec::from_nsecs(deadline_remaining(deadline));
+        if let Ok(mut writer) = UserBufferWriter::new(timeout, size_of::<TimeSpec>(), true) {
+            writer.copy_one_to_user(&ts, 0).ok();
+        }
+    }
+}
@@ -15,6 +15,7 @@ use crate::{
     driver::base::device::device_number::DeviceNumber,
     filesystem::vfs::{
         core::{generate_inode_id, ROOT_INODE},
+        mount::{MountFS, MountRecord},
         FileType,
     },
     kerror, kinfo,
@@ -23,13 +24,16 @@ use crate::{
         spinlock::{SpinLock, SpinLockGuard},
     },
     mm::allocator::page_frame::FrameAllocator,
-    process::{Pid, ProcessManager},
+    process::{
+        coredump::{core_pattern, set_core_pattern},
+        Pid, ProcessManager,
+    },
     time::TimeSpec,
 };
 
 use super::vfs::{
     file::{FileMode, FilePrivateData},
-    syscall::ModeType,
+    syscall::{ModeType, RenameFlags},
     FileSystem, FsInfo, IndexNode, InodeId, Metadata,
 };
 
@@ -48,6 +52,10 @@ pub enum ProcFileType {
     ProcMeminfo = 1,
     /// kmsg
     ProcKmsg = 2,
+    /// mounts
+    ProcMounts = 3,
+    /// sys/kernel/core_pattern
+    ProcCorePattern = 4,
     //todo: 其他文件类型
     ///默认文件类型
     Default,
@@ -59,6 +67,8 @@ impl From<u8> for ProcFileType {
             0 => ProcFileType::ProcStatus,
             1 => ProcFileType::ProcMeminfo,
             2 => ProcFileType::ProcKmsg,
+            3 => ProcFileType::ProcMounts,
+            4 => ProcFileType::ProcCorePattern,
             _ => ProcFileType::Default,
         }
     }
@@ -243,6 +253,32 @@ impl ProcFSInode {
         return Ok((data.len() * size_of::<u8>()) as i64);
     }
 
+    /// 打开 mounts 文件
+    fn open_mounts(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let data: &mut Vec<u8> = &mut pdata.data;
+        // 每一行的格式为：挂载源 挂载点 文件系统类型 挂载选项 0 0
+        for record in MountFS::mount_records() {
+            data.append(
+                &mut format!(
+                    "{} {} {} rw 0 0\n",
+                    record.source, record.path, record.fs_type
+                )
+                .as_bytes()
+                .to_owned(),
+            );
+        }
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
+    /// 打开 sys/kernel/core_pattern 文件
+    fn open_core_pattern(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let data: &mut Vec<u8> = &mut pdata.data;
+        data.append(&mut format!("{}\n", core_pattern()).as_bytes().to_owned());
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
     /// proc文件系统读取函数
     fn proc_read(
         &self,
@@ -356,6 +392,46 @@ impl ProcFS {
             panic!("create ksmg error");
         }
 
+        // 创建mounts文件
+        let binding = inode.create(
+            "mounts",
+            FileType::File,
+            ModeType::from_bits_truncate(0o444),
+        );
+        if let Ok(mounts) = binding {
+            let mounts_file = mounts
+                .as_any_ref()
+                .downcast_ref::<LockedProcFSInode>()
+                .unwrap();
+            mounts_file.0.lock().fdata.pid = Pid::new(0);
+            mounts_file.0.lock().fdata.ftype = ProcFileType::ProcMounts;
+        } else {
+            panic!("create mounts error");
+        }
+
+        // 创建sys/kernel/core_pattern文件
+        let kernel_dir = inode
+            .create("sys", FileType::Dir, ModeType::from_bits_truncate(0o555))
+            .and_then(|sys| {
+                sys.create("kernel", FileType::Dir, ModeType::from_bits_truncate(0o555))
+            })
+            .expect("create sys/kernel error");
+        let binding = kernel_dir.create(
+            "core_pattern",
+            FileType::File,
+            ModeType::from_bits_truncate(0o644),
+        );
+        if let Ok(core_pattern) = binding {
+            let core_pattern_file = core_pattern
+                .as_any_ref()
+                .downcast_ref::<LockedProcFSInode>()
+                .unwrap();
+            core_pattern_file.0.lock().fdata.pid = Pid::new(0);
+            core_pattern_file.0.lock().fdata.ftype = ProcFileType::ProcCorePattern;
+        } else {
+            panic!("create core_pattern error");
+        }
+
         return result;
     }
 
@@ -423,6 +499,8 @@ impl IndexNode for LockedProcFSInode {
         let file_size = match inode.fdata.ftype {
             ProcFileType::ProcStatus => inode.open_status(&mut private_data)?,
             ProcFileType::ProcMeminfo => inode.open_meminfo(&mut private_data)?,
+            ProcFileType::ProcMounts => inode.open_mounts(&mut private_data)?,
+            ProcFileType::ProcCorePattern => inode.open_core_pattern(&mut private_data)?,
             _ => {
                 todo!()
             }
@@ -476,6 +554,10 @@ impl IndexNode for LockedProcFSInode {
         match inode.fdata.ftype {
             ProcFileType::ProcStatus => return inode.proc_read(offset, len, buf, private_data),
             ProcFileType::ProcMeminfo => return inode.proc_read(offset, len, buf, private_data),
+            ProcFileType::ProcMounts => return inode.proc_read(offset, len, buf, private_data),
+            ProcFileType::ProcCorePattern => {
+                return inode.proc_read(offset, len, buf, private_data)
+            }
             ProcFileType::ProcKmsg => (),
             ProcFileType::Default => (),
         };
@@ -498,11 +580,24 @@ impl IndexNode for LockedProcFSInode {
     fn write_at(
         &self,
         _offset: usize,
-        _len: usize,
-        _buf: &[u8],
+        len: usize,
+        buf: &[u8],
         _data: &mut FilePrivateData,
     ) -> Result<usize, SystemError> {
-        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+
+        let inode: SpinLockGuard<ProcFSInode> = self.0.lock();
+        match inode.fdata.ftype {
+            // 写入的内容替换原有的core_pattern，忽略结尾的换行符
+            ProcFileType::ProcCorePattern => {
+                let pattern = core::str::from_utf8(&buf[..len]).map_err(|_| SystemError::EINVAL)?;
+                set_core_pattern(pattern.trim_end_matches('\n'))?;
+                return Ok(len);
+            }
+            _ => return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+        }
     }
 
     fn fs(&self) -> Arc<dyn FileSystem> {
@@ -655,6 +750,7 @@ impl IndexNode for LockedProcFSInode {
         _old_name: &str,
         _target: &Arc<dyn IndexNode>,
         _new_name: &str,
+        _flags: RenameFlags,
     ) -> Result<(), SystemError> {
         return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
     }
@@ -779,11 +875,12 @@ pub fn procfs_init() -> Result<(), SystemError> {
         let procfs: Arc<ProcFS> = ProcFS::new();
 
         // procfs 挂载
-        let _t = ROOT_INODE()
+        let mount_fs = ROOT_INODE()
             .find("proc")
             .expect("Cannot find /proc")
             .mount(procfs)
             .expect("Failed to mount proc");
+        MountFS::add_mount_record(MountRecord::new("proc", "/proc", "procfs", mount_fs, true));
         kinfo!("ProcFS mounted.");
         result = Some(Ok(()));
     });
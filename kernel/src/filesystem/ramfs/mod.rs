@@ -11,15 +11,19 @@ use system_error::SystemError;
 
 use crate::{
     driver::base::device::device_number::DeviceNumber,
-    filesystem::vfs::{core::generate_inode_id, FileType},
+    filesystem::{
+        page_cache::PageCache,
+        vfs::{core::generate_inode_id, FileType},
+    },
     ipc::pipe::LockedPipeInode,
     libs::spinlock::{SpinLock, SpinLockGuard},
     time::TimeSpec,
 };
 
 use super::vfs::{
-    file::FilePrivateData, syscall::ModeType, FileSystem, FsInfo, IndexNode, InodeId, Metadata,
-    SpecialNodeData,
+    file::FilePrivateData,
+    syscall::{ModeType, RenameFlags},
+    FileSystem, FsInfo, IndexNode, InodeId, Metadata, SpecialNodeData,
 };
 
 /// RamFS的inode名称的最大长度
@@ -57,6 +61,8 @@ pub struct RamFSInode {
     fs: Weak<RamFS>,
     /// 指向特殊节点
     special_node: Option<SpecialNodeData>,
+    /// 文件的页面缓存，只有普通文件才有
+    page_cache: Option<Arc<PageCache>>,
 }
 
 impl FileSystem for RamFS {
@@ -104,6 +110,7 @@ impl RamFS {
             },
             fs: Weak::default(),
             special_node: None,
+            page_cache: None,
         })));
 
         let result: Arc<RamFS> = Arc::new(RamFS { root_inode: root });
@@ -120,6 +127,22 @@ impl RamFS {
     }
 }
 
+impl LockedRamFSInode {
+    /// 判断当前inode是否是文件夹`dir`自身或者它的祖先
+    fn is_ancestor_of(self: &Arc<Self>, dir: &Arc<LockedRamFSInode>) -> bool {
+        let mut ancestor: Option<Arc<LockedRamFSInode>> = Some(dir.clone());
+        while let Some(inode) = ancestor {
+            if Arc::ptr_eq(&inode, self) {
+                return true;
+            }
+            // 根目录的父目录是它自己
+            let parent = inode.0.lock().parent.upgrade();
+            ancestor = parent.filter(|parent| !Arc::ptr_eq(parent, &inode));
+        }
+        return false;
+    }
+}
+
 impl IndexNode for LockedRamFSInode {
     fn truncate(&self, len: usize) -> Result<(), SystemError> {
         let mut inode = self.0.lock();
@@ -132,6 +155,9 @@ impl IndexNode for LockedRamFSInode {
         //当前文件长度大于_len才进行截断，否则不操作
         if inode.data.len() > len {
             inode.data.resize(len, 0);
+            if let Some(page_cache) = &inode.page_cache {
+                page_cache.resize(len);
+            }
         }
         return Ok(());
     }
@@ -177,6 +203,10 @@ impl IndexNode for LockedRamFSInode {
         // 拷贝数据
         let src = &inode.data[start..end];
         buf[0..src.len()].copy_from_slice(src);
+        // 页面缓存中可能有尚未写回的数据，以缓存中的为准
+        if let Some(page_cache) = &inode.page_cache {
+            page_cache.read(start, &mut buf[0..src.len()]);
+        }
         return Ok(src.len());
     }
 
@@ -208,6 +238,9 @@ impl IndexNode for LockedRamFSInode {
 
         let target = &mut data[offset..offset + len];
         target.copy_from_slice(&buf[0..len]);
+        if let Some(page_cache) = &inode.page_cache {
+            page_cache.write(offset, &buf[0..len]);
+        }
         return Ok(len);
     }
 
@@ -243,6 +276,9 @@ impl IndexNode for LockedRamFSInode {
         let mut inode = self.0.lock();
         if inode.metadata.file_type == FileType::File {
             inode.data.resize(len, 0);
+            if let Some(page_cache) = &inode.page_cache {
+                page_cache.resize(len);
+            }
             return Ok(());
         } else {
             return Err(SystemError::EINVAL);
@@ -291,10 +327,15 @@ impl IndexNode for LockedRamFSInode {
             },
             fs: inode.fs.clone(),
             special_node: None,
+            page_cache: None,
         })));
 
         // 初始化inode的自引用的weak指针
         result.0.lock().self_ref = Arc::downgrade(&result);
+        if file_type == FileType::File {
+            let weak: Weak<dyn IndexNode> = Arc::downgrade(&result) as Weak<dyn IndexNode>;
+            result.0.lock().page_cache = Some(PageCache::new(weak));
+        }
 
         // 将子inode插入父inode的B树中
         inode.children.insert(String::from(name), result.clone());
@@ -379,17 +420,102 @@ impl IndexNode for LockedRamFSInode {
         old_name: &str,
         target: &Arc<dyn IndexNode>,
         new_name: &str,
+        flags: RenameFlags,
     ) -> Result<(), SystemError> {
-        let old_inode: Arc<dyn IndexNode> = self.find(old_name)?;
+        if flags.contains(RenameFlags::RENAME_WHITEOUT) {
+            return Err(SystemError::EINVAL);
+        }
+        let target: &LockedRamFSInode = target
+            .downcast_ref::<LockedRamFSInode>()
+            .ok_or(SystemError::EXDEV)?;
+        let target_ref = {
+            let guard = target.0.lock();
+            if guard.metadata.file_type != FileType::Dir {
+                return Err(SystemError::ENOTDIR);
+            }
+            guard.self_ref.clone()
+        };
 
-        // 在新的目录下创建一个硬链接
-        target.link(new_name, &old_inode)?;
-        // 取消现有的目录下的这个硬链接
-        if let Err(err) = self.unlink(old_name) {
-            // 如果取消失败，那就取消新的目录下的硬链接
-            target.unlink(new_name)?;
-            return Err(err);
+        let old_inode: Arc<LockedRamFSInode> = self
+            .0
+            .lock()
+            .children
+            .get(old_name)
+            .cloned()
+            .ok_or(SystemError::ENOENT)?;
+        let existing: Option<Arc<LockedRamFSInode>> =
+            target.0.lock().children.get(new_name).cloned();
+
+        // 不能把文件夹移动到它自己的子目录中
+        if old_inode.0.lock().metadata.file_type == FileType::Dir {
+            if let Some(target_dir) = target_ref.upgrade() {
+                if old_inode.is_ancestor_of(&target_dir) {
+                    return Err(SystemError::EINVAL);
+                }
+            }
         }
+
+        // 源和目标是同一个inode时，什么也不做
+        if let Some(existing) = &existing {
+            if Arc::ptr_eq(existing, &old_inode) {
+                return Ok(());
+            }
+        }
+
+        if flags.contains(RenameFlags::RENAME_EXCHANGE) {
+            let existing = existing.ok_or(SystemError::ENOENT)?;
+            // 交换时，目标文件夹会被移动到源所在的目录，因此源所在的目录也不能位于目标之中
+            let self_dir = self.0.lock().self_ref.upgrade();
+            if existing.0.lock().metadata.file_type == FileType::Dir {
+                if let Some(self_dir) = self_dir {
+                    if existing.is_ancestor_of(&self_dir) {
+                        return Err(SystemError::EINVAL);
+                    }
+                }
+            }
+            self.0
+                .lock()
+                .children
+                .insert(String::from(old_name), existing.clone());
+            target
+                .0
+                .lock()
+                .children
+                .insert(String::from(new_name), old_inode.clone());
+            let self_ref = self.0.lock().self_ref.clone();
+            existing.0.lock().parent = self_ref;
+            old_inode.0.lock().parent = target_ref;
+            return Ok(());
+        }
+
+        if let Some(existing) = existing {
+            if flags.contains(RenameFlags::RENAME_NOREPLACE) {
+                return Err(SystemError::EEXIST);
+            }
+            let old_is_dir = old_inode.0.lock().metadata.file_type == FileType::Dir;
+            let mut existing_guard = existing.0.lock();
+            match (
+                old_is_dir,
+                existing_guard.metadata.file_type == FileType::Dir,
+            ) {
+                (true, false) => return Err(SystemError::ENOTDIR),
+                (false, true) => return Err(SystemError::EISDIR),
+                (true, true) if !existing_guard.children.is_empty() => {
+                    return Err(SystemError::ENOTEMPTY)
+                }
+                _ => {}
+            }
+            // 被替换的目标失去一个硬链接
+            existing_guard.metadata.nlinks -= 1;
+        }
+
+        self.0.lock().children.remove(old_name);
+        target
+            .0
+            .lock()
+            .children
+            .insert(String::from(new_name), old_inode.clone());
+        old_inode.0.lock().parent = target_ref;
         return Ok(());
     }
 
@@ -512,6 +638,7 @@ impl IndexNode for LockedRamFSInode {
             },
             fs: inode.fs.clone(),
             special_node: None,
+            page_cache: None,
         })));
 
         nod.0.lock().self_ref = Arc::downgrade(&nod);
@@ -539,4 +666,8 @@ impl IndexNode for LockedRamFSInode {
     fn special_node(&self) -> Option<super::vfs::SpecialNodeData> {
         return self.0.lock().special_node.clone();
     }
+
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        return self.0.lock().page_cache.clone();
+    }
 }
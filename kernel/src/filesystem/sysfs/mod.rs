@@ -4,7 +4,11 @@ use self::{dir::SysKernDirPriv, file::SysKernFilePriv};
 
 use super::{
     kernfs::{KernFS, KernFSInode},
-    vfs::{syscall::ModeType, FileSystem},
+    vfs::{
+        mount::{MountFS, MountRecord},
+        syscall::ModeType,
+        FileSystem,
+    },
 };
 use crate::{
     driver::base::kobject::KObject,
@@ -42,11 +46,12 @@ pub fn sysfs_init() -> Result<(), SystemError> {
         unsafe { SYSFS_INSTANCE = Some(sysfs) };
 
         // sysfs 挂载
-        let _t = ROOT_INODE()
+        let mount_fs = ROOT_INODE()
             .find("sys")
             .expect("Cannot find /sys")
             .mount(sysfs_instance().fs().clone())
             .expect("Failed to mount sysfs");
+        MountFS::add_mount_record(MountRecord::new("sysfs", "/sys", "sysfs", mount_fs, true));
         kinfo!("SysFS mounted.");
 
         // kdebug!("sys_bus_init result: {:?}", SYS_BUS_INODE().list());
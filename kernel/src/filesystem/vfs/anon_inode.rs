@@ -0,0 +1,139 @@
+//! 匿名inode：signalfd、timerfd等不属于任何文件系统、只能通过文件描述符访问的inode
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/fs/anon_inodes.c
+
+use core::mem::size_of;
+
+use alloc::{
+    collections::LinkedList,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    libs::spinlock::SpinLock,
+    net::event_poll::{EPollEventType, EPollItem, EventPoll},
+    syscall::user_access::UserBufferReader,
+};
+
+use super::file::FileMode;
+
+/// 匿名inode文件的私有信息
+#[derive(Debug, Clone)]
+pub struct AnonInodePrivateData {
+    mode: FileMode,
+}
+
+impl AnonInodePrivateData {
+    pub fn new(mode: FileMode) -> Self {
+        return Self { mode };
+    }
+
+    pub fn set_mode(&mut self, mode: FileMode) {
+        self.mode = mode;
+    }
+
+    /// 文件是否以非阻塞模式打开
+    #[inline]
+    pub fn nonblock(&self) -> bool {
+        return self.mode.contains(FileMode::O_NONBLOCK);
+    }
+}
+
+/// 监听匿名inode的epoll项
+#[derive(Debug)]
+pub struct AnonInodeEpItems(SpinLock<LinkedList<Arc<EPollItem>>>);
+
+impl AnonInodeEpItems {
+    pub fn new() -> Self {
+        return Self(SpinLock::new(LinkedList::new()));
+    }
+
+    /// ## 处理EventPoll::ADD_EPOLLITEM
+    ///
+    /// ## 参数
+    ///
+    /// - `arg` ioctl的参数，指向要添加的`Arc<EPollItem>`
+    pub fn add_from_ioctl(&self, arg: usize) -> Result<(), SystemError> {
+        let _ = UserBufferReader::new(
+            arg as *const Arc<EPollItem>,
+            size_of::<Arc<EPollItem>>(),
+            false,
+        )?;
+        let epitem = unsafe { &*(arg as *const Arc<EPollItem>) };
+        self.0.lock_irqsave().push_back(epitem.clone());
+        return Ok(());
+    }
+
+    /// 通知监听这个inode的epoll发生了events事件
+    pub fn wakeup(&self, events: EPollEventType) {
+        let _ = EventPoll::wakeup_epoll(&self.0, events);
+    }
+
+    pub fn remove(&self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
+        let is_remove = !self
+            .0
+            .lock_irqsave()
+            .extract_if(|x| x.epoll().ptr_eq(epoll))
+            .collect::<Vec<_>>()
+            .is_empty();
+
+        if is_remove {
+            return Ok(());
+        }
+
+        Err(SystemError::ENOENT)
+    }
+}
+
+/// 为匿名inode实现IndexNode中与具体功能无关的方法
+///
+/// 匿名inode不能写入，也不属于任何文件系统
+#[macro_export]
+macro_rules! anon_inode_common_ops {
+    () => {
+        fn write_at(
+            &self,
+            _offset: usize,
+            _len: usize,
+            _buf: &[u8],
+            _data: &mut $crate::filesystem::vfs::FilePrivateData,
+        ) -> Result<usize, system_error::SystemError> {
+            Err(system_error::SystemError::EINVAL)
+        }
+
+        fn fs(&self) -> alloc::sync::Arc<dyn $crate::filesystem::vfs::FileSystem> {
+            todo!()
+        }
+
+        fn as_any_ref(&self) -> &dyn ::core::any::Any {
+            self
+        }
+
+        fn list(
+            &self,
+        ) -> Result<alloc::vec::Vec<alloc::string::String>, system_error::SystemError> {
+            Err(system_error::SystemError::ENOTDIR)
+        }
+
+        fn metadata(&self) -> Result<$crate::filesystem::vfs::Metadata, system_error::SystemError> {
+            Ok($crate::filesystem::vfs::Metadata::default())
+        }
+
+        fn open(
+            &self,
+            _data: &mut $crate::filesystem::vfs::FilePrivateData,
+            _mode: &$crate::filesystem::vfs::file::FileMode,
+        ) -> Result<(), system_error::SystemError> {
+            Ok(())
+        }
+
+        fn close(
+            &self,
+            _data: &mut $crate::filesystem::vfs::FilePrivateData,
+        ) -> Result<(), system_error::SystemError> {
+            Ok(())
+        }
+    };
+}
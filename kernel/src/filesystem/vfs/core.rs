@@ -1,27 +1,40 @@
 use core::{hint::spin_loop, sync::atomic::Ordering};
 
-use alloc::{format, string::ToString, sync::Arc};
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+};
 use system_error::SystemError;
 
 use crate::{
     driver::{
-        base::block::disk_info::Partition,
-        disk::ahci::{self},
+        base::block::{block_device::BlockDevice, disk_info::Partition},
+        disk::{
+            ahci::{self, ahci_inode::LockedAhciInode},
+            virtio_blk::{virtio_blk_disks, virtio_blk_inode::LockedVirtIOBlkInode},
+        },
     },
     filesystem::{
         devfs::devfs_init,
+        devpts::devpts_instance,
         fat::fs::FATFileSystem,
         procfs::procfs_init,
         ramfs::RamFS,
         sysfs::sysfs_init,
-        vfs::{mount::MountFS, syscall::ModeType, AtomicInodeId, FileSystem, FileType},
+        vfs::{
+            mount::{MountFS, MountRecord, UmountFlags},
+            syscall::{ModeType, RenameFlags},
+            AtomicInodeId, FileSystem, FileType,
+        },
     },
     kdebug, kerror, kinfo,
     process::ProcessManager,
 };
 
 use super::{
-    file::FileMode,
+    fcntl::AtFlags,
+    permission::{inode_init_owner, inode_permission, may_delete, PermissionMask},
     utils::{rsplit_path, user_path_at},
     IndexNode, InodeId, MAX_PATHLEN, VFS_MAX_FOLLOW_SYMLINK_TIMES,
 };
@@ -54,6 +67,7 @@ pub fn vfs_init() -> Result<(), SystemError> {
     let ramfs = RamFS::new();
     let mount_fs = MountFS::new(ramfs, None);
     let root_inode = mount_fs.root_inode();
+    MountFS::add_mount_record(MountRecord::new("rootfs", "/", "ramfs", mount_fs, true));
 
     unsafe {
         __ROOT_INODE = Some(root_inode.clone());
@@ -106,15 +120,23 @@ fn do_migrate(
         r.unwrap()
     };
     // 迁移挂载点
-    mountpoint
+    let new_fs = mountpoint
         .mount(fs.inner_filesystem())
         .expect(format!("Failed to migrate {mountpoint_name} ").as_str());
+    MountFS::update_mount_record(fs, |record| record.mount_fs = new_fs);
     return Ok(());
 }
 
 /// @brief 迁移伪文件系统的inode
 /// 请注意，为了避免删掉了伪文件系统内的信息，因此没有在原root inode那里调用unlink.
-fn migrate_virtual_filesystem(new_fs: Arc<dyn FileSystem>) -> Result<(), SystemError> {
+///
+/// @param source 新的根文件系统的挂载源
+/// @param fs_type 新的根文件系统的类型名称
+fn migrate_virtual_filesystem(
+    new_fs: Arc<dyn FileSystem>,
+    source: &str,
+    fs_type: &str,
+) -> Result<(), SystemError> {
     kinfo!("VFS: Migrating filesystems...");
 
     // ==== 在这里获取要被迁移的文件系统的inode ===
@@ -124,6 +146,8 @@ fn migrate_virtual_filesystem(new_fs: Arc<dyn FileSystem>) -> Result<(), SystemE
     let dev: &MountFS = binding.as_any_ref().downcast_ref::<MountFS>().unwrap();
     let binding = ROOT_INODE().find("sys").expect("SysFs not mounted!").fs();
     let sys: &MountFS = binding.as_any_ref().downcast_ref::<MountFS>().unwrap();
+    let binding = ROOT_INODE().fs();
+    let old_root: &MountFS = binding.as_any_ref().downcast_ref::<MountFS>().unwrap();
 
     let new_fs = MountFS::new(new_fs, None);
     // 获取新的根文件系统的根节点的引用
@@ -133,6 +157,11 @@ fn migrate_virtual_filesystem(new_fs: Arc<dyn FileSystem>) -> Result<(), SystemE
     do_migrate(new_root_inode.clone(), "proc", proc)?;
     do_migrate(new_root_inode.clone(), "dev", dev)?;
     do_migrate(new_root_inode.clone(), "sys", sys)?;
+    MountFS::update_mount_record(old_root, |record| {
+        record.source = source.to_string();
+        record.fs_type = fs_type.to_string();
+        record.mount_fs = new_fs;
+    });
     unsafe {
         // drop旧的Root inode
         let old_root_inode = __ROOT_INODE.take().unwrap();
@@ -149,12 +178,7 @@ fn migrate_virtual_filesystem(new_fs: Arc<dyn FileSystem>) -> Result<(), SystemE
 
 pub fn mount_root_fs() -> Result<(), SystemError> {
     kinfo!("Try to mount FAT32 as root fs...");
-    let partiton: Arc<Partition> = ahci::get_disks_by_name("ahci_disk_0".to_string())
-        .unwrap()
-        .0
-        .lock()
-        .partitions[0]
-        .clone();
+    let (partiton, source) = root_partition()?;
 
     let fatfs: Result<Arc<FATFileSystem>, SystemError> = FATFileSystem::new(partiton);
     if fatfs.is_err() {
@@ -167,7 +191,7 @@ pub fn mount_root_fs() -> Result<(), SystemError> {
         }
     }
     let fatfs: Arc<FATFileSystem> = fatfs.unwrap();
-    let r = migrate_virtual_filesystem(fatfs);
+    let r = migrate_virtual_filesystem(fatfs, source, "vfat");
     if r.is_err() {
         kerror!("Failed to migrate virtual filesystem to FAT32!");
         loop {
@@ -179,35 +203,229 @@ pub fn mount_root_fs() -> Result<(), SystemError> {
     return Ok(());
 }
 
-/// @brief 创建文件/文件夹
-pub fn do_mkdir(path: &str, _mode: FileMode) -> Result<u64, SystemError> {
-    // 文件名过长
-    if path.len() > MAX_PATHLEN as usize {
-        return Err(SystemError::ENAMETOOLONG);
+/// 文件系统的构造函数，参数为挂载源
+type FileSystemMaker = fn(source: &str) -> Result<Arc<dyn FileSystem>, SystemError>;
+
+/// 已注册的文件系统类型。mount系统调用根据类型名称，在这里查找文件系统的构造函数
+static FILESYSTEM_TYPES: &[(&str, FileSystemMaker)] = &[
+    ("ramfs", |_| Ok(RamFS::new())),
+    ("fat", make_fatfs),
+    ("vfat", make_fatfs),
+    ("procfs", |_| mounted_pseudo_fs("procfs")),
+    ("proc", |_| mounted_pseudo_fs("procfs")),
+    ("sysfs", |_| mounted_pseudo_fs("sysfs")),
+    ("devfs", |_| mounted_pseudo_fs("devfs")),
+    ("devpts", |_| Ok(devpts_instance())),
+];
+
+/// 在挂载源对应的块设备的第一个分区上创建FAT文件系统
+fn make_fatfs(source: &str) -> Result<Arc<dyn FileSystem>, SystemError> {
+    let (disk, partition) = lookup_block_device(source)?;
+    // 挂载整个磁盘时，使用磁盘上的第一个分区
+    let partition = match partition {
+        Some(partition) => partition,
+        None => disk
+            .partitions()
+            .first()
+            .cloned()
+            .ok_or(SystemError::EINVAL)?,
+    };
+    return Ok(FATFileSystem::new(partition)?);
+}
+
+/// 根据路径查找块设备，返回块设备所在的磁盘，以及它对应的分区（如果路径指向的是分区）
+fn lookup_block_device(
+    path: &str,
+) -> Result<(Arc<dyn BlockDevice>, Option<Arc<Partition>>), SystemError> {
+    let pcb = ProcessManager::current_pcb();
+    let (inode_begin, remain_path) = user_path_at(&pcb, AtFlags::AT_FDCWD.bits(), path)?;
+    let inode = inode_begin.lookup_follow_symlink(&remain_path, VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+    if inode.metadata()?.file_type != FileType::BlockDevice {
+        return Err(SystemError::ENOTBLK);
     }
 
-    let inode: Result<Arc<dyn IndexNode>, SystemError> = ROOT_INODE().lookup(path);
+    if let Some(ahci) = inode.as_any_ref().downcast_ref::<LockedAhciInode>() {
+        return Ok((ahci.disk(), None));
+    }
+    if let Some(virtio) = inode.as_any_ref().downcast_ref::<LockedVirtIOBlkInode>() {
+        return Ok((virtio.disk(), virtio.partition()));
+    }
+    return Err(SystemError::ENOTBLK);
+}
 
-    if inode.is_err() {
-        let errno = inode.unwrap_err();
-        // 文件不存在，且需要创建
-        if errno == SystemError::ENOENT {
-            let (filename, parent_path) = rsplit_path(path);
-            // 查找父目录
-            let parent_inode: Arc<dyn IndexNode> =
-                ROOT_INODE().lookup(parent_path.unwrap_or("/"))?;
-            // 创建文件夹
-            let _create_inode: Arc<dyn IndexNode> = parent_inode.create(
-                filename,
-                FileType::Dir,
-                ModeType::from_bits_truncate(0o755),
-            )?;
-        } else {
-            // 不需要创建文件，因此返回错误码
-            return Err(errno);
+/// 查找根文件系统所在的分区，返回分区以及记录到挂载表中的设备名
+///
+/// 优先使用第一个AHCI磁盘，没有AHCI磁盘时使用第一个virtio-blk磁盘
+fn root_partition() -> Result<(Arc<Partition>, &'static str), SystemError> {
+    if let Ok(disk) = ahci::get_disks_by_name("ahci_disk_0".to_string()) {
+        if let Some(partition) = disk.0.lock().partitions.first() {
+            return Ok((partition.clone(), "/dev/ahci_0"));
+        }
+    }
+
+    if let Some(disk) = virtio_blk_disks().first() {
+        if let Some(partition) = disk.partitions().first() {
+            return Ok((partition.clone(), "/dev/vda1"));
         }
     }
 
+    kerror!("No disk partition found for root fs");
+    return Err(SystemError::ENODEV);
+}
+
+/// 伪文件系统在系统中只有一个实例，再次挂载时复用启动时挂载的那个实例
+fn mounted_pseudo_fs(fs_type: &str) -> Result<Arc<dyn FileSystem>, SystemError> {
+    return MountFS::mount_records()
+        .iter()
+        .find(|r| r.fs_type == fs_type)
+        .map(|r| r.mount_fs.inner_filesystem())
+        .ok_or(SystemError::ENODEV);
+}
+
+/// 把路径转换为绝对路径，用于记录到挂载表中
+fn absolute_path(path: &str) -> String {
+    let mut result = if path.starts_with('/') {
+        String::from(path)
+    } else {
+        let cwd = ProcessManager::current_pcb().basic().cwd();
+        format!("{}/{}", cwd.trim_end_matches('/'), path)
+    };
+    while result.len() > 1 && result.ends_with('/') {
+        result.pop();
+    }
+    return result;
+}
+
+/// @brief 挂载文件系统
+///
+/// @param source 挂载源，例如块设备的路径
+/// @param target 挂载点的路径
+/// @param fs_type 文件系统类型的名称
+pub fn do_mount(source: &str, target: &str, fs_type: &str) -> Result<(), SystemError> {
+    let maker = FILESYSTEM_TYPES
+        .iter()
+        .find(|(name, _)| *name == fs_type)
+        .ok_or(SystemError::ENODEV)?
+        .1;
+
+    let pcb = ProcessManager::current_pcb();
+    let (inode_begin, remain_path) = user_path_at(&pcb, AtFlags::AT_FDCWD.bits(), target)?;
+    let mountpoint =
+        inode_begin.lookup_follow_symlink(&remain_path, VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+    if mountpoint.metadata()?.file_type != FileType::Dir {
+        return Err(SystemError::ENOTDIR);
+    }
+
+    // 挂载点已经是某个文件系统的根目录，暂不支持在同一个挂载点上叠加挂载
+    let binding = mountpoint.fs();
+    let parent_fs = binding
+        .as_any_ref()
+        .downcast_ref::<MountFS>()
+        .ok_or(SystemError::EINVAL)?;
+    if parent_fs.is_root_inode(&mountpoint)? {
+        return Err(SystemError::EBUSY);
+    }
+
+    let fs = maker(source)?;
+    let mount_fs = mountpoint.mount(fs)?;
+    MountFS::add_mount_record(MountRecord::new(
+        source,
+        &absolute_path(target),
+        fs_type,
+        mount_fs,
+        false,
+    ));
+    return Ok(());
+}
+
+/// @brief 卸载文件系统
+///
+/// @param target 被卸载的文件系统的挂载点的路径
+/// @param flags umount2的标志位
+pub fn do_umount2(target: &str, flags: UmountFlags) -> Result<(), SystemError> {
+    if flags.contains(UmountFlags::MNT_EXPIRE) {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+    let max_follow_times = if flags.contains(UmountFlags::UMOUNT_NOFOLLOW) {
+        0
+    } else {
+        VFS_MAX_FOLLOW_SYMLINK_TIMES
+    };
+
+    // 查找过程中得到的inode都持有所在文件系统的引用，需要在卸载之前释放它们
+    let fs = {
+        let pcb = ProcessManager::current_pcb();
+        let (inode_begin, remain_path) = user_path_at(&pcb, AtFlags::AT_FDCWD.bits(), target)?;
+        let inode = inode_begin.lookup_follow_symlink(&remain_path, max_follow_times)?;
+        let fs = inode.fs();
+        let mount_fs = fs
+            .as_any_ref()
+            .downcast_ref::<MountFS>()
+            .ok_or(SystemError::EINVAL)?;
+        // 只能对挂载点进行卸载
+        if !mount_fs.is_root_inode(&inode)? {
+            return Err(SystemError::EINVAL);
+        }
+        if !flags.contains(UmountFlags::MNT_DETACH) && fs_used_as_cwd(&fs) {
+            return Err(SystemError::EBUSY);
+        }
+        fs
+    };
+
+    return fs
+        .as_any_ref()
+        .downcast_ref::<MountFS>()
+        .unwrap()
+        .umount(flags);
+}
+
+/// 检查是否有进程的工作目录位于指定的文件系统中
+///
+/// 进程的工作目录以路径的形式保存，不会持有所在文件系统的引用，因此卸载时需要单独检查。
+/// 所有进程的根目录都是根文件系统的根目录，而根文件系统不能被卸载，因此不需要检查根目录
+fn fs_used_as_cwd(fs: &Arc<dyn FileSystem>) -> bool {
+    let fs_ptr = Arc::as_ptr(fs) as *const u8;
+    return ProcessManager::filter(|_| true).iter().any(|pcb| {
+        let cwd = pcb.basic().cwd();
+        match ROOT_INODE().lookup_follow_symlink(&cwd, VFS_MAX_FOLLOW_SYMLINK_TIMES) {
+            Ok(inode) => core::ptr::eq(Arc::as_ptr(&inode.fs()) as *const u8, fs_ptr),
+            // 工作目录已经不存在了，它不可能位于要卸载的文件系统中
+            Err(_) => false,
+        }
+    });
+}
+
+/// ## 创建文件夹，`path`为相对路径时，相对于`dirfd`
+///
+/// ## 参数
+///
+/// - `dirfd`：`path`为相对路径时，相对于这个目录
+/// - `path`：要创建的文件夹的路径
+/// - `mode`：文件夹的权限，会去掉当前进程的权限掩码中的位
+pub fn do_mkdir(dirfd: i32, path: &str, mode: ModeType) -> Result<u64, SystemError> {
+    // 文件名过长
+    if path.len() > MAX_PATHLEN as usize {
+        return Err(SystemError::ENAMETOOLONG);
+    }
+
+    let (parent, name) = lookup_parent(dirfd, path)?;
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(SystemError::EEXIST);
+    }
+    match parent.find(&name) {
+        Ok(_) => return Err(SystemError::EEXIST),
+        Err(SystemError::ENOENT) => {}
+        Err(e) => return Err(e),
+    }
+    inode_permission(
+        &parent,
+        PermissionMask::MAY_WRITE | PermissionMask::MAY_EXEC,
+    )?;
+
+    let umask = ModeType::from_bits_truncate(ProcessManager::current_pcb().basic().umask());
+    let mode = mode & (ModeType::S_IRWXUGO | ModeType::S_ISVTX) & !umask;
+    let inode: Arc<dyn IndexNode> = parent.create(&name, FileType::Dir, mode)?;
+    inode_init_owner(&parent, &inode)?;
     return Ok(0);
 }
 
@@ -240,6 +458,7 @@ pub fn do_remove_dir(dirfd: i32, path: &str) -> Result<u64, SystemError> {
     if target_inode.metadata()?.file_type != FileType::Dir {
         return Err(SystemError::ENOTDIR);
     }
+    may_delete(&parent_inode, &target_inode)?;
 
     // 删除文件夹
     parent_inode.rmdir(filename)?;
@@ -256,8 +475,9 @@ pub fn do_unlink_at(dirfd: i32, path: &str) -> Result<u64, SystemError> {
     let pcb = ProcessManager::current_pcb();
     let (inode_begin, remain_path) = user_path_at(&pcb, dirfd, path)?;
 
+    // 路径的最后一级是符号链接时，删除的是符号链接本身
     let inode: Result<Arc<dyn IndexNode>, SystemError> =
-        inode_begin.lookup_follow_symlink(&remain_path, VFS_MAX_FOLLOW_SYMLINK_TIMES);
+        inode_begin.lookup_follow_symlink2(&remain_path, VFS_MAX_FOLLOW_SYMLINK_TIMES, false);
 
     if inode.is_err() {
         let errno = inode.clone().unwrap_err();
@@ -266,8 +486,9 @@ pub fn do_unlink_at(dirfd: i32, path: &str) -> Result<u64, SystemError> {
             return Err(SystemError::ENOENT);
         }
     }
+    let inode = inode?;
     // 禁止在目录上unlink
-    if inode.unwrap().metadata()?.file_type == FileType::Dir {
+    if inode.metadata()?.file_type == FileType::Dir {
         return Err(SystemError::EPERM);
     }
 
@@ -279,9 +500,174 @@ pub fn do_unlink_at(dirfd: i32, path: &str) -> Result<u64, SystemError> {
     if parent_inode.metadata()?.file_type != FileType::Dir {
         return Err(SystemError::ENOTDIR);
     }
+    may_delete(&parent_inode, &inode)?;
 
     // 删除文件
     parent_inode.unlink(filename)?;
 
     return Ok(0);
 }
+
+/// ## 查找路径的最后一级所在的目录
+///
+/// ## 返回值
+///
+/// (父目录的inode, 最后一级的名字)
+fn lookup_parent(dirfd: i32, path: &str) -> Result<(Arc<dyn IndexNode>, String), SystemError> {
+    if path.is_empty() {
+        return Err(SystemError::ENOENT);
+    }
+    let pcb = ProcessManager::current_pcb();
+    let (inode_begin, remain_path) = user_path_at(&pcb, dirfd, path)?;
+    let (filename, parent_path) = rsplit_path(&remain_path);
+
+    let parent_inode: Arc<dyn IndexNode> = inode_begin
+        .lookup_follow_symlink(parent_path.unwrap_or("."), VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+    if parent_inode.metadata()?.file_type != FileType::Dir {
+        return Err(SystemError::ENOTDIR);
+    }
+    return Ok((parent_inode, filename.to_string()));
+}
+
+/// ## 重命名文件或文件夹，可以跨越目录，但不能跨越文件系统
+///
+/// ## 参数
+///
+/// - `olddirfd`：`oldpath`为相对路径时，相对于这个目录
+/// - `oldpath`：源路径
+/// - `newdirfd`：`newpath`为相对路径时，相对于这个目录
+/// - `newpath`：目标路径
+/// - `flags`：renameat2的标志位
+pub fn do_renameat2(
+    olddirfd: i32,
+    oldpath: &str,
+    newdirfd: i32,
+    newpath: &str,
+    flags: RenameFlags,
+) -> Result<(), SystemError> {
+    if oldpath.len() > MAX_PATHLEN || newpath.len() > MAX_PATHLEN {
+        return Err(SystemError::ENAMETOOLONG);
+    }
+    // RENAME_NOREPLACE与RENAME_EXCHANGE互斥
+    if flags.contains(RenameFlags::RENAME_NOREPLACE | RenameFlags::RENAME_EXCHANGE) {
+        return Err(SystemError::EINVAL);
+    }
+
+    let (old_parent, old_name) = lookup_parent(olddirfd, oldpath)?;
+    let (new_parent, new_name) = lookup_parent(newdirfd, newpath)?;
+    // 不能重命名"."和".."，也不能重命名根目录
+    for name in [&old_name, &new_name] {
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(SystemError::EBUSY);
+        }
+    }
+
+    let old_inode: Arc<dyn IndexNode> = old_parent.find(&old_name)?;
+    let new_inode: Option<Arc<dyn IndexNode>> = match new_parent.find(&new_name) {
+        Ok(inode) => Some(inode),
+        Err(SystemError::ENOENT) => None,
+        Err(e) => return Err(e),
+    };
+
+    match &new_inode {
+        Some(_) if flags.contains(RenameFlags::RENAME_NOREPLACE) => {
+            return Err(SystemError::EEXIST);
+        }
+        None if flags.contains(RenameFlags::RENAME_EXCHANGE) => {
+            return Err(SystemError::ENOENT);
+        }
+        _ => {}
+    }
+
+    may_delete(&old_parent, &old_inode)?;
+    match &new_inode {
+        Some(new_inode) => may_delete(&new_parent, new_inode)?,
+        None => inode_permission(
+            &new_parent,
+            PermissionMask::MAY_WRITE | PermissionMask::MAY_EXEC,
+        )?,
+    }
+
+    return old_parent.move_(&old_name, &new_parent, &new_name, flags);
+}
+
+/// ## 创建符号链接
+///
+/// ## 参数
+///
+/// - `target`：符号链接的内容，不要求指向的文件存在
+/// - `newdirfd`：`linkpath`为相对路径时，相对于这个目录
+/// - `linkpath`：符号链接的路径
+pub fn do_symlinkat(target: &str, newdirfd: i32, linkpath: &str) -> Result<(), SystemError> {
+    if target.len() > MAX_PATHLEN || linkpath.len() > MAX_PATHLEN {
+        return Err(SystemError::ENAMETOOLONG);
+    }
+    if target.is_empty() {
+        return Err(SystemError::ENOENT);
+    }
+
+    let (parent, name) = lookup_parent(newdirfd, linkpath)?;
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(SystemError::EEXIST);
+    }
+    inode_permission(
+        &parent,
+        PermissionMask::MAY_WRITE | PermissionMask::MAY_EXEC,
+    )?;
+
+    let inode: Arc<dyn IndexNode> = parent.symlink(&name, target)?;
+    inode_init_owner(&parent, &inode)?;
+    return Ok(());
+}
+
+/// ## 创建硬链接
+///
+/// ## 参数
+///
+/// - `olddirfd`：`oldpath`为相对路径时，相对于这个目录
+/// - `oldpath`：已经存在的文件的路径
+/// - `newdirfd`：`newpath`为相对路径时，相对于这个目录
+/// - `newpath`：新的硬链接的路径
+/// - `flags`：指定了AT_SYMLINK_FOLLOW时，`oldpath`为符号链接则链接到它指向的文件，否则链接到符号链接本身
+pub fn do_linkat(
+    olddirfd: i32,
+    oldpath: &str,
+    newdirfd: i32,
+    newpath: &str,
+    flags: AtFlags,
+) -> Result<(), SystemError> {
+    if oldpath.len() > MAX_PATHLEN || newpath.len() > MAX_PATHLEN {
+        return Err(SystemError::ENAMETOOLONG);
+    }
+    if oldpath.is_empty() {
+        return Err(SystemError::ENOENT);
+    }
+
+    let pcb = ProcessManager::current_pcb();
+    let (inode_begin, remain_path) = user_path_at(&pcb, olddirfd, oldpath)?;
+    let old_inode: Arc<dyn IndexNode> = inode_begin.lookup_follow_symlink2(
+        &remain_path,
+        VFS_MAX_FOLLOW_SYMLINK_TIMES,
+        flags.contains(AtFlags::AT_SYMLINK_FOLLOW),
+    )?;
+    // 不能为文件夹创建硬链接
+    if old_inode.metadata()?.file_type == FileType::Dir {
+        return Err(SystemError::EPERM);
+    }
+
+    let (new_parent, new_name) = lookup_parent(newdirfd, newpath)?;
+    if new_name.is_empty() || new_name == "." || new_name == ".." {
+        return Err(SystemError::EEXIST);
+    }
+    match new_parent.find(&new_name) {
+        Ok(_) => return Err(SystemError::EEXIST),
+        Err(SystemError::ENOENT) => {}
+        Err(e) => return Err(e),
+    }
+    inode_permission(
+        &new_parent,
+        PermissionMask::MAY_WRITE | PermissionMask::MAY_EXEC,
+    )?;
+
+    return new_parent.link(&new_name, &old_inode);
+}
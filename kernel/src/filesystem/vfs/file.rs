@@ -11,7 +11,10 @@ use crate::{
         tty::tty_device::TtyFilePrivateData,
     },
     filesystem::procfs::ProcfsFilePrivateData,
-    ipc::pipe::{LockedPipeInode, PipeFsPrivateData},
+    ipc::{
+        pipe::{LockedPipeInode, PipeFsPrivateData},
+        signalfd::SignalFdInode,
+    },
     kerror,
     libs::spinlock::SpinLock,
     net::{
@@ -19,9 +22,13 @@ use crate::{
         socket::SocketInode,
     },
     process::ProcessManager,
+    time::timerfd::TimerFdInode,
 };
 
-use super::{Dirent, FileType, IndexNode, InodeId, Metadata, SpecialNodeData};
+use super::{
+    anon_inode::AnonInodePrivateData, Dirent, FileType, IndexNode, InodeId, Metadata,
+    SpecialNodeData,
+};
 
 /// 文件私有信息的枚举类型
 #[derive(Debug, Clone)]
@@ -37,6 +44,8 @@ pub enum FilePrivateData {
     Tty(TtyFilePrivateData),
     /// epoll私有信息
     EPoll(EPollPrivateData),
+    /// signalfd、timerfd等匿名inode的私有信息
+    AnonInode(AnonInodePrivateData),
     /// 不需要文件私有信息
     Unused,
 }
@@ -49,8 +58,10 @@ impl Default for FilePrivateData {
 
 impl FilePrivateData {
     pub fn update_mode(&mut self, mode: FileMode) {
-        if let FilePrivateData::Pipefs(pdata) = self {
-            pdata.set_mode(mode);
+        match self {
+            FilePrivateData::Pipefs(pdata) => pdata.set_mode(mode),
+            FilePrivateData::AnonInode(pdata) => pdata.set_mode(mode),
+            _ => {}
         }
     }
 }
@@ -128,6 +139,8 @@ pub struct File {
     /// readdir时候用的，暂存的本次循环中，所有子目录项的名字的数组
     readdir_subdirs_name: Vec<String>,
     pub private_data: FilePrivateData,
+    /// 打开文件时使用的绝对路径，不是通过路径打开的文件（例如管道、socket）为None
+    path: Option<String>,
 }
 
 impl File {
@@ -154,6 +167,7 @@ impl File {
             file_type,
             readdir_subdirs_name: Vec::new(),
             private_data: FilePrivateData::default(),
+            path: None,
         };
         // kdebug!("inode:{:?}",f.inode);
         f.inode.open(&mut f.private_data, &mode)?;
@@ -406,6 +420,7 @@ impl File {
             file_type: self.file_type.clone(),
             readdir_subdirs_name: self.readdir_subdirs_name.clone(),
             private_data: self.private_data.clone(),
+            path: self.path.clone(),
         };
         // 调用inode的open方法，让inode知道有新的文件打开了这个inode
         if self.inode.open(&mut res.private_data, &res.mode).is_err() {
@@ -415,6 +430,18 @@ impl File {
         return Some(res);
     }
 
+    /// 获取打开文件时使用的绝对路径
+    #[inline]
+    pub fn path(&self) -> Option<&str> {
+        return self.path.as_deref();
+    }
+
+    /// 设置打开文件时使用的绝对路径
+    #[inline]
+    pub fn set_path(&mut self, path: String) {
+        self.path = Some(path);
+    }
+
     /// @brief 获取文件的类型
     #[inline]
     pub fn file_type(&self) -> FileType {
@@ -450,6 +477,12 @@ impl File {
         // 直接修改文件的打开模式
         self.mode = mode;
         self.private_data.update_mode(mode);
+        if self.file_type == FileType::Socket {
+            let inode = self.inode.downcast_ref::<SocketInode>().unwrap();
+            inode
+                .inner()
+                .set_nonblock(mode.contains(FileMode::O_NONBLOCK));
+        }
         return Ok(());
     }
 
@@ -508,7 +541,22 @@ impl File {
 
                 return socket.remove_epoll(epoll);
             }
-            _ => return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+            FileType::Pipe => {
+                let inode = self.inode.downcast_ref::<LockedPipeInode>().unwrap();
+                return inode.inner().lock().remove_epoll(epoll);
+            }
+            _ => {
+                if let FilePrivateData::Tty(tty_priv) = &self.private_data {
+                    return tty_priv.tty().core().remove_epitem(epoll);
+                }
+                if let Some(inode) = self.inode.downcast_ref::<SignalFdInode>() {
+                    return inode.epitems().remove(epoll);
+                }
+                if let Some(inode) = self.inode.downcast_ref::<TimerFdInode>() {
+                    return inode.epitems().remove(epoll);
+                }
+                return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+            }
         }
     }
 
@@ -592,12 +640,34 @@ impl FileDescriptorVec {
     /// - `Ok(i32)` 申请成功，返回申请到的文件描述符
     /// - `Err(SystemError)` 申请失败，返回错误码，并且，file对象将被drop掉
     pub fn alloc_fd(&mut self, file: File, fd: Option<i32>) -> Result<i32, SystemError> {
+        return self.install_fd(Arc::new(SpinLock::new(file)), fd);
+    }
+
+    /// 申请文件描述符，并让它指向一个已经打开的文件对象。
+    ///
+    /// 与`alloc_fd`不同，新的文件描述符与原有的持有者共享同一个文件对象（包括文件偏移量和状态标志），
+    /// 用于通过SCM_RIGHTS传递文件描述符等场景。
+    ///
+    /// ## 参数
+    ///
+    /// - `file` 已经打开的文件对象
+    /// - `fd` 如果为Some(i32)，表示指定要申请这个文件描述符，如果这个文件描述符已经被使用，那么返回EBADF
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(i32)` 申请成功，返回申请到的文件描述符
+    /// - `Err(SystemError)` 申请失败，返回错误码
+    pub fn install_fd(
+        &mut self,
+        file: Arc<SpinLock<File>>,
+        fd: Option<i32>,
+    ) -> Result<i32, SystemError> {
         if fd.is_some() {
             // 指定了要申请的文件描述符编号
             let new_fd = fd.unwrap();
             let x = &mut self.fds[new_fd as usize];
             if x.is_none() {
-                *x = Some(Arc::new(SpinLock::new(file)));
+                *x = Some(file);
                 return Ok(new_fd);
             } else {
                 return Err(SystemError::EBADF);
@@ -606,7 +676,7 @@ impl FileDescriptorVec {
             // 没有指定要申请的文件描述符编号
             for i in 0..FileDescriptorVec::PROCESS_MAX_FD {
                 if self.fds[i].is_none() {
-                    self.fds[i] = Some(Arc::new(SpinLock::new(file)));
+                    self.fds[i] = Some(file);
                     return Ok(i as i32);
                 }
             }
@@ -639,10 +709,11 @@ impl FileDescriptorVec {
 
         self.get_file_by_fd(fd).ok_or(SystemError::EBADF)?;
 
-        // 把文件描述符数组对应位置设置为空
-        let file = self.fds[fd as usize].take().unwrap();
+        // 把文件描述符数组对应位置设置为空。
+        // 文件对象可能还被其他文件描述符或者正在传递的SCM_RIGHTS消息引用，
+        // 最后一个引用被释放时，文件才会真正被关闭
+        self.fds[fd as usize].take().unwrap();
 
-        assert!(Arc::strong_count(&file) == 1);
         return Ok(());
     }
 
@@ -1,10 +1,12 @@
+pub mod anon_inode;
 pub mod core;
 pub mod fcntl;
 pub mod file;
 pub mod mount;
 pub mod open;
+pub mod permission;
 pub mod syscall;
-mod utils;
+pub mod utils;
 
 use ::core::{any::Any, fmt::Debug, sync::atomic::AtomicUsize};
 
@@ -15,12 +17,19 @@ use crate::{
     driver::base::{
         block::block_device::BlockDevice, char::CharDevice, device::device_number::DeviceNumber,
     },
+    filesystem::page_cache::PageCache,
     ipc::pipe::LockedPipeInode,
     libs::casting::DowncastArc,
+    process::ProcessManager,
     time::TimeSpec,
 };
 
-use self::{core::generate_inode_id, file::FileMode, syscall::ModeType};
+use self::{
+    core::generate_inode_id,
+    file::FileMode,
+    permission::{inode_permission, PermissionMask},
+    syscall::{ModeType, RenameFlags},
+};
 pub use self::{core::ROOT_INODE, file::FilePrivateData, mount::MountFS};
 
 /// vfs容许的最大的路径名称长度
@@ -256,6 +265,29 @@ pub trait IndexNode: Any + Sync + Send + Debug {
         return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
     }
 
+    /// @brief 在当前目录下，创建一个名为Name的符号链接，其内容为target
+    ///
+    /// 默认先创建一个符号链接类型的inode，再把target写入其中。不能直接写入符号链接的文件系统需要重写此方法
+    ///
+    /// @param name 符号链接的名称
+    /// @param target 符号链接指向的路径
+    ///
+    /// @return 成功：Ok(新的inode的Arc指针)
+    ///         失败：Err(错误码)
+    fn symlink(&self, name: &str, target: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let inode = self.create(name, FileType::SymLink, ModeType::from_bits_truncate(0o777))?;
+        if let Err(e) = inode.write_at(
+            0,
+            target.len(),
+            target.as_bytes(),
+            &mut FilePrivateData::Unused,
+        ) {
+            self.unlink(name).ok();
+            return Err(e);
+        }
+        return Ok(inode);
+    }
+
     /// @brief 在当前目录下，删除一个名为Name的硬链接
     ///
     /// @param name 硬链接的名称
@@ -279,12 +311,16 @@ pub trait IndexNode: Any + Sync + Send + Debug {
 
     /// @brief 将指定名称的子目录项的文件内容，移动到target这个目录下。如果_old_name所指向的inode与_target的相同，那么则直接执行重命名的操作。
     ///
+    /// 目标已经存在时，默认用源替换目标（目标是文件夹时，必须为空）
+    ///
     /// @param old_name 旧的名字
     ///
     /// @param target 移动到指定的inode
     ///
     /// @param new_name 新的文件名
     ///
+    /// @param flags renameat2的标志位。文件系统不支持的标志位，应当返回EINVAL
+    ///
     /// @return 成功: Ok()
     ///         失败: Err(错误码)
     fn move_(
@@ -292,6 +328,7 @@ pub trait IndexNode: Any + Sync + Send + Debug {
         _old_name: &str,
         _target: &Arc<dyn IndexNode>,
         _new_name: &str,
+        _flags: RenameFlags,
     ) -> Result<(), SystemError> {
         // 若文件系统没有实现此方法，则返回“不支持”
         return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
@@ -393,6 +430,13 @@ pub trait IndexNode: Any + Sync + Send + Debug {
     fn special_node(&self) -> Option<SpecialNodeData> {
         None
     }
+
+    /// ## 返回文件的页面缓存
+    ///
+    /// 只有支持文件映射（mmap）的文件系统才需要实现此方法
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        None
+    }
 }
 
 impl DowncastArc for dyn IndexNode {
@@ -429,6 +473,42 @@ impl dyn IndexNode {
         &self,
         path: &str,
         max_follow_times: usize,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        return self.lookup_follow_symlink2(path, max_follow_times, true);
+    }
+
+    /// @brief 查找文件（考虑符号链接），可以指定是否跟随路径最后一级的符号链接
+    ///
+    /// @param path 文件路径
+    /// @param max_follow_times 最大经过的符号链接的大小
+    /// @param follow_final_symlink 路径的最后一级是符号链接时，是否跟随它（对应AT_SYMLINK_NOFOLLOW）
+    ///
+    /// @return Ok(Arc<dyn IndexNode>) 要寻找的目录项的inode
+    /// @return Err(SystemError) 错误码
+    pub fn lookup_follow_symlink2(
+        &self,
+        path: &str,
+        max_follow_times: usize,
+        follow_final_symlink: bool,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        // max_follow_times为0表示不跟随符号链接
+        let max_follow_times = if max_follow_times == 0 {
+            None
+        } else {
+            Some(max_follow_times)
+        };
+        return self.do_lookup(path, max_follow_times, follow_final_symlink);
+    }
+
+    /// @brief 查找文件的实际实现
+    ///
+    /// @param max_follow_times 还可以跟随的符号链接的数量，为None时不跟随符号链接。
+    /// 需要跟随符号链接但是数量已经用完时，返回ELOOP
+    fn do_lookup(
+        &self,
+        path: &str,
+        max_follow_times: Option<usize>,
+        follow_final_symlink: bool,
     ) -> Result<Arc<dyn IndexNode>, SystemError> {
         if self.metadata()?.file_type != FileType::Dir {
             return Err(SystemError::ENOTDIR);
@@ -450,6 +530,10 @@ impl dyn IndexNode {
             if result.metadata()?.file_type != FileType::Dir {
                 return Err(SystemError::ENOTDIR);
             }
+            // 在文件夹中查找需要有文件夹的执行权限。进程管理初始化之前只有内核在查找文件，不需要检查
+            if ProcessManager::initialized() {
+                inode_permission(&result, PermissionMask::MAY_EXEC)?;
+            }
 
             let name;
 
@@ -475,10 +559,19 @@ impl dyn IndexNode {
             let inode = result.find(&name)?;
 
             // 处理符号链接的问题
-            if inode.metadata()?.file_type == FileType::SymLink && max_follow_times > 0 {
-                let mut content = [0u8; 256];
+            if inode.metadata()?.file_type == FileType::SymLink
+                && max_follow_times.is_some()
+                && (follow_final_symlink || !rest_path.is_empty())
+            {
+                let max_follow_times = max_follow_times.unwrap();
+                if max_follow_times == 0 {
+                    return Err(SystemError::ELOOP);
+                }
+
+                let mut content = vec![0u8; MAX_PATHLEN];
                 // 读取符号链接
-                let len = inode.read_at(0, 256, &mut content, &mut FilePrivateData::Unused)?;
+                let len =
+                    inode.read_at(0, MAX_PATHLEN, &mut content, &mut FilePrivateData::Unused)?;
 
                 // 将读到的数据转换为utf8字符串（先转为str，再转为String）
                 let link_path = String::from(
@@ -487,7 +580,11 @@ impl dyn IndexNode {
 
                 let new_path = link_path + "/" + &rest_path;
                 // 继续查找符号链接
-                return result.lookup_follow_symlink(&new_path, max_follow_times - 1);
+                return result.do_lookup(
+                    &new_path,
+                    Some(max_follow_times - 1),
+                    follow_final_symlink,
+                );
             } else {
                 result = inode;
             }
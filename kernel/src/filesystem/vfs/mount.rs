@@ -5,16 +5,92 @@ use core::{
 
 use alloc::{
     collections::BTreeMap,
+    string::String,
     sync::{Arc, Weak},
+    vec::Vec,
 };
 use system_error::SystemError;
 
-use crate::{driver::base::device::device_number::DeviceNumber, libs::spinlock::SpinLock};
+use crate::{
+    driver::base::device::device_number::DeviceNumber, filesystem::page_cache::PageCache,
+    libs::spinlock::SpinLock,
+};
 
 use super::{
-    file::FileMode, syscall::ModeType, FilePrivateData, FileSystem, FileType, IndexNode, InodeId,
+    file::FileMode,
+    syscall::{ModeType, RenameFlags},
+    FilePrivateData, FileSystem, FileType, IndexNode, InodeId,
 };
 
+lazy_static! {
+    /// 全局的挂载表，按挂载的先后顺序记录系统中所有的挂载
+    static ref MOUNT_LIST: SpinLock<Vec<MountRecord>> = SpinLock::new(Vec::new());
+}
+
+bitflags! {
+    /// umount2系统调用的标志位
+    pub struct UmountFlags: u32 {
+        /// 强制卸载（当前与普通卸载的行为相同）
+        const MNT_FORCE = 0x00000001;
+        /// 延迟卸载：立即把文件系统从挂载树上摘除，不检查它是否仍在被使用
+        const MNT_DETACH = 0x00000002;
+        /// 标记挂载点为过期（暂不支持）
+        const MNT_EXPIRE = 0x00000004;
+        /// 不跟随挂载点路径上的符号链接
+        const UMOUNT_NOFOLLOW = 0x00000008;
+    }
+}
+
+bitflags! {
+    /// mount系统调用的标志位
+    pub struct MountFlags: u32 {
+        /// 只读挂载（暂未实现）
+        const MS_RDONLY = 1;
+        const MS_NOSUID = 2;
+        const MS_NODEV = 4;
+        const MS_NOEXEC = 8;
+        /// 重新挂载（暂不支持）
+        const MS_REMOUNT = 32;
+        /// 绑定挂载（暂不支持）
+        const MS_BIND = 4096;
+        /// 移动挂载点（暂不支持）
+        const MS_MOVE = 8192;
+    }
+}
+
+/// 挂载表中的一条记录
+#[derive(Debug, Clone)]
+pub struct MountRecord {
+    /// 挂载源，例如块设备的路径
+    pub source: String,
+    /// 挂载点的绝对路径
+    pub path: String,
+    /// 文件系统类型的名称
+    pub fs_type: String,
+    /// 被挂载的文件系统
+    pub mount_fs: Arc<MountFS>,
+    /// 是否禁止卸载。内核依赖的伪文件系统（如/proc、/dev）不能被卸载
+    pub pinned: bool,
+}
+
+impl MountRecord {
+    pub fn new(
+        source: &str,
+        path: &str,
+        fs_type: &str,
+        mount_fs: Arc<MountFS>,
+        pinned: bool,
+    ) -> Self {
+        return Self {
+            source: String::from(source),
+            path: String::from(path),
+            fs_type: String::from(fs_type),
+            mount_fs,
+            pinned,
+        };
+    }
+}
+
 /// @brief 挂载文件系统
 /// 挂载文件系统的时候，套了MountFS这一层，以实现文件系统的递归挂载
 #[derive(Debug)]
@@ -85,6 +161,75 @@ impl MountFS {
     pub fn inner_filesystem(&self) -> Arc<dyn FileSystem> {
         return self.inner_filesystem.clone();
     }
+
+    /// 判断`inode`是否为当前文件系统的根节点
+    pub fn is_root_inode(&self, inode: &Arc<dyn IndexNode>) -> Result<bool, SystemError> {
+        return Ok(
+            self.inner_filesystem.root_inode().metadata()?.inode_id == inode.metadata()?.inode_id
+        );
+    }
+
+    /// 把一次挂载记录到全局的挂载表中
+    pub fn add_mount_record(record: MountRecord) {
+        MOUNT_LIST.lock().push(record);
+    }
+
+    /// 在挂载表中找到`mount_fs`对应的记录，并用`f`更新它。用于迁移伪文件系统时更新挂载表
+    pub fn update_mount_record(mount_fs: &MountFS, f: impl FnOnce(&mut MountRecord)) {
+        let mut list = MOUNT_LIST.lock();
+        if let Some(record) = list
+            .iter_mut()
+            .find(|r| core::ptr::eq(r.mount_fs.as_ref(), mount_fs))
+        {
+            f(record);
+        }
+    }
+
+    /// 获取挂载表的快照
+    pub fn mount_records() -> Vec<MountRecord> {
+        return MOUNT_LIST.lock().clone();
+    }
+
+    /// 卸载当前文件系统
+    ///
+    /// 调用者需要持有且仅持有一个指向当前MountFS的Arc指针，除此之外，
+    /// 只有父级的挂载树和挂载表会引用它。如果还有其他引用（例如文件系统内的文件被打开，
+    /// 或者是某个进程的工作目录），那么说明文件系统正在被使用，返回EBUSY（MNT_DETACH除外）
+    ///
+    /// ## 返回值
+    ///
+    /// - `EBUSY`: 当前文件系统是根文件系统、内还有其他挂载点、正在被使用，或者禁止被卸载
+    pub fn umount(&self, flags: UmountFlags) -> Result<(), SystemError> {
+        let mountpoint = self.self_mountpoint.as_ref().ok_or(SystemError::EBUSY)?;
+        if !self.mountpoints.lock().is_empty() {
+            return Err(SystemError::EBUSY);
+        }
+
+        let mut list = MOUNT_LIST.lock();
+        let index = list
+            .iter()
+            .position(|r| core::ptr::eq(r.mount_fs.as_ref(), self));
+        if let Some(index) = index {
+            if list[index].pinned {
+                return Err(SystemError::EBUSY);
+            }
+        }
+
+        if !flags.contains(UmountFlags::MNT_DETACH) {
+            // 调用者、父级的挂载树、挂载表各持有一个引用
+            let expected = 2 + index.map_or(0, |_| 1);
+            if self.self_ref.strong_count() > expected {
+                return Err(SystemError::EBUSY);
+            }
+        }
+
+        let inode_id = mountpoint.inner_inode.metadata()?.inode_id;
+        mountpoint.mount_fs.mountpoints.lock().remove(&inode_id);
+        if let Some(index) = index {
+            list.remove(index);
+        }
+        return Ok(());
+    }
 }
 
 impl MountFSInode {
@@ -222,7 +367,23 @@ impl IndexNode for MountFSInode {
     }
 
     fn link(&self, name: &str, other: &Arc<dyn IndexNode>) -> Result<(), SystemError> {
-        return self.inner_inode.link(name, other);
+        // 不能跨越文件系统创建硬链接
+        let other = other
+            .downcast_ref::<MountFSInode>()
+            .ok_or(SystemError::EXDEV)?;
+        if !Arc::ptr_eq(&self.mount_fs, &other.mount_fs) {
+            return Err(SystemError::EXDEV);
+        }
+        return self.inner_inode.link(name, &other.inner_inode);
+    }
+
+    fn symlink(&self, name: &str, target: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        return Ok(MountFSInode {
+            inner_inode: self.inner_inode.symlink(name, target)?,
+            mount_fs: self.mount_fs.clone(),
+            self_ref: Weak::default(),
+        }
+        .wrap());
     }
 
     /// @brief 在挂载文件系统中删除文件/文件夹
@@ -252,14 +413,40 @@ impl IndexNode for MountFSInode {
         return r;
     }
 
-    #[inline]
     fn move_(
         &self,
         old_name: &str,
         target: &Arc<dyn IndexNode>,
         new_name: &str,
+        flags: RenameFlags,
     ) -> Result<(), SystemError> {
-        return self.inner_inode.move_(old_name, target, new_name);
+        // 不能跨越文件系统移动
+        let target = target
+            .downcast_ref::<MountFSInode>()
+            .ok_or(SystemError::EXDEV)?;
+        if !Arc::ptr_eq(&self.mount_fs, &target.mount_fs) {
+            return Err(SystemError::EXDEV);
+        }
+
+        // 挂载点不能被移动，也不能被替换
+        let old_id = self.inner_inode.find(old_name)?.metadata()?.inode_id;
+        let new_id = target
+            .inner_inode
+            .find(new_name)
+            .and_then(|inode| inode.metadata())
+            .map(|md| md.inode_id)
+            .ok();
+        let mountpoints = self.mount_fs.mountpoints.lock();
+        if mountpoints.contains_key(&old_id)
+            || new_id.map_or(false, |id| mountpoints.contains_key(&id))
+        {
+            return Err(SystemError::EBUSY);
+        }
+        drop(mountpoints);
+
+        return self
+            .inner_inode
+            .move_(old_name, &target.inner_inode, new_name, flags);
     }
 
     fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
@@ -339,6 +526,15 @@ impl IndexNode for MountFSInode {
         if metadata.file_type != FileType::Dir {
             return Err(SystemError::ENOTDIR);
         }
+        // 同一个挂载点上只能挂载一个文件系统
+        if self
+            .mount_fs
+            .mountpoints
+            .lock()
+            .contains_key(&metadata.inode_id)
+        {
+            return Err(SystemError::EBUSY);
+        }
 
         // 为新的挂载点创建挂载文件系统
         let new_mount_fs: Arc<MountFS> = MountFS::new(fs, Some(self.self_ref.upgrade().unwrap()));
@@ -370,6 +566,11 @@ impl IndexNode for MountFSInode {
         self.inner_inode.special_node()
     }
 
+    #[inline]
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        self.inner_inode.page_cache()
+    }
+
     #[inline]
     fn poll(&self, private_data: &FilePrivateData) -> Result<usize, SystemError> {
         self.inner_inode.poll(private_data)
@@ -2,13 +2,21 @@ use alloc::sync::Arc;
 use system_error::SystemError;
 
 use crate::{
-    driver::base::block::SeekFrom, process::ProcessManager,
+    driver::base::block::SeekFrom,
+    process::{
+        cred::{Kgid, Kuid},
+        ProcessManager,
+    },
     syscall::user_access::check_and_clone_cstr,
 };
 
 use super::{
     fcntl::AtFlags,
     file::{File, FileMode},
+    permission::{
+        generic_permission, inode_init_owner, inode_owner_or_capable, inode_permission,
+        PermissionMask,
+    },
     syscall::{ModeType, OpenHow, OpenHowResolve},
     utils::{rsplit_path, user_path_at},
     FileType, IndexNode, MAX_PATHLEN, ROOT_INODE, VFS_MAX_FOLLOW_SYMLINK_TIMES,
@@ -32,7 +40,7 @@ pub(super) fn do_faccessat(
         return Err(SystemError::EINVAL);
     }
 
-    // let follow_symlink = flags & AtFlags::AT_SYMLINK_NOFOLLOW.bits() as u32 == 0;
+    let follow_symlink = flags & AtFlags::AT_SYMLINK_NOFOLLOW.bits() as u32 == 0;
 
     let path = check_and_clone_cstr(path, Some(MAX_PATHLEN))?;
 
@@ -43,13 +51,33 @@ pub(super) fn do_faccessat(
     let (inode, path) = user_path_at(&ProcessManager::current_pcb(), dirfd, &path)?;
 
     // 如果找不到文件，则返回错误码ENOENT
-    let _inode = inode.lookup_follow_symlink(path.as_str(), VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+    let inode = inode.lookup_follow_symlink2(
+        path.as_str(),
+        VFS_MAX_FOLLOW_SYMLINK_TIMES,
+        follow_symlink,
+    )?;
 
-    // todo: 接着完善（可以借鉴linux 6.1.9的do_faccessat）
+    // F_OK：只检查文件是否存在
+    if mode.is_empty() {
+        return Ok(0);
+    }
+
+    // 除非指定了AT_EACCESS，否则使用真实用户id和真实组id进行检查
+    let mut cred = (*ProcessManager::current_pcb().cred()).clone();
+    if flags & AtFlags::AT_EACCESS.bits() as u32 == 0 {
+        cred.fsuid = cred.uid;
+        cred.fsgid = cred.gid;
+    }
+
+    generic_permission(
+        &inode.metadata()?,
+        &cred,
+        PermissionMask::from_bits_truncate(mode.bits()),
+    )?;
     return Ok(0);
 }
 
-pub fn do_fchmodat(dirfd: i32, path: *const u8, _mode: ModeType) -> Result<usize, SystemError> {
+pub fn do_fchmodat(dirfd: i32, path: *const u8, mode: ModeType) -> Result<usize, SystemError> {
     let path = check_and_clone_cstr(path, Some(MAX_PATHLEN))?;
 
     if path.len() == 0 {
@@ -59,11 +87,108 @@ pub fn do_fchmodat(dirfd: i32, path: *const u8, _mode: ModeType) -> Result<usize
     let (inode, path) = user_path_at(&ProcessManager::current_pcb(), dirfd, &path)?;
 
     // 如果找不到文件，则返回错误码ENOENT
-    let _inode = inode.lookup_follow_symlink(path.as_str(), VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+    let inode = inode.lookup_follow_symlink(path.as_str(), VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+
+    return chmod_common(&inode, mode);
+}
+
+/// 修改文件的权限位，只有文件的所有者或root用户可以修改
+pub(super) fn chmod_common(
+    inode: &Arc<dyn IndexNode>,
+    mode: ModeType,
+) -> Result<usize, SystemError> {
+    let cred = ProcessManager::current_pcb().cred();
+    let mut metadata = inode.metadata()?;
+    inode_owner_or_capable(&metadata, &cred)?;
+
+    let mut mode = mode & ModeType::S_IALLUGO;
+    // 不属于文件所在组的普通用户不能设置S_ISGID
+    if !cred.fs_override() && !cred.in_group(Kgid::new(metadata.gid)) {
+        mode.remove(ModeType::S_ISGID);
+    }
+    metadata.mode = (metadata.mode & !ModeType::S_IALLUGO) | mode;
+    inode.set_metadata(&metadata)?;
+    return Ok(0);
+}
+
+pub fn do_fchownat(
+    dirfd: i32,
+    path: *const u8,
+    uid: Option<Kuid>,
+    gid: Option<Kgid>,
+    flags: u32,
+) -> Result<usize, SystemError> {
+    if flags & !((AtFlags::AT_SYMLINK_NOFOLLOW | AtFlags::AT_EMPTY_PATH).bits() as u32) != 0 {
+        return Err(SystemError::EINVAL);
+    }
+    let follow_symlink = flags & AtFlags::AT_SYMLINK_NOFOLLOW.bits() as u32 == 0;
+
+    let path = check_and_clone_cstr(path, Some(MAX_PATHLEN))?;
 
-    kwarn!("do_fchmodat: not implemented yet\n");
-    // todo: 真正去改变文件的权限
+    if path.len() == 0 {
+        if flags & AtFlags::AT_EMPTY_PATH.bits() as u32 == 0 {
+            return Err(SystemError::ENOENT);
+        }
+        // 指定了AT_EMPTY_PATH并且路径为空时，修改dirfd本身（AT_FDCWD时为当前工作目录）的所有者
+        let pcb = ProcessManager::current_pcb();
+        let inode = if dirfd == AtFlags::AT_FDCWD.bits() {
+            ROOT_INODE().lookup_follow_symlink(&pcb.basic().cwd(), VFS_MAX_FOLLOW_SYMLINK_TIMES)?
+        } else {
+            let binding = pcb.fd_table();
+            let file = binding
+                .read()
+                .get_file_by_fd(dirfd)
+                .ok_or(SystemError::EBADF)?;
+            let inode = file.lock().inode();
+            inode
+        };
+        return chown_common(&inode, uid, gid);
+    }
+
+    let (inode, path) = user_path_at(&ProcessManager::current_pcb(), dirfd, &path)?;
+    let inode = inode.lookup_follow_symlink2(
+        path.as_str(),
+        VFS_MAX_FOLLOW_SYMLINK_TIMES,
+        follow_symlink,
+    )?;
+
+    return chown_common(&inode, uid, gid);
+}
+
+/// 修改文件的所有者和组
+///
+/// 只有root用户可以修改文件的所有者。文件的所有者可以把文件的组修改为自己所属的组
+pub(super) fn chown_common(
+    inode: &Arc<dyn IndexNode>,
+    uid: Option<Kuid>,
+    gid: Option<Kgid>,
+) -> Result<usize, SystemError> {
+    let cred = ProcessManager::current_pcb().cred();
+    let mut metadata = inode.metadata()?;
 
+    if !cred.fs_override() {
+        let is_owner = cred.fsuid == Kuid::new(metadata.uid);
+        let uid_ok = uid.map_or(true, |uid| uid == Kuid::new(metadata.uid));
+        let gid_ok = gid.map_or(true, |gid| {
+            gid == Kgid::new(metadata.gid) || cred.in_group(gid)
+        });
+        if !is_owner || !uid_ok || !gid_ok {
+            return Err(SystemError::EPERM);
+        }
+    }
+
+    if let Some(uid) = uid {
+        metadata.uid = uid.data();
+    }
+    if let Some(gid) = gid {
+        metadata.gid = gid.data();
+    }
+    // 修改普通文件的所有者之后，清除set-user-ID和set-group-ID位
+    if metadata.file_type != FileType::Dir && (uid.is_some() || gid.is_some()) {
+        metadata.mode.remove(ModeType::S_ISUID | ModeType::S_ISGID);
+    }
+
+    inode.set_metadata(&metadata)?;
     return Ok(0);
 }
 
@@ -90,14 +215,8 @@ fn do_sys_openat2(
         return Err(SystemError::ENAMETOOLONG);
     }
     let (inode_begin, path) = user_path_at(&ProcessManager::current_pcb(), dirfd, path)?;
-    let inode: Result<Arc<dyn IndexNode>, SystemError> = inode_begin.lookup_follow_symlink(
-        &path,
-        if follow_symlink {
-            VFS_MAX_FOLLOW_SYMLINK_TIMES
-        } else {
-            0
-        },
-    );
+    let inode: Result<Arc<dyn IndexNode>, SystemError> =
+        inode_begin.lookup_follow_symlink2(&path, VFS_MAX_FOLLOW_SYMLINK_TIMES, follow_symlink);
 
     let inode: Arc<dyn IndexNode> = if inode.is_err() {
         let errno = inode.unwrap_err();
@@ -108,21 +227,32 @@ fn do_sys_openat2(
         {
             let (filename, parent_path) = rsplit_path(&path);
             // 查找父目录
-            let parent_inode: Arc<dyn IndexNode> =
-                ROOT_INODE().lookup(parent_path.unwrap_or("/"))?;
-            // 创建文件
+            let parent_inode: Arc<dyn IndexNode> = inode_begin
+                .lookup_follow_symlink(parent_path.unwrap_or("."), VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+            inode_permission(
+                &parent_inode,
+                PermissionMask::MAY_WRITE | PermissionMask::MAY_EXEC,
+            )?;
+            // 创建文件，文件的权限需要去掉当前进程的权限掩码中的位
+            let umask = ModeType::from_bits_truncate(ProcessManager::current_pcb().basic().umask());
             let inode: Arc<dyn IndexNode> = parent_inode.create(
                 filename,
                 FileType::File,
-                ModeType::from_bits_truncate(0o755),
+                how.mode & ModeType::S_IALLUGO & !umask,
             )?;
+            inode_init_owner(&parent_inode, &inode)?;
             inode
         } else {
             // 不需要创建文件，因此返回错误码
             return Err(errno);
         }
     } else {
-        inode.unwrap()
+        let inode = inode.unwrap();
+        // O_PATH只获取文件的路径，不需要访问文件的权限
+        if !how.o_flags.contains(FileMode::O_PATH) {
+            inode_permission(&inode, PermissionMask::from_open_flags(how.o_flags))?;
+        }
+        inode
     };
 
     let file_type: FileType = inode.metadata()?.file_type;
@@ -134,6 +264,10 @@ fn do_sys_openat2(
     // 创建文件对象
 
     let mut file: File = File::new(inode, how.o_flags)?;
+    // 相对于dirfd的路径无法得到绝对路径，此时不记录
+    if path.starts_with('/') {
+        file.set_path(path);
+    }
 
     // 打开模式为“追加”
     if how.o_flags.contains(FileMode::O_APPEND) {
@@ -0,0 +1,144 @@
+//! 文件访问权限检查
+//!
+//! 参考：https://code.dragonos.org.cn/xref/linux-6.1.9/fs/namei.c
+
+use alloc::sync::Arc;
+use system_error::SystemError;
+
+use crate::process::{
+    cred::{Cred, Kgid, Kuid},
+    ProcessManager,
+};
+
+use super::{file::FileMode, syscall::ModeType, FileType, IndexNode, Metadata};
+
+bitflags! {
+    /// 访问文件时请求的权限，与access系统调用的mode参数的取值相同
+    pub struct PermissionMask: u32 {
+        /// 执行文件或者查找目录
+        const MAY_EXEC = 0o1;
+        /// 写入
+        const MAY_WRITE = 0o2;
+        /// 读取
+        const MAY_READ = 0o4;
+    }
+}
+
+impl PermissionMask {
+    /// 打开文件时需要的权限
+    pub fn from_open_flags(o_flags: FileMode) -> Self {
+        let mut mask = match o_flags.accmode() {
+            x if x == FileMode::O_WRONLY.bits() => PermissionMask::MAY_WRITE,
+            x if x == FileMode::O_RDWR.bits() => {
+                PermissionMask::MAY_READ | PermissionMask::MAY_WRITE
+            }
+            _ => PermissionMask::MAY_READ,
+        };
+        if o_flags.contains(FileMode::O_TRUNC) {
+            mask.insert(PermissionMask::MAY_WRITE);
+        }
+        return mask;
+    }
+}
+
+/// ## 根据文件的元数据，检查凭证是否有权限以`mask`访问文件
+///
+/// 文件的所有者使用所有者的权限位，属于文件所在组的用户使用组的权限位，其他用户使用其他用户的权限位。
+/// root用户可以读写任何文件，但只能执行至少有一个执行权限位的文件
+pub fn generic_permission(
+    metadata: &Metadata,
+    cred: &Cred,
+    mask: PermissionMask,
+) -> Result<(), SystemError> {
+    let mode = metadata.mode.bits();
+    let perm = if cred.fsuid == Kuid::new(metadata.uid) {
+        mode >> 6
+    } else if cred.in_group(Kgid::new(metadata.gid)) {
+        mode >> 3
+    } else {
+        mode
+    };
+
+    if mask.bits() & !perm & 0o7 == 0 {
+        return Ok(());
+    }
+
+    if cred.fs_override() {
+        if !mask.contains(PermissionMask::MAY_EXEC)
+            || metadata.file_type == FileType::Dir
+            || mode & ModeType::S_IXUGO.bits() != 0
+        {
+            return Ok(());
+        }
+    }
+
+    return Err(SystemError::EACCES);
+}
+
+/// ## 检查当前进程是否有权限以`mask`访问inode
+pub fn inode_permission(
+    inode: &Arc<dyn IndexNode>,
+    mask: PermissionMask,
+) -> Result<(), SystemError> {
+    let cred = ProcessManager::current_pcb().cred();
+    return generic_permission(&inode.metadata()?, &cred, mask);
+}
+
+/// ## 检查当前进程是否可以删除目录`dir`中的`victim`
+///
+/// 需要对目录有写和查找的权限。如果目录设置了粘滞位，则只有文件或目录的所有者才能删除文件
+pub fn may_delete(
+    dir: &Arc<dyn IndexNode>,
+    victim: &Arc<dyn IndexNode>,
+) -> Result<(), SystemError> {
+    let cred = ProcessManager::current_pcb().cred();
+    let dir_md = dir.metadata()?;
+    generic_permission(
+        &dir_md,
+        &cred,
+        PermissionMask::MAY_WRITE | PermissionMask::MAY_EXEC,
+    )?;
+
+    if dir_md.mode.contains(ModeType::S_ISVTX) && !cred.fs_override() {
+        let victim_md = victim.metadata()?;
+        if cred.fsuid != Kuid::new(victim_md.uid) && cred.fsuid != Kuid::new(dir_md.uid) {
+            return Err(SystemError::EPERM);
+        }
+    }
+
+    return Ok(());
+}
+
+/// ## 检查当前进程是否是文件的所有者（或者有权限忽略所有权）
+///
+/// 用于chmod等只有文件所有者才能进行的操作
+pub fn inode_owner_or_capable(metadata: &Metadata, cred: &Cred) -> Result<(), SystemError> {
+    if cred.fsuid == Kuid::new(metadata.uid) || cred.fs_override() {
+        return Ok(());
+    }
+    return Err(SystemError::EPERM);
+}
+
+/// ## 把在目录`dir`中新创建的`inode`的所有者设置为当前进程
+///
+/// 如果目录设置了S_ISGID，新文件继承目录的组。
+/// 不支持修改元数据的文件系统（例如FAT）不记录文件的所有者，此时忽略这一步
+pub fn inode_init_owner(
+    dir: &Arc<dyn IndexNode>,
+    inode: &Arc<dyn IndexNode>,
+) -> Result<(), SystemError> {
+    let cred = ProcessManager::current_pcb().cred();
+    let dir_md = dir.metadata()?;
+    let mut metadata = inode.metadata()?;
+    metadata.uid = cred.fsuid.data();
+    metadata.gid = if dir_md.mode.contains(ModeType::S_ISGID) {
+        dir_md.gid
+    } else {
+        cred.fsgid.data()
+    };
+
+    match inode.set_metadata(&metadata) {
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP) => Ok(()),
+        r => r,
+    }
+}
@@ -22,10 +22,14 @@ use crate::{
 };
 
 use super::{
-    core::{do_mkdir, do_remove_dir, do_unlink_at},
+    core::{
+        do_linkat, do_mkdir, do_mount, do_remove_dir, do_renameat2, do_symlinkat, do_umount2,
+        do_unlink_at,
+    },
     fcntl::{AtFlags, FcntlCommand, FD_CLOEXEC},
     file::{File, FileMode},
-    open::{do_faccessat, do_fchmodat, do_sys_open},
+    mount::{MountFlags, UmountFlags},
+    open::{chmod_common, chown_common, do_faccessat, do_fchmodat, do_fchownat, do_sys_open},
     utils::{rsplit_path, user_path_at},
     Dirent, FileType, IndexNode, MAX_PATHLEN, ROOT_INODE, VFS_MAX_FOLLOW_SYMLINK_TIMES,
 };
@@ -240,6 +244,18 @@ bitflags! {
         const RESOLVE_CACHED = 0x20;
     }
 }
+
+bitflags! {
+    /// renameat2系统调用的标志位
+    pub struct RenameFlags: u32 {
+        /// 目标已经存在时返回EEXIST，而不是替换它
+        const RENAME_NOREPLACE = 1 << 0;
+        /// 原子地交换源和目标，二者都必须存在
+        const RENAME_EXCHANGE = 1 << 1;
+        /// 在源的位置留下一个whiteout对象（仅用于overlayfs，暂不支持）
+        const RENAME_WHITEOUT = 1 << 2;
+    }
+}
 impl Syscall {
     /// @brief 为当前进程打开一个文件
     ///
@@ -275,7 +291,11 @@ impl Syscall {
         let binding = ProcessManager::current_pcb().fd_table();
         let mut fd_table_guard = binding.write();
 
+        let file = fd_table_guard.get_file_by_fd(fd as i32);
         let res = fd_table_guard.drop_fd(fd as i32).map(|_| 0);
+        drop(fd_table_guard);
+        // 释放文件描述符表的锁之后再关闭文件，关闭文件时可能需要睡眠（例如设置了SO_LINGER的socket）
+        drop(file);
 
         return res;
     }
@@ -535,7 +555,29 @@ impl Syscall {
     ///
     /// @return uint64_t 负数错误码 / 0表示成功
     pub fn mkdir(path: &str, mode: usize) -> Result<usize, SystemError> {
-        return do_mkdir(path, FileMode::from_bits_truncate(mode as u32)).map(|x| x as usize);
+        return do_mkdir(
+            AtFlags::AT_FDCWD.bits(),
+            path,
+            ModeType::from_bits_truncate(mode as u32),
+        )
+        .map(|x| x as usize);
+    }
+
+    /// ## 创建文件夹，`path`为相对路径时，相对于`dirfd`
+    ///
+    /// ## 参数
+    ///
+    /// - `dirfd`：`path`为相对路径时，相对于这个目录
+    /// - `path`：要创建的文件夹的路径
+    /// - `mode`：文件夹的权限
+    pub fn mkdirat(dirfd: i32, path: *const u8, mode: u32) -> Result<usize, SystemError> {
+        if path.is_null() {
+            return Err(SystemError::EFAULT);
+        }
+        let path = check_and_clone_cstr(path, Some(MAX_PATHLEN))?;
+
+        return do_mkdir(dirfd, path.trim(), ModeType::from_bits_truncate(mode))
+            .map(|x| x as usize);
     }
 
     /// **删除文件夹、取消文件的链接、删除文件的系统调用**
@@ -602,6 +644,122 @@ impl Syscall {
         return do_unlink_at(AtFlags::AT_FDCWD.bits(), pathname).map(|v| v as usize);
     }
 
+    /// ## 重命名文件或文件夹
+    pub fn rename(oldpath: *const u8, newpath: *const u8) -> Result<usize, SystemError> {
+        return Self::renameat2(
+            AtFlags::AT_FDCWD.bits(),
+            oldpath,
+            AtFlags::AT_FDCWD.bits(),
+            newpath,
+            0,
+        );
+    }
+
+    /// ## 重命名文件或文件夹，相对路径分别相对于`olddirfd`和`newdirfd`
+    pub fn renameat(
+        olddirfd: i32,
+        oldpath: *const u8,
+        newdirfd: i32,
+        newpath: *const u8,
+    ) -> Result<usize, SystemError> {
+        return Self::renameat2(olddirfd, oldpath, newdirfd, newpath, 0);
+    }
+
+    /// ## 重命名文件或文件夹
+    ///
+    /// ## 参数
+    ///
+    /// - `olddirfd`：`oldpath`为相对路径时，相对于这个目录
+    /// - `oldpath`：源路径
+    /// - `newdirfd`：`newpath`为相对路径时，相对于这个目录
+    /// - `newpath`：目标路径
+    /// - `flags`：RENAME_NOREPLACE或者RENAME_EXCHANGE
+    pub fn renameat2(
+        olddirfd: i32,
+        oldpath: *const u8,
+        newdirfd: i32,
+        newpath: *const u8,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        let flags = RenameFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        if oldpath.is_null() || newpath.is_null() {
+            return Err(SystemError::EFAULT);
+        }
+        let oldpath = check_and_clone_cstr(oldpath, Some(MAX_PATHLEN))?;
+        let newpath = check_and_clone_cstr(newpath, Some(MAX_PATHLEN))?;
+
+        do_renameat2(olddirfd, oldpath.trim(), newdirfd, newpath.trim(), flags)?;
+        return Ok(0);
+    }
+
+    /// ## 创建符号链接
+    pub fn symlink(target: *const u8, linkpath: *const u8) -> Result<usize, SystemError> {
+        return Self::symlinkat(target, AtFlags::AT_FDCWD.bits(), linkpath);
+    }
+
+    /// ## 创建符号链接，`linkpath`为相对路径时，相对于`newdirfd`
+    ///
+    /// ## 参数
+    ///
+    /// - `target`：符号链接的内容
+    /// - `newdirfd`：`linkpath`为相对路径时，相对于这个目录
+    /// - `linkpath`：符号链接的路径
+    pub fn symlinkat(
+        target: *const u8,
+        newdirfd: i32,
+        linkpath: *const u8,
+    ) -> Result<usize, SystemError> {
+        if target.is_null() || linkpath.is_null() {
+            return Err(SystemError::EFAULT);
+        }
+        let target = check_and_clone_cstr(target, Some(MAX_PATHLEN))?;
+        let linkpath = check_and_clone_cstr(linkpath, Some(MAX_PATHLEN))?;
+
+        do_symlinkat(&target, newdirfd, linkpath.trim())?;
+        return Ok(0);
+    }
+
+    /// ## 创建硬链接
+    pub fn link(oldpath: *const u8, newpath: *const u8) -> Result<usize, SystemError> {
+        return Self::linkat(
+            AtFlags::AT_FDCWD.bits(),
+            oldpath,
+            AtFlags::AT_FDCWD.bits(),
+            newpath,
+            0,
+        );
+    }
+
+    /// ## 创建硬链接
+    ///
+    /// ## 参数
+    ///
+    /// - `olddirfd`：`oldpath`为相对路径时，相对于这个目录
+    /// - `oldpath`：已经存在的文件的路径
+    /// - `newdirfd`：`newpath`为相对路径时，相对于这个目录
+    /// - `newpath`：新的硬链接的路径
+    /// - `flags`：只支持AT_SYMLINK_FOLLOW
+    pub fn linkat(
+        olddirfd: i32,
+        oldpath: *const u8,
+        newdirfd: i32,
+        newpath: *const u8,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        if flags & !(AtFlags::AT_SYMLINK_FOLLOW.bits() as u32) != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let flags = AtFlags::from_bits_truncate(flags as i32);
+        if oldpath.is_null() || newpath.is_null() {
+            return Err(SystemError::EFAULT);
+        }
+        let oldpath = check_and_clone_cstr(oldpath, Some(MAX_PATHLEN))?;
+        let newpath = check_and_clone_cstr(newpath, Some(MAX_PATHLEN))?;
+
+        do_linkat(olddirfd, oldpath.trim(), newdirfd, newpath.trim(), flags)?;
+        return Ok(0);
+    }
+
     /// @brief 根据提供的文件描述符的fd，复制对应的文件结构体，并返回新复制的文件结构体对应的fd
     pub fn dup(oldfd: i32) -> Result<usize, SystemError> {
         let binding = ProcessManager::current_pcb().fd_table();
@@ -858,14 +1016,56 @@ impl Syscall {
     }
 
     pub fn stat(path: &str, user_kstat: *mut PosixKstat) -> Result<usize, SystemError> {
-        let fd = Self::open(path, FileMode::O_RDONLY, ModeType::empty(), true)?;
+        let fd = Self::open(path, FileMode::O_PATH, ModeType::empty(), true)?;
         let r = Self::fstat(fd as i32, user_kstat);
         Self::close(fd).ok();
         return r;
     }
 
     pub fn lstat(path: &str, user_kstat: *mut PosixKstat) -> Result<usize, SystemError> {
-        let fd = Self::open(path, FileMode::O_RDONLY, ModeType::empty(), false)?;
+        let fd = Self::open(path, FileMode::O_PATH, ModeType::empty(), false)?;
+        let r = Self::fstat(fd as i32, user_kstat);
+        Self::close(fd).ok();
+        return r;
+    }
+
+    /// ## 获取相对于`dirfd`的文件的信息
+    ///
+    /// ## 参数
+    ///
+    /// - `flags`：指定了AT_SYMLINK_NOFOLLOW时，路径的最后一级为符号链接则获取符号链接本身的信息；
+    /// 指定了AT_EMPTY_PATH并且路径为空时，获取`dirfd`本身的信息
+    pub fn newfstatat(
+        dirfd: i32,
+        path: *const u8,
+        user_kstat: *mut PosixKstat,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        let allowed =
+            AtFlags::AT_SYMLINK_NOFOLLOW | AtFlags::AT_EMPTY_PATH | AtFlags::AT_NO_AUTOMOUNT;
+        if flags & !(allowed.bits() as u32) != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let flags = AtFlags::from_bits_truncate(flags as i32);
+        if path.is_null() {
+            return Err(SystemError::EFAULT);
+        }
+        let path = check_and_clone_cstr(path, Some(MAX_PATHLEN))?;
+
+        if path.is_empty() {
+            if flags.contains(AtFlags::AT_EMPTY_PATH) {
+                return Self::fstat(dirfd, user_kstat);
+            }
+            return Err(SystemError::ENOENT);
+        }
+
+        let fd = do_sys_open(
+            dirfd,
+            &path,
+            FileMode::O_PATH,
+            ModeType::empty(),
+            !flags.contains(AtFlags::AT_SYMLINK_NOFOLLOW),
+        )?;
         let r = Self::fstat(fd as i32, user_kstat);
         Self::close(fd).ok();
         return r;
@@ -943,7 +1143,8 @@ impl Syscall {
 
         let (inode, path) = user_path_at(&ProcessManager::current_pcb(), dirfd, &path)?;
 
-        let inode = inode.lookup(path.as_str())?;
+        let inode =
+            inode.lookup_follow_symlink2(path.as_str(), VFS_MAX_FOLLOW_SYMLINK_TIMES, false)?;
         if inode.metadata()?.file_type != FileType::SymLink {
             return Err(SystemError::EINVAL);
         }
@@ -1005,16 +1206,122 @@ impl Syscall {
     }
 
     pub fn fchmod(fd: i32, mode: u32) -> Result<usize, SystemError> {
-        let _mode = ModeType::from_bits(mode).ok_or(SystemError::EINVAL)?;
+        let mode = ModeType::from_bits(mode).ok_or(SystemError::EINVAL)?;
+        let binding = ProcessManager::current_pcb().fd_table();
+        let fd_table_guard = binding.read();
+        let file = fd_table_guard
+            .get_file_by_fd(fd)
+            .ok_or(SystemError::EBADF)?;
+        drop(fd_table_guard);
+
+        let inode = file.lock().inode();
+        return chmod_common(&inode, mode);
+    }
+
+    /// # 修改文件的所有者和组
+    ///
+    /// ## 参数
+    ///
+    /// - `uid`: 新的所有者，为-1时不修改
+    /// - `gid`: 新的组，为-1时不修改
+    pub fn fchownat(
+        dirfd: i32,
+        pathname: *const u8,
+        uid: usize,
+        gid: usize,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        return do_fchownat(
+            dirfd,
+            pathname,
+            Self::optional_uid(uid),
+            Self::optional_gid(gid),
+            flags,
+        );
+    }
+
+    pub fn chown(pathname: *const u8, uid: usize, gid: usize) -> Result<usize, SystemError> {
+        return Self::fchownat(AtFlags::AT_FDCWD.bits(), pathname, uid, gid, 0);
+    }
+
+    pub fn lchown(pathname: *const u8, uid: usize, gid: usize) -> Result<usize, SystemError> {
+        return Self::fchownat(
+            AtFlags::AT_FDCWD.bits(),
+            pathname,
+            uid,
+            gid,
+            AtFlags::AT_SYMLINK_NOFOLLOW.bits() as u32,
+        );
+    }
+
+    pub fn fchown(fd: i32, uid: usize, gid: usize) -> Result<usize, SystemError> {
         let binding = ProcessManager::current_pcb().fd_table();
         let fd_table_guard = binding.read();
-        let _file = fd_table_guard
+        let file = fd_table_guard
             .get_file_by_fd(fd)
             .ok_or(SystemError::EBADF)?;
+        drop(fd_table_guard);
+
+        let inode = file.lock().inode();
+        return chown_common(&inode, Self::optional_uid(uid), Self::optional_gid(gid));
+    }
+
+    /// # mount系统调用：挂载文件系统
+    ///
+    /// ## 参数
+    ///
+    /// - `source`: 挂载源，伪文件系统可以传入NULL
+    /// - `target`: 挂载点的路径
+    /// - `fs_type`: 文件系统类型的名称
+    /// - `flags`: 挂载标志位，目前不支持重新挂载、绑定挂载和移动挂载点
+    /// - `_data`: 文件系统相关的挂载选项（暂不支持）
+    pub fn mount(
+        source: *const u8,
+        target: *const u8,
+        fs_type: *const u8,
+        flags: u32,
+        _data: *const u8,
+    ) -> Result<usize, SystemError> {
+        if !ProcessManager::current_pcb().cred().can_sys_admin() {
+            return Err(SystemError::EPERM);
+        }
+        let flags = MountFlags::from_bits_truncate(flags);
+        if flags.intersects(MountFlags::MS_REMOUNT | MountFlags::MS_BIND | MountFlags::MS_MOVE) {
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+
+        let source = if source.is_null() {
+            String::from("none")
+        } else {
+            check_and_clone_cstr(source, Some(MAX_PATHLEN))?
+        };
+        let target = check_and_clone_cstr(target, Some(MAX_PATHLEN))?;
+        let fs_type = check_and_clone_cstr(fs_type, Some(MAX_PATHLEN))?;
+        if target.is_empty() {
+            return Err(SystemError::ENOENT);
+        }
+
+        do_mount(source.trim(), target.trim(), fs_type.trim())?;
+        return Ok(0);
+    }
+
+    /// # umount2系统调用：卸载文件系统
+    ///
+    /// ## 参数
+    ///
+    /// - `target`: 被卸载的文件系统的挂载点的路径
+    /// - `flags`: 卸载标志位
+    pub fn umount2(target: *const u8, flags: u32) -> Result<usize, SystemError> {
+        if !ProcessManager::current_pcb().cred().can_sys_admin() {
+            return Err(SystemError::EPERM);
+        }
+        let flags = UmountFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        let target = check_and_clone_cstr(target, Some(MAX_PATHLEN))?;
+        if target.is_empty() {
+            return Err(SystemError::ENOENT);
+        }
 
-        // fchmod没完全实现，因此不修改文件的权限
-        // todo: 实现fchmod
-        kwarn!("fchmod not fully implemented");
+        do_umount2(target.trim(), flags)?;
         return Ok(0);
     }
 }
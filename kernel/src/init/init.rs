@@ -14,6 +14,7 @@ use crate::{
             screen_manager::{scm_init, scm_reinit},
             textui::textui_init,
         },
+        rand::rand_init,
     },
     mm::init::mm_init,
     process::{kthread::kthread_init, process_init, ProcessManager},
@@ -21,7 +22,8 @@ use crate::{
     smp::{early_smp_init, SMPArch},
     syscall::Syscall,
     time::{
-        clocksource::clocksource_boot_finish, timekeeping::timekeeping_init, timer::timer_init,
+        clocksource::clocksource_boot_finish, hrtimer::hrtimer_init, timekeeping::timekeeping_init,
+        timer::timer_init,
     },
 };
 
@@ -70,7 +72,9 @@ fn do_start_kernel() {
     softirq_init().expect("softirq init failed");
     Syscall::init().expect("syscall init failed");
     timekeeping_init();
+    rand_init();
     timer_init();
+    hrtimer_init();
     kthread_init();
     clocksource_boot_finish();
 
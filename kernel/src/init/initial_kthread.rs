@@ -34,11 +34,14 @@ fn kernel_init() -> Result<(), SystemError> {
     // scm_enable_double_buffer().expect("Failed to enable double buffer");
     stdio_init().expect("Failed to initialize stdio");
 
-    ahci_init().expect("Failed to initialize AHCI");
+    ahci_init().unwrap_or_else(|err| {
+        kerror!("Failed to initialize AHCI: {:?}", err);
+    });
+    // virtio-blk磁盘也可能作为根文件系统所在的磁盘
+    virtio_probe();
 
     mount_root_fs().expect("Failed to mount root fs");
 
-    virtio_probe();
     e1000e_init();
     net_init().unwrap_or_else(|err| {
         kerror!("Failed to initialize network: {:?}", err);
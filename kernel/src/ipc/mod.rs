@@ -1,4 +1,5 @@
 pub mod pipe;
 pub mod signal;
 pub mod signal_types;
+pub mod signalfd;
 pub mod syscall;
@@ -67,10 +67,11 @@ impl InnerPipeInode {
             return Err(SystemError::EBADFD);
         };
 
-        if mode.contains(FileMode::O_RDONLY) {
+        // O_RDONLY的值为0，需要通过访问模式判断是读端还是写端
+        if mode.accmode() == FileMode::O_RDONLY.bits() {
             if self.valid_cnt != 0 {
                 // 有数据可读
-                events.insert(EPollEventType::EPOLLIN & EPollEventType::EPOLLRDNORM);
+                events.insert(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM);
             }
 
             // 没有写者
@@ -79,10 +80,10 @@ impl InnerPipeInode {
             }
         }
 
-        if mode.contains(FileMode::O_WRONLY) {
+        if mode.accmode() == FileMode::O_WRONLY.bits() {
             // 管道内数据未满
             if self.valid_cnt as usize != PIPE_BUFF_SIZE {
-                events.insert(EPollEventType::EPOLLIN & EPollEventType::EPOLLWRNORM);
+                events.insert(EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM);
             }
 
             // 没有读者
@@ -98,6 +99,18 @@ impl InnerPipeInode {
         self.epitems.lock().push_back(epitem);
         Ok(())
     }
+
+    pub fn remove_epoll(&mut self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
+        let removed = self
+            .epitems
+            .lock()
+            .extract_if(|epitem| epitem.epoll().ptr_eq(epoll))
+            .count();
+        if removed == 0 {
+            return Err(SystemError::ENOENT);
+        }
+        Ok(())
+    }
 }
 
 impl LockedPipeInode {
@@ -232,9 +245,11 @@ impl IndexNode for LockedPipeInode {
             .write_wait_queue
             .wakeup(Some(ProcessState::Blocked(true)));
 
-        let pollflag = EPollEventType::from_bits_truncate(inode.poll(&data)? as u32);
-        // 唤醒epoll中等待的进程
-        EventPoll::wakeup_epoll(&mut inode.epitems, pollflag)?;
+        // 管道有了空闲的空间，唤醒在写端的epoll中等待的进程
+        let _ = EventPoll::wakeup_epoll(
+            &inode.epitems,
+            EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM,
+        );
 
         //返回读取的字节数
         return Ok(num);
@@ -250,10 +265,10 @@ impl IndexNode for LockedPipeInode {
         if mode.contains(FileMode::O_RDWR) {
             return Err(SystemError::EACCES);
         }
-        if mode.contains(FileMode::O_RDONLY) {
+        if mode.accmode() == FileMode::O_RDONLY.bits() {
             guard.reader += 1;
         }
-        if mode.contains(FileMode::O_WRONLY) {
+        if mode.accmode() == FileMode::O_WRONLY.bits() {
             guard.writer += 1;
         }
 
@@ -281,7 +296,7 @@ impl IndexNode for LockedPipeInode {
         let mut guard = self.0.lock();
 
         // 写端关闭
-        if mode.contains(FileMode::O_WRONLY) {
+        if mode.accmode() == FileMode::O_WRONLY.bits() {
             assert!(guard.writer > 0);
             guard.writer -= 1;
             // 如果已经没有写端了，则唤醒读端
@@ -289,18 +304,20 @@ impl IndexNode for LockedPipeInode {
                 guard
                     .read_wait_queue
                     .wakeup_all(Some(ProcessState::Blocked(true)));
+                let _ = EventPoll::wakeup_epoll(&guard.epitems, EPollEventType::EPOLLHUP);
             }
         }
 
         // 读端关闭
-        if mode.contains(FileMode::O_RDONLY) {
+        if mode.accmode() == FileMode::O_RDONLY.bits() {
             assert!(guard.reader > 0);
             guard.reader -= 1;
-            // 如果已经没有写端了，则唤醒读端
+            // 如果已经没有读端了，则唤醒写端
             if guard.reader == 0 {
                 guard
                     .write_wait_queue
                     .wakeup_all(Some(ProcessState::Blocked(true)));
+                let _ = EventPoll::wakeup_epoll(&guard.epitems, EPollEventType::EPOLLERR);
             }
         }
 
@@ -384,9 +401,11 @@ impl IndexNode for LockedPipeInode {
             .read_wait_queue
             .wakeup(Some(ProcessState::Blocked(true)));
 
-        let pollflag = EPollEventType::from_bits_truncate(inode.poll(&data)? as u32);
-        // 唤醒epoll中等待的进程
-        EventPoll::wakeup_epoll(&mut inode.epitems, pollflag)?;
+        // 管道有了新的数据，唤醒在读端的epoll中等待的进程
+        let _ = EventPoll::wakeup_epoll(
+            &inode.epitems,
+            EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM,
+        );
 
         // 返回写入的字节数
         return Ok(len);
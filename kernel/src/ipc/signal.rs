@@ -11,8 +11,11 @@ use crate::{
     process::{pid::PidType, Pid, ProcessControlBlock, ProcessFlags, ProcessManager},
 };
 
-use super::signal_types::{
-    SaHandlerType, SigInfo, SigType, Sigaction, SignalStruct, SIG_KERNEL_STOP_MASK,
+use super::{
+    signal_types::{
+        SaHandlerType, SigHow, SigInfo, SigType, Sigaction, SignalStruct, SIG_KERNEL_STOP_MASK,
+    },
+    signalfd::signalfd_notify,
 };
 
 impl Signal {
@@ -61,6 +64,81 @@ impl Signal {
         return retval;
     }
 
+    /// ## 向指定的进程控制块发送信号
+    ///
+    /// 与`send_signal_info`不同，不需要根据pid查找进程，因此可以在定时器到期回调等上下文中使用
+    ///
+    /// ## 参数
+    ///
+    /// - `info` 要发送的信息
+    /// - `pcb` 接收信号的进程
+    pub fn send_signal_info_to_pcb(
+        &self,
+        info: Option<&mut SigInfo>,
+        pcb: Arc<ProcessControlBlock>,
+    ) -> Result<i32, SystemError> {
+        if !self.is_valid() {
+            return Err(SystemError::EINVAL);
+        }
+        return self.send_signal(info, pcb, PidType::PID);
+    }
+
+    /// ## 由内核向进程组中的每个进程发送信号（对应linux的kill_pgrp）
+    ///
+    /// ## 参数
+    ///
+    /// - `pgid` 进程组id
+    ///
+    /// ## 返回值
+    ///
+    /// 进程组中没有进程时返回ESRCH
+    pub fn send_signal_to_pgrp(&self, pgid: Pid) -> Result<(), SystemError> {
+        if !self.is_valid() {
+            return Err(SystemError::EINVAL);
+        }
+        // 进程组的组长的pid等于pgid
+        let members = ProcessManager::filter(|pcb| pcb.pid() == pgid || pcb.basic().pgid() == pgid);
+        if members.is_empty() {
+            return Err(SystemError::ESRCH);
+        }
+        for pcb in members {
+            let mut info = SigInfo::new(*self, 0, SigCode::Kernel, SigType::Kill(Pid::new(0)));
+            // 组内某个进程无法接收信号时，继续发送给其他进程
+            let _ = self.send_signal(Some(&mut info), pcb, PidType::PID);
+        }
+        return Ok(());
+    }
+
+    /// ## 强制向当前进程发送信号
+    ///
+    /// 用于缺页异常等同步产生的信号。与linux的force_sig_info相同，信号被屏蔽或者被忽略时，
+    /// 把它的处理方式恢复为默认并解除屏蔽，以免进程在产生异常的指令处反复陷入异常
+    ///
+    /// ## 参数
+    ///
+    /// - `info` 要发送的信息
+    pub fn force_send_to_current(&self, info: &mut SigInfo) -> Result<i32, SystemError> {
+        let pcb = ProcessManager::current_pcb();
+        let blocked = pcb
+            .sig_info_irqsave()
+            .sig_block()
+            .contains(self.into_sigset());
+
+        let mut sig_struct = pcb.sig_struct_irqsave();
+        let action = &mut sig_struct.handlers[*self as usize - 1];
+        if blocked || action.is_ignore() {
+            action.set_action(SigactionType::SaHandler(SaHandlerType::SigDefault));
+        }
+        drop(sig_struct);
+
+        if blocked {
+            let mut sig_info = pcb.sig_info_mut();
+            sig_info.sig_block_mut().remove(self.into_sigset());
+            sig_info.recalc_sigpending();
+        }
+        return self.send_signal_info_to_pcb(Some(info), pcb);
+    }
+
     /// @brief 判断是否需要强制发送信号，然后发送信号
     /// 进入函数后加锁
     ///
@@ -103,7 +181,6 @@ impl Signal {
         else if !self.is_rt_signal() && pending.queue().find(self.clone()).0.is_some() {
             return Ok(0);
         } else {
-            // TODO signalfd_notify 完善 signalfd 机制
             // 如果是其他信号，则加入到sigqueue内，然后complete_signal
             let new_sig_info = match info {
                 Some(siginfo) => {
@@ -127,6 +204,9 @@ impl Signal {
                 .q
                 .push(new_sig_info);
 
+            // 唤醒正在通过signalfd等待这个信号的进程
+            signalfd_notify(&pcb, *self);
+
             if pt == PidType::PGID || pt == PidType::SID {}
             self.complete_signal(pcb.clone(), pt);
         }
@@ -153,7 +233,6 @@ impl Signal {
 
         // 判断目标进程是否想接收这个信号
         if self.wants_signal(pcb.clone()) {
-            // todo: 将信号产生的消息通知到正在监听这个信号的进程（引入signalfd之后，在这里调用signalfd_notify)
             // 将这个信号加到目标进程的sig_pending中
             pcb.sig_info_mut()
                 .sig_pending_mut()
@@ -330,11 +409,6 @@ fn signal_wake_up(pcb: Arc<ProcessControlBlock>, _guard: SpinLockGuard<SignalStr
     }
 }
 
-/// @brief 当一个进程具有多个线程之后，在这里需要重新计算线程的flag中的TIF_SIGPENDING位
-fn recalc_sigpending() {
-    // todo:
-}
-
 /// @brief 刷新指定进程的sighand的sigaction，将满足条件的sigaction恢复为Default
 ///     除非某个信号被设置为ignore且force_default为false，否则都不会将其恢复
 ///
@@ -424,14 +498,13 @@ pub(super) fn do_sigaction(
     return Ok(());
 }
 
-/// 设置当前进程的屏蔽信号 (sig_block)，待引入 [sigprocmask](https://man7.org/linux/man-pages/man2/sigprocmask.2.html) 系统调用后要删除这个散装函数
+/// 设置当前进程的屏蔽信号 (sig_block)
 ///
 /// ## 参数
 ///
 /// - `new_set` 新的屏蔽信号bitmap的值
 pub fn set_current_sig_blocked(new_set: &mut SigSet) {
     new_set.remove(SigSet::from(Signal::SIGKILL.into()) | SigSet::from(Signal::SIGSTOP.into()));
-    //TODO 把这个散装函数用 sigsetops 替换掉
     let pcb = ProcessManager::current_pcb();
 
     /*
@@ -445,8 +518,33 @@ pub fn set_current_sig_blocked(new_set: &mut SigSet) {
     let guard = pcb.sig_struct_irqsave();
     // todo: 当一个进程有多个线程后，在这里需要设置每个线程的block字段，并且 retarget_shared_pending（虽然我还没搞明白linux这部分是干啥的）
 
-    // 设置当前进程的sig blocked
-    *pcb.sig_info_mut().sig_block_mut() = *new_set;
-    recalc_sigpending();
+    // 设置当前进程的sig blocked，被解除屏蔽的信号会在这里重新置位
+    let mut siginfo = pcb.sig_info_mut();
+    *siginfo.sig_block_mut() = *new_set;
+    siginfo.recalc_sigpending();
+    drop(siginfo);
     drop(guard);
 }
+
+/// 按照`how`修改当前进程的信号屏蔽字
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/signal.c#3124
+///
+/// ## 参数
+///
+/// - `how` 修改的方式
+/// - `set` 要加入、移除或者设置的信号集合
+///
+/// ## 返回值
+///
+/// 修改之前的信号屏蔽字
+pub fn sigprocmask(how: SigHow, set: &SigSet) -> SigSet {
+    let old_set = *ProcessManager::current_pcb().sig_info_irqsave().sig_block();
+    let mut new_set = match how {
+        SigHow::Block => old_set | *set,
+        SigHow::Unblock => old_set.difference(*set),
+        SigHow::SetMask => *set,
+    };
+    set_current_sig_blocked(&mut new_set);
+    return old_set;
+}
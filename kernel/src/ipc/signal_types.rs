@@ -5,9 +5,10 @@ use core::{
     sync::atomic::AtomicI64,
 };
 
-use alloc::{boxed::Box, vec::Vec};
+use alloc::{boxed::Box, sync::Weak, vec::Vec};
 use system_error::SystemError;
 
+use super::signalfd::SignalFdInode;
 use crate::{
     arch::{
         asm::bitops::ffz,
@@ -15,8 +16,9 @@ use crate::{
         ipc::signal::{SigCode, SigFlags, SigSet, Signal, MAX_SIG_NUM},
     },
     mm::VirtAddr,
-    process::Pid,
+    process::{resource::RLimit64, Pid},
     syscall::user_access::UserBufferWriter,
+    time::{itimer::ProcessITimers, posix_timers::PosixTimerTable},
 };
 
 /// 用户态程序传入的SIG_DFL的值
@@ -57,6 +59,28 @@ pub const SIG_KERNEL_IGNORE_MASK: SigSet = Signal::into_sigset(Signal::SIGCONT)
     .union(Signal::into_sigset(Signal::SIGIO_OR_POLL))
     .union(Signal::into_sigset(Signal::SIGSYS));
 
+/// sigprocmask系统调用的how参数，表示如何修改进程的信号屏蔽字
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigHow {
+    /// 把set中的信号加入屏蔽字
+    Block = 0,
+    /// 把set中的信号从屏蔽字中移除
+    Unblock = 1,
+    /// 把屏蔽字设置为set
+    SetMask = 2,
+}
+
+impl SigHow {
+    pub fn from_i32(how: i32) -> Result<Self, SystemError> {
+        match how {
+            0 => Ok(Self::Block),
+            1 => Ok(Self::Unblock),
+            2 => Ok(Self::SetMask),
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+}
+
 /// SignalStruct 在 pcb 中加锁
 #[derive(Debug)]
 pub struct SignalStruct {
@@ -69,6 +93,14 @@ pub struct InnerSignalStruct {
     /// 如果对应linux，这部分会有一个引用计数，但是没发现在哪里有用到需要计算引用的地方，因此
     /// 暂时删掉，不然这个Arc会导致其他地方的代码十分丑陋
     pub handlers: [Sigaction; MAX_SIG_NUM as usize],
+    /// 进程收到信号时需要通知的signalfd
+    pub signalfds: Vec<Weak<SignalFdInode>>,
+    /// 间隔定时器（setitimer、alarm）
+    pub itimers: ProcessITimers,
+    /// 通过timer_create创建的定时器
+    pub posix_timers: PosixTimerTable,
+    /// core文件大小的资源限制（RLIMIT_CORE），fork时被子进程继承
+    pub core_rlimit: RLimit64,
 }
 
 impl SignalStruct {
@@ -99,6 +131,10 @@ impl Default for InnerSignalStruct {
         Self {
             cnt: Default::default(),
             handlers: [Sigaction::default(); MAX_SIG_NUM as usize],
+            signalfds: Vec::new(),
+            itimers: ProcessITimers::default(),
+            posix_timers: PosixTimerTable::default(),
+            core_rlimit: RLimit64::UNLIMITED,
         }
     }
 }
@@ -301,10 +337,22 @@ pub struct SigInfo {
 }
 
 impl SigInfo {
+    pub fn sig_no(&self) -> i32 {
+        self.sig_no
+    }
+
     pub fn sig_code(&self) -> SigCode {
         self.sig_code
     }
 
+    pub fn errno(&self) -> i32 {
+        self.errno
+    }
+
+    pub fn sig_type(&self) -> SigType {
+        self.sig_type
+    }
+
     pub fn set_sig_type(&mut self, sig_type: SigType) {
         self.sig_type = sig_type;
     }
@@ -333,8 +381,16 @@ impl SigInfo {
 #[derive(Copy, Clone, Debug)]
 pub enum SigType {
     Kill(Pid),
+    /// POSIX定时器到期
+    Timer {
+        /// 定时器的id
+        tid: i32,
+        /// 错过的到期次数
+        overrun: i32,
+        /// 创建定时器时指定的数据
+        sigval: u64,
+    },
     // 后续完善下列中的具体字段
-    // Timer,
     // Rt,
     // SigChild,
     // SigFault,
@@ -389,6 +445,19 @@ impl SigPending {
     pub fn signal_mut(&mut self) -> &mut SigSet {
         &mut self.signal
     }
+
+    /// 重新计算待处理信号的位图
+    ///
+    /// `signal`只记录没有被屏蔽的待处理信号，用于判断进程是否需要处理信号（例如打断睡眠）。
+    /// 被屏蔽的信号只保存在队列中，当它们被解除屏蔽时，通过本函数重新置位
+    ///
+    /// ## 参数
+    ///
+    /// - `blocked` 当前被屏蔽的信号
+    pub fn recalc(&mut self, blocked: &SigSet) {
+        self.signal = (self.signal | self.queue.sigset()).difference(*blocked);
+    }
+
     /// @brief 获取下一个要处理的信号（sig number越小的信号，优先级越高）
     ///
     /// @param pending 等待处理的信号
@@ -397,9 +466,9 @@ impl SigPending {
     pub fn next_signal(&self, sig_mask: &SigSet) -> Signal {
         let mut sig = Signal::INVALID;
 
-        let s = self.signal();
+        // 被屏蔽的信号只保存在队列中，因此需要同时考虑队列中的信号
+        let s = self.signal() | self.queue.sigset();
         let m = *sig_mask;
-        m.is_empty();
         // 获取第一个待处理的信号的号码
         let x = s & (!m);
         if x.bits() != 0 {
@@ -457,8 +526,9 @@ impl SigPending {
     /// @brief 从sigpending中删除mask中被置位的信号。也就是说，比如mask的第1位被置为1,那么就从sigqueue中删除所有signum为2的信号的信息。
     pub fn flush_by_mask(&mut self, mask: &SigSet) {
         // 定义过滤器，从sigqueue中删除mask中被置位的信号
-        let filter = |x: &SigInfo| !mask.contains(SigSet::from_bits_truncate(x.sig_no as u64));
+        let filter = |x: &SigInfo| !mask.contains(Signal::from(x.sig_no).into());
         self.queue.q.retain(filter);
+        self.signal.remove(*mask);
     }
 }
 
@@ -498,6 +568,15 @@ impl SigQueue {
         return (info, still_pending);
     }
 
+    /// 队列中的所有信号组成的集合
+    pub fn sigset(&self) -> SigSet {
+        let mut set = SigSet::empty();
+        for x in self.q.iter() {
+            set.insert(Signal::from(x.sig_no).into());
+        }
+        return set;
+    }
+
     /// @brief 在信号队列中寻找第一个满足要求的siginfo, 并将其从队列中删除，然后返回这个siginfo
     ///
     /// @return (第一个满足要求的siginfo; 从队列中删除前是否有多个满足条件的siginfo)
@@ -0,0 +1,270 @@
+//! signalfd：通过文件描述符读取发送给进程的信号
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/fs/signalfd.c
+
+use core::mem::size_of;
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    anon_inode_common_ops,
+    arch::ipc::signal::{SigSet, Signal},
+    filesystem::vfs::{anon_inode::AnonInodeEpItems, file::FileMode, FilePrivateData, IndexNode},
+    libs::{spinlock::SpinLock, wait_queue::WaitQueue},
+    net::event_poll::{EPollEventType, EventPoll},
+    process::{ProcessControlBlock, ProcessManager},
+};
+
+use super::signal_types::{SigInfo, SigType};
+
+bitflags! {
+    /// signalfd4系统调用的flags参数
+    pub struct SignalFdFlags: u32 {
+        const SFD_CLOEXEC = FileMode::O_CLOEXEC.bits();
+        const SFD_NONBLOCK = FileMode::O_NONBLOCK.bits();
+    }
+}
+
+/// 从signalfd中读出的信号信息，与Linux的`struct signalfd_siginfo`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SignalFdSigInfo {
+    pub ssi_signo: u32,
+    pub ssi_errno: i32,
+    pub ssi_code: i32,
+    pub ssi_pid: u32,
+    pub ssi_uid: u32,
+    pub ssi_fd: i32,
+    pub ssi_tid: u32,
+    pub ssi_band: u32,
+    pub ssi_overrun: u32,
+    pub ssi_trapno: u32,
+    pub ssi_status: i32,
+    pub ssi_int: i32,
+    pub ssi_ptr: u64,
+    pub ssi_utime: u64,
+    pub ssi_stime: u64,
+    pub ssi_addr: u64,
+    pub ssi_addr_lsb: u16,
+    __pad2: u16,
+    pub ssi_syscall: i32,
+    pub ssi_call_addr: u64,
+    pub ssi_arch: u32,
+    __pad: [u8; 28],
+}
+
+impl From<&SigInfo> for SignalFdSigInfo {
+    fn from(info: &SigInfo) -> Self {
+        let mut ret: SignalFdSigInfo = unsafe { core::mem::zeroed() };
+        ret.ssi_signo = info.sig_no() as u32;
+        ret.ssi_errno = info.errno();
+        ret.ssi_code = info.sig_code() as i32;
+        match info.sig_type() {
+            SigType::Kill(pid) => ret.ssi_pid = pid.data() as u32,
+            SigType::Timer {
+                tid,
+                overrun,
+                sigval,
+            } => {
+                ret.ssi_tid = tid as u32;
+                ret.ssi_overrun = overrun as u32;
+                ret.ssi_int = sigval as i32;
+                ret.ssi_ptr = sigval;
+            }
+        }
+        return ret;
+    }
+}
+
+/// signalfd文件的inode
+#[derive(Debug)]
+pub struct SignalFdInode {
+    self_ref: Weak<SignalFdInode>,
+    /// 通过这个signalfd读取的信号
+    mask: SpinLock<SigSet>,
+    /// 在read中等待信号的进程
+    wait_queue: WaitQueue,
+    epitems: AnonInodeEpItems,
+}
+
+impl SignalFdInode {
+    pub fn new(mask: SigSet) -> Arc<Self> {
+        return Arc::new_cyclic(|self_ref| Self {
+            self_ref: self_ref.clone(),
+            mask: SpinLock::new(Self::sanitize_mask(mask)),
+            wait_queue: WaitQueue::INIT,
+            epitems: AnonInodeEpItems::new(),
+        });
+    }
+
+    /// SIGKILL和SIGSTOP不能通过signalfd读取
+    fn sanitize_mask(mut mask: SigSet) -> SigSet {
+        mask.remove(Signal::SIGKILL.into_sigset() | Signal::SIGSTOP.into_sigset());
+        return mask;
+    }
+
+    pub fn mask(&self) -> SigSet {
+        return *self.mask.lock_irqsave();
+    }
+
+    pub fn set_mask(&self, mask: SigSet) {
+        *self.mask.lock_irqsave() = Self::sanitize_mask(mask);
+        // 新的mask中可能已经有待处理的信号
+        self.wakeup();
+    }
+
+    /// 让pcb收到信号时通知这个signalfd
+    ///
+    /// signalfd读取的是调用read的进程的信号，因此在读取和监听signalfd时都要进行注册
+    pub fn register(&self, pcb: &Arc<ProcessControlBlock>) {
+        let mut sig_struct = pcb.sig_struct_irqsave();
+        let signalfds = &mut sig_struct.signalfds;
+        signalfds.retain(|x| x.strong_count() > 0);
+        if !signalfds.iter().any(|x| x.ptr_eq(&self.self_ref)) {
+            signalfds.push(self.self_ref.clone());
+        }
+    }
+
+    /// 当前进程是否有可以通过这个signalfd读取的信号
+    fn poll_events(&self) -> EPollEventType {
+        let pending = ProcessManager::current_pcb()
+            .sig_info_irqsave()
+            .pending_signals();
+        if pending.intersects(self.mask()) {
+            return EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+        }
+        return EPollEventType::empty();
+    }
+
+    fn wakeup(&self) {
+        // 在mask的锁内唤醒，与dequeue中“检查信号并加入等待队列”的过程互斥，避免丢失唤醒
+        let guard = self.mask.lock_irqsave();
+        self.wait_queue.wakeup_all(None);
+        drop(guard);
+        self.epitems
+            .wakeup(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM);
+    }
+
+    /// 从当前进程中取出一个mask中的信号
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(None)` 非阻塞模式下没有可以读取的信号
+    /// - `Err(SystemError::ERESTARTSYS)` 等待过程中被其他信号打断
+    fn dequeue(&self, nonblock: bool) -> Result<Option<SigInfo>, SystemError> {
+        let pcb = ProcessManager::current_pcb();
+        loop {
+            let mask = self.mask.lock_irqsave();
+            let (sig, info) = pcb.sig_info_mut().dequeue_signal(&!*mask);
+            if sig != Signal::INVALID {
+                return Ok(info);
+            }
+            if nonblock {
+                return Ok(None);
+            }
+            if pcb.sig_info_irqsave().sig_pending().has_pending() {
+                return Err(SystemError::ERESTARTSYS);
+            }
+
+            self.wait_queue.sleep_unlock_spinlock(mask);
+        }
+    }
+
+    pub fn epitems(&self) -> &AnonInodeEpItems {
+        return &self.epitems;
+    }
+}
+
+impl IndexNode for SignalFdInode {
+    /// 读取信号，每个信号对应一个`SignalFdSigInfo`结构体
+    fn read_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        let nonblock = match data {
+            FilePrivateData::AnonInode(sfd_data) => sfd_data.nonblock(),
+            _ => return Err(SystemError::EBADF),
+        };
+
+        let count = len / size_of::<SignalFdSigInfo>();
+        if count == 0 || buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+
+        self.register(&ProcessManager::current_pcb());
+
+        let mut nread = 0;
+        while nread < count {
+            // 已经读到信号之后，不再阻塞等待更多的信号
+            let info = match self.dequeue(nonblock || nread > 0) {
+                Ok(Some(info)) => info,
+                Ok(None) if nread == 0 => return Err(SystemError::EAGAIN_OR_EWOULDBLOCK),
+                Err(e) if nread == 0 => return Err(e),
+                _ => break,
+            };
+
+            let ssi = SignalFdSigInfo::from(&info);
+            let offset = nread * size_of::<SignalFdSigInfo>();
+            let bytes = unsafe {
+                core::slice::from_raw_parts(
+                    &ssi as *const SignalFdSigInfo as *const u8,
+                    size_of::<SignalFdSigInfo>(),
+                )
+            };
+            buf[offset..offset + bytes.len()].copy_from_slice(bytes);
+            nread += 1;
+        }
+
+        return Ok(nread * size_of::<SignalFdSigInfo>());
+    }
+
+    fn poll(&self, _private_data: &FilePrivateData) -> Result<usize, SystemError> {
+        return Ok(self.poll_events().bits() as usize);
+    }
+
+    fn ioctl(
+        &self,
+        cmd: u32,
+        arg: usize,
+        _private_data: &FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        match cmd {
+            EventPoll::ADD_EPOLLITEM => {
+                self.epitems.add_from_ioctl(arg)?;
+                self.register(&ProcessManager::current_pcb());
+                return Ok(0);
+            }
+            _ => Err(SystemError::ENOIOCTLCMD),
+        }
+    }
+
+    anon_inode_common_ops!();
+}
+
+/// 进程收到信号时，唤醒在它的signalfd上等待的进程
+///
+/// ## 参数
+///
+/// - `pcb` 收到信号的进程
+/// - `sig` 收到的信号
+pub fn signalfd_notify(pcb: &Arc<ProcessControlBlock>, sig: Signal) {
+    let signalfds: Vec<Arc<SignalFdInode>> = pcb
+        .sig_struct_irqsave()
+        .signalfds
+        .iter()
+        .filter_map(|x| x.upgrade())
+        .collect();
+
+    for sfd in signalfds {
+        if sfd.mask().contains(sig.into_sigset()) {
+            sfd.wakeup();
+        }
+    }
+}
@@ -1,30 +1,55 @@
 use core::{
     ffi::{c_int, c_void},
+    mem::size_of,
     sync::atomic::compiler_fence,
 };
 
 use system_error::SystemError;
 
 use crate::{
-    arch::ipc::signal::{SigCode, SigFlags, SigSet, Signal},
+    arch::{
+        ipc::signal::{SigCode, SigFlags, SigSet, Signal},
+        sched::sched,
+        CurrentIrqArch,
+    },
+    exception::InterruptArch,
     filesystem::vfs::{
+        anon_inode::AnonInodePrivateData,
         file::{File, FileMode},
         FilePrivateData,
     },
     kerror, kwarn,
     mm::VirtAddr,
     process::{Pid, ProcessManager},
-    syscall::{user_access::UserBufferWriter, Syscall},
+    syscall::{
+        user_access::{UserBufferReader, UserBufferWriter},
+        Syscall,
+    },
+    time::{
+        timer::{next_n_us_timer_jiffies, Timer, WakeUpHelper},
+        TimeSpec,
+    },
 };
 
 use super::{
     pipe::{LockedPipeInode, PipeFsPrivateData},
+    signal::sigprocmask,
     signal_types::{
-        SaHandlerType, SigInfo, SigType, Sigaction, SigactionType, UserSigaction, USER_SIG_DFL,
-        USER_SIG_ERR, USER_SIG_IGN,
+        SaHandlerType, SigHow, SigInfo, SigType, Sigaction, SigactionType, UserSigaction,
+        USER_SIG_DFL, USER_SIG_ERR, USER_SIG_IGN,
     },
+    signalfd::{SignalFdFlags, SignalFdInode},
 };
 
+/// 从用户空间读取一个信号集合
+pub fn read_sigset_from_user(set: *const SigSet, sigsetsize: usize) -> Result<SigSet, SystemError> {
+    if sigsetsize != size_of::<SigSet>() {
+        return Err(SystemError::EINVAL);
+    }
+    let reader = UserBufferReader::new(set, size_of::<SigSet>(), true)?;
+    return Ok(*reader.read_one_from_user::<SigSet>(0)?);
+}
+
 impl Syscall {
     /// # 创建带参数的匿名管道
     ///
@@ -229,4 +254,266 @@ impl Syscall {
         }
         return retval.map(|_| 0);
     }
+
+    /// # 检查并修改当前进程的信号屏蔽字
+    ///
+    /// ## 参数
+    ///
+    /// - `how`: 修改的方式（SIG_BLOCK、SIG_UNBLOCK、SIG_SETMASK）
+    /// - `nset`: 用户空间传入的新的信号集合，为空时不修改屏蔽字
+    /// - `oset`: 用于返回原来的屏蔽字，可以为空
+    /// - `sigsetsize`: 信号集合的大小
+    pub fn rt_sigprocmask(
+        how: i32,
+        nset: *const SigSet,
+        oset: *mut SigSet,
+        sigsetsize: usize,
+    ) -> Result<usize, SystemError> {
+        if sigsetsize != size_of::<SigSet>() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let old_set = if nset.is_null() {
+            *ProcessManager::current_pcb().sig_info_irqsave().sig_block()
+        } else {
+            let set = read_sigset_from_user(nset, sigsetsize)?;
+            sigprocmask(SigHow::from_i32(how)?, &set)
+        };
+
+        if !oset.is_null() {
+            let mut writer = UserBufferWriter::new(oset, size_of::<SigSet>(), true)?;
+            writer.copy_one_to_user(&old_set, 0)?;
+        }
+        return Ok(0);
+    }
+
+    /// # 获取被屏蔽且正在等待处理的信号
+    pub fn rt_sigpending(set: *mut SigSet, sigsetsize: usize) -> Result<usize, SystemError> {
+        if sigsetsize > size_of::<SigSet>() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let pending = {
+            let siginfo = ProcessManager::current_pcb().sig_info_irqsave();
+            siginfo.pending_signals() & *siginfo.sig_block()
+        };
+
+        let mut writer = UserBufferWriter::new(set, size_of::<SigSet>(), true)?;
+        writer.copy_one_to_user(&pending, 0)?;
+        return Ok(0);
+    }
+
+    /// # 临时替换信号屏蔽字，并等待信号
+    ///
+    /// 原来的屏蔽字会在信号处理函数返回之后恢复。本系统调用总是返回EINTR
+    pub fn rt_sigsuspend(newset: *const SigSet, sigsetsize: usize) -> Result<usize, SystemError> {
+        let newset = read_sigset_from_user(newset, sigsetsize)?;
+        let pcb = ProcessManager::current_pcb();
+        pcb.sig_info_mut().set_temporary_sig_block(newset);
+
+        loop {
+            let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+            if pcb.sig_info_irqsave().sig_pending().has_pending() {
+                break;
+            }
+            ProcessManager::mark_sleep(true).ok();
+            drop(irq_guard);
+            sched();
+        }
+
+        return Err(SystemError::EINTR);
+    }
+
+    /// # 同步地等待信号集合中的信号
+    ///
+    /// ## 参数
+    ///
+    /// - `uthese`: 要等待的信号集合，这些信号通常已经被屏蔽
+    /// - `uinfo`: 用于返回信号的信息，可以为空
+    /// - `uts`: 等待的超时时间，为空时一直等待
+    /// - `sigsetsize`: 信号集合的大小
+    ///
+    /// ## 返回值
+    ///
+    /// 成功时返回收到的信号。超时返回EAGAIN，被其他信号打断返回EINTR
+    pub fn rt_sigtimedwait(
+        uthese: *const SigSet,
+        uinfo: *mut SigInfo,
+        uts: *const TimeSpec,
+        sigsetsize: usize,
+    ) -> Result<usize, SystemError> {
+        let mut these = read_sigset_from_user(uthese, sigsetsize)?;
+        // SIGKILL和SIGSTOP不能被等待
+        these.remove(Signal::SIGKILL.into_sigset() | Signal::SIGSTOP.into_sigset());
+
+        let timeout = if uts.is_null() {
+            None
+        } else {
+            let reader = UserBufferReader::new(uts, size_of::<TimeSpec>(), true)?;
+            let ts = *reader.read_one_from_user::<TimeSpec>(0)?;
+            if ts.tv_sec < 0 || ts.tv_nsec < 0 || ts.tv_nsec >= 1000000000 {
+                return Err(SystemError::EINVAL);
+            }
+            // 转换为微秒时向上取整，溢出的超时时间视为不合法
+            let us = (ts.tv_sec as u64)
+                .checked_mul(1000000)
+                .and_then(|us| us.checked_add((ts.tv_nsec as u64 + 999) / 1000))
+                .ok_or(SystemError::EINVAL)?;
+            Some(us)
+        };
+
+        let pcb = ProcessManager::current_pcb();
+        let (mut sig, mut info) = pcb.sig_info_mut().dequeue_signal(&!these);
+
+        if sig == Signal::INVALID {
+            let mut timed_out = false;
+            match timeout {
+                Some(0) => timed_out = true,
+                _ => {
+                    let timer = timeout.map(|us| {
+                        Timer::new(WakeUpHelper::new(pcb.clone()), next_n_us_timer_jiffies(us))
+                    });
+
+                    // 等待期间临时解除对these的屏蔽，使得这些信号能够唤醒当前进程。
+                    // 返回用户态之前会恢复原来的屏蔽字，因此这些信号不会被信号处理函数处理
+                    let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+                    let real_blocked = {
+                        let mut siginfo = pcb.sig_info_mut();
+                        let real_blocked = *siginfo.sig_block();
+                        *siginfo.sig_block_mut() = real_blocked.difference(these);
+                        siginfo.recalc_sigpending();
+                        real_blocked
+                    };
+
+                    if pcb.sig_info_irqsave().sig_pending().has_pending() {
+                        drop(irq_guard);
+                    } else {
+                        if let Some(timer) = &timer {
+                            timer.activate();
+                        }
+                        ProcessManager::mark_sleep(true).ok();
+                        drop(irq_guard);
+                        sched();
+                    }
+
+                    let mut siginfo = pcb.sig_info_mut();
+                    *siginfo.sig_block_mut() = real_blocked;
+                    siginfo.recalc_sigpending();
+                    (sig, info) = siginfo.dequeue_signal(&!these);
+                    drop(siginfo);
+
+                    if let Some(timer) = timer {
+                        if timer.timeout() {
+                            timed_out = true;
+                        } else {
+                            timer.cancel();
+                        }
+                    }
+                }
+            }
+
+            if sig == Signal::INVALID {
+                return Err(if timed_out {
+                    SystemError::EAGAIN_OR_EWOULDBLOCK
+                } else {
+                    SystemError::EINTR
+                });
+            }
+        }
+
+        if !uinfo.is_null() {
+            info.unwrap().copy_siginfo_to_user(uinfo)?;
+        }
+        return Ok(sig as usize);
+    }
+
+    /// # 向进程发送带有信息的信号
+    ///
+    /// 只有向自己发送信号时，才能把si_code设置为内核使用的值（大于等于0），以免伪造kill等来源的信号
+    ///
+    /// ## 参数
+    ///
+    /// - `pid`: 目标进程
+    /// - `sig`: 信号
+    /// - `uinfo`: 用户空间传入的siginfo_t，目前只使用其中的si_code
+    pub fn rt_sigqueueinfo(
+        pid: Pid,
+        sig: c_int,
+        uinfo: *const c_void,
+    ) -> Result<usize, SystemError> {
+        // siginfo_t开头的三个字段依次为si_signo、si_errno和si_code
+        let reader = UserBufferReader::new(uinfo as *const i32, 3 * size_of::<i32>(), true)?;
+        let mut header = [0i32; 3];
+        reader.copy_from_user(&mut header, 0)?;
+        let (errno, code) = (header[1], header[2]);
+
+        let current_pid = ProcessManager::current_pcb().pid();
+        if code >= 0 && pid != current_pid {
+            return Err(SystemError::EPERM);
+        }
+
+        // 信号0不会被发送，只用于检查目标进程是否存在
+        if sig == 0 {
+            ProcessManager::find(pid).ok_or(SystemError::ESRCH)?;
+            return Ok(0);
+        }
+
+        let sig = Signal::from(sig);
+        if sig == Signal::INVALID {
+            return Err(SystemError::EINVAL);
+        }
+
+        // 用户态发送的信号统一记为通过sigqueue发送
+        let mut info = SigInfo::new(sig, errno, SigCode::Queue, SigType::Kill(current_pid));
+        return sig
+            .send_signal_info(Some(&mut info), pid)
+            .map(|x| x as usize);
+    }
+
+    /// # 创建或者修改signalfd
+    ///
+    /// ## 参数
+    ///
+    /// - `ufd`: 为-1时创建新的signalfd，否则修改这个signalfd要读取的信号集合
+    /// - `user_mask`: 要通过signalfd读取的信号集合
+    /// - `sizemask`: 信号集合的大小
+    /// - `flags`: SFD_NONBLOCK、SFD_CLOEXEC
+    pub fn signalfd4(
+        ufd: i32,
+        user_mask: *const SigSet,
+        sizemask: usize,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        let flags = SignalFdFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        let mask = read_sigset_from_user(user_mask, sizemask)?;
+
+        let pcb = ProcessManager::current_pcb();
+        if ufd != -1 {
+            let file = pcb
+                .fd_table()
+                .read()
+                .get_file_by_fd(ufd)
+                .ok_or(SystemError::EBADF)?;
+            let inode = file.lock_irqsave().inode();
+            inode
+                .downcast_ref::<SignalFdInode>()
+                .ok_or(SystemError::EINVAL)?
+                .set_mask(mask);
+            return Ok(ufd as usize);
+        }
+
+        let inode = SignalFdInode::new(mask);
+        inode.register(&pcb);
+
+        let mode =
+            FileMode::O_RDWR | (FileMode::from_bits_truncate(flags.bits()) & FileMode::O_NONBLOCK);
+        let mut file = File::new(inode, mode)?;
+        file.private_data = FilePrivateData::AnonInode(AnonInodePrivateData::new(mode));
+        if flags.contains(SignalFdFlags::SFD_CLOEXEC) {
+            file.set_close_on_exec(true);
+        }
+
+        let fd = pcb.fd_table().write().alloc_fd(file, None)?;
+        return Ok(fd as usize);
+    }
 }
@@ -57,6 +57,11 @@ impl CpuMask {
         self.bmp.get(cpu.data() as usize)
     }
 
+    /// 将所有cpu都设置为`value`
+    pub fn set_all(&mut self, value: bool) {
+        self.bmp.set_all(value);
+    }
+
     pub fn is_empty(&self) -> bool {
         self.bmp.is_empty()
     }
@@ -65,8 +70,9 @@ impl CpuMask {
     pub fn iter_cpu(&self) -> CpuMaskIter {
         CpuMaskIter {
             mask: self,
-            index: ProcessorId::new(0),
+            index: None,
             set: true,
+            begin: true,
         }
     }
 
@@ -74,36 +80,40 @@ impl CpuMask {
     pub fn iter_zero_cpu(&self) -> CpuMaskIter {
         CpuMaskIter {
             mask: self,
-            index: ProcessorId::new(0),
+            index: None,
             set: false,
+            begin: true,
         }
     }
 }
 
 pub struct CpuMaskIter<'a> {
     mask: &'a CpuMask,
-    index: ProcessorId,
+    index: Option<ProcessorId>,
     set: bool,
+    begin: bool,
 }
 
 impl<'a> Iterator for CpuMaskIter<'a> {
     type Item = ProcessorId;
 
     fn next(&mut self) -> Option<ProcessorId> {
-        if self.index.data() == 0 {
-            if self.set {
-                self.index = self.mask.first()?;
+        if self.begin {
+            // 第一次迭代时，从第一个符合条件的cpu开始，避免跳过它
+            self.begin = false;
+            self.index = if self.set {
+                self.mask.first()
             } else {
-                self.index = self.mask.first_zero()?;
-            }
-        }
-
-        if self.set {
-            self.index = self.mask.next_index(self.index)?;
-        } else {
-            self.index = self.mask.next_zero_index(self.index)?;
+                self.mask.first_zero()
+            };
+        } else if let Some(index) = self.index {
+            self.index = if self.set {
+                self.mask.next_index(index)
+            } else {
+                self.mask.next_zero_index(index)
+            };
         }
-        Some(self.index)
+        self.index
     }
 }
 
@@ -15,9 +15,12 @@ use elf::{
 use system_error::SystemError;
 
 use crate::{
-    arch::{CurrentElfArch, MMArch},
+    arch::{interrupt::TrapFrame, CurrentElfArch, MMArch},
     driver::base::block::SeekFrom,
-    filesystem::vfs::file::File,
+    filesystem::vfs::{
+        file::{File, FileMode},
+        MAX_PATHLEN, ROOT_INODE, VFS_MAX_FOLLOW_SYMLINK_TIMES,
+    },
     kerror,
     libs::align::page_align_up,
     mm::{
@@ -40,6 +43,11 @@ use super::rwlock::RwLockWriteGuard;
 pub trait ElfArch: Clone + Copy + Debug {
     const ELF_ET_DYN_BASE: usize;
     const ELF_PAGE_SIZE: usize;
+    /// ELF文件头中的e_machine字段
+    const ELF_MACHINE: u16;
+
+    /// 把中断栈帧中的用户态寄存器按照`elf_gregset_t`的顺序导出，用于core dump的NT_PRSTATUS
+    fn core_copy_regs(frame: &TrapFrame) -> Vec<usize>;
 }
 
 #[derive(Debug)]
@@ -131,6 +139,7 @@ impl ElfLoader {
                 prot_flags,
                 MapFlags::MAP_ANONYMOUS | MapFlags::MAP_FIXED_NOREPLACE,
                 false,
+                true,
             );
             if r.is_err() {
                 kerror!("set_elf_brk: map_anonymous failed, err={:?}", r);
@@ -184,7 +193,7 @@ impl ElfLoader {
     /// ## 参数
     ///
     /// - `user_vm_guard`：用户空间地址空间
-    /// - `param`：执行参数
+    /// - `file`：要加载的ELF文件
     /// - `phent`：ELF文件的ProgramHeader
     /// - `addr_to_map`：当前段应该被加载到的内存地址
     /// - `prot`：保护标志
@@ -197,7 +206,7 @@ impl ElfLoader {
     fn load_elf_segment(
         &self,
         user_vm_guard: &mut RwLockWriteGuard<'_, InnerAddressSpace>,
-        param: &mut ExecParam,
+        file: &mut File,
         phent: &ProgramHeader,
         mut addr_to_map: VirtAddr,
         prot: &ProtFlags,
@@ -255,7 +264,7 @@ impl ElfLoader {
             // kdebug!("total_size={}", total_size);
 
             map_addr = user_vm_guard
-                .map_anonymous(addr_to_map, total_size, tmp_prot, *map_flags, false)
+                .map_anonymous(addr_to_map, total_size, tmp_prot, *map_flags, false, true)
                 .map_err(map_err_handler)?
                 .virt_address();
             // kdebug!("map ok: addr_to_map={:?}", addr_to_map);
@@ -274,7 +283,7 @@ impl ElfLoader {
                 map_addr + beginning_page_offset,
                 seg_in_file_size,
                 file_offset,
-                param,
+                file,
             )?;
             if tmp_prot != *prot {
                 user_vm_guard.mprotect(
@@ -287,7 +296,7 @@ impl ElfLoader {
             // kdebug!("total size = 0");
 
             map_addr = user_vm_guard
-                .map_anonymous(addr_to_map, map_size, tmp_prot, *map_flags, false)?
+                .map_anonymous(addr_to_map, map_size, tmp_prot, *map_flags, false, true)?
                 .virt_address();
             // kdebug!(
             //     "map ok: addr_to_map={:?}, map_addr={map_addr:?},beginning_page_offset={beginning_page_offset:?}",
@@ -299,7 +308,7 @@ impl ElfLoader {
                 map_addr + beginning_page_offset,
                 seg_in_file_size,
                 file_offset,
-                param,
+                file,
             )?;
 
             if tmp_prot != *prot {
@@ -321,15 +330,14 @@ impl ElfLoader {
     /// - `vaddr`：要加载到的虚拟地址
     /// - `size`：要加载的大小
     /// - `offset_in_file`：在文件内的偏移量
-    /// - `param`：执行参数
+    /// - `file`：要加载的ELF文件
     fn do_load_file(
         &self,
         mut vaddr: VirtAddr,
         size: usize,
         offset_in_file: usize,
-        param: &mut ExecParam,
+        file: &mut File,
     ) -> Result<(), SystemError> {
-        let file = param.file_mut();
         if (file.metadata()?.size as usize) < offset_in_file + size {
             return Err(SystemError::ENOEXEC);
         }
@@ -372,14 +380,17 @@ impl ElfLoader {
     /// - `entrypoint_vaddr`：程序入口地址
     /// - `phdr_vaddr`：程序头表地址
     /// - `elf_header`：ELF文件头
+    /// - `interp_base`：动态链接器的加载地址，如果没有动态链接器，则为None
     fn create_auxv(
         &self,
         param: &mut ExecParam,
         entrypoint_vaddr: VirtAddr,
         phdr_vaddr: Option<VirtAddr>,
         ehdr: &elf::file::FileHeader<AnyEndian>,
+        interp_base: Option<VirtAddr>,
     ) -> Result<(), ExecError> {
         let phdr_vaddr = phdr_vaddr.unwrap_or(VirtAddr::new(0));
+        let interp_base = interp_base.unwrap_or(VirtAddr::new(0));
 
         let init_info = param.init_info_mut();
         init_info
@@ -395,6 +406,9 @@ impl ElfLoader {
         init_info
             .auxv
             .insert(AtType::Entry as u8, entrypoint_vaddr.data());
+        init_info
+            .auxv
+            .insert(AtType::Base as u8, interp_base.data());
 
         return Ok(());
     }
@@ -419,7 +433,7 @@ impl ElfLoader {
     ///
     /// ## 参数
     ///
-    /// - `param`：执行参数
+    /// - `file`：ELF文件
     /// - `ehdr`：文件头
     /// - `data_buf`：用于缓存SegmentTable的Vec。
     ///     这是因为SegmentTable的生命周期与data_buf一致。初始化这个Vec的大小为0即可。
@@ -428,7 +442,7 @@ impl ElfLoader {
     ///
     /// 这个函数由elf库的`elf::elf_bytes::find_phdrs`修改而来。
     fn parse_segments<'a>(
-        param: &mut ExecParam,
+        file: &mut File,
         ehdr: &FileHeader<AnyEndian>,
         data_buf: &'a mut Vec<u8>,
     ) -> Result<Option<elf::segment::SegmentTable<'a, AnyEndian>>, elf::ParseError> {
@@ -436,7 +450,6 @@ impl ElfLoader {
         if ehdr.e_phoff == 0 {
             return Ok(None);
         }
-        let file = param.file_mut();
         // If the number of segments is greater than or equal to PN_XNUM (0xffff),
         // e_phnum is set to PN_XNUM, and the actual number of program header table
         // entries is contained in the sh_info field of the section header at index 0.
@@ -498,6 +511,221 @@ impl ElfLoader {
     fn parse_gnu_property() -> Result<(), ExecError> {
         return Ok(());
     }
+
+    /// 计算所有PT_LOAD段加载到内存后占用的总大小
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/fs/binfmt_elf.c#399
+    fn total_mapping_size(&self, phdr_table: elf::segment::SegmentTable<'_, AnyEndian>) -> usize {
+        let mut has_load = false;
+        let mut min_address = VirtAddr::new(usize::MAX);
+        let mut max_address = VirtAddr::new(0usize);
+        let loadable_sections = phdr_table
+            .into_iter()
+            .filter(|seg| seg.p_type == elf::abi::PT_LOAD);
+        for seg_to_load in loadable_sections {
+            min_address = min(
+                min_address,
+                self.elf_page_start(VirtAddr::new(seg_to_load.p_vaddr.try_into().unwrap())),
+            );
+            max_address = max(
+                max_address,
+                VirtAddr::new(
+                    (seg_to_load.p_vaddr + seg_to_load.p_memsz)
+                        .try_into()
+                        .unwrap(),
+                ),
+            );
+            has_load = true;
+        }
+        if has_load {
+            max_address - min_address
+        } else {
+            0
+        }
+    }
+
+    /// 打开PT_INTERP段指定的动态链接器，并读取它的文件头
+    ///
+    /// ## 参数
+    ///
+    /// - `file`：要执行的ELF文件
+    /// - `seg`：PT_INTERP段
+    /// - `ehdr`：要执行的ELF文件的文件头
+    ///
+    /// ## 返回值
+    ///
+    /// 动态链接器的文件，以及它的文件头
+    fn open_interpreter(
+        &self,
+        file: &mut File,
+        seg: &ProgramHeader,
+        ehdr: &FileHeader<AnyEndian>,
+    ) -> Result<(File, FileHeader<AnyEndian>), ExecError> {
+        // PT_INTERP段的内容是动态链接器的路径，以'\0'结尾
+        // 在分配缓冲区之前检查长度，避免构造的ELF文件申请任意大小的内存
+        if seg.p_filesz < 2 || seg.p_filesz > MAX_PATHLEN as u64 {
+            return Err(ExecError::NotExecutable);
+        }
+        let path_len = seg.p_filesz as usize;
+        let mut path_buf = vec![0u8; path_len];
+        file.lseek(SeekFrom::SeekSet(seg.p_offset as i64))
+            .map_err(|_| ExecError::ParseError)?;
+        let len = file
+            .read(path_len, &mut path_buf)
+            .map_err(|_| ExecError::ParseError)?;
+        if len != path_len || path_buf[path_len - 1] != 0 {
+            return Err(ExecError::NotExecutable);
+        }
+
+        let interpreter_path = core::str::from_utf8(&path_buf[..path_len - 1]).map_err(|e| {
+            ExecError::Other(format!(
+                "Failed to parse the path of dynamic linker with error {}",
+                e
+            ))
+        })?;
+
+        let inode = ROOT_INODE()
+            .lookup_follow_symlink(interpreter_path, VFS_MAX_FOLLOW_SYMLINK_TIMES)
+            .map_err(|e| {
+                ExecError::Other(format!(
+                    "Failed to find dynamic linker {}, error: {:?}",
+                    interpreter_path, e
+                ))
+            })?;
+        let mut interp_file = File::new(inode, FileMode::O_RDONLY).map_err(|e| {
+            ExecError::Other(format!(
+                "Failed to open dynamic linker {}, error: {:?}",
+                interpreter_path, e
+            ))
+        })?;
+
+        let mut head_buf = [0u8; 512];
+        interp_file
+            .lseek(SeekFrom::SeekSet(0))
+            .map_err(|_| ExecError::ParseError)?;
+        let len = interp_file
+            .read(head_buf.len(), &mut head_buf)
+            .map_err(|_| ExecError::ParseError)?;
+        let interp_ehdr =
+            Self::parse_ehdr(&head_buf[..len]).map_err(|_| ExecError::NotExecutable)?;
+
+        // 对动态链接器做一些简单的一致性检查
+        // 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/fs/binfmt_elf.c#950
+        if interp_ehdr.class != ehdr.class || interp_ehdr.e_machine != ehdr.e_machine {
+            return Err(ExecError::WrongArchitecture);
+        }
+        let interp_type = ElfType::from(interp_ehdr.e_type);
+        if interp_type != ElfType::Executable && interp_type != ElfType::DSO {
+            return Err(ExecError::NotExecutable);
+        }
+
+        return Ok((interp_file, interp_ehdr));
+    }
+
+    /// 把动态链接器加载到用户空间
+    ///
+    /// 参考Linux的load_elf_interp函数
+    /// https://code.dragonos.org.cn/xref/linux-6.1.9/fs/binfmt_elf.c#597
+    ///
+    /// ## 参数
+    ///
+    /// - `user_vm_guard`：用户空间地址空间
+    /// - `interp_file`：动态链接器的文件
+    /// - `interp_ehdr`：动态链接器的文件头
+    ///
+    /// ## 返回值
+    ///
+    /// 动态链接器的加载偏移量（也就是AT_BASE）
+    fn load_elf_interp(
+        &self,
+        user_vm_guard: &mut RwLockWriteGuard<'_, InnerAddressSpace>,
+        interp_file: &mut File,
+        interp_ehdr: &FileHeader<AnyEndian>,
+    ) -> Result<VirtAddr, ExecError> {
+        let mut phdr_buf = Vec::new();
+        let phdr_table = Self::parse_segments(interp_file, interp_ehdr, &mut phdr_buf)
+            .map_err(|_| ExecError::ParseError)?
+            .ok_or(ExecError::ParseError)?;
+
+        let total_size = self.total_mapping_size(phdr_table);
+        if total_size == 0 {
+            return Err(ExecError::InvalidParemeter);
+        }
+
+        let is_dyn = ElfType::from(interp_ehdr.e_type) == ElfType::DSO;
+        let mut load_addr = 0usize;
+        let mut first_pt_load = true;
+
+        let loadable_sections = phdr_table
+            .into_iter()
+            .filter(|seg| seg.p_type == elf::abi::PT_LOAD);
+        for seg_to_load in loadable_sections {
+            let elf_prot_flags = self.make_prot(seg_to_load.p_flags, true, true);
+            let vaddr = VirtAddr::new(seg_to_load.p_vaddr as usize);
+
+            // 位置无关的动态链接器的第一个段由mmap决定加载地址，后面的段必须紧随其后
+            let mut elf_map_flags = MapFlags::MAP_PRIVATE;
+            if !is_dyn || !first_pt_load {
+                elf_map_flags.insert(MapFlags::MAP_FIXED_NOREPLACE);
+            }
+
+            let (map_addr, ok) = self
+                .load_elf_segment(
+                    user_vm_guard,
+                    interp_file,
+                    &seg_to_load,
+                    vaddr + load_addr,
+                    &elf_prot_flags,
+                    &elf_map_flags,
+                    if first_pt_load { total_size } else { 0 },
+                )
+                .map_err(|e| match e {
+                    SystemError::EFAULT => ExecError::BadAddress(None),
+                    SystemError::ENOMEM => ExecError::OutOfMemory,
+                    _ => ExecError::Other(format!("load_elf_interp failed: {:?}", e)),
+                })?;
+            if !ok {
+                return Err(ExecError::BadAddress(Some(map_addr)));
+            }
+
+            if first_pt_load {
+                first_pt_load = false;
+                if is_dyn {
+                    load_addr = map_addr.data() - self.elf_page_start(vaddr).data();
+                }
+            }
+
+            // 检查段的地址是否合法
+            let seg_start = vaddr + load_addr;
+            let seg_end = seg_start + seg_to_load.p_memsz as usize;
+            if !seg_start.check_user()
+                || seg_to_load.p_filesz > seg_to_load.p_memsz
+                || self.elf_page_align_up(seg_end) >= MMArch::USER_END_VADDR
+            {
+                return Err(ExecError::InvalidParemeter);
+            }
+
+            // 映射.bss中超出文件内容所在页面的部分。文件内容所在的最后一页中剩余的部分已经被清零
+            let file_end = self.elf_page_align_up(seg_start + seg_to_load.p_filesz as usize);
+            let mem_end = self.elf_page_align_up(seg_end);
+            if mem_end > file_end {
+                user_vm_guard
+                    .map_anonymous(
+                        file_end,
+                        mem_end - file_end,
+                        elf_prot_flags,
+                        MapFlags::MAP_PRIVATE
+                            | MapFlags::MAP_ANONYMOUS
+                            | MapFlags::MAP_FIXED_NOREPLACE,
+                        false,
+                        true,
+                    )
+                    .map_err(|_| ExecError::OutOfMemory)?;
+            }
+        }
+
+        return Ok(VirtAddr::new(load_addr));
+    }
 }
 
 impl BinaryLoader for ElfLoader {
@@ -539,11 +767,11 @@ impl BinaryLoader for ElfLoader {
         // kdebug!("to parse segments");
         // 加载ELF文件并映射到用户空间
         let mut phdr_buf = Vec::new();
-        let phdr_table = Self::parse_segments(param, &ehdr, &mut phdr_buf)
+        let phdr_table = Self::parse_segments(param.file_mut(), &ehdr, &mut phdr_buf)
             .map_err(|_| ExecError::ParseError)?
             .ok_or(ExecError::ParseError)?;
         let mut _gnu_property_data: Option<ProgramHeader> = None;
-        let interpreter: Option<File> = None;
+        let mut interpreter: Option<(File, FileHeader<AnyEndian>)> = None;
         for seg in phdr_table {
             if seg.p_type == PT_GNU_PROPERTY {
                 _gnu_property_data = Some(seg.clone());
@@ -559,24 +787,7 @@ impl BinaryLoader for ElfLoader {
                 return Err(ExecError::NotExecutable);
             }
 
-            let interpreter_ptr = unsafe {
-                core::slice::from_raw_parts(
-                    seg.p_offset as *const u8,
-                    seg.p_filesz.try_into().unwrap(),
-                )
-            };
-            let _interpreter_path = core::str::from_utf8(interpreter_ptr).map_err(|e| {
-                ExecError::Other(format!(
-                    "Failed to parse the path of dynamic linker with error {}",
-                    e
-                ))
-            })?;
-
-            //TODO 加入对动态链接器的加载，参照 https://code.dragonos.org.cn/xref/linux-6.1.9/fs/binfmt_elf.c#890
-        }
-        if interpreter.is_some() {
-            /* Some simple consistency checks for the interpreter */
-            // 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/fs/binfmt_elf.c#950
+            interpreter = Some(self.open_interpreter(param.file_mut(), &seg, &ehdr)?);
         }
         Self::parse_gnu_property()?;
 
@@ -598,32 +809,7 @@ impl BinaryLoader for ElfLoader {
         let mut phdr_vaddr: Option<VirtAddr> = None;
         let mut _reloc_func_desc = 0usize;
         // 参考https://code.dragonos.org.cn/xref/linux-6.1.9/fs/binfmt_elf.c#1158，获取要加载的total_size
-        let mut has_load = false;
-        let mut min_address = VirtAddr::new(usize::MAX);
-        let mut max_address = VirtAddr::new(0usize);
-        let loadable_sections = phdr_table
-            .into_iter()
-            .filter(|seg| seg.p_type == elf::abi::PT_LOAD);
-        for seg_to_load in loadable_sections {
-            min_address = min(
-                min_address,
-                self.elf_page_start(VirtAddr::new(seg_to_load.p_vaddr.try_into().unwrap())),
-            );
-            max_address = max(
-                max_address,
-                VirtAddr::new(
-                    (seg_to_load.p_vaddr + seg_to_load.p_memsz)
-                        .try_into()
-                        .unwrap(),
-                ),
-            );
-            has_load = true;
-        }
-        let total_size = if has_load {
-            max_address - min_address
-        } else {
-            0
-        };
+        let total_size = self.total_mapping_size(phdr_table);
         let loadable_sections = phdr_table
             .into_iter()
             .filter(|seg| seg.p_type == elf::abi::PT_LOAD);
@@ -669,17 +855,17 @@ impl BinaryLoader for ElfLoader {
                  */
                 elf_map_flags.insert(MapFlags::MAP_FIXED_NOREPLACE);
             } else if elf_type == ElfType::DSO {
-                // TODO: 支持动态链接
                 if interpreter.is_some() {
+                    // 需要动态链接器的位置无关可执行文件，被加载到ELF_ET_DYN_BASE，
+                    // 从而给动态链接器（它由mmap决定加载地址）让出低地址的空间
                     load_bias = CurrentElfArch::ELF_ET_DYN_BASE;
                     if ProcessManager::current_pcb()
                         .flags()
                         .contains(ProcessFlags::RANDOMIZE)
                     {
                         //这里x86下需要一个随机加载的方法，但是很多架构，比如Risc-V都是0，就暂时不写了
-                    } else {
-                        load_bias = 0;
                     }
+                    elf_map_flags.insert(MapFlags::MAP_FIXED_NOREPLACE);
                 }
                 load_bias = self
                     .elf_page_start(VirtAddr::new(
@@ -696,7 +882,7 @@ impl BinaryLoader for ElfLoader {
             let e = self
                 .load_elf_segment(
                     &mut user_vm,
-                    param,
+                    param.file_mut(),
                     &seg_to_load,
                     vaddr + load_bias,
                     &elf_prot_flags,
@@ -821,13 +1007,19 @@ impl BinaryLoader for ElfLoader {
             // kdebug!("elf_bss = {elf_bss:?}, elf_brk = {elf_brk:?}");
             return Err(ExecError::BadAddress(Some(elf_bss)));
         }
-        if interpreter.is_some() {
-            // TODO 添加对动态加载器的处理
-            // 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/fs/binfmt_elf.c#1249
+        // 有动态链接器时，从动态链接器的入口开始执行，由它来加载程序依赖的动态库并跳转到程序的入口
+        // 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/fs/binfmt_elf.c#1249
+        let mut entrypoint = program_entrypoint;
+        let mut interp_base = None;
+        if let Some((mut interp_file, interp_ehdr)) = interpreter {
+            let interp_load_addr =
+                self.load_elf_interp(&mut user_vm, &mut interp_file, &interp_ehdr)?;
+            entrypoint = VirtAddr::new(interp_ehdr.e_entry as usize) + interp_load_addr.data();
+            interp_base = Some(interp_load_addr);
         }
         // kdebug!("to create auxv");
 
-        self.create_auxv(param, program_entrypoint, phdr_vaddr, &ehdr)?;
+        self.create_auxv(param, program_entrypoint, phdr_vaddr, &ehdr, interp_base)?;
 
         // kdebug!("auxv create ok");
         user_vm.start_code = start_code.unwrap_or(VirtAddr::new(0));
@@ -835,7 +1027,7 @@ impl BinaryLoader for ElfLoader {
         user_vm.start_data = start_data.unwrap_or(VirtAddr::new(0));
         user_vm.end_data = end_data.unwrap_or(VirtAddr::new(0));
 
-        let result = BinaryLoaderResult::new(program_entrypoint);
+        let result = BinaryLoaderResult::new(entrypoint);
         // kdebug!("elf load OK!!!");
         return Ok(result);
     }
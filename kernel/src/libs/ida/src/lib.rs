@@ -1,51 +1,232 @@
 #![no_std]
-#![feature(core_intrinsics)]
 #![allow(clippy::needless_return)]
 
-use core::intrinsics::unlikely;
-use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+
+/// 每个位图块管理的id数量
+const IDA_BITMAP_BITS: usize = 1024;
+/// 每个位图块由多少个u64组成
+const IDA_BITMAP_LONGS: usize = IDA_BITMAP_BITS / u64::BITS as usize;
 
 /// id分配器
 ///
-/// TODO: 当前只是为了简单实现功能，将来这里应使用类似linux的ida的方式去实现
+/// 参考linux的ida，把id空间按`IDA_BITMAP_BITS`划分为若干个块，每个块用一个位图记录其中的id是否已被分配。
+/// 块按需创建，块中的id全部被释放后，块也会被回收，因此即使id的范围很大，也只会占用与已分配id数量相当的内存。
+///
+/// 分配器本身不带锁，多个使用者共享同一个分配器时，需要由调用者加锁。
 #[derive(Debug)]
 pub struct IdAllocator {
-    current_id: AtomicUsize,
+    /// 块号到位图块的映射
+    bitmaps: BTreeMap<usize, IdaBitmap>,
+    /// 可分配的最小id
+    min_id: usize,
+    /// 可分配的最大id（不包含）
     max_id: usize,
-    dead: AtomicBool,
+    /// 循环分配时，下一次开始查找的id
+    next_id: usize,
+    /// 已分配的id数量
+    used: usize,
 }
 
 impl IdAllocator {
     /// 创建一个新的id分配器
-    pub const fn new(initial_id: usize, max_id: usize) -> Self {
+    ///
+    /// ## 参数
+    ///
+    /// - `min_id`：可分配的最小id
+    /// - `max_id`：可分配的最大id（不包含），必须大于`min_id`
+    pub const fn new(min_id: usize, max_id: usize) -> Self {
+        assert!(min_id < max_id);
         Self {
-            current_id: AtomicUsize::new(initial_id),
+            bitmaps: BTreeMap::new(),
+            min_id,
             max_id,
-            dead: AtomicBool::new(false),
+            next_id: min_id,
+            used: 0,
         }
     }
 
     /// 分配一个新的id
     ///
+    /// 总是分配当前可用的最小id
+    ///
     /// ## 返回
     ///
     /// 如果分配成功，返回Some(id)，否则返回None
-    pub fn alloc(&self) -> Option<usize> {
-        if unlikely(self.dead.load(Ordering::SeqCst)) {
-            return None;
+    pub fn alloc(&mut self) -> Option<usize> {
+        return self.alloc_range(self.min_id, self.max_id);
+    }
+
+    /// 在[min, max)中分配一个id
+    ///
+    /// 范围会被限制在分配器的范围之内，总是分配范围内可用的最小id
+    ///
+    /// ## 返回
+    ///
+    /// 如果分配成功，返回Some(id)，否则返回None
+    pub fn alloc_range(&mut self, min: usize, max: usize) -> Option<usize> {
+        let min = min.max(self.min_id);
+        let max = max.min(self.max_id);
+        let id = self.find_free(min, max)?;
+        self.mark_used(id);
+        return Some(id);
+    }
+
+    /// 循环分配一个id
+    ///
+    /// 从上一次循环分配到的id之后开始查找，到达最大id后再从最小id开始查找。
+    /// 这样刚被释放的id不会马上被再次分配（例如pid）
+    ///
+    /// ## 返回
+    ///
+    /// 如果分配成功，返回Some(id)，否则返回None
+    pub fn alloc_cyclic(&mut self) -> Option<usize> {
+        let id = self
+            .find_free(self.next_id, self.max_id)
+            .or_else(|| self.find_free(self.min_id, self.next_id))?;
+        self.mark_used(id);
+        self.next_id = if id + 1 >= self.max_id {
+            self.min_id
+        } else {
+            id + 1
+        };
+        return Some(id);
+    }
+
+    /// 释放一个id
+    ///
+    /// 释放未分配的id或者超出范围的id不会有任何效果
+    pub fn free(&mut self, id: usize) {
+        if id < self.min_id || id >= self.max_id {
+            return;
         }
 
-        let ret = self.current_id.fetch_add(1, Ordering::SeqCst);
-        // 如果id溢出，panic
-        if ret == self.max_id {
-            self.dead.store(true, Ordering::SeqCst);
-            return None;
+        let block = id / IDA_BITMAP_BITS;
+        let bitmap = match self.bitmaps.get_mut(&block) {
+            Some(bitmap) => bitmap,
+            None => return,
+        };
+
+        if !bitmap.clear(id % IDA_BITMAP_BITS) {
+            return;
+        }
+        self.used -= 1;
+
+        // 块中的id已经全部被释放，回收这个块
+        if bitmap.count == 0 {
+            self.bitmaps.remove(&block);
+        }
+    }
+
+    /// 判断id是否已经被分配
+    pub fn exists(&self, id: usize) -> bool {
+        if id < self.min_id || id >= self.max_id {
+            return false;
+        }
+
+        return self
+            .bitmaps
+            .get(&(id / IDA_BITMAP_BITS))
+            .map(|bitmap| bitmap.test(id % IDA_BITMAP_BITS))
+            .unwrap_or(false);
+    }
+
+    /// 已分配的id数量
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// 在[start, end)中查找第一个未被分配的id
+    fn find_free(&self, start: usize, end: usize) -> Option<usize> {
+        let mut id = start;
+        while id < end {
+            let block = id / IDA_BITMAP_BITS;
+            let found = match self.bitmaps.get(&block) {
+                // 块不存在，说明块中的id都没有被分配
+                None => id,
+                Some(bitmap) => match bitmap.next_free(id % IDA_BITMAP_BITS) {
+                    Some(bit) => block * IDA_BITMAP_BITS + bit,
+                    None => {
+                        // 块已满，从下一个块的开头继续查找
+                        id = block.checked_add(1)?.checked_mul(IDA_BITMAP_BITS)?;
+                        continue;
+                    }
+                },
+            };
+
+            return if found < end { Some(found) } else { None };
+        }
+
+        return None;
+    }
+
+    fn mark_used(&mut self, id: usize) {
+        self.bitmaps
+            .entry(id / IDA_BITMAP_BITS)
+            .or_insert_with(IdaBitmap::new)
+            .set(id % IDA_BITMAP_BITS);
+        self.used += 1;
+    }
+}
+
+/// 记录一个块中的id是否已被分配的位图
+#[derive(Debug)]
+struct IdaBitmap {
+    map: [u64; IDA_BITMAP_LONGS],
+    /// 已分配的id数量
+    count: usize,
+}
+
+impl IdaBitmap {
+    const fn new() -> Self {
+        Self {
+            map: [0; IDA_BITMAP_LONGS],
+            count: 0,
         }
+    }
+
+    #[inline]
+    fn test(&self, bit: usize) -> bool {
+        self.map[bit / 64] & (1 << (bit % 64)) != 0
+    }
 
-        return Some(ret);
+    #[inline]
+    fn set(&mut self, bit: usize) {
+        debug_assert!(!self.test(bit));
+        self.map[bit / 64] |= 1 << (bit % 64);
+        self.count += 1;
     }
 
-    pub fn free(&self, _id: usize) {
-        // todo: free
+    /// 清除一个位，返回这个位原本是否被设置
+    #[inline]
+    fn clear(&mut self, bit: usize) -> bool {
+        if !self.test(bit) {
+            return false;
+        }
+        self.map[bit / 64] &= !(1 << (bit % 64));
+        self.count -= 1;
+        return true;
+    }
+
+    /// 从bit开始（包含bit）查找第一个未被设置的位
+    fn next_free(&self, bit: usize) -> Option<usize> {
+        if self.count == IDA_BITMAP_BITS {
+            return None;
+        }
+
+        let mut word = bit / 64;
+        let mut mask = !0u64 << (bit % 64);
+        while word < IDA_BITMAP_LONGS {
+            let free = !self.map[word] & mask;
+            if free != 0 {
+                return Some(word * 64 + free.trailing_zeros() as usize);
+            }
+            word += 1;
+            mask = !0;
+        }
+
+        return None;
     }
 }
@@ -0,0 +1,130 @@
+//! id分配器的集成测试
+
+use ida::IdAllocator;
+
+/// 测试按顺序分配id
+#[test]
+fn test_alloc_sequential() {
+    let mut ida = IdAllocator::new(0, 100);
+    for i in 0..100 {
+        assert_eq!(ida.alloc(), Some(i));
+    }
+    assert_eq!(ida.alloc(), None);
+    assert_eq!(ida.used(), 100);
+}
+
+/// 测试id的起始值
+#[test]
+fn test_alloc_min_id() {
+    let mut ida = IdAllocator::new(1, 4);
+    assert_eq!(ida.alloc(), Some(1));
+    assert_eq!(ida.alloc(), Some(2));
+    assert_eq!(ida.alloc(), Some(3));
+    assert_eq!(ida.alloc(), None);
+    assert!(!ida.exists(0));
+}
+
+/// 测试释放后的id能被再次分配
+#[test]
+fn test_free_and_reuse() {
+    let mut ida = IdAllocator::new(0, 10);
+    for _ in 0..10 {
+        ida.alloc().unwrap();
+    }
+    assert_eq!(ida.alloc(), None);
+
+    ida.free(3);
+    ida.free(7);
+    assert!(!ida.exists(3));
+    assert!(!ida.exists(7));
+    assert_eq!(ida.used(), 8);
+
+    // 总是分配最小的可用id
+    assert_eq!(ida.alloc(), Some(3));
+    assert_eq!(ida.alloc(), Some(7));
+    assert_eq!(ida.alloc(), None);
+}
+
+/// 测试重复释放和释放超出范围的id
+#[test]
+fn test_free_invalid() {
+    let mut ida = IdAllocator::new(5, 10);
+    assert_eq!(ida.alloc(), Some(5));
+    ida.free(5);
+    ida.free(5);
+    ida.free(0);
+    ida.free(100);
+    assert_eq!(ida.used(), 0);
+    assert_eq!(ida.alloc(), Some(5));
+}
+
+/// 测试在指定范围内分配id
+#[test]
+fn test_alloc_range() {
+    let mut ida = IdAllocator::new(0, 100);
+    assert_eq!(ida.alloc_range(10, 12), Some(10));
+    assert_eq!(ida.alloc_range(10, 12), Some(11));
+    assert_eq!(ida.alloc_range(10, 12), None);
+    // 范围被限制在分配器的范围之内
+    assert_eq!(ida.alloc_range(95, 1000), Some(95));
+    assert_eq!(ida.alloc_range(100, 1000), None);
+    assert_eq!(ida.alloc(), Some(0));
+}
+
+/// 测试循环分配
+#[test]
+fn test_alloc_cyclic() {
+    let mut ida = IdAllocator::new(1, 5);
+    assert_eq!(ida.alloc_cyclic(), Some(1));
+    assert_eq!(ida.alloc_cyclic(), Some(2));
+    ida.free(1);
+    // 刚被释放的id不会马上被再次分配
+    assert_eq!(ida.alloc_cyclic(), Some(3));
+    assert_eq!(ida.alloc_cyclic(), Some(4));
+    // 到达最大id后回绕
+    assert_eq!(ida.alloc_cyclic(), Some(1));
+    assert_eq!(ida.alloc_cyclic(), None);
+    ida.free(3);
+    assert_eq!(ida.alloc_cyclic(), Some(3));
+}
+
+/// 测试跨越多个位图块的分配与释放
+#[test]
+fn test_multiple_blocks() {
+    let mut ida = IdAllocator::new(0, 10000);
+    for i in 0..5000 {
+        assert_eq!(ida.alloc(), Some(i));
+    }
+    for i in (0..5000).step_by(2) {
+        ida.free(i);
+    }
+    assert_eq!(ida.used(), 2500);
+    for i in (0..5000).step_by(2) {
+        assert_eq!(ida.alloc(), Some(i));
+    }
+    assert_eq!(ida.alloc(), Some(5000));
+}
+
+/// 测试很大的id范围
+#[test]
+fn test_large_range() {
+    let mut ida = IdAllocator::new(usize::MAX - 3, usize::MAX);
+    assert_eq!(ida.alloc(), Some(usize::MAX - 3));
+    assert_eq!(ida.alloc(), Some(usize::MAX - 2));
+    assert_eq!(ida.alloc(), Some(usize::MAX - 1));
+    assert_eq!(ida.alloc(), None);
+    ida.free(usize::MAX - 2);
+    assert_eq!(ida.alloc_cyclic(), Some(usize::MAX - 2));
+}
+
+/// 长时间反复分配和释放id，id不会耗尽
+#[test]
+fn test_stress_reuse() {
+    let mut ida = IdAllocator::new(0, 64);
+    for _ in 0..100000 {
+        let id = ida.alloc_cyclic().unwrap();
+        assert!(id < 64);
+        ida.free(id);
+    }
+    assert_eq!(ida.used(), 0);
+}
@@ -1,3 +1,28 @@
+//! 内核随机数生成器
+//!
+//! 熵源（硬件随机数、中断时间、TSC抖动）先被混入输入池，
+//! 输入池积累到足够的熵之后，从中提取密钥给基于ChaCha20的CRNG重新播种，
+//! 所有的随机数都由CRNG生成。
+//!
+//! 参考：https://code.dragonos.org.cn/xref/linux-6.1.9/drivers/char/random.c
+
+use core::sync::atomic::{compiler_fence, AtomicBool, Ordering};
+
+use system_error::SystemError;
+
+use crate::{
+    arch::{
+        rand::{arch_get_random_long, arch_get_random_seed_long},
+        sched::sched,
+        CurrentIrqArch, CurrentTimeArch,
+    },
+    exception::InterruptArch,
+    kinfo,
+    libs::{spinlock::SpinLock, wait_queue::WaitQueue},
+    process::ProcessManager,
+    time::{timekeeping::getnstimeofday, TimeArch},
+};
+
 bitflags! {
     pub struct GRandFlags: u8{
         const GRND_NONBLOCK = 0x0001;
@@ -5,3 +30,444 @@ bitflags! {
         const GRND_INSECURE = 0x0004;
     }
 }
+
+/// CRNG初始化所需的熵（bit）
+const CRNG_INIT_BITS: usize = 256;
+/// CRNG的密钥长度（字节）
+const CHACHA_KEY_SIZE: usize = 32;
+/// ChaCha20一个块的长度（字节）
+const CHACHA_BLOCK_SIZE: usize = 64;
+/// "expand 32-byte k"
+const CHACHA_CONSTANTS: [u32; 4] = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
+
+/// 每收集多少次中断，就把快速池中的数据混入输入池
+const FAST_POOL_MIX_INTERVAL: u32 = 64;
+/// 启动时采集TSC抖动的次数
+const JITTER_SAMPLES: usize = 4096;
+
+static INPUT_POOL: SpinLock<InputPool> = SpinLock::new(InputPool::new());
+static FAST_POOL: SpinLock<FastPool> = SpinLock::new(FastPool::new());
+static BASE_CRNG: SpinLock<Crng> = SpinLock::new(Crng::new());
+/// CRNG是否已经获得了足够的熵
+static CRNG_READY: AtomicBool = AtomicBool::new(false);
+/// 等待CRNG初始化完成的进程
+static CRNG_INIT_WAIT: WaitQueue = WaitQueue::INIT;
+
+#[inline(always)]
+fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(16);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(12);
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(8);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(7);
+}
+
+/// ChaCha20的核心置换（20轮，并把输入加回输出）
+fn chacha20_permute(state: &[u32; 16]) -> [u32; 16] {
+    let mut x = *state;
+    for _ in 0..10 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+
+    for (out, inp) in x.iter_mut().zip(state.iter()) {
+        *out = out.wrapping_add(*inp);
+    }
+    return x;
+}
+
+/// 生成一个ChaCha20的密钥流块
+fn chacha20_block(key: &[u32; 8], counter: u64, nonce: u64, out: &mut [u8; CHACHA_BLOCK_SIZE]) {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&CHACHA_CONSTANTS);
+    state[4..12].copy_from_slice(key);
+    state[12] = counter as u32;
+    state[13] = (counter >> 32) as u32;
+    state[14] = nonce as u32;
+    state[15] = (nonce >> 32) as u32;
+
+    let x = chacha20_permute(&state);
+    for (chunk, word) in out.chunks_exact_mut(4).zip(x.iter()) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+}
+
+fn key_from_bytes(bytes: &[u8]) -> [u32; 8] {
+    let mut key = [0u32; 8];
+    for (word, chunk) in key.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    return key;
+}
+
+/// 输入池
+///
+/// 以ChaCha20置换为核心的海绵结构：输入被异或进状态的前半部分，每填满一次就做一次置换。
+/// 提取时只输出置换后状态的前半部分，并且在输出后再做一次置换，使得输出无法用来回推之前的状态
+#[derive(Debug)]
+struct InputPool {
+    state: [u32; 16],
+    /// 下一个输入字节在状态中的位置
+    pos: usize,
+    /// 自上次提取以来，被记入的熵（bit）
+    entropy_count: usize,
+}
+
+impl InputPool {
+    const RATE: usize = CHACHA_KEY_SIZE;
+
+    const fn new() -> Self {
+        Self {
+            state: [0; 16],
+            pos: 0,
+            entropy_count: 0,
+        }
+    }
+
+    fn mix(&mut self, data: &[u8]) {
+        for &b in data {
+            self.state[self.pos / 4] ^= (b as u32) << (8 * (self.pos % 4));
+            self.pos += 1;
+            if self.pos == Self::RATE {
+                self.state = chacha20_permute(&self.state);
+                self.pos = 0;
+            }
+        }
+    }
+
+    fn credit(&mut self, bits: usize) {
+        self.entropy_count = self.entropy_count.saturating_add(bits);
+    }
+
+    /// 提取一个密钥，并清空熵的计数
+    fn extract(&mut self, out: &mut [u8; CHACHA_KEY_SIZE]) {
+        // 区分提取与混入，避免输入数据恰好构造出相同的状态
+        self.state[15] ^= 0x80000000;
+        self.state = chacha20_permute(&self.state);
+        for (chunk, word) in out.chunks_exact_mut(4).zip(self.state.iter()) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        self.state = chacha20_permute(&self.state);
+        self.pos = 0;
+        self.entropy_count = 0;
+    }
+}
+
+/// 快速池
+///
+/// 在中断上下文中收集中断的时间，只做很少的运算，攒够一定次数后再混入输入池
+#[derive(Debug)]
+struct FastPool {
+    pool: [u64; 4],
+    count: u32,
+}
+
+impl FastPool {
+    const fn new() -> Self {
+        Self {
+            pool: [0; 4],
+            count: 0,
+        }
+    }
+
+    /// SipHash的一轮置换
+    #[inline(always)]
+    fn permute(&mut self) {
+        let [mut a, mut b, mut c, mut d] = self.pool;
+        a = a.wrapping_add(b);
+        b = b.rotate_left(13);
+        b ^= a;
+        a = a.rotate_left(32);
+        c = c.wrapping_add(d);
+        d = d.rotate_left(16);
+        d ^= c;
+        a = a.wrapping_add(d);
+        d = d.rotate_left(21);
+        d ^= a;
+        c = c.wrapping_add(b);
+        b = b.rotate_left(17);
+        b ^= c;
+        c = c.rotate_left(32);
+        self.pool = [a, b, c, d];
+    }
+
+    fn mix(&mut self, x: u64, y: u64) {
+        self.pool[3] ^= x;
+        self.permute();
+        self.pool[0] ^= x;
+        self.pool[3] ^= y;
+        self.permute();
+        self.pool[0] ^= y;
+    }
+
+    fn as_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (chunk, word) in bytes.chunks_exact_mut(8).zip(self.pool.iter()) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        return bytes;
+    }
+}
+
+/// 基于ChaCha20的CRNG
+///
+/// 每次生成随机数时，先用当前密钥生成一个块：前半部分替换当前密钥（快速密钥擦除），
+/// 后半部分作为这一次请求的临时密钥。这样既不用在生成大量数据时持有锁，
+/// 泄露当前密钥也无法推算出之前生成过的随机数
+#[derive(Debug)]
+struct Crng {
+    key: [u32; 8],
+    /// 每次使用当前密钥生成块时递增，保证不会重复使用同一个(key, nonce)
+    nonce: u64,
+}
+
+impl Crng {
+    const fn new() -> Self {
+        Self {
+            key: [0; 8],
+            nonce: 0,
+        }
+    }
+
+    fn reseed(&mut self, seed: &[u8; CHACHA_KEY_SIZE]) {
+        let mut block = [0u8; CHACHA_BLOCK_SIZE];
+        chacha20_block(&self.key, 0, self.nonce, &mut block);
+        self.nonce = self.nonce.wrapping_add(1);
+        // 新密钥同时依赖旧密钥和新的种子，种子质量不好时也不会使CRNG变得更弱
+        for (b, s) in block.iter_mut().zip(seed.iter()) {
+            *b ^= *s;
+        }
+        self.key = key_from_bytes(&block[..CHACHA_KEY_SIZE]);
+        block.fill(0);
+    }
+
+    /// 生成一个临时密钥，并擦除当前密钥
+    fn make_key(&mut self) -> [u32; 8] {
+        let mut block = [0u8; CHACHA_BLOCK_SIZE];
+        chacha20_block(&self.key, 0, self.nonce, &mut block);
+        self.nonce = self.nonce.wrapping_add(1);
+        self.key = key_from_bytes(&block[..CHACHA_KEY_SIZE]);
+        let key = key_from_bytes(&block[CHACHA_KEY_SIZE..]);
+        block.fill(0);
+        return key;
+    }
+}
+
+/// ## CRNG是否已经获得了足够的熵
+#[inline]
+pub fn crng_ready() -> bool {
+    CRNG_READY.load(Ordering::Acquire)
+}
+
+/// 从输入池中提取熵，给CRNG重新播种
+fn crng_reseed() {
+    let mut seed = [0u8; CHACHA_KEY_SIZE];
+    INPUT_POOL.lock_irqsave().extract(&mut seed);
+
+    // 硬件随机数不记入熵，但是混入种子中，能提高种子的质量
+    for chunk in seed.chunks_exact_mut(8) {
+        if let Some(r) = arch_get_random_seed_long().or_else(arch_get_random_long) {
+            for (b, r) in chunk.iter_mut().zip(r.to_le_bytes()) {
+                *b ^= r;
+            }
+        }
+    }
+
+    BASE_CRNG.lock_irqsave().reseed(&seed);
+    seed.fill(0);
+
+    // 可能在中断上下文中被调用，因此这里不打印日志
+    if !CRNG_READY.swap(true, Ordering::AcqRel) {
+        CRNG_INIT_WAIT.wakeup_all(None);
+    }
+}
+
+/// 向输入池混入数据，并记入bits个bit的熵
+fn mix_pool_bytes(data: &[u8], bits: usize) {
+    let mut pool = INPUT_POOL.lock_irqsave();
+    pool.mix(data);
+    pool.credit(bits);
+    let need_reseed = !crng_ready() && pool.entropy_count >= CRNG_INIT_BITS;
+    drop(pool);
+
+    if need_reseed {
+        crng_reseed();
+    }
+}
+
+/// ## 向输入池混入与设备相关的数据
+///
+/// 这些数据（例如用户写入/dev/random的数据）不被认为含有熵，只用于扰乱输入池的状态
+pub fn add_device_randomness(data: &[u8]) {
+    mix_pool_bytes(data, 0);
+}
+
+/// ## 收集中断的时间作为熵
+///
+/// 在中断处理函数中调用。每收集`FAST_POOL_MIX_INTERVAL`次中断，记入1bit的熵
+pub fn add_interrupt_randomness(irq: u32) {
+    let cycles = CurrentTimeArch::get_cycles() as u64;
+
+    // 中断上下文中不能等待锁，拿不到锁时放弃这一次的数据即可
+    let mut fast = match FAST_POOL.try_lock_irqsave() {
+        Ok(fast) => fast,
+        Err(_) => return,
+    };
+    fast.mix(cycles, ((irq as u64) << 32) ^ cycles.rotate_left(17));
+    fast.count += 1;
+    if fast.count < FAST_POOL_MIX_INTERVAL {
+        return;
+    }
+
+    let mut pool = match INPUT_POOL.try_lock_irqsave() {
+        Ok(pool) => pool,
+        Err(_) => return,
+    };
+    pool.mix(&fast.as_bytes());
+    pool.credit(1);
+    fast.count = 0;
+    let need_reseed = !crng_ready() && pool.entropy_count >= CRNG_INIT_BITS;
+    drop(pool);
+    drop(fast);
+
+    if need_reseed {
+        crng_reseed();
+    }
+}
+
+/// ## 填充随机字节
+///
+/// 不会阻塞。如果CRNG还没有获得足够的熵，生成的随机数不能用于密码学用途
+pub fn get_random_bytes(buf: &mut [u8]) {
+    if buf.is_empty() {
+        return;
+    }
+
+    // 输入池积累了足够的熵时，重新播种
+    if INPUT_POOL.lock_irqsave().entropy_count >= CRNG_INIT_BITS {
+        crng_reseed();
+    }
+
+    let mut key = BASE_CRNG.lock_irqsave().make_key();
+    let mut block = [0u8; CHACHA_BLOCK_SIZE];
+    for (counter, chunk) in buf.chunks_mut(CHACHA_BLOCK_SIZE).enumerate() {
+        chacha20_block(&key, counter as u64, 0, &mut block);
+        chunk.copy_from_slice(&block[..chunk.len()]);
+    }
+
+    key.fill(0);
+    block.fill(0);
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// ## 获取一个随机数
+pub fn rand() -> usize {
+    let mut bytes = [0u8; core::mem::size_of::<usize>()];
+    get_random_bytes(&mut bytes);
+    return usize::from_ne_bytes(bytes);
+}
+
+/// ## 等待CRNG获得足够的熵
+///
+/// 等待过程中可以被信号打断，此时返回ERESTARTSYS
+pub fn wait_for_random_bytes() -> Result<(), SystemError> {
+    while !crng_ready() {
+        let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+        if crng_ready() {
+            break;
+        }
+        unsafe { CRNG_INIT_WAIT.sleep_without_schedule() };
+        drop(irq_guard);
+        sched();
+
+        if ProcessManager::current_pcb()
+            .sig_info_irqsave()
+            .sig_pending()
+            .has_pending()
+        {
+            return Err(SystemError::ERESTARTSYS);
+        }
+    }
+
+    return Ok(());
+}
+
+/// 采集TSC的抖动，混入输入池
+///
+/// 连续读取时间戳计数器，两次读取之间的间隔受缓存、流水线、总线竞争等因素影响而不断变化。
+/// 在虚拟机或者行为固定的硬件上，这些间隔可能是可以预测的，因此混入的数据不计入熵，
+/// CRNG是否就绪仍然由硬件随机数和中断提供的熵决定
+fn collect_jitter_entropy() {
+    let mut last = CurrentTimeArch::get_cycles();
+    let mut scratch = [0usize; 64];
+
+    for i in 0..JITTER_SAMPLES {
+        // 做一些访存操作，放大时间的抖动
+        let idx = last % scratch.len();
+        scratch[idx] = scratch[idx].wrapping_add(last ^ i);
+
+        let now = CurrentTimeArch::get_cycles();
+        mix_pool_bytes(&now.to_ne_bytes(), 0);
+        last = now;
+    }
+
+    let folded = scratch.iter().fold(0usize, |acc, x| acc.rotate_left(7) ^ x);
+    mix_pool_bytes(&folded.to_ne_bytes(), 0);
+}
+
+/// ## 初始化随机数生成器
+///
+/// 需要在时间子系统初始化之后调用
+pub fn rand_init() {
+    let now = getnstimeofday();
+    add_device_randomness(&now.tv_sec.to_ne_bytes());
+    add_device_randomness(&now.tv_nsec.to_ne_bytes());
+
+    // 信任CPU提供的硬件随机数
+    let mut hw_words = 0;
+    for _ in 0..(CRNG_INIT_BITS / u64::BITS as usize) {
+        match arch_get_random_seed_long().or_else(arch_get_random_long) {
+            Some(r) => {
+                mix_pool_bytes(&r.to_ne_bytes(), u64::BITS as usize);
+                hw_words += 1;
+            }
+            None => break,
+        }
+    }
+    if hw_words == 0 {
+        kinfo!("random: no hardware random number generator found");
+    }
+
+    collect_jitter_entropy();
+
+    if crng_ready() {
+        kinfo!("random: crng init done");
+    } else {
+        // 熵还不够，继续等待中断提供的熵。此时生成的随机数已经不可预测，只是强度还不够
+        crng_reseed_insecure();
+        kinfo!("random: crng not yet initialized, waiting for more entropy");
+    }
+}
+
+/// 在熵不足时，用输入池中已有的数据给CRNG播种，但不把CRNG标记为就绪
+fn crng_reseed_insecure() {
+    let mut seed = [0u8; CHACHA_KEY_SIZE];
+    let mut pool = INPUT_POOL.lock_irqsave();
+    let entropy = pool.entropy_count;
+    pool.extract(&mut seed);
+    // 保留熵的计数，使得之后的熵能够继续累积到初始化所需的数量
+    pool.entropy_count = entropy;
+    drop(pool);
+
+    BASE_CRNG.lock_irqsave().reseed(&seed);
+    seed.fill(0);
+}
@@ -3,11 +3,20 @@ use core::{
     ops::{Add, AddAssign, Mul, Sub, SubAssign},
 };
 
+use alloc::collections::BTreeMap;
+
 use crate::{
     arch::{mm::LockedFrameAllocator, MMArch},
+    libs::spinlock::SpinLock,
     mm::{MemoryManagementArch, PhysAddr, VirtAddr},
 };
 
+/// 被多个页表项共享的物理页帧的引用计数表
+///
+/// 只有被共享的页帧（例如fork之后，父子进程以写时复制的方式共享的页面）才会出现在这个表里，
+/// 不在表中的页帧的引用计数视为1
+static SHARED_FRAME_REFCOUNT: SpinLock<BTreeMap<PhysAddr, usize>> = SpinLock::new(BTreeMap::new());
+
 /// @brief 物理页帧的表示
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct PhysPageFrame {
@@ -353,3 +362,41 @@ pub unsafe fn deallocate_page_frames(frame: PhysPageFrame, count: PageFrameCount
         LockedFrameAllocator.free(frame.phys_address(), count);
     }
 }
+
+/// 增加物理页帧的引用计数
+///
+/// 当一个物理页帧被映射到新的页表项时（例如fork时父子进程共享页面），需要调用本函数
+pub fn page_frame_ref_inc(paddr: PhysAddr) {
+    let mut guard = SHARED_FRAME_REFCOUNT.lock_irqsave();
+    let count = guard.entry(paddr).or_insert(1);
+    *count += 1;
+}
+
+/// 获取物理页帧的引用计数
+pub fn page_frame_ref_count(paddr: PhysAddr) -> usize {
+    return SHARED_FRAME_REFCOUNT
+        .lock_irqsave()
+        .get(&paddr)
+        .copied()
+        .unwrap_or(1);
+}
+
+/// 减少物理页帧的引用计数，当引用计数降为0时，把页帧归还给全局页帧分配器
+///
+/// ## 返回值
+///
+/// 如果页帧被释放，返回true
+pub unsafe fn page_frame_ref_dec(paddr: PhysAddr) -> bool {
+    let mut guard = SHARED_FRAME_REFCOUNT.lock_irqsave();
+    if let Some(count) = guard.get_mut(&paddr) {
+        *count -= 1;
+        if *count <= 1 {
+            guard.remove(&paddr);
+        }
+        return false;
+    }
+    drop(guard);
+
+    deallocate_page_frames(PhysPageFrame::new(paddr), PageFrameCount::new(1));
+    return true;
+}
@@ -0,0 +1,339 @@
+// 缺页异常处理
+
+use alloc::sync::Arc;
+use system_error::SystemError;
+
+use crate::{
+    arch::{
+        ipc::signal::{SigCode, Signal},
+        mm::PageMapper,
+        MMArch,
+    },
+    ipc::signal_types::{SigInfo, SigType},
+    kerror,
+    process::ProcessManager,
+};
+
+use super::{
+    allocator::page_frame::{
+        allocate_page_frames, page_frame_ref_count, page_frame_ref_dec, PageFrameCount,
+    },
+    page::{InactiveFlusher, PageFlush},
+    ucontext::{copy_page_frame, LockedVMA},
+    MemoryManagementArch, VirtAddr, VmFlags,
+};
+
+bitflags! {
+    /// 缺页异常的标志位
+    pub struct FaultFlags: u64 {
+        /// 写操作引起的缺页
+        const FAULT_FLAG_WRITE = 1 << 0;
+        /// 用户态引起的缺页
+        const FAULT_FLAG_USER = 1 << 6;
+        /// 取指令引起的缺页
+        const FAULT_FLAG_INSTRUCTION = 1 << 8;
+    }
+
+    /// 缺页处理的结果
+    pub struct VmFaultReason: u32 {
+        /// 内存不足
+        const VM_FAULT_OOM = 0x000001;
+        /// 无法从文件中读取映射的页面
+        const VM_FAULT_SIGBUS = 0x000002;
+        /// 访问的地址不在任何VMA内，或者VMA的权限不允许本次访问
+        const VM_FAULT_SIGSEGV = 0x000040;
+        /// 需要在释放地址空间的锁之后，从文件中读取映射的页面，然后重新处理缺页
+        const VM_FAULT_RETRY = 0x000400;
+        /// 缺页已处理完成
+        const VM_FAULT_COMPLETED = 0x004000;
+    }
+}
+
+/// 缺页异常的信息
+#[derive(Debug)]
+pub struct PageFaultMessage {
+    /// 产生缺页的VMA
+    vma: Arc<LockedVMA>,
+    /// 产生缺页的地址
+    address: VirtAddr,
+    /// 缺页的标志位
+    flags: FaultFlags,
+}
+
+impl PageFaultMessage {
+    pub fn new(vma: Arc<LockedVMA>, address: VirtAddr, flags: FaultFlags) -> Self {
+        Self {
+            vma: vma.clone(),
+            address,
+            flags,
+        }
+    }
+
+    #[inline(always)]
+    pub fn vma(&self) -> Arc<LockedVMA> {
+        self.vma.clone()
+    }
+
+    /// 产生缺页的地址（已经向下对齐到页边界）
+    #[inline(always)]
+    pub fn address_aligned_down(&self) -> VirtAddr {
+        VirtAddr::new(self.address.data() & MMArch::PAGE_MASK)
+    }
+
+    #[inline(always)]
+    pub fn address(&self) -> VirtAddr {
+        self.address
+    }
+
+    #[inline(always)]
+    pub fn flags(&self) -> FaultFlags {
+        self.flags
+    }
+}
+
+/// 缺页异常处理器
+pub struct PageFaultHandler;
+
+impl PageFaultHandler {
+    /// 处理用户地址空间内的缺页异常
+    ///
+    /// 根据缺页地址在当前进程的`UserMappings`中查找VMA，检查VMA的权限，然后为其分配物理页
+    ///
+    /// ## 参数
+    ///
+    /// - `address`: 产生缺页的地址
+    /// - `flags`: 缺页的标志位
+    ///
+    /// ## 返回值
+    ///
+    /// 缺页处理的结果
+    pub unsafe fn do_user_addr_fault(address: VirtAddr, flags: FaultFlags) -> VmFaultReason {
+        let current_address_space = match ProcessManager::current_pcb().basic().user_vm() {
+            Some(vm) => vm,
+            None => return VmFaultReason::VM_FAULT_SIGSEGV,
+        };
+
+        loop {
+            let mut space_guard = current_address_space.write_irqsave();
+            let vma = match space_guard.mappings.contains(address) {
+                Some(vma) => vma,
+                None => return VmFaultReason::VM_FAULT_SIGSEGV,
+            };
+
+            let message = PageFaultMessage::new(vma.clone(), address, flags);
+            let reason = Self::handle_mm_fault(message, &mut space_guard.user_mapper.utable);
+            drop(space_guard);
+            if !reason.contains(VmFaultReason::VM_FAULT_RETRY) {
+                return reason;
+            }
+
+            // 读取文件需要进行块设备I/O，可能会睡眠，因此在释放地址空间的锁之后再填充页面缓存
+            let (page_cache, index) = match vma.lock().file_page(address) {
+                Some(x) => x,
+                None => continue,
+            };
+            match page_cache.get_page(index) {
+                Ok(paddr) => page_frame_ref_dec(paddr),
+                Err(SystemError::ENOMEM) => return VmFaultReason::VM_FAULT_OOM,
+                Err(_) => return VmFaultReason::VM_FAULT_SIGBUS,
+            }
+        }
+    }
+
+    /// 处理缺页异常
+    ///
+    /// ## 参数
+    ///
+    /// - `pfm`: 缺页异常信息
+    /// - `mapper`: 页表映射器
+    ///
+    /// ## 返回值
+    ///
+    /// 缺页处理的结果
+    pub unsafe fn handle_mm_fault(pfm: PageFaultMessage, mapper: &mut PageMapper) -> VmFaultReason {
+        if !Self::vma_access_permitted(&pfm) {
+            return VmFaultReason::VM_FAULT_SIGSEGV;
+        }
+
+        return Self::handle_pte_fault(pfm, mapper);
+    }
+
+    /// 检查VMA的权限是否允许本次访问
+    fn vma_access_permitted(pfm: &PageFaultMessage) -> bool {
+        let vma = pfm.vma();
+        let vm_flags = *vma.lock().vm_flags();
+        let flags = pfm.flags();
+
+        if flags.contains(FaultFlags::FAULT_FLAG_WRITE) {
+            return vm_flags.contains(VmFlags::VM_WRITE);
+        }
+
+        if flags.contains(FaultFlags::FAULT_FLAG_INSTRUCTION) {
+            return vm_flags.contains(VmFlags::VM_EXEC);
+        }
+
+        return vm_flags.intersects(VmFlags::VM_READ | VmFlags::VM_WRITE | VmFlags::VM_EXEC);
+    }
+
+    /// 处理页表项层面的缺页
+    unsafe fn handle_pte_fault(pfm: PageFaultMessage, mapper: &mut PageMapper) -> VmFaultReason {
+        let address = pfm.address_aligned_down();
+
+        if let Some((_, flags)) = mapper.translate(address) {
+            // 对只读页面的写入：VMA允许写（已经在vma_access_permitted中检查过），说明这是一个写时复制的页面
+            if pfm.flags().contains(FaultFlags::FAULT_FLAG_WRITE) && !flags.has_write() {
+                return Self::do_wp_page(pfm, mapper);
+            }
+            // 页面已经存在（可能是其他CPU刚刚处理完这个缺页），只需要刷新TLB
+            MMArch::invalidate_page(address);
+            return VmFaultReason::VM_FAULT_COMPLETED;
+        }
+
+        if pfm.vma().lock().file_page(address).is_some() {
+            return Self::do_fault(pfm, mapper);
+        }
+
+        return Self::do_anonymous_page(pfm, mapper);
+    }
+
+    /// 处理匿名页的缺页：分配一个清零的物理页并映射到缺页地址
+    unsafe fn do_anonymous_page(pfm: PageFaultMessage, mapper: &mut PageMapper) -> VmFaultReason {
+        let address = pfm.address_aligned_down();
+        let vma = pfm.vma();
+        let page_flags = vma.lock().flags();
+
+        let flush = match mapper.map(address, page_flags) {
+            Some(flush) => flush,
+            None => return VmFaultReason::VM_FAULT_OOM,
+        };
+
+        let paddr = mapper.translate(address).unwrap().0;
+        MMArch::write_bytes(MMArch::phys_2_virt(paddr).unwrap(), 0, MMArch::PAGE_SIZE);
+
+        flush.flush();
+
+        return VmFaultReason::VM_FAULT_COMPLETED;
+    }
+
+    /// 处理文件映射的缺页：从文件的页面缓存中获取物理页并映射到缺页地址
+    ///
+    /// 页面不在缓存中时返回`VM_FAULT_RETRY`，由调用者在释放地址空间的锁之后填充缓存并重试
+    ///
+    /// 共享映射直接映射页面缓存中的物理页，读缺页以只读的方式映射，等到写入时再标记为脏页；
+    /// 私有映射的读缺页以只读的方式映射页面缓存中的物理页，等到写入时再复制，
+    /// 私有映射的写缺页则直接复制一份新的物理页
+    unsafe fn do_fault(pfm: PageFaultMessage, mapper: &mut PageMapper) -> VmFaultReason {
+        let address = pfm.address_aligned_down();
+        let vma = pfm.vma();
+        let guard = vma.lock();
+        let page_flags = guard.flags();
+        let shared = guard.vm_flags().contains(VmFlags::VM_SHARED);
+        let (page_cache, index) = guard.file_page(address).unwrap();
+        drop(guard);
+
+        let paddr = match page_cache.find_page(index) {
+            Some(paddr) => paddr,
+            None => return VmFaultReason::VM_FAULT_RETRY,
+        };
+
+        let write = pfm.flags().contains(FaultFlags::FAULT_FLAG_WRITE);
+        if !shared && write {
+            let new_paddr = match allocate_page_frames(PageFrameCount::new(1)) {
+                Some((new_paddr, _)) => new_paddr,
+                None => {
+                    page_frame_ref_dec(paddr);
+                    return VmFaultReason::VM_FAULT_OOM;
+                }
+            };
+            copy_page_frame(paddr, new_paddr);
+            page_frame_ref_dec(paddr);
+
+            let flush = mapper.map_phys(address, new_paddr, page_flags).unwrap();
+            flush.flush();
+            return VmFaultReason::VM_FAULT_COMPLETED;
+        }
+
+        // 页表项持有get_page时增加的引用计数
+        let page_flags = if shared && write {
+            page_cache.mark_dirty(index);
+            page_flags
+        } else {
+            page_flags.set_write(false)
+        };
+        let flush = mapper.map_phys(address, paddr, page_flags).unwrap();
+        flush.flush();
+
+        return VmFaultReason::VM_FAULT_COMPLETED;
+    }
+
+    /// 处理写保护缺页（写时复制）
+    ///
+    /// 共享映射的页面直接恢复写权限，如果是文件映射，还要把页面标记为脏页。
+    /// 私有映射中，如果物理页只被当前页表项引用，直接恢复写权限；否则复制一份新的物理页，
+    /// 把缺页地址映射到新页上，并减少旧页的引用计数
+    unsafe fn do_wp_page(pfm: PageFaultMessage, mapper: &mut PageMapper) -> VmFaultReason {
+        let address = pfm.address_aligned_down();
+        let vma = pfm.vma();
+        let guard = vma.lock();
+        let page_flags = guard.flags();
+        let shared = guard.vm_flags().contains(VmFlags::VM_SHARED);
+        let file_page = guard.file_page(address);
+        drop(guard);
+        let old_paddr = mapper.translate(address).unwrap().0;
+
+        // 共享文件映射的页面在第一次写入之前是只读的，用于记录哪些页面需要写回
+        if shared {
+            if let Some((page_cache, index)) = file_page {
+                page_cache.mark_dirty(index);
+            }
+        }
+
+        // 共享映射中的页面不需要复制，所有映射者都应该看到同一份数据
+        if shared || page_frame_ref_count(old_paddr) == 1 {
+            // 其他地址空间已经不再共享这个页面了
+            let flush = mapper.remap(address, page_flags).unwrap();
+            flush.flush();
+            return VmFaultReason::VM_FAULT_COMPLETED;
+        }
+
+        let new_paddr = match allocate_page_frames(PageFrameCount::new(1)) {
+            Some((paddr, _)) => paddr,
+            None => return VmFaultReason::VM_FAULT_OOM,
+        };
+        copy_page_frame(old_paddr, new_paddr);
+
+        // 解除旧页的映射，但不释放它，旧页由引用计数决定何时释放
+        let (_, _, flush) = mapper.unmap_phys(address, false).unwrap();
+        flush.ignore();
+        let flush = mapper.map_phys(address, new_paddr, page_flags).unwrap();
+        // 同一地址空间的其他线程可能在别的核心上缓存了指向旧页的表项，
+        // 必须在减少旧页引用计数之前把它们刷掉
+        Self::shootdown_tlb(flush);
+
+        page_frame_ref_dec(old_paddr);
+
+        return VmFaultReason::VM_FAULT_COMPLETED;
+    }
+
+    /// 刷新当前核心以及其他所有核心上的TLB
+    ///
+    /// 缺页处理时的页表是当前激活的页表，但是同一地址空间的其他线程可能正运行在
+    /// 其他核心上，只刷新本地TLB会让它们继续使用旧的表项
+    fn shootdown_tlb(flush: PageFlush<MMArch>) {
+        flush.flush();
+        // InactiveFlusher在drop时向其他核心发送刷新TLB的IPI
+        drop(InactiveFlusher::new());
+    }
+
+    /// 向当前进程强制发送SIGSEGV信号
+    ///
+    /// 当用户态访问了非法地址时调用。进程屏蔽或者忽略SIGSEGV时，仍然会被终止
+    pub fn send_sigsegv(address: VirtAddr) {
+        let pid = ProcessManager::current_pid();
+        kerror!("Segmentation fault: pid: {:?}, address: {:?}", pid, address);
+        let mut info = SigInfo::new(Signal::SIGSEGV, 0, SigCode::Kernel, SigType::Kill(pid));
+        Signal::SIGSEGV
+            .force_send_to_current(&mut info)
+            .expect("failed to send SIGSEGV to current process");
+    }
+}
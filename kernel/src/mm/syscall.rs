@@ -1,13 +1,15 @@
 use core::intrinsics::unlikely;
 
-use alloc::sync::Arc;
+use alloc::{string::String, sync::Arc};
 use system_error::SystemError;
 
 use crate::{
     arch::MMArch,
+    filesystem::vfs::{file::FileMode, FileType},
     kerror,
     libs::align::{check_aligned, page_align_up},
     mm::MemoryManagementArch,
+    process::ProcessManager,
     syscall::Syscall,
 };
 
@@ -65,6 +67,16 @@ bitflags! {
         const MAP_UNINITIALIZED = 0x4000000;
     }
 
+    /// Memory synchronization flags
+    pub struct MsFlags: u64 {
+        /// sync memory asynchronously
+        const MS_ASYNC = 1;
+        /// invalidate the caches
+        const MS_INVALIDATE = 2;
+        /// synchronous memory sync
+        const MS_SYNC = 4;
+    }
+
     /// Memory mremapping flags
     pub struct MremapFlags: u8 {
         const MREMAP_MAYMOVE = 1;
@@ -89,6 +101,10 @@ impl From<MapFlags> for VmFlags {
             vm_flags |= VmFlags::VM_SYNC;
         }
 
+        if map_flags.contains(MapFlags::MAP_SHARED) {
+            vm_flags |= VmFlags::VM_SHARED | VmFlags::VM_MAYSHARE;
+        }
+
         vm_flags
     }
 }
@@ -198,8 +214,8 @@ impl Syscall {
     /// - `len`：映射的长度
     /// - `prot`：保护标志
     /// - `flags`：映射标志
-    /// - `fd`：文件描述符（暂时不支持）
-    /// - `offset`：文件偏移量 （暂时不支持）
+    /// - `fd`：文件描述符（仅文件映射时有效）
+    /// - `offset`：文件偏移量，必须按页对齐（仅文件映射时有效）
     ///
     /// ## 返回值
     ///
@@ -209,8 +225,8 @@ impl Syscall {
         len: usize,
         prot_flags: usize,
         map_flags: usize,
-        _fd: i32,
-        _offset: usize,
+        fd: i32,
+        offset: usize,
     ) -> Result<usize, SystemError> {
         let map_flags = MapFlags::from_bits_truncate(map_flags as u64);
         let prot_flags = ProtFlags::from_bits_truncate(prot_flags as u64);
@@ -224,17 +240,16 @@ impl Syscall {
             );
             return Err(SystemError::EINVAL);
         }
-        // 暂时不支持除匿名页以外的映射
-        if !map_flags.contains(MapFlags::MAP_ANONYMOUS) {
-            kerror!("mmap: not support file mapping");
-            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
-        }
-
         // 暂时不支持巨页映射
         if map_flags.contains(MapFlags::MAP_HUGETLB) {
             kerror!("mmap: not support huge page mapping");
             return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
         }
+
+        if !map_flags.contains(MapFlags::MAP_ANONYMOUS) {
+            return Self::mmap_file(start_vaddr, len, prot_flags, map_flags, fd, offset);
+        }
+
         let current_address_space = AddressSpace::current()?;
         let start_page = current_address_space.write().map_anonymous(
             start_vaddr,
@@ -242,6 +257,67 @@ impl Syscall {
             prot_flags,
             map_flags,
             true,
+            map_flags.contains(MapFlags::MAP_POPULATE),
+        )?;
+        return Ok(start_page.virt_address().data());
+    }
+
+    /// 进行文件映射
+    fn mmap_file(
+        start_vaddr: VirtAddr,
+        len: usize,
+        prot_flags: ProtFlags,
+        map_flags: MapFlags,
+        fd: i32,
+        offset: usize,
+    ) -> Result<usize, SystemError> {
+        if !check_aligned(offset, MMArch::PAGE_SIZE) {
+            return Err(SystemError::EINVAL);
+        }
+        // 共享映射和私有映射必须指定其中一个
+        if map_flags.contains(MapFlags::MAP_SHARED) == map_flags.contains(MapFlags::MAP_PRIVATE) {
+            return Err(SystemError::EINVAL);
+        }
+
+        let file = ProcessManager::current_pcb()
+            .fd_table()
+            .read()
+            .get_file_by_fd(fd)
+            .ok_or(SystemError::EBADF)?;
+        let (inode, mode, path) = {
+            let guard = file.lock();
+            if guard.file_type() != FileType::File {
+                return Err(SystemError::ENODEV);
+            }
+            (guard.inode(), guard.mode(), guard.path().map(String::from))
+        };
+        // 只有支持页面缓存的文件系统才能进行文件映射
+        if inode.page_cache().is_none() {
+            return Err(SystemError::ENODEV);
+        }
+
+        let readable = mode.accmode() != FileMode::O_WRONLY.bits();
+        let writable = mode.accmode() != FileMode::O_RDONLY.bits();
+        if !readable {
+            return Err(SystemError::EACCES);
+        }
+        if map_flags.contains(MapFlags::MAP_SHARED)
+            && prot_flags.contains(ProtFlags::PROT_WRITE)
+            && !writable
+        {
+            return Err(SystemError::EACCES);
+        }
+
+        let current_address_space = AddressSpace::current()?;
+        let start_page = current_address_space.write().map_file(
+            start_vaddr,
+            len,
+            prot_flags,
+            map_flags,
+            inode,
+            offset / MMArch::PAGE_SIZE,
+            path,
+            writable,
         )?;
         return Ok(start_page.virt_address().data());
     }
@@ -358,11 +434,59 @@ impl Syscall {
         let start_frame = VirtPageFrame::new(start_vaddr);
         let page_count = PageFrameCount::new(len / MMArch::PAGE_SIZE);
 
-        current_address_space
-            .write()
+        let mut guard = current_address_space.write();
+        let page_caches = guard.shared_page_caches(start_frame, page_count);
+        guard
             .munmap(start_frame, page_count)
             .map_err(|_| SystemError::EINVAL)?;
+        drop(guard);
+
+        // 写回时需要访问文件系统，不能持有地址空间的锁
+        for page_cache in page_caches {
+            page_cache.sync()?;
+        }
+
+        return Ok(0);
+    }
+
+    /// ## msync系统调用
+    ///
+    /// 把指定区域内的共享文件映射中被修改的页面写回文件
+    ///
+    /// ## 参数
+    ///
+    /// - `start_vaddr`：起始地址(已经对齐到页)
+    /// - `len`：长度(已经对齐到页)
+    /// - `flags`：同步标志，只有指定了MS_SYNC时才会等待写回完成
+    pub fn msync(start_vaddr: VirtAddr, len: usize, flags: usize) -> Result<usize, SystemError> {
+        let flags = MsFlags::from_bits(flags as u64).ok_or(SystemError::EINVAL)?;
+        if flags.contains(MsFlags::MS_ASYNC | MsFlags::MS_SYNC) {
+            return Err(SystemError::EINVAL);
+        }
+        if unlikely(verify_area(start_vaddr, len).is_err()) {
+            return Err(SystemError::ENOMEM);
+        }
+        if unlikely(len == 0) {
+            return Ok(0);
+        }
+
+        let current_address_space: Arc<AddressSpace> = AddressSpace::current()?;
+        let start_frame = VirtPageFrame::new(start_vaddr);
+        let page_count = PageFrameCount::new(len / MMArch::PAGE_SIZE);
+
+        let page_caches = current_address_space
+            .write()
+            .msync(start_frame, page_count)?;
+
+        // 被修改的页面已经被标记为脏页，MS_ASYNC不需要等待写回，它们会在MS_SYNC或者解除映射时被写回
+        if !flags.contains(MsFlags::MS_SYNC) {
+            return Ok(0);
+        }
 
+        // 写回时需要访问文件系统，不能持有地址空间的锁
+        for page_cache in page_caches {
+            page_cache.sync()?;
+        }
         return Ok(0);
     }
 
@@ -10,6 +10,7 @@ use core::{
 
 use alloc::{
     collections::BTreeMap,
+    string::String,
     sync::{Arc, Weak},
     vec::Vec,
 };
@@ -19,22 +20,24 @@ use system_error::SystemError;
 use crate::{
     arch::{mm::PageMapper, CurrentIrqArch, MMArch},
     exception::InterruptArch,
+    filesystem::{page_cache::PageCache, vfs::IndexNode},
+    kwarn,
     libs::{
         align::page_align_up,
         rwlock::{RwLock, RwLockWriteGuard},
         spinlock::{SpinLock, SpinLockGuard},
     },
     process::ProcessManager,
-    syscall::user_access::{UserBufferReader, UserBufferWriter},
 };
 
 use super::{
     allocator::page_frame::{
-        deallocate_page_frames, PageFrameCount, PhysPageFrame, VirtPageFrame, VirtPageFrameIter,
+        deallocate_page_frames, page_frame_ref_count, page_frame_ref_dec, page_frame_ref_inc,
+        PageFrameCount, PhysPageFrame, VirtPageFrame, VirtPageFrameIter,
     },
     page::{Flusher, InactiveFlusher, PageFlags, PageFlushAll},
     syscall::{MapFlags, MremapFlags, ProtFlags},
-    MemoryManagementArch, PageTableKind, VirtAddr, VirtRegion, VmFlags,
+    MemoryManagementArch, PageTableKind, PhysAddr, VirtAddr, VirtRegion, VmFlags,
 };
 
 /// MMAP_MIN_ADDR的默认值
@@ -119,6 +122,9 @@ pub struct InnerAddressSpace {
     pub end_code: VirtAddr,
     pub start_data: VirtAddr,
     pub end_data: VirtAddr,
+
+    /// execve时压入用户栈的auxv，以(类型, 值)的形式保存，用于生成core dump
+    pub saved_auxv: Vec<(usize, usize)>,
 }
 
 impl InnerAddressSpace {
@@ -136,6 +142,7 @@ impl InnerAddressSpace {
             end_code: VirtAddr(0),
             start_data: VirtAddr(0),
             end_data: VirtAddr(0),
+            saved_auxv: Vec::new(),
         };
         if create_stack {
             // kdebug!("to create user stack.");
@@ -162,69 +169,99 @@ impl InnerAddressSpace {
         }
         let _current_stack_size = self.user_stack.as_ref().unwrap().stack_size();
 
-        let current_mapper = &mut self.user_mapper.utable;
+        // 父进程的页表项会被改为只读，如果父进程的地址空间是当前的地址空间，需要刷新TLB。
+        // 父进程的其他线程可能正运行在其他cpu上，也需要刷新那些cpu的TLB
+        let mut flusher: PageFlushAll<MMArch> = PageFlushAll::new();
 
         // 拷贝空洞
         new_guard.mappings.vm_holes = self.mappings.vm_holes.clone();
+        new_guard.saved_auxv = self.saved_auxv.clone();
+
+        let mut cow_pages: Vec<(VirtAddr, PageFlags<MMArch>)> = Vec::new();
+        if let Err(e) = self.clone_mappings(&mut new_guard, &mut flusher, &mut cow_pages) {
+            // 恢复父进程中被改为只读的页表项。新的地址空间中已经建立的映射所增加的页面引用计数，
+            // 会在丢弃新的地址空间、解除它的所有映射时被释放
+            for (page, flags) in cow_pages {
+                let flush = unsafe { self.user_mapper.utable.remap(page, flags) }
+                    .expect("VMA page not mapped");
+                flusher.consume(flush);
+            }
+            drop(flusher);
+            drop(InactiveFlusher::new());
+            drop(new_guard);
+            drop(new_addr_space);
+            drop(irq_guard);
+            return Err(e);
+        }
 
-        for vma in self.mappings.vmas.iter() {
-            // TODO: 增加对VMA是否为文件映射的判断，如果是的话，就跳过
+        drop(flusher);
+        drop(InactiveFlusher::new());
+        drop(new_guard);
+        drop(irq_guard);
+        return Ok(new_addr_space);
+    }
 
+    /// 把当前地址空间中的VMA以及已经分配了物理页的页面拷贝到新的地址空间中
+    ///
+    /// ## 参数
+    ///
+    /// - `new_guard`: 新的地址空间
+    /// - `flusher`: 刷新父进程页表项的刷新器
+    /// - `cow_pages`: 记录父进程中被改为只读的页面及其原来的标志位，用于失败时恢复
+    fn clone_mappings(
+        &mut self,
+        new_guard: &mut InnerAddressSpace,
+        flusher: &mut PageFlushAll<MMArch>,
+        cow_pages: &mut Vec<(VirtAddr, PageFlags<MMArch>)>,
+    ) -> Result<(), SystemError> {
+        let current_mapper = &mut self.user_mapper.utable;
+        for vma in self.mappings.vmas.iter() {
             let vma_guard: SpinLockGuard<'_, VMA> = vma.lock();
-            let old_flags = vma_guard.flags();
-            let tmp_flags: PageFlags<MMArch> = PageFlags::new().set_write(true);
-
-            // 分配内存页并创建新的VMA
-            let new_vma = VMA::zeroed(
-                VirtPageFrame::new(vma_guard.region.start()),
-                PageFrameCount::new(vma_guard.region.size() / MMArch::PAGE_SIZE),
-                vma_guard.vm_flags().clone(),
-                tmp_flags,
-                &mut new_guard.user_mapper.utable,
-                (),
-            )?;
-            new_guard.mappings.vmas.insert(new_vma.clone());
-            // kdebug!("new vma: {:x?}", new_vma);
-            let mut new_vma_guard = new_vma.lock();
-            for page in new_vma_guard.pages().map(|p| p.virt_address()) {
+            let page_flags = vma_guard.flags();
+            let shared = vma_guard.vm_flags().contains(VmFlags::VM_SHARED);
+            // 私有映射的页面在父子进程之间以只读的方式共享，等到写缺页时再复制（写时复制）。
+            // 共享文件映射的页面在新的地址空间中也以只读的方式映射，以便在写缺页时标记脏页
+            let file_backed = matches!(vma_guard.provider(), Provider::File { .. });
+            let cow_flags = if shared && !file_backed {
+                page_flags
+            } else {
+                page_flags.set_write(false)
+            };
+
+            // 创建新的VMA。由于采用了按需分页，这里不会预先分配物理页，
+            // 只有在当前地址空间中已经分配了物理页的页面，才需要共享给新的地址空间
+            let mut new_vma = VMA::new(vma_guard.region, *vma_guard.vm_flags(), page_flags, true);
+            new_vma.set_provider(vma_guard.provider().clone());
+            new_guard.mappings.vmas.insert(LockedVMA::new(new_vma));
+            for page in vma_guard.pages().map(|p| p.virt_address()) {
                 // kdebug!("page: {:x?}", page);
-                let current_frame = unsafe {
-                    MMArch::phys_2_virt(
-                        current_mapper
-                            .translate(page)
-                            .expect("VMA page not mapped")
-                            .0,
-                    )
-                }
-                .expect("Phys2Virt: vaddr overflow.")
-                .data() as *mut u8;
-
-                let new_frame = unsafe {
-                    MMArch::phys_2_virt(
-                        new_guard
-                            .user_mapper
-                            .utable
-                            .translate(page)
-                            .expect("VMA page not mapped")
-                            .0,
-                    )
+                let (current_paddr, current_flags) = match current_mapper.translate(page) {
+                    Some(x) => x,
+                    None => continue,
+                };
+
+                if !shared {
+                    let flush = unsafe { current_mapper.remap(page, cow_flags) }
+                        .expect("VMA page not mapped");
+                    flusher.consume(flush);
+                    cow_pages.push((page, current_flags));
                 }
-                .expect("Phys2Virt: vaddr overflow.")
-                .data() as *mut u8;
 
-                unsafe {
-                    // 拷贝数据
-                    new_frame.copy_from_nonoverlapping(current_frame, MMArch::PAGE_SIZE);
+                let flush = unsafe {
+                    new_guard
+                        .user_mapper
+                        .utable
+                        .map_phys(page, current_paddr, cow_flags)
                 }
+                .ok_or(SystemError::ENOMEM)?;
+                // 新的地址空间不是当前的地址空间，因此不需要刷新TLB
+                unsafe { flush.ignore() };
+
+                page_frame_ref_inc(current_paddr);
             }
             drop(vma_guard);
-
-            new_vma_guard.remap(old_flags, &mut new_guard.user_mapper.utable, ())?;
-            drop(new_vma_guard);
         }
-        drop(new_guard);
-        drop(irq_guard);
-        return Ok(new_addr_space);
+        return Ok(());
     }
 
     /// 判断当前的地址空间是否是当前进程的地址空间
@@ -242,6 +279,7 @@ impl InnerAddressSpace {
     /// - `prot_flags`：保护标志
     /// - `map_flags`：映射标志
     /// - `round_to_min`：是否将`start_vaddr`对齐到`mmap_min`，如果为`true`，则当`start_vaddr`不为0时，会对齐到`mmap_min`，否则仅向下对齐到页边界
+    /// - `allocate_at_once`：是否立即分配物理页。如果为`false`，则只创建VMA，物理页在第一次访问时由缺页异常处理程序分配
     ///
     /// ## 返回
     ///
@@ -253,21 +291,8 @@ impl InnerAddressSpace {
         prot_flags: ProtFlags,
         map_flags: MapFlags,
         round_to_min: bool,
+        allocate_at_once: bool,
     ) -> Result<VirtPageFrame, SystemError> {
-        // 用于对齐hint的函数
-        let round_hint_to_min = |hint: VirtAddr| {
-            // 先把hint向下对齐到页边界
-            let addr = hint.data() & (!MMArch::PAGE_OFFSET_MASK);
-            // kdebug!("map_anonymous: hint = {:?}, addr = {addr:#x}", hint);
-            // 如果hint不是0，且hint小于DEFAULT_MMAP_MIN_ADDR，则对齐到DEFAULT_MMAP_MIN_ADDR
-            if (addr != 0) && round_to_min && (addr < DEFAULT_MMAP_MIN_ADDR) {
-                Some(VirtAddr::new(page_align_up(DEFAULT_MMAP_MIN_ADDR)))
-            } else if addr == 0 {
-                None
-            } else {
-                Some(VirtAddr::new(addr))
-            }
-        };
         // kdebug!("map_anonymous: start_vaddr = {:?}", start_vaddr);
         // kdebug!("map_anonymous: len(no align) = {}", len);
 
@@ -281,13 +306,91 @@ impl InnerAddressSpace {
 
         // kdebug!("map_anonymous: len = {}", len);
 
-        let start_page: VirtPageFrame = self.mmap(
-            round_hint_to_min(start_vaddr),
+        let start_page: VirtPageFrame = if allocate_at_once {
+            self.mmap(
+                round_hint_to_min(start_vaddr, round_to_min),
+                PageFrameCount::from_bytes(len).unwrap(),
+                prot_flags,
+                map_flags,
+                move |page, count, flags, mapper, flusher| {
+                    Ok(VMA::zeroed(page, count, vm_flags, flags, mapper, flusher)?)
+                },
+            )?
+        } else {
+            self.mmap(
+                round_hint_to_min(start_vaddr, round_to_min),
+                PageFrameCount::from_bytes(len).unwrap(),
+                prot_flags,
+                map_flags,
+                move |page, count, flags, _mapper, _flusher| {
+                    Ok(LockedVMA::new(VMA::new(
+                        VirtRegion::new(page.virt_address(), count.bytes()),
+                        vm_flags,
+                        flags,
+                        true,
+                    )))
+                },
+            )?
+        };
+
+        return Ok(start_page);
+    }
+
+    /// 进行文件映射
+    ///
+    /// 只创建VMA，物理页在第一次访问时由缺页异常处理程序从文件的页面缓存中获取
+    ///
+    /// ## 参数
+    ///
+    /// - `start_vaddr`：映射的起始地址
+    /// - `len`：映射的长度
+    /// - `prot_flags`：保护标志
+    /// - `map_flags`：映射标志
+    /// - `inode`：被映射的文件的inode，它必须拥有页面缓存
+    /// - `pgoff`：映射的起始地址在文件中对应的页号
+    /// - `path`：被映射的文件的路径，用于在core dump中记录文件映射
+    /// - `may_write`：共享映射是否允许写入（即文件是否以可写的方式打开）
+    ///
+    /// ## 返回
+    ///
+    /// 返回映射的起始虚拟页帧
+    #[allow(clippy::too_many_arguments)]
+    pub fn map_file(
+        &mut self,
+        start_vaddr: VirtAddr,
+        len: usize,
+        prot_flags: ProtFlags,
+        map_flags: MapFlags,
+        inode: Arc<dyn IndexNode>,
+        pgoff: usize,
+        path: Option<String>,
+        may_write: bool,
+    ) -> Result<VirtPageFrame, SystemError> {
+        let len = page_align_up(len);
+
+        let mut vm_flags = VmFlags::from(prot_flags)
+            | VmFlags::from(map_flags)
+            | VmFlags::VM_MAYREAD
+            | VmFlags::VM_MAYEXEC;
+        // 私有映射的修改不会写回文件，因此总是可以写入
+        if may_write || !map_flags.contains(MapFlags::MAP_SHARED) {
+            vm_flags |= VmFlags::VM_MAYWRITE;
+        }
+
+        let start_page = self.mmap(
+            round_hint_to_min(start_vaddr, true),
             PageFrameCount::from_bytes(len).unwrap(),
             prot_flags,
             map_flags,
-            move |page, count, flags, mapper, flusher| {
-                Ok(VMA::zeroed(page, count, vm_flags, flags, mapper, flusher)?)
+            move |page, count, flags, _mapper, _flusher| {
+                let mut vma = VMA::new(
+                    VirtRegion::new(page.virt_address(), count.bytes()),
+                    vm_flags,
+                    flags,
+                    true,
+                );
+                vma.set_provider(Provider::File { inode, pgoff, path });
+                Ok(LockedVMA::new(vma))
             },
         )?;
 
@@ -422,20 +525,65 @@ impl InnerAddressSpace {
             self.munmap(start_page, page_count)?;
         }
 
+        // 文件映射在新的内存区域中仍然映射同一个文件
+        let old_provider = self
+            .mappings
+            .contains(old_vaddr)
+            .ok_or(SystemError::EINVAL)?
+            .lock()
+            .provider_at(old_vaddr);
+
         // 获取映射后的新内存页面
-        let new_page = self.map_anonymous(new_vaddr, new_len, prot_flags, map_flags, true)?;
+        let new_page = match old_provider {
+            Provider::Allocated => {
+                self.map_anonymous(new_vaddr, new_len, prot_flags, map_flags, true, false)?
+            }
+            Provider::File { inode, pgoff, path } => self.map_file(
+                new_vaddr,
+                new_len,
+                prot_flags,
+                map_flags,
+                inode,
+                pgoff,
+                path,
+                vm_flags.contains(VmFlags::VM_MAYWRITE),
+            )?,
+        };
         let new_page_vaddr = new_page.virt_address();
+        let shared = vm_flags.contains(VmFlags::VM_SHARED);
 
         // 拷贝旧内存区域内容到新内存区域
-        let old_buffer_reader =
-            UserBufferReader::new(old_vaddr.data() as *const u8, old_len, true)?;
-        let old_buf: &[u8] = old_buffer_reader.read_from_user(0)?;
-        let mut new_buffer_writer =
-            UserBufferWriter::new(new_page_vaddr.data() as *mut u8, new_len, true)?;
-        let new_buf: &mut [u8] = new_buffer_writer.buffer(0)?;
-        let len = old_buf.len().min(new_buf.len());
-        for i in 0..len {
-            new_buf[i] = old_buf[i];
+        // 由于当前持有地址空间的锁，这里不能通过用户地址访问（否则会在缺页异常处理程序中死锁），
+        // 因此直接通过页表拷贝那些已经分配了物理页的页面
+        let page_flags = PageFlags::from_prot_flags(prot_flags, true);
+        let mut flusher: PageFlushAll<MMArch> = PageFlushAll::new();
+        let copy_pages = cmp::min(old_len, new_len) / MMArch::PAGE_SIZE;
+        for i in 0..copy_pages {
+            let old_page = old_vaddr + i * MMArch::PAGE_SIZE;
+            let old_paddr = match self.user_mapper.utable.translate(old_page) {
+                Some((paddr, _)) => paddr,
+                None => continue,
+            };
+
+            let new_page = new_page_vaddr + i * MMArch::PAGE_SIZE;
+            if shared {
+                // 共享映射的新旧区域必须映射同一个物理页
+                let flush = unsafe {
+                    self.user_mapper
+                        .utable
+                        .map_phys(new_page, old_paddr, page_flags)
+                }
+                .ok_or(SystemError::ENOMEM)?;
+                flusher.consume(flush);
+                page_frame_ref_inc(old_paddr);
+                continue;
+            }
+
+            let flush = unsafe { self.user_mapper.utable.map(new_page, page_flags) }
+                .ok_or(SystemError::ENOMEM)?;
+            flusher.consume(flush);
+            let new_paddr = self.user_mapper.utable.translate(new_page).unwrap().0;
+            unsafe { copy_page_frame(old_paddr, new_paddr) };
         }
 
         return Ok(new_page_vaddr);
@@ -468,8 +616,6 @@ impl InnerAddressSpace {
             let intersection = r.lock().region().intersect(&to_unmap).unwrap();
             let (before, r, after) = r.extract(intersection).unwrap();
 
-            // TODO: 当引入后备页映射后，这里需要增加通知文件的逻辑
-
             if let Some(before) = before {
                 // 如果前面有VMA，则需要将前面的VMA重新插入到地址空间的VMA列表中
                 self.mappings.insert_vma(before);
@@ -483,11 +629,69 @@ impl InnerAddressSpace {
             r.unmap(&mut self.user_mapper.utable, &mut flusher);
         }
 
-        // TODO: 当引入后备页映射后，这里需要增加通知文件的逻辑
-
         return Ok(());
     }
 
+    /// 收集进程的地址空间中，指定区域内的共享文件映射的脏页
+    ///
+    /// 区域内可写的页面会被重新写保护。由于写回时需要访问文件系统，本函数不进行写回，
+    /// 调用者需要在释放地址空间的锁之后，对返回的页面缓存调用`sync`
+    ///
+    /// # 参数
+    ///
+    /// - `start_page`：起始页帧
+    /// - `page_count`：页帧数量
+    ///
+    /// # Errors
+    ///
+    /// - `ENOMEM`：指定的区域没有被映射
+    pub fn msync(
+        &mut self,
+        start_page: VirtPageFrame,
+        page_count: PageFrameCount,
+    ) -> Result<Vec<Arc<PageCache>>, SystemError> {
+        let region = VirtRegion::new(start_page.virt_address(), page_count.bytes());
+        let vmas: Vec<Arc<LockedVMA>> = self.mappings.conflicts(region).collect::<Vec<_>>();
+        if vmas.is_empty() {
+            return Err(SystemError::ENOMEM);
+        }
+
+        let mut flusher: PageFlushAll<MMArch> = PageFlushAll::new();
+        let mut page_caches = Vec::new();
+        for vma in vmas {
+            let guard = vma.lock();
+            let intersection = guard.region().intersect(&region).unwrap();
+            if let Some(page_cache) = guard.write_protect_file_pages(
+                intersection,
+                &mut self.user_mapper.utable,
+                &mut flusher,
+            ) {
+                page_caches.push(page_cache);
+            }
+        }
+        drop(flusher);
+        // 其他核心上的线程可能还缓存着可写的表项
+        drop(InactiveFlusher::new());
+
+        return Ok(page_caches);
+    }
+
+    /// 获取指定区域内的共享文件映射所使用的页面缓存
+    ///
+    /// 用于在解除映射并释放地址空间的锁之后，把这些映射中被修改的页面写回文件
+    pub fn shared_page_caches(
+        &self,
+        start_page: VirtPageFrame,
+        page_count: PageFrameCount,
+    ) -> Vec<Arc<PageCache>> {
+        let region = VirtRegion::new(start_page.virt_address(), page_count.bytes());
+        return self
+            .mappings
+            .conflicts(region)
+            .filter_map(|vma| vma.lock().shared_page_cache())
+            .collect();
+    }
+
     pub fn mprotect(
         &mut self,
         start_page: VirtPageFrame,
@@ -544,6 +748,16 @@ impl InnerAddressSpace {
                 .set_write(prot_flags.contains(ProtFlags::PROT_WRITE));
 
             r_guard.remap(new_flags, mapper, &mut flusher)?;
+
+            // 同步更新VMA的访问权限，缺页异常处理程序会根据它来判断访问是否合法
+            let mut new_vm_flags = *r_guard.vm_flags();
+            new_vm_flags.set(VmFlags::VM_READ, prot_flags.contains(ProtFlags::PROT_READ));
+            new_vm_flags.set(
+                VmFlags::VM_WRITE,
+                prot_flags.contains(ProtFlags::PROT_WRITE),
+            );
+            new_vm_flags.set(VmFlags::VM_EXEC, prot_flags.contains(ProtFlags::PROT_EXEC));
+            r_guard.set_vm_flags(new_vm_flags);
             drop(r_guard);
             self.mappings.insert_vma(r);
         }
@@ -598,7 +812,7 @@ impl InnerAddressSpace {
             let len = new_brk - self.brk;
             let prot_flags = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE | ProtFlags::PROT_EXEC;
             let map_flags = MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS | MapFlags::MAP_FIXED;
-            self.map_anonymous(old_brk, len, prot_flags, map_flags, true)?;
+            self.map_anonymous(old_brk, len, prot_flags, map_flags, true, false)?;
 
             self.brk = new_brk;
             return Ok(old_brk);
@@ -636,9 +850,21 @@ impl InnerAddressSpace {
 
 impl Drop for InnerAddressSpace {
     fn drop(&mut self) {
+        let page_caches: Vec<Arc<PageCache>> = self
+            .mappings
+            .iter_vmas()
+            .filter_map(|vma| vma.lock().shared_page_cache())
+            .collect();
         unsafe {
             self.unmap_all();
         }
+
+        // 地址空间已经不再被使用，把共享文件映射中被修改的页面写回文件
+        for page_cache in page_caches {
+            if let Err(e) = page_cache.sync() {
+                kwarn!("Failed to write back file mapping: {:?}", e);
+            }
+        }
     }
 }
 
@@ -917,11 +1143,17 @@ impl LockedVMA {
         let mut guard = self.lock();
         assert!(guard.mapped);
         for page in guard.region.pages() {
-            // 暂时要求所有的页帧都已经映射到页表
-            // TODO: 引入Lazy Mapping, 通过缺页中断来映射页帧，这里就不必要求所有的页帧都已经映射到页表了
+            // 按需分页的VMA中，尚未被访问过的页面没有映射到页表，跳过即可
+            let (paddr, old_flags) = match mapper.translate(page.virt_address()) {
+                Some(x) => x,
+                None => continue,
+            };
             let r = unsafe {
                 mapper
-                    .remap(page.virt_address(), flags)
+                    .remap(
+                        page.virt_address(),
+                        guard.remap_page_flags(flags, paddr, old_flags),
+                    )
                     .expect("Failed to remap, beacuse of some page is not mapped")
             };
             flusher.consume(r);
@@ -931,23 +1163,20 @@ impl LockedVMA {
     }
 
     pub fn unmap(&self, mapper: &mut PageMapper, mut flusher: impl Flusher<MMArch>) {
-        // todo: 如果当前vma与文件相关，完善文件相关的逻辑
-
         let mut guard = self.lock();
         assert!(guard.mapped);
+
+        // 共享文件映射中被修改的页面已经在写缺页时被标记为脏页，由页面缓存负责写回
         for page in guard.region.pages() {
+            // 按需分页的VMA中，尚未被访问过的页面没有映射到页表，跳过即可
+            if mapper.translate(page.virt_address()).is_none() {
+                continue;
+            }
             let (paddr, _, flush) = unsafe { mapper.unmap_phys(page.virt_address(), true) }
                 .expect("Failed to unmap, beacuse of some page is not mapped");
 
-            // todo: 获取物理页的anon_vma的守卫
-
-            // todo: 从anon_vma中删除当前VMA
-
-            // todo: 如果物理页的anon_vma链表长度为0，则释放物理页.
-
-            // 目前由于还没有实现共享页，所以直接释放物理页也没问题。
-            // 但是在实现共享页之后，就不能直接释放物理页了，需要在anon_vma链表长度为0的时候才能释放物理页
-            unsafe { deallocate_page_frames(PhysPageFrame::new(paddr), PageFrameCount::new(1)) };
+            // 物理页可能仍被其他地址空间共享（写时复制），只有引用计数降为0时才会被释放
+            unsafe { page_frame_ref_dec(paddr) };
 
             flusher.consume(flush);
         }
@@ -996,7 +1225,7 @@ impl LockedVMA {
 
         let before: Option<Arc<LockedVMA>> = guard.region.before(&region).map(|virt_region| {
             let mut vma: VMA = unsafe { guard.clone() };
-            vma.region = virt_region;
+            vma.set_region(virt_region);
 
             let vma: Arc<LockedVMA> = LockedVMA::new(vma);
             vma
@@ -1004,13 +1233,13 @@ impl LockedVMA {
 
         let after: Option<Arc<LockedVMA>> = guard.region.after(&region).map(|virt_region| {
             let mut vma: VMA = unsafe { guard.clone() };
-            vma.region = virt_region;
+            vma.set_region(virt_region);
 
             let vma: Arc<LockedVMA> = LockedVMA::new(vma);
             vma
         });
 
-        guard.region = region;
+        guard.set_region(region);
 
         // TODO: 重新设置before、after这两个VMA里面的物理页的anon_vma
 
@@ -1045,9 +1274,19 @@ impl core::hash::Hash for VMA {
 }
 
 /// 描述不同类型的内存提供者或资源
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Provider {
-    Allocated, // TODO:其他
+    /// 匿名映射，物理页由页帧分配器分配
+    Allocated,
+    /// 文件映射，物理页来自文件的页面缓存
+    File {
+        /// 被映射的文件的inode
+        inode: Arc<dyn IndexNode>,
+        /// VMA的起始地址在文件中对应的页号
+        pgoff: usize,
+        /// 被映射的文件的路径，未知时为None
+        path: Option<String>,
+    },
 }
 
 #[allow(dead_code)]
@@ -1098,10 +1337,118 @@ impl VMA {
             mapped: self.mapped,
             user_address_space: self.user_address_space.clone(),
             self_ref: self.self_ref.clone(),
-            provider: Provider::Allocated,
+            provider: self.provider.clone(),
         };
     }
 
+    pub fn provider(&self) -> &Provider {
+        return &self.provider;
+    }
+
+    pub fn set_provider(&mut self, provider: Provider) {
+        self.provider = provider;
+    }
+
+    /// 获取从`vaddr`开始映射的内存提供者
+    ///
+    /// 对于文件映射，返回的`Provider`中的页号对应的是`vaddr`所在的页面
+    pub fn provider_at(&self, vaddr: VirtAddr) -> Provider {
+        match &self.provider {
+            Provider::Allocated => Provider::Allocated,
+            Provider::File { inode, pgoff, path } => Provider::File {
+                inode: inode.clone(),
+                pgoff: pgoff + (vaddr - self.region.start()) / MMArch::PAGE_SIZE,
+                path: path.clone(),
+            },
+        }
+    }
+
+    /// 修改VMA的虚拟地址范围。文件映射的VMA会同步调整其在文件中的起始页号
+    fn set_region(&mut self, region: VirtRegion) {
+        if let Provider::File { pgoff, .. } = &mut self.provider {
+            if region.start() >= self.region.start() {
+                *pgoff += (region.start() - self.region.start()) / MMArch::PAGE_SIZE;
+            } else {
+                *pgoff -= (self.region.start() - region.start()) / MMArch::PAGE_SIZE;
+            }
+        }
+        self.region = region;
+    }
+
+    /// 获取文件映射中，`vaddr`所在的页面对应的页面缓存和页号
+    ///
+    /// 如果当前VMA不是文件映射，返回None
+    pub fn file_page(&self, vaddr: VirtAddr) -> Option<(Arc<PageCache>, usize)> {
+        match &self.provider {
+            Provider::Allocated => None,
+            Provider::File { inode, pgoff, .. } => {
+                let index = pgoff + (vaddr - self.region.start()) / MMArch::PAGE_SIZE;
+                Some((inode.page_cache()?, index))
+            }
+        }
+    }
+
+    /// 获取共享文件映射所使用的页面缓存
+    ///
+    /// 对于匿名映射和私有文件映射，返回None
+    pub fn shared_page_cache(&self) -> Option<Arc<PageCache>> {
+        if !self.vm_flags.contains(VmFlags::VM_SHARED) {
+            return None;
+        }
+        return self
+            .file_page(self.region.start())
+            .map(|(page_cache, _)| page_cache);
+    }
+
+    /// 重新对共享文件映射中，`region`范围内可写的页面进行写保护
+    ///
+    /// 共享文件映射的页面在第一次写入时才会变为可写，并在写缺页中被标记为脏页。
+    /// 写回之前重新进行写保护，使得写回之后的写入能够再次被记录下来。
+    ///
+    /// 本函数不会写回数据，调用者需要在释放地址空间的锁之后，对返回的页面缓存调用`sync`
+    pub fn write_protect_file_pages(
+        &self,
+        region: VirtRegion,
+        mapper: &mut PageMapper,
+        mut flusher: impl Flusher<MMArch>,
+    ) -> Option<Arc<PageCache>> {
+        let page_cache = self.shared_page_cache()?;
+
+        for page in region.pages() {
+            let vaddr = page.virt_address();
+            let flags = match mapper.translate(vaddr) {
+                Some((_, flags)) if flags.has_write() => flags,
+                _ => continue,
+            };
+            let (_, index) = self.file_page(vaddr).unwrap();
+            page_cache.mark_dirty(index);
+            let flush = unsafe { mapper.remap(vaddr, flags.set_write(false)) }.unwrap();
+            flusher.consume(flush);
+        }
+
+        return Some(page_cache);
+    }
+
+    /// 计算重新映射VMA中已经映射的页面时，页表项应当使用的标志位
+    ///
+    /// - 私有映射中仍被多个地址空间共享的页面（写时复制）必须保持只读，等到写缺页时再复制
+    /// - 共享文件映射中还没有被写入过的页面必须保持只读，等到写缺页时再标记为脏页
+    fn remap_page_flags(
+        &self,
+        flags: PageFlags<MMArch>,
+        paddr: PhysAddr,
+        old_flags: PageFlags<MMArch>,
+    ) -> PageFlags<MMArch> {
+        let shared = self.vm_flags.contains(VmFlags::VM_SHARED);
+        if !shared && page_frame_ref_count(paddr) > 1 {
+            return flags.set_write(false);
+        }
+        if shared && !old_flags.has_write() && matches!(self.provider, Provider::File { .. }) {
+            return flags.set_write(false);
+        }
+        return flags;
+    }
+
     #[inline(always)]
     pub fn flags(&self) -> PageFlags<MMArch> {
         return self.flags;
@@ -1123,11 +1470,17 @@ impl VMA {
         assert!(self.mapped);
         for page in self.region.pages() {
             // kdebug!("remap page {:?}", page.virt_address());
-            // 暂时要求所有的页帧都已经映射到页表
-            // TODO: 引入Lazy Mapping, 通过缺页中断来映射页帧，这里就不必要求所有的页帧都已经映射到页表了
+            // 按需分页的VMA中，尚未被访问过的页面没有映射到页表，跳过即可
+            let (paddr, old_flags) = match mapper.translate(page.virt_address()) {
+                Some(x) => x,
+                None => continue,
+            };
             let r = unsafe {
                 mapper
-                    .remap(page.virt_address(), flags)
+                    .remap(
+                        page.virt_address(),
+                        self.remap_page_flags(flags, paddr, old_flags),
+                    )
                     .expect("Failed to remap, beacuse of some page is not mapped")
             };
             // kdebug!("consume page {:?}", page.virt_address());
@@ -1149,8 +1502,10 @@ impl VMA {
 
         match self.provider {
             Provider::Allocated { .. } => true,
-
-            #[allow(unreachable_patterns)]
+            // 以只读方式打开的文件，不能被共享映射为可写的
+            Provider::File { .. } if prot_flags.contains(ProtFlags::PROT_WRITE) => {
+                self.vm_flags.contains(VmFlags::VM_MAYWRITE)
+            }
             _ => is_downgrade,
         }
     }
@@ -1303,6 +1658,38 @@ impl Ord for VMA {
     }
 }
 
+/// 对齐mmap的地址提示
+///
+/// 先把`hint`向下对齐到页边界，如果`round_to_min`为`true`，且`hint`不为0，
+/// 则把小于`DEFAULT_MMAP_MIN_ADDR`的`hint`对齐到`DEFAULT_MMAP_MIN_ADDR`。`hint`为0时返回None
+fn round_hint_to_min(hint: VirtAddr, round_to_min: bool) -> Option<VirtAddr> {
+    // 先把hint向下对齐到页边界
+    let addr = hint.data() & (!MMArch::PAGE_OFFSET_MASK);
+    // 如果hint不是0，且hint小于DEFAULT_MMAP_MIN_ADDR，则对齐到DEFAULT_MMAP_MIN_ADDR
+    if (addr != 0) && round_to_min && (addr < DEFAULT_MMAP_MIN_ADDR) {
+        Some(VirtAddr::new(page_align_up(DEFAULT_MMAP_MIN_ADDR)))
+    } else if addr == 0 {
+        None
+    } else {
+        Some(VirtAddr::new(addr))
+    }
+}
+
+/// 将一个物理页帧的内容拷贝到另一个物理页帧
+///
+/// ## 安全性
+///
+/// 调用者需要保证`src`和`dst`都是有效的、页对齐的物理页帧，且二者不重叠
+pub(super) unsafe fn copy_page_frame(src: PhysAddr, dst: PhysAddr) {
+    let src = MMArch::phys_2_virt(src)
+        .expect("Phys2Virt: vaddr overflow.")
+        .data() as *const u8;
+    let dst = MMArch::phys_2_virt(dst)
+        .expect("Phys2Virt: vaddr overflow.")
+        .data() as *mut u8;
+    dst.copy_from_nonoverlapping(src, MMArch::PAGE_SIZE);
+}
+
 #[derive(Debug)]
 pub struct UserStack {
     // 栈底地址
@@ -1348,6 +1735,7 @@ impl UserStack {
             prot_flags,
             map_flags,
             false,
+            false,
         )?;
         // test_buddy();
         // 设置保护页只读
@@ -1399,6 +1787,7 @@ impl UserStack {
             prot_flags,
             map_flags,
             false,
+            false,
         )?;
 
         return Ok(());
@@ -1433,6 +1822,7 @@ impl UserStack {
             prot_flags,
             map_flags,
             false,
+            false,
         )?;
 
         return Ok(());
@@ -35,6 +35,67 @@ pub mod syscall;
 #[derive(Debug, Clone)]
 pub struct LockedEventPoll(Arc<SpinLock<EventPoll>>);
 
+impl LockedEventPoll {
+    /// 创建一个不属于任何文件的epoll对象，poll和select使用它来监听文件的事件
+    pub fn new() -> Self {
+        let epoll = Self(Arc::new(SpinLock::new(EventPoll::new())));
+        epoll.0.lock_irqsave().self_ref = Some(Arc::downgrade(&epoll.0));
+        return epoll;
+    }
+
+    /// ## 监听文件的事件
+    ///
+    /// 文件状态发生变化时，会唤醒在这个epoll上等待的进程
+    ///
+    /// ## 参数
+    ///
+    /// - `fd`: 文件对应的描述符
+    /// - `file`: 要监听的文件
+    /// - `events`: 感兴趣的事件
+    pub fn watch(
+        &self,
+        fd: i32,
+        file: Arc<SpinLock<File>>,
+        events: EPollEventType,
+    ) -> Result<(), SystemError> {
+        let epitem = Arc::new(EPollItem::new(
+            Arc::downgrade(&self.0),
+            EPollEvent {
+                events: events.bits(),
+                data: fd as u64,
+            },
+            fd,
+            Arc::downgrade(&file),
+        ));
+        let mut epoll_guard = self.0.lock_irqsave();
+        if epoll_guard.ep_items.get(&fd).is_some() {
+            return Err(SystemError::EEXIST);
+        }
+        let r = EventPoll::ep_insert(&mut epoll_guard, file, epitem);
+        if r.is_err() {
+            epoll_guard.ep_items.remove(&fd);
+        }
+        return r;
+    }
+
+    /// ## 停止监听所有文件
+    pub fn unwatch_all(&self) {
+        let mut epoll_guard = self.0.lock_irqsave();
+        epoll_guard.ep_unregister_all();
+    }
+
+    /// ## 把当前进程加入epoll的等待队列，并标记为睡眠状态，但是不调度
+    ///
+    /// ## Safety
+    ///
+    /// 调用前必须关闭中断，调用者需要在之后手动调用调度函数
+    pub unsafe fn sleep_without_schedule(&self) {
+        let guard = self.0.lock_irqsave();
+        guard.epoll_wq.sleep_without_schedule();
+        drop(guard);
+    }
+}
+
 /// 内核的Epoll对象结构体，当用户创建一个Epoll时，内核就会创建一个该类型对象
 /// 它对应一个epfd
 #[derive(Debug)]
@@ -164,8 +225,12 @@ impl IndexNode for EPollInode {
     }
 
     fn poll(&self, _private_data: &FilePrivateData) -> Result<usize, SystemError> {
-        // 需要实现epoll嵌套epoll时，需要实现这里
-        todo!()
+        // TODO: 实现epoll嵌套epoll时，需要在这里重新检查就绪队列中的事件
+        let mut events = EPollEventType::empty();
+        if self.epoll.0.lock_irqsave().ep_events_available() {
+            events.insert(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM);
+        }
+        Ok(events.bits() as usize)
     }
 
     fn fs(&self) -> Arc<dyn crate::filesystem::vfs::FileSystem> {
@@ -192,23 +257,8 @@ impl IndexNode for EPollInode {
         epoll.shutdown.store(true, Ordering::SeqCst);
         epoll.ep_wake_all();
 
-        let fds = epoll.ep_items.keys().cloned().collect::<Vec<_>>();
-
         // 清理红黑树里面的epitems
-        for fd in fds {
-            let file = ProcessManager::current_pcb()
-                .fd_table()
-                .read()
-                .get_file_by_fd(fd);
-
-            if file.is_some() {
-                file.unwrap()
-                    .lock_irqsave()
-                    .remove_epoll(&Arc::downgrade(&self.epoll.0))?;
-            }
-
-            epoll.ep_items.remove(&fd);
-        }
+        epoll.ep_unregister_all();
 
         Ok(())
     }
@@ -643,7 +693,8 @@ impl EventPoll {
 
         let _ = epoll
             .ready_list
-            .extract_if(|item| Arc::ptr_eq(item, &epitem));
+            .extract_if(|item| Arc::ptr_eq(item, &epitem))
+            .count();
 
         Ok(())
     }
@@ -678,6 +729,22 @@ impl EventPoll {
         Ok(())
     }
 
+    /// ### 从所有监听的文件上移除epitem，并清空红黑树和就绪队列
+    ///
+    /// 通过epitem中保存的文件指针找到文件，即使文件描述符已经被关闭或者复用也不会出错
+    fn ep_unregister_all(&mut self) {
+        let self_ref = self.self_ref.clone().unwrap();
+        let fds = self.ep_items.keys().cloned().collect::<Vec<_>>();
+        for fd in fds {
+            if let Some(epitem) = self.ep_items.remove(&fd) {
+                if let Some(file) = epitem.file().upgrade() {
+                    file.lock_irqsave().remove_epoll(&self_ref).ok();
+                }
+            }
+        }
+        self.ready_list.clear();
+    }
+
     /// ### 判断epoll是否有就绪item
     pub fn ep_events_available(&self) -> bool {
         !self.ready_list.is_empty()
@@ -708,24 +775,35 @@ impl EventPoll {
     }
 
     /// ### epoll的回调，支持epoll的文件有事件到来时直接调用该方法即可
+    ///
+    /// 同一个文件可能被多个epoll（包括poll和select内部使用的epoll）监听，需要通知所有的epitem
     pub fn wakeup_epoll(
         epitems: &SpinLock<LinkedList<Arc<EPollItem>>>,
         pollflags: EPollEventType,
     ) -> Result<(), SystemError> {
         let mut epitems_guard = epitems.try_lock_irqsave()?;
-        // 一次只取一个，因为一次也只有一个进程能拿到对应文件的🔓
-        if let Some(epitem) = epitems_guard.pop_front() {
-            let epoll = epitem.epoll().upgrade().unwrap();
-            let mut epoll_guard = epoll.try_lock()?;
-            let binding = epitem.clone();
-            let event_guard = binding.event().read();
-            let ep_events = EPollEventType::from_bits_truncate(event_guard.events());
+        // epoll已经被释放，但是epitem还没有从文件上移除
+        let _ = epitems_guard
+            .extract_if(|epitem| epitem.epoll().upgrade().is_none())
+            .count();
+
+        for epitem in epitems_guard.iter() {
+            let epoll = match epitem.epoll().upgrade() {
+                Some(epoll) => epoll,
+                None => continue,
+            };
+            // 这个epoll正在被其他地方使用时跳过，等待者在睡眠前后会再次检查事件，不会一直睡眠
+            let mut epoll_guard = match epoll.try_lock_irqsave() {
+                Ok(guard) => guard,
+                Err(_) => continue,
+            };
+            let ep_events = EPollEventType::from_bits_truncate(epitem.event().read().events());
 
             // 检查事件合理性以及是否有感兴趣的事件
-            if !(ep_events
+            if !ep_events
                 .difference(EPollEventType::EP_PRIVATE_BITS)
                 .is_empty()
-                || pollflags.difference(ep_events).is_empty())
+                && (pollflags.is_empty() || pollflags.intersects(ep_events))
             {
                 // TODO: 未处理pm相关
 
@@ -743,8 +821,6 @@ impl EventPoll {
                     }
                 }
             }
-
-            epitems_guard.push_back(epitem);
         }
         Ok(())
     }
@@ -8,8 +8,11 @@ use alloc::{collections::BTreeMap, sync::Arc};
 use crate::{driver::net::NetDriver, libs::rwlock::RwLock};
 use smoltcp::wire::IpEndpoint;
 
+use self::socket::{netlink::NetlinkEndpoint, unix::UnixEndpoint};
+
 pub mod event_poll;
 pub mod net_core;
+pub mod netdevice;
 pub mod socket;
 pub mod syscall;
 
@@ -41,9 +44,12 @@ pub enum Endpoint {
     LinkLayer(LinkLayerEndpoint),
     /// 网络层端点
     Ip(Option<IpEndpoint>),
+    /// Unix域端点
+    Unix(UnixEndpoint),
+    /// Netlink端点
+    Netlink(NetlinkEndpoint),
     /// 不需要端点
     Unused,
-    // todo: 增加NetLink机制后，增加NetLink端点
 }
 
 /// @brief 链路层端点
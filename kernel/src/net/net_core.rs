@@ -1,29 +1,42 @@
-use alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
-use smoltcp::{socket::dhcpv4, wire};
+use alloc::{boxed::Box, string::ToString, sync::Arc, vec::Vec};
+use smoltcp::{iface::SocketSet, socket::dhcpv4, wire};
 use system_error::SystemError;
 
 use crate::{
-    driver::net::NetDriver,
+    arch::sched::sched,
+    driver::net::{
+        loopback::{is_loopback, loopback_init},
+        IfFlags, NetDriver,
+    },
     kdebug, kinfo, kwarn,
-    libs::rwlock::RwLockReadGuard,
+    libs::spinlock::SpinLock,
     net::{socket::SocketPollMethod, NET_DRIVERS},
+    process::{
+        kthread::{KernelThreadClosure, KernelThreadMechanism},
+        ProcessControlBlock, ProcessManager,
+    },
     time::timer::{next_n_ms_timer_jiffies, Timer, TimerFunction},
 };
 
 use super::{
     event_poll::{EPollEventType, EventPoll},
-    socket::{sockets::TcpSocket, HANDLE_MAP, SOCKET_SET},
+    socket::{sockets::TcpSocket, SocketKey, HANDLE_MAP},
 };
 
+/// 网络接收线程
+static mut NET_RX_THREAD: Option<Arc<ProcessControlBlock>> = None;
+/// 是否有等待网络接收线程处理的数据包
+static NET_RX_PENDING: SpinLock<bool> = SpinLock::new(false);
+
 /// The network poll function, which will be called by timer.
 ///
-/// The main purpose of this function is to poll all network interfaces.
+/// The main purpose of this function is to wake up the network rx thread to poll all network interfaces.
 #[derive(Debug)]
 struct NetWorkPollFunc;
 
 impl TimerFunction for NetWorkPollFunc {
     fn run(&mut self) -> Result<(), SystemError> {
-        poll_ifaces_try_lock(10).ok();
+        wakeup_net_rx_thread();
         let next_time = next_n_ms_timer_jiffies(10);
         let timer = Timer::new(Box::new(NetWorkPollFunc), next_time);
         timer.activate();
@@ -32,6 +45,8 @@ impl TimerFunction for NetWorkPollFunc {
 }
 
 pub fn net_init() -> Result<(), SystemError> {
+    loopback_init();
+    net_rx_thread_init();
     dhcp_query()?;
     // Init poll timer function
     // let next_time = next_n_ms_timer_jiffies(5);
@@ -43,7 +58,12 @@ pub fn net_init() -> Result<(), SystemError> {
 fn dhcp_query() -> Result<(), SystemError> {
     let binding = NET_DRIVERS.write_irqsave();
 
-    let net_face = binding.get(&0).ok_or(SystemError::ENODEV)?.clone();
+    // 回环接口的地址是固定的，只在网卡上进行DHCP
+    let net_face = binding
+        .values()
+        .find(|iface| !is_loopback(iface))
+        .ok_or(SystemError::ENODEV)?
+        .clone();
 
     drop(binding);
 
@@ -56,13 +76,13 @@ fn dhcp_query() -> Result<(), SystemError> {
     // IMPORTANT: This should be removed in production.
     dhcp_socket.set_max_lease_duration(Some(smoltcp::time::Duration::from_secs(10)));
 
-    let dhcp_handle = SOCKET_SET.lock_irqsave().add(dhcp_socket);
+    let dhcp_handle = net_face.sockets().lock_irqsave().add(dhcp_socket);
 
     const DHCP_TRY_ROUND: u8 = 10;
     for i in 0..DHCP_TRY_ROUND {
         kdebug!("DHCP try round: {}", i);
-        net_face.poll(&mut SOCKET_SET.lock_irqsave()).ok();
-        let mut binding = SOCKET_SET.lock_irqsave();
+        poll_iface(&net_face).ok();
+        let mut binding = net_face.sockets().lock_irqsave();
         let event = binding.get_mut::<dhcpv4::Socket>(dhcp_handle).poll();
 
         match event {
@@ -119,78 +139,137 @@ fn dhcp_query() -> Result<(), SystemError> {
     return Err(SystemError::ETIMEDOUT);
 }
 
+/// ## 获取所有已经启用的网络接口
+pub fn up_ifaces() -> Vec<Arc<dyn NetDriver>> {
+    return NET_DRIVERS
+        .read_irqsave()
+        .values()
+        .filter(|iface| iface.flags().contains(IfFlags::UP))
+        .cloned()
+        .collect();
+}
+
+/// ## 选择新创建的socket所在的网络接口
+///
+/// socket在绑定地址或者连接之前不知道会使用哪个接口，先放在第一个网卡上。没有网卡时放在回环接口上
+pub fn default_iface() -> Option<Arc<dyn NetDriver>> {
+    let guard = NET_DRIVERS.read_irqsave();
+    let up = guard
+        .values()
+        .find(|iface| iface.flags().contains(IfFlags::UP) && !is_loopback(iface));
+    return up
+        .or_else(|| guard.values().find(|iface| !is_loopback(iface)))
+        .or_else(|| guard.values().next())
+        .cloned();
+}
+
+/// ## 查找拥有地址`addr`的网络接口
+pub fn local_iface(addr: &wire::IpAddress) -> Option<Arc<dyn NetDriver>> {
+    return NET_DRIVERS
+        .read_irqsave()
+        .values()
+        .find(|iface| {
+            iface
+                .inner_iface()
+                .lock()
+                .ip_addrs()
+                .iter()
+                .any(|cidr| cidr.address() == *addr)
+        })
+        .cloned();
+}
+
+/// ## 选择发往`addr`的数据包使用的网络接口
+///
+/// 只考虑已经启用的接口。优先选择地址与`addr`在同一个网段的接口，否则选择第一个网卡
+pub fn route_iface(addr: &wire::IpAddress) -> Option<Arc<dyn NetDriver>> {
+    let ifaces = up_ifaces();
+    let direct = ifaces.iter().find(|iface| {
+        iface
+            .inner_iface()
+            .lock()
+            .ip_addrs()
+            .iter()
+            .any(|cidr| cidr.contains_addr(addr))
+    });
+    if let Some(iface) = direct {
+        return Some(iface.clone());
+    }
+    return ifaces.into_iter().find(|iface| !is_loopback(iface));
+}
+
+/// ## 轮询一个网络接口
+///
+/// 只会锁住这个接口的socket集合，轮询之后唤醒集合中有事件发生的socket
+pub fn poll_iface(iface: &Arc<dyn NetDriver>) -> Result<(), SystemError> {
+    let mut sockets = iface.sockets().lock_irqsave();
+    iface.poll(&mut sockets).ok();
+    return send_event(iface.nic_id(), &sockets);
+}
+
+/// 轮询所有已经启用的网络接口。每个接口只处理自己的socket集合
 pub fn poll_ifaces() {
-    let guard: RwLockReadGuard<BTreeMap<usize, Arc<dyn NetDriver>>> = NET_DRIVERS.read_irqsave();
-    if guard.len() == 0 {
+    let ifaces = up_ifaces();
+    if ifaces.is_empty() {
         kwarn!("poll_ifaces: No net driver found!");
         return;
     }
-    let mut sockets = SOCKET_SET.lock_irqsave();
-    for (_, iface) in guard.iter() {
-        iface.poll(&mut sockets).ok();
+    for iface in ifaces.iter() {
+        poll_iface(iface).ok();
     }
-    let _ = send_event(&sockets);
 }
 
-/// 对ifaces进行轮询，最多对SOCKET_SET尝试times次加锁。
+/// @brief 创建网络接收线程
+fn net_rx_thread_init() {
+    let closure = KernelThreadClosure::StaticEmptyClosure((&(net_rx_thread as fn() -> i32), ()));
+    let pcb = KernelThreadMechanism::create_and_run(closure, "net_rx".to_string())
+        .ok_or("")
+        .expect("create net_rx thread failed");
+    unsafe {
+        NET_RX_THREAD = Some(pcb);
+    }
+}
+
+/// ## 网络接收线程
 ///
-/// @return 轮询成功，返回Ok(())
-/// @return 加锁超时，返回SystemError::EAGAIN_OR_EWOULDBLOCK
-/// @return 没有网卡，返回SystemError::ENODEV
-pub fn poll_ifaces_try_lock(times: u16) -> Result<(), SystemError> {
-    let mut i = 0;
-    while i < times {
-        let guard: RwLockReadGuard<BTreeMap<usize, Arc<dyn NetDriver>>> =
-            NET_DRIVERS.read_irqsave();
-        if guard.len() == 0 {
-            kwarn!("poll_ifaces: No net driver found!");
-            // 没有网卡，返回错误
-            return Err(SystemError::ENODEV);
-        }
-        let sockets = SOCKET_SET.try_lock_irqsave();
-        // 加锁失败，继续尝试
-        if sockets.is_err() {
-            i += 1;
+/// 网卡中断只负责唤醒这个线程，收到的数据包在这里通过轮询网络接口来处理，
+/// 这样中断上下文中不需要获取socket集合的锁
+fn net_rx_thread() -> i32 {
+    loop {
+        let mut pending = NET_RX_PENDING.lock_irqsave();
+        if !*pending {
+            // 在持有锁的时候标记为睡眠，避免错过在这之后到来的唤醒
+            ProcessManager::mark_sleep(true).ok();
+            drop(pending);
+            sched();
             continue;
         }
+        *pending = false;
+        drop(pending);
 
-        let mut sockets = sockets.unwrap();
-        for (_, iface) in guard.iter() {
-            iface.poll(&mut sockets).ok();
-        }
-        send_event(&sockets)?;
-        return Ok(());
+        poll_ifaces();
     }
-
-    // 尝试次数用完，返回错误
-    return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
 }
 
-/// 对ifaces进行轮询，最多对SOCKET_SET尝试一次加锁。
+/// ## 唤醒网络接收线程
 ///
-/// @return 轮询成功，返回Ok(())
-/// @return 加锁超时，返回SystemError::EAGAIN_OR_EWOULDBLOCK
-/// @return 没有网卡，返回SystemError::ENODEV
-pub fn poll_ifaces_try_lock_onetime() -> Result<(), SystemError> {
-    let guard: RwLockReadGuard<BTreeMap<usize, Arc<dyn NetDriver>>> = NET_DRIVERS.read_irqsave();
-    if guard.len() == 0 {
-        kwarn!("poll_ifaces: No net driver found!");
-        // 没有网卡，返回错误
-        return Err(SystemError::ENODEV);
+/// 可以在中断上下文中调用
+pub fn wakeup_net_rx_thread() {
+    *NET_RX_PENDING.lock_irqsave() = true;
+    if let Some(pcb) = unsafe { NET_RX_THREAD.as_ref() } {
+        ProcessManager::wakeup(pcb).ok();
     }
-    let mut sockets = SOCKET_SET.try_lock_irqsave()?;
-    for (_, iface) in guard.iter() {
-        iface.poll(&mut sockets).ok();
-    }
-    send_event(&sockets)?;
-    return Ok(());
 }
 
 /// ### 处理轮询后的事件
-fn send_event(sockets: &smoltcp::iface::SocketSet) -> Result<(), SystemError> {
+///
+/// ## 参数
+/// - `iface_id`: socket集合所属的网络接口的id
+/// - `sockets`: 刚刚被轮询过的socket集合
+fn send_event(iface_id: usize, sockets: &SocketSet) -> Result<(), SystemError> {
     for (handle, socket_type) in sockets.iter() {
         let handle_guard = HANDLE_MAP.read_irqsave();
-        let item = handle_guard.get(&handle);
+        let item = handle_guard.get(&SocketKey::new(iface_id, handle));
         if item.is_none() {
             continue;
         }
@@ -204,11 +283,7 @@ fn send_event(sockets: &smoltcp::iface::SocketSet) -> Result<(), SystemError> {
         // 分发到相应类型socket处理
         match socket_type {
             smoltcp::socket::Socket::Raw(_) | smoltcp::socket::Socket::Udp(_) => {
-                handle_guard
-                    .get(&handle)
-                    .unwrap()
-                    .wait_queue
-                    .wakeup_any(events);
+                handle_item.wait_queue.wakeup_any(events);
             }
             smoltcp::socket::Socket::Icmp(_) => unimplemented!("Icmp socket hasn't unimplemented"),
             smoltcp::socket::Socket::Tcp(inner_socket) => {
@@ -218,20 +293,14 @@ fn send_event(sockets: &smoltcp::iface::SocketSet) -> Result<(), SystemError> {
                 if inner_socket.state() == smoltcp::socket::tcp::State::Established {
                     events |= TcpSocket::CAN_CONNECT;
                 }
-                handle_guard
-                    .get(&handle)
-                    .unwrap()
-                    .wait_queue
-                    .wakeup_any(events);
+                handle_item.check_connect_result(inner_socket.state());
+                handle_item.wait_queue.wakeup_any(events);
             }
             smoltcp::socket::Socket::Dhcpv4(_) => {}
             smoltcp::socket::Socket::Dns(_) => unimplemented!("Dns socket hasn't unimplemented"),
         }
-        drop(handle_guard);
-        let mut handle_guard = HANDLE_MAP.write_irqsave();
-        let handle_item = handle_guard.get_mut(&handle).unwrap();
         EventPoll::wakeup_epoll(
-            &mut handle_item.epitems,
+            &handle_item.epitems,
             EPollEventType::from_bits_truncate(events as u32),
         )?;
         // crate::kdebug!(
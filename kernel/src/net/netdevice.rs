@@ -0,0 +1,342 @@
+//! 网络接口的配置
+//!
+//! 实现socket上用于查询和配置网络接口的ioctl命令，例如`ifconfig`使用的SIOCGIFCONF、SIOCSIFADDR等。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/net/core/dev_ioctl.c
+
+use core::mem::size_of;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use smoltcp::wire::{self, IpCidr, Ipv4Address, Ipv4Cidr};
+use system_error::SystemError;
+
+use crate::{
+    driver::net::{loopback::is_loopback, IfFlags, NetDriver},
+    process::ProcessManager,
+    syscall::user_access::{UserBufferReader, UserBufferWriter},
+};
+
+use super::{
+    socket::AddressFamily,
+    syscall::{SockAddrIn, SockAddrPlaceholder},
+    NET_DRIVERS,
+};
+
+/// 获取所有接口的地址列表
+pub const SIOCGIFCONF: u32 = 0x8912;
+/// 获取接口的标志
+pub const SIOCGIFFLAGS: u32 = 0x8913;
+/// 设置接口的标志
+pub const SIOCSIFFLAGS: u32 = 0x8914;
+/// 获取接口的IPv4地址
+pub const SIOCGIFADDR: u32 = 0x8915;
+/// 设置接口的IPv4地址
+pub const SIOCSIFADDR: u32 = 0x8916;
+/// 获取接口的子网掩码
+pub const SIOCGIFNETMASK: u32 = 0x891b;
+/// 设置接口的子网掩码
+pub const SIOCSIFNETMASK: u32 = 0x891c;
+/// 获取接口的硬件地址
+pub const SIOCGIFHWADDR: u32 = 0x8927;
+/// 根据接口名获取接口的索引号
+pub const SIOCGIFINDEX: u32 = 0x8933;
+
+/// 接口名的最大长度（包括结尾的'\0'）
+pub const IFNAMSIZ: usize = 16;
+
+/// 以太网设备的硬件类型
+pub const ARPHRD_ETHER: u16 = 1;
+/// 回环设备的硬件类型
+pub const ARPHRD_LOOPBACK: u16 = 772;
+
+/// 用户传入ioctl的接口请求
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/include/uapi/linux/if.h#234
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IfReq {
+    /// 接口名
+    pub ifr_name: [u8; IFNAMSIZ],
+    pub ifr_ifru: IfReqData,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union IfReqData {
+    pub addr: SockAddrIn,
+    pub hwaddr: SockAddrPlaceholder,
+    pub flags: i16,
+    pub ifindex: i32,
+    /// 使联合体的大小与Linux的一致
+    _pad: [u64; 3],
+}
+
+/// SIOCGIFCONF使用的缓冲区
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct IfConf {
+    /// 缓冲区的长度，返回时为写入的长度
+    pub ifc_len: i32,
+    /// 缓冲区，为空时只返回需要的长度
+    pub ifc_buf: *mut IfReq,
+}
+
+/// ## 获取接口的索引号
+///
+/// 索引号从1开始，0表示不指定接口
+#[inline]
+pub fn iface_index(iface: &Arc<dyn NetDriver>) -> u32 {
+    return iface.nic_id() as u32 + 1;
+}
+
+/// 根据索引号查找接口
+pub fn iface_by_index(index: u32) -> Option<Arc<dyn NetDriver>> {
+    if index == 0 {
+        return None;
+    }
+    return NET_DRIVERS
+        .read_irqsave()
+        .get(&(index as usize - 1))
+        .cloned();
+}
+
+/// 根据接口名查找接口
+pub fn iface_by_name(name: &str) -> Option<Arc<dyn NetDriver>> {
+    return NET_DRIVERS
+        .read_irqsave()
+        .values()
+        .find(|iface| iface.name() == name)
+        .cloned();
+}
+
+/// 获取接口的硬件类型
+pub fn iface_hardware_type(iface: &Arc<dyn NetDriver>) -> u16 {
+    if is_loopback(iface) {
+        return ARPHRD_LOOPBACK;
+    }
+    return ARPHRD_ETHER;
+}
+
+/// 获取接口的MTU
+pub fn iface_mtu(iface: &Arc<dyn NetDriver>) -> u32 {
+    if is_loopback(iface) {
+        return 65536;
+    }
+    return 1500;
+}
+
+/// ## 修改接口的标志
+///
+/// 只修改用户可以修改的标志。启用或者关闭接口时，同时设置或者清除RUNNING标志
+pub fn change_iface_flags(iface: &Arc<dyn NetDriver>, flags: IfFlags) {
+    let old = iface.flags();
+    let mut new = (old - IfFlags::USER_CHANGEABLE) | (flags & IfFlags::USER_CHANGEABLE);
+    new.set(IfFlags::RUNNING, new.contains(IfFlags::UP));
+    iface.set_flags(new);
+}
+
+/// 获取接口的IPv4地址，未配置地址时返回None
+pub fn iface_ipv4_cidr(iface: &Arc<dyn NetDriver>) -> Option<Ipv4Cidr> {
+    return iface
+        .inner_iface()
+        .lock()
+        .ip_addrs()
+        .iter()
+        .find_map(|cidr| match cidr {
+            IpCidr::Ipv4(cidr) if !cidr.address().is_unspecified() => Some(*cidr),
+            _ => None,
+        });
+}
+
+/// ## 设置接口的IPv4地址
+///
+/// 替换接口原有的IPv4地址，`cidr`为None时删除接口的IPv4地址。接口的IPv6地址保持不变
+pub fn set_iface_ipv4_cidr(
+    iface: &Arc<dyn NetDriver>,
+    cidr: Option<Ipv4Cidr>,
+) -> Result<(), SystemError> {
+    let mut r = Ok(());
+    iface.inner_iface().lock().update_ip_addrs(|addrs| {
+        let others: Vec<IpCidr> = addrs
+            .iter()
+            .filter(|addr| !matches!(addr, IpCidr::Ipv4(_)))
+            .cloned()
+            .collect();
+        addrs.clear();
+        for addr in cidr.map(IpCidr::Ipv4).into_iter().chain(others) {
+            if addrs.push(addr).is_err() {
+                r = Err(SystemError::ENOSPC);
+                return;
+            }
+        }
+    });
+    return r;
+}
+
+/// 按照地址的类别得到默认的前缀长度
+fn classful_prefix_len(addr: &Ipv4Address) -> u8 {
+    match addr.0[0] {
+        0..=127 => 8,
+        128..=191 => 16,
+        192..=223 => 24,
+        _ => 32,
+    }
+}
+
+fn ipv4_to_sockaddr(addr: &Ipv4Address) -> SockAddrIn {
+    return SockAddrIn {
+        sin_family: AddressFamily::INet as u16,
+        sin_port: 0,
+        sin_addr: u32::from_be_bytes(addr.0).to_be(),
+        sin_zero: [0; 8],
+    };
+}
+
+fn sockaddr_to_ipv4(addr: &SockAddrIn) -> Result<Ipv4Address, SystemError> {
+    if addr.sin_family != AddressFamily::INet as u16 {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok(Ipv4Address::from_bytes(
+        &u32::from_be(addr.sin_addr).to_be_bytes(),
+    ));
+}
+
+/// 从IfReq中取出接口名
+fn ifreq_name(req: &IfReq) -> Result<String, SystemError> {
+    let len = req
+        .ifr_name
+        .iter()
+        .position(|c| *c == 0)
+        .unwrap_or(IFNAMSIZ);
+    let name = core::str::from_utf8(&req.ifr_name[..len]).map_err(|_| SystemError::ENODEV)?;
+    return Ok(String::from(name));
+}
+
+/// ## 处理网络接口相关的ioctl命令
+///
+/// ## 参数
+///
+/// - `cmd`: ioctl命令
+/// - `data`: 用户空间的参数的地址
+///
+/// ## 返回值
+///
+/// 成功返回0，不是网络接口相关的命令时返回EOPNOTSUPP_OR_ENOTSUP
+pub fn netdev_ioctl(cmd: u32, data: usize) -> Result<usize, SystemError> {
+    match cmd {
+        SIOCGIFCONF => return netdev_ifconf(data),
+        SIOCGIFFLAGS | SIOCSIFFLAGS | SIOCGIFADDR | SIOCSIFADDR | SIOCGIFNETMASK
+        | SIOCSIFNETMASK | SIOCGIFHWADDR | SIOCGIFINDEX => {}
+        _ => return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+    }
+
+    // 修改接口配置需要CAP_NET_ADMIN
+    if matches!(cmd, SIOCSIFFLAGS | SIOCSIFADDR | SIOCSIFNETMASK)
+        && !ProcessManager::current_pcb().cred().can_net_admin()
+    {
+        return Err(SystemError::EPERM);
+    }
+
+    let reader = UserBufferReader::new(data as *const IfReq, size_of::<IfReq>(), true)?;
+    let mut req = *reader.read_one_from_user::<IfReq>(0)?;
+    let iface = iface_by_name(&ifreq_name(&req)?).ok_or(SystemError::ENODEV)?;
+
+    match cmd {
+        SIOCGIFFLAGS => {
+            req.ifr_ifru.flags = iface.flags().bits() as i16;
+        }
+        SIOCSIFFLAGS => {
+            let flags = IfFlags::from_bits_truncate(unsafe { req.ifr_ifru.flags } as u16 as u32);
+            change_iface_flags(&iface, flags);
+            return Ok(0);
+        }
+        SIOCGIFADDR => {
+            let cidr = iface_ipv4_cidr(&iface).ok_or(SystemError::EADDRNOTAVAIL)?;
+            req.ifr_ifru.addr = ipv4_to_sockaddr(&cidr.address());
+        }
+        SIOCSIFADDR => {
+            let addr = sockaddr_to_ipv4(unsafe { &req.ifr_ifru.addr })?;
+            let cidr = if addr.is_unspecified() {
+                None
+            } else {
+                Some(Ipv4Cidr::new(addr, classful_prefix_len(&addr)))
+            };
+            set_iface_ipv4_cidr(&iface, cidr)?;
+            return Ok(0);
+        }
+        SIOCGIFNETMASK => {
+            let cidr = iface_ipv4_cidr(&iface).ok_or(SystemError::EADDRNOTAVAIL)?;
+            req.ifr_ifru.addr = ipv4_to_sockaddr(&cidr.netmask());
+        }
+        SIOCSIFNETMASK => {
+            let netmask = sockaddr_to_ipv4(unsafe { &req.ifr_ifru.addr })?;
+            let cidr = iface_ipv4_cidr(&iface).ok_or(SystemError::EADDRNOTAVAIL)?;
+            let cidr =
+                Ipv4Cidr::from_netmask(cidr.address(), netmask).map_err(|_| SystemError::EINVAL)?;
+            set_iface_ipv4_cidr(&iface, Some(cidr))?;
+            return Ok(0);
+        }
+        SIOCGIFHWADDR => {
+            let mut hwaddr = SockAddrPlaceholder {
+                family: iface_hardware_type(&iface),
+                data: [0; 14],
+            };
+            let mac: wire::EthernetAddress = iface.mac();
+            hwaddr.data[..mac.0.len()].copy_from_slice(&mac.0);
+            req.ifr_ifru.hwaddr = hwaddr;
+        }
+        SIOCGIFINDEX => {
+            req.ifr_ifru.ifindex = iface_index(&iface) as i32;
+        }
+        _ => unreachable!(),
+    }
+
+    let mut writer = UserBufferWriter::new(data as *mut IfReq, size_of::<IfReq>(), true)?;
+    writer.copy_one_to_user(&req, 0)?;
+    return Ok(0);
+}
+
+/// ## SIOCGIFCONF：获取所有配置了IPv4地址的接口
+///
+/// 缓冲区为空时，只返回保存所有接口需要的长度
+fn netdev_ifconf(data: usize) -> Result<usize, SystemError> {
+    let reader = UserBufferReader::new(data as *const IfConf, size_of::<IfConf>(), true)?;
+    let mut ifconf = *reader.read_one_from_user::<IfConf>(0)?;
+
+    let ifaces: Vec<Arc<dyn NetDriver>> = NET_DRIVERS.read_irqsave().values().cloned().collect();
+    let reqs: Vec<IfReq> = ifaces
+        .iter()
+        .filter_map(|iface| {
+            let cidr = iface_ipv4_cidr(iface)?;
+            let mut req = IfReq {
+                ifr_name: [0; IFNAMSIZ],
+                ifr_ifru: IfReqData {
+                    addr: ipv4_to_sockaddr(&cidr.address()),
+                },
+            };
+            let name = iface.name();
+            let len = name.len().min(IFNAMSIZ - 1);
+            req.ifr_name[..len].copy_from_slice(&name.as_bytes()[..len]);
+            Some(req)
+        })
+        .collect();
+
+    if ifconf.ifc_buf.is_null() {
+        ifconf.ifc_len = (reqs.len() * size_of::<IfReq>()) as i32;
+    } else {
+        if ifconf.ifc_len < 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let count = reqs.len().min(ifconf.ifc_len as usize / size_of::<IfReq>());
+        if count > 0 {
+            let mut writer =
+                UserBufferWriter::new(ifconf.ifc_buf, count * size_of::<IfReq>(), true)?;
+            writer.copy_to_user(&reqs[..count], 0)?;
+        }
+        ifconf.ifc_len = (count * size_of::<IfReq>()) as i32;
+    }
+
+    let mut writer = UserBufferWriter::new(data as *mut IfConf, size_of::<IfConf>(), true)?;
+    writer.copy_one_to_user(&ifconf, 0)?;
+    return Ok(0);
+}
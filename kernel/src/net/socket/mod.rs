@@ -1,4 +1,8 @@
-use core::{any::Any, fmt::Debug, sync::atomic::AtomicUsize};
+use core::{
+    any::Any,
+    fmt::Debug,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
 
 use alloc::{
     boxed::Box,
@@ -9,41 +13,54 @@ use alloc::{
 };
 use hashbrown::HashMap;
 use smoltcp::{
-    iface::{SocketHandle, SocketSet},
+    iface::SocketHandle,
     socket::{self, tcp, udp},
 };
 use system_error::SystemError;
 
 use crate::{
-    arch::{rand::rand, sched::sched},
+    arch::sched::sched,
+    driver::net::NetDriver,
     filesystem::vfs::{
         file::FileMode, syscall::ModeType, FilePrivateData, FileSystem, FileType, IndexNode,
         Metadata,
     },
     libs::{
+        rand::rand,
         rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
         spinlock::{SpinLock, SpinLockGuard},
         wait_queue::EventWaitQueue,
     },
+    process::ProcessManager,
+    time::{
+        timer::{next_n_us_timer_jiffies, Timer, WakeUpHelper},
+        Duration, Instant, USEC_PER_SEC,
+    },
 };
 
-use self::sockets::{RawSocket, SeqpacketSocket, TcpSocket, UdpSocket};
+use self::{
+    netlink::NetlinkSocket,
+    sockets::{RawSocket, SeqpacketSocket, TcpSocket, UdpSocket},
+    unix::{ScmData, UnixDatagramSocket, UnixStreamSocket},
+};
 
 use super::{
     event_poll::{EPollEventType, EPollItem, EventPoll},
     net_core::poll_ifaces,
+    netdevice::netdev_ioctl,
+    syscall::PosixSocketOption,
     Endpoint, Protocol, ShutdownType,
 };
 
+pub mod netlink;
 pub mod sockets;
+pub mod unix;
 
 lazy_static! {
-    /// 所有socket的集合
-    /// TODO: 优化这里，自己实现SocketSet！！！现在这样的话，不管全局有多少个网卡，每个时间点都只会有1个进程能够访问socket
-    pub static ref SOCKET_SET: SpinLock<SocketSet<'static >> = SpinLock::new(SocketSet::new(vec![]));
-    /// SocketHandle表，每个SocketHandle对应一个SocketHandleItem，
-    /// 注意！：在网卡中断中需要拿到这张表的🔓，在获取读锁时应该确保关中断避免死锁
-    pub static ref HANDLE_MAP: RwLock<HashMap<SocketHandle, SocketHandleItem>> = RwLock::new(HashMap::new());
+    /// SocketHandle表，每个SocketKey对应一个SocketHandleItem。
+    /// 同一个socket在多个网络接口上的副本共享同一个SocketHandleItem
+    /// 注意！：在网络接收线程中需要拿到这张表的🔓，在获取读锁时应该确保关中断避免死锁
+    pub static ref HANDLE_MAP: RwLock<HashMap<SocketKey, Arc<SocketHandleItem>>> = RwLock::new(HashMap::new());
     /// 端口管理器
     pub static ref PORT_MANAGER: PortManager = PortManager::new();
 }
@@ -60,22 +77,32 @@ pub(super) fn new_socket(
 ) -> Result<Box<dyn Socket>, SystemError> {
     let socket: Box<dyn Socket> = match address_family {
         AddressFamily::Unix => match socket_type {
-            PosixSocketType::Stream => Box::new(TcpSocket::new(SocketOptions::default())),
-            PosixSocketType::Datagram => Box::new(UdpSocket::new(SocketOptions::default())),
-            PosixSocketType::Raw => Box::new(RawSocket::new(protocol, SocketOptions::default())),
+            PosixSocketType::Stream => Box::new(UnixStreamSocket::new(SocketOptions::default())),
+            PosixSocketType::Datagram => {
+                Box::new(UnixDatagramSocket::new(SocketOptions::default()))
+            }
             PosixSocketType::SeqPacket => Box::new(SeqpacketSocket::new(SocketOptions::default())),
             _ => {
                 return Err(SystemError::EINVAL);
             }
         },
         AddressFamily::INet => match socket_type {
-            PosixSocketType::Stream => Box::new(TcpSocket::new(SocketOptions::default())),
-            PosixSocketType::Datagram => Box::new(UdpSocket::new(SocketOptions::default())),
-            PosixSocketType::Raw => Box::new(RawSocket::new(protocol, SocketOptions::default())),
+            PosixSocketType::Stream => Box::new(TcpSocket::new(SocketOptions::default())?),
+            PosixSocketType::Datagram => Box::new(UdpSocket::new(SocketOptions::default())?),
+            PosixSocketType::Raw => Box::new(RawSocket::new(protocol, SocketOptions::default())?),
             _ => {
                 return Err(SystemError::EINVAL);
             }
         },
+        AddressFamily::Netlink => match socket_type {
+            PosixSocketType::Raw | PosixSocketType::Datagram => Box::new(NetlinkSocket::new(
+                protocol.into(),
+                SocketOptions::default(),
+            )?),
+            _ => {
+                return Err(SystemError::ESOCKTNOSUPPORT);
+            }
+        },
         _ => {
             return Err(SystemError::EAFNOSUPPORT);
         }
@@ -103,6 +130,37 @@ pub trait Socket: Sync + Send + Debug + Any {
     /// @return 返回写入的数据的长度
     fn write(&self, buf: &[u8], to: Option<Endpoint>) -> Result<usize, SystemError>;
 
+    /// @brief 接收数据，同时接收随数据发送的辅助数据（对应于recvmsg）
+    ///
+    /// @param buf 读取到的数据存放的缓冲区
+    ///
+    /// @return (读取的数据的长度, 读取数据的端点, 辅助数据)
+    fn recv_msg(&mut self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint, Vec<ScmData>) {
+        let (ret, endpoint) = self.read(buf);
+        return (ret, endpoint, Vec::new());
+    }
+
+    /// @brief 发送数据，同时附带辅助数据（对应于sendmsg）
+    ///
+    /// 只有Unix域socket支持辅助数据，其他socket收到辅助数据时返回EINVAL
+    ///
+    /// @param buf 要写入的数据
+    /// @param to 要写入的目的端点
+    /// @param scm 辅助数据
+    ///
+    /// @return 返回写入的数据的长度
+    fn send_msg(
+        &self,
+        buf: &[u8],
+        to: Option<Endpoint>,
+        scm: Vec<ScmData>,
+    ) -> Result<usize, SystemError> {
+        if !scm.is_empty() {
+            return Err(SystemError::EINVAL);
+        }
+        return self.write(buf, to);
+    }
+
     /// @brief 对应于POSIX的connect函数，用于连接到指定的远程服务器端点
     ///
     /// It is used to establish a connection to a remote server.
@@ -221,7 +279,7 @@ pub trait Socket: Sync + Send + Debug + Any {
     ///
     /// @return 返回设置是否成功, 如果不支持该选项，返回ENOSYS
     fn setsockopt(
-        &self,
+        &mut self,
         _level: usize,
         _optname: usize,
         _optval: &[u8],
@@ -230,14 +288,49 @@ pub trait Socket: Sync + Send + Debug + Any {
         return Ok(());
     }
 
-    fn socket_handle(&self) -> SocketHandle {
+    /// @brief 获取socket的选项
+    ///
+    /// @param level 选项的层次
+    /// @param optname 选项的名称
+    /// @param optval 保存选项值的缓冲区，缓冲区不够大时选项值会被截断
+    ///
+    /// @return 返回写入optval的长度, 如果不支持该选项，返回ENOPROTOOPT
+    fn getsockopt(
+        &self,
+        level: usize,
+        optname: usize,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        if level != SOL_SOCKET as usize {
+            return Err(SystemError::ENOPROTOOPT);
+        }
+        let optname =
+            PosixSocketOption::try_from(optname as i32).map_err(|_| SystemError::ENOPROTOOPT)?;
+        return self.metadata()?.getsockopt(optname, optval);
+    }
+
+    /// @brief 获取socket在HANDLE_MAP中的key
+    fn socket_handle(&self) -> SocketKey {
         todo!()
     }
 
+    /// @brief socket的最后一个文件被关闭时调用，释放不经过smoltcp的socket所占用的资源
+    fn close(&mut self) {}
+
+    /// @brief 调用close之后，设置了SO_LINGER的socket需要等待的发送是否已经完成
+    fn linger_done(&self) -> bool {
+        return true;
+    }
+
+    /// @brief 文件的O_NONBLOCK标志发生变化时调用
+    ///
+    /// @param nonblock 是否为非阻塞模式
+    fn set_nonblock(&mut self, _nonblock: bool) {}
+
     fn add_epoll(&mut self, epitem: Arc<EPollItem>) -> Result<(), SystemError> {
         HANDLE_MAP
-            .write_irqsave()
-            .get_mut(&self.socket_handle())
+            .read_irqsave()
+            .get(&self.socket_handle())
             .unwrap()
             .add_epoll(epitem);
         Ok(())
@@ -245,8 +338,8 @@ pub trait Socket: Sync + Send + Debug + Any {
 
     fn remove_epoll(&mut self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
         HANDLE_MAP
-            .write_irqsave()
-            .get_mut(&self.socket_handle())
+            .read_irqsave()
+            .get(&self.socket_handle())
             .unwrap()
             .remove_epoll(epoll)?;
 
@@ -254,8 +347,8 @@ pub trait Socket: Sync + Send + Debug + Any {
     }
 
     fn clear_epoll(&mut self) -> Result<(), SystemError> {
-        let mut handle_map_guard = HANDLE_MAP.write_irqsave();
-        let handle_item = handle_map_guard.get_mut(&self.socket_handle()).unwrap();
+        let handle_map_guard = HANDLE_MAP.read_irqsave();
+        let handle_item = handle_map_guard.get(&self.socket_handle()).unwrap();
 
         for epitem in handle_item.epitems.lock_irqsave().iter() {
             let epoll = epitem.epoll();
@@ -303,8 +396,11 @@ impl SocketInode {
 }
 
 impl IndexNode for SocketInode {
-    fn open(&self, _data: &mut FilePrivateData, _mode: &FileMode) -> Result<(), SystemError> {
+    fn open(&self, _data: &mut FilePrivateData, mode: &FileMode) -> Result<(), SystemError> {
         self.1.fetch_add(1, core::sync::atomic::Ordering::SeqCst);
+        self.0
+            .lock_irqsave()
+            .set_nonblock(mode.contains(FileMode::O_NONBLOCK));
         Ok(())
     }
 
@@ -314,12 +410,49 @@ impl IndexNode for SocketInode {
             // 最后一次关闭，需要释放
             let mut socket = self.0.lock_irqsave();
 
-            if socket.metadata().unwrap().socket_type == SocketType::SeqpacketSocket {
-                return Ok(());
+            match socket.metadata().unwrap().socket_type {
+                SocketType::SeqpacketSocket => return Ok(()),
+                SocketType::UnixStreamSocket
+                | SocketType::UnixDatagramSocket
+                | SocketType::NetlinkSocket => {
+                    socket.close();
+                    return Ok(());
+                }
+                _ => {}
             }
 
-            if let Some(Endpoint::Ip(Some(ip))) = socket.endpoint() {
-                PORT_MANAGER.unbind_port(socket.metadata().unwrap().socket_type, ip.port)?;
+            // 释放socket占用的端口
+            socket.close();
+
+            // 设置了SO_LINGER时，等待发送缓冲区中的数据发送完毕，最多等待linger的时间。
+            // 等待时不能持有socket的锁。在不能睡眠的上下文中关闭时（例如持有文件描述符表的锁），不等待
+            let linger = socket.metadata().unwrap().linger;
+            if let Some(timeout) = linger.filter(|timeout| *timeout != Duration::ZERO) {
+                let deadline = Instant::now() + timeout;
+                let handle = socket.socket_handle();
+                drop(socket);
+                while ProcessManager::current_pcb().preempt_count() == 0 {
+                    poll_ifaces();
+                    if self.0.lock_irqsave().linger_done() {
+                        break;
+                    }
+                    // 超时或者被信号打断时，不再等待，剩下的数据继续在后台发送
+                    if SocketHandleItem::sleep(
+                        handle,
+                        (EPollEventType::EPOLLOUT | EPollEventType::EPOLLHUP).bits() as u64,
+                        HANDLE_MAP.read_irqsave(),
+                        Some(deadline),
+                    )
+                    .is_err()
+                        || ProcessManager::current_pcb()
+                            .sig_info_irqsave()
+                            .sig_pending()
+                            .has_pending()
+                    {
+                        break;
+                    }
+                }
+                socket = self.0.lock_irqsave();
             }
 
             socket.clear_epoll()?;
@@ -357,6 +490,16 @@ impl IndexNode for SocketInode {
         return Ok(events.bits() as usize);
     }
 
+    fn ioctl(
+        &self,
+        cmd: u32,
+        data: usize,
+        _private_data: &FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        // 网络接口的配置命令对所有类型的socket都有效
+        return netdev_ioctl(cmd, data);
+    }
+
     fn fs(&self) -> Arc<dyn FileSystem> {
         todo!()
     }
@@ -395,6 +538,10 @@ pub struct SocketHandleItem {
     pub wait_queue: EventWaitQueue,
     /// epitems，考虑写在这是否是最优解？
     pub epitems: SpinLock<LinkedList<Arc<EPollItem>>>,
+    /// 等待通过SO_ERROR读取的错误
+    error: SpinLock<Option<SystemError>>,
+    /// connect返回EINPROGRESS之后，连接是否仍在后台进行
+    connecting: AtomicBool,
 }
 
 impl SocketHandleItem {
@@ -404,6 +551,8 @@ impl SocketHandleItem {
             shutdown_type: RwLock::new(ShutdownType::empty()),
             wait_queue: EventWaitQueue::new(),
             epitems: SpinLock::new(LinkedList::new()),
+            error: SpinLock::new(None),
+            connecting: AtomicBool::new(false),
         }
     }
 
@@ -413,15 +562,37 @@ impl SocketHandleItem {
             shutdown_type: RwLock::new(ShutdownType::empty()),
             wait_queue: EventWaitQueue::new(),
             epitems: SpinLock::new(LinkedList::new()),
+            error: SpinLock::new(None),
+            connecting: AtomicBool::new(false),
         }
     }
 
     /// ### 在socket的等待队列上睡眠
+    ///
+    /// ## 参数
+    /// - `deadline`: 最多睡眠到这个时刻，为None时一直睡眠到被唤醒
+    ///
+    /// ## 返回值
+    /// - `Ok(())`: 被唤醒
+    /// - `Err(SystemError::EAGAIN_OR_EWOULDBLOCK)`: 已经超过了`deadline`
     pub fn sleep(
-        socket_handle: SocketHandle,
+        socket_handle: SocketKey,
         events: u64,
-        handle_map_guard: RwLockReadGuard<'_, HashMap<SocketHandle, SocketHandleItem>>,
-    ) {
+        handle_map_guard: RwLockReadGuard<'_, HashMap<SocketKey, Arc<SocketHandleItem>>>,
+        deadline: Option<Instant>,
+    ) -> Result<(), SystemError> {
+        let mut timer = None;
+        if let Some(deadline) = deadline {
+            let now = Instant::now();
+            if deadline <= now {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            timer = Some(Timer::new(
+                WakeUpHelper::new(ProcessManager::current_pcb()),
+                next_n_us_timer_jiffies((deadline - now).total_micros()),
+            ));
+        }
+
         unsafe {
             handle_map_guard
                 .get(&socket_handle)
@@ -430,22 +601,62 @@ impl SocketHandleItem {
                 .sleep_without_schedule(events)
         };
         drop(handle_map_guard);
+        // 在进入睡眠状态之后再启动定时器，避免定时器在睡眠之前到期导致错过唤醒
+        if let Some(timer) = &timer {
+            timer.activate();
+        }
         sched();
+
+        if let Some(timer) = timer {
+            if timer.timeout() {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            timer.cancel();
+        }
+        return Ok(());
     }
 
     pub fn shutdown_type(&self) -> ShutdownType {
         self.shutdown_type.read().clone()
     }
 
-    pub fn shutdown_type_writer(&mut self) -> RwLockWriteGuard<ShutdownType> {
+    pub fn shutdown_type_writer(&self) -> RwLockWriteGuard<ShutdownType> {
         self.shutdown_type.write_irqsave()
     }
 
-    pub fn add_epoll(&mut self, epitem: Arc<EPollItem>) {
+    pub fn add_epoll(&self, epitem: Arc<EPollItem>) {
         self.epitems.lock_irqsave().push_back(epitem)
     }
 
-    pub fn remove_epoll(&mut self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
+    /// 记录一个等待通过SO_ERROR读取的错误
+    pub fn set_error(&self, error: SystemError) {
+        *self.error.lock_irqsave() = Some(error);
+    }
+
+    /// 设置连接是否在后台进行。在后台进行的连接的结果通过SO_ERROR报告
+    pub fn set_connecting(&self, connecting: bool) {
+        self.connecting.store(connecting, Ordering::SeqCst);
+    }
+
+    /// 检查在后台进行的连接是否已经结束，连接失败时把错误记录到SO_ERROR中
+    ///
+    /// ## 参数
+    /// - `state`: 刚刚被轮询过的tcp socket的状态
+    pub fn check_connect_result(&self, state: tcp::State) {
+        if state == tcp::State::SynSent || !self.connecting.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        if state != tcp::State::Established {
+            self.set_error(SystemError::ECONNREFUSED);
+        }
+    }
+
+    /// 取出等待通过SO_ERROR读取的错误
+    pub fn take_error(&self) -> Option<SystemError> {
+        return self.error.lock_irqsave().take();
+    }
+
+    pub fn remove_epoll(&self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
         let is_remove = !self
             .epitems
             .lock_irqsave()
@@ -461,13 +672,22 @@ impl SocketHandleItem {
     }
 }
 
+/// # 端口上的一个绑定
+#[derive(Debug)]
+struct PortBinding {
+    handle: Arc<GlobalSocketHandle>,
+    /// 绑定时socket的选项，用于判断端口能否被复用
+    options: SocketOptions,
+}
+
 /// # TCP 和 UDP 的端口管理器。
 /// 如果 TCP/UDP 的 socket 绑定了某个端口，它会在对应的表中记录，以检测端口冲突。
+/// 设置了SO_REUSEADDR或SO_REUSEPORT的多个socket可以绑定到同一个端口
 pub struct PortManager {
     // TCP 端口记录表
-    tcp_port_table: SpinLock<HashMap<u16, Arc<GlobalSocketHandle>>>,
+    tcp_port_table: SpinLock<HashMap<u16, Vec<PortBinding>>>,
     // UDP 端口记录表
-    udp_port_table: SpinLock<HashMap<u16, Arc<GlobalSocketHandle>>>,
+    udp_port_table: SpinLock<HashMap<u16, Vec<PortBinding>>>,
 }
 
 impl PortManager {
@@ -518,12 +738,19 @@ impl PortManager {
 
     /// @brief 检测给定端口是否已被占用，如果未被占用则在 TCP/UDP 对应的表中记录
     ///
-    /// TODO: 增加支持端口复用的逻辑
+    /// 端口已被占用时，如果新的socket能与端口上已有的每个socket共用端口，也可以绑定：
+    /// - 双方都设置了SO_REUSEPORT
+    /// - 双方都设置了SO_REUSEADDR，并且已有的socket不是正在监听的TCP socket
+    ///
+    /// 注意：检查TCP socket是否正在监听时会锁住它所在网络接口的socket集合，调用者不能持有任何socket集合的锁
+    ///
+    /// @param options 新的socket的选项
     pub fn bind_port(
         &self,
         socket_type: SocketType,
         port: u16,
         handle: Arc<GlobalSocketHandle>,
+        options: SocketOptions,
     ) -> Result<(), SystemError> {
         if port > 0 {
             let mut listen_table_guard = match socket_type {
@@ -531,51 +758,191 @@ impl PortManager {
                 SocketType::TcpSocket => self.tcp_port_table.lock(),
                 _ => panic!("{:?} cann't bind a port", socket_type),
             };
-            match listen_table_guard.get(&port) {
-                Some(_) => return Err(SystemError::EADDRINUSE),
-                None => listen_table_guard.insert(port, handle),
-            };
+            let bindings = listen_table_guard.entry(port).or_insert_with(Vec::new);
+            if !bindings
+                .iter()
+                .all(|binding| Self::can_share(socket_type, binding, options))
+            {
+                return Err(SystemError::EADDRINUSE);
+            }
+            bindings.push(PortBinding { handle, options });
             drop(listen_table_guard);
         }
         return Ok(());
     }
 
+    /// 设置了`options`的新socket能否与`binding`共用端口
+    fn can_share(socket_type: SocketType, binding: &PortBinding, options: SocketOptions) -> bool {
+        if binding.options.contains(SocketOptions::REUSEPORT)
+            && options.contains(SocketOptions::REUSEPORT)
+        {
+            return true;
+        }
+        if binding.options.contains(SocketOptions::REUSEADDR)
+            && options.contains(SocketOptions::REUSEADDR)
+        {
+            if socket_type != SocketType::TcpSocket {
+                return true;
+            }
+            let iface = binding.handle.iface();
+            let sockets = iface.sockets().lock_irqsave();
+            return !sockets
+                .get::<tcp::Socket>(binding.handle.handle())
+                .is_listening();
+        }
+        return false;
+    }
+
+    /// @brief 把端口上`old`的绑定换成`new`，绑定时的选项保持不变
+    pub fn replace_handle(
+        &self,
+        socket_type: SocketType,
+        port: u16,
+        old: &Arc<GlobalSocketHandle>,
+        new: Arc<GlobalSocketHandle>,
+    ) {
+        let mut listen_table_guard = match socket_type {
+            SocketType::UdpSocket => self.udp_port_table.lock(),
+            SocketType::TcpSocket => self.tcp_port_table.lock(),
+            _ => return,
+        };
+        if let Some(binding) = listen_table_guard
+            .get_mut(&port)
+            .and_then(|bindings| bindings.iter_mut().find(|b| Arc::ptr_eq(&b.handle, old)))
+        {
+            binding.handle = new;
+        }
+    }
+
     /// @brief 在对应的端口记录表中将端口和 socket 解绑
-    pub fn unbind_port(&self, socket_type: SocketType, port: u16) -> Result<(), SystemError> {
+    ///
+    /// @param handle 要解绑的socket，端口上的其他socket不受影响
+    pub fn unbind_port(
+        &self,
+        socket_type: SocketType,
+        port: u16,
+        handle: &Arc<GlobalSocketHandle>,
+    ) -> Result<(), SystemError> {
         let mut listen_table_guard = match socket_type {
             SocketType::UdpSocket => self.udp_port_table.lock(),
             SocketType::TcpSocket => self.tcp_port_table.lock(),
             _ => return Ok(()),
         };
-        listen_table_guard.remove(&port);
+        if let Some(bindings) = listen_table_guard.get_mut(&port) {
+            bindings.retain(|binding| !Arc::ptr_eq(&binding.handle, handle));
+            if bindings.is_empty() {
+                listen_table_guard.remove(&port);
+            }
+        }
         drop(listen_table_guard);
         return Ok(());
     }
 }
 
+/// # socket在全局的标识
+///
+/// 每个网络接口有自己的socket集合，不同集合分配的SocketHandle可能相同，所以需要加上网络接口的id来区分
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SocketKey {
+    /// socket所在的网络接口的id
+    pub iface_id: usize,
+    /// socket在网络接口的socket集合中的句柄
+    pub handle: SocketHandle,
+}
+
+impl SocketKey {
+    pub fn new(iface_id: usize, handle: SocketHandle) -> Self {
+        return Self { iface_id, handle };
+    }
+}
+
 /// # socket的句柄管理组件
 /// 它在smoltcp的SocketHandle上封装了一层，增加更多的功能。
-/// 比如，在socket被关闭时，自动释放socket的资源，通知系统的其他组件。
+/// 比如，记录socket位于哪个网络接口的socket集合中，在socket被关闭时，自动释放socket的资源，通知系统的其他组件。
 #[derive(Debug)]
-pub struct GlobalSocketHandle(SocketHandle);
+pub struct GlobalSocketHandle(SpinLock<(Arc<dyn NetDriver>, SocketHandle)>);
 
 impl GlobalSocketHandle {
-    pub fn new(handle: SocketHandle) -> Arc<Self> {
-        return Arc::new(Self(handle));
+    /// ## 创建socket的句柄
+    ///
+    /// ## 参数
+    /// - `iface`: socket所在的网络接口
+    /// - `handle`: socket在`iface`的socket集合中的句柄
+    pub fn new(iface: Arc<dyn NetDriver>, handle: SocketHandle) -> Arc<Self> {
+        return Arc::new(Self(SpinLock::new((iface, handle))));
     }
-}
 
-impl Clone for GlobalSocketHandle {
-    fn clone(&self) -> Self {
-        Self(self.0)
+    /// socket所在的网络接口
+    pub fn iface(&self) -> Arc<dyn NetDriver> {
+        return self.0.lock_irqsave().0.clone();
+    }
+
+    /// socket在所在网络接口的socket集合中的句柄
+    pub fn handle(&self) -> SocketHandle {
+        return self.0.lock_irqsave().1;
+    }
+
+    /// socket在HANDLE_MAP中的key
+    pub fn key(&self) -> SocketKey {
+        let guard = self.0.lock_irqsave();
+        return SocketKey::new(guard.0.nic_id(), guard.1);
+    }
+
+    /// ## 把socket移动到另一个网络接口的socket集合中
+    ///
+    /// socket的状态和缓冲区中的数据会一起移动，它在HANDLE_MAP中的项也会换到新的key下面
+    pub fn move_to(&self, iface: Arc<dyn NetDriver>) {
+        let mut guard = self.0.lock_irqsave();
+        if guard.0.nic_id() == iface.nic_id() {
+            return;
+        }
+
+        // 在释放原来的句柄之前取出HANDLE_MAP中的项，避免删掉复用了这个句柄的其他socket的项
+        let item = HANDLE_MAP
+            .write_irqsave()
+            .remove(&SocketKey::new(guard.0.nic_id(), guard.1));
+
+        let old_socket = guard.0.sockets().lock_irqsave().remove(guard.1);
+        let mut sockets = iface.sockets().lock_irqsave();
+        let handle = match old_socket {
+            socket::Socket::Raw(s) => sockets.add(s),
+            socket::Socket::Icmp(s) => sockets.add(s),
+            socket::Socket::Udp(s) => sockets.add(s),
+            socket::Socket::Tcp(s) => sockets.add(s),
+            socket::Socket::Dhcpv4(s) => sockets.add(s),
+            socket::Socket::Dns(s) => sockets.add(s),
+        };
+        drop(sockets);
+
+        if let Some(item) = item {
+            HANDLE_MAP
+                .write_irqsave()
+                .insert(SocketKey::new(iface.nic_id(), handle), item);
+        }
+        *guard = (iface, handle);
+    }
+
+    /// ## 让本socket与`owner`共享HANDLE_MAP中的项
+    ///
+    /// 本socket上发生的事件会唤醒在`owner`上等待的进程。用于同一个socket在多个网络接口上的副本
+    pub fn share_handle_item(&self, owner: &GlobalSocketHandle) {
+        let (key, owner_key) = (self.key(), owner.key());
+        let mut handle_map_guard = HANDLE_MAP.write_irqsave();
+        if let Some(item) = handle_map_guard.get(&owner_key).cloned() {
+            handle_map_guard.insert(key, item);
+        }
     }
 }
 
 impl Drop for GlobalSocketHandle {
     fn drop(&mut self) {
-        let mut socket_set_guard = SOCKET_SET.lock_irqsave();
-        socket_set_guard.remove(self.0); // 删除的时候，会发送一条FINISH的信息？
-        drop(socket_set_guard);
+        let guard = self.0.lock_irqsave();
+        // 先删除HANDLE_MAP中的项，再释放句柄，避免删掉复用了这个句柄的其他socket的项
+        HANDLE_MAP
+            .write_irqsave()
+            .remove(&SocketKey::new(guard.0.nic_id(), guard.1));
+        guard.0.sockets().lock_irqsave().remove(guard.1); // 删除的时候，会发送一条FINISH的信息？
+        drop(guard);
         poll_ifaces();
     }
 }
@@ -591,6 +958,12 @@ pub enum SocketType {
     UdpSocket,
     /// 用于进程间通信的 Socket
     SeqpacketSocket,
+    /// Unix域的流式 Socket
+    UnixStreamSocket,
+    /// Unix域的数据报 Socket
+    UnixDatagramSocket,
+    /// 用于与内核通信的 Netlink Socket
+    NetlinkSocket,
 }
 
 bitflags! {
@@ -607,6 +980,8 @@ bitflags! {
         const REUSEADDR = 1 << 3;
         /// 是否允许重用端口
         const REUSEPORT = 1 << 4;
+        /// 是否在连接空闲时发送keepalive探测
+        const KEEPALIVE = 1 << 5;
     }
 }
 
@@ -623,6 +998,12 @@ pub struct SocketMetadata {
     pub metadata_buf_size: usize,
     /// socket的选项
     pub options: SocketOptions,
+    /// 接收操作的超时时间(SO_RCVTIMEO)，为None时一直阻塞
+    pub recv_timeout: Option<Duration>,
+    /// 发送操作的超时时间(SO_SNDTIMEO)，为None时一直阻塞
+    pub send_timeout: Option<Duration>,
+    /// SO_LINGER设置的超时时间，为None时表示没有启用
+    pub linger: Option<Duration>,
 }
 
 impl SocketMetadata {
@@ -639,8 +1020,172 @@ impl SocketMetadata {
             tx_buf_size,
             metadata_buf_size,
             options,
+            recv_timeout: None,
+            send_timeout: None,
+            linger: None,
         }
     }
+
+    /// 根据SO_RCVTIMEO计算接收操作超时的时刻
+    pub fn recv_deadline(&self) -> Option<Instant> {
+        return self.recv_timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    /// 根据SO_SNDTIMEO计算发送操作超时的时刻
+    pub fn send_deadline(&self) -> Option<Instant> {
+        return self.send_timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    /// ## 设置SOL_SOCKET层次上各种socket通用的选项
+    ///
+    /// ## 返回值
+    /// - `Err(SystemError::ENOPROTOOPT)`: 不是通用的选项，由具体的socket处理
+    pub fn setsockopt(
+        &mut self,
+        optname: PosixSocketOption,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        match optname {
+            PosixSocketOption::SO_REUSEADDR => self
+                .options
+                .set(SocketOptions::REUSEADDR, optval_to_i32(optval)? != 0),
+            PosixSocketOption::SO_REUSEPORT => self
+                .options
+                .set(SocketOptions::REUSEPORT, optval_to_i32(optval)? != 0),
+            PosixSocketOption::SO_BROADCAST => self
+                .options
+                .set(SocketOptions::BROADCAST, optval_to_i32(optval)? != 0),
+            PosixSocketOption::SO_KEEPALIVE => self
+                .options
+                .set(SocketOptions::KEEPALIVE, optval_to_i32(optval)? != 0),
+            PosixSocketOption::SO_RCVTIMEO_OLD | PosixSocketOption::SO_RCVTIMEO_NEW => {
+                self.recv_timeout = optval_to_timeout(optval)?;
+            }
+            PosixSocketOption::SO_SNDTIMEO_OLD | PosixSocketOption::SO_SNDTIMEO_NEW => {
+                self.send_timeout = optval_to_timeout(optval)?;
+            }
+            PosixSocketOption::SO_LINGER => {
+                // struct linger { int l_onoff; int l_linger; }
+                if optval.len() < 2 * core::mem::size_of::<i32>() {
+                    return Err(SystemError::EINVAL);
+                }
+                let onoff = optval_to_i32(&optval[0..4])?;
+                let seconds = optval_to_i32(&optval[4..8])?;
+                self.linger = if onoff != 0 {
+                    Some(Duration::from_secs(seconds.max(0) as u64))
+                } else {
+                    None
+                };
+            }
+            _ => return Err(SystemError::ENOPROTOOPT),
+        }
+        return Ok(());
+    }
+
+    /// ## 读取SOL_SOCKET层次上各种socket通用的选项
+    ///
+    /// ## 返回值
+    /// - `Ok(len)`: 写入`optval`的长度
+    /// - `Err(SystemError::ENOPROTOOPT)`: 不是通用的选项
+    pub fn getsockopt(
+        &self,
+        optname: PosixSocketOption,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        let value: i32 = match optname {
+            PosixSocketOption::SO_TYPE => {
+                let posix_type = match self.socket_type {
+                    SocketType::TcpSocket | SocketType::UnixStreamSocket => PosixSocketType::Stream,
+                    SocketType::UdpSocket | SocketType::UnixDatagramSocket => {
+                        PosixSocketType::Datagram
+                    }
+                    SocketType::SeqpacketSocket => PosixSocketType::SeqPacket,
+                    SocketType::RawSocket | SocketType::NetlinkSocket => PosixSocketType::Raw,
+                };
+                posix_type as i32
+            }
+            PosixSocketOption::SO_ERROR => 0,
+            PosixSocketOption::SO_SNDBUF => self.tx_buf_size as i32,
+            PosixSocketOption::SO_RCVBUF => self.rx_buf_size as i32,
+            PosixSocketOption::SO_REUSEADDR => {
+                self.options.contains(SocketOptions::REUSEADDR) as i32
+            }
+            PosixSocketOption::SO_REUSEPORT => {
+                self.options.contains(SocketOptions::REUSEPORT) as i32
+            }
+            PosixSocketOption::SO_BROADCAST => {
+                self.options.contains(SocketOptions::BROADCAST) as i32
+            }
+            PosixSocketOption::SO_KEEPALIVE => {
+                self.options.contains(SocketOptions::KEEPALIVE) as i32
+            }
+            PosixSocketOption::SO_RCVTIMEO_OLD | PosixSocketOption::SO_RCVTIMEO_NEW => {
+                return Ok(write_optval(optval, &timeout_to_optval(self.recv_timeout)));
+            }
+            PosixSocketOption::SO_SNDTIMEO_OLD | PosixSocketOption::SO_SNDTIMEO_NEW => {
+                return Ok(write_optval(optval, &timeout_to_optval(self.send_timeout)));
+            }
+            PosixSocketOption::SO_LINGER => {
+                let mut linger = [0u8; 8];
+                if let Some(timeout) = self.linger {
+                    linger[0..4].copy_from_slice(&1i32.to_ne_bytes());
+                    linger[4..8].copy_from_slice(&(timeout.secs() as i32).to_ne_bytes());
+                }
+                return Ok(write_optval(optval, &linger));
+            }
+            _ => return Err(SystemError::ENOPROTOOPT),
+        };
+        return Ok(write_optval(optval, &value.to_ne_bytes()));
+    }
+}
+
+/// 从setsockopt的optval中读出一个int
+fn optval_to_i32(optval: &[u8]) -> Result<i32, SystemError> {
+    if optval.len() < core::mem::size_of::<i32>() {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok(i32::from_ne_bytes(optval[0..4].try_into().unwrap()));
+}
+
+/// 把选项的值写入getsockopt的optval，缓冲区不够大时截断。返回写入的长度
+fn write_optval(optval: &mut [u8], value: &[u8]) -> usize {
+    let len = core::cmp::min(optval.len(), value.len());
+    optval[..len].copy_from_slice(&value[..len]);
+    return len;
+}
+
+/// ## 从optval中读出`struct timeval`表示的超时时间
+///
+/// 全为0表示永不超时；秒数为负数时与Linux一样，表示立即超时
+fn optval_to_timeout(optval: &[u8]) -> Result<Option<Duration>, SystemError> {
+    if optval.len() < 2 * core::mem::size_of::<i64>() {
+        return Err(SystemError::EINVAL);
+    }
+    let sec = i64::from_ne_bytes(optval[0..8].try_into().unwrap());
+    let usec = i64::from_ne_bytes(optval[8..16].try_into().unwrap());
+    if usec < 0 || usec >= USEC_PER_SEC as i64 {
+        return Err(SystemError::EDOM);
+    }
+    if sec < 0 {
+        return Ok(Some(Duration::ZERO));
+    }
+    if sec == 0 && usec == 0 {
+        return Ok(None);
+    }
+    let micros = (sec as u64)
+        .saturating_mul(USEC_PER_SEC as u64)
+        .saturating_add(usec as u64);
+    return Ok(Some(Duration::from_micros(micros)));
+}
+
+/// 把超时时间转换成`struct timeval`
+fn timeout_to_optval(timeout: Option<Duration>) -> [u8; 16] {
+    let mut timeval = [0u8; 16];
+    if let Some(timeout) = timeout {
+        timeval[0..8].copy_from_slice(&(timeout.secs() as i64).to_ne_bytes());
+        timeval[8..16].copy_from_slice(&(timeout.micros() as i64).to_ne_bytes());
+    }
+    return timeval;
 }
 
 /// @brief 地址族的枚举
@@ -0,0 +1,551 @@
+//! Netlink socket
+//!
+//! 目前只支持NETLINK_ROUTE协议，用户进程通过它查询和配置网络接口、地址以及路由。
+//! 内核在发送请求时同步地处理其中的消息，并把回复直接放入发送者的接收队列。
+//!
+//! 参考 https://man7.org/linux/man-pages/man7/netlink.7.html
+
+pub mod route;
+
+use core::{cmp::min, mem::size_of};
+
+use alloc::{
+    boxed::Box,
+    collections::{LinkedList, VecDeque},
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    libs::{spinlock::SpinLock, wait_queue::EventWaitQueue},
+    net::{
+        event_poll::{EPollEventType, EPollItem, EventPoll},
+        Endpoint,
+    },
+    process::ProcessManager,
+};
+
+use super::{Socket, SocketMetadata, SocketOptions, SocketType};
+
+/// 路由相关的netlink协议
+pub const NETLINK_ROUTE: u8 = 0;
+
+/// 消息的对齐长度
+pub const NLMSG_ALIGNTO: usize = 4;
+/// 内核打包回复时，一个数据报的最大长度
+pub const NLMSG_GOODSIZE: usize = 4096;
+
+/// 没有操作，消息会被忽略
+pub const NLMSG_NOOP: u16 = 1;
+/// 错误或者确认消息
+pub const NLMSG_ERROR: u16 = 2;
+/// 多条消息组成的回复的结束
+pub const NLMSG_DONE: u16 = 3;
+/// 小于这个值的消息类型是控制消息
+pub const NLMSG_MIN_TYPE: u16 = 0x10;
+
+bitflags! {
+    /// netlink消息头中的标志
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/include/uapi/linux/netlink.h#54
+    pub struct NetlinkMessageFlags: u16 {
+        /// 这是一个请求
+        const REQUEST = 0x1;
+        /// 这是多条消息组成的回复中的一条，以NLMSG_DONE结束
+        const MULTI = 0x2;
+        /// 处理完成后回复确认消息
+        const ACK = 0x4;
+        const ECHO = 0x8;
+
+        /* GET请求的标志 */
+        /// 返回整个表
+        const ROOT = 0x100;
+        /// 返回所有匹配的项
+        const MATCH = 0x200;
+        /// 返回所有的项
+        const DUMP = Self::ROOT.bits | Self::MATCH.bits;
+
+        /* NEW请求的标志 */
+        /// 替换已经存在的项
+        const REPLACE = 0x100;
+        /// 项已经存在时返回错误
+        const EXCL = 0x200;
+        /// 项不存在时创建
+        const CREATE = 0x400;
+        /// 添加到列表的末尾
+        const APPEND = 0x800;
+    }
+}
+
+/// netlink消息头
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NlMsgHdr {
+    /// 消息的长度，包括消息头
+    pub nlmsg_len: u32,
+    /// 消息的类型
+    pub nlmsg_type: u16,
+    /// 消息的标志
+    pub nlmsg_flags: u16,
+    /// 序列号
+    pub nlmsg_seq: u32,
+    /// 发送者的端口号，内核发送的消息为0
+    pub nlmsg_pid: u32,
+}
+
+/// NLMSG_ERROR消息的内容
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NlMsgErr {
+    /// 错误码的相反数，为0表示确认
+    pub error: i32,
+    /// 出错的请求的消息头
+    pub msg: NlMsgHdr,
+}
+
+/// 消息中的属性的头部，后面紧跟属性的值
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RtAttr {
+    /// 属性的长度，包括头部
+    pub rta_len: u16,
+    /// 属性的类型
+    pub rta_type: u16,
+}
+
+/// 属性类型中用于标志的比特
+const NLA_TYPE_MASK: u16 = !((1 << 15) | (1 << 14));
+
+/// Netlink socket的地址
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NetlinkEndpoint {
+    /// 端口号，为0表示内核
+    pub pid: u32,
+    /// 订阅的多播组
+    pub groups: u32,
+}
+
+impl NetlinkEndpoint {
+    /// 内核的地址
+    pub const KERNEL: Self = Self { pid: 0, groups: 0 };
+}
+
+#[inline]
+pub fn nlmsg_align(len: usize) -> usize {
+    return (len + NLMSG_ALIGNTO - 1) & !(NLMSG_ALIGNTO - 1);
+}
+
+/// 把结构体转换为字节切片
+fn struct_as_bytes<T: Copy>(value: &T) -> &[u8] {
+    return unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+}
+
+/// 从字节切片的开头读取一个结构体，长度不足时返回None
+pub fn read_struct<T: Copy>(buf: &[u8]) -> Option<T> {
+    if buf.len() < size_of::<T>() {
+        return None;
+    }
+    return Some(unsafe { core::ptr::read_unaligned(buf.as_ptr() as *const T) });
+}
+
+/// ## 解析消息中的属性
+///
+/// ## 返回值
+///
+/// (属性的类型, 属性的值)的列表。遇到格式错误的属性时停止解析
+pub fn parse_attrs(buf: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut attrs = Vec::new();
+    let mut offset = 0;
+    while let Some(attr) = read_struct::<RtAttr>(&buf[offset..]) {
+        let len = attr.rta_len as usize;
+        if len < size_of::<RtAttr>() || offset + len > buf.len() {
+            break;
+        }
+        attrs.push((
+            attr.rta_type & NLA_TYPE_MASK,
+            &buf[offset + size_of::<RtAttr>()..offset + len],
+        ));
+        offset = min(offset + nlmsg_align(len), buf.len());
+    }
+    return attrs;
+}
+
+/// 构造一条netlink消息
+pub struct NetlinkMessageBuilder {
+    buf: Vec<u8>,
+}
+
+impl NetlinkMessageBuilder {
+    pub fn new(msg_type: u16, flags: NetlinkMessageFlags, seq: u32, pid: u32) -> Self {
+        let mut builder = Self { buf: Vec::new() };
+        builder.push(&NlMsgHdr {
+            nlmsg_len: 0,
+            nlmsg_type: msg_type,
+            nlmsg_flags: flags.bits(),
+            nlmsg_seq: seq,
+            nlmsg_pid: pid,
+        });
+        return builder;
+    }
+
+    /// 在消息末尾追加一个结构体
+    pub fn push<T: Copy>(&mut self, value: &T) {
+        self.push_bytes(struct_as_bytes(value));
+    }
+
+    /// 在消息末尾追加一个属性
+    pub fn push_attr(&mut self, attr_type: u16, data: &[u8]) {
+        let attr = RtAttr {
+            rta_len: (size_of::<RtAttr>() + data.len()) as u16,
+            rta_type: attr_type,
+        };
+        self.buf.extend_from_slice(struct_as_bytes(&attr));
+        self.push_bytes(data);
+    }
+
+    fn push_bytes(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+        self.buf.resize(nlmsg_align(self.buf.len()), 0);
+    }
+
+    /// 填写消息的长度，得到完整的消息
+    pub fn build(mut self) -> Vec<u8> {
+        let len = (self.buf.len() as u32).to_ne_bytes();
+        self.buf[..len.len()].copy_from_slice(&len);
+        return self.buf;
+    }
+}
+
+/// 构造一条NLMSG_ERROR消息，`error`为None时表示确认
+fn error_message(request: &NlMsgHdr, error: Option<SystemError>, pid: u32) -> Vec<u8> {
+    let mut builder = NetlinkMessageBuilder::new(
+        NLMSG_ERROR,
+        NetlinkMessageFlags::empty(),
+        request.nlmsg_seq,
+        pid,
+    );
+    builder.push(&NlMsgErr {
+        error: error.map_or(0, |e| e.to_posix_errno()),
+        msg: *request,
+    });
+    return builder.build();
+}
+
+/// 构造多条消息组成的回复的结束消息
+pub fn done_message(request: &NlMsgHdr, pid: u32) -> Vec<u8> {
+    let mut builder = NetlinkMessageBuilder::new(
+        NLMSG_DONE,
+        NetlinkMessageFlags::MULTI,
+        request.nlmsg_seq,
+        pid,
+    );
+    builder.push(&0i32);
+    return builder.build();
+}
+
+/// # Netlink socket的接收队列
+#[derive(Debug)]
+struct NetlinkQueue {
+    /// 尚未被读取的数据报，每个数据报包含一条或者多条消息
+    datagrams: SpinLock<VecDeque<Vec<u8>>>,
+    /// 接收者在上面等待EPOLLIN
+    wait_queue: EventWaitQueue,
+    /// 队列所属socket的epitems
+    epitems: SpinLock<LinkedList<Arc<EPollItem>>>,
+}
+
+impl NetlinkQueue {
+    /// 接收队列的默认大小
+    pub const DEFAULT_BUF_SIZE: usize = 64 * 1024;
+
+    fn new() -> Arc<Self> {
+        return Arc::new(Self {
+            datagrams: SpinLock::new(VecDeque::new()),
+            wait_queue: EventWaitQueue::new(),
+            epitems: SpinLock::new(LinkedList::new()),
+        });
+    }
+
+    /// ## 把消息放入队列
+    ///
+    /// 相邻的消息被打包进同一个数据报，每个数据报不超过NLMSG_GOODSIZE。队列已满时返回ENOBUFS
+    fn push(&self, messages: Vec<Vec<u8>>) -> Result<(), SystemError> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        let mut guard = self.datagrams.lock_irqsave();
+        let queued: usize = guard.iter().map(|d| d.len()).sum();
+        let total: usize = messages.iter().map(|m| m.len()).sum();
+        if queued + total > Self::DEFAULT_BUF_SIZE {
+            return Err(SystemError::ENOBUFS);
+        }
+
+        let mut datagram: Vec<u8> = Vec::new();
+        for message in messages {
+            if !datagram.is_empty() && datagram.len() + message.len() > NLMSG_GOODSIZE {
+                guard.push_back(core::mem::take(&mut datagram));
+            }
+            datagram.extend_from_slice(&message);
+        }
+        guard.push_back(datagram);
+        drop(guard);
+
+        let events = EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+        self.wait_queue.wakeup_any(events.bits() as u64);
+        let _ = EventPoll::wakeup_epoll(&self.epitems, events);
+        return Ok(());
+    }
+
+    /// ## 从队列中读取一个数据报，超出缓冲区的部分会被丢弃
+    ///
+    /// ## 参数
+    /// - `buf`: 接收缓冲区
+    /// - `nonblock`: 队列为空时是否直接返回EAGAIN，否则阻塞等待
+    fn recv(&self, buf: &mut [u8], nonblock: bool) -> Result<usize, SystemError> {
+        let mut guard = self.datagrams.lock_irqsave();
+        loop {
+            if let Some(datagram) = guard.pop_front() {
+                let len = min(buf.len(), datagram.len());
+                buf[..len].copy_from_slice(&datagram[..len]);
+                return Ok(len);
+            }
+            if nonblock {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+
+            self.wait_queue
+                .sleep_unlock_spinlock(EPollEventType::EPOLLIN.bits() as u64, guard);
+            if ProcessManager::current_pcb()
+                .sig_info_irqsave()
+                .sig_pending()
+                .has_pending()
+            {
+                return Err(SystemError::ERESTARTSYS);
+            }
+            guard = self.datagrams.lock_irqsave();
+        }
+    }
+
+    fn poll_in(&self) -> EPollEventType {
+        if self.datagrams.lock_irqsave().is_empty() {
+            return EPollEventType::empty();
+        }
+        return EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+    }
+
+    fn add_epoll(&self, epitem: Arc<EPollItem>) {
+        self.epitems.lock_irqsave().push_back(epitem);
+    }
+
+    fn remove_epoll(&self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
+        let is_remove = !self
+            .epitems
+            .lock_irqsave()
+            .extract_if(|x| x.epoll().ptr_eq(epoll))
+            .collect::<Vec<_>>()
+            .is_empty();
+
+        if is_remove {
+            return Ok(());
+        }
+
+        Err(SystemError::ENOENT)
+    }
+
+    fn clear_epoll(&self) -> Result<(), SystemError> {
+        for epitem in self.epitems.lock_irqsave().iter() {
+            let epoll = epitem.epoll();
+            if let Some(epoll) = epoll.upgrade() {
+                EventPoll::ep_remove(&mut epoll.lock_irqsave(), epitem.fd(), None)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// # Netlink socket
+///
+/// 只能与内核通信，不支持多播组
+#[derive(Debug, Clone)]
+pub struct NetlinkSocket {
+    metadata: SocketMetadata,
+    /// 本socket绑定的地址
+    endpoint: NetlinkEndpoint,
+    /// 本socket的接收队列
+    rx: Arc<NetlinkQueue>,
+}
+
+impl NetlinkSocket {
+    /// 默认的元数据缓冲区大小
+    pub const DEFAULT_METADATA_BUF_SIZE: usize = 1024;
+
+    /// # 创建一个netlink socket
+    ///
+    /// ## 参数
+    /// - `protocol`: netlink协议，目前只支持NETLINK_ROUTE
+    /// - `options`: socket的选项
+    pub fn new(protocol: u8, options: SocketOptions) -> Result<Self, SystemError> {
+        if protocol != NETLINK_ROUTE {
+            return Err(SystemError::EPROTONOSUPPORT);
+        }
+
+        let metadata = SocketMetadata::new(
+            SocketType::NetlinkSocket,
+            NetlinkQueue::DEFAULT_BUF_SIZE,
+            NetlinkQueue::DEFAULT_BUF_SIZE,
+            Self::DEFAULT_METADATA_BUF_SIZE,
+            options,
+        );
+
+        return Ok(Self {
+            metadata,
+            endpoint: NetlinkEndpoint::default(),
+            rx: NetlinkQueue::new(),
+        });
+    }
+
+    /// 本socket的端口号，没有绑定时使用进程的pid
+    fn port_id(&self) -> u32 {
+        if self.endpoint.pid != 0 {
+            return self.endpoint.pid;
+        }
+        return ProcessManager::current_pid().data() as u32;
+    }
+
+    /// ## 处理用户发给内核的消息
+    ///
+    /// 出错的请求以及设置了NLM_F_ACK的请求会收到一条NLMSG_ERROR消息
+    fn handle_messages(&self, buf: &[u8]) -> Result<(), SystemError> {
+        let pid = self.port_id();
+        let mut offset = 0;
+        while let Some(hdr) = read_struct::<NlMsgHdr>(&buf[offset..]) {
+            let len = hdr.nlmsg_len as usize;
+            if len < size_of::<NlMsgHdr>() || offset + len > buf.len() {
+                break;
+            }
+            let payload = &buf[offset + size_of::<NlMsgHdr>()..offset + len];
+            offset = min(offset + nlmsg_align(len), buf.len());
+
+            let flags = NetlinkMessageFlags::from_bits_truncate(hdr.nlmsg_flags);
+            if !flags.contains(NetlinkMessageFlags::REQUEST) {
+                continue;
+            }
+
+            let r = if hdr.nlmsg_type < NLMSG_MIN_TYPE {
+                // 控制消息不需要处理
+                Ok(Vec::new())
+            } else {
+                route::rtnetlink_rcv_msg(&hdr, payload, pid)
+            };
+
+            let mut replies = match r {
+                Ok(replies) => replies,
+                Err(e) => {
+                    self.rx.push(vec![error_message(&hdr, Some(e), pid)])?;
+                    continue;
+                }
+            };
+            if flags.contains(NetlinkMessageFlags::ACK) {
+                replies.push(error_message(&hdr, None, pid));
+            }
+            self.rx.push(replies)?;
+        }
+        return Ok(());
+    }
+}
+
+impl Socket for NetlinkSocket {
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn core::any::Any {
+        self
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint) {
+        return (
+            self.rx
+                .recv(buf, !self.metadata.options.contains(SocketOptions::BLOCK)),
+            Endpoint::Netlink(NetlinkEndpoint::KERNEL),
+        );
+    }
+
+    fn write(&self, buf: &[u8], to: Option<Endpoint>) -> Result<usize, SystemError> {
+        match to {
+            None | Some(Endpoint::Netlink(NetlinkEndpoint { pid: 0, .. })) => {}
+            // 不支持向其他用户进程发送消息
+            Some(Endpoint::Netlink(_)) => return Err(SystemError::ECONNREFUSED),
+            Some(_) => return Err(SystemError::EINVAL),
+        }
+
+        self.handle_messages(buf)?;
+        return Ok(buf.len());
+    }
+
+    fn connect(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        match endpoint {
+            Endpoint::Netlink(NetlinkEndpoint { pid: 0, .. }) => return Ok(()),
+            Endpoint::Netlink(_) => return Err(SystemError::ECONNREFUSED),
+            _ => return Err(SystemError::EINVAL),
+        }
+    }
+
+    fn bind(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        let mut endpoint = match endpoint {
+            Endpoint::Netlink(endpoint) => endpoint,
+            _ => return Err(SystemError::EINVAL),
+        };
+        if endpoint.pid == 0 {
+            endpoint.pid = ProcessManager::current_pid().data() as u32;
+        }
+        self.endpoint = endpoint;
+        return Ok(());
+    }
+
+    fn endpoint(&self) -> Option<Endpoint> {
+        return Some(Endpoint::Netlink(self.endpoint));
+    }
+
+    fn peer_endpoint(&self) -> Option<Endpoint> {
+        return Some(Endpoint::Netlink(NetlinkEndpoint::KERNEL));
+    }
+
+    fn poll(&self) -> EPollEventType {
+        return self.rx.poll_in() | EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM;
+    }
+
+    fn metadata(&self) -> Result<SocketMetadata, SystemError> {
+        Ok(self.metadata.clone())
+    }
+
+    fn box_clone(&self) -> Box<dyn Socket> {
+        Box::new(self.clone())
+    }
+
+    fn add_epoll(&mut self, epitem: Arc<EPollItem>) -> Result<(), SystemError> {
+        self.rx.add_epoll(epitem);
+        Ok(())
+    }
+
+    fn remove_epoll(&mut self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
+        return self.rx.remove_epoll(epoll);
+    }
+
+    fn clear_epoll(&mut self) -> Result<(), SystemError> {
+        return self.rx.clear_epoll();
+    }
+
+    fn close(&mut self) {
+        self.rx.datagrams.lock_irqsave().clear();
+        let _ = self.clear_epoll();
+    }
+
+    fn set_nonblock(&mut self, nonblock: bool) {
+        self.metadata.options.set(SocketOptions::BLOCK, !nonblock);
+    }
+}
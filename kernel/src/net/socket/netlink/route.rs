@@ -0,0 +1,590 @@
+//! NETLINK_ROUTE协议
+//!
+//! 支持查询网络接口、查询和添加地址、查询和添加路由。
+//!
+//! 参考 https://man7.org/linux/man-pages/man7/rtnetlink.7.html
+
+use core::mem::size_of;
+
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+use smoltcp::{
+    iface::Route,
+    wire::{IpAddress, IpCidr, Ipv4Address, Ipv6Address},
+};
+use system_error::SystemError;
+
+use crate::{
+    driver::net::{loopback::is_loopback, IfFlags, NetDriver},
+    net::{
+        netdevice::{iface_by_index, iface_by_name, iface_hardware_type, iface_index, iface_mtu},
+        socket::AddressFamily,
+        NET_DRIVERS,
+    },
+    process::ProcessManager,
+};
+
+use super::{
+    done_message, parse_attrs, read_struct, NetlinkMessageBuilder, NetlinkMessageFlags, NlMsgHdr,
+};
+
+pub const RTM_NEWLINK: u16 = 16;
+pub const RTM_GETLINK: u16 = 18;
+pub const RTM_NEWADDR: u16 = 20;
+pub const RTM_GETADDR: u16 = 22;
+pub const RTM_NEWROUTE: u16 = 24;
+pub const RTM_GETROUTE: u16 = 26;
+
+/* 接口的属性 */
+pub const IFLA_ADDRESS: u16 = 1;
+pub const IFLA_BROADCAST: u16 = 2;
+pub const IFLA_IFNAME: u16 = 3;
+pub const IFLA_MTU: u16 = 4;
+pub const IFLA_OPERSTATE: u16 = 16;
+
+/* 地址的属性 */
+pub const IFA_ADDRESS: u16 = 1;
+pub const IFA_LOCAL: u16 = 2;
+pub const IFA_LABEL: u16 = 3;
+pub const IFA_BROADCAST: u16 = 4;
+
+/// 永久的地址
+pub const IFA_F_PERMANENT: u8 = 0x80;
+
+/* 路由的属性 */
+pub const RTA_DST: u16 = 1;
+pub const RTA_OIF: u16 = 4;
+pub const RTA_GATEWAY: u16 = 5;
+pub const RTA_PREFSRC: u16 = 7;
+pub const RTA_TABLE: u16 = 15;
+
+/// 主路由表
+pub const RT_TABLE_MAIN: u8 = 254;
+
+/// 内核根据接口的地址生成的路由
+pub const RTPROT_KERNEL: u8 = 2;
+/// 启动时或者由用户添加的路由
+pub const RTPROT_BOOT: u8 = 3;
+
+pub const RT_SCOPE_UNIVERSE: u8 = 0;
+pub const RT_SCOPE_LINK: u8 = 253;
+pub const RT_SCOPE_HOST: u8 = 254;
+
+/// 单播路由
+pub const RTN_UNICAST: u8 = 1;
+
+/// 接口的运行状态
+pub const IF_OPER_UNKNOWN: u8 = 0;
+pub const IF_OPER_DOWN: u8 = 2;
+pub const IF_OPER_UP: u8 = 6;
+
+/// RTM_*LINK消息的内容
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IfInfoMsg {
+    pub ifi_family: u8,
+    pub ifi_pad: u8,
+    /// 硬件类型
+    pub ifi_type: u16,
+    /// 接口的索引号
+    pub ifi_index: i32,
+    /// 接口的标志
+    pub ifi_flags: u32,
+    /// 要修改的标志
+    pub ifi_change: u32,
+}
+
+/// RTM_*ADDR消息的内容
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IfAddrMsg {
+    pub ifa_family: u8,
+    /// 地址的前缀长度
+    pub ifa_prefixlen: u8,
+    pub ifa_flags: u8,
+    /// 地址的作用域
+    pub ifa_scope: u8,
+    /// 接口的索引号
+    pub ifa_index: u32,
+}
+
+/// RTM_*ROUTE消息的内容
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RtMsg {
+    pub rtm_family: u8,
+    /// 目的地址的前缀长度
+    pub rtm_dst_len: u8,
+    /// 源地址的前缀长度
+    pub rtm_src_len: u8,
+    pub rtm_tos: u8,
+    /// 路由表
+    pub rtm_table: u8,
+    /// 路由的来源
+    pub rtm_protocol: u8,
+    /// 路由的作用域
+    pub rtm_scope: u8,
+    /// 路由的类型
+    pub rtm_type: u8,
+    pub rtm_flags: u32,
+}
+
+/// ## 处理一条NETLINK_ROUTE消息
+///
+/// ## 参数
+///
+/// - `hdr`: 消息头
+/// - `payload`: 消息头之后的内容
+/// - `pid`: 发送者的端口号
+///
+/// ## 返回值
+///
+/// 要发送给请求者的回复
+pub fn rtnetlink_rcv_msg(
+    hdr: &NlMsgHdr,
+    payload: &[u8],
+    pid: u32,
+) -> Result<Vec<Vec<u8>>, SystemError> {
+    match hdr.nlmsg_type {
+        RTM_GETLINK => return rtnl_getlink(hdr, payload, pid),
+        RTM_NEWADDR => return rtnl_newaddr(hdr, payload),
+        RTM_GETADDR => return rtnl_getaddr(hdr, payload, pid),
+        RTM_NEWROUTE => return rtnl_newroute(hdr, payload),
+        RTM_GETROUTE => return rtnl_getroute(hdr, payload, pid),
+        _ => return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+    }
+}
+
+/// 修改地址、路由等网络配置需要CAP_NET_ADMIN，没有权限时返回EPERM
+fn check_net_admin() -> Result<(), SystemError> {
+    if !ProcessManager::current_pcb().cred().can_net_admin() {
+        return Err(SystemError::EPERM);
+    }
+    return Ok(());
+}
+
+#[inline]
+fn is_dump(hdr: &NlMsgHdr) -> bool {
+    return NetlinkMessageFlags::from_bits_truncate(hdr.nlmsg_flags)
+        .contains(NetlinkMessageFlags::DUMP);
+}
+
+/// 读取消息开头的结构体，并解析它后面的属性
+fn parse_msg<T: Copy>(payload: &[u8]) -> Result<(T, Vec<(u16, &[u8])>), SystemError> {
+    let msg = read_struct::<T>(payload).ok_or(SystemError::EINVAL)?;
+    return Ok((msg, parse_attrs(&payload[size_of::<T>()..])));
+}
+
+fn find_attr<'a>(attrs: &[(u16, &'a [u8])], attr_type: u16) -> Option<&'a [u8]> {
+    return attrs
+        .iter()
+        .find(|(t, _)| *t == attr_type)
+        .map(|(_, data)| *data);
+}
+
+/// 把属性的值解析为指定地址族的地址
+fn parse_addr(family: u8, data: &[u8]) -> Result<IpAddress, SystemError> {
+    if family == AddressFamily::INet as u8 && data.len() == 4 {
+        return Ok(IpAddress::Ipv4(Ipv4Address::from_bytes(data)));
+    }
+    if family == AddressFamily::INet6 as u8 && data.len() == 16 {
+        return Ok(IpAddress::Ipv6(Ipv6Address::from_bytes(data)));
+    }
+    return Err(SystemError::EINVAL);
+}
+
+/// 根据地址族和前缀长度构造网段，前缀长度无效时返回EINVAL
+fn make_cidr(addr: IpAddress, prefix_len: u8) -> Result<IpCidr, SystemError> {
+    let max = match addr {
+        IpAddress::Ipv4(_) => 32,
+        IpAddress::Ipv6(_) => 128,
+    };
+    if prefix_len > max {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok(IpCidr::new(addr, prefix_len));
+}
+
+fn addr_family(addr: &IpAddress) -> u8 {
+    match addr {
+        IpAddress::Ipv4(_) => AddressFamily::INet as u8,
+        IpAddress::Ipv6(_) => AddressFamily::INet6 as u8,
+    }
+}
+
+/// 网段的网络地址
+fn cidr_network(cidr: &IpCidr) -> IpAddress {
+    match cidr {
+        IpCidr::Ipv4(cidr) => IpAddress::Ipv4(cidr.network().address()),
+        IpCidr::Ipv6(cidr) => {
+            let mut bytes = cidr.address().0;
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                let bits = (cidr.prefix_len() as usize).saturating_sub(i * 8).min(8);
+                *byte &= !(0xffu8.checked_shr(bits as u32).unwrap_or(0));
+            }
+            IpAddress::Ipv6(Ipv6Address(bytes))
+        }
+    }
+}
+
+/// 地址族过滤条件，AF_UNSPEC匹配所有地址
+#[inline]
+fn family_matches(filter: u8, addr: &IpAddress) -> bool {
+    return filter == AddressFamily::Unspecified as u8 || filter == addr_family(addr);
+}
+
+/// 接口名，以'\0'结尾
+fn ifname_attr(iface: &Arc<dyn NetDriver>) -> Vec<u8> {
+    let mut name = iface.name().into_bytes();
+    name.push(0);
+    return name;
+}
+
+/// 已经配置的地址，不包括DHCP失败时设置的未指定地址
+fn iface_addrs(iface: &Arc<dyn NetDriver>) -> Vec<IpCidr> {
+    return iface
+        .inner_iface()
+        .lock()
+        .ip_addrs()
+        .iter()
+        .filter(|cidr| !cidr.address().is_unspecified())
+        .cloned()
+        .collect();
+}
+
+fn all_ifaces() -> Vec<Arc<dyn NetDriver>> {
+    return NET_DRIVERS.read_irqsave().values().cloned().collect();
+}
+
+/// 构造描述接口的RTM_NEWLINK消息
+fn fill_link(
+    iface: &Arc<dyn NetDriver>,
+    flags: NetlinkMessageFlags,
+    seq: u32,
+    pid: u32,
+) -> Vec<u8> {
+    let iface_flags = iface.flags();
+    let mut builder = NetlinkMessageBuilder::new(RTM_NEWLINK, flags, seq, pid);
+    builder.push(&IfInfoMsg {
+        ifi_family: AddressFamily::Unspecified as u8,
+        ifi_pad: 0,
+        ifi_type: iface_hardware_type(iface),
+        ifi_index: iface_index(iface) as i32,
+        ifi_flags: iface_flags.bits(),
+        ifi_change: 0,
+    });
+    builder.push_attr(IFLA_IFNAME, &ifname_attr(iface));
+    builder.push_attr(IFLA_MTU, &iface_mtu(iface).to_ne_bytes());
+
+    let operstate = if is_loopback(iface) {
+        IF_OPER_UNKNOWN
+    } else if iface_flags.contains(IfFlags::RUNNING) {
+        IF_OPER_UP
+    } else {
+        IF_OPER_DOWN
+    };
+    builder.push_attr(IFLA_OPERSTATE, &[operstate]);
+
+    let mac = iface.mac();
+    let broadcast = if iface_flags.contains(IfFlags::BROADCAST) {
+        [0xff; 6]
+    } else {
+        [0; 6]
+    };
+    builder.push_attr(IFLA_ADDRESS, &mac.0);
+    builder.push_attr(IFLA_BROADCAST, &broadcast);
+    return builder.build();
+}
+
+/// ## RTM_GETLINK：查询接口
+///
+/// 设置了NLM_F_DUMP时返回所有接口，否则根据索引号或者接口名返回一个接口
+fn rtnl_getlink(hdr: &NlMsgHdr, payload: &[u8], pid: u32) -> Result<Vec<Vec<u8>>, SystemError> {
+    if is_dump(hdr) {
+        let mut replies: Vec<Vec<u8>> = all_ifaces()
+            .iter()
+            .map(|iface| fill_link(iface, NetlinkMessageFlags::MULTI, hdr.nlmsg_seq, pid))
+            .collect();
+        replies.push(done_message(hdr, pid));
+        return Ok(replies);
+    }
+
+    let (ifi, attrs) = parse_msg::<IfInfoMsg>(payload)?;
+    let iface = if ifi.ifi_index > 0 {
+        iface_by_index(ifi.ifi_index as u32)
+    } else if let Some(name) = find_attr(&attrs, IFLA_IFNAME) {
+        let len = name.iter().position(|c| *c == 0).unwrap_or(name.len());
+        let name = String::from_utf8(name[..len].to_vec()).map_err(|_| SystemError::EINVAL)?;
+        iface_by_name(&name)
+    } else {
+        return Err(SystemError::EINVAL);
+    };
+    let iface = iface.ok_or(SystemError::ENODEV)?;
+
+    return Ok(vec![fill_link(
+        &iface,
+        NetlinkMessageFlags::empty(),
+        hdr.nlmsg_seq,
+        pid,
+    )]);
+}
+
+/// 构造描述地址的RTM_NEWADDR消息
+fn fill_addr(iface: &Arc<dyn NetDriver>, cidr: &IpCidr, seq: u32, pid: u32) -> Vec<u8> {
+    let addr = cidr.address();
+    let scope = match addr {
+        IpAddress::Ipv4(addr) if addr.is_loopback() => RT_SCOPE_HOST,
+        IpAddress::Ipv6(addr) if addr.is_loopback() => RT_SCOPE_HOST,
+        IpAddress::Ipv6(addr) if addr.is_link_local() => RT_SCOPE_LINK,
+        _ => RT_SCOPE_UNIVERSE,
+    };
+
+    let mut builder = NetlinkMessageBuilder::new(RTM_NEWADDR, NetlinkMessageFlags::MULTI, seq, pid);
+    builder.push(&IfAddrMsg {
+        ifa_family: addr_family(&addr),
+        ifa_prefixlen: cidr.prefix_len(),
+        ifa_flags: IFA_F_PERMANENT,
+        ifa_scope: scope,
+        ifa_index: iface_index(iface),
+    });
+    builder.push_attr(IFA_ADDRESS, addr.as_bytes());
+    if let IpCidr::Ipv4(cidr) = cidr {
+        builder.push_attr(IFA_LOCAL, addr.as_bytes());
+        if let Some(broadcast) = cidr.broadcast() {
+            builder.push_attr(IFA_BROADCAST, broadcast.as_bytes());
+        }
+        builder.push_attr(IFA_LABEL, &ifname_attr(iface));
+    }
+    return builder.build();
+}
+
+/// ## RTM_GETADDR：查询所有接口的地址
+///
+/// 只支持NLM_F_DUMP请求，可以按照地址族过滤
+fn rtnl_getaddr(hdr: &NlMsgHdr, payload: &[u8], pid: u32) -> Result<Vec<Vec<u8>>, SystemError> {
+    if !is_dump(hdr) {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+    let family = payload
+        .first()
+        .cloned()
+        .unwrap_or(AddressFamily::Unspecified as u8);
+
+    let mut replies = Vec::new();
+    for iface in all_ifaces() {
+        for cidr in iface_addrs(&iface) {
+            if family_matches(family, &cidr.address()) {
+                replies.push(fill_addr(&iface, &cidr, hdr.nlmsg_seq, pid));
+            }
+        }
+    }
+    replies.push(done_message(hdr, pid));
+    return Ok(replies);
+}
+
+/// ## RTM_NEWADDR：为接口添加地址
+///
+/// 地址已经存在时，设置了NLM_F_EXCL则返回EEXIST，否则更新它的前缀长度
+fn rtnl_newaddr(hdr: &NlMsgHdr, payload: &[u8]) -> Result<Vec<Vec<u8>>, SystemError> {
+    check_net_admin()?;
+    let (ifa, attrs) = parse_msg::<IfAddrMsg>(payload)?;
+    let iface = iface_by_index(ifa.ifa_index).ok_or(SystemError::ENODEV)?;
+    let addr = find_attr(&attrs, IFA_LOCAL)
+        .or_else(|| find_attr(&attrs, IFA_ADDRESS))
+        .ok_or(SystemError::EINVAL)?;
+    let addr = parse_addr(ifa.ifa_family, addr)?;
+    if addr.is_unspecified() {
+        return Err(SystemError::EINVAL);
+    }
+    let cidr = make_cidr(addr, ifa.ifa_prefixlen)?;
+    let flags = NetlinkMessageFlags::from_bits_truncate(hdr.nlmsg_flags);
+
+    let mut r = Ok(Vec::new());
+    iface.inner_iface().lock().update_ip_addrs(|addrs| {
+        // DHCP失败时会设置一个未指定的IPv4地址，用新的地址替换它
+        let existing = addrs.iter_mut().find(|old| {
+            let old = old.address();
+            old == addr || (old.is_unspecified() && addr_family(&old) == addr_family(&addr))
+        });
+        match existing {
+            Some(old) if old.address() == addr && flags.contains(NetlinkMessageFlags::EXCL) => {
+                r = Err(SystemError::EEXIST);
+            }
+            Some(old) => *old = cidr,
+            None => {
+                if addrs.push(cidr).is_err() {
+                    r = Err(SystemError::ENOSPC);
+                }
+            }
+        }
+    });
+    return r;
+}
+
+/// 构造描述路由的RTM_NEWROUTE消息
+fn fill_route(
+    iface: &Arc<dyn NetDriver>,
+    dst: &IpCidr,
+    gateway: Option<IpAddress>,
+    prefsrc: Option<IpAddress>,
+    seq: u32,
+    pid: u32,
+) -> Vec<u8> {
+    let (protocol, scope) = match gateway {
+        Some(_) => (RTPROT_BOOT, RT_SCOPE_UNIVERSE),
+        None => (RTPROT_KERNEL, RT_SCOPE_LINK),
+    };
+
+    let mut builder =
+        NetlinkMessageBuilder::new(RTM_NEWROUTE, NetlinkMessageFlags::MULTI, seq, pid);
+    builder.push(&RtMsg {
+        rtm_family: addr_family(&dst.address()),
+        rtm_dst_len: dst.prefix_len(),
+        rtm_src_len: 0,
+        rtm_tos: 0,
+        rtm_table: RT_TABLE_MAIN,
+        rtm_protocol: protocol,
+        rtm_scope: scope,
+        rtm_type: RTN_UNICAST,
+        rtm_flags: 0,
+    });
+    builder.push_attr(RTA_TABLE, &(RT_TABLE_MAIN as u32).to_ne_bytes());
+    if dst.prefix_len() > 0 {
+        builder.push_attr(RTA_DST, cidr_network(dst).as_bytes());
+    }
+    if let Some(prefsrc) = prefsrc {
+        builder.push_attr(RTA_PREFSRC, prefsrc.as_bytes());
+    }
+    if let Some(gateway) = gateway {
+        builder.push_attr(RTA_GATEWAY, gateway.as_bytes());
+    }
+    builder.push_attr(RTA_OIF, &iface_index(iface).to_ne_bytes());
+    return builder.build();
+}
+
+/// 接口上的网关路由
+fn iface_routes(iface: &Arc<dyn NetDriver>) -> Vec<Route> {
+    let mut routes = Vec::new();
+    iface
+        .inner_iface()
+        .lock()
+        .routes_mut()
+        .update(|table| routes.extend(table.iter().cloned()));
+    return routes;
+}
+
+/// ## RTM_GETROUTE：查询主路由表
+///
+/// 只支持NLM_F_DUMP请求。每个网卡的地址对应一条直连路由，此外还有添加到网卡上的网关路由
+fn rtnl_getroute(hdr: &NlMsgHdr, payload: &[u8], pid: u32) -> Result<Vec<Vec<u8>>, SystemError> {
+    if !is_dump(hdr) {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+    let family = payload
+        .first()
+        .cloned()
+        .unwrap_or(AddressFamily::Unspecified as u8);
+
+    let mut replies = Vec::new();
+    // 回环接口的路由属于本地路由表
+    for iface in all_ifaces().iter().filter(|iface| !is_loopback(iface)) {
+        for cidr in iface_addrs(iface) {
+            if family_matches(family, &cidr.address()) {
+                replies.push(fill_route(
+                    iface,
+                    &cidr,
+                    None,
+                    Some(cidr.address()),
+                    hdr.nlmsg_seq,
+                    pid,
+                ));
+            }
+        }
+        for route in iface_routes(iface) {
+            if family_matches(family, &route.via_router) {
+                replies.push(fill_route(
+                    iface,
+                    &route.cidr,
+                    Some(route.via_router),
+                    None,
+                    hdr.nlmsg_seq,
+                    pid,
+                ));
+            }
+        }
+    }
+    replies.push(done_message(hdr, pid));
+    return Ok(replies);
+}
+
+/// ## RTM_NEWROUTE：添加网关路由
+///
+/// 必须指定网关，网关必须与出口接口的某个地址在同一个网段。没有指定出口接口时，
+/// 选择能够直接到达网关的接口
+fn rtnl_newroute(hdr: &NlMsgHdr, payload: &[u8]) -> Result<Vec<Vec<u8>>, SystemError> {
+    check_net_admin()?;
+    let (rtm, attrs) = parse_msg::<RtMsg>(payload)?;
+    if rtm.rtm_type != RTN_UNICAST {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+
+    // smoltcp的路由表只支持经过网关的路由
+    let gateway = find_attr(&attrs, RTA_GATEWAY).ok_or(SystemError::EOPNOTSUPP_OR_ENOTSUP)?;
+    let gateway = parse_addr(rtm.rtm_family, gateway)?;
+    let dst = match find_attr(&attrs, RTA_DST) {
+        Some(dst) => parse_addr(rtm.rtm_family, dst)?,
+        None if rtm.rtm_dst_len == 0 => match gateway {
+            IpAddress::Ipv4(_) => IpAddress::Ipv4(Ipv4Address::UNSPECIFIED),
+            IpAddress::Ipv6(_) => IpAddress::Ipv6(Ipv6Address::UNSPECIFIED),
+        },
+        None => return Err(SystemError::EINVAL),
+    };
+    let cidr = make_cidr(dst, rtm.rtm_dst_len)?;
+
+    let reachable = |iface: &Arc<dyn NetDriver>| {
+        iface_addrs(iface)
+            .iter()
+            .any(|cidr| cidr.contains_addr(&gateway))
+    };
+    let iface = match find_attr(&attrs, RTA_OIF) {
+        Some(oif) => {
+            let oif = read_struct::<u32>(oif).ok_or(SystemError::EINVAL)?;
+            iface_by_index(oif).ok_or(SystemError::ENODEV)?
+        }
+        None => all_ifaces()
+            .into_iter()
+            .find(|iface| reachable(iface))
+            .ok_or(SystemError::ENETUNREACH)?,
+    };
+    if !reachable(&iface) {
+        return Err(SystemError::ENETUNREACH);
+    }
+
+    let flags = NetlinkMessageFlags::from_bits_truncate(hdr.nlmsg_flags);
+    let route = Route {
+        cidr,
+        via_router: gateway,
+        preferred_until: None,
+        expires_at: None,
+    };
+    let mut r = Ok(Vec::new());
+    iface.inner_iface().lock().routes_mut().update(|table| {
+        let existing = table.iter_mut().find(|old| old.cidr == cidr);
+        match existing {
+            Some(old) => {
+                if flags.contains(NetlinkMessageFlags::REPLACE)
+                    && !flags.contains(NetlinkMessageFlags::EXCL)
+                {
+                    *old = route;
+                } else {
+                    r = Err(SystemError::EEXIST);
+                }
+            }
+            None => {
+                if table.push(route).is_err() {
+                    r = Err(SystemError::ENOSPC);
+                }
+            }
+        }
+    });
+    return r;
+}
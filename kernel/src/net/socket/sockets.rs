@@ -1,6 +1,5 @@
 use alloc::{boxed::Box, sync::Arc, vec::Vec};
 use smoltcp::{
-    iface::SocketHandle,
     socket::{raw, tcp, udp},
     wire,
 };
@@ -9,18 +8,269 @@ use system_error::SystemError;
 use crate::{
     driver::net::NetDriver,
     kerror, kwarn,
-    libs::{rwlock::RwLock, spinlock::SpinLock},
+    libs::spinlock::SpinLock,
     net::{
-        event_poll::EPollEventType, net_core::poll_ifaces, Endpoint, Protocol, ShutdownType,
-        NET_DRIVERS,
+        event_poll::EPollEventType,
+        net_core::{default_iface, local_iface, poll_ifaces, route_iface, up_ifaces},
+        netdevice::iface_by_index,
+        syscall::{
+            PosixIpProtocol, PosixIpSocketOptions, PosixSocketOption, PosixTcpSocketOptions,
+        },
+        Endpoint, Protocol, ShutdownType,
     },
 };
 
 use super::{
-    GlobalSocketHandle, Socket, SocketHandleItem, SocketMetadata, SocketOptions, SocketPollMethod,
-    SocketType, SocketpairOps, HANDLE_MAP, PORT_MANAGER, SOCKET_SET,
+    optval_to_i32, write_optval, GlobalSocketHandle, Socket, SocketHandleItem, SocketKey,
+    SocketMetadata, SocketOptions, SocketPollMethod, SocketType, SocketpairOps, HANDLE_MAP,
+    PORT_MANAGER, SOL_SOCKET,
 };
 
+/// # IP层和TCP层的socket选项
+///
+/// 通过setsockopt设置，在发送数据包或者创建新的smoltcp socket时应用
+#[derive(Debug, Clone, Copy)]
+struct InetOptions {
+    /// IP_TTL，为None时使用默认值
+    ttl: Option<u8>,
+    /// IP_MULTICAST_TTL
+    multicast_ttl: u8,
+    /// IP_MULTICAST_LOOP。smoltcp不会把发出的多播数据包回送给本机，这个选项只被记录下来
+    multicast_loop: bool,
+    /// IP_MULTICAST_IF，发送多播数据包时使用的本地地址
+    multicast_if: Option<wire::Ipv4Address>,
+    /// TCP_NODELAY，为true时关闭Nagle算法
+    nodelay: bool,
+    /// TCP_KEEPIDLE，连接空闲多少秒之后开始发送keepalive探测
+    keep_idle: u32,
+    /// TCP_KEEPINTVL，keepalive探测之间间隔的秒数
+    keep_intvl: u32,
+    /// TCP_KEEPCNT，没有响应的keepalive探测达到这个数量时中止连接
+    keep_cnt: u32,
+}
+
+impl InetOptions {
+    /// 没有设置IP_TTL时使用的TTL
+    const DEFAULT_TTL: u8 = 64;
+
+    fn new() -> Self {
+        return Self {
+            ttl: None,
+            multicast_ttl: 1,
+            multicast_loop: true,
+            multicast_if: None,
+            nodelay: false,
+            keep_idle: 7200,
+            keep_intvl: 75,
+            keep_cnt: 9,
+        };
+    }
+
+    /// 发往`addr`的数据包使用的TTL
+    fn hop_limit(&self, addr: &wire::IpAddress) -> u8 {
+        if addr.is_multicast() {
+            return self.multicast_ttl;
+        }
+        return self.ttl.unwrap_or(Self::DEFAULT_TTL);
+    }
+
+    /// ## 设置SOL_SOCKET、IPPROTO_IP和IPPROTO_TCP层次上的选项
+    ///
+    /// SOL_SOCKET层次上的选项保存在`metadata`中。不支持的选项只打印警告，不返回错误
+    fn setsockopt(
+        &mut self,
+        metadata: &mut SocketMetadata,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        if level == SOL_SOCKET as usize {
+            let optname = PosixSocketOption::try_from(optname as i32)
+                .map_err(|_| SystemError::ENOPROTOOPT)?;
+            return match metadata.setsockopt(optname, optval) {
+                Err(SystemError::ENOPROTOOPT) => {
+                    kwarn!("setsockopt: unsupported socket option {optname:?}");
+                    Ok(())
+                }
+                r => r,
+            };
+        }
+
+        let protocol =
+            PosixIpProtocol::try_from(level as u16).map_err(|_| SystemError::ENOPROTOOPT)?;
+        match protocol {
+            PosixIpProtocol::IP => {
+                let optname = PosixIpSocketOptions::try_from(optname as i32)
+                    .map_err(|_| SystemError::ENOPROTOOPT)?;
+                match optname {
+                    PosixIpSocketOptions::Ttl => {
+                        self.ttl = match optval_to_i32(optval)? {
+                            -1 => None,
+                            ttl @ 1..=255 => Some(ttl as u8),
+                            _ => return Err(SystemError::EINVAL),
+                        };
+                    }
+                    PosixIpSocketOptions::MulticastTtl => {
+                        self.multicast_ttl = match optval_to_byte_or_i32(optval)? {
+                            -1 => 1,
+                            ttl @ 0..=255 => ttl as u8,
+                            _ => return Err(SystemError::EINVAL),
+                        };
+                    }
+                    PosixIpSocketOptions::MulticastLoop => {
+                        self.multicast_loop = optval_to_byte_or_i32(optval)? != 0;
+                    }
+                    PosixIpSocketOptions::MulticastIf => {
+                        self.multicast_if = optval_to_multicast_if(optval)?;
+                    }
+                    _ => {
+                        kwarn!("setsockopt: unsupported ip option {optname:?}");
+                    }
+                }
+            }
+            PosixIpProtocol::TCP if metadata.socket_type == SocketType::TcpSocket => {
+                let optname = PosixTcpSocketOptions::try_from(optname as i32)
+                    .map_err(|_| SystemError::ENOPROTOOPT)?;
+                match optname {
+                    PosixTcpSocketOptions::NoDelay => {
+                        self.nodelay = optval_to_i32(optval)? != 0;
+                    }
+                    PosixTcpSocketOptions::KeepIdle => {
+                        self.keep_idle = optval_to_range(optval, 1, 32767)?;
+                    }
+                    PosixTcpSocketOptions::KeepIntvl => {
+                        self.keep_intvl = optval_to_range(optval, 1, 32767)?;
+                    }
+                    PosixTcpSocketOptions::KeepCnt => {
+                        self.keep_cnt = optval_to_range(optval, 1, 127)?;
+                    }
+                    _ => {
+                        kwarn!("setsockopt: unsupported tcp option {optname:?}");
+                    }
+                }
+            }
+            _ => return Err(SystemError::ENOPROTOOPT),
+        }
+        return Ok(());
+    }
+
+    /// ## 读取SOL_SOCKET、IPPROTO_IP和IPPROTO_TCP层次上的选项
+    ///
+    /// ## 返回值
+    /// 写入`optval`的长度
+    fn getsockopt(
+        &self,
+        metadata: &SocketMetadata,
+        level: usize,
+        optname: usize,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        if level == SOL_SOCKET as usize {
+            let optname = PosixSocketOption::try_from(optname as i32)
+                .map_err(|_| SystemError::ENOPROTOOPT)?;
+            return metadata.getsockopt(optname, optval);
+        }
+
+        let protocol =
+            PosixIpProtocol::try_from(level as u16).map_err(|_| SystemError::ENOPROTOOPT)?;
+        let value: i32 = match protocol {
+            PosixIpProtocol::IP => {
+                let optname = PosixIpSocketOptions::try_from(optname as i32)
+                    .map_err(|_| SystemError::ENOPROTOOPT)?;
+                match optname {
+                    PosixIpSocketOptions::Ttl => self.ttl.unwrap_or(Self::DEFAULT_TTL) as i32,
+                    PosixIpSocketOptions::MulticastTtl => self.multicast_ttl as i32,
+                    PosixIpSocketOptions::MulticastLoop => self.multicast_loop as i32,
+                    PosixIpSocketOptions::MulticastIf => {
+                        let addr = self.multicast_if.unwrap_or(wire::Ipv4Address::UNSPECIFIED);
+                        return Ok(write_optval(optval, addr.as_bytes()));
+                    }
+                    _ => return Err(SystemError::ENOPROTOOPT),
+                }
+            }
+            PosixIpProtocol::TCP if metadata.socket_type == SocketType::TcpSocket => {
+                let optname = PosixTcpSocketOptions::try_from(optname as i32)
+                    .map_err(|_| SystemError::ENOPROTOOPT)?;
+                match optname {
+                    PosixTcpSocketOptions::NoDelay => self.nodelay as i32,
+                    PosixTcpSocketOptions::KeepIdle => self.keep_idle as i32,
+                    PosixTcpSocketOptions::KeepIntvl => self.keep_intvl as i32,
+                    PosixTcpSocketOptions::KeepCnt => self.keep_cnt as i32,
+                    PosixTcpSocketOptions::Congestion => return Ok(0),
+                    _ => return Err(SystemError::ENOPROTOOPT),
+                }
+            }
+            _ => return Err(SystemError::ENOPROTOOPT),
+        };
+        return Ok(write_optval(optval, &value.to_ne_bytes()));
+    }
+
+    /// ## 把选项应用到smoltcp的tcp socket上
+    ///
+    /// smoltcp只有一个keepalive间隔：连接空闲这么久之后发送探测，之后每隔这么久再探测一次。
+    /// 这里把TCP_KEEPIDLE作为这个间隔，并且在TCP_KEEPIDLE + TCP_KEEPINTVL * TCP_KEEPCNT秒内收不到对端的响应时中止连接
+    fn apply_to_tcp(&self, metadata: &SocketMetadata, socket: &mut tcp::Socket) {
+        socket.set_hop_limit(self.ttl);
+        socket.set_nagle_enabled(!self.nodelay);
+        if metadata.options.contains(SocketOptions::KEEPALIVE) {
+            let timeout = self.keep_idle as u64 + self.keep_intvl as u64 * self.keep_cnt as u64;
+            socket.set_keep_alive(Some(smoltcp::time::Duration::from_secs(
+                self.keep_idle as u64,
+            )));
+            socket.set_timeout(Some(smoltcp::time::Duration::from_secs(timeout)));
+        } else {
+            socket.set_keep_alive(None);
+            socket.set_timeout(None);
+        }
+    }
+}
+
+/// 从optval中读出一个int。与Linux一样，IP_MULTICAST_TTL等选项也可以只传入一个字节
+fn optval_to_byte_or_i32(optval: &[u8]) -> Result<i32, SystemError> {
+    if optval.len() >= core::mem::size_of::<i32>() {
+        return optval_to_i32(optval);
+    }
+    return optval.first().map(|x| *x as i32).ok_or(SystemError::EINVAL);
+}
+
+/// 从optval中读出一个在`[min, max]`范围内的int
+fn optval_to_range(optval: &[u8], min: i32, max: i32) -> Result<u32, SystemError> {
+    let value = optval_to_i32(optval)?;
+    if value < min || value > max {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok(value as u32);
+}
+
+/// ## 从optval中读出IP_MULTICAST_IF指定的本地地址
+///
+/// optval可以是`struct in_addr`，也可以是`struct ip_mreqn`。使用`ip_mreqn`时，如果没有指定地址，就使用接口索引号对应的接口的地址
+fn optval_to_multicast_if(optval: &[u8]) -> Result<Option<wire::Ipv4Address>, SystemError> {
+    // struct ip_mreqn { struct in_addr imr_multiaddr; struct in_addr imr_address; int imr_ifindex; }
+    const IP_MREQN_SIZE: usize = 12;
+
+    let addr = if optval.len() >= IP_MREQN_SIZE {
+        let addr = wire::Ipv4Address::from_bytes(&optval[4..8]);
+        let ifindex = optval_to_i32(&optval[8..12])?;
+        if addr.is_unspecified() && ifindex > 0 {
+            let iface = iface_by_index(ifindex as u32).ok_or(SystemError::ENODEV)?;
+            let addr = iface.inner_iface().lock().ipv4_addr();
+            addr.ok_or(SystemError::EADDRNOTAVAIL)?
+        } else {
+            addr
+        }
+    } else if optval.len() >= 4 {
+        wire::Ipv4Address::from_bytes(&optval[0..4])
+    } else {
+        return Err(SystemError::EINVAL);
+    };
+
+    if addr.is_unspecified() {
+        return Ok(None);
+    }
+    return Ok(Some(addr));
+}
+
 /// @brief 表示原始的socket。原始套接字绕过传输层协议（如 TCP 或 UDP）并提供对网络层协议（如 IP）的直接访问。
 ///
 /// ref: https://man7.org/linux/man-pages/man7/raw.7.html
@@ -33,6 +283,8 @@ pub struct RawSocket {
     header_included: bool,
     /// socket的metadata
     metadata: SocketMetadata,
+    /// IP层的选项
+    inet_options: InetOptions,
 }
 
 impl RawSocket {
@@ -48,8 +300,8 @@ impl RawSocket {
     /// @param protocol 协议号
     /// @param options socket的选项
     ///
-    /// @return 返回创建的原始的socket
-    pub fn new(protocol: Protocol, options: SocketOptions) -> Self {
+    /// @return 返回创建的原始的socket，没有网络接口时返回ENODEV
+    pub fn new(protocol: Protocol, options: SocketOptions) -> Result<Self, SystemError> {
         let rx_buffer = raw::PacketBuffer::new(
             vec![raw::PacketMetadata::EMPTY; Self::DEFAULT_METADATA_BUF_SIZE],
             vec![0; Self::DEFAULT_RX_BUF_SIZE],
@@ -66,9 +318,10 @@ impl RawSocket {
             tx_buffer,
         );
 
-        // 把socket添加到socket集合中，并得到socket的句柄
+        // 把socket添加到默认网络接口的socket集合中，并得到socket的句柄
+        let iface = default_iface().ok_or(SystemError::ENODEV)?;
         let handle: Arc<GlobalSocketHandle> =
-            GlobalSocketHandle::new(SOCKET_SET.lock_irqsave().add(socket));
+            GlobalSocketHandle::new(iface.clone(), iface.sockets().lock_irqsave().add(socket));
 
         let metadata = SocketMetadata::new(
             SocketType::RawSocket,
@@ -78,11 +331,12 @@ impl RawSocket {
             options,
         );
 
-        return Self {
+        return Ok(Self {
             handle,
             header_included: false,
             metadata,
-        };
+            inet_options: InetOptions::new(),
+        });
     }
 }
 
@@ -97,10 +351,12 @@ impl Socket for RawSocket {
 
     fn read(&mut self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint) {
         poll_ifaces();
+        let deadline = self.metadata.recv_deadline();
         loop {
             // 如何优化这里？
-            let mut socket_set_guard = SOCKET_SET.lock_irqsave();
-            let socket = socket_set_guard.get_mut::<raw::Socket>(self.handle.0);
+            let iface = self.handle.iface();
+            let mut socket_set_guard = iface.sockets().lock_irqsave();
+            let socket = socket_set_guard.get_mut::<raw::Socket>(self.handle.handle());
 
             match socket.recv_slice(buf) {
                 Ok(len) => {
@@ -121,19 +377,23 @@ impl Socket for RawSocket {
                 }
             }
             drop(socket_set_guard);
-            SocketHandleItem::sleep(
+            if let Err(e) = SocketHandleItem::sleep(
                 self.socket_handle(),
                 EPollEventType::EPOLLIN.bits() as u64,
                 HANDLE_MAP.read_irqsave(),
-            );
+                deadline,
+            ) {
+                return (Err(e), Endpoint::Ip(None));
+            }
         }
     }
 
     fn write(&self, buf: &[u8], to: Option<Endpoint>) -> Result<usize, SystemError> {
         // 如果用户发送的数据包，包含IP头，则直接发送
         if self.header_included {
-            let mut socket_set_guard = SOCKET_SET.lock_irqsave();
-            let socket = socket_set_guard.get_mut::<raw::Socket>(self.handle.0);
+            let iface = self.handle.iface();
+            let mut socket_set_guard = iface.sockets().lock_irqsave();
+            let socket = socket_set_guard.get_mut::<raw::Socket>(self.handle.handle());
             match socket.send_slice(buf) {
                 Ok(_) => {
                     return Ok(buf.len());
@@ -146,12 +406,13 @@ impl Socket for RawSocket {
             // 如果用户发送的数据包，不包含IP头，则需要自己构造IP头
 
             if let Some(Endpoint::Ip(Some(endpoint))) = to {
-                let mut socket_set_guard = SOCKET_SET.lock_irqsave();
-                let socket: &mut raw::Socket =
-                    socket_set_guard.get_mut::<raw::Socket>(self.handle.0);
+                let iface = route_iface(&endpoint.addr).ok_or(SystemError::ENETUNREACH)?;
+                // 数据包由socket所在的网络接口发送，所以先把socket移动到发往目的地址的接口上
+                self.handle.move_to(iface.clone());
 
-                // 暴力解决方案：只考虑0号网卡。 TODO：考虑多网卡的情况！！！
-                let iface = NET_DRIVERS.read_irqsave().get(&0).unwrap().clone();
+                let mut socket_set_guard = iface.sockets().lock_irqsave();
+                let socket: &mut raw::Socket =
+                    socket_set_guard.get_mut::<raw::Socket>(self.handle.handle());
 
                 // 构造IP头
                 let ipv4_src_addr: Option<wire::Ipv4Address> =
@@ -175,6 +436,7 @@ impl Socket for RawSocket {
                     packet.set_total_len((20 + len) as u16);
                     packet.set_src_addr(ipv4_src_addr);
                     packet.set_dst_addr(ipv4_dst);
+                    packet.set_hop_limit(self.inet_options.hop_limit(&endpoint.addr));
 
                     // 设置ipv4 header的protocol字段
                     packet.set_next_header(socket.ip_protocol().into());
@@ -208,6 +470,42 @@ impl Socket for RawSocket {
         Ok(())
     }
 
+    fn setsockopt(
+        &mut self,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        if level == PosixIpProtocol::IP as usize
+            && optname == PosixIpSocketOptions::HdrIncl as usize
+        {
+            self.header_included = optval_to_i32(optval)? != 0;
+            return Ok(());
+        }
+        return self
+            .inet_options
+            .setsockopt(&mut self.metadata, level, optname, optval);
+    }
+
+    fn getsockopt(
+        &self,
+        level: usize,
+        optname: usize,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        if level == PosixIpProtocol::IP as usize
+            && optname == PosixIpSocketOptions::HdrIncl as usize
+        {
+            return Ok(write_optval(
+                optval,
+                &(self.header_included as i32).to_ne_bytes(),
+            ));
+        }
+        return self
+            .inet_options
+            .getsockopt(&self.metadata, level, optname, optval);
+    }
+
     fn metadata(&self) -> Result<SocketMetadata, SystemError> {
         Ok(self.metadata.clone())
     }
@@ -216,8 +514,8 @@ impl Socket for RawSocket {
         return Box::new(self.clone());
     }
 
-    fn socket_handle(&self) -> SocketHandle {
-        self.handle.0
+    fn socket_handle(&self) -> SocketKey {
+        self.handle.key()
     }
 }
 
@@ -227,8 +525,12 @@ impl Socket for RawSocket {
 #[derive(Debug, Clone)]
 pub struct UdpSocket {
     pub handle: Arc<GlobalSocketHandle>,
+    /// 绑定到未指定地址时，在其他网络接口上接收数据的副本
+    replicas: Vec<Arc<GlobalSocketHandle>>,
     remote_endpoint: Option<Endpoint>, // 记录远程endpoint提供给connect()， 应该使用IP地址。
     metadata: SocketMetadata,
+    /// IP层的选项
+    inet_options: InetOptions,
 }
 
 impl UdpSocket {
@@ -243,21 +545,14 @@ impl UdpSocket {
     ///
     /// @param options socket的选项
     ///
-    /// @return 返回创建的udp的socket
-    pub fn new(options: SocketOptions) -> Self {
-        let rx_buffer = udp::PacketBuffer::new(
-            vec![udp::PacketMetadata::EMPTY; Self::DEFAULT_METADATA_BUF_SIZE],
-            vec![0; Self::DEFAULT_RX_BUF_SIZE],
-        );
-        let tx_buffer = udp::PacketBuffer::new(
-            vec![udp::PacketMetadata::EMPTY; Self::DEFAULT_METADATA_BUF_SIZE],
-            vec![0; Self::DEFAULT_TX_BUF_SIZE],
-        );
-        let socket = udp::Socket::new(rx_buffer, tx_buffer);
+    /// @return 返回创建的udp的socket，没有网络接口时返回ENODEV
+    pub fn new(options: SocketOptions) -> Result<Self, SystemError> {
+        let socket = Self::create_inner_socket();
 
-        // 把socket添加到socket集合中，并得到socket的句柄
+        // 把socket添加到默认网络接口的socket集合中，并得到socket的句柄
+        let iface = default_iface().ok_or(SystemError::ENODEV)?;
         let handle: Arc<GlobalSocketHandle> =
-            GlobalSocketHandle::new(SOCKET_SET.lock_irqsave().add(socket));
+            GlobalSocketHandle::new(iface.clone(), iface.sockets().lock_irqsave().add(socket));
 
         let metadata = SocketMetadata::new(
             SocketType::UdpSocket,
@@ -267,17 +562,37 @@ impl UdpSocket {
             options,
         );
 
-        return Self {
+        return Ok(Self {
             handle,
+            replicas: Vec::new(),
             remote_endpoint: None,
             metadata,
-        };
+            inet_options: InetOptions::new(),
+        });
+    }
+
+    /// 创建一个使用默认缓冲区大小的smoltcp udp socket
+    fn create_inner_socket() -> udp::Socket<'static> {
+        let rx_buffer = udp::PacketBuffer::new(
+            vec![udp::PacketMetadata::EMPTY; Self::DEFAULT_METADATA_BUF_SIZE],
+            vec![0; Self::DEFAULT_RX_BUF_SIZE],
+        );
+        let tx_buffer = udp::PacketBuffer::new(
+            vec![udp::PacketMetadata::EMPTY; Self::DEFAULT_METADATA_BUF_SIZE],
+            vec![0; Self::DEFAULT_TX_BUF_SIZE],
+        );
+        return udp::Socket::new(rx_buffer, tx_buffer);
     }
 
     fn do_bind(&self, socket: &mut udp::Socket, endpoint: Endpoint) -> Result<(), SystemError> {
         if let Endpoint::Ip(Some(ip)) = endpoint {
             // 检测端口是否已被占用
-            PORT_MANAGER.bind_port(self.metadata.socket_type, ip.port, self.handle.clone())?;
+            PORT_MANAGER.bind_port(
+                self.metadata.socket_type,
+                ip.port,
+                self.handle.clone(),
+                self.metadata.options,
+            )?;
 
             let bind_res = if ip.addr.is_unspecified() {
                 socket.bind(ip.port)
@@ -293,6 +608,63 @@ impl UdpSocket {
             return Err(SystemError::EINVAL);
         }
     }
+
+    /// ## 在其他已启用的网络接口上创建绑定到`port`的副本
+    ///
+    /// smoltcp的socket只能收到所在网络接口上的数据包，绑定到未指定地址的socket要在每个接口上都有一个副本。
+    /// 副本与本socket共享HANDLE_MAP中的项，副本收到数据时会唤醒在本socket上等待的进程
+    fn bind_replicas(&mut self, port: u16) -> Result<(), SystemError> {
+        let home = self.handle.iface().nic_id();
+        for iface in up_ifaces() {
+            if iface.nic_id() == home {
+                continue;
+            }
+            let mut socket = Self::create_inner_socket();
+            socket.bind(port).map_err(|_| SystemError::EINVAL)?;
+
+            let replica =
+                GlobalSocketHandle::new(iface.clone(), iface.sockets().lock_irqsave().add(socket));
+            replica.share_handle_item(&self.handle);
+            self.replicas.push(replica);
+        }
+        return Ok(());
+    }
+
+    /// ## 选择发往`remote`的数据包使用的socket
+    ///
+    /// 优先使用位于路由选择的网络接口上的socket。如果没有，并且本socket没有绑定具体的地址，
+    /// 就把本socket移动到这个接口上。多播数据包优先从IP_MULTICAST_IF指定的地址所在的接口发送
+    fn sending_handle(&self, remote: &wire::IpAddress) -> Arc<GlobalSocketHandle> {
+        let multicast_iface = self
+            .inet_options
+            .multicast_if
+            .filter(|_| remote.is_multicast())
+            .and_then(|addr| local_iface(&wire::IpAddress::Ipv4(addr)));
+        let iface = match multicast_iface.or_else(|| route_iface(remote)) {
+            Some(iface) => iface,
+            None => return self.handle.clone(),
+        };
+
+        let found = core::iter::once(&self.handle)
+            .chain(self.replicas.iter())
+            .find(|handle| handle.iface().nic_id() == iface.nic_id());
+        if let Some(handle) = found {
+            return handle.clone();
+        }
+
+        let bound_addr = {
+            let home = self.handle.iface();
+            let sockets = home.sockets().lock_irqsave();
+            sockets
+                .get::<udp::Socket>(self.handle.handle())
+                .endpoint()
+                .addr
+        };
+        if bound_addr.is_none() {
+            self.handle.move_to(iface);
+        }
+        return self.handle.clone();
+    }
 }
 
 impl Socket for UdpSocket {
@@ -306,30 +678,38 @@ impl Socket for UdpSocket {
 
     /// @brief 在read函数执行之前，请先bind到本地的指定端口
     fn read(&mut self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint) {
+        let deadline = self.metadata.recv_deadline();
         loop {
             // kdebug!("Wait22 to Read");
             poll_ifaces();
-            let mut socket_set_guard = SOCKET_SET.lock_irqsave();
-            let socket = socket_set_guard.get_mut::<udp::Socket>(self.handle.0);
 
-            // kdebug!("Wait to Read");
+            // 本socket和它的副本中任何一个收到数据都可以读取
+            for handle in core::iter::once(&self.handle).chain(self.replicas.iter()) {
+                let iface = handle.iface();
+                let mut socket_set_guard = iface.sockets().lock_irqsave();
+                let socket = socket_set_guard.get_mut::<udp::Socket>(handle.handle());
 
-            if socket.can_recv() {
-                if let Ok((size, remote_endpoint)) = socket.recv_slice(buf) {
-                    drop(socket_set_guard);
-                    poll_ifaces();
-                    return (Ok(size), Endpoint::Ip(Some(remote_endpoint)));
+                // kdebug!("Wait to Read");
+
+                if socket.can_recv() {
+                    if let Ok((size, remote_endpoint)) = socket.recv_slice(buf) {
+                        drop(socket_set_guard);
+                        poll_ifaces();
+                        return (Ok(size), Endpoint::Ip(Some(remote_endpoint)));
+                    }
                 }
-            } else {
-                // 如果socket没有连接，则忙等
-                // return (Err(SystemError::ENOTCONN), Endpoint::Ip(None));
             }
-            drop(socket_set_guard);
-            SocketHandleItem::sleep(
+            // 如果socket没有连接，则忙等
+            // return (Err(SystemError::ENOTCONN), Endpoint::Ip(None));
+
+            if let Err(e) = SocketHandleItem::sleep(
                 self.socket_handle(),
                 EPollEventType::EPOLLIN.bits() as u64,
                 HANDLE_MAP.read_irqsave(),
-            );
+                deadline,
+            ) {
+                return (Err(e), Endpoint::Ip(None));
+            }
         }
     }
 
@@ -346,8 +726,16 @@ impl Socket for UdpSocket {
         };
         // kdebug!("udp write: remote = {:?}", remote_endpoint);
 
-        let mut socket_set_guard = SOCKET_SET.lock_irqsave();
-        let socket = socket_set_guard.get_mut::<udp::Socket>(self.handle.0);
+        let hop_limit = self.inet_options.hop_limit(&remote_endpoint.addr);
+        if hop_limit == 0 {
+            // TTL为0的多播数据包不会离开本机，而smoltcp也不会把它回送给本机
+            return Ok(buf.len());
+        }
+
+        let handle = self.sending_handle(&remote_endpoint.addr);
+        let iface = handle.iface();
+        let mut socket_set_guard = iface.sockets().lock_irqsave();
+        let socket = socket_set_guard.get_mut::<udp::Socket>(handle.handle());
         // kdebug!("is open()={}", socket.is_open());
         // kdebug!("socket endpoint={:?}", socket.endpoint());
         if socket.endpoint().port == 0 {
@@ -371,6 +759,7 @@ impl Socket for UdpSocket {
         // kdebug!("is open()={}", socket.is_open());
         if socket.can_send() {
             // kdebug!("udp write: can send");
+            socket.set_hop_limit(Some(hop_limit));
             match socket.send_slice(&buf, *remote_endpoint) {
                 Ok(()) => {
                     // kdebug!("udp write: send ok");
@@ -390,24 +779,44 @@ impl Socket for UdpSocket {
     }
 
     fn bind(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
-        let mut sockets = SOCKET_SET.lock_irqsave();
-        let socket = sockets.get_mut::<udp::Socket>(self.handle.0);
         // kdebug!("UDP Bind to {:?}", endpoint);
-        return self.do_bind(socket, endpoint);
+        if let Endpoint::Ip(Some(ip)) = endpoint {
+            if !ip.addr.is_unspecified() {
+                // 只在拥有这个地址的网络接口上接收数据
+                if let Some(iface) = local_iface(&ip.addr) {
+                    self.handle.move_to(iface);
+                }
+            }
+
+            let iface = self.handle.iface();
+            let mut sockets = iface.sockets().lock_irqsave();
+            let socket = sockets.get_mut::<udp::Socket>(self.handle.handle());
+            self.do_bind(socket, endpoint)?;
+            drop(sockets);
+
+            if ip.addr.is_unspecified() {
+                self.bind_replicas(ip.port)?;
+            }
+            return Ok(());
+        }
+        return Err(SystemError::EINVAL);
     }
 
     fn poll(&self) -> EPollEventType {
-        let sockets = SOCKET_SET.lock_irqsave();
-        let socket = sockets.get::<udp::Socket>(self.handle.0);
-
-        return SocketPollMethod::udp_poll(
-            socket,
-            HANDLE_MAP
-                .read_irqsave()
-                .get(&self.socket_handle())
-                .unwrap()
-                .shutdown_type(),
-        );
+        let shutdown_type = HANDLE_MAP
+            .read_irqsave()
+            .get(&self.socket_handle())
+            .unwrap()
+            .shutdown_type();
+
+        let mut events = EPollEventType::empty();
+        for handle in core::iter::once(&self.handle).chain(self.replicas.iter()) {
+            let iface = handle.iface();
+            let sockets = iface.sockets().lock_irqsave();
+            let socket = sockets.get::<udp::Socket>(handle.handle());
+            events |= SocketPollMethod::udp_poll(socket, shutdown_type);
+        }
+        return events;
     }
 
     fn connect(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
@@ -419,6 +828,28 @@ impl Socket for UdpSocket {
         };
     }
 
+    fn setsockopt(
+        &mut self,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        return self
+            .inet_options
+            .setsockopt(&mut self.metadata, level, optname, optval);
+    }
+
+    fn getsockopt(
+        &self,
+        level: usize,
+        optname: usize,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        return self
+            .inet_options
+            .getsockopt(&self.metadata, level, optname, optval);
+    }
+
     fn ioctl(
         &self,
         _cmd: usize,
@@ -438,8 +869,9 @@ impl Socket for UdpSocket {
     }
 
     fn endpoint(&self) -> Option<Endpoint> {
-        let sockets = SOCKET_SET.lock_irqsave();
-        let socket = sockets.get::<udp::Socket>(self.handle.0);
+        let iface = self.handle.iface();
+        let sockets = iface.sockets().lock_irqsave();
+        let socket = sockets.get::<udp::Socket>(self.handle.handle());
         let listen_endpoint = socket.endpoint();
 
         if listen_endpoint.port == 0 {
@@ -462,8 +894,17 @@ impl Socket for UdpSocket {
         return self.remote_endpoint.clone();
     }
 
-    fn socket_handle(&self) -> SocketHandle {
-        self.handle.0
+    fn socket_handle(&self) -> SocketKey {
+        self.handle.key()
+    }
+
+    /// 释放socket绑定的端口
+    fn close(&mut self) {
+        if let Some(Endpoint::Ip(Some(ip))) = self.endpoint() {
+            PORT_MANAGER
+                .unbind_port(self.metadata.socket_type, ip.port, &self.handle)
+                .ok();
+        }
     }
 }
 
@@ -473,9 +914,13 @@ impl Socket for UdpSocket {
 #[derive(Debug, Clone)]
 pub struct TcpSocket {
     handle: Arc<GlobalSocketHandle>,
+    /// 监听未指定地址时，在其他网络接口上监听的socket
+    listeners: Vec<Arc<GlobalSocketHandle>>,
     local_endpoint: Option<wire::IpEndpoint>, // save local endpoint for bind()
     is_listening: bool,
     metadata: SocketMetadata,
+    /// IP层和TCP层的选项
+    inet_options: InetOptions,
 }
 
 impl TcpSocket {
@@ -494,15 +939,14 @@ impl TcpSocket {
     ///
     /// @param options socket的选项
     ///
-    /// @return 返回创建的tcp的socket
-    pub fn new(options: SocketOptions) -> Self {
-        let rx_buffer = tcp::SocketBuffer::new(vec![0; Self::DEFAULT_RX_BUF_SIZE]);
-        let tx_buffer = tcp::SocketBuffer::new(vec![0; Self::DEFAULT_TX_BUF_SIZE]);
-        let socket = tcp::Socket::new(rx_buffer, tx_buffer);
+    /// @return 返回创建的tcp的socket，没有网络接口时返回ENODEV
+    pub fn new(options: SocketOptions) -> Result<Self, SystemError> {
+        let socket = Self::create_inner_socket();
 
-        // 把socket添加到socket集合中，并得到socket的句柄
+        // 把socket添加到默认网络接口的socket集合中，并得到socket的句柄
+        let iface = default_iface().ok_or(SystemError::ENODEV)?;
         let handle: Arc<GlobalSocketHandle> =
-            GlobalSocketHandle::new(SOCKET_SET.lock_irqsave().add(socket));
+            GlobalSocketHandle::new(iface.clone(), iface.sockets().lock_irqsave().add(socket));
 
         let metadata = SocketMetadata::new(
             SocketType::TcpSocket,
@@ -512,13 +956,23 @@ impl TcpSocket {
             options,
         );
 
-        return Self {
+        return Ok(Self {
             handle,
+            listeners: Vec::new(),
             local_endpoint: None,
             is_listening: false,
             metadata,
-        };
+            inet_options: InetOptions::new(),
+        });
+    }
+
+    /// 创建一个使用默认缓冲区大小的smoltcp tcp socket
+    fn create_inner_socket() -> tcp::Socket<'static> {
+        let rx_buffer = tcp::SocketBuffer::new(vec![0; Self::DEFAULT_RX_BUF_SIZE]);
+        let tx_buffer = tcp::SocketBuffer::new(vec![0; Self::DEFAULT_TX_BUF_SIZE]);
+        return tcp::Socket::new(rx_buffer, tx_buffer);
     }
+
     fn do_listen(
         &mut self,
         socket: &mut tcp::Socket,
@@ -545,6 +999,41 @@ impl TcpSocket {
             Err(_) => Err(SystemError::EINVAL),
         };
     }
+
+    /// ## 在其他已启用的网络接口上监听`local_endpoint`
+    ///
+    /// smoltcp的socket只能接受从所在网络接口进入的连接，监听未指定地址时每个接口上都要有一个监听的socket。
+    /// 它们与本socket共享HANDLE_MAP中的项，有连接到来时会唤醒在本socket上等待accept的进程
+    fn listen_on_other_ifaces(
+        &mut self,
+        local_endpoint: wire::IpEndpoint,
+    ) -> Result<(), SystemError> {
+        let home = self.handle.iface().nic_id();
+        for iface in up_ifaces() {
+            if iface.nic_id() == home {
+                continue;
+            }
+            let mut socket = Self::create_inner_socket();
+            self.inet_options.apply_to_tcp(&self.metadata, &mut socket);
+            self.do_listen(&mut socket, local_endpoint)?;
+
+            let listener =
+                GlobalSocketHandle::new(iface.clone(), iface.sockets().lock_irqsave().add(socket));
+            listener.share_handle_item(&self.handle);
+            self.listeners.push(listener);
+        }
+        return Ok(());
+    }
+
+    /// 把选项应用到本socket在各个网络接口上的smoltcp socket
+    fn apply_options(&self) {
+        for handle in core::iter::once(&self.handle).chain(self.listeners.iter()) {
+            let iface = handle.iface();
+            let mut sockets = iface.sockets().lock_irqsave();
+            let socket = sockets.get_mut::<tcp::Socket>(handle.handle());
+            self.inet_options.apply_to_tcp(&self.metadata, socket);
+        }
+    }
 }
 
 impl Socket for TcpSocket {
@@ -568,10 +1057,12 @@ impl Socket for TcpSocket {
         }
         // kdebug!("tcp socket: read, buf len={}", buf.len());
 
+        let deadline = self.metadata.recv_deadline();
         loop {
             poll_ifaces();
-            let mut socket_set_guard = SOCKET_SET.lock_irqsave();
-            let socket = socket_set_guard.get_mut::<tcp::Socket>(self.handle.0);
+            let iface = self.handle.iface();
+            let mut socket_set_guard = iface.sockets().lock_irqsave();
+            let socket = socket_set_guard.get_mut::<tcp::Socket>(self.handle.handle());
 
             // 如果socket已经关闭，返回错误
             if !socket.is_active() {
@@ -604,8 +1095,8 @@ impl Socket for TcpSocket {
                         tcp::RecvError::Finished => {
                             // 对端写端已关闭，我们应该关闭读端
                             HANDLE_MAP
-                                .write_irqsave()
-                                .get_mut(&self.socket_handle())
+                                .read_irqsave()
+                                .get(&self.socket_handle())
                                 .unwrap()
                                 .shutdown_type_writer()
                                 .insert(ShutdownType::RCV_SHUTDOWN);
@@ -617,11 +1108,14 @@ impl Socket for TcpSocket {
                 return (Err(SystemError::ENOTCONN), Endpoint::Ip(None));
             }
             drop(socket_set_guard);
-            SocketHandleItem::sleep(
+            if let Err(e) = SocketHandleItem::sleep(
                 self.socket_handle(),
                 EPollEventType::EPOLLIN.bits() as u64,
                 HANDLE_MAP.read_irqsave(),
-            );
+                deadline,
+            ) {
+                return (Err(e), Endpoint::Ip(None));
+            }
         }
     }
 
@@ -635,8 +1129,9 @@ impl Socket for TcpSocket {
         {
             return Err(SystemError::ENOTCONN);
         }
-        let mut socket_set_guard = SOCKET_SET.lock_irqsave();
-        let socket = socket_set_guard.get_mut::<tcp::Socket>(self.handle.0);
+        let iface = self.handle.iface();
+        let mut socket_set_guard = iface.sockets().lock_irqsave();
+        let socket = socket_set_guard.get_mut::<tcp::Socket>(self.handle.handle());
 
         if socket.is_open() {
             if socket.can_send() {
@@ -660,30 +1155,49 @@ impl Socket for TcpSocket {
     }
 
     fn poll(&self) -> EPollEventType {
-        let mut socket_set_guard = SOCKET_SET.lock_irqsave();
-        let socket = socket_set_guard.get_mut::<tcp::Socket>(self.handle.0);
-
-        return SocketPollMethod::tcp_poll(
-            socket,
-            HANDLE_MAP
-                .read_irqsave()
-                .get(&self.socket_handle())
-                .unwrap()
-                .shutdown_type(),
-        );
+        let shutdown_type = HANDLE_MAP
+            .read_irqsave()
+            .get(&self.socket_handle())
+            .unwrap()
+            .shutdown_type();
+
+        // 正在监听时，任何一个网络接口上的socket有连接到来都可以accept
+        let mut events = EPollEventType::empty();
+        for handle in core::iter::once(&self.handle).chain(self.listeners.iter()) {
+            let iface = handle.iface();
+            let mut socket_set_guard = iface.sockets().lock_irqsave();
+            let socket = socket_set_guard.get_mut::<tcp::Socket>(handle.handle());
+            events |= SocketPollMethod::tcp_poll(socket, shutdown_type);
+        }
+        return events;
     }
 
     fn connect(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
-        let mut sockets = SOCKET_SET.lock_irqsave();
-        let socket = sockets.get_mut::<tcp::Socket>(self.handle.0);
-
         if let Endpoint::Ip(Some(ip)) = endpoint {
-            let temp_port = PORT_MANAGER.get_ephemeral_port(self.metadata.socket_type)?;
-            // 检测端口是否被占用
-            PORT_MANAGER.bind_port(self.metadata.socket_type, temp_port, self.handle.clone())?;
+            // 已经bind过的socket使用绑定的端口，否则分配一个临时端口
+            let temp_port = match self.local_endpoint {
+                Some(local) => local.port,
+                None => {
+                    let port = PORT_MANAGER.get_ephemeral_port(self.metadata.socket_type)?;
+                    // 检测端口是否被占用
+                    PORT_MANAGER.bind_port(
+                        self.metadata.socket_type,
+                        port,
+                        self.handle.clone(),
+                        self.metadata.options,
+                    )?;
+                    port
+                }
+            };
 
             // kdebug!("temp_port: {}", temp_port);
-            let iface: Arc<dyn NetDriver> = NET_DRIVERS.write_irqsave().get(&0).unwrap().clone();
+            let iface: Arc<dyn NetDriver> =
+                route_iface(&ip.addr).ok_or(SystemError::ENETUNREACH)?;
+            // 连接上的数据包都经过这个网络接口，所以把socket移动到它的socket集合中
+            self.handle.move_to(iface.clone());
+
+            let mut sockets = iface.sockets().lock_irqsave();
+            let socket = sockets.get_mut::<tcp::Socket>(self.handle.handle());
             let mut inner_iface = iface.inner_iface().lock();
             // kdebug!("to connect: {ip:?}");
 
@@ -691,12 +1205,15 @@ impl Socket for TcpSocket {
                 Ok(()) => {
                     // avoid deadlock
                     drop(inner_iface);
-                    drop(iface);
                     drop(sockets);
+                    drop(iface);
+                    // 设置了SO_SNDTIMEO时，超时后连接在后台继续进行，与Linux一样返回EINPROGRESS
+                    let deadline = self.metadata.send_deadline();
                     loop {
                         poll_ifaces();
-                        let mut sockets = SOCKET_SET.lock_irqsave();
-                        let socket = sockets.get_mut::<tcp::Socket>(self.handle.0);
+                        let iface = self.handle.iface();
+                        let mut sockets = iface.sockets().lock_irqsave();
+                        let socket = sockets.get_mut::<tcp::Socket>(self.handle.handle());
 
                         match socket.state() {
                             tcp::State::Established => {
@@ -708,9 +1225,24 @@ impl Socket for TcpSocket {
                                     self.socket_handle(),
                                     Self::CAN_CONNECT,
                                     HANDLE_MAP.read_irqsave(),
-                                );
+                                    deadline,
+                                )
+                                .map_err(|e| match e {
+                                    // 只有超时才表示连接仍在后台进行，连接的结果通过SO_ERROR报告。
+                                    // 其他错误（例如被信号打断）原样返回
+                                    SystemError::EAGAIN_OR_EWOULDBLOCK => {
+                                        HANDLE_MAP
+                                            .read_irqsave()
+                                            .get(&self.socket_handle())
+                                            .unwrap()
+                                            .set_connecting(true);
+                                        SystemError::EINPROGRESS
+                                    }
+                                    e => e,
+                                })?;
                             }
                             _ => {
+                                // 错误已经通过返回值报告给调用者，不再记录到SO_ERROR中
                                 return Err(SystemError::ECONNREFUSED);
                             }
                         }
@@ -738,15 +1270,22 @@ impl Socket for TcpSocket {
         }
 
         let local_endpoint = self.local_endpoint.ok_or(SystemError::EINVAL)?;
-        let mut sockets = SOCKET_SET.lock_irqsave();
-        let socket = sockets.get_mut::<tcp::Socket>(self.handle.0);
+        let iface = self.handle.iface();
+        let mut sockets = iface.sockets().lock_irqsave();
+        let socket = sockets.get_mut::<tcp::Socket>(self.handle.handle());
 
         if socket.is_listening() {
             // kdebug!("Tcp Socket is already listening on {local_endpoint}");
             return Ok(());
         }
         // kdebug!("Tcp Socket  before listen, open={}", socket.is_open());
-        return self.do_listen(socket, local_endpoint);
+        self.do_listen(socket, local_endpoint)?;
+        drop(sockets);
+
+        if local_endpoint.addr.is_unspecified() {
+            self.listen_on_other_ifaces(local_endpoint)?;
+        }
+        return Ok(());
     }
 
     fn bind(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
@@ -756,7 +1295,19 @@ impl Socket for TcpSocket {
             }
 
             // 检测端口是否已被占用
-            PORT_MANAGER.bind_port(self.metadata.socket_type, ip.port, self.handle.clone())?;
+            PORT_MANAGER.bind_port(
+                self.metadata.socket_type,
+                ip.port,
+                self.handle.clone(),
+                self.metadata.options,
+            )?;
+
+            if !ip.addr.is_unspecified() {
+                // 只在拥有这个地址的网络接口上收发数据
+                if let Some(iface) = local_iface(&ip.addr) {
+                    self.handle.move_to(iface);
+                }
+            }
 
             self.local_endpoint = Some(ip);
             self.is_listening = false;
@@ -767,94 +1318,107 @@ impl Socket for TcpSocket {
 
     fn shutdown(&mut self, shutdown_type: super::ShutdownType) -> Result<(), SystemError> {
         // TODO：目前只是在表层判断，对端不知晓，后续需使用tcp实现
-        HANDLE_MAP
-            .write_irqsave()
-            .get_mut(&self.socket_handle())
+        *HANDLE_MAP
+            .read_irqsave()
+            .get(&self.socket_handle())
             .unwrap()
-            .shutdown_type = RwLock::new(shutdown_type);
+            .shutdown_type_writer() = shutdown_type;
         return Ok(());
     }
 
     fn accept(&mut self) -> Result<(Box<dyn Socket>, Endpoint), SystemError> {
         let endpoint = self.local_endpoint.ok_or(SystemError::EINVAL)?;
+        let deadline = self.metadata.recv_deadline();
         loop {
             // kdebug!("tcp accept: poll_ifaces()");
             poll_ifaces();
 
-            let mut sockets = SOCKET_SET.lock_irqsave();
+            // 在各个网络接口上监听的socket中，找到已经有连接到来的那一个
+            // 序号0表示self.handle，其余的表示self.listeners中的socket
+            let listeners: Vec<Arc<GlobalSocketHandle>> = core::iter::once(&self.handle)
+                .chain(self.listeners.iter())
+                .cloned()
+                .collect();
+            for (index, listener) in listeners.into_iter().enumerate() {
+                let iface = listener.iface();
+                let mut sockets = iface.sockets().lock_irqsave();
 
-            let socket = sockets.get_mut::<tcp::Socket>(self.handle.0);
+                let socket = sockets.get_mut::<tcp::Socket>(listener.handle());
+
+                if !socket.is_active() {
+                    continue;
+                }
 
-            if socket.is_active() {
                 // kdebug!("tcp accept: socket.is_active()");
                 let remote_ep = socket.remote_endpoint().ok_or(SystemError::ENOTCONN)?;
 
-                let new_socket = {
-                    // Initialize the TCP socket's buffers.
-                    let rx_buffer = tcp::SocketBuffer::new(vec![0; Self::DEFAULT_RX_BUF_SIZE]);
-                    let tx_buffer = tcp::SocketBuffer::new(vec![0; Self::DEFAULT_TX_BUF_SIZE]);
+                let (new_socket, old_handle, new_handle) = {
                     // The new TCP socket used for sending and receiving data.
-                    let mut tcp_socket = tcp::Socket::new(rx_buffer, tx_buffer);
+                    let mut tcp_socket = Self::create_inner_socket();
+                    self.inet_options
+                        .apply_to_tcp(&self.metadata, &mut tcp_socket);
                     self.do_listen(&mut tcp_socket, endpoint)
                         .expect("do_listen failed");
 
                     // tcp_socket.listen(endpoint).unwrap();
 
                     // 之所以把old_handle存入new_socket, 是因为当前时刻，smoltcp已经把old_handle对应的socket与远程的endpoint关联起来了
-                    // 因此需要再为当前的socket分配一个新的handle
-                    let new_handle = GlobalSocketHandle::new(sockets.add(tcp_socket));
-                    let old_handle = ::core::mem::replace(&mut self.handle, new_handle.clone());
-
-                    // 更新端口与 handle 的绑定
-                    if let Some(Endpoint::Ip(Some(ip))) = self.endpoint() {
-                        PORT_MANAGER.unbind_port(self.metadata.socket_type, ip.port)?;
-                        PORT_MANAGER.bind_port(
-                            self.metadata.socket_type,
-                            ip.port,
-                            new_handle.clone(),
-                        )?;
-                    }
-
-                    let metadata = SocketMetadata::new(
-                        SocketType::TcpSocket,
-                        Self::DEFAULT_TX_BUF_SIZE,
-                        Self::DEFAULT_RX_BUF_SIZE,
-                        Self::DEFAULT_METADATA_BUF_SIZE,
-                        self.metadata.options,
-                    );
-
+                    // 因此需要在同一个网络接口上为监听分配一个新的handle
+                    let new_handle =
+                        GlobalSocketHandle::new(iface.clone(), sockets.add(tcp_socket));
+                    let old_handle = if index == 0 {
+                        ::core::mem::replace(&mut self.handle, new_handle.clone())
+                    } else {
+                        ::core::mem::replace(&mut self.listeners[index - 1], new_handle.clone())
+                    };
+
+                    // 新的连接继承监听socket的选项
                     let new_socket = Box::new(TcpSocket {
                         handle: old_handle.clone(),
+                        listeners: Vec::new(),
                         local_endpoint: self.local_endpoint,
                         is_listening: false,
-                        metadata,
+                        metadata: self.metadata.clone(),
+                        inet_options: self.inet_options,
                     });
 
                     // 更新handle表
+                    let (old_key, new_key) = (old_handle.key(), new_handle.key());
                     let mut handle_guard = HANDLE_MAP.write_irqsave();
                     // 先删除原来的
-                    let item = handle_guard.remove(&old_handle.0).unwrap();
+                    let item = handle_guard.remove(&old_key).unwrap();
                     // 按照smoltcp行为，将新的handle绑定到原来的item
-                    handle_guard.insert(new_handle.0, item);
+                    handle_guard.insert(new_key, item);
                     let new_item = SocketHandleItem::from_socket(&new_socket);
                     // 插入新的item
-                    handle_guard.insert(old_handle.0, new_item);
+                    handle_guard.insert(old_key, Arc::new(new_item));
 
-                    new_socket
+                    (new_socket, old_handle, new_handle)
                 };
                 // kdebug!("tcp accept: new socket: {:?}", new_socket);
                 drop(sockets);
+
+                // 更新端口与 handle 的绑定，端口管理器中只记录了self.handle
+                // 端口管理器在检查端口冲突时会锁住socket集合，所以要在释放socket集合的锁之后更新
+                if index == 0 {
+                    PORT_MANAGER.replace_handle(
+                        self.metadata.socket_type,
+                        endpoint.port,
+                        &old_handle,
+                        new_handle,
+                    );
+                }
                 poll_ifaces();
 
                 return Ok((new_socket, Endpoint::Ip(Some(remote_ep))));
             }
-            drop(sockets);
 
             SocketHandleItem::sleep(
                 self.socket_handle(),
                 Self::CAN_ACCPET,
                 HANDLE_MAP.read_irqsave(),
-            );
+                deadline,
+            )?;
         }
     }
 
@@ -863,8 +1427,9 @@ impl Socket for TcpSocket {
             self.local_endpoint.clone().map(|x| Endpoint::Ip(Some(x)));
 
         if result.is_none() {
-            let sockets = SOCKET_SET.lock_irqsave();
-            let socket = sockets.get::<tcp::Socket>(self.handle.0);
+            let iface = self.handle.iface();
+            let sockets = iface.sockets().lock_irqsave();
+            let socket = sockets.get::<tcp::Socket>(self.handle.handle());
             if let Some(ep) = socket.local_endpoint() {
                 result = Some(Endpoint::Ip(Some(ep)));
             }
@@ -873,8 +1438,9 @@ impl Socket for TcpSocket {
     }
 
     fn peer_endpoint(&self) -> Option<Endpoint> {
-        let sockets = SOCKET_SET.lock_irqsave();
-        let socket = sockets.get::<tcp::Socket>(self.handle.0);
+        let iface = self.handle.iface();
+        let sockets = iface.sockets().lock_irqsave();
+        let socket = sockets.get::<tcp::Socket>(self.handle.handle());
         return socket.remote_endpoint().map(|x| Endpoint::Ip(Some(x)));
     }
 
@@ -886,8 +1452,90 @@ impl Socket for TcpSocket {
         return Box::new(self.clone());
     }
 
-    fn socket_handle(&self) -> SocketHandle {
-        self.handle.0
+    fn socket_handle(&self) -> SocketKey {
+        self.handle.key()
+    }
+
+    fn setsockopt(
+        &mut self,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        self.inet_options
+            .setsockopt(&mut self.metadata, level, optname, optval)?;
+        self.apply_options();
+        return Ok(());
+    }
+
+    fn getsockopt(
+        &self,
+        level: usize,
+        optname: usize,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        if level == SOL_SOCKET as usize {
+            let value = match PosixSocketOption::try_from(optname as i32) {
+                Ok(PosixSocketOption::SO_ERROR) => HANDLE_MAP
+                    .read_irqsave()
+                    .get(&self.socket_handle())
+                    .unwrap()
+                    .take_error()
+                    .map(|e| -e.to_posix_errno())
+                    .unwrap_or(0),
+                Ok(PosixSocketOption::SO_ACCEPTCONN) => self.is_listening as i32,
+                _ => {
+                    return self
+                        .inet_options
+                        .getsockopt(&self.metadata, level, optname, optval)
+                }
+            };
+            return Ok(write_optval(optval, &value.to_ne_bytes()));
+        }
+        return self
+            .inet_options
+            .getsockopt(&self.metadata, level, optname, optval);
+    }
+
+    /// 释放socket绑定的端口，并关闭连接
+    ///
+    /// 设置了SO_LINGER并且超时时间为0时，向对端发送RST中止连接，否则发送FIN。
+    /// 这里持有socket的锁，不能睡眠。SO_LINGER的超时时间不为0时，由调用者通过`linger_done`等待发送完毕
+    fn close(&mut self) {
+        if let Some(Endpoint::Ip(Some(ip))) = self.endpoint() {
+            PORT_MANAGER
+                .unbind_port(self.metadata.socket_type, ip.port, &self.handle)
+                .ok();
+        }
+
+        let abort = self.metadata.linger == Some(crate::time::Duration::ZERO);
+        for handle in core::iter::once(&self.handle).chain(self.listeners.iter()) {
+            let iface = handle.iface();
+            let mut sockets = iface.sockets().lock_irqsave();
+            let socket = sockets.get_mut::<tcp::Socket>(handle.handle());
+            if abort {
+                socket.abort();
+            } else {
+                socket.close();
+            }
+        }
+        poll_ifaces();
+    }
+
+    /// 发送缓冲区中的数据以及FIN都已经被对端确认
+    fn linger_done(&self) -> bool {
+        let iface = self.handle.iface();
+        let mut sockets = iface.sockets().lock_irqsave();
+        let socket = sockets.get_mut::<tcp::Socket>(self.handle.handle());
+        return !matches!(
+            socket.state(),
+            tcp::State::SynReceived
+                | tcp::State::Established
+                | tcp::State::CloseWait
+                | tcp::State::FinWait1
+                | tcp::State::Closing
+                | tcp::State::LastAck
+        );
     }
 }
 
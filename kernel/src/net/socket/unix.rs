@@ -0,0 +1,1172 @@
+use core::{
+    cmp::min,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, LinkedList, VecDeque},
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    arch::sched::sched,
+    filesystem::vfs::{
+        fcntl::AtFlags,
+        file::File,
+        syscall::ModeType,
+        utils::{rsplit_path, user_path_at},
+        FileType, IndexNode, InodeId, VFS_MAX_FOLLOW_SYMLINK_TIMES,
+    },
+    libs::{
+        spinlock::{SpinLock, SpinLockGuard},
+        wait_queue::EventWaitQueue,
+    },
+    net::{
+        event_poll::{EPollEventType, EPollItem, EventPoll},
+        syscall::PosixSocketOption,
+        Endpoint, ShutdownType,
+    },
+    process::ProcessManager,
+    time::{
+        timer::{next_n_us_timer_jiffies, Timer, WakeUpHelper},
+        Instant,
+    },
+};
+
+use super::{Socket, SocketMetadata, SocketOptions, SocketType, SocketpairOps, SOL_SOCKET};
+
+lazy_static! {
+    /// 已经绑定了地址的Unix域socket，键为地址，值为socket的接收队列
+    static ref UNIX_BIND_TABLE: SpinLock<BTreeMap<UnixBindKey, Arc<UnixQueue>>> =
+        SpinLock::new(BTreeMap::new());
+}
+
+/// Unix域socket的地址
+///
+/// 参考：https://man7.org/linux/man-pages/man7/unix.7.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnixEndpoint {
+    /// 没有绑定地址
+    Unnamed,
+    /// 文件系统中的路径，绑定时会在该路径上创建一个socket文件
+    Path(String),
+    /// 抽象命名空间中的名字（不包含开头的'\0'），与文件系统无关
+    Abstract(Vec<u8>),
+}
+
+/// 绑定表的键
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum UnixBindKey {
+    /// 路径名对应的socket文件，由(设备号, inode号)确定
+    Inode(usize, InodeId),
+    /// 抽象命名空间中的名字
+    Abstract(Vec<u8>),
+}
+
+impl UnixBindKey {
+    fn from_inode(inode: &Arc<dyn IndexNode>) -> Result<Self, SystemError> {
+        let metadata = inode.metadata()?;
+        return Ok(Self::Inode(metadata.dev_id, metadata.inode_id));
+    }
+}
+
+/// SCM_CREDENTIALS 传递的进程凭证
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct UCred {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl UCred {
+    /// 获取当前进程的凭证
+    pub fn current() -> Self {
+        let pcb = ProcessManager::current_pcb();
+        let cred = pcb.cred();
+        Self {
+            pid: pcb.pid().data() as i32,
+            uid: cred.uid.data() as u32,
+            gid: cred.gid.data() as u32,
+        }
+    }
+}
+
+/// 通过sendmsg/recvmsg在Unix域socket之间传递的辅助数据
+#[derive(Debug, Clone)]
+pub enum ScmData {
+    /// SCM_RIGHTS：传递打开的文件，接收者会为它们分配新的文件描述符
+    Rights(Vec<Arc<SpinLock<File>>>),
+    /// SCM_CREDENTIALS：发送者的凭证
+    Credentials(UCred),
+}
+
+/// 接收队列中的一条消息
+#[derive(Debug)]
+struct UnixMessage {
+    data: Vec<u8>,
+    /// 流式socket中，这条消息已经被读取的字节数
+    consumed: usize,
+    /// 发送者的地址
+    from: UnixEndpoint,
+    /// 随这条消息发送的辅助数据，在读取这条消息的第一个字节时交给接收者
+    scm: Vec<ScmData>,
+}
+
+/// # Unix域socket的接收队列
+///
+/// 发送者直接把数据放入接收者的队列中，因此它被通信的双方以及绑定表共享。
+/// 监听中的流式socket使用它存放等待accept的连接
+#[derive(Debug)]
+pub struct UnixQueue {
+    socket_type: SocketType,
+    inner: SpinLock<InnerUnixQueue>,
+    /// 接收者在上面等待EPOLLIN，发送者在上面等待EPOLLOUT
+    wait_queue: EventWaitQueue,
+    /// 队列所属socket的epitems
+    epitems: SpinLock<LinkedList<Arc<EPollItem>>>,
+    /// 队列所属的socket是否设置了SO_PASSCRED
+    pass_cred: AtomicBool,
+}
+
+#[derive(Debug)]
+struct InnerUnixQueue {
+    messages: VecDeque<UnixMessage>,
+    /// 队列中尚未被读取的字节数
+    len: usize,
+    /// 等待accept的连接
+    backlog: VecDeque<UnixStreamSocket>,
+    /// 允许的最大等待连接数，为0表示没有在监听
+    max_backlog: usize,
+    /// 接收者不再读取数据（接收者关闭了读端，或者已经被关闭）
+    reader_closed: bool,
+    /// 不会再有数据写入（流式socket的对端关闭了写端，或者已经被关闭）
+    writer_closed: bool,
+    /// 流式socket对端的接收队列，用于在读取数据后通知对端可写
+    peer: Weak<UnixQueue>,
+}
+
+impl UnixQueue {
+    /// 接收队列的默认大小
+    pub const DEFAULT_BUF_SIZE: usize = 64 * 1024;
+    /// 一次sendmsg最多能传递的文件数
+    pub const SCM_MAX_FD: usize = 253;
+
+    fn new(socket_type: SocketType) -> Arc<Self> {
+        return Arc::new(Self {
+            socket_type,
+            inner: SpinLock::new(InnerUnixQueue {
+                messages: VecDeque::new(),
+                len: 0,
+                backlog: VecDeque::new(),
+                max_backlog: 0,
+                reader_closed: false,
+                writer_closed: false,
+                peer: Weak::new(),
+            }),
+            wait_queue: EventWaitQueue::new(),
+            epitems: SpinLock::new(LinkedList::new()),
+            pass_cred: AtomicBool::new(false),
+        });
+    }
+
+    /// 向队列中放入一条消息，队列已满时会阻塞
+    ///
+    /// ## 参数
+    ///
+    /// - `buf`: 要发送的数据
+    /// - `from`: 发送者的地址
+    /// - `scm`: 随消息发送的辅助数据
+    /// - `stream`: 是否是流式socket。流式socket在队列空间不足时只放入一部分数据，
+    ///   数据报socket则要么全部放入，要么等待
+    /// - `deadline`: 最多等待到这个时刻(SO_SNDTIMEO)，为None时一直等待
+    ///
+    /// ## 返回值
+    ///
+    /// 放入队列的字节数
+    fn send(
+        &self,
+        buf: &[u8],
+        from: UnixEndpoint,
+        scm: Vec<ScmData>,
+        stream: bool,
+        deadline: Option<Instant>,
+    ) -> Result<usize, SystemError> {
+        if !stream && buf.len() > Self::DEFAULT_BUF_SIZE {
+            return Err(SystemError::EMSGSIZE);
+        }
+
+        let mut guard = self.inner.lock_irqsave();
+        loop {
+            if guard.reader_closed {
+                return Err(if stream {
+                    SystemError::EPIPE
+                } else {
+                    SystemError::ECONNREFUSED
+                });
+            }
+
+            let free = Self::DEFAULT_BUF_SIZE - guard.len;
+            if (stream && free > 0) || (!stream && free >= buf.len()) {
+                break;
+            }
+
+            self.wait(EPollEventType::EPOLLOUT, guard, deadline)?;
+            guard = self.inner.lock_irqsave();
+        }
+
+        let len = min(buf.len(), Self::DEFAULT_BUF_SIZE - guard.len);
+        guard.messages.push_back(UnixMessage {
+            data: buf[..len].to_vec(),
+            consumed: 0,
+            from,
+            scm,
+        });
+        guard.len += len;
+        drop(guard);
+
+        self.notify(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM);
+        return Ok(len);
+    }
+
+    /// 从队列中读取数据，队列为空时会阻塞
+    ///
+    /// 流式socket会跨越消息边界读取数据，但不会把带有辅助数据的消息与之前的消息合并；
+    /// 数据报socket每次读取一条消息，超出缓冲区的部分会被丢弃
+    ///
+    /// ## 参数
+    ///
+    /// - `deadline`: 最多等待到这个时刻(SO_RCVTIMEO)，为None时一直等待
+    ///
+    /// ## 返回值
+    ///
+    /// (读取的字节数, 发送者的地址, 辅助数据)。如果不会再有数据到来，返回读取的字节数为0
+    fn recv(
+        &self,
+        buf: &mut [u8],
+        stream: bool,
+        deadline: Option<Instant>,
+    ) -> Result<(usize, UnixEndpoint, Vec<ScmData>), SystemError> {
+        let mut guard = self.inner.lock_irqsave();
+        while guard.messages.is_empty() {
+            if guard.reader_closed || guard.writer_closed {
+                return Ok((0, UnixEndpoint::Unnamed, Vec::new()));
+            }
+
+            self.wait(EPollEventType::EPOLLIN, guard, deadline)?;
+            guard = self.inner.lock_irqsave();
+        }
+
+        let from = guard.messages.front().unwrap().from.clone();
+        let mut scm = Vec::new();
+        let mut read = 0;
+        if stream {
+            while read < buf.len() {
+                let msg = match guard.messages.front_mut() {
+                    Some(msg) => msg,
+                    None => break,
+                };
+                if msg.consumed == 0 {
+                    // 辅助数据只能随着消息的第一个字节交给接收者
+                    if read > 0 && !msg.scm.is_empty() {
+                        break;
+                    }
+                    scm = core::mem::take(&mut msg.scm);
+                }
+
+                let len = min(buf.len() - read, msg.data.len() - msg.consumed);
+                buf[read..read + len].copy_from_slice(&msg.data[msg.consumed..msg.consumed + len]);
+                msg.consumed += len;
+                read += len;
+                if msg.consumed == msg.data.len() {
+                    guard.messages.pop_front();
+                }
+            }
+            guard.len -= read;
+        } else {
+            let msg = guard.messages.pop_front().unwrap();
+            read = min(buf.len(), msg.data.len());
+            buf[..read].copy_from_slice(&msg.data[..read]);
+            guard.len -= msg.data.len();
+            scm = msg.scm;
+        }
+        let peer = guard.peer.upgrade();
+        drop(guard);
+
+        self.wait_queue
+            .wakeup_any(EPollEventType::EPOLLOUT.bits() as u64);
+        if let Some(peer) = peer {
+            let _ = EventPoll::wakeup_epoll(
+                &peer.epitems,
+                EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM,
+            );
+        }
+        return Ok((read, from, scm));
+    }
+
+    /// 开始监听，设置最大等待连接数
+    fn listen(&self, backlog: usize) {
+        self.inner.lock_irqsave().max_backlog = core::cmp::max(backlog, 1);
+    }
+
+    /// 把一个新的连接放入监听socket的等待队列
+    fn push_connection(&self, socket: UnixStreamSocket) -> Result<(), SystemError> {
+        let mut guard = self.inner.lock_irqsave();
+        if guard.max_backlog == 0 || guard.reader_closed {
+            return Err(SystemError::ECONNREFUSED);
+        }
+        if guard.backlog.len() >= guard.max_backlog {
+            return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+        }
+        guard.backlog.push_back(socket);
+        drop(guard);
+
+        self.notify(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM);
+        return Ok(());
+    }
+
+    /// 从监听socket的等待队列中取出一个连接，没有连接时会阻塞，最多等待到`deadline`
+    fn pop_connection(&self, deadline: Option<Instant>) -> Result<UnixStreamSocket, SystemError> {
+        let mut guard = self.inner.lock_irqsave();
+        loop {
+            if let Some(socket) = guard.backlog.pop_front() {
+                return Ok(socket);
+            }
+            if guard.reader_closed {
+                return Err(SystemError::EINVAL);
+            }
+
+            self.wait(EPollEventType::EPOLLIN, guard, deadline)?;
+            guard = self.inner.lock_irqsave();
+        }
+    }
+
+    /// ## 在队列上等待事件，进入等待状态后释放队列的锁
+    ///
+    /// ## 参数
+    ///
+    /// - `deadline`: 最多等待到这个时刻，为None时一直等待到被唤醒
+    ///
+    /// ## 返回值
+    ///
+    /// - `Err(SystemError::EAGAIN_OR_EWOULDBLOCK)`: 已经超过了`deadline`
+    /// - `Err(SystemError::ERESTARTSYS)`: 等待被信号打断
+    fn wait(
+        &self,
+        events: EPollEventType,
+        guard: SpinLockGuard<InnerUnixQueue>,
+        deadline: Option<Instant>,
+    ) -> Result<(), SystemError> {
+        let mut timer = None;
+        if let Some(deadline) = deadline {
+            let now = Instant::now();
+            if deadline <= now {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            timer = Some(Timer::new(
+                WakeUpHelper::new(ProcessManager::current_pcb()),
+                next_n_us_timer_jiffies((deadline - now).total_micros()),
+            ));
+        }
+
+        unsafe { self.wait_queue.sleep_without_schedule(events.bits() as u64) };
+        drop(guard);
+        // 在进入睡眠状态之后再启动定时器，避免定时器在睡眠之前到期导致错过唤醒
+        if let Some(timer) = &timer {
+            timer.activate();
+        }
+        sched();
+
+        if let Some(timer) = timer {
+            if timer.timeout() {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            timer.cancel();
+        }
+        if signal_pending() {
+            return Err(SystemError::ERESTARTSYS);
+        }
+        return Ok(());
+    }
+
+    /// 接收者不再读取数据，之后向队列发送数据会失败
+    fn close_reader(&self) {
+        self.inner.lock_irqsave().reader_closed = true;
+        self.wait_queue.wakeup_all();
+        let _ = EventPoll::wakeup_epoll(
+            &self.epitems,
+            EPollEventType::EPOLLIN | EPollEventType::EPOLLRDHUP,
+        );
+    }
+
+    /// 不会再有数据写入，读取完队列中的数据后，接收者会读到文件末尾
+    fn close_writer(&self) {
+        self.inner.lock_irqsave().writer_closed = true;
+        self.wait_queue.wakeup_all();
+        let _ = EventPoll::wakeup_epoll(
+            &self.epitems,
+            EPollEventType::EPOLLIN | EPollEventType::EPOLLRDHUP,
+        );
+    }
+
+    /// 队列所属的socket被关闭：丢弃队列中的数据，并关闭所有尚未被accept的连接
+    fn close(&self) {
+        let mut guard = self.inner.lock_irqsave();
+        guard.reader_closed = true;
+        guard.writer_closed = true;
+        guard.len = 0;
+        let messages = core::mem::take(&mut guard.messages);
+        let backlog = core::mem::take(&mut guard.backlog);
+        drop(guard);
+        self.wait_queue.wakeup_all();
+
+        // 消息中可能带有文件，关闭这些文件时不能持有队列的锁
+        drop(messages);
+        for mut socket in backlog {
+            socket.close();
+        }
+    }
+
+    /// 设置流式socket对端的接收队列
+    fn set_peer(&self, peer: &Arc<UnixQueue>) {
+        self.inner.lock_irqsave().peer = Arc::downgrade(peer);
+    }
+
+    /// 唤醒等待事件的进程和epoll
+    fn notify(&self, events: EPollEventType) {
+        self.wait_queue.wakeup_any(events.bits() as u64);
+        let _ = EventPoll::wakeup_epoll(&self.epitems, events);
+    }
+
+    /// 队列所属的socket是否有数据可读
+    fn poll_in(&self) -> EPollEventType {
+        let guard = self.inner.lock_irqsave();
+        let mut events = EPollEventType::empty();
+        if !guard.messages.is_empty() || !guard.backlog.is_empty() {
+            events.insert(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM);
+        }
+        if guard.reader_closed || guard.writer_closed {
+            events.insert(
+                EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM | EPollEventType::EPOLLRDHUP,
+            );
+        }
+        return events;
+    }
+
+    /// 发送者是否可以向队列写入数据
+    fn poll_out(&self) -> EPollEventType {
+        let guard = self.inner.lock_irqsave();
+        if guard.reader_closed {
+            return EPollEventType::EPOLLOUT | EPollEventType::EPOLLERR;
+        }
+        if guard.len < Self::DEFAULT_BUF_SIZE {
+            return EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM;
+        }
+        return EPollEventType::empty();
+    }
+
+    fn add_epoll(&self, epitem: Arc<EPollItem>) {
+        self.epitems.lock_irqsave().push_back(epitem);
+    }
+
+    fn remove_epoll(&self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
+        let is_remove = !self
+            .epitems
+            .lock_irqsave()
+            .extract_if(|x| x.epoll().ptr_eq(epoll))
+            .collect::<Vec<_>>()
+            .is_empty();
+
+        if is_remove {
+            return Ok(());
+        }
+
+        Err(SystemError::ENOENT)
+    }
+
+    fn clear_epoll(&self) -> Result<(), SystemError> {
+        for epitem in self.epitems.lock_irqsave().iter() {
+            let epoll = epitem.epoll();
+            if let Some(epoll) = epoll.upgrade() {
+                EventPoll::ep_remove(&mut epoll.lock_irqsave(), epitem.fd(), None)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 处理SOL_SOCKET层次上Unix域socket特有的选项
+    /// ## 设置Unix域socket的选项
+    ///
+    /// SO_PASSCRED记录在接收队列上，其他SOL_SOCKET层次的选项交给socket的元数据处理
+    fn setsockopt(
+        &self,
+        metadata: &mut SocketMetadata,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        if level != SOL_SOCKET as usize {
+            return Err(SystemError::ENOPROTOOPT);
+        }
+
+        let optname =
+            PosixSocketOption::try_from(optname as i32).map_err(|_| SystemError::ENOPROTOOPT)?;
+        match optname {
+            PosixSocketOption::SO_PASSCRED => {
+                if optval.len() < core::mem::size_of::<i32>() {
+                    return Err(SystemError::EINVAL);
+                }
+                let value = i32::from_ne_bytes(optval[..4].try_into().unwrap());
+                self.pass_cred.store(value != 0, Ordering::SeqCst);
+                return Ok(());
+            }
+            _ => return metadata.setsockopt(optname, optval),
+        }
+    }
+
+    /// 如果接收者设置了SO_PASSCRED，而发送者没有附带凭证，就附带上发送者的凭证
+    fn attach_credentials(&self, scm: &mut Vec<ScmData>) {
+        if !self.pass_cred.load(Ordering::SeqCst) {
+            return;
+        }
+        if !scm.iter().any(|x| matches!(x, ScmData::Credentials(_))) {
+            scm.push(ScmData::Credentials(UCred::current()));
+        }
+    }
+}
+
+/// 当前进程是否有待处理的信号
+fn signal_pending() -> bool {
+    ProcessManager::current_pcb()
+        .sig_info_irqsave()
+        .sig_pending()
+        .has_pending()
+}
+
+/// 把socket的接收队列绑定到地址上
+///
+/// 对于路径名地址，会在文件系统中创建一个socket文件；如果该路径已经存在，返回EADDRINUSE
+fn bind_endpoint(
+    endpoint: &UnixEndpoint,
+    queue: &Arc<UnixQueue>,
+) -> Result<UnixBindKey, SystemError> {
+    let key = match endpoint {
+        UnixEndpoint::Unnamed => return Err(SystemError::EINVAL),
+        UnixEndpoint::Abstract(name) => UnixBindKey::Abstract(name.clone()),
+        UnixEndpoint::Path(path) => {
+            let (inode_begin, path) = user_path_at(
+                &ProcessManager::current_pcb(),
+                AtFlags::AT_FDCWD.bits(),
+                path,
+            )?;
+            if inode_begin
+                .lookup_follow_symlink(&path, VFS_MAX_FOLLOW_SYMLINK_TIMES)
+                .is_ok()
+            {
+                return Err(SystemError::EADDRINUSE);
+            }
+
+            let (filename, parent_path) = rsplit_path(&path);
+            let parent_inode = inode_begin
+                .lookup_follow_symlink(parent_path.unwrap_or("/"), VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+            let inode = parent_inode.create(
+                filename,
+                FileType::Socket,
+                ModeType::from_bits_truncate(0o755),
+            )?;
+            UnixBindKey::from_inode(&inode)?
+        }
+    };
+
+    let mut table = UNIX_BIND_TABLE.lock_irqsave();
+    if table.contains_key(&key) {
+        return Err(SystemError::EADDRINUSE);
+    }
+    table.insert(key.clone(), queue.clone());
+    return Ok(key);
+}
+
+/// 查找绑定在地址上的socket的接收队列
+fn lookup_endpoint(endpoint: &UnixEndpoint) -> Result<Arc<UnixQueue>, SystemError> {
+    let key = match endpoint {
+        UnixEndpoint::Unnamed => return Err(SystemError::EINVAL),
+        UnixEndpoint::Abstract(name) => UnixBindKey::Abstract(name.clone()),
+        UnixEndpoint::Path(path) => {
+            let (inode_begin, path) = user_path_at(
+                &ProcessManager::current_pcb(),
+                AtFlags::AT_FDCWD.bits(),
+                path,
+            )?;
+            let inode = inode_begin.lookup_follow_symlink(&path, VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+            if inode.metadata()?.file_type != FileType::Socket {
+                return Err(SystemError::ECONNREFUSED);
+            }
+            UnixBindKey::from_inode(&inode)?
+        }
+    };
+
+    return UNIX_BIND_TABLE
+        .lock_irqsave()
+        .get(&key)
+        .cloned()
+        .ok_or(SystemError::ECONNREFUSED);
+}
+
+/// 解除地址的绑定。路径名地址对应的socket文件会被保留，与Linux的行为一致
+fn unbind_endpoint(key: &UnixBindKey) {
+    UNIX_BIND_TABLE.lock_irqsave().remove(key);
+}
+
+/// 从Endpoint中取出Unix域地址
+fn unix_endpoint(endpoint: Endpoint) -> Result<UnixEndpoint, SystemError> {
+    match endpoint {
+        Endpoint::Unix(endpoint) => Ok(endpoint),
+        _ => Err(SystemError::EINVAL),
+    }
+}
+
+/// 流式socket的连接状态
+#[derive(Debug, Clone)]
+enum UnixStreamState {
+    /// 刚创建，或者只是绑定了地址
+    Unconnected,
+    /// 正在监听
+    Listening,
+    /// 已经与对端建立连接
+    Connected {
+        /// 对端的接收队列
+        peer: Arc<UnixQueue>,
+        /// 对端的地址
+        peer_endpoint: UnixEndpoint,
+    },
+}
+
+/// # Unix域的流式socket
+///
+/// ref: https://man7.org/linux/man-pages/man7/unix.7.html
+#[derive(Debug, Clone)]
+pub struct UnixStreamSocket {
+    metadata: SocketMetadata,
+    /// 本socket的接收队列
+    rx: Arc<UnixQueue>,
+    /// 本socket绑定的地址
+    endpoint: UnixEndpoint,
+    /// 本socket在绑定表中的键
+    bind_key: Option<UnixBindKey>,
+    state: UnixStreamState,
+    shutdown: ShutdownType,
+}
+
+impl UnixStreamSocket {
+    /// 默认的元数据缓冲区大小
+    pub const DEFAULT_METADATA_BUF_SIZE: usize = 1024;
+
+    /// # 创建一个Unix域的流式socket
+    ///
+    /// ## 参数
+    /// - `options`: socket的选项
+    pub fn new(options: SocketOptions) -> Self {
+        let metadata = SocketMetadata::new(
+            SocketType::UnixStreamSocket,
+            UnixQueue::DEFAULT_BUF_SIZE,
+            UnixQueue::DEFAULT_BUF_SIZE,
+            Self::DEFAULT_METADATA_BUF_SIZE,
+            options,
+        );
+
+        return Self {
+            metadata,
+            rx: UnixQueue::new(SocketType::UnixStreamSocket),
+            endpoint: UnixEndpoint::Unnamed,
+            bind_key: None,
+            state: UnixStreamState::Unconnected,
+            shutdown: ShutdownType::empty(),
+        };
+    }
+
+    /// 把两个socket连接起来
+    ///
+    /// ## 参数
+    ///
+    /// - `a`, `b`: 要连接的两个socket
+    fn pair(a: &mut Self, b: &mut Self) {
+        a.rx.set_peer(&b.rx);
+        b.rx.set_peer(&a.rx);
+        a.state = UnixStreamState::Connected {
+            peer: b.rx.clone(),
+            peer_endpoint: b.endpoint.clone(),
+        };
+        b.state = UnixStreamState::Connected {
+            peer: a.rx.clone(),
+            peer_endpoint: a.endpoint.clone(),
+        };
+    }
+
+    fn peer(&self) -> Result<&Arc<UnixQueue>, SystemError> {
+        match &self.state {
+            UnixStreamState::Connected { peer, .. } => Ok(peer),
+            _ => Err(SystemError::ENOTCONN),
+        }
+    }
+}
+
+impl Socket for UnixStreamSocket {
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn core::any::Any {
+        self
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint) {
+        let (ret, endpoint, _) = self.recv_msg(buf);
+        // 没有通过recvmsg接收的辅助数据会被丢弃，其中的文件也随之关闭
+        return (ret, endpoint);
+    }
+
+    fn write(&self, buf: &[u8], to: Option<Endpoint>) -> Result<usize, SystemError> {
+        return self.send_msg(buf, to, Vec::new());
+    }
+
+    fn recv_msg(&mut self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint, Vec<ScmData>) {
+        let peer_endpoint = match &self.state {
+            UnixStreamState::Connected { peer_endpoint, .. } => peer_endpoint.clone(),
+            _ => {
+                return (
+                    Err(SystemError::ENOTCONN),
+                    Endpoint::Unix(UnixEndpoint::Unnamed),
+                    Vec::new(),
+                )
+            }
+        };
+
+        match self.rx.recv(buf, true, self.metadata.recv_deadline()) {
+            Ok((len, _, scm)) => (Ok(len), Endpoint::Unix(peer_endpoint), scm),
+            Err(e) => (Err(e), Endpoint::Unix(peer_endpoint), Vec::new()),
+        }
+    }
+
+    fn send_msg(
+        &self,
+        buf: &[u8],
+        _to: Option<Endpoint>,
+        mut scm: Vec<ScmData>,
+    ) -> Result<usize, SystemError> {
+        let peer = self.peer()?;
+        if self.shutdown.contains(ShutdownType::SEND_SHUTDOWN) {
+            return Err(SystemError::EPIPE);
+        }
+        peer.attach_credentials(&mut scm);
+
+        let deadline = self.metadata.send_deadline();
+        let mut sent = 0;
+        loop {
+            // 辅助数据随第一段数据发送
+            let scm = core::mem::take(&mut scm);
+            match peer.send(&buf[sent..], self.endpoint.clone(), scm, true, deadline) {
+                Ok(len) => sent += len,
+                // 已经发送了一部分数据时，返回已经发送的字节数
+                Err(_) if sent > 0 => break,
+                Err(e) => return Err(e),
+            }
+            if sent == buf.len() {
+                break;
+            }
+        }
+        return Ok(sent);
+    }
+
+    fn connect(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        match self.state {
+            UnixStreamState::Unconnected => {}
+            UnixStreamState::Listening => return Err(SystemError::EINVAL),
+            UnixStreamState::Connected { .. } => return Err(SystemError::EISCONN),
+        }
+
+        let endpoint = unix_endpoint(endpoint)?;
+        let listener = lookup_endpoint(&endpoint)?;
+        if listener.socket_type != SocketType::UnixStreamSocket {
+            return Err(SystemError::EPROTOTYPE);
+        }
+
+        // 服务端的socket在accept时交给监听者
+        let mut server = Self::new(self.metadata.options);
+        server.endpoint = endpoint;
+        Self::pair(self, &mut server);
+
+        if let Err(e) = listener.push_connection(server) {
+            self.state = UnixStreamState::Unconnected;
+            return Err(e);
+        }
+        return Ok(());
+    }
+
+    fn bind(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        if self.bind_key.is_some() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let endpoint = unix_endpoint(endpoint)?;
+        self.bind_key = Some(bind_endpoint(&endpoint, &self.rx)?);
+        self.endpoint = endpoint;
+        return Ok(());
+    }
+
+    fn shutdown(&mut self, shutdown_type: ShutdownType) -> Result<(), SystemError> {
+        let peer = self.peer()?.clone();
+        if shutdown_type.contains(ShutdownType::RCV_SHUTDOWN) {
+            self.rx.close_reader();
+        }
+        if shutdown_type.contains(ShutdownType::SEND_SHUTDOWN) {
+            peer.close_writer();
+        }
+        self.shutdown.insert(shutdown_type);
+        return Ok(());
+    }
+
+    fn listen(&mut self, backlog: usize) -> Result<(), SystemError> {
+        match self.state {
+            UnixStreamState::Connected { .. } => return Err(SystemError::EINVAL),
+            _ => {}
+        }
+        // todo: 未绑定地址时自动绑定一个抽象地址
+        if self.bind_key.is_none() {
+            return Err(SystemError::EINVAL);
+        }
+
+        self.rx.listen(backlog);
+        self.state = UnixStreamState::Listening;
+        return Ok(());
+    }
+
+    fn accept(&mut self) -> Result<(Box<dyn Socket>, Endpoint), SystemError> {
+        match self.state {
+            UnixStreamState::Listening => {}
+            _ => return Err(SystemError::EINVAL),
+        }
+
+        let socket = self.rx.pop_connection(self.metadata.recv_deadline())?;
+        let peer_endpoint = socket.peer_endpoint().unwrap();
+        return Ok((Box::new(socket), peer_endpoint));
+    }
+
+    fn endpoint(&self) -> Option<Endpoint> {
+        return Some(Endpoint::Unix(self.endpoint.clone()));
+    }
+
+    fn peer_endpoint(&self) -> Option<Endpoint> {
+        match &self.state {
+            UnixStreamState::Connected { peer_endpoint, .. } => {
+                Some(Endpoint::Unix(peer_endpoint.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    fn socketpair_ops(&self) -> Option<&'static dyn SocketpairOps> {
+        Some(&UnixStreamSocketpairOps)
+    }
+
+    fn poll(&self) -> EPollEventType {
+        match &self.state {
+            UnixStreamState::Unconnected => EPollEventType::EPOLLHUP,
+            UnixStreamState::Listening => self.rx.poll_in(),
+            UnixStreamState::Connected { peer, .. } => {
+                let mut events = self.rx.poll_in();
+                if !self.shutdown.contains(ShutdownType::SEND_SHUTDOWN) {
+                    events.insert(peer.poll_out());
+                }
+                if self.shutdown.contains(ShutdownType::SHUTDOWN_MASK) {
+                    events.insert(EPollEventType::EPOLLHUP);
+                }
+                events
+            }
+        }
+    }
+
+    fn metadata(&self) -> Result<SocketMetadata, SystemError> {
+        Ok(self.metadata.clone())
+    }
+
+    fn box_clone(&self) -> Box<dyn Socket> {
+        Box::new(self.clone())
+    }
+
+    fn setsockopt(
+        &mut self,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        return self
+            .rx
+            .setsockopt(&mut self.metadata, level, optname, optval);
+    }
+
+    fn add_epoll(&mut self, epitem: Arc<EPollItem>) -> Result<(), SystemError> {
+        self.rx.add_epoll(epitem);
+        Ok(())
+    }
+
+    fn remove_epoll(&mut self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
+        return self.rx.remove_epoll(epoll);
+    }
+
+    fn clear_epoll(&mut self) -> Result<(), SystemError> {
+        return self.rx.clear_epoll();
+    }
+
+    fn close(&mut self) {
+        if let Some(key) = self.bind_key.take() {
+            unbind_endpoint(&key);
+        }
+        if let UnixStreamState::Connected { peer, .. } = &self.state {
+            peer.close_writer();
+        }
+        self.rx.close();
+        let _ = self.clear_epoll();
+    }
+}
+
+struct UnixStreamSocketpairOps;
+
+impl SocketpairOps for UnixStreamSocketpairOps {
+    fn socketpair(&self, socket0: &mut Box<dyn Socket>, socket1: &mut Box<dyn Socket>) {
+        let pair0 = socket0
+            .as_mut()
+            .as_any_mut()
+            .downcast_mut::<UnixStreamSocket>()
+            .unwrap();
+
+        let pair1 = socket1
+            .as_mut()
+            .as_any_mut()
+            .downcast_mut::<UnixStreamSocket>()
+            .unwrap();
+        UnixStreamSocket::pair(pair0, pair1);
+    }
+}
+
+/// # Unix域的数据报socket
+///
+/// ref: https://man7.org/linux/man-pages/man7/unix.7.html
+#[derive(Debug, Clone)]
+pub struct UnixDatagramSocket {
+    metadata: SocketMetadata,
+    /// 本socket的接收队列
+    rx: Arc<UnixQueue>,
+    /// 本socket绑定的地址
+    endpoint: UnixEndpoint,
+    /// 本socket在绑定表中的键
+    bind_key: Option<UnixBindKey>,
+    /// 通过connect指定的默认对端：(对端的接收队列, 对端的地址)
+    peer: Option<(Arc<UnixQueue>, UnixEndpoint)>,
+    shutdown: ShutdownType,
+}
+
+impl UnixDatagramSocket {
+    /// 默认的元数据缓冲区大小
+    pub const DEFAULT_METADATA_BUF_SIZE: usize = 1024;
+
+    /// # 创建一个Unix域的数据报socket
+    ///
+    /// ## 参数
+    /// - `options`: socket的选项
+    pub fn new(options: SocketOptions) -> Self {
+        let metadata = SocketMetadata::new(
+            SocketType::UnixDatagramSocket,
+            UnixQueue::DEFAULT_BUF_SIZE,
+            UnixQueue::DEFAULT_BUF_SIZE,
+            Self::DEFAULT_METADATA_BUF_SIZE,
+            options,
+        );
+
+        return Self {
+            metadata,
+            rx: UnixQueue::new(SocketType::UnixDatagramSocket),
+            endpoint: UnixEndpoint::Unnamed,
+            bind_key: None,
+            peer: None,
+            shutdown: ShutdownType::empty(),
+        };
+    }
+}
+
+impl Socket for UnixDatagramSocket {
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn core::any::Any {
+        self
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint) {
+        let (ret, endpoint, _) = self.recv_msg(buf);
+        return (ret, endpoint);
+    }
+
+    fn write(&self, buf: &[u8], to: Option<Endpoint>) -> Result<usize, SystemError> {
+        return self.send_msg(buf, to, Vec::new());
+    }
+
+    fn recv_msg(&mut self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint, Vec<ScmData>) {
+        match self.rx.recv(buf, false, self.metadata.recv_deadline()) {
+            Ok((len, from, scm)) => (Ok(len), Endpoint::Unix(from), scm),
+            Err(e) => (Err(e), Endpoint::Unix(UnixEndpoint::Unnamed), Vec::new()),
+        }
+    }
+
+    fn send_msg(
+        &self,
+        buf: &[u8],
+        to: Option<Endpoint>,
+        mut scm: Vec<ScmData>,
+    ) -> Result<usize, SystemError> {
+        if self.shutdown.contains(ShutdownType::SEND_SHUTDOWN) {
+            return Err(SystemError::EPIPE);
+        }
+
+        let target = match to {
+            Some(endpoint) => lookup_endpoint(&unix_endpoint(endpoint)?)?,
+            None => self.peer.as_ref().ok_or(SystemError::ENOTCONN)?.0.clone(),
+        };
+        if target.socket_type != SocketType::UnixDatagramSocket {
+            return Err(SystemError::EPROTOTYPE);
+        }
+
+        target.attach_credentials(&mut scm);
+        return target.send(
+            buf,
+            self.endpoint.clone(),
+            scm,
+            false,
+            self.metadata.send_deadline(),
+        );
+    }
+
+    fn connect(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        let endpoint = unix_endpoint(endpoint)?;
+        let peer = lookup_endpoint(&endpoint)?;
+        if peer.socket_type != SocketType::UnixDatagramSocket {
+            return Err(SystemError::EPROTOTYPE);
+        }
+
+        self.peer = Some((peer, endpoint));
+        return Ok(());
+    }
+
+    fn bind(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        if self.bind_key.is_some() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let endpoint = unix_endpoint(endpoint)?;
+        self.bind_key = Some(bind_endpoint(&endpoint, &self.rx)?);
+        self.endpoint = endpoint;
+        return Ok(());
+    }
+
+    fn shutdown(&mut self, shutdown_type: ShutdownType) -> Result<(), SystemError> {
+        if shutdown_type.contains(ShutdownType::RCV_SHUTDOWN) {
+            self.rx.close_reader();
+        }
+        self.shutdown.insert(shutdown_type);
+        return Ok(());
+    }
+
+    fn endpoint(&self) -> Option<Endpoint> {
+        return Some(Endpoint::Unix(self.endpoint.clone()));
+    }
+
+    fn peer_endpoint(&self) -> Option<Endpoint> {
+        return self
+            .peer
+            .as_ref()
+            .map(|(_, endpoint)| Endpoint::Unix(endpoint.clone()));
+    }
+
+    fn socketpair_ops(&self) -> Option<&'static dyn SocketpairOps> {
+        Some(&UnixDatagramSocketpairOps)
+    }
+
+    fn poll(&self) -> EPollEventType {
+        let mut events = self.rx.poll_in();
+        if !self.shutdown.contains(ShutdownType::SEND_SHUTDOWN) {
+            match &self.peer {
+                Some((peer, _)) => events.insert(peer.poll_out()),
+                None => events.insert(EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM),
+            }
+        }
+        if self.shutdown.contains(ShutdownType::SHUTDOWN_MASK) {
+            events.insert(EPollEventType::EPOLLHUP);
+        }
+        return events;
+    }
+
+    fn metadata(&self) -> Result<SocketMetadata, SystemError> {
+        Ok(self.metadata.clone())
+    }
+
+    fn box_clone(&self) -> Box<dyn Socket> {
+        Box::new(self.clone())
+    }
+
+    fn setsockopt(
+        &mut self,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        return self
+            .rx
+            .setsockopt(&mut self.metadata, level, optname, optval);
+    }
+
+    fn add_epoll(&mut self, epitem: Arc<EPollItem>) -> Result<(), SystemError> {
+        self.rx.add_epoll(epitem);
+        Ok(())
+    }
+
+    fn remove_epoll(&mut self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
+        return self.rx.remove_epoll(epoll);
+    }
+
+    fn clear_epoll(&mut self) -> Result<(), SystemError> {
+        return self.rx.clear_epoll();
+    }
+
+    fn close(&mut self) {
+        if let Some(key) = self.bind_key.take() {
+            unbind_endpoint(&key);
+        }
+        self.rx.close();
+        let _ = self.clear_epoll();
+    }
+}
+
+struct UnixDatagramSocketpairOps;
+
+impl SocketpairOps for UnixDatagramSocketpairOps {
+    fn socketpair(&self, socket0: &mut Box<dyn Socket>, socket1: &mut Box<dyn Socket>) {
+        let pair0 = socket0
+            .as_mut()
+            .as_any_mut()
+            .downcast_mut::<UnixDatagramSocket>()
+            .unwrap();
+
+        let pair1 = socket1
+            .as_mut()
+            .as_any_mut()
+            .downcast_mut::<UnixDatagramSocket>()
+            .unwrap();
+        pair0.peer = Some((pair1.rx.clone(), pair1.endpoint.clone()));
+        pair1.peer = Some((pair0.rx.clone(), pair0.endpoint.clone()));
+    }
+}
@@ -1,6 +1,6 @@
 use core::cmp::min;
 
-use alloc::{boxed::Box, sync::Arc};
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
 use num_traits::{FromPrimitive, ToPrimitive};
 use smoltcp::wire;
 use system_error::SystemError;
@@ -10,10 +10,18 @@ use crate::{
         file::{File, FileMode},
         syscall::{IoVec, IoVecs},
     },
+    include::bindings::bindings::PAGE_4K_SIZE,
     libs::spinlock::SpinLockGuard,
     mm::{verify_area, VirtAddr},
-    net::socket::{AddressFamily, SOL_SOCKET},
-    process::ProcessManager,
+    net::socket::{
+        netlink::NetlinkEndpoint,
+        unix::{ScmData, UCred, UnixEndpoint, UnixQueue},
+        AddressFamily, SOL_SOCKET,
+    },
+    process::{
+        cred::{Kgid, Kuid},
+        ProcessManager,
+    },
     syscall::Syscall,
 };
 
@@ -38,18 +46,29 @@ impl Syscall {
         protocol: usize,
     ) -> Result<usize, SystemError> {
         let address_family = AddressFamily::try_from(address_family as u16)?;
-        let socket_type = PosixSocketType::try_from((socket_type & 0xf) as u8)?;
         let protocol = Protocol::from(protocol as u8);
 
+        let mut file_mode = FileMode::O_RDWR;
+        if socket_type & SOCK_NONBLOCK.bits() as usize != 0 {
+            file_mode |= FileMode::O_NONBLOCK;
+        }
+        if socket_type & SOCK_CLOEXEC.bits() as usize != 0 {
+            file_mode |= FileMode::O_CLOEXEC;
+        }
+        let socket_type = PosixSocketType::try_from((socket_type & 0xf) as u8)?;
+
         let socket = new_socket(address_family, socket_type, protocol)?;
 
-        let handle_item = SocketHandleItem::new(&socket);
-        HANDLE_MAP
-            .write_irqsave()
-            .insert(socket.socket_handle(), handle_item);
+        // Unix域socket和netlink socket不经过smoltcp，没有SocketHandle
+        if !matches!(address_family, AddressFamily::Unix | AddressFamily::Netlink) {
+            let handle_item = SocketHandleItem::new(&socket);
+            HANDLE_MAP
+                .write_irqsave()
+                .insert(socket.socket_handle(), Arc::new(handle_item));
+        }
 
         let socketinode: Arc<SocketInode> = SocketInode::new(socket);
-        let f = File::new(socketinode, FileMode::O_RDWR)?;
+        let f = File::new(socketinode, file_mode)?;
         // 把socket添加到当前进程的文件描述符表中
         let binding = ProcessManager::current_pcb().fd_table();
         let mut fd_table_guard = binding.write();
@@ -116,7 +135,7 @@ impl Syscall {
             .get_socket(fd as i32)
             .ok_or(SystemError::EBADF)?;
         // 获取内层的socket（真正的数据）
-        let socket: SpinLockGuard<Box<dyn Socket>> = socket_inode.inner();
+        let mut socket: SpinLockGuard<Box<dyn Socket>> = socket_inode.inner();
         return socket.setsockopt(level, optname, optval).map(|_| 0);
     }
 
@@ -136,60 +155,22 @@ impl Syscall {
         optval: *mut u8,
         optlen: *mut u32,
     ) -> Result<usize, SystemError> {
-        // 获取socket
-        let optval = optval as *mut u32;
+        // optlen是调用者提供的缓冲区的长度，返回时写入选项值的实际长度
+        let len = unsafe { *(optlen as *const i32) };
+        if len < 0 {
+            return Err(SystemError::EINVAL);
+        }
+        // 系统调用入口只检查了一页大小的optval
+        let len = min(len as usize, PAGE_4K_SIZE as usize);
+        let optval = unsafe { core::slice::from_raw_parts_mut(optval, len) };
+
         let binding: Arc<SocketInode> = ProcessManager::current_pcb()
             .get_socket(fd as i32)
             .ok_or(SystemError::EBADF)?;
         let socket = binding.inner();
-
-        if level as u8 == SOL_SOCKET {
-            let optname = PosixSocketOption::try_from(optname as i32)
-                .map_err(|_| SystemError::ENOPROTOOPT)?;
-            match optname {
-                PosixSocketOption::SO_SNDBUF => {
-                    // 返回发送缓冲区大小
-                    unsafe {
-                        *optval = socket.metadata()?.tx_buf_size as u32;
-                        *optlen = core::mem::size_of::<u32>() as u32;
-                    }
-                    return Ok(0);
-                }
-                PosixSocketOption::SO_RCVBUF => {
-                    let optval = optval as *mut u32;
-                    // 返回默认的接收缓冲区大小
-                    unsafe {
-                        *optval = socket.metadata()?.rx_buf_size as u32;
-                        *optlen = core::mem::size_of::<u32>() as u32;
-                    }
-                    return Ok(0);
-                }
-                _ => {
-                    return Err(SystemError::ENOPROTOOPT);
-                }
-            }
-        }
-        drop(socket);
-
-        // To manipulate options at any other level the
-        // protocol number of the appropriate protocol controlling the
-        // option is supplied.  For example, to indicate that an option is
-        // to be interpreted by the TCP protocol, level should be set to the
-        // protocol number of TCP.
-
-        let posix_protocol =
-            PosixIpProtocol::try_from(level as u16).map_err(|_| SystemError::ENOPROTOOPT)?;
-        if posix_protocol == PosixIpProtocol::TCP {
-            let optname = PosixTcpSocketOptions::try_from(optname as i32)
-                .map_err(|_| SystemError::ENOPROTOOPT)?;
-            match optname {
-                PosixTcpSocketOptions::Congestion => return Ok(0),
-                _ => {
-                    return Err(SystemError::ENOPROTOOPT);
-                }
-            }
-        }
-        return Err(SystemError::ENOPROTOOPT);
+        let written = socket.getsockopt(level, optname, optval)?;
+        unsafe { *optlen = written as u32 };
+        return Ok(0);
     }
 
     /// @brief sys_connect系统调用的实际执行函数
@@ -292,6 +273,35 @@ impl Syscall {
         return Ok(n);
     }
 
+    /// @brief sys_sendmsg系统调用的实际执行函数
+    ///
+    /// @param fd 文件描述符
+    /// @param msg MsgHdr
+    /// @param flags 标志，暂时未使用
+    ///
+    /// @return 成功返回发送的字节数，失败返回错误码
+    pub fn sendmsg(fd: usize, msg: &MsgHdr, _flags: u32) -> Result<usize, SystemError> {
+        // 检查每个缓冲区地址是否合法，生成iovecs
+        let iovs = unsafe { IoVecs::from_user(msg.msg_iov, msg.msg_iovlen, false)? };
+        let buf = iovs.gather();
+
+        let endpoint = if msg.msg_name.is_null() {
+            None
+        } else {
+            Some(SockAddr::to_endpoint(
+                msg.msg_name,
+                msg.msg_namelen as usize,
+            )?)
+        };
+        let scm = unsafe { CmsgHdr::read_from_user(msg.msg_control, msg.msg_controllen)? };
+
+        let socket: Arc<SocketInode> = ProcessManager::current_pcb()
+            .get_socket(fd as i32)
+            .ok_or(SystemError::EBADF)?;
+        let socket = unsafe { socket.inner_no_preempt() };
+        return socket.send_msg(&buf, endpoint, scm);
+    }
+
     /// @brief sys_recvmsg系统调用的实际执行函数
     ///
     /// @param fd 文件描述符
@@ -310,7 +320,7 @@ impl Syscall {
 
         let mut buf = iovs.new_buf(true);
         // 从socket中读取数据
-        let (n, endpoint) = socket.read(&mut buf);
+        let (n, endpoint, scm) = socket.recv_msg(&mut buf);
         drop(socket);
 
         let n: usize = n?;
@@ -318,6 +328,11 @@ impl Syscall {
         // 将数据写入用户空间的iovecs
         iovs.scatter(&buf[..n]);
 
+        msg.msg_flags = 0;
+        unsafe {
+            CmsgHdr::write_to_user(msg, scm)?;
+        }
+
         let sockaddr_in = SockAddr::from(endpoint);
         unsafe {
             sockaddr_in.write_to_user(msg.msg_name, &mut msg.msg_namelen)?;
@@ -524,6 +539,11 @@ pub struct SockAddrUn {
     pub sun_path: [u8; 108],
 }
 
+impl SockAddrUn {
+    /// sun_path在结构体中的偏移量
+    pub const PATH_OFFSET: usize = core::mem::size_of::<u16>();
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct SockAddrLl {
@@ -539,10 +559,10 @@ pub struct SockAddrLl {
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct SockAddrNl {
-    nl_family: u16,
-    nl_pad: u16,
-    nl_pid: u32,
-    nl_groups: u32,
+    pub nl_family: u16,
+    pub nl_pad: u16,
+    pub nl_pid: u32,
+    pub nl_groups: u32,
 }
 
 #[repr(C)]
@@ -573,11 +593,13 @@ impl SockAddr {
         .map_err(|_| SystemError::EFAULT)?;
 
         let addr = unsafe { addr.as_ref() }.ok_or(SystemError::EFAULT)?;
-        if len < addr.len()? {
+        let family = AddressFamily::try_from(unsafe { addr.family })?;
+        // Unix域地址的长度由用户给出，路径名可以不以'\0'结尾
+        if family != AddressFamily::Unix && len < addr.len()? {
             return Err(SystemError::EINVAL);
         }
         unsafe {
-            match AddressFamily::try_from(addr.family)? {
+            match family {
                 AddressFamily::INet => {
                     let addr_in: SockAddrIn = addr.addr_in;
 
@@ -593,11 +615,31 @@ impl SockAddr {
                     return Err(SystemError::EINVAL);
                 }
                 AddressFamily::Netlink => {
-                    // TODO: support netlink socket
-                    return Err(SystemError::EINVAL);
+                    let addr_nl: SockAddrNl = addr.addr_nl;
+                    return Ok(Endpoint::Netlink(NetlinkEndpoint {
+                        pid: addr_nl.nl_pid,
+                        groups: addr_nl.nl_groups,
+                    }));
                 }
                 AddressFamily::Unix => {
-                    return Err(SystemError::EINVAL);
+                    if len < SockAddrUn::PATH_OFFSET {
+                        return Err(SystemError::EINVAL);
+                    }
+                    let len = min(len, core::mem::size_of::<SockAddrUn>());
+                    let path = &addr.addr_un.sun_path[..len - SockAddrUn::PATH_OFFSET];
+
+                    if path.is_empty() {
+                        return Ok(Endpoint::Unix(UnixEndpoint::Unnamed));
+                    }
+                    // 以'\0'开头的是抽象命名空间中的名字
+                    if path[0] == 0 {
+                        return Ok(Endpoint::Unix(UnixEndpoint::Abstract(path[1..].to_vec())));
+                    }
+
+                    let path_len = path.iter().position(|c| *c == 0).unwrap_or(path.len());
+                    let path =
+                        core::str::from_utf8(&path[..path_len]).map_err(|_| SystemError::EINVAL)?;
+                    return Ok(Endpoint::Unix(UnixEndpoint::Path(String::from(path))));
                 }
                 _ => {
                     return Err(SystemError::EINVAL);
@@ -612,7 +654,25 @@ impl SockAddr {
             AddressFamily::INet => Ok(core::mem::size_of::<SockAddrIn>()),
             AddressFamily::Packet => Ok(core::mem::size_of::<SockAddrLl>()),
             AddressFamily::Netlink => Ok(core::mem::size_of::<SockAddrNl>()),
-            AddressFamily::Unix => Err(SystemError::EINVAL),
+            AddressFamily::Unix => {
+                let sun_path = unsafe { &self.addr_un.sun_path };
+                let path_len = if sun_path[0] != 0 {
+                    // 路径名，长度包含结尾的'\0'
+                    sun_path
+                        .iter()
+                        .position(|c| *c == 0)
+                        .map(|x| x + 1)
+                        .unwrap_or(sun_path.len())
+                } else {
+                    // 抽象命名空间中的名字，长度包含开头的'\0'
+                    sun_path
+                        .iter()
+                        .rposition(|c| *c != 0)
+                        .map(|x| x + 1)
+                        .unwrap_or(0)
+                };
+                Ok(SockAddrUn::PATH_OFFSET + path_len)
+            }
             _ => Err(SystemError::EINVAL),
         };
 
@@ -706,8 +766,39 @@ impl From<Endpoint> for SockAddr {
 
                 return SockAddr { addr_ll };
             }
+
+            Endpoint::Unix(unix_endpoint) => {
+                let mut addr_un = SockAddrUn {
+                    sun_family: AddressFamily::Unix as u16,
+                    sun_path: [0; 108],
+                };
+                match unix_endpoint {
+                    UnixEndpoint::Unnamed => {}
+                    UnixEndpoint::Path(path) => {
+                        let len = min(path.len(), addr_un.sun_path.len() - 1);
+                        addr_un.sun_path[..len].copy_from_slice(&path.as_bytes()[..len]);
+                    }
+                    UnixEndpoint::Abstract(name) => {
+                        let len = min(name.len(), addr_un.sun_path.len() - 1);
+                        addr_un.sun_path[1..len + 1].copy_from_slice(&name[..len]);
+                    }
+                }
+
+                return SockAddr { addr_un };
+            }
+
+            Endpoint::Netlink(netlink_endpoint) => {
+                let addr_nl = SockAddrNl {
+                    nl_family: AddressFamily::Netlink as u16,
+                    nl_pad: 0,
+                    nl_pid: netlink_endpoint.pid,
+                    nl_groups: netlink_endpoint.groups,
+                };
+
+                return SockAddr { addr_nl };
+            }
             _ => {
-                // todo: support other endpoint, like Netlink...
+                // todo: support other endpoint
                 unimplemented!("not support {value:?}");
             }
         }
@@ -733,6 +824,186 @@ pub struct MsgHdr {
     pub msg_flags: u32,
 }
 
+/// 控制数据被截断（msg_flags）
+pub const MSG_CTRUNC: u32 = 0x8;
+
+/// 传递文件描述符
+pub const SCM_RIGHTS: i32 = 1;
+/// 传递进程凭证
+pub const SCM_CREDENTIALS: i32 = 2;
+
+/// 辅助数据的头部
+///
+/// 参考：https://man7.org/linux/man-pages/man3/cmsg.3.html
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CmsgHdr {
+    /// 包括头部在内的数据长度
+    pub cmsg_len: usize,
+    /// 协议层次
+    pub cmsg_level: i32,
+    /// 协议相关的类型
+    pub cmsg_type: i32,
+}
+
+impl CmsgHdr {
+    const HDR_LEN: usize = core::mem::size_of::<CmsgHdr>();
+
+    /// 辅助数据按照usize对齐
+    const fn align(len: usize) -> usize {
+        let align = core::mem::size_of::<usize>();
+        return (len + align - 1) & !(align - 1);
+    }
+
+    /// 解析sendmsg传入的辅助数据
+    ///
+    /// @param control 用户空间的辅助数据缓冲区
+    /// @param len 缓冲区长度
+    pub unsafe fn read_from_user(
+        control: *const u8,
+        len: usize,
+    ) -> Result<Vec<ScmData>, SystemError> {
+        let mut scm = Vec::new();
+        if control.is_null() || len == 0 {
+            return Ok(scm);
+        }
+        verify_area(VirtAddr::new(control as usize), len).map_err(|_| SystemError::EFAULT)?;
+        let control = core::slice::from_raw_parts(control, len);
+
+        let mut offset = 0;
+        while offset + Self::HDR_LEN <= len {
+            let hdr = (control[offset..].as_ptr() as *const CmsgHdr).read_unaligned();
+            if hdr.cmsg_len < Self::HDR_LEN || offset + hdr.cmsg_len > len {
+                return Err(SystemError::EINVAL);
+            }
+            let data = &control[offset + Self::HDR_LEN..offset + hdr.cmsg_len];
+            if hdr.cmsg_level != SOL_SOCKET as i32 {
+                return Err(SystemError::EINVAL);
+            }
+
+            match hdr.cmsg_type {
+                SCM_RIGHTS => {
+                    let count = data.len() / core::mem::size_of::<i32>();
+                    if count > UnixQueue::SCM_MAX_FD {
+                        return Err(SystemError::EINVAL);
+                    }
+                    let binding = ProcessManager::current_pcb().fd_table();
+                    let fd_table_guard = binding.read();
+                    let mut files = Vec::with_capacity(count);
+                    for fd in data.chunks_exact(core::mem::size_of::<i32>()) {
+                        let fd = i32::from_ne_bytes(fd.try_into().unwrap());
+                        files.push(
+                            fd_table_guard
+                                .get_file_by_fd(fd)
+                                .ok_or(SystemError::EBADF)?,
+                        );
+                    }
+                    if !files.is_empty() {
+                        scm.push(ScmData::Rights(files));
+                    }
+                }
+                SCM_CREDENTIALS => {
+                    if data.len() < core::mem::size_of::<UCred>() {
+                        return Err(SystemError::EINVAL);
+                    }
+                    let cred = (data.as_ptr() as *const UCred).read_unaligned();
+                    // 只能发送自己的凭证，特权进程可以发送其他的pid、uid和gid
+                    let pcb = ProcessManager::current_pcb();
+                    let current = pcb.cred();
+                    let uid = Kuid::new(cred.uid as usize);
+                    let gid = Kgid::new(cred.gid as usize);
+                    if cred.pid != pcb.pid().data() as i32 && !current.can_sys_admin() {
+                        return Err(SystemError::EPERM);
+                    }
+                    if uid != current.uid
+                        && uid != current.euid
+                        && uid != current.suid
+                        && !current.can_setuid()
+                    {
+                        return Err(SystemError::EPERM);
+                    }
+                    if gid != current.gid
+                        && gid != current.egid
+                        && gid != current.sgid
+                        && !current.can_setgid()
+                    {
+                        return Err(SystemError::EPERM);
+                    }
+                    scm.push(ScmData::Credentials(cred));
+                }
+                _ => return Err(SystemError::EINVAL),
+            }
+            offset += Self::align(hdr.cmsg_len);
+        }
+        return Ok(scm);
+    }
+
+    /// 把recvmsg收到的辅助数据写入用户空间，并更新msg的msg_controllen和msg_flags
+    ///
+    /// 传递的文件会在当前进程中分配新的文件描述符。缓冲区放不下的辅助数据会被丢弃，并设置MSG_CTRUNC
+    pub unsafe fn write_to_user(msg: &mut MsgHdr, scm: Vec<ScmData>) -> Result<(), SystemError> {
+        let len = if msg.msg_control.is_null() {
+            0
+        } else {
+            msg.msg_controllen
+        };
+        if len > 0 {
+            verify_area(VirtAddr::new(msg.msg_control as usize), len)
+                .map_err(|_| SystemError::EFAULT)?;
+        }
+
+        let mut written = 0;
+        for data in scm {
+            let space = len.saturating_sub(written + Self::HDR_LEN);
+            let (cmsg_type, payload) = match data {
+                ScmData::Credentials(cred) => {
+                    let payload = core::slice::from_raw_parts(
+                        &cred as *const UCred as *const u8,
+                        core::mem::size_of::<UCred>(),
+                    );
+                    (SCM_CREDENTIALS, payload.to_vec())
+                }
+                ScmData::Rights(files) => {
+                    let count = min(files.len(), space / core::mem::size_of::<i32>());
+                    let mut payload = Vec::with_capacity(count * core::mem::size_of::<i32>());
+                    let binding = ProcessManager::current_pcb().fd_table();
+                    let mut fd_table_guard = binding.write();
+                    // 接收者与发送者共享同一个打开的文件，而不是重新打开一次
+                    for file in files.iter().take(count) {
+                        let fd = fd_table_guard.install_fd(file.clone(), None)?;
+                        payload.extend_from_slice(&fd.to_ne_bytes());
+                    }
+                    if count < files.len() {
+                        msg.msg_flags |= MSG_CTRUNC;
+                    }
+                    (SCM_RIGHTS, payload)
+                }
+            };
+
+            if payload.is_empty() || payload.len() > space {
+                msg.msg_flags |= MSG_CTRUNC;
+                continue;
+            }
+
+            let hdr = CmsgHdr {
+                cmsg_len: Self::HDR_LEN + payload.len(),
+                cmsg_level: SOL_SOCKET as i32,
+                cmsg_type,
+            };
+            let buf = core::slice::from_raw_parts_mut(msg.msg_control.add(written), hdr.cmsg_len);
+            buf[..Self::HDR_LEN].copy_from_slice(core::slice::from_raw_parts(
+                &hdr as *const CmsgHdr as *const u8,
+                Self::HDR_LEN,
+            ));
+            buf[Self::HDR_LEN..].copy_from_slice(&payload);
+            written = min(len, written + Self::align(hdr.cmsg_len));
+        }
+
+        msg.msg_controllen = written;
+        return Ok(());
+    }
+}
+
 #[derive(Debug, Clone, Copy, FromPrimitive, ToPrimitive, PartialEq, Eq)]
 pub enum PosixIpProtocol {
     /// Dummy protocol for TCP.
@@ -1017,3 +1288,58 @@ impl Into<i32> for PosixTcpSocketOptions {
         <Self as ToPrimitive>::to_i32(&self).unwrap()
     }
 }
+
+/// IPPROTO_IP层次上的socket选项
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/include/uapi/linux/in.h#94
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+pub enum PosixIpSocketOptions {
+    /// Type of service.
+    Tos = 1,
+    /// Time to live.
+    Ttl = 2,
+    /// User supplies the IP header.
+    HdrIncl = 3,
+    /// IP options.
+    Options = 4,
+    RouterAlert = 5,
+    RecvOpts = 6,
+    RetOpts = 7,
+    /// Receive packet information as a cmsg.
+    PktInfo = 8,
+    PktOptions = 9,
+    /// Path MTU discovery.
+    MtuDiscover = 10,
+    RecvErr = 11,
+    RecvTtl = 12,
+    RecvTos = 13,
+    Mtu = 14,
+    FreeBind = 15,
+    /// Local address used for outgoing multicast packets.
+    MulticastIf = 32,
+    /// Time to live of outgoing multicast packets.
+    MulticastTtl = 33,
+    /// Loop outgoing multicast packets back to local sockets.
+    MulticastLoop = 34,
+    /// Join a multicast group.
+    AddMembership = 35,
+    /// Leave a multicast group.
+    DropMembership = 36,
+}
+
+impl TryFrom<i32> for PosixIpSocketOptions {
+    type Error = SystemError;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match <Self as FromPrimitive>::from_i32(value) {
+            Some(p) => Ok(p),
+            None => Err(SystemError::EINVAL),
+        }
+    }
+}
+
+impl Into<i32> for PosixIpSocketOptions {
+    fn into(self) -> i32 {
+        <Self as ToPrimitive>::to_i32(&self).unwrap()
+    }
+}
@@ -0,0 +1,580 @@
+//! 进程的core dump：进程因为信号而终止时，把进程的内存和寄存器写入ELF格式的core文件，以便用gdb进行调试
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/fs/coredump.c
+//! 和 https://code.dragonos.org.cn/xref/linux-6.1.9/fs/binfmt_elf.c
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    arch::{interrupt::TrapFrame, ipc::signal::Signal, CurrentElfArch, MMArch},
+    driver::base::block::SeekFrom,
+    filesystem::vfs::{
+        file::{File, FileMode},
+        permission::{inode_init_owner, inode_permission, PermissionMask},
+        syscall::ModeType,
+        utils::rsplit_path,
+        FileType, IndexNode, ROOT_INODE,
+    },
+    kinfo, kwarn,
+    libs::{elf::ElfArch, spinlock::SpinLock},
+    mm::{
+        ucontext::{AddressSpace, Provider},
+        MemoryManagementArch, VirtAddr, VmFlags,
+    },
+    process::{ProcessControlBlock, ProcessFlags, ProcessManager},
+    time::timekeeping::getnstimeofday,
+};
+
+/// core_pattern的最大长度（包括结尾的'\0'）
+pub const CORENAME_MAX_SIZE: usize = 128;
+
+/// 进程状态信息
+const NT_PRSTATUS: u32 = 1;
+/// 进程的基本信息
+const NT_PRPSINFO: u32 = 3;
+/// 进程的auxv
+const NT_AUXV: u32 = 6;
+/// 文件映射的信息
+const NT_FILE: u32 = 0x46494c45;
+
+/// ELF文件头的大小
+const ELF_EHDR_SIZE: usize = 64;
+/// 程序头的大小
+const ELF_PHDR_SIZE: usize = 56;
+
+lazy_static! {
+    /// core文件的路径模板，与Linux的/proc/sys/kernel/core_pattern相同
+    static ref CORE_PATTERN: SpinLock<String> = SpinLock::new(String::from("core"));
+}
+
+/// 获取core文件的路径模板
+pub fn core_pattern() -> String {
+    return CORE_PATTERN.lock().clone();
+}
+
+/// 设置core文件的路径模板
+///
+/// ## 参数
+///
+/// - `pattern` 新的路径模板，支持的格式说明符见[`format_corename`]
+pub fn set_core_pattern(pattern: &str) -> Result<(), SystemError> {
+    if pattern.len() >= CORENAME_MAX_SIZE {
+        return Err(SystemError::EINVAL);
+    }
+    *CORE_PATTERN.lock() = String::from(pattern);
+    return Ok(());
+}
+
+/// 根据core_pattern生成core文件的绝对路径
+///
+/// 支持以下格式说明符：
+/// - `%%` 字符'%'
+/// - `%p` 进程的tgid
+/// - `%i` 线程的pid
+/// - `%u` 进程的真实用户id
+/// - `%g` 进程的真实组id
+/// - `%s` 导致core dump的信号
+/// - `%t` core dump的时间（自1970年1月1日以来的秒数）
+/// - `%e` 进程的名称
+///
+/// 相对路径相对于进程的当前工作目录
+fn format_corename(pcb: &Arc<ProcessControlBlock>, sig: Signal) -> Result<String, SystemError> {
+    let pattern = core_pattern();
+    // 暂不支持把core dump通过管道交给用户程序处理
+    if pattern.starts_with('|') {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+    if pattern.is_empty() {
+        return Err(SystemError::EINVAL);
+    }
+
+    let cred = pcb.cred();
+    let mut corename = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            corename.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => corename.push('%'),
+            Some('p') => corename.push_str(&pcb.tgid().data().to_string()),
+            Some('i') => corename.push_str(&pcb.pid().data().to_string()),
+            Some('u') => corename.push_str(&cred.uid.data().to_string()),
+            Some('g') => corename.push_str(&cred.gid.data().to_string()),
+            Some('s') => corename.push_str(&(sig as usize).to_string()),
+            Some('t') => corename.push_str(&getnstimeofday().tv_sec.to_string()),
+            // 进程名中的'/'会被当作路径分隔符，因此替换为'!'
+            Some('e') => corename.push_str(&pcb.basic().name().replace('/', "!")),
+            // 与Linux相同，忽略不认识的格式说明符
+            _ => {}
+        }
+    }
+
+    if !corename.starts_with('/') {
+        let cwd = pcb.basic().cwd();
+        corename = format!("{}/{}", cwd.trim_end_matches('/'), corename);
+    }
+    return Ok(corename);
+}
+
+/// 创建（或截断已存在的）core文件
+fn open_core_file(corename: &str) -> Result<File, SystemError> {
+    let (filename, parent_path) = rsplit_path(corename);
+    let parent: Arc<dyn IndexNode> = ROOT_INODE().lookup(parent_path.unwrap_or("/"))?;
+
+    let inode = match parent.find(filename) {
+        Ok(inode) => {
+            // 与Linux相同，只覆盖没有其他硬链接的普通文件
+            let metadata = inode.metadata()?;
+            if metadata.file_type != FileType::File || metadata.nlinks > 1 {
+                return Err(SystemError::EPERM);
+            }
+            inode_permission(&inode, PermissionMask::MAY_WRITE)?;
+            inode
+        }
+        Err(SystemError::ENOENT) => {
+            inode_permission(
+                &parent,
+                PermissionMask::MAY_WRITE | PermissionMask::MAY_EXEC,
+            )?;
+            let inode = parent.create(
+                filename,
+                FileType::File,
+                ModeType::from_bits_truncate(0o600),
+            )?;
+            inode_init_owner(&parent, &inode)?;
+            inode
+        }
+        Err(e) => return Err(e),
+    };
+
+    let mut file = File::new(inode, FileMode::O_WRONLY)?;
+    file.ftruncate(0)?;
+    return Ok(file);
+}
+
+/// core文件中的一段内存区域
+struct CoreVma {
+    start: usize,
+    end: usize,
+    vm_flags: VmFlags,
+    /// 文件映射的(文件中的页号, 文件路径)
+    file: Option<(usize, String)>,
+}
+
+impl CoreVma {
+    /// 是否把区域的内容写入core文件。不可读的区域（例如栈的保护页）只记录地址范围
+    fn dump_content(&self) -> bool {
+        return self.vm_flags.contains(VmFlags::VM_READ);
+    }
+
+    fn filesz(&self) -> usize {
+        if self.dump_content() {
+            return self.end - self.start;
+        }
+        return 0;
+    }
+
+    fn elf_flags(&self) -> u32 {
+        let mut flags = 0;
+        if self.vm_flags.contains(VmFlags::VM_READ) {
+            flags |= elf::abi::PF_R;
+        }
+        if self.vm_flags.contains(VmFlags::VM_WRITE) {
+            flags |= elf::abi::PF_W;
+        }
+        if self.vm_flags.contains(VmFlags::VM_EXEC) {
+            flags |= elf::abi::PF_X;
+        }
+        return flags;
+    }
+}
+
+fn put_u16(buf: &mut Vec<u8>, v: u16) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, v: u32) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_u64(buf: &mut Vec<u8>, v: u64) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+/// 把buf补齐到align字节对齐
+fn pad_to(buf: &mut Vec<u8>, align: usize) {
+    let len = (buf.len() + align - 1) & !(align - 1);
+    buf.resize(len, 0);
+}
+
+/// 把字符串写入定长的、以'\0'结尾的字段
+fn put_cstr(buf: &mut Vec<u8>, s: &str, size: usize) {
+    let bytes = s.as_bytes();
+    let len = bytes.len().min(size - 1);
+    buf.extend_from_slice(&bytes[..len]);
+    buf.resize(buf.len() + size - len, 0);
+}
+
+/// 向notes中添加一个名称为"CORE"的note
+fn put_note(notes: &mut Vec<u8>, note_type: u32, desc: &[u8]) {
+    const NAME: &[u8] = b"CORE\0";
+    put_u32(notes, NAME.len() as u32);
+    put_u32(notes, desc.len() as u32);
+    put_u32(notes, note_type);
+    notes.extend_from_slice(NAME);
+    pad_to(notes, 4);
+    notes.extend_from_slice(desc);
+    pad_to(notes, 4);
+}
+
+/// 生成NT_PRSTATUS（struct elf_prstatus）
+fn prstatus_desc(pcb: &Arc<ProcessControlBlock>, sig: Signal, frame: &TrapFrame) -> Vec<u8> {
+    let (pending, blocked) = {
+        let sig_info = pcb.sig_info_irqsave();
+        (sig_info.pending_signals(), *sig_info.sig_block())
+    };
+    let basic = pcb.basic();
+
+    let mut desc = Vec::new();
+    // pr_info: si_signo, si_code, si_errno
+    put_u32(&mut desc, sig as u32);
+    put_u32(&mut desc, 0);
+    put_u32(&mut desc, 0);
+    // pr_cursig
+    put_u16(&mut desc, sig as u16);
+    pad_to(&mut desc, 8);
+    put_u64(&mut desc, pending.bits());
+    put_u64(&mut desc, blocked.bits());
+    put_u32(&mut desc, pcb.pid().data() as u32);
+    put_u32(&mut desc, basic.ppid().data() as u32);
+    put_u32(&mut desc, basic.pgid().data() as u32);
+    // pr_sid
+    put_u32(&mut desc, 0);
+    // pr_utime, pr_stime, pr_cutime, pr_cstime
+    desc.resize(desc.len() + 4 * 16, 0);
+    for reg in CurrentElfArch::core_copy_regs(frame) {
+        put_u64(&mut desc, reg as u64);
+    }
+    // pr_fpvalid
+    put_u32(&mut desc, 0);
+    pad_to(&mut desc, 8);
+    return desc;
+}
+
+/// 生成NT_PRPSINFO（struct elf_prpsinfo）
+fn prpsinfo_desc(pcb: &Arc<ProcessControlBlock>) -> Vec<u8> {
+    let cred = pcb.cred();
+    let basic = pcb.basic();
+
+    let mut desc = Vec::new();
+    // pr_state, pr_sname, pr_zomb, pr_nice
+    desc.extend_from_slice(&[0, b'R', 0, 0]);
+    pad_to(&mut desc, 8);
+    // pr_flag
+    put_u64(&mut desc, 0);
+    put_u32(&mut desc, cred.uid.data() as u32);
+    put_u32(&mut desc, cred.gid.data() as u32);
+    put_u32(&mut desc, pcb.pid().data() as u32);
+    put_u32(&mut desc, basic.ppid().data() as u32);
+    put_u32(&mut desc, basic.pgid().data() as u32);
+    // pr_sid
+    put_u32(&mut desc, 0);
+    // pr_fname, pr_psargs
+    put_cstr(&mut desc, basic.name(), 16);
+    put_cstr(&mut desc, basic.name(), 80);
+    return desc;
+}
+
+/// 生成NT_AUXV，以AT_NULL结尾
+fn auxv_desc(auxv: &[(usize, usize)]) -> Vec<u8> {
+    let mut desc = Vec::new();
+    for &(k, v) in auxv.iter().filter(|(k, _)| *k != 0) {
+        put_u64(&mut desc, k as u64);
+        put_u64(&mut desc, v as u64);
+    }
+    put_u64(&mut desc, 0);
+    put_u64(&mut desc, 0);
+    return desc;
+}
+
+/// 生成NT_FILE：文件映射的数量、页大小、每个映射的(起始地址, 结束地址, 文件中的页号)，然后是各个文件的路径
+fn file_desc(vmas: &[CoreVma]) -> Vec<u8> {
+    let files: Vec<(&CoreVma, &(usize, String))> = vmas
+        .iter()
+        .filter_map(|vma| vma.file.as_ref().map(|f| (vma, f)))
+        .collect();
+
+    let mut desc = Vec::new();
+    put_u64(&mut desc, files.len() as u64);
+    put_u64(&mut desc, MMArch::PAGE_SIZE as u64);
+    for (vma, (pgoff, _)) in files.iter() {
+        put_u64(&mut desc, vma.start as u64);
+        put_u64(&mut desc, vma.end as u64);
+        put_u64(&mut desc, *pgoff as u64);
+    }
+    for (_, (_, path)) in files.iter() {
+        desc.extend_from_slice(path.as_bytes());
+        desc.push(0);
+    }
+    return desc;
+}
+
+/// 生成ELF文件头和程序头
+fn elf_headers(vmas: &[CoreVma], notes_size: usize, data_offset: usize) -> Vec<u8> {
+    let phnum = vmas.len() + 1;
+    let mut buf = Vec::new();
+
+    // e_ident: ELF魔数、64位、小端序、版本号1、System V ABI
+    buf.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    pad_to(&mut buf, 16);
+    put_u16(&mut buf, elf::abi::ET_CORE);
+    put_u16(&mut buf, CurrentElfArch::ELF_MACHINE);
+    // e_version
+    put_u32(&mut buf, 1);
+    // e_entry
+    put_u64(&mut buf, 0);
+    // e_phoff
+    put_u64(&mut buf, ELF_EHDR_SIZE as u64);
+    // e_shoff
+    put_u64(&mut buf, 0);
+    // e_flags
+    put_u32(&mut buf, 0);
+    put_u16(&mut buf, ELF_EHDR_SIZE as u16);
+    put_u16(&mut buf, ELF_PHDR_SIZE as u16);
+    put_u16(&mut buf, phnum as u16);
+    // e_shentsize, e_shnum, e_shstrndx
+    put_u16(&mut buf, 0);
+    put_u16(&mut buf, 0);
+    put_u16(&mut buf, 0);
+
+    // PT_NOTE
+    put_u32(&mut buf, elf::abi::PT_NOTE);
+    put_u32(&mut buf, 0);
+    put_u64(&mut buf, (ELF_EHDR_SIZE + phnum * ELF_PHDR_SIZE) as u64);
+    put_u64(&mut buf, 0);
+    put_u64(&mut buf, 0);
+    put_u64(&mut buf, notes_size as u64);
+    put_u64(&mut buf, 0);
+    put_u64(&mut buf, 0);
+
+    // 每个VMA对应一个PT_LOAD
+    let mut offset = data_offset;
+    for vma in vmas.iter() {
+        put_u32(&mut buf, elf::abi::PT_LOAD);
+        put_u32(&mut buf, vma.elf_flags());
+        put_u64(&mut buf, offset as u64);
+        put_u64(&mut buf, vma.start as u64);
+        put_u64(&mut buf, 0);
+        put_u64(&mut buf, vma.filesz() as u64);
+        put_u64(&mut buf, (vma.end - vma.start) as u64);
+        put_u64(&mut buf, MMArch::PAGE_SIZE as u64);
+        offset += vma.filesz();
+    }
+
+    return buf;
+}
+
+/// 写入core文件，超出RLIMIT_CORE的部分被丢弃
+struct CoreWriter {
+    file: File,
+    /// 当前的写入位置
+    pos: usize,
+    /// core文件的大小限制
+    limit: usize,
+}
+
+impl CoreWriter {
+    fn new(file: File, limit: usize) -> Self {
+        return Self {
+            file,
+            pos: 0,
+            limit,
+        };
+    }
+
+    /// ## 写入数据
+    ///
+    /// ## 返回值
+    ///
+    /// 写入之后是否还没有达到大小限制
+    fn write(&mut self, buf: &[u8]) -> Result<bool, SystemError> {
+        let len = buf.len().min(self.limit - self.pos);
+        self.file.write(len, &buf[..len])?;
+        self.pos += len;
+        return Ok(self.pos < self.limit);
+    }
+
+    /// ## 跳过`len`字节，在文件中留下空洞
+    ///
+    /// ## 返回值
+    ///
+    /// 跳过之后是否还没有达到大小限制
+    fn skip(&mut self, len: usize) -> Result<bool, SystemError> {
+        self.pos += len.min(self.limit - self.pos);
+        self.file.lseek(SeekFrom::SeekSet(self.pos as i64))?;
+        return Ok(self.pos < self.limit);
+    }
+
+    /// 结束写入。文件以空洞结尾时，需要把文件扩展到写入位置
+    fn finish(self) -> Result<(), SystemError> {
+        if self.file.metadata()?.size < self.pos as i64 {
+            self.file.ftruncate(self.pos)?;
+        }
+        return Ok(());
+    }
+}
+
+/// ## 把VMA的内容写入core文件
+///
+/// 按需分页的地址空间中，尚未分配物理页的页面不需要写入，只在文件中留下空洞
+///
+/// ## 返回值
+///
+/// 写入之后是否还没有达到core文件的大小限制
+fn write_vma(
+    writer: &mut CoreWriter,
+    address_space: &Arc<AddressSpace>,
+    vma: &CoreVma,
+) -> Result<bool, SystemError> {
+    let mut page_buf = alloc::vec![0u8; MMArch::PAGE_SIZE];
+    for vaddr in (vma.start..vma.end).step_by(MMArch::PAGE_SIZE) {
+        // 写文件可能会睡眠，因此每次只在拷贝一个页面时持有地址空间的锁
+        let mapped = {
+            let guard = address_space.read();
+            let vaddr = unsafe {
+                guard
+                    .user_mapper
+                    .utable
+                    .translate(VirtAddr::new(vaddr))
+                    .and_then(|(paddr, _)| MMArch::phys_2_virt(paddr))
+            };
+            if let Some(vaddr) = vaddr {
+                page_buf.copy_from_slice(unsafe {
+                    core::slice::from_raw_parts(vaddr.data() as *const u8, MMArch::PAGE_SIZE)
+                });
+            }
+            vaddr.is_some()
+        };
+        let more = if mapped {
+            writer.write(&page_buf)?
+        } else {
+            writer.skip(MMArch::PAGE_SIZE)?
+        };
+        if !more {
+            return Ok(false);
+        }
+    }
+    return Ok(true);
+}
+
+/// 为当前进程生成core文件
+///
+/// ## 参数
+///
+/// - `sig` 导致进程终止的信号
+/// - `frame` 进程收到信号时的用户态寄存器
+/// - `limit` core文件的大小限制
+///
+/// ## 返回值
+///
+/// 成功时返回core文件的路径
+fn elf_core_dump(sig: Signal, frame: &TrapFrame, limit: usize) -> Result<String, SystemError> {
+    let pcb = ProcessManager::current_pcb();
+    // 内核线程没有用户地址空间，不生成core文件
+    let address_space = pcb.basic().user_vm().ok_or(SystemError::EINVAL)?;
+
+    let (vmas, auxv) = {
+        let guard = address_space.read();
+        let mut vmas: Vec<CoreVma> = guard
+            .mappings
+            .iter_vmas()
+            .map(|vma| {
+                let vma = vma.lock();
+                let file = match vma.provider() {
+                    Provider::File {
+                        pgoff,
+                        path: Some(path),
+                        ..
+                    } => Some((*pgoff, path.clone())),
+                    _ => None,
+                };
+                CoreVma {
+                    start: vma.region().start().data(),
+                    end: vma.region().end().data(),
+                    vm_flags: *vma.vm_flags(),
+                    file,
+                }
+            })
+            .collect();
+        vmas.sort_by_key(|vma| vma.start);
+        (vmas, guard.saved_auxv.clone())
+    };
+
+    let mut notes = Vec::new();
+    put_note(&mut notes, NT_PRSTATUS, &prstatus_desc(&pcb, sig, frame));
+    put_note(&mut notes, NT_PRPSINFO, &prpsinfo_desc(&pcb));
+    put_note(&mut notes, NT_AUXV, &auxv_desc(&auxv));
+    put_note(&mut notes, NT_FILE, &file_desc(&vmas));
+
+    // 内存数据从页对齐的位置开始存放
+    let notes_offset = ELF_EHDR_SIZE + (vmas.len() + 1) * ELF_PHDR_SIZE;
+    let data_offset =
+        (notes_offset + notes.len() + MMArch::PAGE_SIZE - 1) & !(MMArch::PAGE_SIZE - 1);
+
+    let corename = format_corename(&pcb, sig)?;
+    let mut writer = CoreWriter::new(open_core_file(&corename)?, limit);
+
+    let mut head = elf_headers(&vmas, notes.len(), data_offset);
+    head.extend_from_slice(&notes);
+    head.resize(data_offset, 0);
+    if writer.write(&head)? {
+        for vma in vmas.iter().filter(|vma| vma.dump_content()) {
+            if !write_vma(&mut writer, &address_space, vma)? {
+                break;
+            }
+        }
+    }
+    writer.finish()?;
+
+    return Ok(corename);
+}
+
+/// ## 进程因为信号`sig`而终止时，生成core文件
+///
+/// RLIMIT_CORE小于一个页面，或者进程在执行set-user-ID程序等之后凭证发生了变化时，不生成core文件。
+/// 失败时只打印警告，不影响进程的终止
+pub fn do_coredump(sig: Signal, frame: &TrapFrame) {
+    let pcb = ProcessManager::current_pcb();
+    if pcb.flags().contains(ProcessFlags::NOT_DUMPABLE) {
+        return;
+    }
+    let limit = pcb.sig_struct_irqsave().core_rlimit.rlim_cur;
+    if limit < MMArch::PAGE_SIZE as u64 {
+        return;
+    }
+    let limit = limit.min(usize::MAX as u64) as usize;
+
+    let pid = pcb.pid();
+    match elf_core_dump(sig, frame, limit) {
+        Ok(corename) => kinfo!(
+            "pid {:?} killed by signal {:?}, core dumped to {}",
+            pid,
+            sig,
+            corename
+        ),
+        Err(e) => kwarn!(
+            "pid {:?} killed by signal {:?}, failed to dump core: {:?}",
+            pid,
+            sig,
+            e
+        ),
+    }
+}
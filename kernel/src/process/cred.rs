@@ -0,0 +1,214 @@
+//! 进程的凭证
+//!
+//! 参考：https://code.dragonos.org.cn/xref/linux-6.1.9/include/linux/cred.h
+
+use alloc::vec::Vec;
+use system_error::SystemError;
+
+int_like!(Kuid, usize);
+int_like!(Kgid, usize);
+
+impl Kuid {
+    /// root用户
+    pub const ROOT: Kuid = Kuid(0);
+}
+
+impl Kgid {
+    /// root用户组
+    pub const ROOT: Kgid = Kgid(0);
+}
+
+/// 附加组的最大数量
+pub const NGROUPS_MAX: usize = 65536;
+
+/// 进程的凭证
+///
+/// 凭证一旦被设置到进程上，就不会再被修改。需要修改时，复制一份新的凭证，修改之后再替换进程的凭证。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cred {
+    /// 真实用户id
+    pub uid: Kuid,
+    /// 真实组id
+    pub gid: Kgid,
+    /// 保存的用户id
+    pub suid: Kuid,
+    /// 保存的组id
+    pub sgid: Kgid,
+    /// 有效用户id
+    pub euid: Kuid,
+    /// 有效组id
+    pub egid: Kgid,
+    /// 访问文件系统时使用的用户id
+    pub fsuid: Kuid,
+    /// 访问文件系统时使用的组id
+    pub fsgid: Kgid,
+    /// 附加组
+    pub groups: Vec<Kgid>,
+}
+
+impl Cred {
+    /// 创建root用户的凭证
+    pub const fn new_root() -> Self {
+        Self {
+            uid: Kuid::ROOT,
+            gid: Kgid::ROOT,
+            suid: Kuid::ROOT,
+            sgid: Kgid::ROOT,
+            euid: Kuid::ROOT,
+            egid: Kgid::ROOT,
+            fsuid: Kuid::ROOT,
+            fsgid: Kgid::ROOT,
+            groups: Vec::new(),
+        }
+    }
+
+    /// 是否有修改用户id的权限（对应linux的CAP_SETUID）
+    ///
+    /// todo: 目前没有实现capabilities，有效用户id为root时拥有所有的权限
+    #[inline]
+    pub fn can_setuid(&self) -> bool {
+        self.euid == Kuid::ROOT
+    }
+
+    /// 是否有修改组id的权限（对应linux的CAP_SETGID）
+    #[inline]
+    pub fn can_setgid(&self) -> bool {
+        self.euid == Kuid::ROOT
+    }
+
+    /// 是否有提高进程调度优先级、修改其他用户进程调度属性的权限（对应linux的CAP_SYS_NICE）
+    #[inline]
+    pub fn can_nice(&self) -> bool {
+        self.euid == Kuid::ROOT
+    }
+
+    /// 是否有配置网络接口、路由表等网络设置的权限（对应linux的CAP_NET_ADMIN）
+    #[inline]
+    pub fn can_net_admin(&self) -> bool {
+        self.euid == Kuid::ROOT
+    }
+
+    /// 是否有挂载、卸载文件系统等系统管理操作的权限（对应linux的CAP_SYS_ADMIN）
+    #[inline]
+    pub fn can_sys_admin(&self) -> bool {
+        self.euid == Kuid::ROOT
+    }
+
+    /// 是否有提高资源限制的权限（对应linux的CAP_SYS_RESOURCE）
+    #[inline]
+    pub fn can_sys_resource(&self) -> bool {
+        self.euid == Kuid::ROOT
+    }
+
+    /// 访问文件系统时，是否可以忽略文件的权限（对应linux的CAP_DAC_OVERRIDE和CAP_FOWNER）
+    #[inline]
+    pub fn fs_override(&self) -> bool {
+        self.fsuid == Kuid::ROOT
+    }
+
+    /// 访问文件系统时，是否属于组`gid`
+    pub fn in_group(&self, gid: Kgid) -> bool {
+        return self.fsgid == gid || self.groups.contains(&gid);
+    }
+
+    /// ## setuid系统调用的语义
+    ///
+    /// 有权限时，同时设置真实、有效和保存的用户id；否则只能把有效用户id设置为真实或保存的用户id
+    pub fn setuid(&mut self, uid: Kuid) -> Result<(), SystemError> {
+        if self.can_setuid() {
+            self.uid = uid;
+            self.suid = uid;
+        } else if uid != self.uid && uid != self.suid {
+            return Err(SystemError::EPERM);
+        }
+
+        self.euid = uid;
+        self.fsuid = uid;
+        return Ok(());
+    }
+
+    /// ## setgid系统调用的语义
+    pub fn setgid(&mut self, gid: Kgid) -> Result<(), SystemError> {
+        if self.can_setgid() {
+            self.gid = gid;
+            self.sgid = gid;
+        } else if gid != self.gid && gid != self.sgid {
+            return Err(SystemError::EPERM);
+        }
+
+        self.egid = gid;
+        self.fsgid = gid;
+        return Ok(());
+    }
+
+    /// ## setresuid系统调用的语义
+    ///
+    /// 参数为None时表示不修改对应的id。没有权限时，新的id必须是当前的真实、有效或保存的用户id之一
+    pub fn setresuid(
+        &mut self,
+        ruid: Option<Kuid>,
+        euid: Option<Kuid>,
+        suid: Option<Kuid>,
+    ) -> Result<(), SystemError> {
+        if !self.can_setuid() {
+            let allowed = |id: &Kuid| *id == self.uid || *id == self.euid || *id == self.suid;
+            if ![ruid, euid, suid].iter().flatten().all(allowed) {
+                return Err(SystemError::EPERM);
+            }
+        }
+
+        if let Some(ruid) = ruid {
+            self.uid = ruid;
+        }
+        if let Some(euid) = euid {
+            self.euid = euid;
+        }
+        if let Some(suid) = suid {
+            self.suid = suid;
+        }
+        self.fsuid = self.euid;
+        return Ok(());
+    }
+
+    /// ## setresgid系统调用的语义
+    pub fn setresgid(
+        &mut self,
+        rgid: Option<Kgid>,
+        egid: Option<Kgid>,
+        sgid: Option<Kgid>,
+    ) -> Result<(), SystemError> {
+        if !self.can_setgid() {
+            let allowed = |id: &Kgid| *id == self.gid || *id == self.egid || *id == self.sgid;
+            if ![rgid, egid, sgid].iter().flatten().all(allowed) {
+                return Err(SystemError::EPERM);
+            }
+        }
+
+        if let Some(rgid) = rgid {
+            self.gid = rgid;
+        }
+        if let Some(egid) = egid {
+            self.egid = egid;
+        }
+        if let Some(sgid) = sgid {
+            self.sgid = sgid;
+        }
+        self.fsgid = self.egid;
+        return Ok(());
+    }
+
+    /// ## setgroups系统调用的语义
+    pub fn setgroups(&mut self, mut groups: Vec<Kgid>) -> Result<(), SystemError> {
+        if !self.can_setgid() {
+            return Err(SystemError::EPERM);
+        }
+        if groups.len() > NGROUPS_MAX {
+            return Err(SystemError::EINVAL);
+        }
+
+        groups.sort();
+        groups.dedup();
+        self.groups = groups;
+        return Ok(());
+    }
+}
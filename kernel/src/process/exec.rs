@@ -7,13 +7,19 @@ use crate::{
     driver::base::block::SeekFrom,
     filesystem::vfs::{
         file::{File, FileMode},
-        ROOT_INODE,
+        permission::{inode_permission, PermissionMask},
+        syscall::ModeType,
+        FileType, ROOT_INODE,
     },
     libs::elf::ELF_LOADER,
     mm::{
         ucontext::{AddressSpace, UserStack},
         VirtAddr,
     },
+    process::{
+        cred::{Cred, Kgid, Kuid},
+        ProcessManager,
+    },
 };
 
 /// 系统支持的所有二进制文件加载器的列表
@@ -193,6 +199,37 @@ pub fn load_binary_file(param: &mut ExecParam) -> Result<BinaryLoaderResult, Sys
     return Ok(result);
 }
 
+/// ## 检查当前进程能否执行文件，并计算执行文件之后进程的凭证
+///
+/// 如果文件设置了set-user-ID（set-group-ID）位，新的有效用户id（组id）为文件的所有者（组）。
+/// 执行之后，保存的用户id和组id被设置为新的有效用户id和组id
+pub fn prepare_exec_creds(file_path: &str) -> Result<Cred, SystemError> {
+    let inode = ROOT_INODE().lookup(file_path)?;
+    let metadata = inode.metadata()?;
+    if metadata.file_type != FileType::File {
+        return Err(SystemError::EACCES);
+    }
+    inode_permission(&inode, PermissionMask::MAY_EXEC)?;
+
+    let mut cred = (*ProcessManager::current_pcb().cred()).clone();
+    if metadata.mode.contains(ModeType::S_ISUID) {
+        cred.euid = Kuid::new(metadata.uid);
+    }
+    // 没有组执行权限的S_ISGID表示强制锁，而不是set-group-ID
+    if metadata
+        .mode
+        .contains(ModeType::S_ISGID | ModeType::S_IXGRP)
+    {
+        cred.egid = Kgid::new(metadata.gid);
+    }
+
+    cred.suid = cred.euid;
+    cred.fsuid = cred.euid;
+    cred.sgid = cred.egid;
+    cred.fsgid = cred.egid;
+    return Ok(cred);
+}
+
 /// 程序初始化信息，这些信息会被压入用户栈中
 #[derive(Debug)]
 pub struct ProcInitInfo {
@@ -285,6 +285,12 @@ impl ProcessManager {
             (*new_pcb.sig_struct_irqsave()).handlers =
                 current_pcb.sig_struct_irqsave().handlers.clone();
         }
+
+        let core_rlimit = current_pcb.sig_struct_irqsave().core_rlimit;
+        new_pcb.sig_struct_irqsave().core_rlimit = core_rlimit;
+
+        // 子进程继承父进程的信号屏蔽字，但是不继承待处理的信号
+        *new_pcb.sig_info_mut().sig_block_mut() = *current_pcb.sig_info_irqsave().sig_block();
         return Ok(());
     }
 
@@ -397,6 +403,9 @@ impl ProcessManager {
             )
         });
 
+        // 继承调度策略、优先级和cpu亲和性
+        pcb.sched_info().inherit_from(current_pcb.sched_info());
+
         // 拷贝用户地址空间
         Self::copy_mm(&clone_flags, &current_pcb, &pcb).unwrap_or_else(|e| {
             panic!(
@@ -3,7 +3,9 @@ use core::{
     hint::spin_loop,
     intrinsics::{likely, unlikely},
     mem::ManuallyDrop,
-    sync::atomic::{compiler_fence, AtomicBool, AtomicIsize, AtomicUsize, Ordering},
+    sync::atomic::{
+        compiler_fence, AtomicBool, AtomicI32, AtomicIsize, AtomicU64, AtomicUsize, Ordering,
+    },
 };
 
 use alloc::{
@@ -32,6 +34,7 @@ use crate::{
     libs::{
         align::AlignedBox,
         casting::DowncastArc,
+        cpumask::CpuMask,
         futex::{
             constant::{FutexFlag, FUTEX_BITSET_MATCH_ANY},
             futex::Futex,
@@ -44,6 +47,7 @@ use crate::{
     mm::{percpu::PerCpuVar, set_INITIAL_PROCESS_ADDRESS_SPACE, ucontext::AddressSpace, VirtAddr},
     net::socket::SocketInode,
     sched::{
+        cfs::SchedEntity,
         completion::Completion,
         core::{sched_enqueue, CPU_EXECUTING},
         SchedPolicy, SchedPriority,
@@ -53,12 +57,15 @@ use crate::{
         kick_cpu,
     },
     syscall::{user_access::clear_user, Syscall},
+    time::itimer::exit_itimers,
 };
 
-use self::kthread::WorkerPrivate;
+use self::{cred::Cred, kthread::WorkerPrivate};
 
 pub mod abi;
 pub mod c_adapter;
+pub mod coredump;
+pub mod cred;
 pub mod exec;
 pub mod exit;
 pub mod fork;
@@ -182,6 +189,39 @@ impl ProcessManager {
         return ALL_PROCESS.lock_irqsave().as_ref()?.get(&pid).cloned();
     }
 
+    /// 获取线程组中所有线程占用CPU的时间之和（纳秒）
+    ///
+    /// ## 参数
+    ///
+    /// - `tgid` : 线程组id
+    pub fn thread_group_cpu_time(tgid: Pid) -> u64 {
+        return ALL_PROCESS
+            .lock_irqsave()
+            .as_ref()
+            .map(|all| {
+                all.values()
+                    .filter(|pcb| pcb.tgid() == tgid)
+                    .map(|pcb| pcb.sched_info().cpu_time())
+                    .sum()
+            })
+            .unwrap_or(0);
+    }
+
+    /// 获取系统中所有满足条件的进程
+    ///
+    /// ## 参数
+    ///
+    /// - `filter` : 过滤条件，返回true的进程会被包含在结果中
+    pub fn filter(
+        filter: impl Fn(&Arc<ProcessControlBlock>) -> bool,
+    ) -> Vec<Arc<ProcessControlBlock>> {
+        return ALL_PROCESS
+            .lock_irqsave()
+            .as_ref()
+            .map(|all| all.values().filter(|pcb| filter(pcb)).cloned().collect())
+            .unwrap_or_default();
+    }
+
     /// 向系统中添加一个进程的pcb
     ///
     /// ## 参数
@@ -362,6 +402,8 @@ impl ProcessManager {
             thread.vfork_done.as_ref().unwrap().complete_all();
         }
         drop(thread);
+        // 停止进程的定时器，以免进程退出之后还收到定时器的信号
+        exit_itimers(&pcb);
         unsafe { pcb.basic_mut().set_user_vm(None) };
         drop(pcb);
         ProcessManager::exit_notify();
@@ -413,6 +455,11 @@ impl ProcessManager {
         // 由于进程切换前使用了SpinLockGuard::leak()，所以这里需要手动释放锁
         prev_pcb.arch_info.force_unlock();
         next_pcb.arch_info.force_unlock();
+
+        // 切换完成之后，prev进程才可以被迁移到其他cpu上
+        if !Arc::ptr_eq(&prev_pcb, &next_pcb) {
+            prev_pcb.sched_info().set_running(false);
+        }
     }
 
     /// 如果目标进程正在目标CPU上运行，那么就让这个cpu陷入内核态
@@ -533,6 +580,8 @@ bitflags! {
         const NEED_MIGRATE = 1 << 7;
         /// 随机化的虚拟地址空间，主要用于动态链接器的加载
         const RANDOMIZE = 1 << 8;
+        /// 进程的凭证在执行set-user-ID程序或者set*id时发生了变化，不能生成core文件
+        const NOT_DUMPABLE = 1 << 9;
     }
 }
 
@@ -579,6 +628,9 @@ pub struct ProcessControlBlock {
 
     /// 线程信息
     thread: RwLock<ThreadInfo>,
+
+    /// 进程的凭证
+    cred: SpinLock<Arc<Cred>>,
 }
 
 impl ProcessControlBlock {
@@ -606,15 +658,24 @@ impl ProcessControlBlock {
 
     #[inline(never)]
     fn do_create_pcb(name: String, kstack: KernelStack, is_idle: bool) -> Arc<Self> {
-        let (pid, ppid, cwd) = if is_idle {
-            (Pid(0), Pid(0), "/".to_string())
+        let (pid, ppid, cwd, umask, cred) = if is_idle {
+            (
+                Pid(0),
+                Pid(0),
+                "/".to_string(),
+                ProcessBasicInfo::DEFAULT_UMASK,
+                Arc::new(Cred::new_root()),
+            )
         } else {
             let ppid = ProcessManager::current_pcb().pid();
             let cwd = ProcessManager::current_pcb().basic().cwd();
-            (Self::generate_pid(), ppid, cwd)
+            let umask = ProcessManager::current_pcb().basic().umask();
+            // 子进程继承父进程的凭证
+            let cred = ProcessManager::current_pcb().cred();
+            (Self::generate_pid(), ppid, cwd, umask, cred)
         };
 
-        let basic_info = ProcessBasicInfo::new(Pid(0), ppid, name, cwd, None);
+        let basic_info = ProcessBasicInfo::new(Pid(0), ppid, name, cwd, umask, None);
         let preempt_count = AtomicUsize::new(0);
         let flags = unsafe { LockFreeFlags::new(ProcessFlags::empty()) };
 
@@ -644,6 +705,7 @@ impl ProcessControlBlock {
             children: RwLock::new(Vec::new()),
             wait_queue: WaitQueue::INIT,
             thread: RwLock::new(ThreadInfo::new()),
+            cred: SpinLock::new(cred),
         };
 
         // 初始化系统调用栈
@@ -751,6 +813,17 @@ impl ProcessControlBlock {
         return self.arch_info.lock();
     }
 
+    /// 获取进程的凭证
+    #[inline(always)]
+    pub fn cred(&self) -> Arc<Cred> {
+        return self.cred.lock_irqsave().clone();
+    }
+
+    /// 替换进程的凭证
+    pub fn set_cred(&self, cred: Cred) {
+        *self.cred.lock_irqsave() = Arc::new(cred);
+    }
+
     #[inline(always)]
     pub fn kernel_stack(&self) -> RwLockReadGuard<KernelStack> {
         return self.kernel_stack.read();
@@ -782,6 +855,12 @@ impl ProcessControlBlock {
         return self.tgid;
     }
 
+    /// 获取线程组的组长，组长已经被回收时返回None
+    #[inline]
+    pub fn group_leader(&self) -> Option<Arc<ProcessControlBlock>> {
+        return self.thread.read_irqsave().group_leader();
+    }
+
     /// 获取文件描述符表的Arc指针
     #[inline(always)]
     pub fn fd_table(&self) -> Arc<RwLock<FileDescriptorVec>> {
@@ -948,6 +1027,9 @@ pub struct ProcessBasicInfo {
     /// 当前进程的工作目录
     cwd: String,
 
+    /// 创建文件时的权限掩码
+    umask: u32,
+
     /// 用户地址空间
     user_vm: Option<Arc<AddressSpace>>,
 
@@ -956,12 +1038,16 @@ pub struct ProcessBasicInfo {
 }
 
 impl ProcessBasicInfo {
+    /// 初始进程的权限掩码
+    pub const DEFAULT_UMASK: u32 = 0o022;
+
     #[inline(never)]
     pub fn new(
         pgid: Pid,
         ppid: Pid,
         name: String,
         cwd: String,
+        umask: u32,
         user_vm: Option<Arc<AddressSpace>>,
     ) -> RwLock<Self> {
         let fd_table = Arc::new(RwLock::new(FileDescriptorVec::new()));
@@ -970,6 +1056,7 @@ impl ProcessBasicInfo {
             ppid,
             name,
             cwd,
+            umask,
             user_vm,
             fd_table: Some(fd_table),
         });
@@ -998,6 +1085,14 @@ impl ProcessBasicInfo {
         return self.cwd = path;
     }
 
+    pub fn umask(&self) -> u32 {
+        return self.umask;
+    }
+
+    pub fn set_umask(&mut self, umask: u32) {
+        self.umask = umask;
+    }
+
     pub fn user_vm(&self) -> Option<Arc<AddressSpace>> {
         return self.user_vm.clone();
     }
@@ -1023,12 +1118,22 @@ pub struct ProcessSchedulerInfo {
     /// 该字段存储要被迁移到的目标处理器核心号
     migrate_to: AtomicProcessorId,
     inner_locked: RwLock<InnerSchedInfo>,
-    /// 进程的调度优先级
-    priority: SchedPriority,
+    /// 进程的调度优先级（`SchedPriority`的值），在持有`inner_locked`的写锁时修改
+    priority: AtomicI32,
+    /// 进程的nice值。实时进程也保留nice值，切换回CFS时使用
+    nice: AtomicI32,
+    /// 进程可以在哪些cpu上运行
+    cpus_allowed: RwLock<CpuMask>,
     /// 当前进程的虚拟运行时间
     virtual_runtime: AtomicIsize,
     /// 由实时调度器管理的时间片
     rt_time_slice: AtomicIsize,
+    /// 进程占用CPU的时间（纳秒）
+    cpu_time: AtomicU64,
+    /// 进程在CFS运行队列上的调度实体
+    sched_entity: SpinLock<SchedEntity>,
+    /// 进程是否正在某个cpu上运行（从被选中切换到切换完成之后被换下），正在运行的进程不能被迁移
+    running: AtomicBool,
 }
 
 #[derive(Debug)]
@@ -1051,12 +1156,18 @@ impl InnerSchedInfo {
     pub fn policy(&self) -> SchedPolicy {
         return self.sched_policy;
     }
+
+    pub fn set_policy(&mut self, policy: SchedPolicy) {
+        self.sched_policy = policy;
+    }
 }
 
 impl ProcessSchedulerInfo {
     #[inline(never)]
     pub fn new(on_cpu: Option<ProcessorId>) -> Self {
         let cpu_id = on_cpu.unwrap_or(ProcessorId::INVALID);
+        let mut cpus_allowed = CpuMask::new();
+        cpus_allowed.set_all(true);
         return Self {
             on_cpu: AtomicProcessorId::new(cpu_id),
             migrate_to: AtomicProcessorId::new(ProcessorId::INVALID),
@@ -1066,10 +1177,30 @@ impl ProcessSchedulerInfo {
             }),
             virtual_runtime: AtomicIsize::new(0),
             rt_time_slice: AtomicIsize::new(0),
-            priority: SchedPriority::new(100).unwrap(),
+            cpu_time: AtomicU64::new(0),
+            priority: AtomicI32::new(SchedPriority::DEFAULT.data()),
+            nice: AtomicI32::new(0),
+            cpus_allowed: RwLock::new(cpus_allowed),
+            sched_entity: SpinLock::new(SchedEntity::new()),
+            running: AtomicBool::new(false),
         };
     }
 
+    /// fork时，子进程继承父进程的调度策略、优先级和cpu亲和性
+    pub fn inherit_from(&self, parent: &ProcessSchedulerInfo) {
+        let parent_inner = parent.inner_lock_read_irqsave();
+        let mut inner = self.inner_lock_write_irqsave();
+        inner.set_policy(parent_inner.policy());
+        self.priority
+            .store(parent.priority.load(Ordering::SeqCst), Ordering::SeqCst);
+        self.nice
+            .store(parent.nice.load(Ordering::SeqCst), Ordering::SeqCst);
+        drop(inner);
+        drop(parent_inner);
+
+        *self.cpus_allowed.write_irqsave() = parent.cpus_allowed();
+    }
+
     pub fn on_cpu(&self) -> Option<ProcessorId> {
         let on_cpu = self.on_cpu.load(Ordering::SeqCst);
         if on_cpu == ProcessorId::INVALID {
@@ -1163,8 +1294,62 @@ impl ProcessSchedulerInfo {
         self.rt_time_slice.fetch_add(delta, Ordering::SeqCst);
     }
 
+    /// 获取进程占用CPU的时间（纳秒）
+    pub fn cpu_time(&self) -> u64 {
+        return self.cpu_time.load(Ordering::SeqCst);
+    }
+
+    /// 把`delta`纳秒的CPU时间记到进程上
+    pub fn account_cpu_time(&self, delta: u64) {
+        self.cpu_time.fetch_add(delta, Ordering::SeqCst);
+    }
+
     pub fn priority(&self) -> SchedPriority {
-        return self.priority;
+        return SchedPriority::new(self.priority.load(Ordering::SeqCst)).unwrap();
+    }
+
+    /// ## 设置进程的调度优先级
+    ///
+    /// 调用者需要持有`inner_locked`的写锁，保证优先级与调度策略一致
+    pub fn set_priority(&self, priority: SchedPriority) {
+        self.priority.store(priority.data(), Ordering::SeqCst);
+    }
+
+    pub fn nice(&self) -> i32 {
+        return self.nice.load(Ordering::SeqCst);
+    }
+
+    pub fn set_nice(&self, nice: i32) {
+        self.nice.store(nice, Ordering::SeqCst);
+    }
+
+    /// 获取进程的cpu亲和性
+    pub fn cpus_allowed(&self) -> CpuMask {
+        return self.cpus_allowed.read_irqsave().clone();
+    }
+
+    pub fn set_cpus_allowed(&self, mask: CpuMask) {
+        *self.cpus_allowed.write_irqsave() = mask;
+    }
+
+    /// 进程是否可以在`cpu`上运行
+    pub fn cpu_allowed(&self, cpu: ProcessorId) -> bool {
+        return self.cpus_allowed.read_irqsave().get(cpu).unwrap_or(false);
+    }
+
+    /// ## 获取进程的CFS调度实体
+    ///
+    /// 需要同时持有运行队列的锁时，必须先获取运行队列的锁
+    pub fn sched_entity(&self) -> SpinLockGuard<SchedEntity> {
+        return self.sched_entity.lock_irqsave();
+    }
+
+    pub fn is_running(&self) -> bool {
+        return self.running.load(Ordering::SeqCst);
+    }
+
+    pub fn set_running(&self, running: bool) {
+        self.running.store(running, Ordering::SeqCst);
     }
 }
 
@@ -1300,6 +1485,8 @@ pub struct ProcessSignalInfo {
     sig_pending: SigPending,
     // sig_shared_pending 中存储当前线程所属进程要处理的信号
     sig_shared_pending: SigPending,
+    // sigsuspend等系统调用临时修改信号屏蔽字时，保存原来的屏蔽字，在信号处理完之后恢复
+    saved_sigmask: Option<SigSet>,
     // 当前进程对应的tty
     tty: Option<Arc<TtyCore>>,
 }
@@ -1344,11 +1531,42 @@ impl ProcessSignalInfo {
     /// - `sig_mask` 被忽略掉的信号
     ///
     pub fn dequeue_signal(&mut self, sig_mask: &SigSet) -> (Signal, Option<SigInfo>) {
-        let res = self.sig_pending.dequeue_signal(sig_mask);
-        if res.0 != Signal::INVALID {
-            return res;
-        } else {
-            return self.sig_shared_pending.dequeue_signal(sig_mask);
+        let mut res = self.sig_pending.dequeue_signal(sig_mask);
+        if res.0 == Signal::INVALID {
+            res = self.sig_shared_pending.dequeue_signal(sig_mask);
+        }
+        // 同一个信号可能有多个在排队，或者有其他信号因为之前已有待处理的信号而没有置位
+        self.recalc_sigpending();
+        return res;
+    }
+
+    /// 根据当前的信号屏蔽字，重新计算待处理信号的位图
+    pub fn recalc_sigpending(&mut self) {
+        self.sig_pending.recalc(&self.sig_block);
+        self.sig_shared_pending.recalc(&self.sig_block);
+    }
+
+    /// 所有待处理的信号（包括被屏蔽的信号）
+    pub fn pending_signals(&self) -> SigSet {
+        return self.sig_pending.signal()
+            | self.sig_pending.queue().sigset()
+            | self.sig_shared_pending.signal()
+            | self.sig_shared_pending.queue().sigset();
+    }
+
+    /// 临时修改信号屏蔽字，原来的屏蔽字会在下一次处理信号时恢复
+    pub fn set_temporary_sig_block(&mut self, mut new_set: SigSet) {
+        new_set.remove(Signal::SIGKILL.into_sigset() | Signal::SIGSTOP.into_sigset());
+        self.saved_sigmask = Some(self.sig_block);
+        self.sig_block = new_set;
+        self.recalc_sigpending();
+    }
+
+    /// 如果屏蔽字被临时修改过，则恢复原来的屏蔽字
+    pub fn restore_saved_sigmask(&mut self) {
+        if let Some(mask) = self.saved_sigmask.take() {
+            self.sig_block = mask;
+            self.recalc_sigpending();
         }
     }
 }
@@ -1359,6 +1577,7 @@ impl Default for ProcessSignalInfo {
             sig_block: SigSet::empty(),
             sig_pending: SigPending::default(),
             sig_shared_pending: SigPending::default(),
+            saved_sigmask: None,
             tty: None,
         }
     }
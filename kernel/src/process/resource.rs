@@ -84,6 +84,17 @@ pub struct RLimit64 {
     pub rlim_max: u64,
 }
 
+impl RLimit64 {
+    /// 表示没有限制的资源限制值
+    pub const INFINITY: u64 = u64::MAX;
+
+    /// 软限制和硬限制都是无限制
+    pub const UNLIMITED: Self = Self {
+        rlim_cur: Self::INFINITY,
+        rlim_max: Self::INFINITY,
+    };
+}
+
 /// Resource limit IDs
 ///
 /// ## Note
@@ -9,10 +9,12 @@ use system_error::SystemError;
 
 use super::{
     abi::WaitOption,
+    cred::{Cred, Kgid, Kuid, NGROUPS_MAX},
+    exec::prepare_exec_creds,
     exit::kernel_wait4,
     fork::{CloneFlags, KernelCloneArgs},
     resource::{RLimit64, RLimitID, RUsage, RUsageWho},
-    KernelStack, Pid, ProcessManager,
+    KernelStack, Pid, ProcessFlags, ProcessManager,
 };
 use crate::{
     arch::{interrupt::TrapFrame, MMArch},
@@ -24,9 +26,12 @@ use crate::{
     process::ProcessControlBlock,
     sched::completion::Completion,
     syscall::{
-        user_access::{check_and_clone_cstr, check_and_clone_cstr_array, UserBufferWriter},
+        user_access::{
+            check_and_clone_cstr, check_and_clone_cstr_array, UserBufferReader, UserBufferWriter,
+        },
         Syscall,
     },
+    time::posix_timers::exit_posix_timers,
 };
 
 impl Syscall {
@@ -79,11 +84,21 @@ impl Syscall {
             panic!("Failed to execve: {:?}", e);
         }
         let (path, argv, envp) = r.unwrap();
+        // 在销毁当前进程的地址空间之前检查权限
+        let new_cred = prepare_exec_creds(&path)?;
         ProcessManager::current_pcb()
             .basic_mut()
             .set_name(ProcessControlBlock::generate_name(&path, &argv));
 
         Self::do_execve(path, argv, envp, frame)?;
+        // 有效id与真实id不同时（例如执行了set-user-ID程序），不允许生成core文件，以免泄露特权程序的内存
+        let dumpable = new_cred.euid == new_cred.uid && new_cred.egid == new_cred.gid;
+        ProcessManager::current_pcb()
+            .flags()
+            .set(ProcessFlags::NOT_DUMPABLE, !dumpable);
+        ProcessManager::current_pcb().set_cred(new_cred);
+        // POSIX定时器不会保留到新的程序中，间隔定时器则会保留
+        exit_posix_timers();
 
         // 关闭设置了O_CLOEXEC的文件描述符
         let fd_table = ProcessManager::current_pcb().fd_table();
@@ -244,25 +259,151 @@ impl Syscall {
     }
 
     pub fn getuid() -> Result<usize, SystemError> {
-        // todo: 增加credit功能之后，需要修改
-        return Ok(0);
+        return Ok(ProcessManager::current_pcb().cred().uid.data());
     }
 
     pub fn getgid() -> Result<usize, SystemError> {
-        // todo: 增加credit功能之后，需要修改
-        return Ok(0);
+        return Ok(ProcessManager::current_pcb().cred().gid.data());
     }
 
     pub fn geteuid() -> Result<usize, SystemError> {
-        // todo: 增加credit功能之后，需要修改
-        return Ok(0);
+        return Ok(ProcessManager::current_pcb().cred().euid.data());
     }
 
     pub fn getegid() -> Result<usize, SystemError> {
-        // todo: 增加credit功能之后，需要修改
+        return Ok(ProcessManager::current_pcb().cred().egid.data());
+    }
+
+    /// 用户态传入的uid为-1时，表示不修改
+    pub(crate) fn optional_uid(uid: usize) -> Option<Kuid> {
+        if uid as u32 == u32::MAX {
+            None
+        } else {
+            Some(Kuid::new(uid as u32 as usize))
+        }
+    }
+
+    /// 用户态传入的gid为-1时，表示不修改
+    pub(crate) fn optional_gid(gid: usize) -> Option<Kgid> {
+        if gid as u32 == u32::MAX {
+            None
+        } else {
+            Some(Kgid::new(gid as u32 as usize))
+        }
+    }
+
+    /// 复制当前进程的凭证，修改之后替换当前进程的凭证
+    fn modify_cred(
+        f: impl FnOnce(&mut Cred) -> Result<(), SystemError>,
+    ) -> Result<usize, SystemError> {
+        let pcb = ProcessManager::current_pcb();
+        let old = pcb.cred();
+        let mut cred = (*old).clone();
+        f(&mut cred)?;
+        // 与linux的commit_creds相同，有效id或文件系统id改变之后不能再生成core文件
+        if cred.euid != old.euid
+            || cred.egid != old.egid
+            || cred.fsuid != old.fsuid
+            || cred.fsgid != old.fsgid
+        {
+            pcb.flags().insert(ProcessFlags::NOT_DUMPABLE);
+        }
+        pcb.set_cred(cred);
+        return Ok(0);
+    }
+
+    pub fn setuid(uid: usize) -> Result<usize, SystemError> {
+        let uid = Self::optional_uid(uid).ok_or(SystemError::EINVAL)?;
+        return Self::modify_cred(|cred| cred.setuid(uid));
+    }
+
+    pub fn setgid(gid: usize) -> Result<usize, SystemError> {
+        let gid = Self::optional_gid(gid).ok_or(SystemError::EINVAL)?;
+        return Self::modify_cred(|cred| cred.setgid(gid));
+    }
+
+    pub fn setresuid(ruid: usize, euid: usize, suid: usize) -> Result<usize, SystemError> {
+        return Self::modify_cred(|cred| {
+            cred.setresuid(
+                Self::optional_uid(ruid),
+                Self::optional_uid(euid),
+                Self::optional_uid(suid),
+            )
+        });
+    }
+
+    pub fn setresgid(rgid: usize, egid: usize, sgid: usize) -> Result<usize, SystemError> {
+        return Self::modify_cred(|cred| {
+            cred.setresgid(
+                Self::optional_gid(rgid),
+                Self::optional_gid(egid),
+                Self::optional_gid(sgid),
+            )
+        });
+    }
+
+    pub fn getresuid(ruid: *mut u32, euid: *mut u32, suid: *mut u32) -> Result<usize, SystemError> {
+        let cred = ProcessManager::current_pcb().cred();
+        for (ptr, id) in [(ruid, cred.uid), (euid, cred.euid), (suid, cred.suid)] {
+            let mut writer = UserBufferWriter::new(ptr, core::mem::size_of::<u32>(), true)?;
+            writer.copy_one_to_user(&(id.data() as u32), 0)?;
+        }
+        return Ok(0);
+    }
+
+    pub fn getresgid(rgid: *mut u32, egid: *mut u32, sgid: *mut u32) -> Result<usize, SystemError> {
+        let cred = ProcessManager::current_pcb().cred();
+        for (ptr, id) in [(rgid, cred.gid), (egid, cred.egid), (sgid, cred.sgid)] {
+            let mut writer = UserBufferWriter::new(ptr, core::mem::size_of::<u32>(), true)?;
+            writer.copy_one_to_user(&(id.data() as u32), 0)?;
+        }
         return Ok(0);
     }
 
+    /// # 设置当前进程的附加组
+    ///
+    /// ## 参数
+    ///
+    /// - `size`: 附加组的数量
+    /// - `list`: 附加组的数组
+    pub fn setgroups(size: usize, list: *const u32) -> Result<usize, SystemError> {
+        if size > NGROUPS_MAX {
+            return Err(SystemError::EINVAL);
+        }
+
+        let groups: Vec<Kgid> = if size == 0 {
+            Vec::new()
+        } else {
+            let reader = UserBufferReader::new(list, size * core::mem::size_of::<u32>(), true)?;
+            reader
+                .read_from_user::<u32>(0)?
+                .iter()
+                .map(|gid| Kgid::new(*gid as usize))
+                .collect()
+        };
+
+        return Self::modify_cred(|cred| cred.setgroups(groups));
+    }
+
+    /// # 获取当前进程的附加组
+    ///
+    /// `size`为0时，只返回附加组的数量
+    pub fn getgroups(size: usize, list: *mut u32) -> Result<usize, SystemError> {
+        let cred = ProcessManager::current_pcb().cred();
+        let count = cred.groups.len();
+        if size == 0 || count == 0 {
+            return Ok(count);
+        }
+        if size < count {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut writer = UserBufferWriter::new(list, count * core::mem::size_of::<u32>(), true)?;
+        let groups: Vec<u32> = cred.groups.iter().map(|gid| gid.data() as u32).collect();
+        writer.copy_to_user(&groups, 0)?;
+        return Ok(count);
+    }
+
     pub fn get_rusage(who: i32, rusage: *mut RUsage) -> Result<usize, SystemError> {
         let who = RUsageWho::try_from(who)?;
         let mut writer = UserBufferWriter::new(rusage, core::mem::size_of::<RUsage>(), true)?;
@@ -277,7 +418,7 @@ impl Syscall {
 
     /// # 设置资源限制
     ///
-    /// TODO: 目前暂时不支持设置资源限制，只提供读取默认值的功能
+    /// TODO: 目前只支持设置RLIMIT_CORE，其他资源只提供读取默认值的功能
     ///
     /// ## 参数
     ///
@@ -292,12 +433,15 @@ impl Syscall {
     /// - 如果old_limit不为NULL，则返回旧的资源限制到old_limit
     ///
     pub fn prlimit64(
-        _pid: Pid,
+        pid: Pid,
         resource: usize,
-        _new_limit: *const RLimit64,
+        new_limit: *const RLimit64,
         old_limit: *mut RLimit64,
     ) -> Result<usize, SystemError> {
         let resource = RLimitID::try_from(resource)?;
+        if resource == RLimitID::Core {
+            return Self::prlimit64_core(pid, new_limit, old_limit);
+        }
         let mut writer = None;
 
         if !old_limit.is_null() {
@@ -341,4 +485,48 @@ impl Syscall {
             }
         }
     }
+
+    /// # 读取或者设置进程的RLIMIT_CORE
+    ///
+    /// 提高硬限制需要root权限
+    fn prlimit64_core(
+        pid: Pid,
+        new_limit: *const RLimit64,
+        old_limit: *mut RLimit64,
+    ) -> Result<usize, SystemError> {
+        let current = ProcessManager::current_pcb();
+        let pcb = if pid.data() == 0 || pid == current.pid() {
+            current.clone()
+        } else {
+            ProcessManager::find(pid).ok_or(SystemError::ESRCH)?
+        };
+
+        let new = if new_limit.is_null() {
+            None
+        } else {
+            let reader = UserBufferReader::new(new_limit, core::mem::size_of::<RLimit64>(), true)?;
+            let new = *reader.read_one_from_user::<RLimit64>(0)?;
+            if new.rlim_cur > new.rlim_max {
+                return Err(SystemError::EINVAL);
+            }
+            Some(new)
+        };
+
+        let mut sig_struct = pcb.sig_struct_irqsave();
+        let old = sig_struct.core_rlimit;
+        if let Some(new) = new {
+            if new.rlim_max > old.rlim_max && !current.cred().can_sys_resource() {
+                return Err(SystemError::EPERM);
+            }
+            sig_struct.core_rlimit = new;
+        }
+        drop(sig_struct);
+
+        if !old_limit.is_null() {
+            let mut writer =
+                UserBufferWriter::new(old_limit, core::mem::size_of::<RLimit64>(), true)?;
+            writer.copy_one_to_user(&old, 0)?;
+        }
+        return Ok(0);
+    }
 }
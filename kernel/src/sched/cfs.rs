@@ -7,21 +7,73 @@ use crate::{
     exception::InterruptArch,
     include::bindings::bindings::MAX_CPU_NUM,
     kBUG,
-    libs::{
-        rbtree::RBTree,
-        spinlock::{SpinLock, SpinLockGuard},
-    },
+    libs::{rbtree::RBTree, spinlock::SpinLock},
     process::{
-        ProcessControlBlock, ProcessFlags, ProcessManager, ProcessSchedulerInfo, ProcessState,
+        Pid, ProcessControlBlock, ProcessFlags, ProcessManager, ProcessSchedulerInfo, ProcessState,
     },
     smp::{core::smp_get_processor_id, cpu::ProcessorId},
+    time::{
+        tick::{tick_nohz_kick_cpu, TICK_NSEC},
+        timekeeping::ktime_get_monotonic_ns,
+    },
 };
 
 use super::{
-    core::{sched_enqueue, Scheduler},
+    core::{sched_cpu_active, sched_enqueue, select_task_cpu, Scheduler, CPU_EXECUTING},
+    pelt::SchedAvg,
     SchedPriority,
 };
 
+/// nice值为0的进程的权重
+pub const NICE_0_LOAD: u64 = 1024;
+
+/// nice值-20到19对应的权重，nice值每增加1，进程获得的CPU时间大约减少10%
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/sched/core.c#11246
+#[rustfmt::skip]
+const SCHED_PRIO_TO_WEIGHT: [u64; 40] = [
+    /* -20 */ 88761, 71755, 56483, 46273, 36291,
+    /* -15 */ 29154, 23254, 18705, 14949, 11916,
+    /* -10 */ 9548, 7620, 6100, 4904, 3906,
+    /*  -5 */ 3121, 2501, 1991, 1586, 1277,
+    /*   0 */ 1024, 820, 655, 526, 423,
+    /*   5 */ 335, 272, 215, 172, 137,
+    /*  10 */ 110, 87, 70, 56, 45,
+    /*  15 */ 36, 29, 23, 18, 15,
+];
+
+/// 调度周期：队列中的进程不多时，每个可运行的进程在这段时间内至少运行一次（纳秒）
+const SCHED_LATENCY_NS: u64 = 24_000_000;
+/// 进程每次被调度之后至少运行的时间（纳秒）
+const SCHED_MIN_GRANULARITY_NS: u64 = 3_000_000;
+/// 被唤醒的进程的虚拟运行时间最多比队列的最小虚拟运行时间少这么多，避免长时间睡眠的进程独占cpu
+const SCHED_WAKEUP_CREDIT_NS: i64 = SCHED_LATENCY_NS as i64 / 2;
+/// 周期性负载均衡的间隔（时钟节拍数）
+const LOAD_BALANCE_INTERVAL: i64 = 4;
+/// 最繁忙的cpu的负载超过当前cpu的负载的百分比大于这个值时，才进行负载均衡
+const LOAD_BALANCE_IMBALANCE_PCT: u64 = 125;
+/// 一次负载均衡最多迁移的进程数量
+const LOAD_BALANCE_MAX_MIGRATE: usize = 8;
+
+/// 获取CFS进程的权重
+pub fn sched_prio_to_weight(priority: SchedPriority) -> u64 {
+    let index = (priority.data() - SchedPriority::MAX_RT_PRIO)
+        .clamp(0, SCHED_PRIO_TO_WEIGHT.len() as i32 - 1);
+    return SCHED_PRIO_TO_WEIGHT[index as usize];
+}
+
+/// 把进程实际运行的`delta_ns`纳秒换算为虚拟运行时间，权重越大，虚拟运行时间增长越慢
+#[inline]
+fn calc_delta_fair(delta_ns: u64, weight: u64) -> u64 {
+    return delta_ns * NICE_0_LOAD / weight;
+}
+
+/// 负载跟踪使用的当前时间（纳秒）
+#[inline]
+fn sched_clock() -> u64 {
+    return ktime_get_monotonic_ns().max(0) as u64;
+}
+
 /// 声明全局的cfs调度器实例
 pub static mut CFS_SCHEDULER_PTR: Option<Box<SchedulerCFS>> = None;
 
@@ -41,13 +93,116 @@ pub unsafe fn sched_cfs_init() {
     }
 }
 
+/// CFS调度实体：进程在CFS运行队列上的状态，由进程所在队列的锁和自身的锁共同保护
+///
+/// 加锁顺序：先获取运行队列的锁，再获取调度实体的锁
+#[derive(Debug)]
+pub struct SchedEntity {
+    /// 进程在运行队列的红黑树中的键，不在树中时为None
+    tree_key: Option<(i64, Pid)>,
+    /// 进程作为可运行进程被计入了哪个cpu的运行队列（正在运行的进程也被计入）
+    on_rq: Option<ProcessorId>,
+    /// 进程的负载平均值被计入了哪个cpu的运行队列
+    last_cpu: Option<ProcessorId>,
+    /// 进程被计入运行队列时的权重
+    weight: u64,
+    /// 进程的负载平均值
+    avg: SchedAvg,
+}
+
+impl SchedEntity {
+    pub const fn new() -> Self {
+        return Self {
+            tree_key: None,
+            on_rq: None,
+            last_cpu: None,
+            weight: NICE_0_LOAD,
+            avg: SchedAvg::new(),
+        };
+    }
+
+    /// 进程的负载平均值
+    pub fn load_avg(&self) -> u64 {
+        return self.avg.load_avg();
+    }
+}
+
+/// CFS队列中由自旋锁保护的部分
+#[derive(Debug)]
+struct CfsRunQueue {
+    /// 等待运行的进程，按照（虚拟运行时间，pid）排序
+    tree: RBTree<(i64, Pid), Arc<ProcessControlBlock>>,
+    /// 计入这个队列的可运行进程的数量，包括正在运行的进程
+    nr_running: usize,
+    /// 计入这个队列的可运行进程的权重之和
+    load_weight: u64,
+    /// 队列的最小虚拟运行时间，单调递增
+    min_vruntime: i64,
+    /// 队列的负载平均值
+    avg: SchedAvg,
+}
+
+impl CfsRunQueue {
+    fn new() -> Self {
+        return Self {
+            tree: RBTree::new(),
+            nr_running: 0,
+            load_weight: 0,
+            min_vruntime: 0,
+            avg: SchedAvg::new(),
+        };
+    }
+
+    /// 把队列的负载平均值更新到`now`。修改`load_weight`之前必须调用
+    fn update_load_avg(&mut self, now: u64) {
+        self.avg.update(now, self.load_weight);
+    }
+
+    /// 队列的负载：负载平均值反映最近一段时间的负载，权重之和反映刚刚加入的进程，取二者的较大值
+    fn load(&self) -> u64 {
+        return self.avg.load_avg().max(self.load_weight);
+    }
+
+    /// ## 更新队列的最小虚拟运行时间
+    ///
+    /// ## 参数
+    ///
+    /// - `curr_vruntime` 在这个队列上正在运行的CFS进程的虚拟运行时间
+    fn update_min_vruntime(&mut self, curr_vruntime: Option<i64>) {
+        let first = self.tree.get_first().map(|(key, _)| key.0);
+        let vruntime = match (first, curr_vruntime) {
+            (Some(a), Some(b)) => a.min(b),
+            (Some(a), None) | (None, Some(a)) => a,
+            (None, None) => return,
+        };
+        self.min_vruntime = self.min_vruntime.max(vruntime);
+    }
+
+    /// 把可运行的进程计入队列
+    fn account_enqueue(&mut self, se: &mut SchedEntity, cpu: ProcessorId, weight: u64) {
+        self.nr_running += 1;
+        self.load_weight += weight;
+        se.weight = weight;
+        se.on_rq = Some(cpu);
+    }
+
+    /// 把不再可运行或者被迁移的进程从队列中减去
+    fn account_dequeue(&mut self, se: &mut SchedEntity) {
+        self.nr_running -= 1;
+        self.load_weight -= se.weight;
+        se.on_rq = None;
+    }
+}
+
 /// @brief CFS队列（per-cpu的）
 #[derive(Debug)]
 struct CFSQueue {
     /// 当前cpu上执行的进程剩余的时间片
     cpu_exec_proc_jiffies: i64,
+    /// 距离下一次周期性负载均衡的时钟节拍数
+    balance_countdown: i64,
     /// 自旋锁保护的队列
-    locked_queue: SpinLock<RBTree<i64, Arc<ProcessControlBlock>>>,
+    locked_queue: SpinLock<CfsRunQueue>,
     /// 当前核心的队列专属的IDLE进程的pcb
     idle_pcb: Arc<ProcessControlBlock>,
 }
@@ -56,55 +211,19 @@ impl CFSQueue {
     pub fn new(idle_pcb: Arc<ProcessControlBlock>) -> CFSQueue {
         CFSQueue {
             cpu_exec_proc_jiffies: 0,
-            locked_queue: SpinLock::new(RBTree::new()),
+            balance_countdown: LOAD_BALANCE_INTERVAL,
+            locked_queue: SpinLock::new(CfsRunQueue::new()),
             idle_pcb,
         }
     }
 
-    /// @brief 将pcb加入队列
-    pub fn enqueue(&mut self, pcb: Arc<ProcessControlBlock>) {
+    /// 将队列中虚拟运行时间最小的pcb弹出，队列为空时返回None
+    pub fn dequeue(&mut self) -> Option<Arc<ProcessControlBlock>> {
         let mut queue = self.locked_queue.lock_irqsave();
-
-        // 如果进程是IDLE进程，那么就不加入队列
-        if pcb.pid().into() == 0 {
-            return;
-        }
-
-        queue.insert(pcb.sched_info().virtual_runtime() as i64, pcb.clone());
-    }
-
-    /// @brief 将pcb从调度队列中弹出,若队列为空，则返回IDLE进程的pcb
-    pub fn dequeue(&mut self) -> Arc<ProcessControlBlock> {
-        let res: Arc<ProcessControlBlock>;
-        let mut queue = self.locked_queue.lock_irqsave();
-        if !queue.is_empty() {
-            // 队列不为空，返回下一个要执行的pcb
-            res = queue.pop_first().unwrap().1;
-        } else {
-            // 如果队列为空，则返回IDLE进程的pcb
-            res = self.idle_pcb.clone();
-        }
-        return res;
-    }
-
-    /// @brief 获取cfs队列的最小运行时间
-    ///
-    /// @return Option<i64> 如果队列不为空，那么返回队列中，最小的虚拟运行时间；否则返回None
-    pub fn min_vruntime(
-        queue: &SpinLockGuard<RBTree<i64, Arc<ProcessControlBlock>>>,
-    ) -> Option<i64> {
-        if !queue.is_empty() {
-            return Some(queue.get_first().unwrap().1.sched_info().virtual_runtime() as i64);
-        } else {
-            return None;
-        }
-    }
-    /// 获取运行队列的长度
-    #[allow(dead_code)]
-    pub fn get_cfs_queue_size(
-        queue: &SpinLockGuard<RBTree<i64, Arc<ProcessControlBlock>>>,
-    ) -> usize {
-        return queue.len();
+        let (_, pcb) = queue.tree.pop_first()?;
+        pcb.sched_info().sched_entity().tree_key = None;
+        queue.update_min_vruntime(Some(pcb.sched_info().virtual_runtime() as i64));
+        return Some(pcb);
     }
 }
 
@@ -132,28 +251,30 @@ impl SchedulerCFS {
         return result;
     }
 
-    /// @brief 更新这个cpu上，这个进程的可执行时间。
+    /// ## 按照进程的权重在调度周期中所占的比例，设置这个cpu上的进程可以执行的时间
     #[inline]
-    fn update_cpu_exec_proc_jiffies(
-        _priority: SchedPriority,
-        cfs_queue: &mut CFSQueue,
-        is_idle: bool,
-    ) -> &mut CFSQueue {
-        // todo: 引入调度周期以及所有进程的优先权进行计算，然后设置分配给进程的可执行时间
-        if !is_idle {
-            cfs_queue.cpu_exec_proc_jiffies = 10;
-        } else {
+    fn update_cpu_exec_proc_jiffies(&mut self, pcb: &Arc<ProcessControlBlock>, cpu: ProcessorId) {
+        let cfs_queue = &mut self.cpu_queue[cpu.data() as usize];
+        if Arc::ptr_eq(pcb, &cfs_queue.idle_pcb) {
             cfs_queue.cpu_exec_proc_jiffies = 0;
+            return;
         }
 
-        return cfs_queue;
+        let weight = sched_prio_to_weight(pcb.sched_info().priority());
+        let queue = cfs_queue.locked_queue.lock_irqsave();
+        let nr_running = queue.nr_running.max(1) as u64;
+        let load_weight = queue.load_weight.max(weight);
+        drop(queue);
+
+        let period = SCHED_LATENCY_NS.max(nr_running * SCHED_MIN_GRANULARITY_NS);
+        let slice = period * weight / load_weight;
+        cfs_queue.cpu_exec_proc_jiffies = (slice / TICK_NSEC as u64).max(1) as i64;
     }
 
     /// @brief 时钟中断到来时，由sched的core模块中的函数，调用本函数，更新CFS进程的可执行时间
     pub fn timer_update_jiffies(&mut self, sched_info: &ProcessSchedulerInfo) {
-        let current_cpu_queue: &mut CFSQueue =
-            self.cpu_queue[smp_get_processor_id().data() as usize];
-        // todo: 引入调度周期以及所有进程的优先权进行计算，然后设置进程的可执行时间
+        let cpu = smp_get_processor_id();
+        let current_cpu_queue: &mut CFSQueue = self.cpu_queue[cpu.data() as usize];
 
         let mut queue = None;
         for _ in 0..10 {
@@ -165,7 +286,7 @@ impl SchedulerCFS {
         if queue.is_none() {
             return;
         }
-        let queue = queue.unwrap();
+        let mut queue = queue.unwrap();
         // 更新进程的剩余可执行时间
         current_cpu_queue.cpu_exec_proc_jiffies -= 1;
         // 时间片耗尽，标记需要被调度
@@ -174,22 +295,184 @@ impl SchedulerCFS {
                 .flags()
                 .insert(ProcessFlags::NEED_SCHEDULE);
         }
+
+        // 更新当前进程和队列的负载
+        let now = sched_clock();
+        queue.update_load_avg(now);
+        let mut se = sched_info.sched_entity();
+        // IDLE进程不计入队列，它的虚拟运行时间不参与计算队列的最小虚拟运行时间
+        let on_rq = se.on_rq == Some(cpu);
+        let weight = if on_rq {
+            se.avg.update(now, se.weight);
+            se.weight
+        } else {
+            sched_prio_to_weight(sched_info.priority())
+        };
+        drop(se);
+
+        // 按照进程的权重更新当前进程的虚拟运行时间
+        let delta = calc_delta_fair(TICK_NSEC as u64, weight);
+        sched_info.increase_virtual_runtime(delta as isize);
+        let curr_vruntime = sched_info.virtual_runtime() as i64;
+        queue.update_min_vruntime(on_rq.then_some(curr_vruntime));
         drop(queue);
+    }
 
-        // 更新当前进程的虚拟运行时间
-        sched_info.increase_virtual_runtime(1);
+    /// ## 将进程加入cpu的cfs调度队列
+    ///
+    /// ## 参数
+    ///
+    /// - `wakeup` 进程是否刚刚被唤醒。被唤醒的进程的虚拟运行时间不能比队列的最小虚拟运行时间落后太多
+    fn enqueue_task(&mut self, pcb: Arc<ProcessControlBlock>, wakeup: bool) {
+        // 如果进程是IDLE进程，那么就不加入队列
+        if pcb.pid().into() == 0 {
+            return;
+        }
+        let cpu = pcb.sched_info().on_cpu().unwrap();
+
+        // 进程的负载还计入在其他cpu的队列上时（例如进程被迁移），先从那个队列中移除
+        let se = pcb.sched_info().sched_entity();
+        let old_cpu = se.on_rq.or(se.last_cpu).filter(|old| *old != cpu);
+        drop(se);
+        if let Some(old_cpu) = old_cpu {
+            self.detach_task(old_cpu, &pcb);
+        }
+
+        let weight = sched_prio_to_weight(pcb.sched_info().priority());
+        let now = sched_clock();
+        let mut queue = self.cpu_queue[cpu.data() as usize]
+            .locked_queue
+            .lock_irqsave();
+        let mut se = pcb.sched_info().sched_entity();
+        if se.tree_key.is_some() {
+            return;
+        }
+
+        queue.update_load_avg(now);
+        if se.on_rq.is_none() {
+            // 进程从睡眠中醒来，睡眠期间的负载为0
+            if se.avg.is_new() {
+                se.avg.init(now, weight);
+            } else {
+                se.avg.update(now, 0);
+            }
+            queue.account_enqueue(&mut se, cpu, weight);
+        }
+
+        let mut vruntime = pcb.sched_info().virtual_runtime() as i64;
+        if se.last_cpu != Some(cpu) {
+            // 不同队列的虚拟运行时间没有可比性，迁移过来的进程从队列的最小虚拟运行时间开始
+            queue.avg.attach(&se.avg);
+            se.last_cpu = Some(cpu);
+            vruntime = queue.min_vruntime;
+        } else if wakeup {
+            vruntime = vruntime.max(queue.min_vruntime - SCHED_WAKEUP_CREDIT_NS);
+        }
+        pcb.sched_info().set_virtual_runtime(vruntime as isize);
+
+        let key = (vruntime, pcb.pid());
+        se.tree_key = Some(key);
+        drop(se);
+        queue.tree.insert(key, pcb);
+    }
+
+    /// ## 将进程从`cpu`的队列中完全移除，包括它在队列上的负载
+    fn detach_task(&mut self, cpu: ProcessorId, pcb: &Arc<ProcessControlBlock>) {
+        let now = sched_clock();
+        let mut queue = self.cpu_queue[cpu.data() as usize]
+            .locked_queue
+            .lock_irqsave();
+        let mut se = pcb.sched_info().sched_entity();
+        queue.update_load_avg(now);
+        if se.on_rq == Some(cpu) {
+            if let Some(key) = se.tree_key.take() {
+                queue.tree.remove(&key);
+            }
+            se.avg.update(now, se.weight);
+            queue.account_dequeue(&mut se);
+        }
+        if se.last_cpu == Some(cpu) {
+            queue.avg.detach(&se.avg);
+            se.last_cpu = None;
+        }
     }
 
-    /// @brief 将进程加入cpu的cfs调度队列，并且重设其虚拟运行时间为当前队列的最小值
+    /// @brief 将进程加入cpu的cfs调度队列，并且重设其虚拟运行时间
     pub fn enqueue_reset_vruntime(&mut self, pcb: Arc<ProcessControlBlock>) {
-        let cpu_queue = &mut self.cpu_queue[pcb.sched_info().on_cpu().unwrap().data() as usize];
-        let queue = cpu_queue.locked_queue.lock_irqsave();
-        if queue.len() > 0 {
-            pcb.sched_info()
-                .set_virtual_runtime(CFSQueue::min_vruntime(&queue).unwrap_or(0) as isize)
+        self.enqueue_task(pcb, true);
+    }
+
+    /// ## 进程不再可运行（睡眠或者退出）时调用，从它所在的队列中减去它的负载
+    ///
+    /// 调用者检查进程状态之后、本函数获取队列的锁之前，进程可能已经在其他cpu上被唤醒并重新入队，
+    /// 因此需要在持有队列的锁的情况下重新检查进程状态，进程已经可运行时不做任何事
+    pub fn dequeue_task_sleep(&mut self, pcb: &Arc<ProcessControlBlock>) {
+        self.do_dequeue(pcb, true);
+    }
+
+    /// ## 把进程从它所在的队列中移除，并减去它的负载
+    ///
+    /// ## 参数
+    ///
+    /// - `sleep`: 为true时，如果进程已经重新变为可运行的，则不移除
+    fn do_dequeue(&mut self, pcb: &Arc<ProcessControlBlock>, sleep: bool) {
+        let cpu = match pcb.sched_info().sched_entity().on_rq {
+            Some(cpu) => cpu,
+            None => return,
+        };
+        let now = sched_clock();
+        let mut queue = self.cpu_queue[cpu.data() as usize]
+            .locked_queue
+            .lock_irqsave();
+        let mut se = pcb.sched_info().sched_entity();
+        if se.on_rq != Some(cpu) {
+            return;
         }
-        drop(queue);
-        cpu_queue.enqueue(pcb);
+        // 唤醒者在设置Runnable之后才会获取队列的锁来入队，
+        // 因此这里看到的不是Runnable时，之后的入队一定发生在本次移除之后
+        if sleep && pcb.sched_info().inner_lock_read_irqsave().state() == ProcessState::Runnable {
+            return;
+        }
+        queue.update_load_avg(now);
+        if let Some(key) = se.tree_key.take() {
+            queue.tree.remove(&key);
+        }
+        se.avg.update(now, se.weight);
+        queue.account_dequeue(&mut se);
+    }
+
+    /// ## 将进程从cfs调度队列中移除
+    ///
+    /// 修改进程的调度策略之前调用
+    ///
+    /// ## 返回值
+    ///
+    /// 进程是否在等待运行的队列中
+    pub fn dequeue(&mut self, pcb: &Arc<ProcessControlBlock>) -> bool {
+        let queued = pcb.sched_info().sched_entity().tree_key.is_some();
+        self.do_dequeue(pcb, false);
+        return queued;
+    }
+
+    /// ## 进程的nice值被修改之后，更新它在队列上的权重
+    pub fn reweight_task(&mut self, pcb: &Arc<ProcessControlBlock>) {
+        let cpu = match pcb.sched_info().sched_entity().on_rq {
+            Some(cpu) => cpu,
+            None => return,
+        };
+        let weight = sched_prio_to_weight(pcb.sched_info().priority());
+        let now = sched_clock();
+        let mut queue = self.cpu_queue[cpu.data() as usize]
+            .locked_queue
+            .lock_irqsave();
+        let mut se = pcb.sched_info().sched_entity();
+        if se.on_rq != Some(cpu) {
+            return;
+        }
+        queue.update_load_avg(now);
+        se.avg.update(now, se.weight);
+        queue.load_weight = queue.load_weight - se.weight + weight;
+        se.weight = weight;
     }
 
     /// @brief 设置cpu的队列的IDLE进程的pcb
@@ -198,12 +481,183 @@ impl SchedulerCFS {
         // kdebug!("set cpu idle: id={}", cpu_id);
         self.cpu_queue[cpu_id].idle_pcb = pcb;
     }
-    /// 获取某个cpu的运行队列中的进程数
+
+    /// 获取某个cpu的运行队列中等待运行的进程数
+    #[allow(dead_code)]
     pub fn get_cfs_queue_len(&mut self, cpu_id: ProcessorId) -> usize {
         let queue = self.cpu_queue[cpu_id.data() as usize]
             .locked_queue
             .lock_irqsave();
-        return CFSQueue::get_cfs_queue_size(&queue);
+        return queue.tree.len();
+    }
+
+    /// ## 获取cpu的负载以及计入队列的可运行进程数量
+    pub fn cpu_load(&mut self, cpu: ProcessorId) -> (u64, usize) {
+        let now = sched_clock();
+        let mut queue = self.cpu_queue[cpu.data() as usize]
+            .locked_queue
+            .lock_irqsave();
+        queue.update_load_avg(now);
+        return (queue.load(), queue.nr_running);
+    }
+
+    /// ## 从队列中挑选下一个要运行的进程
+    ///
+    /// 队列为空时，先尝试从其他cpu拉取进程，仍然没有进程可以运行时返回None
+    fn pick_next_task(&mut self, cpu: ProcessorId) -> Option<Arc<ProcessControlBlock>> {
+        loop {
+            let proc = match self.cpu_queue[cpu.data() as usize].dequeue() {
+                Some(proc) => proc,
+                None => {
+                    if self.load_balance(cpu, true) > 0 {
+                        continue;
+                    }
+                    return None;
+                }
+            };
+
+            // 进程的cpu亲和性在排队期间被修改，迁移到允许的cpu上
+            if !proc.sched_info().cpu_allowed(cpu) && !proc.sched_info().is_running() {
+                let target = select_task_cpu(&proc);
+                if target != cpu {
+                    proc.sched_info().set_on_cpu(Some(target));
+                    self.enqueue_task(proc, false);
+                    tick_nohz_kick_cpu(target);
+                    continue;
+                }
+            }
+            return Some(proc);
+        }
+    }
+
+    /// ## 时钟中断中调用，每隔`LOAD_BALANCE_INTERVAL`个时钟节拍进行一次负载均衡
+    pub fn trigger_load_balance(&mut self) {
+        let cpu = smp_get_processor_id();
+        let cfs_queue = &mut self.cpu_queue[cpu.data() as usize];
+        cfs_queue.balance_countdown -= 1;
+        if cfs_queue.balance_countdown > 0 {
+            return;
+        }
+        cfs_queue.balance_countdown = LOAD_BALANCE_INTERVAL;
+
+        let current = ProcessManager::current_pcb();
+        let idle = current.pid() == Pid::new(0);
+        if self.load_balance(cpu, idle) > 0 && idle {
+            current.flags().insert(ProcessFlags::NEED_SCHEDULE);
+        }
+        self.nohz_balance_kick(cpu);
+    }
+
+    /// ## 当前cpu上有进程在等待时，唤醒一个停止了时钟节拍的空闲cpu，由它在进入调度时拉取进程
+    fn nohz_balance_kick(&mut self, this_cpu: ProcessorId) {
+        let (_, nr_running) = self.cpu_load(this_cpu);
+        if nr_running < 2 {
+            return;
+        }
+        for i in 0..MAX_CPU_NUM {
+            let cpu = ProcessorId::new(i);
+            if cpu == this_cpu || !sched_cpu_active(cpu) {
+                continue;
+            }
+            if CPU_EXECUTING.get(cpu) == Pid::new(0) && self.cpu_load(cpu).1 == 0 {
+                tick_nohz_kick_cpu(cpu);
+                return;
+            }
+        }
+    }
+
+    /// ## 从最繁忙的cpu拉取进程到`this_cpu`
+    ///
+    /// ## 参数
+    ///
+    /// - `idle` `this_cpu`是否空闲。空闲的cpu只要能拉取到进程就进行迁移
+    ///
+    /// ## 返回值
+    ///
+    /// 迁移的进程数量
+    pub fn load_balance(&mut self, this_cpu: ProcessorId, idle: bool) -> usize {
+        let (this_load, _) = self.cpu_load(this_cpu);
+
+        // 找到负载最大，并且有进程在等待运行的cpu
+        let mut busiest = None;
+        let mut busiest_load = 0;
+        for i in 0..MAX_CPU_NUM {
+            let cpu = ProcessorId::new(i);
+            if cpu == this_cpu || !sched_cpu_active(cpu) {
+                continue;
+            }
+            let (load, nr_running) = self.cpu_load(cpu);
+            if nr_running >= 2 && load > busiest_load {
+                busiest = Some(cpu);
+                busiest_load = load;
+            }
+        }
+        let busiest = match busiest {
+            Some(cpu) => cpu,
+            None => return 0,
+        };
+        if busiest_load * 100 <= this_load * LOAD_BALANCE_IMBALANCE_PCT {
+            return 0;
+        }
+
+        // 迁移负载差的一半，使两个cpu的负载接近
+        let imbalance = (busiest_load - this_load) / 2;
+        let tasks = self.detach_tasks(busiest, this_cpu, imbalance, idle);
+        let count = tasks.len();
+        for pcb in tasks {
+            pcb.sched_info().set_on_cpu(Some(this_cpu));
+            self.enqueue_task(pcb, false);
+        }
+        return count;
+    }
+
+    /// ## 从`src_cpu`的队列中取出可以迁移到`dst_cpu`的进程，总负载不超过`imbalance`
+    ///
+    /// 从虚拟运行时间最大的进程开始挑选，这些进程最近没有运行，缓存已经失效。
+    /// 正在运行或者还没有完成切换的进程，以及cpu亲和性不包含`dst_cpu`的进程不会被迁移
+    fn detach_tasks(
+        &mut self,
+        src_cpu: ProcessorId,
+        dst_cpu: ProcessorId,
+        mut imbalance: u64,
+        idle: bool,
+    ) -> Vec<Arc<ProcessControlBlock>> {
+        let now = sched_clock();
+        let mut queue = self.cpu_queue[src_cpu.data() as usize]
+            .locked_queue
+            .lock_irqsave();
+        queue.update_load_avg(now);
+
+        let mut candidates = Vec::new();
+        for (key, pcb) in queue.tree.iter().rev() {
+            if candidates.len() >= LOAD_BALANCE_MAX_MIGRATE || imbalance == 0 {
+                break;
+            }
+            if pcb.sched_info().is_running() || !pcb.sched_info().cpu_allowed(dst_cpu) {
+                continue;
+            }
+            let load = pcb.sched_info().sched_entity().load_avg();
+            // 空闲的cpu至少迁移一个进程，否则不迁移负载过大的进程，避免在两个cpu之间来回迁移
+            if load / 2 > imbalance && !(idle && candidates.is_empty()) {
+                continue;
+            }
+            imbalance = imbalance.saturating_sub(load);
+            candidates.push(*key);
+        }
+
+        let mut tasks = Vec::new();
+        for key in candidates {
+            let pcb = queue.tree.remove(&key).unwrap();
+            let mut se = pcb.sched_info().sched_entity();
+            se.tree_key = None;
+            se.avg.update(now, se.weight);
+            queue.account_dequeue(&mut se);
+            queue.avg.detach(&se.avg);
+            se.last_cpu = None;
+            drop(se);
+            tasks.push(pcb);
+        }
+        return tasks;
     }
 }
 
@@ -217,14 +671,18 @@ impl Scheduler for SchedulerCFS {
             .flags()
             .remove(ProcessFlags::NEED_SCHEDULE);
 
-        let current_cpu_id = smp_get_processor_id().data() as usize;
+        let current_cpu_id = smp_get_processor_id();
 
-        let current_cpu_queue: &mut CFSQueue = self.cpu_queue[current_cpu_id];
-
-        let proc: Arc<ProcessControlBlock> = current_cpu_queue.dequeue();
+        let proc: Arc<ProcessControlBlock> =
+            self.pick_next_task(current_cpu_id).unwrap_or_else(|| {
+                self.cpu_queue[current_cpu_id.data() as usize]
+                    .idle_pcb
+                    .clone()
+            });
 
         compiler_fence(core::sync::atomic::Ordering::SeqCst);
         // 如果当前不是running态，或者当前进程的虚拟运行时间大于等于下一个进程的，那就需要切换。
+        // 当前进程的cpu亲和性不再包含这个cpu时，也需要切换，之后会被迁移到允许的cpu上
         let state = ProcessManager::current_pcb()
             .sched_info()
             .inner_lock_read_irqsave()
@@ -232,6 +690,9 @@ impl Scheduler for SchedulerCFS {
         if (state != ProcessState::Runnable)
             || (ProcessManager::current_pcb().sched_info().virtual_runtime()
                 >= proc.sched_info().virtual_runtime())
+            || !ProcessManager::current_pcb()
+                .sched_info()
+                .cpu_allowed(current_cpu_id)
         {
             compiler_fence(core::sync::atomic::Ordering::SeqCst);
             // 本次切换由于时间片到期引发，则再次加入就绪队列，否则交由其它功能模块进行管理
@@ -241,12 +702,8 @@ impl Scheduler for SchedulerCFS {
             }
             compiler_fence(core::sync::atomic::Ordering::SeqCst);
             // 设置进程可以执行的时间
-            if current_cpu_queue.cpu_exec_proc_jiffies <= 0 {
-                SchedulerCFS::update_cpu_exec_proc_jiffies(
-                    proc.sched_info().priority(),
-                    current_cpu_queue,
-                    Arc::ptr_eq(&proc, &current_cpu_queue.idle_pcb),
-                );
+            if self.cpu_queue[current_cpu_id.data() as usize].cpu_exec_proc_jiffies <= 0 {
+                self.update_cpu_exec_proc_jiffies(&proc, current_cpu_id);
             }
 
             compiler_fence(core::sync::atomic::Ordering::SeqCst);
@@ -257,13 +714,8 @@ impl Scheduler for SchedulerCFS {
 
             // 设置进程可以执行的时间
             compiler_fence(core::sync::atomic::Ordering::SeqCst);
-            if current_cpu_queue.cpu_exec_proc_jiffies <= 0 {
-                SchedulerCFS::update_cpu_exec_proc_jiffies(
-                    ProcessManager::current_pcb().sched_info().priority(),
-                    current_cpu_queue,
-                    Arc::ptr_eq(&proc, &current_cpu_queue.idle_pcb),
-                );
-                // kdebug!("cpu:{:?}",current_cpu_id);
+            if self.cpu_queue[current_cpu_id.data() as usize].cpu_exec_proc_jiffies <= 0 {
+                self.update_cpu_exec_proc_jiffies(&ProcessManager::current_pcb(), current_cpu_id);
             }
 
             compiler_fence(core::sync::atomic::Ordering::SeqCst);
@@ -276,8 +728,6 @@ impl Scheduler for SchedulerCFS {
     }
 
     fn enqueue(&mut self, pcb: Arc<ProcessControlBlock>) {
-        let cpu_queue = &mut self.cpu_queue[pcb.sched_info().on_cpu().unwrap().data() as usize];
-
-        cpu_queue.enqueue(pcb);
+        self.enqueue_task(pcb, false);
     }
 }
@@ -1,21 +1,29 @@
 use core::{
     intrinsics::unlikely,
-    sync::atomic::{compiler_fence, Ordering},
+    sync::atomic::{compiler_fence, AtomicBool, Ordering},
 };
 
 use alloc::{sync::Arc, vec::Vec};
 
 use crate::{
+    arch::CurrentIrqArch,
+    exception::InterruptArch,
     kinfo,
     mm::percpu::PerCpu,
     process::{AtomicPid, Pid, ProcessControlBlock, ProcessFlags, ProcessManager, ProcessState},
-    smp::{core::smp_get_processor_id, cpu::ProcessorId},
+    smp::{
+        core::smp_get_processor_id,
+        cpu::{smp_cpu_manager, ProcessorId},
+    },
+    time::{
+        clocksource::HZ, itimer::itimer_account_cpu_time, tick::tick_nohz_kick_cpu, NSEC_PER_SEC,
+    },
 };
 
-use super::rt::{sched_rt_init, SchedulerRT, __get_rt_scheduler};
+use super::rt::{__get_rt_scheduler, sched_rt_init, SchedulerRT};
 use super::{
-    cfs::{sched_cfs_init, SchedulerCFS, __get_cfs_scheduler},
-    SchedPolicy,
+    cfs::{__get_cfs_scheduler, sched_cfs_init, SchedulerCFS, NICE_0_LOAD},
+    SchedPolicy, SchedPriority,
 };
 
 lazy_static! {
@@ -48,38 +56,86 @@ impl CpuExecuting {
     }
 }
 
-// 获取某个cpu的负载情况，返回当前负载，cpu_id 是获取负载的cpu的id
-// TODO:将获取负载情况调整为最近一段时间运行进程的数量
-#[allow(dead_code)]
-pub fn get_cpu_loads(cpu_id: ProcessorId) -> u32 {
-    let cfs_scheduler = __get_cfs_scheduler();
-    let rt_scheduler = __get_rt_scheduler();
-    let len_cfs = cfs_scheduler.get_cfs_queue_len(cpu_id);
-    let len_rt = rt_scheduler.rt_queue_len(cpu_id);
-    // let load_rt = rt_scheduler.get_load_list_len(cpu_id);
-    // kdebug!("this cpu_id {} is load rt {}", cpu_id, load_rt);
+/// 每个cpu是否已经完成调度相关的初始化，只有完成初始化的cpu才会被选择运行进程
+static SCHED_CPU_ACTIVE: [AtomicBool; PerCpu::MAX_CPU_NUM as usize] =
+    [const { AtomicBool::new(false) }; PerCpu::MAX_CPU_NUM as usize];
+
+/// ## 标记当前cpu已经完成调度相关的初始化，可以运行其他cpu迁移过来的进程
+pub fn sched_cpu_activate(cpu: ProcessorId) {
+    SCHED_CPU_ACTIVE[cpu.data() as usize].store(true, Ordering::SeqCst);
+}
+
+/// cpu是否可以运行进程
+pub fn sched_cpu_active(cpu: ProcessorId) -> bool {
+    return SCHED_CPU_ACTIVE
+        .get(cpu.data() as usize)
+        .map(|active| active.load(Ordering::SeqCst))
+        .unwrap_or(false);
+}
+
+/// ## 获取某个cpu的负载
+///
+/// CFS队列的负载为按照权重计算的负载平均值，每个实时进程按照nice值为0的CFS进程的权重计算
+pub fn get_cpu_loads(cpu_id: ProcessorId) -> u64 {
+    let (cfs_load, _) = __get_cfs_scheduler().cpu_load(cpu_id);
+    let len_rt = __get_rt_scheduler().rt_queue_len(cpu_id) as u64;
+
+    return cfs_load + len_rt * NICE_0_LOAD;
+}
+
+/// cpu是否正在运行IDLE进程，并且没有进程在等待运行
+fn cpu_idle(cpu: ProcessorId) -> bool {
+    return CPU_EXECUTING.get(cpu) == Pid::new(0)
+        && __get_cfs_scheduler().cpu_load(cpu).1 == 0
+        && __get_rt_scheduler().rt_queue_len(cpu) == 0;
+}
+
+/// ## 在进程的cpu亲和性允许的范围内，为进程选择运行的cpu
+///
+/// - 正在运行的进程留在当前的cpu上，等它被换下之后再由负载均衡迁移
+/// - 进程上一次运行的cpu空闲时，留在这个cpu上，它的缓存可能还有效
+/// - 否则选择负载最小的cpu，负载相同时优先选择进程上一次运行的cpu
+pub fn select_task_cpu(pcb: &Arc<ProcessControlBlock>) -> ProcessorId {
+    let cpus_allowed = pcb.sched_info().cpus_allowed();
+    let possible_cpus = smp_cpu_manager().possible_cpus();
+    let usable = |cpu: ProcessorId| -> bool {
+        cpus_allowed.get(cpu).unwrap_or(false)
+            && possible_cpus.get(cpu).unwrap_or(false)
+            && sched_cpu_active(cpu)
+    };
+
+    let prev_cpu = pcb.sched_info().on_cpu();
+    if let Some(prev_cpu) = prev_cpu {
+        if pcb.sched_info().is_running() {
+            return prev_cpu;
+        }
+        if usable(prev_cpu) && cpu_idle(prev_cpu) {
+            return prev_cpu;
+        }
+    }
+
+    let mut target: Option<(ProcessorId, u64)> = None;
+    for cpu in possible_cpus.iter_cpu().filter(|cpu| usable(*cpu)) {
+        let load = get_cpu_loads(cpu);
+        let better = match target {
+            None => true,
+            Some((_, min_load)) => load < min_load || (load == min_load && Some(cpu) == prev_cpu),
+        };
+        if better {
+            target = Some((cpu, load));
+        }
+    }
 
-    return (len_rt + len_cfs) as u32;
+    // 没有可用的cpu时（例如其他cpu还没有完成初始化），留在原来的cpu上
+    return target
+        .map(|(cpu, _)| cpu)
+        .or(prev_cpu)
+        .unwrap_or(smp_get_processor_id());
 }
-// 负载均衡
+
+/// 为进程选择运行的cpu，如果与当前所在的cpu不同，标记进程需要被迁移
 pub fn loads_balance(pcb: Arc<ProcessControlBlock>) {
-    // FIXME: 由于目前负载均衡是直接添加到目标CPU的队列中，导致会由于时序问题导致进程在两个CPU上都存在。
-    // 在调度子系统重写/改进之前，暂时只设置进程在0号CPU上运行
-    // 由于调度器问题，暂时不进行负载均衡，见issue: https://github.com/DragonOS-Community/DragonOS/issues/571
-    let min_loads_cpu_id = ProcessorId::new(0);
-
-    // 获取总的CPU数量
-    // let cpu_num = unsafe { smp_get_total_cpu() };
-    // 获取当前负载最小的CPU的id
-    // let mut min_loads = get_cpu_loads(smp_get_processor_id());
-    // for cpu_id in 0..cpu_num {
-    //     let cpu_id = ProcessorId::new(cpu_id);
-    //     let tmp_cpu_loads = get_cpu_loads(cpu_id);
-    //     if min_loads - tmp_cpu_loads > 0 {
-    //         min_loads_cpu_id = cpu_id;
-    //         min_loads = tmp_cpu_loads;
-    //     }
-    // }
+    let min_loads_cpu_id = select_task_cpu(&pcb);
 
     let pcb_cpu = pcb.sched_info().on_cpu();
     // 将当前pcb迁移到负载最小的CPU
@@ -139,6 +195,13 @@ pub fn do_sched() -> Option<Arc<ProcessControlBlock>> {
     let rt_scheduler: &mut SchedulerRT = __get_rt_scheduler();
     compiler_fence(core::sync::atomic::Ordering::SeqCst);
 
+    // 当前进程不再可运行时，从CFS队列中减去它的负载
+    let current = ProcessManager::current_pcb();
+    if current.sched_info().inner_lock_read_irqsave().state() != ProcessState::Runnable {
+        cfs_scheduler.dequeue_task_sleep(&current);
+    }
+    drop(current);
+
     let next: Arc<ProcessControlBlock>;
     match rt_scheduler.pick_next_task_rt(smp_get_processor_id()) {
         Some(p) => {
@@ -179,7 +242,8 @@ pub fn sched_enqueue(pcb: Arc<ProcessControlBlock>, mut reset_time: bool) {
 
     assert!(pcb.sched_info().on_cpu().is_some());
 
-    match pcb.sched_info().inner_lock_read_irqsave().policy() {
+    let policy = pcb.sched_info().inner_lock_read_irqsave().policy();
+    match policy {
         SchedPolicy::CFS => {
             if reset_time {
                 cfs_scheduler.enqueue_reset_vruntime(pcb.clone());
@@ -189,6 +253,66 @@ pub fn sched_enqueue(pcb: Arc<ProcessControlBlock>, mut reset_time: bool) {
         }
         SchedPolicy::FIFO | SchedPolicy::RR => rt_scheduler.enqueue(pcb.clone()),
     }
+
+    let on_cpu = pcb.sched_info().on_cpu().unwrap();
+    // 实时进程加入当前cpu的队列，并且优先级高于当前进程时，标记当前进程需要被调度
+    if policy.is_rt() && on_cpu == smp_get_processor_id() {
+        let current = ProcessManager::current_pcb();
+        if pcb.sched_info().priority() < current.sched_info().priority() {
+            current.flags().insert(ProcessFlags::NEED_SCHEDULE);
+        }
+    }
+
+    // 目标CPU空闲并且停止了时钟节拍时，需要唤醒它
+    tick_nohz_kick_cpu(on_cpu);
+}
+
+/// ## 修改进程的调度策略和优先级
+///
+/// 在队列中的进程会先出队，按照新的调度策略和优先级重新入队
+pub fn sched_setscheduler(
+    pcb: &Arc<ProcessControlBlock>,
+    policy: SchedPolicy,
+    priority: SchedPriority,
+) {
+    let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+    let rt_queued = __get_rt_scheduler().dequeue(pcb);
+    let cfs_queued = __get_cfs_scheduler().dequeue(pcb);
+    let queued = rt_queued || cfs_queued;
+
+    let mut inner = pcb.sched_info().inner_lock_write_irqsave();
+    inner.set_policy(policy);
+    pcb.sched_info().set_priority(priority);
+    drop(inner);
+
+    if queued {
+        sched_enqueue(pcb.clone(), true);
+    }
+    // 进程的优先级可能低于其他就绪的进程，重新进行调度
+    ProcessManager::current_pcb()
+        .flags()
+        .insert(ProcessFlags::NEED_SCHEDULE);
+    drop(irq_guard);
+}
+
+/// ## 修改进程的nice值
+///
+/// 实时进程只记录nice值，切换回CFS时才生效
+pub fn sched_set_nice(pcb: &Arc<ProcessControlBlock>, nice: i32) {
+    let nice = nice.clamp(SchedPriority::MIN_NICE, SchedPriority::MAX_NICE);
+    let inner = pcb.sched_info().inner_lock_write_irqsave();
+    pcb.sched_info().set_nice(nice);
+    let is_cfs = inner.policy() == SchedPolicy::CFS;
+    if is_cfs {
+        pcb.sched_info()
+            .set_priority(SchedPriority::from_nice(nice));
+    }
+    drop(inner);
+
+    if is_cfs {
+        // 更新进程在CFS队列上的权重
+        __get_cfs_scheduler().reweight_task(pcb);
+    }
 }
 
 /// 初始化进程调度器模块
@@ -207,6 +331,11 @@ pub fn sched_init() {
 #[inline(never)]
 pub fn sched_update_jiffies() {
     let binding = ProcessManager::current_pcb();
+    // 以时钟中断的间隔为单位，统计进程占用CPU的时间
+    binding
+        .sched_info()
+        .account_cpu_time(NSEC_PER_SEC as u64 / HZ);
+    itimer_account_cpu_time(&binding, NSEC_PER_SEC as u64 / HZ);
     let guard = binding.sched_info().inner_lock_try_read_irqsave(10);
     if unlikely(guard.is_none()) {
         return;
@@ -222,4 +351,6 @@ pub fn sched_update_jiffies() {
             __get_rt_scheduler().timer_update_jiffies();
         }
     }
+    // 周期性地在cpu之间迁移CFS进程
+    __get_cfs_scheduler().trigger_load_balance();
 }
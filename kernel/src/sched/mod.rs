@@ -1,11 +1,13 @@
 pub mod cfs;
 pub mod completion;
 pub mod core;
+pub mod pelt;
 pub mod rt;
 pub mod syscall;
 
+use system_error::SystemError;
+
 /// 调度策略
-#[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SchedPolicy {
     /// 完全公平调度
@@ -16,7 +18,46 @@ pub enum SchedPolicy {
     RR,
 }
 
+impl SchedPolicy {
+    /// 用户态的SCHED_OTHER（SCHED_NORMAL）
+    pub const SCHED_OTHER: i32 = 0;
+    /// 用户态的SCHED_FIFO
+    pub const SCHED_FIFO: i32 = 1;
+    /// 用户态的SCHED_RR
+    pub const SCHED_RR: i32 = 2;
+
+    /// 是否为实时调度策略
+    pub fn is_rt(&self) -> bool {
+        return matches!(self, SchedPolicy::FIFO | SchedPolicy::RR);
+    }
+
+    /// 转换为用户态使用的调度策略编号
+    pub fn to_user(&self) -> i32 {
+        match self {
+            SchedPolicy::CFS => Self::SCHED_OTHER,
+            SchedPolicy::FIFO => Self::SCHED_FIFO,
+            SchedPolicy::RR => Self::SCHED_RR,
+        }
+    }
+}
+
+impl TryFrom<i32> for SchedPolicy {
+    type Error = SystemError;
+
+    /// 从用户态的调度策略编号转换，不支持SCHED_BATCH、SCHED_IDLE和SCHED_DEADLINE
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            Self::SCHED_OTHER => Ok(SchedPolicy::CFS),
+            Self::SCHED_FIFO => Ok(SchedPolicy::FIFO),
+            Self::SCHED_RR => Ok(SchedPolicy::RR),
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+}
+
 /// 调度优先级
+///
+/// 数值越小优先级越高：`0..MAX_RT_PRIO`由实时进程使用，`MAX_RT_PRIO..=MAX`对应CFS进程的nice值-20到19
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SchedPriority(i32);
 
@@ -24,6 +65,17 @@ impl SchedPriority {
     const MIN: i32 = 0;
     const MAX: i32 = 139;
 
+    /// 实时进程使用的优先级数量
+    pub const MAX_RT_PRIO: i32 = 100;
+    /// 用户态可以设置的最高实时优先级（sched_param.sched_priority）
+    pub const MAX_USER_RT_PRIO: i32 = Self::MAX_RT_PRIO - 1;
+    /// 最小的nice值（优先级最高）
+    pub const MIN_NICE: i32 = -20;
+    /// 最大的nice值（优先级最低）
+    pub const MAX_NICE: i32 = 19;
+    /// nice值为0的进程的优先级
+    pub const DEFAULT: SchedPriority = SchedPriority(Self::MAX_RT_PRIO - Self::MIN_NICE);
+
     /// 创建一个新的调度优先级
     pub const fn new(priority: i32) -> Option<Self> {
         if Self::validate(priority) {
@@ -41,6 +93,38 @@ impl SchedPriority {
     pub fn data(&self) -> i32 {
         self.0
     }
+
+    /// 由nice值得到CFS进程的优先级，超出范围的nice值会被截断
+    pub fn from_nice(nice: i32) -> Self {
+        let nice = nice.clamp(Self::MIN_NICE, Self::MAX_NICE);
+        return Self(Self::DEFAULT.0 + nice);
+    }
+
+    /// ## 由用户态的实时优先级得到实时进程的优先级
+    ///
+    /// ## 参数
+    ///
+    /// - `rt_priority` sched_param.sched_priority，范围为1到`MAX_USER_RT_PRIO`，数值越大优先级越高
+    pub fn from_rt_priority(rt_priority: i32) -> Option<Self> {
+        if !(1..=Self::MAX_USER_RT_PRIO).contains(&rt_priority) {
+            return None;
+        }
+        return Some(Self(Self::MAX_USER_RT_PRIO - rt_priority));
+    }
+
+    /// 是否为实时进程的优先级
+    pub fn is_rt(&self) -> bool {
+        self.0 < Self::MAX_RT_PRIO
+    }
+
+    /// 获取用户态的实时优先级，CFS进程的实时优先级为0
+    pub fn rt_priority(&self) -> i32 {
+        if self.is_rt() {
+            Self::MAX_USER_RT_PRIO - self.0
+        } else {
+            0
+        }
+    }
 }
 
 pub trait SchedArch {
@@ -0,0 +1,145 @@
+//! 按实体的负载跟踪（Per-Entity Load Tracking）
+//!
+//! 把时间划分为长度为1024微秒的周期，负载平均值是各个周期的负载贡献的几何级数：
+//! 每经过一个周期，之前的贡献衰减为原来的y倍，其中y^32 = 0.5，即32个周期（约32ms）之前的负载只占一半的权重。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/sched/pelt.c
+
+/// 负载贡献衰减一半所需的周期数
+const LOAD_AVG_PERIOD: u64 = 32;
+/// 一直满负载运行时，`load_sum / weight`的最大值，即1024 * (1 + y + y^2 + ...)
+const LOAD_AVG_MAX: u64 = 47742;
+/// 一个周期的长度（微秒）
+const PELT_PERIOD_US: u64 = 1024;
+
+/// y^n * 2^32，n为0到31
+const RUNNABLE_AVG_YN_INV: [u32; LOAD_AVG_PERIOD as usize] = [
+    0xffffffff, 0xfa83b2da, 0xf5257d14, 0xefe4b99a, 0xeac0c6e6, 0xe5b906e6, 0xe0ccdeeb, 0xdbfbb796,
+    0xd744fcc9, 0xd2a81d91, 0xce248c14, 0xc9b9bd85, 0xc5672a10, 0xc12c4cc9, 0xbd08a39e, 0xb8fbaf46,
+    0xb504f333, 0xb123f581, 0xad583ee9, 0xa9a15ab4, 0xa5fed6a9, 0xa2704302, 0x9ef5325f, 0x9b8d39b9,
+    0x9837f050, 0x94f4efa8, 0x91c3d373, 0x8ea4398a, 0x8b95c1e3, 0x88980e80, 0x85aac367, 0x82cd8698,
+];
+
+/// 计算 `val * y^n`
+fn decay_load(mut val: u64, n: u64) -> u64 {
+    if n > LOAD_AVG_PERIOD * 63 {
+        return 0;
+    }
+    let mut local_n = n;
+    if local_n >= LOAD_AVG_PERIOD {
+        val >>= local_n / LOAD_AVG_PERIOD;
+        local_n %= LOAD_AVG_PERIOD;
+    }
+    return ((val as u128 * RUNNABLE_AVG_YN_INV[local_n as usize] as u128) >> 32) as u64;
+}
+
+/// ## 计算跨越了`periods`个完整周期的时间段的负载贡献
+///
+/// ## 参数
+///
+/// - `d1` 上一个未完成的周期中剩余的时间（微秒）
+/// - `d3` 当前未完成的周期中已经经过的时间（微秒）
+fn accumulate_pelt_segments(periods: u64, d1: u64, d3: u64) -> u64 {
+    // d1在periods个周期之前，需要衰减
+    let c1 = decay_load(d1, periods);
+    // 中间的periods-1个完整周期：1024 * (y + y^2 + ... + y^(periods-1))
+    let c2 = LOAD_AVG_MAX - decay_load(LOAD_AVG_MAX, periods) - PELT_PERIOD_US;
+    return c1 + c2 + d3;
+}
+
+/// 负载平均值
+///
+/// 调度实体和CFS运行队列都使用这个结构跟踪负载：调度实体的权重在可运行时为进程的权重，睡眠时为0；
+/// 运行队列的权重为其中所有可运行进程的权重之和
+#[derive(Debug, Clone, Copy)]
+pub struct SchedAvg {
+    /// 上一次更新的时间（纳秒），按照1024纳秒对齐
+    last_update_time: u64,
+    /// 带权重的负载贡献的衰减和
+    load_sum: u64,
+    /// 负载平均值，一直以权重`w`运行时趋近于`w`
+    load_avg: u64,
+    /// 当前未完成的周期中已经经过的时间（微秒）
+    period_contrib: u64,
+}
+
+impl SchedAvg {
+    pub const fn new() -> Self {
+        return Self {
+            last_update_time: 0,
+            load_sum: 0,
+            load_avg: 0,
+            period_contrib: 0,
+        };
+    }
+
+    pub fn load_avg(&self) -> u64 {
+        return self.load_avg;
+    }
+
+    /// 是否从来没有更新过
+    pub fn is_new(&self) -> bool {
+        return self.last_update_time == 0;
+    }
+
+    fn divider(&self) -> u64 {
+        return LOAD_AVG_MAX - PELT_PERIOD_US + self.period_contrib;
+    }
+
+    /// ## 初始化新进程的负载
+    ///
+    /// 新进程的负载未知，假设它一直满负载运行，避免大量新进程被放在同一个cpu上
+    pub fn init(&mut self, now: u64, weight: u64) {
+        self.last_update_time = now & !(PELT_PERIOD_US - 1);
+        self.period_contrib = 0;
+        self.load_avg = weight;
+        self.load_sum = weight * self.divider();
+    }
+
+    /// ## 把负载平均值更新到`now`
+    ///
+    /// ## 参数
+    ///
+    /// - `now` 单调时钟的时间（纳秒）
+    /// - `weight` 从上一次更新到`now`期间的权重
+    pub fn update(&mut self, now: u64, weight: u64) {
+        if now <= self.last_update_time {
+            return;
+        }
+        // 以1024纳秒（约1微秒）为单位
+        let delta_us = (now - self.last_update_time) >> 10;
+        if delta_us == 0 {
+            return;
+        }
+        self.last_update_time += delta_us << 10;
+
+        let delta = delta_us + self.period_contrib;
+        let periods = delta / PELT_PERIOD_US;
+        let contrib = if periods > 0 {
+            self.load_sum = decay_load(self.load_sum, periods);
+            let d3 = delta % PELT_PERIOD_US;
+            let contrib =
+                accumulate_pelt_segments(periods, PELT_PERIOD_US - self.period_contrib, d3);
+            self.period_contrib = d3;
+            contrib
+        } else {
+            self.period_contrib = delta;
+            delta_us
+        };
+
+        self.load_sum += weight * contrib;
+        self.load_avg = self.load_sum / self.divider();
+    }
+
+    /// 迁移到这个运行队列的调度实体，把它的负载加到队列上
+    pub fn attach(&mut self, se: &SchedAvg) {
+        self.load_avg += se.load_avg;
+        self.load_sum += se.load_avg * self.divider();
+    }
+
+    /// 从这个运行队列迁移出去的调度实体，把它的负载从队列上减去
+    pub fn detach(&mut self, se: &SchedAvg) {
+        self.load_avg = self.load_avg.saturating_sub(se.load_avg);
+        self.load_sum = self.load_sum.saturating_sub(se.load_avg * self.divider());
+    }
+}
@@ -7,12 +7,13 @@ use crate::{
     include::bindings::bindings::MAX_CPU_NUM,
     kBUG, kdebug,
     libs::spinlock::SpinLock,
-    process::{ProcessControlBlock, ProcessFlags, ProcessManager},
+    process::{ProcessControlBlock, ProcessFlags, ProcessManager, ProcessState},
     smp::cpu::ProcessorId,
+    time::tick::tick_nohz_kick_cpu,
 };
 
 use super::{
-    core::{sched_enqueue, Scheduler},
+    core::{sched_enqueue, select_task_cpu, Scheduler},
     SchedPolicy,
 };
 
@@ -82,6 +83,12 @@ impl RTQueue {
         queue.push_front(pcb);
     }
 
+    /// 从队列中移除指定的pcb，返回pcb是否在队列中
+    pub fn remove(&mut self, pcb: &Arc<ProcessControlBlock>) -> bool {
+        let mut queue = self.locked_queue.lock_irqsave();
+        return queue.extract_if(|x| Arc::ptr_eq(x, pcb)).next().is_some();
+    }
+
     #[allow(dead_code)]
     pub fn get_rt_queue_size(&mut self) -> usize {
         let queue = self.locked_queue.lock_irqsave();
@@ -128,12 +135,24 @@ impl SchedulerRT {
     pub fn pick_next_task_rt(&mut self, cpu_id: ProcessorId) -> Option<Arc<ProcessControlBlock>> {
         // 循环查找，直到找到
         // 这里应该是优先级数量，而不是CPU数量，需要修改
-        for i in 0..SchedulerRT::MAX_RT_PRIO {
+        let mut i = 0;
+        while i < SchedulerRT::MAX_RT_PRIO {
             let cpu_queue_i: &mut RTQueue = self.cpu_queue[cpu_id.data() as usize][i as usize];
             let proc: Option<Arc<ProcessControlBlock>> = cpu_queue_i.dequeue();
-            if proc.is_some() {
-                return proc;
+            if let Some(proc) = proc {
+                // 进程的cpu亲和性在排队期间被修改，迁移到允许的cpu上
+                if !proc.sched_info().cpu_allowed(cpu_id) && !proc.sched_info().is_running() {
+                    let target = select_task_cpu(&proc);
+                    if target != cpu_id {
+                        proc.sched_info().set_on_cpu(Some(target));
+                        self.enqueue(proc);
+                        tick_nohz_kick_cpu(target);
+                        continue;
+                    }
+                }
+                return Some(proc);
             }
+            i += 1;
         }
         // return 一个空值
         None
@@ -160,6 +179,25 @@ impl SchedulerRT {
         self.cpu_queue[cpu_id][priority].enqueue_front(pcb);
     }
 
+    /// ## 将进程从它所在cpu的rt队列中移除
+    ///
+    /// 修改进程的调度策略或优先级之前调用，修改完成之后再重新加入队列
+    ///
+    /// ## 返回值
+    ///
+    /// 进程是否在队列中
+    pub fn dequeue(&mut self, pcb: &Arc<ProcessControlBlock>) -> bool {
+        let cpu_id = match pcb.sched_info().on_cpu() {
+            Some(cpu_id) => cpu_id,
+            None => return false,
+        };
+        let priority = pcb.sched_info().priority();
+        if !priority.is_rt() {
+            return false;
+        }
+        return self.cpu_queue[cpu_id.data() as usize][priority.data() as usize].remove(pcb);
+    }
+
     pub fn timer_update_jiffies(&self) {
         ProcessManager::current_pcb()
             .sched_info()
@@ -180,12 +218,22 @@ impl Scheduler for SchedulerRT {
             self.pick_next_task_rt(cpu_id).expect("No RT process found");
         let priority = proc.sched_info().priority();
         let policy = proc.sched_info().inner_lock_read_irqsave().policy();
+        // 优先级的数值越小，优先级越高。
+        // 当前进程不再可运行、不能在这个cpu上运行，或者不是实时进程时，必须让出cpu
+        let current = ProcessManager::current_pcb();
+        let current_inner = current.sched_info().inner_lock_read_irqsave();
+        let must_switch = current_inner.state() != ProcessState::Runnable
+            || !current_inner.policy().is_rt()
+            || !current.sched_info().cpu_allowed(cpu_id);
+        drop(current_inner);
+        drop(current);
         match policy {
             // 如果是fifo策略，则可以一直占有cpu直到有优先级更高的任务就绪(即使优先级相同也不行)或者主动放弃(等待资源)
             SchedPolicy::FIFO => {
-                // 如果挑选的进程优先级小于当前进程，则不进行切换
-                if proc.sched_info().priority()
-                    <= ProcessManager::current_pcb().sched_info().priority()
+                // 如果挑选的进程优先级不高于当前进程，则不进行切换
+                if !must_switch
+                    && proc.sched_info().priority()
+                        >= ProcessManager::current_pcb().sched_info().priority()
                 {
                     sched_enqueue(proc, false);
                 } else {
@@ -199,8 +247,9 @@ impl Scheduler for SchedulerRT {
             // RR调度策略需要考虑时间片
             SchedPolicy::RR => {
                 // 同等优先级的，考虑切换
-                if proc.sched_info().priority()
-                    >= ProcessManager::current_pcb().sched_info().priority()
+                if must_switch
+                    || proc.sched_info().priority()
+                        <= ProcessManager::current_pcb().sched_info().priority()
                 {
                     // 判断这个进程时间片是否耗尽，若耗尽则将其时间片赋初值然后入队
                     if proc.sched_info().rt_time_slice() <= 0 {
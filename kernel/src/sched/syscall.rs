@@ -1,11 +1,158 @@
+use alloc::{sync::Arc, vec::Vec};
 use system_error::SystemError;
 
 use crate::{
-    arch::CurrentIrqArch, exception::InterruptArch, process::ProcessManager,
-    smp::core::smp_get_processor_id, syscall::Syscall,
+    arch::{sched::sched, CurrentIrqArch},
+    exception::InterruptArch,
+    libs::cpumask::CpuMask,
+    mm::percpu::PerCpu,
+    process::{cred::Kuid, Pid, ProcessControlBlock, ProcessManager},
+    smp::{
+        core::smp_get_processor_id,
+        cpu::{smp_cpu_manager, ProcessorId},
+    },
+    syscall::{
+        user_access::{UserBufferReader, UserBufferWriter},
+        Syscall,
+    },
+    time::tick::tick_nohz_idle_exit,
 };
 
-use super::core::{do_sched, CPU_EXECUTING};
+use super::{
+    core::{do_sched, sched_set_nice, sched_setscheduler, CPU_EXECUTING},
+    SchedPolicy, SchedPriority,
+};
+
+/// setpriority/getpriority的which参数：who为进程id
+pub const PRIO_PROCESS: i32 = 0;
+/// setpriority/getpriority的which参数：who为进程组id
+pub const PRIO_PGRP: i32 = 1;
+/// setpriority/getpriority的which参数：who为用户id
+pub const PRIO_USER: i32 = 2;
+
+/// cpu亲和性掩码的字节数
+const CPUMASK_SIZE: usize = PerCpu::MAX_CPU_NUM as usize / 8;
+
+/// sched_setscheduler等系统调用使用的调度参数，对应Linux的`struct sched_param`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SchedParam {
+    pub sched_priority: i32,
+}
+
+/// 根据pid查找进程，pid为0时表示当前进程
+fn sched_find_process(pid: i32) -> Result<Arc<ProcessControlBlock>, SystemError> {
+    if pid < 0 {
+        return Err(SystemError::EINVAL);
+    }
+    if pid == 0 {
+        return Ok(ProcessManager::current_pcb());
+    }
+    return ProcessManager::find(Pid::new(pid as usize)).ok_or(SystemError::ESRCH);
+}
+
+/// 当前进程是否可以修改`pcb`的调度属性：有权限的用户可以修改任意进程，其他用户只能修改属于自己的进程
+fn sched_check_permission(pcb: &Arc<ProcessControlBlock>) -> Result<(), SystemError> {
+    let cred = ProcessManager::current_pcb().cred();
+    if cred.can_nice() {
+        return Ok(());
+    }
+    let target = pcb.cred();
+    if cred.euid == target.uid || cred.euid == target.euid {
+        return Ok(());
+    }
+    return Err(SystemError::EPERM);
+}
+
+/// ## 修改进程的调度策略和优先级
+///
+/// ## 参数
+///
+/// - `policy` 新的调度策略，为None时保持原来的调度策略
+fn do_sched_setscheduler(
+    pid: i32,
+    policy: Option<SchedPolicy>,
+    param: *const SchedParam,
+) -> Result<usize, SystemError> {
+    if param.is_null() {
+        return Err(SystemError::EINVAL);
+    }
+    let reader = UserBufferReader::new(param, core::mem::size_of::<SchedParam>(), true)?;
+    let param = reader.read_one_from_user::<SchedParam>(0)?.sched_priority;
+
+    let pcb = sched_find_process(pid)?;
+    let policy = policy.unwrap_or_else(|| pcb.sched_info().inner_lock_read_irqsave().policy());
+
+    // 实时进程的优先级为1到MAX_USER_RT_PRIO，CFS进程的优先级必须为0
+    let priority = if policy.is_rt() {
+        SchedPriority::from_rt_priority(param).ok_or(SystemError::EINVAL)?
+    } else if param == 0 {
+        SchedPriority::from_nice(pcb.sched_info().nice())
+    } else {
+        return Err(SystemError::EINVAL);
+    };
+
+    sched_check_permission(&pcb)?;
+    // 没有权限时，只能降低实时进程的优先级或者把它切换为CFS进程
+    if policy.is_rt() && !ProcessManager::current_pcb().cred().can_nice() {
+        let old = pcb.sched_info().priority();
+        if !old.is_rt() || priority < old {
+            return Err(SystemError::EPERM);
+        }
+    }
+
+    sched_setscheduler(&pcb, policy, priority);
+    return Ok(0);
+}
+
+/// ## 设置`pcb`的nice值
+///
+/// 降低nice值（提高优先级）需要权限
+fn set_one_prio(pcb: &Arc<ProcessControlBlock>, nice: i32) -> Result<(), SystemError> {
+    let nice = nice.clamp(SchedPriority::MIN_NICE, SchedPriority::MAX_NICE);
+    sched_check_permission(pcb)?;
+    if nice < pcb.sched_info().nice() && !ProcessManager::current_pcb().cred().can_nice() {
+        return Err(SystemError::EACCES);
+    }
+    sched_set_nice(pcb, nice);
+    return Ok(());
+}
+
+/// 获取setpriority/getpriority的目标进程
+fn prio_targets(which: i32, who: i32) -> Result<Vec<Arc<ProcessControlBlock>>, SystemError> {
+    if who < 0 {
+        return Err(SystemError::ESRCH);
+    }
+    let current = ProcessManager::current_pcb();
+    let targets = match which {
+        PRIO_PROCESS => {
+            let pcb = if who == 0 {
+                Some(current)
+            } else {
+                ProcessManager::find(Pid::new(who as usize))
+            };
+            pcb.into_iter().collect()
+        }
+        PRIO_PGRP => {
+            let pgid = if who == 0 {
+                current.basic().pgid()
+            } else {
+                Pid::new(who as usize)
+            };
+            ProcessManager::filter(|pcb| pcb.basic().pgid() == pgid)
+        }
+        PRIO_USER => {
+            let uid = if who == 0 {
+                current.cred().uid
+            } else {
+                Kuid::new(who as usize)
+            };
+            ProcessManager::filter(|pcb| pcb.cred().uid == uid)
+        }
+        _ => return Err(SystemError::EINVAL),
+    };
+    return Ok(targets);
+}
 
 impl Syscall {
     /// @brief 让系统立即运行调度器的系统调用
@@ -26,7 +173,13 @@ impl Syscall {
             let current_pcb = ProcessManager::current_pcb();
             // kdebug!("sched: current_pcb: {:?}, next_pcb: {:?}\n", current_pcb, next_pcb);
             if current_pcb.pid() != next_pcb.pid() {
+                // 离开idle进程时，重新开始产生时钟节拍
+                if current_pcb.pid() == Pid::new(0) {
+                    tick_nohz_idle_exit();
+                }
                 CPU_EXECUTING.set(smp_get_processor_id(), next_pcb.pid());
+                // 切换完成之前，next进程不能被其他cpu的负载均衡迁移
+                next_pcb.sched_info().set_running(true);
                 unsafe { ProcessManager::switch_process(current_pcb, next_pcb) };
             }
         }
@@ -37,4 +190,178 @@ impl Syscall {
     pub fn sched_yield() -> Result<usize, SystemError> {
         return Syscall::sched(false);
     }
+
+    /// ## 设置进程的调度策略和优先级
+    ///
+    /// ## 参数
+    ///
+    /// - `pid` 目标进程，为0时表示当前进程
+    /// - `policy` SCHED_OTHER、SCHED_FIFO或SCHED_RR
+    /// - `param` 实时进程的优先级，CFS进程必须为0
+    pub fn sched_setscheduler(
+        pid: i32,
+        policy: i32,
+        param: *const SchedParam,
+    ) -> Result<usize, SystemError> {
+        if pid < 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let policy = SchedPolicy::try_from(policy)?;
+        return do_sched_setscheduler(pid, Some(policy), param);
+    }
+
+    /// ## 获取进程的调度策略
+    pub fn sched_getscheduler(pid: i32) -> Result<usize, SystemError> {
+        let pcb = sched_find_process(pid)?;
+        let policy = pcb.sched_info().inner_lock_read_irqsave().policy();
+        return Ok(policy.to_user() as usize);
+    }
+
+    /// ## 在不改变调度策略的情况下，设置进程的优先级
+    pub fn sched_setparam(pid: i32, param: *const SchedParam) -> Result<usize, SystemError> {
+        return do_sched_setscheduler(pid, None, param);
+    }
+
+    /// ## 获取进程的调度参数，CFS进程的优先级为0
+    pub fn sched_getparam(pid: i32, param: *mut SchedParam) -> Result<usize, SystemError> {
+        if param.is_null() {
+            return Err(SystemError::EINVAL);
+        }
+        let pcb = sched_find_process(pid)?;
+        let param_value = SchedParam {
+            sched_priority: pcb.sched_info().priority().rt_priority(),
+        };
+        let mut writer = UserBufferWriter::new(param, core::mem::size_of::<SchedParam>(), true)?;
+        writer.copy_one_to_user(&param_value, 0)?;
+        return Ok(0);
+    }
+
+    /// ## 获取调度策略支持的最高优先级
+    pub fn sched_get_priority_max(policy: i32) -> Result<usize, SystemError> {
+        let policy = SchedPolicy::try_from(policy)?;
+        if policy.is_rt() {
+            return Ok(SchedPriority::MAX_USER_RT_PRIO as usize);
+        }
+        return Ok(0);
+    }
+
+    /// ## 获取调度策略支持的最低优先级
+    pub fn sched_get_priority_min(policy: i32) -> Result<usize, SystemError> {
+        let policy = SchedPolicy::try_from(policy)?;
+        if policy.is_rt() {
+            return Ok(1);
+        }
+        return Ok(0);
+    }
+
+    /// ## 设置进程、进程组或者用户的所有进程的nice值
+    ///
+    /// ## 参数
+    ///
+    /// - `which` PRIO_PROCESS、PRIO_PGRP或PRIO_USER
+    /// - `who` 进程id、进程组id或用户id，为0时分别表示当前进程、当前进程组和当前用户
+    /// - `niceval` 新的nice值，超出-20到19的值会被截断
+    pub fn setpriority(which: i32, who: i32, niceval: i32) -> Result<usize, SystemError> {
+        let targets = prio_targets(which, who)?;
+        if targets.is_empty() {
+            return Err(SystemError::ESRCH);
+        }
+
+        let mut result = Ok(0);
+        for pcb in targets.iter() {
+            if let Err(e) = set_one_prio(pcb, niceval) {
+                result = Err(e);
+            }
+        }
+        return result;
+    }
+
+    /// ## 获取进程、进程组或者用户的所有进程中最高的优先级
+    ///
+    /// ## 返回值
+    ///
+    /// 与Linux的系统调用相同，返回`20 - nice`，范围为1到40，由libc转换为nice值
+    pub fn getpriority(which: i32, who: i32) -> Result<usize, SystemError> {
+        let targets = prio_targets(which, who)?;
+        let nice = targets
+            .iter()
+            .map(|pcb| pcb.sched_info().nice())
+            .min()
+            .ok_or(SystemError::ESRCH)?;
+        return Ok((20 - nice) as usize);
+    }
+
+    /// ## 设置进程的cpu亲和性
+    ///
+    /// ## 参数
+    ///
+    /// - `pid` 目标进程，为0时表示当前进程
+    /// - `len` 用户态cpu掩码的字节数
+    /// - `user_mask` 用户态的cpu掩码，超出内核支持的cpu数量的部分被忽略
+    pub fn sched_setaffinity(
+        pid: i32,
+        len: usize,
+        user_mask: *const u8,
+    ) -> Result<usize, SystemError> {
+        let pcb = sched_find_process(pid)?;
+        let len = len.min(CPUMASK_SIZE);
+        let reader = UserBufferReader::new(user_mask, len, true)?;
+        let bytes = reader.read_from_user::<u8>(0)?;
+
+        // 只保留系统中存在的cpu
+        let possible_cpus = smp_cpu_manager().possible_cpus();
+        let mut mask = CpuMask::new();
+        for (i, byte) in bytes.iter().enumerate() {
+            for bit in 0..8 {
+                let cpu = ProcessorId::new((i * 8 + bit) as u32);
+                if byte & (1 << bit) != 0 && possible_cpus.get(cpu).unwrap_or(false) {
+                    mask.set(cpu, true);
+                }
+            }
+        }
+        if mask.is_empty() {
+            return Err(SystemError::EINVAL);
+        }
+
+        sched_check_permission(&pcb)?;
+        pcb.sched_info().set_cpus_allowed(mask);
+
+        // 当前进程不能继续在这个cpu上运行时，立即让出cpu，重新入队时会被迁移到允许的cpu上
+        if Arc::ptr_eq(&pcb, &ProcessManager::current_pcb())
+            && !pcb.sched_info().cpu_allowed(smp_get_processor_id())
+        {
+            sched();
+        }
+        return Ok(0);
+    }
+
+    /// ## 获取进程的cpu亲和性
+    ///
+    /// ## 返回值
+    ///
+    /// 写入用户态的cpu掩码的字节数
+    pub fn sched_getaffinity(
+        pid: i32,
+        len: usize,
+        user_mask: *mut u8,
+    ) -> Result<usize, SystemError> {
+        // 用户态的缓冲区需要能够容纳所有cpu，并且按照unsigned long对齐
+        if len < CPUMASK_SIZE || len & (core::mem::size_of::<usize>() - 1) != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let pcb = sched_find_process(pid)?;
+
+        let possible_cpus = smp_cpu_manager().possible_cpus();
+        let mut bytes = [0u8; CPUMASK_SIZE];
+        for cpu in pcb.sched_info().cpus_allowed().iter_cpu() {
+            if possible_cpus.get(cpu).unwrap_or(false) {
+                let index = cpu.data() as usize;
+                bytes[index / 8] |= 1 << (index % 8);
+            }
+        }
+
+        let mut writer = UserBufferWriter::new(user_mask, CPUMASK_SIZE, true)?;
+        writer.copy_to_user(&bytes, 0)?;
+        return Ok(CPUMASK_SIZE);
+    }
 }
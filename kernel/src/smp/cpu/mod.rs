@@ -42,7 +42,6 @@ impl SmpCpuManager {
     }
 
     /// 获取可用的CPU
-    #[allow(dead_code)]
     pub fn possible_cpus(&self) -> &CpuMask {
         &self.possible_cpus
     }
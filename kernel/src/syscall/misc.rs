@@ -1,10 +1,11 @@
-use alloc::vec::Vec;
 use system_error::SystemError;
 
 use crate::{
-    arch::{mm::LockedFrameAllocator, rand::rand},
-    libs::rand::GRandFlags,
+    arch::mm::LockedFrameAllocator,
+    filesystem::vfs::syscall::ModeType,
+    libs::rand::{crng_ready, get_random_bytes, wait_for_random_bytes, GRandFlags},
     mm::allocator::page_frame::FrameAllocator,
+    process::ProcessManager,
 };
 
 use super::{user_access::UserBufferWriter, Syscall};
@@ -57,32 +58,47 @@ impl Syscall {
         return Ok(0);
     }
 
-    pub fn umask(_mask: u32) -> Result<usize, SystemError> {
-        kwarn!("SYS_UMASK has not yet been implemented\n");
-        return Ok(0o777);
+    /// ## 设置当前进程的权限掩码
+    ///
+    /// ## 返回值
+    ///
+    /// 返回原来的权限掩码
+    pub fn umask(mask: u32) -> Result<usize, SystemError> {
+        let pcb = ProcessManager::current_pcb();
+        let mut basic = pcb.basic_mut();
+        let old = basic.umask();
+        basic.set_umask(mask & ModeType::S_IRWXUGO.bits());
+        return Ok(old as usize);
     }
 
     /// ## 将随机字节填入buf
     ///
-    /// ### 该系统调用与linux不一致，因为目前没有其他随机源
+    /// - 默认情况下（以及指定了GRND_RANDOM时），在CRNG获得足够的熵之前会阻塞
+    /// - 指定了GRND_NONBLOCK时，CRNG还没有获得足够的熵则返回EAGAIN
+    /// - 指定了GRND_INSECURE时，永远不会阻塞
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/drivers/char/random.c
     pub fn get_random(buf: *mut u8, len: usize, flags: GRandFlags) -> Result<usize, SystemError> {
-        if flags.bits() == (GRandFlags::GRND_INSECURE.bits() | GRandFlags::GRND_RANDOM.bits()) {
+        if flags.contains(GRandFlags::GRND_INSECURE | GRandFlags::GRND_RANDOM) {
             return Err(SystemError::EINVAL);
         }
 
-        let mut writer = UserBufferWriter::new(buf, len, true)?;
+        // 与linux一样，一次最多读取i32::MAX个字节
+        let len = len.min(i32::MAX as usize);
 
-        let mut ret = Vec::new();
-        let mut count = 0;
-        while count < len {
-            let rand = rand();
-            for offset in 0..4 {
-                ret.push((rand >> (offset * 2)) as u8);
-                count += 1;
+        if !crng_ready() && !flags.contains(GRandFlags::GRND_INSECURE) {
+            if flags.contains(GRandFlags::GRND_NONBLOCK) {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
             }
+            wait_for_random_bytes()?;
         }
 
-        writer.copy_to_user(&ret, 0)?;
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let mut writer = UserBufferWriter::new(buf, len, true)?;
+        get_random_bytes(writer.buffer::<u8>(0)?);
         Ok(len)
     }
 }
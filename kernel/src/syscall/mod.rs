@@ -6,6 +6,7 @@ use core::{
 use crate::{
     arch::{ipc::signal::SigSet, syscall::nr::*},
     driver::base::device::device_number::DeviceNumber,
+    ipc::signal_types::SigInfo,
     libs::{futex::constant::FutexFlag, rand::GRandFlags},
     mm::syscall::MremapFlags,
     net::syscall::MsgHdr,
@@ -14,6 +15,7 @@ use crate::{
         resource::{RLimit64, RUsage},
         ProcessManager,
     },
+    sched::syscall::SchedParam,
     syscall::user_access::check_and_clone_cstr,
 };
 
@@ -23,11 +25,14 @@ use system_error::SystemError;
 use crate::{
     arch::{cpu::cpu_reset, interrupt::TrapFrame, MMArch},
     driver::base::block::SeekFrom,
-    filesystem::vfs::{
-        fcntl::FcntlCommand,
-        file::FileMode,
-        syscall::{ModeType, PosixKstat, SEEK_CUR, SEEK_END, SEEK_MAX, SEEK_SET},
-        MAX_PATHLEN,
+    filesystem::{
+        poll::{syscall::PSelectSigData, PollFd},
+        vfs::{
+            fcntl::FcntlCommand,
+            file::FileMode,
+            syscall::{ModeType, PosixKstat, SEEK_CUR, SEEK_END, SEEK_MAX, SEEK_SET},
+            MAX_PATHLEN,
+        },
     },
     include::bindings::bindings::{PAGE_2M_SIZE, PAGE_4K_SIZE},
     kinfo,
@@ -36,6 +41,8 @@ use crate::{
     net::syscall::SockAddr,
     process::{fork::CloneFlags, Pid},
     time::{
+        itimer::{ITimerSpec, ITimerVal},
+        posix_timers::PosixSigEvent,
         syscall::{PosixTimeZone, PosixTimeval},
         TimeSpec,
     },
@@ -411,11 +418,88 @@ impl Syscall {
                 Self::rmdir(pathname)
             }
 
+            SYS_MOUNT => {
+                let source = args[0] as *const u8;
+                let target = args[1] as *const u8;
+                let fs_type = args[2] as *const u8;
+                let flags = args[3] as u32;
+                let data = args[4] as *const u8;
+                Self::mount(source, target, fs_type, flags, data)
+            }
+
+            SYS_UMOUNT2 => {
+                let target = args[0] as *const u8;
+                let flags = args[1] as u32;
+                Self::umount2(target, flags)
+            }
+
             #[cfg(target_arch = "x86_64")]
             SYS_UNLINK => {
                 let pathname = args[0] as *const u8;
                 Self::unlink(pathname)
             }
+
+            #[cfg(target_arch = "x86_64")]
+            SYS_RENAME => {
+                let oldpath = args[0] as *const u8;
+                let newpath = args[1] as *const u8;
+                Self::rename(oldpath, newpath)
+            }
+
+            #[cfg(target_arch = "x86_64")]
+            SYS_RENAMEAT => {
+                let olddirfd = args[0] as i32;
+                let oldpath = args[1] as *const u8;
+                let newdirfd = args[2] as i32;
+                let newpath = args[3] as *const u8;
+                Self::renameat(olddirfd, oldpath, newdirfd, newpath)
+            }
+
+            SYS_RENAMEAT2 => {
+                let olddirfd = args[0] as i32;
+                let oldpath = args[1] as *const u8;
+                let newdirfd = args[2] as i32;
+                let newpath = args[3] as *const u8;
+                let flags = args[4] as u32;
+                Self::renameat2(olddirfd, oldpath, newdirfd, newpath, flags)
+            }
+
+            #[cfg(target_arch = "x86_64")]
+            SYS_SYMLINK => {
+                let target = args[0] as *const u8;
+                let linkpath = args[1] as *const u8;
+                Self::symlink(target, linkpath)
+            }
+
+            SYS_MKDIRAT => {
+                let dirfd = args[0] as i32;
+                let path = args[1] as *const u8;
+                let mode = args[2] as u32;
+                Self::mkdirat(dirfd, path, mode)
+            }
+
+            SYS_SYMLINKAT => {
+                let target = args[0] as *const u8;
+                let newdirfd = args[1] as i32;
+                let linkpath = args[2] as *const u8;
+                Self::symlinkat(target, newdirfd, linkpath)
+            }
+
+            #[cfg(target_arch = "x86_64")]
+            SYS_LINK => {
+                let oldpath = args[0] as *const u8;
+                let newpath = args[1] as *const u8;
+                Self::link(oldpath, newpath)
+            }
+
+            SYS_LINKAT => {
+                let olddirfd = args[0] as i32;
+                let oldpath = args[1] as *const u8;
+                let newdirfd = args[2] as i32;
+                let newpath = args[3] as *const u8;
+                let flags = args[4] as u32;
+                Self::linkat(olddirfd, oldpath, newdirfd, newpath, flags)
+            }
             SYS_KILL => {
                 let pid = Pid::new(args[0]);
                 let sig = args[1] as c_int;
@@ -569,6 +653,16 @@ impl Syscall {
                 }
             }
 
+            SYS_SENDMSG => {
+                let msg = args[1] as *const MsgHdr;
+                let flags = args[2] as u32;
+
+                let user_buffer_reader =
+                    UserBufferReader::new(msg, core::mem::size_of::<MsgHdr>(), frame.from_user())?;
+                let msg = user_buffer_reader.read_one_from_user::<MsgHdr>(0)?;
+                Self::sendmsg(args[0], msg, flags)
+            }
+
             SYS_RECVMSG => {
                 let msg = args[1] as *mut MsgHdr;
                 let flags = args[2] as u32;
@@ -636,6 +730,16 @@ impl Syscall {
                     Self::munmap(VirtAddr::new(addr), len)
                 }
             }
+            SYS_MSYNC => {
+                let addr = args[0];
+                let len = page_align_up(args[1]);
+                if addr & (MMArch::PAGE_SIZE - 1) != 0 {
+                    // The addr argument is not a multiple of the page size
+                    Err(SystemError::EINVAL)
+                } else {
+                    Self::msync(VirtAddr::new(addr), len, args[2])
+                }
+            }
             SYS_MPROTECT => {
                 let addr = args[0];
                 let len = page_align_up(args[1]);
@@ -795,6 +899,18 @@ impl Syscall {
                 res
             }
 
+            SYS_NEWFSTATAT => {
+                let dirfd = args[0] as i32;
+                let path = args[1] as *const u8;
+                let kstat = args[2] as *mut PosixKstat;
+                let flags = args[3] as u32;
+                verify_area(
+                    VirtAddr::new(kstat as usize),
+                    core::mem::size_of::<PosixKstat>(),
+                )?;
+                Self::newfstatat(dirfd, path, kstat, flags)
+            }
+
             SYS_EPOLL_CREATE => Self::epoll_create(args[0] as i32),
             SYS_EPOLL_CREATE1 => Self::epoll_create1(args[0]),
 
@@ -853,8 +969,38 @@ impl Syscall {
 
             #[cfg(target_arch = "x86_64")]
             SYS_POLL => {
-                kwarn!("SYS_POLL has not yet been implemented");
-                Ok(0)
+                let fds = args[0] as *mut PollFd;
+                let nfds = args[1] as u32;
+                let timeout = args[2] as i32;
+                Self::poll(fds, nfds, timeout)
+            }
+
+            SYS_PPOLL => {
+                let fds = args[0] as *mut PollFd;
+                let nfds = args[1] as u32;
+                let tmo_p = args[2] as *mut TimeSpec;
+                let sigmask = args[3] as *const SigSet;
+                Self::ppoll(fds, nfds, tmo_p, sigmask, args[4])
+            }
+
+            #[cfg(target_arch = "x86_64")]
+            SYS_SELECT => {
+                let nfds = args[0] as i32;
+                let readfds = args[1] as *mut u64;
+                let writefds = args[2] as *mut u64;
+                let exceptfds = args[3] as *mut u64;
+                let timeout = args[4] as *mut PosixTimeval;
+                Self::select(nfds, readfds, writefds, exceptfds, timeout)
+            }
+
+            SYS_PSELECT6 => {
+                let nfds = args[0] as i32;
+                let readfds = args[1] as *mut u64;
+                let writefds = args[2] as *mut u64;
+                let exceptfds = args[3] as *mut u64;
+                let timeout = args[4] as *mut TimeSpec;
+                let sig = args[5] as *const PSelectSigData;
+                Self::pselect6(nfds, readfds, writefds, exceptfds, timeout, sig)
             }
 
             SYS_SETPGID => {
@@ -863,8 +1009,42 @@ impl Syscall {
             }
 
             SYS_RT_SIGPROCMASK => {
-                kwarn!("SYS_RT_SIGPROCMASK has not yet been implemented");
-                Ok(0)
+                let how = args[0] as c_int;
+                let nset = args[1] as *const SigSet;
+                let oset = args[2] as *mut SigSet;
+                Self::rt_sigprocmask(how, nset, oset, args[3])
+            }
+
+            SYS_RT_SIGPENDING => Self::rt_sigpending(args[0] as *mut SigSet, args[1]),
+
+            SYS_RT_SIGSUSPEND => Self::rt_sigsuspend(args[0] as *const SigSet, args[1]),
+
+            SYS_RT_SIGTIMEDWAIT => {
+                let uthese = args[0] as *const SigSet;
+                let uinfo = args[1] as *mut SigInfo;
+                let uts = args[2] as *const TimeSpec;
+                Self::rt_sigtimedwait(uthese, uinfo, uts, args[3])
+            }
+
+            SYS_RT_SIGQUEUEINFO => {
+                let pid = Pid::new(args[0]);
+                let sig = args[1] as c_int;
+                let uinfo = args[2] as *const c_void;
+                Self::rt_sigqueueinfo(pid, sig, uinfo)
+            }
+
+            #[cfg(target_arch = "x86_64")]
+            SYS_SIGNALFD => {
+                let ufd = args[0] as c_int;
+                let user_mask = args[1] as *const SigSet;
+                Self::signalfd4(ufd, user_mask, args[2], 0)
+            }
+
+            SYS_SIGNALFD4 => {
+                let ufd = args[0] as c_int;
+                let user_mask = args[1] as *const SigSet;
+                let flags = args[3] as u32;
+                Self::signalfd4(ufd, user_mask, args[2], flags)
             }
 
             SYS_TKILL => {
@@ -903,16 +1083,34 @@ impl Syscall {
             }
 
             SYS_GETGID => Self::getgid(),
-            SYS_SETUID => {
-                kwarn!("SYS_SETUID has not yet been implemented");
-                Ok(0)
-            }
-            SYS_SETGID => {
-                kwarn!("SYS_SETGID has not yet been implemented");
-                Ok(0)
-            }
+            SYS_SETUID => Self::setuid(args[0]),
+            SYS_SETGID => Self::setgid(args[0]),
             SYS_GETEUID => Self::geteuid(),
             SYS_GETEGID => Self::getegid(),
+            SYS_SETRESUID => Self::setresuid(args[0], args[1], args[2]),
+            SYS_SETRESGID => Self::setresgid(args[0], args[1], args[2]),
+            SYS_GETRESUID => {
+                let ruid = args[0] as *mut u32;
+                let euid = args[1] as *mut u32;
+                let suid = args[2] as *mut u32;
+                Self::getresuid(ruid, euid, suid)
+            }
+            SYS_GETRESGID => {
+                let rgid = args[0] as *mut u32;
+                let egid = args[1] as *mut u32;
+                let sgid = args[2] as *mut u32;
+                Self::getresgid(rgid, egid, sgid)
+            }
+            SYS_SETGROUPS => {
+                let size = args[0];
+                let list = args[1] as *const u32;
+                Self::setgroups(size, list)
+            }
+            SYS_GETGROUPS => {
+                let size = args[0];
+                let list = args[1] as *mut u32;
+                Self::getgroups(size, list)
+            }
             SYS_GETRUSAGE => {
                 let who = args[0] as c_int;
                 let rusage = args[1] as *mut RUsage;
@@ -973,6 +1171,90 @@ impl Syscall {
                 Self::clock_gettime(clockid, timespec)
             }
 
+            SYS_CLOCK_GETRES => {
+                let clockid = args[0] as i32;
+                let res = args[1] as *mut TimeSpec;
+                Self::clock_getres(clockid, res)
+            }
+
+            SYS_CLOCK_NANOSLEEP => {
+                let clockid = args[0] as i32;
+                let flags = args[1] as i32;
+                let request = args[2] as *const TimeSpec;
+                let remain = args[3] as *mut TimeSpec;
+                Self::clock_nanosleep(clockid, flags, request, remain)
+            }
+
+            #[cfg(target_arch = "x86_64")]
+            SYS_ALARM => {
+                let seconds = args[0] as u32;
+                Self::alarm(seconds)
+            }
+
+            SYS_GETITIMER => {
+                let which = args[0] as i32;
+                let curr_value = args[1] as *mut ITimerVal;
+                Self::getitimer(which, curr_value)
+            }
+
+            SYS_SETITIMER => {
+                let which = args[0] as i32;
+                let new_value = args[1] as *const ITimerVal;
+                let old_value = args[2] as *mut ITimerVal;
+                Self::setitimer(which, new_value, old_value)
+            }
+
+            SYS_TIMER_CREATE => {
+                let clockid = args[0] as i32;
+                let sevp = args[1] as *const PosixSigEvent;
+                let timerid = args[2] as *mut i32;
+                Self::timer_create(clockid, sevp, timerid)
+            }
+
+            SYS_TIMER_SETTIME => {
+                let timerid = args[0] as i32;
+                let flags = args[1] as i32;
+                let new_value = args[2] as *const ITimerSpec;
+                let old_value = args[3] as *mut ITimerSpec;
+                Self::timer_settime(timerid, flags, new_value, old_value)
+            }
+
+            SYS_TIMER_GETTIME => {
+                let timerid = args[0] as i32;
+                let curr_value = args[1] as *mut ITimerSpec;
+                Self::timer_gettime(timerid, curr_value)
+            }
+
+            SYS_TIMER_GETOVERRUN => {
+                let timerid = args[0] as i32;
+                Self::timer_getoverrun(timerid)
+            }
+
+            SYS_TIMER_DELETE => {
+                let timerid = args[0] as i32;
+                Self::timer_delete(timerid)
+            }
+
+            SYS_TIMERFD_CREATE => {
+                let clockid = args[0] as i32;
+                let flags = args[1] as u32;
+                Self::timerfd_create(clockid, flags)
+            }
+
+            SYS_TIMERFD_SETTIME => {
+                let fd = args[0] as i32;
+                let flags = args[1] as u32;
+                let new_value = args[2] as *const ITimerSpec;
+                let old_value = args[3] as *mut ITimerSpec;
+                Self::timerfd_settime(fd, flags, new_value, old_value)
+            }
+
+            SYS_TIMERFD_GETTIME => {
+                let fd = args[0] as i32;
+                let curr_value = args[1] as *mut ITimerSpec;
+                Self::timerfd_gettime(fd, curr_value)
+            }
+
             SYS_SYSINFO => {
                 let info = args[0] as *mut SysInfo;
                 Self::sysinfo(info)
@@ -984,8 +1266,27 @@ impl Syscall {
             }
 
             SYS_FCHOWN => {
-                kwarn!("SYS_FCHOWN has not yet been implemented");
-                Ok(0)
+                let fd = args[0] as i32;
+                Self::fchown(fd, args[1], args[2])
+            }
+
+            #[cfg(target_arch = "x86_64")]
+            SYS_CHOWN => {
+                let pathname = args[0] as *const u8;
+                Self::chown(pathname, args[1], args[2])
+            }
+
+            #[cfg(target_arch = "x86_64")]
+            SYS_LCHOWN => {
+                let pathname = args[0] as *const u8;
+                Self::lchown(pathname, args[1], args[2])
+            }
+
+            SYS_FCHOWNAT => {
+                let dirfd = args[0] as i32;
+                let pathname = args[1] as *const u8;
+                let flags = args[4] as u32;
+                Self::fchownat(dirfd, pathname, args[2], args[3], flags)
             }
 
             SYS_FSYNC => {
@@ -1011,10 +1312,56 @@ impl Syscall {
                 Self::fchmodat(dirfd, pathname, mode)
             }
 
-            SYS_SCHED_GETAFFINITY => {
-                // todo: 这个系统调用还没有实现
+            SYS_SCHED_SETSCHEDULER => {
+                let pid = args[0] as i32;
+                let policy = args[1] as i32;
+                let param = args[2] as *const SchedParam;
+                Self::sched_setscheduler(pid, policy, param)
+            }
+
+            SYS_SCHED_GETSCHEDULER => Self::sched_getscheduler(args[0] as i32),
 
-                Err(SystemError::ENOSYS)
+            SYS_SCHED_SETPARAM => {
+                let pid = args[0] as i32;
+                let param = args[1] as *const SchedParam;
+                Self::sched_setparam(pid, param)
+            }
+
+            SYS_SCHED_GETPARAM => {
+                let pid = args[0] as i32;
+                let param = args[1] as *mut SchedParam;
+                Self::sched_getparam(pid, param)
+            }
+
+            SYS_SCHED_GET_PRIORITY_MAX => Self::sched_get_priority_max(args[0] as i32),
+
+            SYS_SCHED_GET_PRIORITY_MIN => Self::sched_get_priority_min(args[0] as i32),
+
+            SYS_SETPRIORITY => {
+                let which = args[0] as i32;
+                let who = args[1] as i32;
+                let niceval = args[2] as i32;
+                Self::setpriority(which, who, niceval)
+            }
+
+            SYS_GETPRIORITY => {
+                let which = args[0] as i32;
+                let who = args[1] as i32;
+                Self::getpriority(which, who)
+            }
+
+            SYS_SCHED_SETAFFINITY => {
+                let pid = args[0] as i32;
+                let len = args[1];
+                let user_mask = args[2] as *const u8;
+                Self::sched_setaffinity(pid, len, user_mask)
+            }
+
+            SYS_SCHED_GETAFFINITY => {
+                let pid = args[0] as i32;
+                let len = args[1];
+                let user_mask = args[2] as *mut u8;
+                Self::sched_getaffinity(pid, len, user_mask)
             }
 
             #[cfg(target_arch = "x86_64")]
@@ -0,0 +1,201 @@
+//! 时钟事件设备
+//!
+//! 时钟事件设备是可以编程、在指定的时间之后产生中断的定时器硬件，例如LAPIC定时器、HPET和RISC-V的SBI定时器。
+//! 与只能读取时间的时钟源（`Clocksource`）相对应
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/time/clockevents.c
+
+use core::fmt::Debug;
+
+use alloc::{collections::LinkedList, sync::Arc};
+use system_error::SystemError;
+
+use crate::{kinfo, libs::spinlock::SpinLock, smp::cpu::ProcessorId};
+
+use super::{tick::tick_check_new_device, timekeeping::ktime_get_monotonic_ns};
+
+lazy_static! {
+    /// 已经注册的时钟事件设备
+    static ref CLOCKEVENT_DEVICES: SpinLock<LinkedList<Arc<dyn ClockEventDevice>>> =
+        SpinLock::new(LinkedList::new());
+}
+
+bitflags! {
+    /// 时钟事件设备支持的工作模式
+    pub struct ClockEventFeatures: u32 {
+        /// 周期性地产生中断
+        const PERIODIC = 1 << 0;
+        /// 在编程的时间到达时产生一次中断
+        const ONESHOT = 1 << 1;
+    }
+}
+
+/// 时钟事件设备的工作状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockEventState {
+    /// 还没有被使用
+    Detached,
+    /// 已经关闭，不会产生中断
+    Shutdown,
+    /// 周期性地产生中断，间隔为`ClockEventDevice::period_ns`
+    Periodic,
+    /// 在`set_next_event`设置的时间产生一次中断
+    Oneshot,
+    /// 处于oneshot模式，但是没有编程下一次中断
+    OneshotStopped,
+}
+
+/// 时钟事件设备产生中断时调用的函数，由使用设备的模块设置
+pub type ClockEventHandler = fn(&Arc<dyn ClockEventDevice>);
+
+/// 时钟事件设备的特性
+pub trait ClockEventDevice: Send + Sync + Debug {
+    fn name(&self) -> &str;
+
+    fn features(&self) -> ClockEventFeatures;
+
+    /// 设备的质量，选择设备时优先使用rating高的设备
+    fn rating(&self) -> u32;
+
+    /// 设备所属的CPU，为None表示设备可以向任意CPU投递中断（全局设备）
+    ///
+    /// 每个CPU的设备只能在对应的CPU上设置状态和编程
+    fn cpu(&self) -> Option<ProcessorId>;
+
+    /// 周期模式下两次中断的间隔（纳秒）
+    fn period_ns(&self) -> u64;
+
+    /// oneshot模式下可以编程的最短时间（纳秒）
+    fn min_delta_ns(&self) -> u64;
+
+    /// oneshot模式下可以编程的最长时间（纳秒）
+    fn max_delta_ns(&self) -> u64;
+
+    /// 让硬件进入指定的状态
+    fn set_state(&self, state: ClockEventState) -> Result<(), SystemError>;
+
+    /// oneshot模式下，在`delta_ns`纳秒之后产生中断
+    fn set_next_event(&self, delta_ns: u64) -> Result<(), SystemError>;
+
+    /// 设备的公共数据
+    fn data(&self) -> &ClockEventData;
+
+    /// 获取设备自身
+    fn clockevent(&self) -> Arc<dyn ClockEventDevice>;
+}
+
+/// 所有时钟事件设备共有的数据
+#[derive(Debug)]
+pub struct ClockEventData {
+    inner: SpinLock<InnerClockEventData>,
+}
+
+#[derive(Debug)]
+struct InnerClockEventData {
+    state: ClockEventState,
+    handler: Option<ClockEventHandler>,
+    /// oneshot模式下，下一次中断时单调时钟的时间（纳秒）
+    next_event: i64,
+}
+
+impl ClockEventData {
+    pub const fn new() -> Self {
+        return Self {
+            inner: SpinLock::new(InnerClockEventData {
+                state: ClockEventState::Detached,
+                handler: None,
+                next_event: i64::MAX,
+            }),
+        };
+    }
+}
+
+impl dyn ClockEventDevice {
+    pub fn state(&self) -> ClockEventState {
+        return self.data().inner.lock_irqsave().state;
+    }
+
+    /// 下一次中断时单调时钟的时间（纳秒），没有编程时为`i64::MAX`
+    pub fn next_event(&self) -> i64 {
+        return self.data().inner.lock_irqsave().next_event;
+    }
+
+    pub fn set_handler(&self, handler: Option<ClockEventHandler>) {
+        self.data().inner.lock_irqsave().handler = handler;
+    }
+
+    /// ## 切换设备的工作状态
+    ///
+    /// 设备不支持指定的模式时返回EINVAL
+    pub fn switch_state(&self, state: ClockEventState) -> Result<(), SystemError> {
+        let mut inner = self.data().inner.lock_irqsave();
+        if inner.state == state {
+            return Ok(());
+        }
+        let required = match state {
+            ClockEventState::Periodic => ClockEventFeatures::PERIODIC,
+            ClockEventState::Oneshot | ClockEventState::OneshotStopped => {
+                ClockEventFeatures::ONESHOT
+            }
+            ClockEventState::Detached | ClockEventState::Shutdown => ClockEventFeatures::empty(),
+        };
+        if !self.features().contains(required) {
+            return Err(SystemError::EINVAL);
+        }
+
+        self.set_state(state)?;
+        inner.state = state;
+        if state != ClockEventState::Oneshot {
+            inner.next_event = i64::MAX;
+        }
+        return Ok(());
+    }
+
+    /// ## 编程下一次中断
+    ///
+    /// ## 参数
+    ///
+    /// - `expires` 产生中断时单调时钟的时间（纳秒）。为None时停止产生中断
+    ///
+    /// 已经过去的时间会被调整为设备能编程的最短时间，超出设备范围的时间会被调整为最长时间，
+    /// 此时中断会提前产生，由中断处理函数重新编程
+    pub fn program_event(&self, expires: Option<i64>) -> Result<(), SystemError> {
+        let expires = match expires {
+            Some(expires) => expires,
+            None => return self.switch_state(ClockEventState::OneshotStopped),
+        };
+        self.switch_state(ClockEventState::Oneshot)?;
+
+        let delta = (expires - ktime_get_monotonic_ns()).max(0) as u64;
+        let delta = delta.clamp(self.min_delta_ns(), self.max_delta_ns());
+        let mut inner = self.data().inner.lock_irqsave();
+        self.set_next_event(delta)?;
+        inner.next_event = expires;
+        return Ok(());
+    }
+
+    /// 在设备的中断处理函数中调用，执行使用设备的模块设置的处理函数
+    pub fn handle_event(&self) {
+        let handler = self.data().inner.lock_irqsave().handler;
+        if let Some(handler) = handler {
+            handler(&self.clockevent());
+        }
+    }
+
+    /// ## 注册时钟事件设备
+    ///
+    /// 每个CPU的设备需要在对应的CPU上注册。注册之后，时钟节拍模块会决定是否使用这个设备
+    pub fn register(&self) {
+        let dev = self.clockevent();
+        self.switch_state(ClockEventState::Shutdown)
+            .expect("clockevent: failed to shutdown device");
+        CLOCKEVENT_DEVICES.lock_irqsave().push_back(dev.clone());
+        kinfo!(
+            "clockevent: registered device '{}', cpu: {:?}, rating: {}",
+            self.name(),
+            self.cpu(),
+            self.rating()
+        );
+        tick_check_new_device(dev);
+    }
+}
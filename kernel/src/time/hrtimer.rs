@@ -0,0 +1,408 @@
+//! 高精度定时器
+//!
+//! 定时器的到期时间以单调时钟的纳秒数表示，按到期时间保存在每个CPU的红黑树中。
+//! 当CPU的时钟事件设备处于oneshot模式（高精度模式）时，设备总是被编程为最早到期的定时器的时间；
+//! 否则在每次时钟节拍中检查到期的定时器，此时定时器的精度为一个节拍
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/time/hrtimer.c
+
+use core::{
+    fmt::Debug,
+    hint::spin_loop,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use alloc::{
+    boxed::Box,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    arch::{sched::sched, CurrentIrqArch},
+    exception::InterruptArch,
+    libs::{rbtree::RBTree, spinlock::SpinLock},
+    mm::percpu::{PerCpu, PerCpuVar},
+    process::{ProcessControlBlock, ProcessManager},
+    smp::{core::smp_get_processor_id, cpu::ProcessorId},
+};
+
+use super::{tick::tick_program_event, timekeeping::ktime_get_monotonic_ns};
+
+/// 一次中断中最多处理的到期定时器数量，避免间隔过短的周期定时器让CPU一直停留在中断中
+const HRTIMER_MAX_RUN_PER_INTERRUPT: usize = 128;
+
+/// 用于区分到期时间相同的定时器
+static HRTIMER_SEQ: AtomicU64 = AtomicU64::new(0);
+
+static mut HRTIMER_BASES: Option<PerCpuVar<SpinLock<HrTimerCpuBase>>> = None;
+
+/// 定时器的到期时间是绝对时间还是相对于现在的时间
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HrTimerMode {
+    Abs,
+    Rel,
+}
+
+/// 定时器到期之后是否需要重新启动
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HrTimerRestart {
+    NoRestart,
+    Restart,
+}
+
+/// 高精度定时器到期时要执行的函数的特征
+pub trait HrTimerFunction: Send + Sync + Debug {
+    /// ## 定时器到期
+    ///
+    /// 在时钟中断中调用，此时中断是关闭的。
+    /// 返回`Restart`之前，需要通过`HrTimer::forward`设置下一次到期的时间
+    fn run(&self, timer: &Arc<HrTimer>) -> HrTimerRestart;
+}
+
+/// 定时器在红黑树中的键
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct HrTimerKey {
+    expires: i64,
+    seq: u64,
+}
+
+#[derive(Debug)]
+pub struct HrTimer {
+    self_ref: Weak<HrTimer>,
+    func: Box<dyn HrTimerFunction>,
+    inner: SpinLock<InnerHrTimer>,
+}
+
+#[derive(Debug)]
+struct InnerHrTimer {
+    /// 到期时单调时钟的时间（纳秒）
+    expires: i64,
+    seq: u64,
+    /// 定时器所在队列的CPU，为None表示定时器没有在队列中
+    cpu: Option<ProcessorId>,
+    /// 定时器最近一次加入的队列所在的CPU，到期函数在这个CPU上执行
+    last_cpu: Option<ProcessorId>,
+    /// 每次启动或者取消定时器时加一。到期函数执行期间定时器被重新设置时，不再按照到期函数的返回值重新启动
+    generation: u64,
+}
+
+/// 每个CPU的定时器队列
+#[derive(Debug)]
+struct HrTimerCpuBase {
+    queue: RBTree<HrTimerKey, Arc<HrTimer>>,
+    /// 时钟事件设备是否处于oneshot模式
+    hres_active: bool,
+    /// 正在处理到期的定时器，处理完之后再统一编程时钟事件设备
+    in_interrupt: bool,
+    /// 正在执行到期函数的定时器
+    running: Option<Arc<HrTimer>>,
+}
+
+impl HrTimerCpuBase {
+    fn new() -> Self {
+        return Self {
+            queue: RBTree::new(),
+            hres_active: false,
+            in_interrupt: false,
+            running: None,
+        };
+    }
+
+    /// `timer`的到期函数是否正在执行
+    fn is_running(&self, timer: &HrTimer) -> bool {
+        return self
+            .running
+            .as_ref()
+            .is_some_and(|running| core::ptr::eq(Arc::as_ptr(running), timer));
+    }
+
+    fn first_expires(&self) -> Option<i64> {
+        return self.queue.get_first().map(|(key, _)| key.expires);
+    }
+
+    /// 把时钟事件设备编程为最早到期的定时器的时间
+    fn reprogram(&self) {
+        if !self.hres_active || self.in_interrupt {
+            return;
+        }
+        tick_program_event(self.first_expires());
+    }
+}
+
+#[inline(always)]
+fn hrtimer_cpu_base(cpu: ProcessorId) -> &'static SpinLock<HrTimerCpuBase> {
+    return unsafe { HRTIMER_BASES.as_ref().unwrap().force_get(cpu) };
+}
+
+impl HrTimer {
+    pub fn new(func: Box<dyn HrTimerFunction>) -> Arc<Self> {
+        return Arc::new_cyclic(|self_ref| Self {
+            self_ref: self_ref.clone(),
+            func,
+            inner: SpinLock::new(InnerHrTimer {
+                expires: 0,
+                seq: 0,
+                cpu: None,
+                last_cpu: None,
+                generation: 0,
+            }),
+        });
+    }
+
+    /// 到期时单调时钟的时间（纳秒）
+    pub fn expires(&self) -> i64 {
+        return self.inner.lock_irqsave().expires;
+    }
+
+    /// 定时器是否在队列中等待到期
+    pub fn is_active(&self) -> bool {
+        return self.inner.lock_irqsave().cpu.is_some();
+    }
+
+    /// ## 在当前CPU上启动定时器
+    ///
+    /// 定时器已经启动时，先将其取消
+    ///
+    /// ## 参数
+    ///
+    /// - `time` 到期时间（纳秒）。为`Abs`时是单调时钟的时间，为`Rel`时是相对于现在的时间
+    pub fn start(&self, time: i64, mode: HrTimerMode) {
+        let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+        self.remove();
+
+        let expires = match mode {
+            HrTimerMode::Abs => time,
+            HrTimerMode::Rel => ktime_get_monotonic_ns().saturating_add(time),
+        };
+        let cpu = smp_get_processor_id();
+        let mut base = hrtimer_cpu_base(cpu).lock_irqsave();
+        let mut inner = self.inner.lock_irqsave();
+        inner.expires = expires;
+        inner.generation += 1;
+        self.enqueue(&mut base, &mut inner, cpu);
+        drop(inner);
+        drop(base);
+        drop(irq_guard);
+    }
+
+    /// ## 取消定时器，并等待正在执行的到期函数结束
+    ///
+    /// 返回之后到期函数不会再执行，调用者可以释放到期函数使用的资源。
+    /// 不能在持有到期函数需要的锁时调用，否则会死锁，此时应该使用`try_to_cancel`
+    ///
+    /// ## 返回值
+    ///
+    /// 定时器是否在到期之前被取消
+    pub fn cancel(&self) -> bool {
+        let _irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+        let ret = self.remove();
+
+        let cpu = match self.inner.lock_irqsave().last_cpu {
+            Some(cpu) => cpu,
+            None => return ret,
+        };
+        // 到期函数在关中断的情况下执行，如果它在当前CPU上运行，说明是在到期函数中取消自己，不能等待
+        if cpu == smp_get_processor_id() {
+            return ret;
+        }
+        while hrtimer_cpu_base(cpu).lock_irqsave().is_running(self) {
+            spin_loop();
+        }
+        return ret;
+    }
+
+    /// ## 取消定时器，不等待正在执行的到期函数
+    ///
+    /// ## 返回值
+    ///
+    /// 定时器是否在到期之前被取消
+    pub fn try_to_cancel(&self) -> bool {
+        return self.remove();
+    }
+
+    /// ## 把周期定时器的到期时间向后推移整数个间隔，使其晚于`now`
+    ///
+    /// 只能在定时器不在队列中时调用，例如在到期函数中
+    ///
+    /// ## 返回值
+    ///
+    /// 推移的间隔数，到期时间已经晚于`now`时返回0
+    pub fn forward(&self, now: i64, interval: i64) -> u64 {
+        let mut inner = self.inner.lock_irqsave();
+        if inner.expires > now || interval <= 0 {
+            return 0;
+        }
+        let overruns = (now - inner.expires) / interval + 1;
+        inner.expires += overruns * interval;
+        return overruns as u64;
+    }
+
+    /// 把定时器加入`cpu`的队列
+    ///
+    /// 需要先获取队列的锁，再获取定时器的锁
+    fn enqueue(&self, base: &mut HrTimerCpuBase, inner: &mut InnerHrTimer, cpu: ProcessorId) {
+        inner.seq = HRTIMER_SEQ.fetch_add(1, Ordering::Relaxed);
+        inner.cpu = Some(cpu);
+        inner.last_cpu = Some(cpu);
+        let key = HrTimerKey {
+            expires: inner.expires,
+            seq: inner.seq,
+        };
+        base.queue.insert(key, self.self_ref.upgrade().unwrap());
+        if base.first_expires() == Some(key.expires) {
+            base.reprogram();
+        }
+    }
+
+    /// 把定时器从队列中移除，并使到期函数的返回值失效
+    fn remove(&self) -> bool {
+        let _irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+        loop {
+            let cpu = {
+                let mut inner = self.inner.lock_irqsave();
+                inner.generation += 1;
+                match inner.cpu {
+                    Some(cpu) => cpu,
+                    None => return false,
+                }
+            };
+
+            // 先获取队列的锁，再获取定时器的锁。期间定时器可能已经到期，此时重新检查
+            let mut base = hrtimer_cpu_base(cpu).lock_irqsave();
+            let mut inner = self.inner.lock_irqsave();
+            if inner.cpu != Some(cpu) {
+                continue;
+            }
+            let was_first = base.first_expires() == Some(inner.expires);
+            base.queue.remove(&HrTimerKey {
+                expires: inner.expires,
+                seq: inner.seq,
+            });
+            inner.cpu = None;
+            // 其他CPU的时钟事件设备无法在这里编程，只会多产生一次中断
+            if was_first && cpu == smp_get_processor_id() {
+                base.reprogram();
+            }
+            return true;
+        }
+    }
+}
+
+/// 处理当前CPU上所有已经到期的定时器
+fn hrtimer_run_expired(base: &SpinLock<HrTimerCpuBase>) {
+    let cpu = smp_get_processor_id();
+    let mut guard = base.lock_irqsave();
+    guard.in_interrupt = true;
+
+    for _ in 0..HRTIMER_MAX_RUN_PER_INTERRUPT {
+        let now = ktime_get_monotonic_ns();
+        match guard.first_expires() {
+            Some(expires) if expires <= now => {}
+            _ => break,
+        }
+        let (_, timer) = guard.queue.pop_first().unwrap();
+        let generation = {
+            let mut inner = timer.inner.lock_irqsave();
+            inner.cpu = None;
+            inner.generation
+        };
+        guard.running = Some(timer.clone());
+        drop(guard);
+
+        let restart = timer.func.run(&timer);
+
+        guard = base.lock_irqsave();
+        guard.running = None;
+        if restart == HrTimerRestart::Restart {
+            let mut inner = timer.inner.lock_irqsave();
+            // 到期函数执行期间，定时器可能被取消或者重新启动
+            if inner.generation == generation && inner.cpu.is_none() {
+                timer.enqueue(&mut guard, &mut inner, cpu);
+            }
+        }
+    }
+
+    guard.in_interrupt = false;
+    guard.reprogram();
+}
+
+/// ## 高精度模式下，时钟事件设备产生中断时调用
+///
+/// 执行到期的定时器，然后把设备编程为下一个定时器到期的时间
+pub fn hrtimer_interrupt() {
+    hrtimer_run_expired(hrtimer_cpu_base(smp_get_processor_id()));
+}
+
+/// ## 低精度模式下，在每次时钟节拍中调用
+pub fn hrtimer_run_queues() {
+    let base = hrtimer_cpu_base(smp_get_processor_id());
+    if base.lock_irqsave().hres_active {
+        return;
+    }
+    hrtimer_run_expired(base);
+}
+
+/// ## 当前CPU的时钟事件设备进入oneshot模式之后调用，开始按照定时器的到期时间编程设备
+pub fn hrtimer_switch_to_hres() {
+    let mut base = hrtimer_cpu_base(smp_get_processor_id()).lock_irqsave();
+    base.hres_active = true;
+    base.reprogram();
+}
+
+/// 当前CPU是否处于高精度模式
+pub fn hrtimer_hres_active() -> bool {
+    return hrtimer_cpu_base(smp_get_processor_id())
+        .lock_irqsave()
+        .hres_active;
+}
+
+/// 定时器到期时唤醒进程
+#[derive(Debug)]
+pub struct HrTimerWakeUp {
+    pcb: Arc<ProcessControlBlock>,
+}
+
+impl HrTimerWakeUp {
+    pub fn new(pcb: Arc<ProcessControlBlock>) -> Box<Self> {
+        return Box::new(Self { pcb });
+    }
+}
+
+impl HrTimerFunction for HrTimerWakeUp {
+    fn run(&self, _timer: &Arc<HrTimer>) -> HrTimerRestart {
+        ProcessManager::wakeup(&self.pcb).ok();
+        return HrTimerRestart::NoRestart;
+    }
+}
+
+/// ## 让当前进程休眠到单调时钟的`expires`纳秒
+///
+/// 到期或者被信号唤醒时返回
+///
+/// ## 返回值
+///
+/// 是否是因为定时器到期而返回
+pub fn hrtimer_sleep_until(expires: i64) -> bool {
+    let timer = HrTimer::new(HrTimerWakeUp::new(ProcessManager::current_pcb()));
+
+    let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+    ProcessManager::mark_sleep(true).ok();
+    timer.start(expires, HrTimerMode::Abs);
+    drop(irq_guard);
+    sched();
+
+    // 被信号提前唤醒时，定时器还在队列中
+    return !timer.cancel();
+}
+
+/// 初始化每个CPU的定时器队列
+#[inline(never)]
+pub fn hrtimer_init() {
+    let bases = (0..PerCpu::MAX_CPU_NUM)
+        .map(|_| SpinLock::new(HrTimerCpuBase::new()))
+        .collect::<Vec<_>>();
+    unsafe {
+        HRTIMER_BASES = Some(PerCpuVar::new(bases).unwrap());
+    }
+}
@@ -0,0 +1,471 @@
+//! 间隔定时器
+//!
+//! 包括setitimer/alarm使用的进程间隔定时器，以及POSIX定时器和timerfd共用的周期定时器`IntervalTimer`
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/time/itimer.c
+
+use core::fmt::Debug;
+
+use alloc::{
+    boxed::Box,
+    sync::{Arc, Weak},
+};
+use num_traits::FromPrimitive;
+use system_error::SystemError;
+
+use crate::{
+    arch::ipc::signal::{SigCode, Signal},
+    ipc::signal_types::{SigInfo, SigType},
+    libs::spinlock::SpinLock,
+    process::{Pid, ProcessControlBlock, ProcessManager},
+};
+
+use super::{
+    hrtimer::{HrTimer, HrTimerFunction, HrTimerMode, HrTimerRestart},
+    syscall::{PosixClockID, PosixTimeval},
+    timekeeping::ktime_get_monotonic_ns,
+    TimeSpec, NSEC_PER_SEC, NSEC_PER_USEC, USEC_PER_SEC,
+};
+
+/// 定时器的设置，与Linux的`struct itimerspec`相同
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ITimerSpec {
+    /// 周期性到期的间隔，为0表示只到期一次
+    pub it_interval: TimeSpec,
+    /// 距离下一次到期的时间，为0表示停止定时器
+    pub it_value: TimeSpec,
+}
+
+impl ITimerSpec {
+    pub fn is_valid(&self) -> bool {
+        return self.it_interval.is_valid() && self.it_value.is_valid();
+    }
+}
+
+/// setitimer和getitimer使用的定时器设置，与Linux的`struct itimerval`相同
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ITimerVal {
+    pub it_interval: PosixTimeval,
+    pub it_value: PosixTimeval,
+}
+
+/// setitimer和getitimer的which参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum ITimerWhich {
+    /// 按照真实时间计时，到期时发送SIGALRM
+    Real = 0,
+    /// 按照进程占用的CPU时间计时，到期时发送SIGVTALRM
+    Virtual = 1,
+    /// 按照进程占用的CPU时间计时，到期时发送SIGPROF
+    Prof = 2,
+}
+
+impl TryFrom<i32> for ITimerWhich {
+    type Error = SystemError;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        <Self as FromPrimitive>::from_i32(value).ok_or(SystemError::EINVAL)
+    }
+}
+
+/// 把timeval转换为纳秒数，timeval不合法时返回EINVAL
+fn timeval_to_nsecs(tv: &PosixTimeval) -> Result<i64, SystemError> {
+    if tv.tv_sec < 0 || tv.tv_usec < 0 || tv.tv_usec >= USEC_PER_SEC as i32 {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok(tv.tv_sec * NSEC_PER_SEC as i64 + tv.tv_usec as i64 * NSEC_PER_USEC as i64);
+}
+
+fn nsecs_to_timeval(nsecs: i64) -> PosixTimeval {
+    let ts = TimeSpec::from_nsecs(nsecs);
+    return PosixTimeval {
+        tv_sec: ts.tv_sec,
+        tv_usec: (ts.tv_nsec / NSEC_PER_USEC as i64) as i32,
+    };
+}
+
+/// 间隔定时器到期时要执行的操作
+pub trait IntervalTimerCallback: Send + Sync + Debug {
+    /// ## 定时器到期
+    ///
+    /// 在时钟中断中调用，此时中断是关闭的
+    ///
+    /// ## 参数
+    ///
+    /// - `overrun` 这一次到期之前错过的到期次数
+    fn expire(&self, overrun: u64);
+}
+
+/// 以某个时钟为基准，可以周期性到期的定时器
+///
+/// 到期时间统一换算为单调时钟的时间，然后通过`HrTimer`在到期时得到通知
+#[derive(Debug)]
+pub struct IntervalTimer {
+    self_ref: Weak<IntervalTimer>,
+    clock: PosixClockID,
+    callback: Box<dyn IntervalTimerCallback>,
+    inner: SpinLock<InnerIntervalTimer>,
+}
+
+#[derive(Debug, Default)]
+struct InnerIntervalTimer {
+    /// 下一次到期时单调时钟的时间（纳秒），为None表示定时器没有启动
+    expires: Option<i64>,
+    /// 周期性到期的间隔（纳秒），为0表示只到期一次
+    interval: i64,
+    /// 上一次到期时错过的到期次数
+    overrun: u64,
+    /// 每次停止定时器时加一，用于忽略已经被取消的`HrTimer`
+    generation: u64,
+    timer: Option<Arc<HrTimer>>,
+}
+
+impl InnerIntervalTimer {
+    fn spec(&self) -> ITimerSpec {
+        let it_value = match self.expires {
+            // 已经到期但还没有来得及处理时，剩余时间记为1ns，以免被当作没有启动
+            Some(expires) => TimeSpec::from_nsecs((expires - ktime_get_monotonic_ns()).max(1)),
+            None => TimeSpec::default(),
+        };
+        return ITimerSpec {
+            it_interval: TimeSpec::from_nsecs(self.interval),
+            it_value,
+        };
+    }
+
+    fn disarm(&mut self) {
+        self.generation += 1;
+        self.expires = None;
+        self.interval = 0;
+        // 持有锁时不能等待到期函数结束，已经开始执行的到期函数会因为generation不同而直接返回
+        if let Some(timer) = self.timer.take() {
+            timer.try_to_cancel();
+        }
+    }
+}
+
+impl IntervalTimer {
+    /// ## 创建一个没有启动的定时器
+    ///
+    /// ## 参数
+    ///
+    /// - `clock` 定时器使用的时钟，必须满足`PosixClockID::is_timer_clock`
+    /// - `callback` 定时器到期时要执行的操作
+    pub fn new(clock: PosixClockID, callback: Box<dyn IntervalTimerCallback>) -> Arc<Self> {
+        return Arc::new_cyclic(|self_ref| Self {
+            self_ref: self_ref.clone(),
+            clock,
+            callback,
+            inner: SpinLock::new(InnerIntervalTimer::default()),
+        });
+    }
+
+    pub fn clock(&self) -> PosixClockID {
+        return self.clock;
+    }
+
+    /// 获取定时器的剩余时间和到期间隔
+    pub fn get(&self) -> ITimerSpec {
+        return self.inner.lock_irqsave().spec();
+    }
+
+    /// 上一次到期时错过的到期次数
+    pub fn overrun(&self) -> u64 {
+        return self.inner.lock_irqsave().overrun;
+    }
+
+    /// ## 设置定时器
+    ///
+    /// ## 参数
+    ///
+    /// - `new` 新的设置，it_value为0时停止定时器
+    /// - `abstime` 为true时，it_value是时钟的绝对时间，否则是相对于现在的时间
+    ///
+    /// ## 返回值
+    ///
+    /// 定时器原来的设置
+    pub fn set(&self, new: &ITimerSpec, abstime: bool) -> ITimerSpec {
+        let mut inner = self.inner.lock_irqsave();
+        let old = inner.spec();
+        inner.disarm();
+        inner.overrun = 0;
+
+        let value = new.it_value.total_nsecs();
+        if value != 0 {
+            let delay = if abstime {
+                value.saturating_sub(self.clock.now().total_nsecs())
+            } else {
+                value
+            };
+            // 绝对时间已经过去时，定时器立即到期
+            inner.expires = Some(ktime_get_monotonic_ns().saturating_add(delay.max(0)));
+            inner.interval = new.it_interval.total_nsecs();
+            self.arm(&mut inner);
+        }
+        return old;
+    }
+
+    /// 停止定时器
+    pub fn cancel(&self) {
+        self.inner.lock_irqsave().disarm();
+    }
+
+    /// 按照下一次到期的时间启动`HrTimer`
+    fn arm(&self, inner: &mut InnerIntervalTimer) {
+        let timer = HrTimer::new(Box::new(IntervalTimerFunc {
+            timer: self.self_ref.clone(),
+            generation: inner.generation,
+        }));
+        timer.start(inner.expires.unwrap(), HrTimerMode::Abs);
+        inner.timer = Some(timer);
+    }
+
+    fn handle_expire(&self, generation: u64) {
+        let mut inner = self.inner.lock_irqsave();
+        if inner.generation != generation {
+            return;
+        }
+        let expires = match inner.expires {
+            Some(expires) => expires,
+            None => return,
+        };
+        inner.timer = None;
+
+        let now = ktime_get_monotonic_ns();
+        if now < expires {
+            // 单调时钟还没有走到到期时间，重新等待剩下的时间
+            self.arm(&mut inner);
+            return;
+        }
+
+        let overrun = if inner.interval > 0 {
+            let missed = (now - expires) / inner.interval;
+            inner.expires =
+                Some(expires.saturating_add((missed + 1).saturating_mul(inner.interval)));
+            self.arm(&mut inner);
+            missed as u64
+        } else {
+            inner.expires = None;
+            0
+        };
+        inner.overrun = overrun;
+        drop(inner);
+
+        self.callback.expire(overrun);
+    }
+}
+
+impl Drop for IntervalTimer {
+    fn drop(&mut self) {
+        self.inner.lock_irqsave().disarm();
+    }
+}
+
+/// `IntervalTimer`使用的`HrTimer`到期时执行的函数
+#[derive(Debug)]
+struct IntervalTimerFunc {
+    timer: Weak<IntervalTimer>,
+    generation: u64,
+}
+
+impl HrTimerFunction for IntervalTimerFunc {
+    fn run(&self, _timer: &Arc<HrTimer>) -> HrTimerRestart {
+        if let Some(timer) = self.timer.upgrade() {
+            timer.handle_expire(self.generation);
+        }
+        return HrTimerRestart::NoRestart;
+    }
+}
+
+/// 按照进程占用的CPU时间计时的间隔定时器（ITIMER_VIRTUAL和ITIMER_PROF）
+#[derive(Debug, Default, Clone, Copy)]
+struct CpuITimer {
+    /// 距离到期还需要占用的CPU时间（纳秒），为0表示定时器没有启动
+    value: u64,
+    /// 周期性到期的间隔（纳秒）
+    interval: u64,
+}
+
+impl CpuITimer {
+    /// 记录`delta`纳秒的CPU时间，返回定时器是否到期
+    fn account(&mut self, delta: u64) -> bool {
+        if self.value == 0 {
+            return false;
+        }
+        if self.value > delta {
+            self.value -= delta;
+            return false;
+        }
+        self.value = self.interval;
+        return true;
+    }
+}
+
+/// 进程的间隔定时器
+///
+/// 间隔定时器属于整个线程组，保存在线程组组长的`SignalStruct`中。fork出的子进程不继承父进程的间隔定时器
+#[derive(Debug, Default)]
+pub struct ProcessITimers {
+    /// ITIMER_REAL，第一次设置时创建
+    real: Option<Arc<IntervalTimer>>,
+    /// ITIMER_VIRTUAL
+    virt: CpuITimer,
+    /// ITIMER_PROF
+    prof: CpuITimer,
+}
+
+/// ITIMER_REAL到期时向进程发送SIGALRM
+#[derive(Debug)]
+struct ITimerRealCallback {
+    /// 线程组组长，在创建定时器时确定，避免在到期回调中查找进程
+    leader: Weak<ProcessControlBlock>,
+}
+
+impl IntervalTimerCallback for ITimerRealCallback {
+    fn expire(&self, _overrun: u64) {
+        if let Some(leader) = self.leader.upgrade() {
+            send_itimer_signal(Signal::SIGALRM, leader);
+        }
+    }
+}
+
+fn send_itimer_signal(sig: Signal, pcb: Arc<ProcessControlBlock>) {
+    let mut info = SigInfo::new(sig, 0, SigCode::Kernel, SigType::Kill(Pid::new(0)));
+    let _ = sig.send_signal_info_to_pcb(Some(&mut info), pcb);
+}
+
+/// 获取进程所在线程组的组长，进程的定时器都保存在组长的pcb中
+pub(super) fn thread_group_leader(pcb: &Arc<ProcessControlBlock>) -> Arc<ProcessControlBlock> {
+    if pcb.pid() == pcb.tgid() {
+        return pcb.clone();
+    }
+    return pcb.group_leader().unwrap_or_else(|| pcb.clone());
+}
+
+/// ## 读取当前进程的间隔定时器
+pub fn do_getitimer(which: ITimerWhich) -> ITimerVal {
+    let leader = thread_group_leader(&ProcessManager::current_pcb());
+    let sig_struct = leader.sig_struct_irqsave();
+    let (interval, value) = match which {
+        ITimerWhich::Real => {
+            let spec = sig_struct
+                .itimers
+                .real
+                .as_ref()
+                .map(|timer| timer.get())
+                .unwrap_or_default();
+            (spec.it_interval.total_nsecs(), spec.it_value.total_nsecs())
+        }
+        ITimerWhich::Virtual => (
+            sig_struct.itimers.virt.interval as i64,
+            sig_struct.itimers.virt.value as i64,
+        ),
+        ITimerWhich::Prof => (
+            sig_struct.itimers.prof.interval as i64,
+            sig_struct.itimers.prof.value as i64,
+        ),
+    };
+
+    return ITimerVal {
+        it_interval: nsecs_to_timeval(interval),
+        it_value: nsecs_to_timeval(value),
+    };
+}
+
+/// ## 设置当前进程的间隔定时器
+///
+/// ## 参数
+///
+/// - `which` 要设置的定时器
+/// - `new` 新的设置，it_value为0时停止定时器
+///
+/// ## 返回值
+///
+/// 定时器原来的设置
+pub fn do_setitimer(which: ITimerWhich, new: &ITimerVal) -> Result<ITimerVal, SystemError> {
+    let interval = timeval_to_nsecs(&new.it_interval)?;
+    let value = timeval_to_nsecs(&new.it_value)?;
+    let old = do_getitimer(which);
+
+    let leader = thread_group_leader(&ProcessManager::current_pcb());
+    let mut sig_struct = leader.sig_struct_irqsave();
+    match which {
+        ITimerWhich::Real => {
+            let timer = sig_struct
+                .itimers
+                .real
+                .get_or_insert_with(|| {
+                    IntervalTimer::new(
+                        PosixClockID::Monotonic,
+                        Box::new(ITimerRealCallback {
+                            leader: Arc::downgrade(&leader),
+                        }),
+                    )
+                })
+                .clone();
+            drop(sig_struct);
+
+            let spec = ITimerSpec {
+                it_interval: TimeSpec::from_nsecs(interval),
+                it_value: TimeSpec::from_nsecs(value),
+            };
+            timer.set(&spec, false);
+        }
+        ITimerWhich::Virtual | ITimerWhich::Prof => {
+            let itimer = if which == ITimerWhich::Virtual {
+                &mut sig_struct.itimers.virt
+            } else {
+                &mut sig_struct.itimers.prof
+            };
+            itimer.value = value as u64;
+            itimer.interval = interval as u64;
+        }
+    }
+
+    return Ok(old);
+}
+
+/// ## 把CPU时间记到进程的ITIMER_VIRTUAL和ITIMER_PROF上
+///
+/// 在时钟中断中调用。目前没有区分用户态和内核态的CPU时间，两个定时器都按照进程占用的全部CPU时间计时
+///
+/// ## 参数
+///
+/// - `pcb` 占用了CPU的进程
+/// - `delta` 占用的CPU时间（纳秒）
+pub fn itimer_account_cpu_time(pcb: &Arc<ProcessControlBlock>, delta: u64) {
+    let leader = thread_group_leader(pcb);
+    let (virt, prof) = match leader.try_sig_struct_irqsave(10) {
+        Some(mut sig_struct) => {
+            let itimers = &mut sig_struct.itimers;
+            (itimers.virt.account(delta), itimers.prof.account(delta))
+        }
+        None => return,
+    };
+
+    if virt {
+        send_itimer_signal(Signal::SIGVTALRM, leader.clone());
+    }
+    if prof {
+        send_itimer_signal(Signal::SIGPROF, leader);
+    }
+}
+
+/// ## 停止进程的所有定时器
+///
+/// 在线程组组长退出时调用，停止间隔定时器并删除所有的POSIX定时器
+pub fn exit_itimers(pcb: &Arc<ProcessControlBlock>) {
+    if pcb.pid() != pcb.tgid() {
+        return;
+    }
+    let mut sig_struct = pcb.sig_struct_irqsave();
+    let itimers = core::mem::take(&mut sig_struct.itimers);
+    let posix_timers = core::mem::take(&mut sig_struct.posix_timers);
+    drop(sig_struct);
+
+    // 在释放锁之后再停止定时器
+    drop(itimers);
+    drop(posix_timers);
+}
@@ -8,14 +8,20 @@ use crate::arch::CurrentTimeArch;
 
 use self::timekeep::ktime_get_real_ns;
 
+pub mod clockevents;
 pub mod clocksource;
+pub mod hrtimer;
+pub mod itimer;
 pub mod jiffies;
+pub mod posix_timers;
 pub mod sleep;
 pub mod syscall;
+pub mod tick;
 pub mod timeconv;
 pub mod timekeep;
 pub mod timekeeping;
 pub mod timer;
+pub mod timerfd;
 /* Time structures. (Partitially taken from smoltcp)
 
 The `time` module contains structures used to represent both
@@ -59,6 +65,29 @@ impl TimeSpec {
         };
     }
 
+    /// 从纳秒数创建TimeSpec
+    pub fn from_nsecs(nsecs: i64) -> TimeSpec {
+        return TimeSpec::new(
+            nsecs.div_euclid(NSEC_PER_SEC as i64),
+            nsecs.rem_euclid(NSEC_PER_SEC as i64),
+        );
+    }
+
+    /// 转换为纳秒数
+    ///
+    /// 超出i64范围时取i64的最大（最小）值，因此很大的超时时间（例如tv_sec为LONG_MAX）表示永不超时
+    pub fn total_nsecs(&self) -> i64 {
+        return self
+            .tv_sec
+            .saturating_mul(NSEC_PER_SEC as i64)
+            .saturating_add(self.tv_nsec);
+    }
+
+    /// 是否是合法的时间：tv_sec非负，并且tv_nsec在[0, NSEC_PER_SEC)之间
+    pub fn is_valid(&self) -> bool {
+        return self.tv_sec >= 0 && self.tv_nsec >= 0 && self.tv_nsec < NSEC_PER_SEC as i64;
+    }
+
     /// 获取当前时间
     pub fn now() -> Self {
         #[cfg(target_arch = "x86_64")]
@@ -425,4 +454,7 @@ impl From<Duration> for smoltcp::time::Duration {
 pub trait TimeArch {
     /// Get CPU cycles (Read from register)
     fn get_cycles() -> usize;
+
+    /// 把`get_cycles`得到的周期数换算为纳秒。计数器的频率还未知时返回0
+    fn cycles2ns(cycles: usize) -> usize;
 }
@@ -0,0 +1,218 @@
+//! POSIX定时器（timer_create等系统调用）
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/time/posix-timers.c
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+};
+use ida::IdAllocator;
+use system_error::SystemError;
+
+use crate::{
+    arch::ipc::signal::{SigCode, Signal, MAX_SIG_NUM},
+    ipc::signal_types::{SigInfo, SigType},
+    process::{Pid, ProcessControlBlock, ProcessManager},
+};
+
+use super::{
+    itimer::{thread_group_leader, IntervalTimer, IntervalTimerCallback},
+    syscall::PosixClockID,
+};
+
+/// 定时器到期时发送信号
+pub const SIGEV_SIGNAL: i32 = 0;
+/// 定时器到期时不进行通知
+pub const SIGEV_NONE: i32 = 1;
+/// 定时器到期时在新线程中执行函数，由libc实现，内核不支持
+pub const SIGEV_THREAD: i32 = 2;
+/// 定时器到期时向指定的线程发送信号
+pub const SIGEV_THREAD_ID: i32 = 4;
+
+/// 一个进程最多可以创建的POSIX定时器数量
+const POSIX_TIMER_MAX: usize = 4096;
+
+/// timer_create的sevp参数，对应Linux的`struct sigevent`中内核使用的部分
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PosixSigEvent {
+    /// 随信号发送的数据
+    pub sigev_value: u64,
+    pub sigev_signo: i32,
+    pub sigev_notify: i32,
+    /// SIGEV_THREAD_ID时接收信号的线程
+    pub sigev_notify_thread_id: i32,
+}
+
+/// POSIX定时器到期时的通知方式
+#[derive(Debug, Clone)]
+enum PosixTimerNotify {
+    None,
+    Signal {
+        sig: Signal,
+        /// 接收信号的进程或线程，在创建定时器时确定，避免在到期回调中查找进程
+        target: Weak<ProcessControlBlock>,
+        sigval: u64,
+    },
+}
+
+#[derive(Debug)]
+struct PosixTimerCallback {
+    id: i32,
+    notify: PosixTimerNotify,
+}
+
+impl IntervalTimerCallback for PosixTimerCallback {
+    fn expire(&self, overrun: u64) {
+        if let PosixTimerNotify::Signal {
+            sig,
+            target,
+            sigval,
+        } = &self.notify
+        {
+            let target = match target.upgrade() {
+                Some(target) => target,
+                None => return,
+            };
+            let (sig, sigval) = (*sig, *sigval);
+            let mut info = SigInfo::new(
+                sig,
+                0,
+                SigCode::Timer,
+                SigType::Timer {
+                    tid: self.id,
+                    overrun: overrun.min(i32::MAX as u64) as i32,
+                    sigval,
+                },
+            );
+            let _ = sig.send_signal_info_to_pcb(Some(&mut info), target);
+        }
+    }
+}
+
+/// 进程通过timer_create创建的定时器，保存在线程组组长的`SignalStruct`中
+///
+/// fork出的子进程不继承父进程的POSIX定时器，execve时删除所有的POSIX定时器
+#[derive(Debug)]
+pub struct PosixTimerTable {
+    ida: IdAllocator,
+    timers: BTreeMap<i32, Arc<IntervalTimer>>,
+}
+
+impl Default for PosixTimerTable {
+    fn default() -> Self {
+        return Self {
+            ida: IdAllocator::new(0, POSIX_TIMER_MAX),
+            timers: BTreeMap::new(),
+        };
+    }
+}
+
+impl PosixTimerTable {
+    pub fn get(&self, id: i32) -> Option<Arc<IntervalTimer>> {
+        return self.timers.get(&id).cloned();
+    }
+}
+
+/// 检查sigevent，得到定时器到期时的通知方式
+fn sigevent_to_notify(event: &PosixSigEvent) -> Result<PosixTimerNotify, SystemError> {
+    let current = ProcessManager::current_pcb();
+    let target = match event.sigev_notify {
+        SIGEV_NONE => return Ok(PosixTimerNotify::None),
+        SIGEV_SIGNAL => thread_group_leader(&current),
+        SIGEV_THREAD_ID => {
+            // 只能向同一个线程组中的线程发送信号
+            let tid = Pid::new(event.sigev_notify_thread_id as usize);
+            match ProcessManager::find(tid) {
+                Some(pcb) if event.sigev_notify_thread_id > 0 && pcb.tgid() == current.tgid() => {
+                    pcb
+                }
+                _ => return Err(SystemError::EINVAL),
+            }
+        }
+        _ => return Err(SystemError::EINVAL),
+    };
+
+    if event.sigev_signo <= 0 || event.sigev_signo as usize > MAX_SIG_NUM {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok(PosixTimerNotify::Signal {
+        sig: Signal::from(event.sigev_signo),
+        target: Arc::downgrade(&target),
+        sigval: event.sigev_value,
+    });
+}
+
+/// ## 为当前进程创建一个没有启动的POSIX定时器
+///
+/// ## 参数
+///
+/// - `clock` 定时器使用的时钟
+/// - `event` 定时器到期时的通知方式，为None时到期时向进程发送SIGALRM
+///
+/// ## 返回值
+///
+/// 新定时器的id
+pub fn do_timer_create(
+    clock: PosixClockID,
+    event: Option<&PosixSigEvent>,
+) -> Result<i32, SystemError> {
+    if clock.is_cpu_clock() {
+        // todo: 支持按照CPU时间计时的POSIX定时器
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+    if !clock.is_timer_clock() {
+        return Err(SystemError::EINVAL);
+    }
+
+    let notify = event.map(sigevent_to_notify).transpose()?;
+
+    let leader = thread_group_leader(&ProcessManager::current_pcb());
+    let mut sig_struct = leader.sig_struct_irqsave();
+    let table = &mut sig_struct.posix_timers;
+    let id = table
+        .ida
+        .alloc()
+        .ok_or(SystemError::EAGAIN_OR_EWOULDBLOCK)? as i32;
+
+    // sevp为NULL时，相当于以SIGALRM为信号、以定时器id为数据的SIGEV_SIGNAL
+    let notify = notify.unwrap_or(PosixTimerNotify::Signal {
+        sig: Signal::SIGALRM,
+        target: Arc::downgrade(&leader),
+        sigval: id as u64,
+    });
+    let timer = IntervalTimer::new(clock, Box::new(PosixTimerCallback { id, notify }));
+    table.timers.insert(id, timer);
+
+    return Ok(id);
+}
+
+/// 获取当前进程id为`id`的POSIX定时器
+pub fn posix_timer(id: i32) -> Result<Arc<IntervalTimer>, SystemError> {
+    let leader = thread_group_leader(&ProcessManager::current_pcb());
+    let timer = leader.sig_struct_irqsave().posix_timers.get(id);
+    return timer.ok_or(SystemError::EINVAL);
+}
+
+/// ## 删除当前进程id为`id`的POSIX定时器
+pub fn do_timer_delete(id: i32) -> Result<(), SystemError> {
+    let leader = thread_group_leader(&ProcessManager::current_pcb());
+    let mut sig_struct = leader.sig_struct_irqsave();
+    let table = &mut sig_struct.posix_timers;
+    let timer = table.timers.remove(&id).ok_or(SystemError::EINVAL)?;
+    table.ida.free(id as usize);
+    drop(sig_struct);
+
+    timer.cancel();
+    return Ok(());
+}
+
+/// ## 删除当前进程的所有POSIX定时器
+///
+/// 在execve时调用
+pub fn exit_posix_timers() {
+    let leader = thread_group_leader(&ProcessManager::current_pcb());
+    let table = core::mem::take(&mut leader.sig_struct_irqsave().posix_timers);
+    drop(table);
+}
@@ -1,23 +1,16 @@
-use core::hint::spin_loop;
-
-use alloc::{boxed::Box, sync::Arc};
 use system_error::SystemError;
 
 use crate::{
-    arch::{sched::sched, CurrentIrqArch, CurrentTimeArch},
-    exception::InterruptArch,
-    include::bindings::bindings::{useconds_t, Cpu_tsc_freq},
-    process::ProcessManager,
-    time::timekeeping::getnstimeofday,
+    include::bindings::bindings::useconds_t,
+    time::{hrtimer::hrtimer_sleep_until, timekeeping::ktime_get_monotonic_ns},
 };
 
-use super::{
-    timer::{next_n_us_timer_jiffies, Timer, WakeUpHelper},
-    TimeArch, TimeSpec,
-};
+use super::TimeSpec;
 
 /// @brief 休眠指定时间（单位：纳秒）
 ///
+/// 使用高精度定时器计时，在高精度模式下精度不受时钟节拍的限制
+///
 /// @param sleep_time 指定休眠的时间
 ///
 /// @return Ok(TimeSpec) 剩余休眠时间
@@ -27,42 +20,15 @@ pub fn nanosleep(sleep_time: TimeSpec) -> Result<TimeSpec, SystemError> {
     if sleep_time.tv_nsec < 0 || sleep_time.tv_nsec >= 1000000000 {
         return Err(SystemError::EINVAL);
     }
-    // 对于小于500us的时间，使用spin/rdtsc来进行定时
-    if sleep_time.tv_nsec < 500000 && sleep_time.tv_sec == 0 {
-        let expired_tsc: u64 = unsafe {
-            CurrentTimeArch::get_cycles() as u64
-                + (sleep_time.tv_nsec as u64 * Cpu_tsc_freq) / 1000000000
-        };
-        while (CurrentTimeArch::get_cycles() as u64) < expired_tsc {
-            spin_loop()
-        }
-        return Ok(TimeSpec {
-            tv_sec: 0,
-            tv_nsec: 0,
-        });
-    }
 
-    let total_sleep_time_us: u64 =
-        sleep_time.tv_sec as u64 * 1000000 + sleep_time.tv_nsec as u64 / 1000;
-    // 创建定时器
-    let handler: Box<WakeUpHelper> = WakeUpHelper::new(ProcessManager::current_pcb());
-    let timer: Arc<Timer> = Timer::new(handler, next_n_us_timer_jiffies(total_sleep_time_us));
-
-    let irq_guard: crate::exception::IrqFlagsGuard =
-        unsafe { CurrentIrqArch::save_and_disable_irq() };
-    ProcessManager::mark_sleep(true).ok();
-
-    let start_time = getnstimeofday();
-    timer.activate();
-
-    drop(irq_guard);
-    sched();
-
-    let end_time = getnstimeofday();
-    // 返回正确的剩余时间
-    let real_sleep_time = end_time - start_time;
-    let rm_time: TimeSpec = (sleep_time - real_sleep_time.into()).into();
+    // 使用单调时钟计算到期时间，避免受到墙上时间调整的影响
+    let expires = ktime_get_monotonic_ns().saturating_add(sleep_time.total_nsecs());
+    if hrtimer_sleep_until(expires) {
+        return Ok(TimeSpec::default());
+    }
 
+    // 被信号提前唤醒，返回剩余时间
+    let rm_time = TimeSpec::from_nsecs((expires - ktime_get_monotonic_ns()).max(0));
     return Ok(rm_time);
 }
 
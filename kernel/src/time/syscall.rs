@@ -4,11 +4,30 @@ use num_traits::FromPrimitive;
 use system_error::SystemError;
 
 use crate::{
-    syscall::{user_access::UserBufferWriter, Syscall},
+    filesystem::vfs::{
+        anon_inode::AnonInodePrivateData,
+        file::{File, FileMode},
+        FilePrivateData,
+    },
+    process::ProcessManager,
+    syscall::{
+        user_access::{UserBufferReader, UserBufferWriter},
+        Syscall,
+    },
     time::{sleep::nanosleep, TimeSpec},
 };
 
-use super::timekeeping::do_gettimeofday;
+use super::{
+    clocksource::HZ,
+    itimer::{do_getitimer, do_setitimer, ITimerSpec, ITimerVal, ITimerWhich},
+    posix_timers::{do_timer_create, do_timer_delete, posix_timer, PosixSigEvent},
+    timekeeping::{
+        do_gettimeofday, getnstimeofday, ktime_get_boottime, ktime_get_monotonic, ktime_get_raw,
+        ktime_get_resolution,
+    },
+    timerfd::{TimerFdFlags, TimerFdInode, TimerFdSetFlags},
+    NSEC_PER_SEC,
+};
 
 pub type PosixTimeT = c_longlong;
 pub type PosixSusecondsT = c_int;
@@ -59,6 +78,79 @@ impl TryFrom<i32> for PosixClockID {
     }
 }
 
+impl PosixClockID {
+    /// 读取时钟的当前值
+    pub fn now(&self) -> TimeSpec {
+        match self {
+            PosixClockID::Realtime | PosixClockID::RealtimeCoarse | PosixClockID::RealtimeAlarm => {
+                getnstimeofday()
+            }
+            PosixClockID::Monotonic | PosixClockID::MonotonicCoarse => ktime_get_monotonic(),
+            PosixClockID::MonotonicRaw => ktime_get_raw(),
+            PosixClockID::Boottime | PosixClockID::BoottimeAlarm => ktime_get_boottime(),
+            PosixClockID::ProcessCPUTimeID => {
+                let tgid = ProcessManager::current_pcb().tgid();
+                TimeSpec::from_nsecs(ProcessManager::thread_group_cpu_time(tgid) as i64)
+            }
+            PosixClockID::ThreadCPUTimeID => {
+                TimeSpec::from_nsecs(ProcessManager::current_pcb().sched_info().cpu_time() as i64)
+            }
+        }
+    }
+
+    /// 时钟的精度
+    pub fn resolution(&self) -> TimeSpec {
+        if self.is_cpu_clock() {
+            // CPU时间在每次调度时钟中断时统计
+            return TimeSpec::from_nsecs((NSEC_PER_SEC as u64 / HZ) as i64);
+        }
+        return ktime_get_resolution();
+    }
+
+    /// 是否是统计进程或线程占用CPU时间的时钟
+    pub fn is_cpu_clock(&self) -> bool {
+        return matches!(
+            self,
+            PosixClockID::ProcessCPUTimeID | PosixClockID::ThreadCPUTimeID
+        );
+    }
+
+    /// 是否可以在这个时钟上设置定时器（timer_create、timerfd_create）
+    pub fn is_timer_clock(&self) -> bool {
+        return matches!(
+            self,
+            PosixClockID::Realtime
+                | PosixClockID::Monotonic
+                | PosixClockID::Boottime
+                | PosixClockID::RealtimeAlarm
+                | PosixClockID::BoottimeAlarm
+        );
+    }
+}
+
+/// clock_nanosleep和timer_settime的flags参数：时间是时钟的绝对时间，而不是相对时间
+pub const TIMER_ABSTIME: i32 = 0x01;
+
+/// 从用户空间读取定时器的设置
+fn read_itimerspec(ptr: *const ITimerSpec) -> Result<ITimerSpec, SystemError> {
+    let spec = *UserBufferReader::new(ptr, core::mem::size_of::<ITimerSpec>(), true)?
+        .read_one_from_user::<ITimerSpec>(0)?;
+    if !spec.is_valid() {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok(spec);
+}
+
+/// 把定时器的设置写到用户空间，`ptr`为NULL时不写入
+fn write_itimerspec(ptr: *mut ITimerSpec, spec: &ITimerSpec) -> Result<(), SystemError> {
+    if !ptr.is_null() {
+        let mut writer =
+            UserBufferWriter::new::<ITimerSpec>(ptr, core::mem::size_of::<ITimerSpec>(), true)?;
+        writer.copy_one_to_user(spec, 0)?;
+    }
+    return Ok(());
+}
+
 impl Syscall {
     /// @brief 休眠指定时间（单位：纳秒）（提供给C的接口）
     ///
@@ -133,19 +225,326 @@ impl Syscall {
 
     pub fn clock_gettime(clock_id: c_int, tp: *mut TimeSpec) -> Result<usize, SystemError> {
         let clock_id = PosixClockID::try_from(clock_id)?;
-        if clock_id != PosixClockID::Realtime {
-            kwarn!("clock_gettime: currently only support Realtime clock, but got {:?}. Defaultly return realtime!!!\n", clock_id);
-        }
         if tp.is_null() {
             return Err(SystemError::EFAULT);
         }
         let mut tp_buf =
             UserBufferWriter::new::<TimeSpec>(tp, core::mem::size_of::<TimeSpec>(), true)?;
 
-        let posix_time = do_gettimeofday();
+        tp_buf.copy_one_to_user(&clock_id.now(), 0)?;
+
+        return Ok(0);
+    }
+
+    /// ## 获取时钟的精度
+    ///
+    /// ## 参数
+    ///
+    /// - `clock_id` 时钟的id
+    /// - `res` 用于返回精度的用户空间地址，为NULL时只检查时钟是否存在
+    pub fn clock_getres(clock_id: c_int, res: *mut TimeSpec) -> Result<usize, SystemError> {
+        let clock_id = PosixClockID::try_from(clock_id)?;
+        if !res.is_null() {
+            let mut res_buf =
+                UserBufferWriter::new::<TimeSpec>(res, core::mem::size_of::<TimeSpec>(), true)?;
+            res_buf.copy_one_to_user(&clock_id.resolution(), 0)?;
+        }
 
-        tp_buf.copy_one_to_user(&posix_time, 0)?;
+        return Ok(0);
+    }
+
+    /// ## 以指定的时钟为基准休眠
+    ///
+    /// ## 参数
+    ///
+    /// - `clock_id` 时钟的id
+    /// - `flags` 为`TIMER_ABSTIME`时，`request`是时钟的绝对时间，否则是相对时间
+    /// - `request` 休眠的时间
+    /// - `remain` 休眠被信号打断时，用于返回剩余的时间（只对相对时间有效），可以为NULL
+    pub fn clock_nanosleep(
+        clock_id: c_int,
+        flags: c_int,
+        request: *const TimeSpec,
+        remain: *mut TimeSpec,
+    ) -> Result<usize, SystemError> {
+        let clock_id = PosixClockID::try_from(clock_id)?;
+        match clock_id {
+            PosixClockID::ThreadCPUTimeID => return Err(SystemError::EINVAL),
+            PosixClockID::ProcessCPUTimeID => return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+            _ => {}
+        }
+
+        let request = *UserBufferReader::new(request, core::mem::size_of::<TimeSpec>(), true)?
+            .read_one_from_user::<TimeSpec>(0)?;
+        if !request.is_valid() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let abstime = flags & TIMER_ABSTIME != 0;
+        let sleep_time = if abstime {
+            let delta = request
+                .total_nsecs()
+                .saturating_sub(clock_id.now().total_nsecs());
+            // 已经过了指定的时间，不需要休眠
+            if delta <= 0 {
+                return Ok(0);
+            }
+            TimeSpec::from_nsecs(delta)
+        } else {
+            request
+        };
+
+        let rm_time = nanosleep(sleep_time)?;
+
+        // 休眠被信号打断
+        if ProcessManager::current_pcb()
+            .sig_info_irqsave()
+            .sig_pending()
+            .has_pending()
+        {
+            if !abstime && !remain.is_null() {
+                let mut remain_buf = UserBufferWriter::new::<TimeSpec>(
+                    remain,
+                    core::mem::size_of::<TimeSpec>(),
+                    true,
+                )?;
+                remain_buf.copy_one_to_user(&rm_time, 0)?;
+            }
+            return Err(SystemError::EINTR);
+        }
+
+        return Ok(0);
+    }
+
+    /// ## 在`seconds`秒之后向进程发送SIGALRM
+    ///
+    /// `seconds`为0时取消之前设置的alarm。alarm与ITIMER_REAL使用同一个定时器
+    ///
+    /// ## 返回值
+    ///
+    /// 之前设置的alarm的剩余秒数，没有设置时返回0
+    pub fn alarm(seconds: u32) -> Result<usize, SystemError> {
+        let new = ITimerVal {
+            it_interval: PosixTimeval::default(),
+            it_value: PosixTimeval {
+                tv_sec: seconds as PosixTimeT,
+                tv_usec: 0,
+            },
+        };
+        let old = do_setitimer(ITimerWhich::Real, &new)?.it_value;
+
+        // 与Linux相同，剩余时间四舍五入到秒，不足1秒时返回1
+        let mut remain = old.tv_sec as usize;
+        if old.tv_usec >= 500000 || (remain == 0 && old.tv_usec > 0) {
+            remain += 1;
+        }
+        return Ok(remain);
+    }
+
+    /// ## 读取进程的间隔定时器
+    ///
+    /// ## 参数
+    ///
+    /// - `which` ITIMER_REAL、ITIMER_VIRTUAL或ITIMER_PROF
+    /// - `curr_value` 用于返回定时器设置的用户空间地址
+    pub fn getitimer(which: c_int, curr_value: *mut ITimerVal) -> Result<usize, SystemError> {
+        let which = ITimerWhich::try_from(which)?;
+        let mut writer = UserBufferWriter::new::<ITimerVal>(
+            curr_value,
+            core::mem::size_of::<ITimerVal>(),
+            true,
+        )?;
+        writer.copy_one_to_user(&do_getitimer(which), 0)?;
+        return Ok(0);
+    }
+
+    /// ## 设置进程的间隔定时器
+    ///
+    /// ## 参数
+    ///
+    /// - `which` ITIMER_REAL、ITIMER_VIRTUAL或ITIMER_PROF
+    /// - `new_value` 新的设置，与Linux相同，为NULL时停止定时器
+    /// - `old_value` 用于返回原来的设置，可以为NULL
+    pub fn setitimer(
+        which: c_int,
+        new_value: *const ITimerVal,
+        old_value: *mut ITimerVal,
+    ) -> Result<usize, SystemError> {
+        let which = ITimerWhich::try_from(which)?;
+        let new = if new_value.is_null() {
+            ITimerVal::default()
+        } else {
+            *UserBufferReader::new(new_value, core::mem::size_of::<ITimerVal>(), true)?
+                .read_one_from_user::<ITimerVal>(0)?
+        };
+        let old_writer = if old_value.is_null() {
+            None
+        } else {
+            Some(UserBufferWriter::new::<ITimerVal>(
+                old_value,
+                core::mem::size_of::<ITimerVal>(),
+                true,
+            )?)
+        };
+
+        let old = do_setitimer(which, &new)?;
+        if let Some(mut writer) = old_writer {
+            writer.copy_one_to_user(&old, 0)?;
+        }
+        return Ok(0);
+    }
+
+    /// ## 创建POSIX定时器
+    ///
+    /// ## 参数
+    ///
+    /// - `clock_id` 定时器使用的时钟
+    /// - `sevp` 定时器到期时的通知方式，支持SIGEV_NONE、SIGEV_SIGNAL和SIGEV_THREAD_ID。
+    ///   为NULL时，定时器到期时向进程发送SIGALRM
+    /// - `timerid` 用于返回新定时器id的用户空间地址
+    pub fn timer_create(
+        clock_id: c_int,
+        sevp: *const PosixSigEvent,
+        timerid: *mut i32,
+    ) -> Result<usize, SystemError> {
+        let clock_id = PosixClockID::try_from(clock_id)?;
+        let event = if sevp.is_null() {
+            None
+        } else {
+            Some(
+                *UserBufferReader::new(sevp, core::mem::size_of::<PosixSigEvent>(), true)?
+                    .read_one_from_user::<PosixSigEvent>(0)?,
+            )
+        };
+        let mut writer = UserBufferWriter::new::<i32>(timerid, core::mem::size_of::<i32>(), true)?;
+
+        let id = do_timer_create(clock_id, event.as_ref())?;
+        if let Err(e) = writer.copy_one_to_user(&id, 0) {
+            do_timer_delete(id).ok();
+            return Err(e);
+        }
+        return Ok(0);
+    }
+
+    /// ## 启动或者停止POSIX定时器
+    ///
+    /// ## 参数
+    ///
+    /// - `timerid` 定时器的id
+    /// - `flags` 为`TIMER_ABSTIME`时，`new_value`的it_value是时钟的绝对时间
+    /// - `new_value` 新的设置，it_value为0时停止定时器
+    /// - `old_value` 用于返回原来的设置，可以为NULL
+    pub fn timer_settime(
+        timerid: i32,
+        flags: c_int,
+        new_value: *const ITimerSpec,
+        old_value: *mut ITimerSpec,
+    ) -> Result<usize, SystemError> {
+        let new = read_itimerspec(new_value)?;
+        let old = posix_timer(timerid)?.set(&new, flags & TIMER_ABSTIME != 0);
+        write_itimerspec(old_value, &old)?;
+        return Ok(0);
+    }
+
+    /// ## 读取POSIX定时器的剩余时间和到期间隔
+    pub fn timer_gettime(timerid: i32, curr_value: *mut ITimerSpec) -> Result<usize, SystemError> {
+        if curr_value.is_null() {
+            return Err(SystemError::EFAULT);
+        }
+        let spec = posix_timer(timerid)?.get();
+        write_itimerspec(curr_value, &spec)?;
+        return Ok(0);
+    }
+
+    /// ## 获取POSIX定时器上一次到期时错过的到期次数
+    pub fn timer_getoverrun(timerid: i32) -> Result<usize, SystemError> {
+        let overrun = posix_timer(timerid)?.overrun();
+        return Ok(overrun.min(i32::MAX as u64) as usize);
+    }
+
+    /// ## 删除POSIX定时器
+    pub fn timer_delete(timerid: i32) -> Result<usize, SystemError> {
+        do_timer_delete(timerid)?;
+        return Ok(0);
+    }
+
+    /// ## 创建timerfd
+    ///
+    /// ## 参数
+    ///
+    /// - `clock_id` 定时器使用的时钟
+    /// - `flags` TFD_NONBLOCK、TFD_CLOEXEC
+    pub fn timerfd_create(clock_id: c_int, flags: u32) -> Result<usize, SystemError> {
+        let flags = TimerFdFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        let clock_id = PosixClockID::try_from(clock_id)?;
+        if !clock_id.is_timer_clock() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mode =
+            FileMode::O_RDWR | (FileMode::from_bits_truncate(flags.bits()) & FileMode::O_NONBLOCK);
+        let mut file = File::new(TimerFdInode::new(clock_id), mode)?;
+        file.private_data = FilePrivateData::AnonInode(AnonInodePrivateData::new(mode));
+        if flags.contains(TimerFdFlags::TFD_CLOEXEC) {
+            file.set_close_on_exec(true);
+        }
+
+        let fd = ProcessManager::current_pcb()
+            .fd_table()
+            .write()
+            .alloc_fd(file, None)?;
+        return Ok(fd as usize);
+    }
+
+    /// ## 启动或者停止timerfd的定时器
+    ///
+    /// ## 参数
+    ///
+    /// - `fd` timerfd的文件描述符
+    /// - `flags` TFD_TIMER_ABSTIME、TFD_TIMER_CANCEL_ON_SET
+    /// - `new_value` 新的设置，it_value为0时停止定时器
+    /// - `old_value` 用于返回原来的设置，可以为NULL
+    pub fn timerfd_settime(
+        fd: i32,
+        flags: u32,
+        new_value: *const ITimerSpec,
+        old_value: *mut ITimerSpec,
+    ) -> Result<usize, SystemError> {
+        let flags = TimerFdSetFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        let new = read_itimerspec(new_value)?;
+
+        let file = ProcessManager::current_pcb()
+            .fd_table()
+            .read()
+            .get_file_by_fd(fd)
+            .ok_or(SystemError::EBADF)?;
+        let inode = file.lock_irqsave().inode();
+        let old = inode
+            .downcast_ref::<TimerFdInode>()
+            .ok_or(SystemError::EINVAL)?
+            .set(&new, flags);
+
+        write_itimerspec(old_value, &old)?;
+        return Ok(0);
+    }
+
+    /// ## 读取timerfd的定时器的剩余时间和到期间隔
+    pub fn timerfd_gettime(fd: i32, curr_value: *mut ITimerSpec) -> Result<usize, SystemError> {
+        if curr_value.is_null() {
+            return Err(SystemError::EFAULT);
+        }
+        let file = ProcessManager::current_pcb()
+            .fd_table()
+            .read()
+            .get_file_by_fd(fd)
+            .ok_or(SystemError::EBADF)?;
+        let inode = file.lock_irqsave().inode();
+        let spec = inode
+            .downcast_ref::<TimerFdInode>()
+            .ok_or(SystemError::EINVAL)?
+            .get();
 
+        write_itimerspec(curr_value, &spec)?;
         return Ok(0);
     }
 }
@@ -0,0 +1,291 @@
+//! 时钟节拍
+//!
+//! 为每个CPU选择一个时钟事件设备产生时钟节拍，并选择一个全局设备推进jiffies和墙上时间。
+//!
+//! CPU的时钟事件设备支持oneshot模式时，切换到高精度模式：设备按照hrtimer的到期时间编程，
+//! 周期性的时钟节拍由一个hrtimer模拟。开启tickless（nohz）模式时，CPU空闲期间停止模拟时钟节拍的hrtimer，
+//! 在下一个hrtimer到期或者其他中断到来之前，CPU不会收到时钟中断
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/time/tick-common.c
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/time/tick-sched.c
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::{boxed::Box, sync::Arc};
+
+use crate::{
+    arch::{interrupt::ipi::send_ipi, CurrentIrqArch},
+    exception::{
+        ipi::{IpiKind, IpiTarget},
+        softirq::{softirq_vectors, SoftirqNumber},
+        InterruptArch,
+    },
+    kinfo, kwarn,
+    libs::spinlock::SpinLock,
+    mm::percpu::PerCpu,
+    sched::core::sched_update_jiffies,
+    smp::{core::smp_get_processor_id, cpu::ProcessorId},
+};
+
+use super::{
+    clockevents::{ClockEventDevice, ClockEventFeatures, ClockEventState},
+    clocksource::HZ,
+    hrtimer::{
+        hrtimer_hres_active, hrtimer_interrupt, hrtimer_run_queues, hrtimer_switch_to_hres,
+        HrTimer, HrTimerFunction, HrTimerMode, HrTimerRestart,
+    },
+    timekeeping::ktime_get_monotonic_ns,
+    timer::{clock, timer_get_first_expire, update_timer_jiffies},
+    NSEC_PER_SEC, NSEC_PER_USEC,
+};
+
+/// 两次时钟节拍的间隔（纳秒）
+pub const TICK_NSEC: i64 = NSEC_PER_SEC as i64 / HZ as i64;
+
+/// 是否开启tickless模式，由`nohz`特性决定
+static TICK_NOHZ_ENABLED: AtomicBool = AtomicBool::new(cfg!(feature = "nohz"));
+
+/// 每个CPU产生时钟节拍的设备
+static TICK_CPU_DEVICE: [SpinLock<Option<Arc<dyn ClockEventDevice>>>;
+    PerCpu::MAX_CPU_NUM as usize] = [const { SpinLock::new(None) }; PerCpu::MAX_CPU_NUM as usize];
+
+/// 推进jiffies和墙上时间的全局设备
+static TICK_DO_TIMER_DEVICE: SpinLock<Option<Arc<dyn ClockEventDevice>>> = SpinLock::new(None);
+
+/// 每个CPU在高精度模式下模拟时钟节拍的状态
+static TICK_SCHED: [SpinLock<TickSched>; PerCpu::MAX_CPU_NUM as usize] =
+    [const { SpinLock::new(TickSched::new()) }; PerCpu::MAX_CPU_NUM as usize];
+
+#[derive(Debug)]
+struct TickSched {
+    /// 模拟时钟节拍的hrtimer，切换到高精度模式之后创建
+    sched_timer: Option<Arc<HrTimer>>,
+    /// CPU空闲时是否停止了时钟节拍
+    tick_stopped: bool,
+}
+
+impl TickSched {
+    const fn new() -> Self {
+        return Self {
+            sched_timer: None,
+            tick_stopped: false,
+        };
+    }
+}
+
+/// 模拟时钟节拍的hrtimer到期时执行的函数
+#[derive(Debug)]
+struct TickSchedTimer;
+
+impl HrTimerFunction for TickSchedTimer {
+    fn run(&self, timer: &Arc<HrTimer>) -> HrTimerRestart {
+        tick_sched_handle();
+        timer.forward(ktime_get_monotonic_ns(), TICK_NSEC);
+        return HrTimerRestart::Restart;
+    }
+}
+
+/// 下一个时钟节拍的时间，时钟节拍对齐到`TICK_NSEC`的整数倍
+fn tick_next_period() -> i64 {
+    return (ktime_get_monotonic_ns() / TICK_NSEC + 1) * TICK_NSEC;
+}
+
+/// 每个时钟节拍中需要进行的工作
+fn tick_sched_handle() {
+    sched_update_jiffies();
+}
+
+/// 周期模式下，CPU的时钟事件设备的中断处理函数
+fn tick_handle_periodic(_dev: &Arc<dyn ClockEventDevice>) {
+    tick_sched_handle();
+    hrtimer_run_queues();
+}
+
+/// 高精度模式下，CPU的时钟事件设备的中断处理函数
+fn tick_handle_oneshot(_dev: &Arc<dyn ClockEventDevice>) {
+    hrtimer_interrupt();
+}
+
+/// 全局设备的中断处理函数，推进jiffies和墙上时间，并处理到期的`Timer`
+fn tick_handle_do_timer(dev: &Arc<dyn ClockEventDevice>) {
+    assert!(!CurrentIrqArch::is_irq_enabled());
+    let period_us = dev.period_ns() / NSEC_PER_USEC as u64;
+    update_timer_jiffies(period_us, period_us as i64);
+
+    if let Ok(first_expire) = timer_get_first_expire() {
+        if first_expire <= clock() {
+            softirq_vectors().raise_softirq(SoftirqNumber::TIMER);
+        }
+    }
+}
+
+/// ## 注册了新的时钟事件设备时调用，决定是否使用这个设备
+pub fn tick_check_new_device(dev: Arc<dyn ClockEventDevice>) {
+    match dev.cpu() {
+        None => tick_setup_do_timer(dev),
+        Some(cpu) if cpu == smp_get_processor_id() => tick_setup_cpu_device(dev),
+        Some(cpu) => {
+            kwarn!(
+                "tick: clockevent '{}' of cpu {:?} registered on cpu {:?}",
+                dev.name(),
+                cpu,
+                smp_get_processor_id()
+            );
+        }
+    }
+}
+
+fn tick_setup_do_timer(dev: Arc<dyn ClockEventDevice>) {
+    let mut current = TICK_DO_TIMER_DEVICE.lock_irqsave();
+    if let Some(old) = current.as_ref() {
+        if old.rating() >= dev.rating() {
+            return;
+        }
+    }
+    if !dev.features().contains(ClockEventFeatures::PERIODIC) {
+        return;
+    }
+
+    dev.set_handler(Some(tick_handle_do_timer));
+    if let Err(e) = dev.switch_state(ClockEventState::Periodic) {
+        kwarn!("tick: failed to start clockevent '{}': {:?}", dev.name(), e);
+        dev.set_handler(None);
+        return;
+    }
+    if let Some(old) = current.replace(dev) {
+        old.set_handler(None);
+        old.switch_state(ClockEventState::Shutdown).ok();
+    }
+}
+
+fn tick_setup_cpu_device(dev: Arc<dyn ClockEventDevice>) {
+    let cpu = smp_get_processor_id();
+    let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+
+    let oneshot = dev.features().contains(ClockEventFeatures::ONESHOT);
+    let hres_active = hrtimer_hres_active();
+    let mut current = TICK_CPU_DEVICE[cpu.data() as usize].lock_irqsave();
+    if let Some(old) = current.as_ref() {
+        // 已经进入高精度模式时，不再切换到只支持周期模式的设备
+        if old.rating() >= dev.rating() || (!oneshot && hres_active) {
+            return;
+        }
+    }
+
+    let (handler, state) = if oneshot {
+        (
+            tick_handle_oneshot as fn(&Arc<dyn ClockEventDevice>),
+            ClockEventState::OneshotStopped,
+        )
+    } else {
+        (
+            tick_handle_periodic as fn(&Arc<dyn ClockEventDevice>),
+            ClockEventState::Periodic,
+        )
+    };
+    dev.set_handler(Some(handler));
+    if let Err(e) = dev.switch_state(state) {
+        kwarn!("tick: failed to start clockevent '{}': {:?}", dev.name(), e);
+        dev.set_handler(None);
+        return;
+    }
+    if let Some(old) = current.replace(dev.clone()) {
+        old.set_handler(None);
+        old.switch_state(ClockEventState::Shutdown).ok();
+    }
+    drop(current);
+
+    if oneshot {
+        tick_setup_sched_timer(cpu);
+        kinfo!(
+            "tick: cpu {:?} switched to high resolution mode, clockevent: '{}'",
+            cpu,
+            dev.name()
+        );
+    }
+    drop(irq_guard);
+}
+
+/// 切换到高精度模式，并启动模拟时钟节拍的hrtimer
+fn tick_setup_sched_timer(cpu: ProcessorId) {
+    hrtimer_switch_to_hres();
+
+    let mut ts = TICK_SCHED[cpu.data() as usize].lock_irqsave();
+    if ts.sched_timer.is_none() {
+        ts.sched_timer = Some(HrTimer::new(Box::new(TickSchedTimer)));
+    }
+    ts.tick_stopped = false;
+    ts.sched_timer
+        .as_ref()
+        .unwrap()
+        .start(tick_next_period(), HrTimerMode::Abs);
+}
+
+/// ## 把当前CPU的时钟事件设备编程为在`expires`时产生中断
+///
+/// ## 参数
+///
+/// - `expires` 单调时钟的时间（纳秒），为None时停止产生中断
+pub fn tick_program_event(expires: Option<i64>) {
+    let dev = TICK_CPU_DEVICE[smp_get_processor_id().data() as usize]
+        .lock_irqsave()
+        .clone();
+    if let Some(dev) = dev {
+        if let Err(e) = dev.program_event(expires) {
+            kwarn!(
+                "tick: failed to program clockevent '{}': {:?}",
+                dev.name(),
+                e
+            );
+        }
+    }
+}
+
+/// ## CPU进入空闲状态之前调用，在tickless模式下停止时钟节拍
+///
+/// ## 返回值
+///
+/// 时钟节拍是否已经停止。停止时钟节拍之后，空闲进程被唤醒时需要主动检查是否有进程需要运行
+pub fn tick_nohz_idle_enter() -> bool {
+    if !TICK_NOHZ_ENABLED.load(Ordering::Relaxed) {
+        return false;
+    }
+    let _irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+    let mut ts = TICK_SCHED[smp_get_processor_id().data() as usize].lock_irqsave();
+    if ts.tick_stopped {
+        return true;
+    }
+    // 低精度模式下无法停止时钟节拍
+    let timer = match ts.sched_timer.as_ref() {
+        Some(timer) => timer.clone(),
+        None => return false,
+    };
+    ts.tick_stopped = true;
+    timer.cancel();
+    return true;
+}
+
+/// ## CPU退出空闲状态时调用，重新开始产生时钟节拍
+pub fn tick_nohz_idle_exit() {
+    let _irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+    let mut ts = TICK_SCHED[smp_get_processor_id().data() as usize].lock_irqsave();
+    if !ts.tick_stopped {
+        return;
+    }
+    ts.tick_stopped = false;
+    if let Some(timer) = ts.sched_timer.as_ref() {
+        timer.start(tick_next_period(), HrTimerMode::Abs);
+    }
+}
+
+/// ## 向其他CPU的运行队列加入进程之后调用
+///
+/// 目标CPU停止了时钟节拍时，通过IPI唤醒它
+pub fn tick_nohz_kick_cpu(cpu: ProcessorId) {
+    if cpu == smp_get_processor_id() {
+        return;
+    }
+    if TICK_SCHED[cpu.data() as usize].lock_irqsave().tick_stopped {
+        send_ipi(IpiKind::KickCpu, IpiTarget::Specified(cpu));
+    }
+}
@@ -1,8 +1,11 @@
 use alloc::sync::Arc;
-use core::sync::atomic::{compiler_fence, AtomicBool, AtomicI64, Ordering};
+use core::{
+    hint::spin_loop,
+    sync::atomic::{compiler_fence, AtomicBool, AtomicI64, AtomicUsize, Ordering},
+};
 
 use crate::{
-    arch::CurrentIrqArch,
+    arch::{CurrentIrqArch, CurrentTimeArch},
     exception::InterruptArch,
     kdebug, kinfo,
     libs::rwlock::RwLock,
@@ -16,7 +19,7 @@ use crate::{
 use super::{
     clocksource::{clocksource_cyc2ns, Clocksource, CycleNum, HZ},
     syscall::PosixTimeval,
-    NSEC_PER_SEC, USEC_PER_SEC,
+    TimeArch, NSEC_PER_SEC, NSEC_PER_USEC, USEC_PER_SEC,
 };
 /// NTP周期频率
 pub const NTP_INTERVAL_FREQ: u64 = HZ;
@@ -31,6 +34,14 @@ pub static TIMEKEEPING_SUSPENDED: AtomicBool = AtomicBool::new(false);
 static __ADDED_USEC: AtomicI64 = AtomicI64::new(0);
 /// 已经递增的秒数
 static __ADDED_SEC: AtomicI64 = AtomicI64::new(0);
+/// 系统启动以来经过的纳秒数（单调时钟）
+static __MONOTONIC_NS: AtomicI64 = AtomicI64::new(0);
+/// 时钟中断的间隔（纳秒）
+static __TICK_NSEC: AtomicI64 = AtomicI64::new((NSEC_PER_SEC as u64 / HZ) as i64);
+/// 上一次时钟中断时的CPU周期数，用于计算两次时钟中断之间经过的时间
+static __TICK_CYCLES: AtomicUsize = AtomicUsize::new(0);
+/// 更新`__MONOTONIC_NS`和`__TICK_CYCLES`时加一，为奇数表示正在更新
+static __MONOTONIC_SEQ: AtomicUsize = AtomicUsize::new(0);
 /// timekeeper全局变量，用于管理timekeeper模块
 static mut __TIMEKEEPER: Option<Timekeeper> = None;
 
@@ -206,6 +217,64 @@ pub fn do_gettimeofday() -> PosixTimeval {
     };
 }
 
+/// # 获取系统启动以来经过的时间（纳秒）
+///
+/// 在上一次时钟中断的时间上，加上CPU周期计数器在这之后经过的时间。
+/// 经过的时间不超过一个时钟中断的间隔，保证单调时钟不会回退
+pub fn ktime_get_monotonic_ns() -> i64 {
+    loop {
+        let seq = __MONOTONIC_SEQ.load(Ordering::Acquire);
+        if seq & 1 != 0 {
+            spin_loop();
+            continue;
+        }
+        let base = __MONOTONIC_NS.load(Ordering::SeqCst);
+        let cycles = __TICK_CYCLES.load(Ordering::SeqCst);
+        let tick_nsec = __TICK_NSEC.load(Ordering::SeqCst);
+        if __MONOTONIC_SEQ.load(Ordering::Acquire) != seq {
+            continue;
+        }
+
+        let delta = CurrentTimeArch::get_cycles().saturating_sub(cycles);
+        let delta_ns = (CurrentTimeArch::cycles2ns(delta) as i64).min(tick_nsec - 1);
+        return base + delta_ns.max(0);
+    }
+}
+
+/// # 获取系统启动以来经过的时间（CLOCK_MONOTONIC）
+///
+/// 单调时钟随时钟中断递增，不会因为墙上时间的同步或者设置而跳变
+pub fn ktime_get_monotonic() -> TimeSpec {
+    return TimeSpec::from_nsecs(ktime_get_monotonic_ns());
+}
+
+/// # 获取不经过NTP调整的单调时间（CLOCK_MONOTONIC_RAW）
+///
+/// 目前没有NTP模块，单调时钟不会被调整，因此与CLOCK_MONOTONIC相同
+pub fn ktime_get_raw() -> TimeSpec {
+    return ktime_get_monotonic();
+}
+
+/// # 获取包括系统休眠时间在内的、系统启动以来经过的时间（CLOCK_BOOTTIME）
+pub fn ktime_get_boottime() -> TimeSpec {
+    let total_sleep_time = timekeeper().0.read_irqsave().total_sleep_time;
+    return TimeSpec::from_nsecs(
+        ktime_get_monotonic()
+            .total_nsecs()
+            .saturating_add(total_sleep_time.total_nsecs()),
+    );
+}
+
+/// # 获取计时的精度
+///
+/// CPU周期计数器的频率已知时为1ns，否则为两次时钟中断的间隔
+pub fn ktime_get_resolution() -> TimeSpec {
+    if CurrentTimeArch::cycles2ns(NSEC_PER_SEC as usize) == 0 {
+        return TimeSpec::from_nsecs(__TICK_NSEC.load(Ordering::SeqCst));
+    }
+    return TimeSpec::from_nsecs(1);
+}
+
 /// # 初始化timekeeping模块
 #[inline(never)]
 pub fn timekeeping_init() {
@@ -271,6 +340,14 @@ pub fn update_wall_time(delta_us: i64) {
     // ================
     compiler_fence(Ordering::SeqCst);
 
+    // 单调时钟只在这里递增，下面与RTC同步墙上时间时不会影响它
+    let delta_ns = delta_us * NSEC_PER_USEC as i64;
+    __MONOTONIC_SEQ.fetch_add(1, Ordering::Release);
+    __MONOTONIC_NS.fetch_add(delta_ns, Ordering::SeqCst);
+    __TICK_NSEC.store(delta_ns, Ordering::SeqCst);
+    __TICK_CYCLES.store(CurrentTimeArch::get_cycles(), Ordering::SeqCst);
+    __MONOTONIC_SEQ.fetch_add(1, Ordering::Release);
+
     __ADDED_USEC.fetch_add(delta_us, Ordering::SeqCst);
     compiler_fence(Ordering::SeqCst);
     let mut retry = 10;
@@ -0,0 +1,189 @@
+//! timerfd：通过文件描述符通知定时器到期
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/fs/timerfd.c
+
+use core::mem::size_of;
+
+use alloc::{
+    boxed::Box,
+    sync::{Arc, Weak},
+};
+use system_error::SystemError;
+
+use crate::{
+    anon_inode_common_ops,
+    filesystem::vfs::{anon_inode::AnonInodeEpItems, file::FileMode, FilePrivateData, IndexNode},
+    libs::{spinlock::SpinLock, wait_queue::WaitQueue},
+    net::event_poll::{EPollEventType, EventPoll},
+    process::ProcessManager,
+};
+
+use super::{
+    itimer::{ITimerSpec, IntervalTimer, IntervalTimerCallback},
+    syscall::PosixClockID,
+};
+
+bitflags! {
+    /// timerfd_create系统调用的flags参数
+    pub struct TimerFdFlags: u32 {
+        const TFD_CLOEXEC = FileMode::O_CLOEXEC.bits();
+        const TFD_NONBLOCK = FileMode::O_NONBLOCK.bits();
+    }
+
+    /// timerfd_settime系统调用的flags参数
+    pub struct TimerFdSetFlags: u32 {
+        /// new_value是时钟的绝对时间
+        const TFD_TIMER_ABSTIME = 1 << 0;
+        /// 实时时钟被修改时取消定时器。目前不支持修改实时时钟，因此忽略这个标志
+        const TFD_TIMER_CANCEL_ON_SET = 1 << 1;
+    }
+}
+
+/// timerfd文件的inode
+#[derive(Debug)]
+pub struct TimerFdInode {
+    timer: Arc<IntervalTimer>,
+    /// 上一次读取之后定时器到期的次数
+    ticks: SpinLock<u64>,
+    /// 在read中等待定时器到期的进程
+    wait_queue: WaitQueue,
+    epitems: AnonInodeEpItems,
+}
+
+/// timerfd的定时器到期时，增加到期次数并唤醒等待的进程
+#[derive(Debug)]
+struct TimerFdCallback {
+    inode: Weak<TimerFdInode>,
+}
+
+impl IntervalTimerCallback for TimerFdCallback {
+    fn expire(&self, overrun: u64) {
+        if let Some(inode) = self.inode.upgrade() {
+            // 在ticks的锁内唤醒，与take_ticks中“检查到期次数并加入等待队列”的过程互斥，避免丢失唤醒
+            let mut ticks = inode.ticks.lock_irqsave();
+            *ticks += overrun + 1;
+            inode.wait_queue.wakeup_all(None);
+            drop(ticks);
+            inode
+                .epitems
+                .wakeup(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM);
+        }
+    }
+}
+
+impl TimerFdInode {
+    pub fn new(clock: PosixClockID) -> Arc<Self> {
+        return Arc::new_cyclic(|self_ref| Self {
+            timer: IntervalTimer::new(
+                clock,
+                Box::new(TimerFdCallback {
+                    inode: self_ref.clone(),
+                }),
+            ),
+            ticks: SpinLock::new(0),
+            wait_queue: WaitQueue::INIT,
+            epitems: AnonInodeEpItems::new(),
+        });
+    }
+
+    /// 获取定时器的剩余时间和到期间隔
+    pub fn get(&self) -> ITimerSpec {
+        return self.timer.get();
+    }
+
+    /// ## 设置定时器
+    ///
+    /// 重新设置定时器时，清空还没有读取的到期次数
+    ///
+    /// ## 返回值
+    ///
+    /// 定时器原来的设置
+    pub fn set(&self, new: &ITimerSpec, flags: TimerFdSetFlags) -> ITimerSpec {
+        let old = self
+            .timer
+            .set(new, flags.contains(TimerFdSetFlags::TFD_TIMER_ABSTIME));
+        *self.ticks.lock_irqsave() = 0;
+        return old;
+    }
+
+    fn poll_events(&self) -> EPollEventType {
+        if *self.ticks.lock_irqsave() > 0 {
+            return EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+        }
+        return EPollEventType::empty();
+    }
+
+    /// 取出定时器到期的次数
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(0)` 非阻塞模式下定时器还没有到期
+    /// - `Err(SystemError::ERESTARTSYS)` 等待过程中被信号打断
+    fn take_ticks(&self, nonblock: bool) -> Result<u64, SystemError> {
+        let pcb = ProcessManager::current_pcb();
+        loop {
+            let mut guard = self.ticks.lock_irqsave();
+            let ticks = core::mem::take(&mut *guard);
+            if ticks > 0 || nonblock {
+                return Ok(ticks);
+            }
+            if pcb.sig_info_irqsave().sig_pending().has_pending() {
+                return Err(SystemError::ERESTARTSYS);
+            }
+
+            self.wait_queue.sleep_unlock_spinlock(guard);
+        }
+    }
+
+    pub fn epitems(&self) -> &AnonInodeEpItems {
+        return &self.epitems;
+    }
+}
+
+impl IndexNode for TimerFdInode {
+    /// 读取定时器到期的次数，结果是一个u64
+    fn read_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        let nonblock = match data {
+            FilePrivateData::AnonInode(tfd_data) => tfd_data.nonblock(),
+            _ => return Err(SystemError::EBADF),
+        };
+
+        if len < size_of::<u64>() || buf.len() < size_of::<u64>() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let ticks = self.take_ticks(nonblock)?;
+        if ticks == 0 {
+            return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+        }
+        buf[..size_of::<u64>()].copy_from_slice(&ticks.to_ne_bytes());
+        return Ok(size_of::<u64>());
+    }
+
+    fn poll(&self, _private_data: &FilePrivateData) -> Result<usize, SystemError> {
+        return Ok(self.poll_events().bits() as usize);
+    }
+
+    fn ioctl(
+        &self,
+        cmd: u32,
+        arg: usize,
+        _private_data: &FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        match cmd {
+            EventPoll::ADD_EPOLLITEM => {
+                self.epitems.add_from_ioctl(arg)?;
+                return Ok(0);
+            }
+            _ => Err(SystemError::ENOIOCTLCMD),
+        }
+    }
+
+    anon_inode_common_ops!();
+}